@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use url::Url;
@@ -5,4 +7,8 @@ use url::Url;
 #[derive(Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct Origin {
     pub url: Url,
+
+    /// Describes the relay to the ones routing to it, ex. its region, capacity or auth domain.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, String>,
 }
@@ -57,6 +57,9 @@ pub struct Track {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub depends: Option<Vec<String>>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<TrackDelivery>,
 }
 
 impl Track {
@@ -74,6 +77,14 @@ impl Track {
         if self.alt_group.is_none() {
             self.alt_group = common.alt_group;
         }
+        if self.delivery.is_none() {
+            self.delivery.clone_from(&common.delivery);
+        }
+    }
+
+    /// Returns false if the track is marked live-only. Tracks without a delivery mode are fetchable.
+    pub fn is_fetchable(&self) -> bool {
+        self.delivery != Some(TrackDelivery::Live)
     }
 }
 
@@ -87,6 +98,19 @@ pub enum TrackPackaging {
     Loc,
 }
 
+/// Whether relays may cache a track and serve it via FETCH.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub enum TrackDelivery {
+    /// Objects may be cached and fetched after they are published.
+    #[serde(rename = "fetchable")]
+    #[default]
+    Fetchable,
+
+    /// Objects are only forwarded to active subscribers and never cached.
+    #[serde(rename = "live")]
+    Live,
+}
+
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct SelectionParam {
     pub codec: Option<String>,
@@ -136,6 +160,9 @@ pub struct CommonTrackFields {
 
     #[serde(rename = "altGroup", skip_serializing_if = "Option::is_none")]
     pub alt_group: Option<u16>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery: Option<TrackDelivery>,
 }
 
 impl CommonTrackFields {
@@ -151,6 +178,7 @@ impl CommonTrackFields {
             packaging: tracks[0].packaging.clone(),
             render_group: tracks[0].render_group,
             alt_group: tracks[0].alt_group,
+            delivery: tracks[0].delivery.clone(),
         };
 
         // Loop over the other tracks to check if they have the same values
@@ -167,6 +195,9 @@ impl CommonTrackFields {
             if track.alt_group != common.alt_group {
                 common.alt_group = None;
             }
+            if track.delivery != common.delivery {
+                common.delivery = None;
+            }
         }
 
         // Loop again to remove the common fields from the tracks
@@ -183,6 +214,9 @@ impl CommonTrackFields {
             if track.alt_group.is_some() {
                 track.alt_group = None;
             }
+            if common.delivery.is_some() {
+                track.delivery = None;
+            }
         }
 
         common
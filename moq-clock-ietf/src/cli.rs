@@ -1,20 +1,10 @@
 use clap::Parser;
-use std::net;
-use url::Url;
 
 #[derive(Parser, Clone)]
 pub struct Cli {
-    /// Listen for UDP packets on the given address.
-    #[arg(long, default_value = "[::]:0")]
-    pub bind: net::SocketAddr,
-
-    /// Connect to the given URL starting with https://
-    #[arg()]
-    pub url: Url,
-
-    /// The TLS configuration.
+    /// How to connect to the relay.
     #[command(flatten)]
-    pub tls: moq_native_ietf::tls::Args,
+    pub client: moq_config::Client,
 
     /// Publish the current time to the relay, otherwise only subscribe.
     #[arg(long)]
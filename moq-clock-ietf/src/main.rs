@@ -1,4 +1,4 @@
-use moq_native_ietf::quic;
+use moq_native_ietf::{quic, tls};
 
 use anyhow::Context;
 
@@ -9,9 +9,9 @@ use clap::Parser;
 use cli::Cli;
 
 use moq_transport::{
-    coding::TrackNamespace,
+    coding::{Token, TrackNamespace},
     serve,
-    session::{Publisher, Subscriber},
+    session::Session,
 };
 
 /// The main entry point for the MoQ Clock IETF example.
@@ -26,28 +26,37 @@ async fn main() -> anyhow::Result<()> {
     tracing::subscriber::set_global_default(tracer).unwrap();
 
     let config = Cli::parse();
-    let tls = config.tls.load()?;
+    let tls = tls::Config::load(&config.client.tls)?;
 
     // Create the QUIC endpoint
-    let quic = quic::Endpoint::new(quic::Config::new(config.bind, None, tls))?;
+    let quic = quic::Endpoint::new(
+        quic::Config::new(config.client.bind, None, tls)
+            .with_transport(config.client.transport.clone()),
+    )?;
 
-    log::info!("connecting to server: url={}", config.url);
+    log::info!("connecting to server: url={}", config.client.url);
 
     // Connect to the server
-    let (session, connection_id) = quic.client.connect(&config.url, None).await?;
+    let (session, connection_id, _) = quic.client.connect(&config.client.url, None).await?;
 
     log::info!(
         "connected with CID: {} (use this to look up qlog/mlog on server)",
         connection_id
     );
 
-    // Depending on whether we are publishing or subscribing, create the appropriate session
-    if config.publish {
-        // Create the publisher session
-        let (session, mut publisher) = Publisher::connect(session)
+    let token = config
+        .client
+        .auth_token
+        .map(|token| Token::new(0, token.into_bytes()));
+
+    // Create the MoQ session
+    let (session, mut publisher, mut subscriber) =
+        Session::connect_with_token(session, None, token)
             .await
             .context("failed to create MoQ Transport session")?;
 
+    // Depending on whether we are publishing or subscribing, use the appropriate half of the session
+    if config.publish {
         if config.datagrams {
             log::info!("publishing clock via datagrams");
 
@@ -82,16 +91,17 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     } else {
-        // Create the subscriber session
-        let (session, mut subscriber) = Subscriber::connect(session)
-            .await
-            .context("failed to create MoQ Transport session")?;
-
         let track_namespace = TrackNamespace::from_utf8_path(&config.namespace);
 
         if config.track_status {
             // Request a track_status for the clock track (testing purposes only)
-            subscriber.track_status(&track_namespace, &config.track);
+            let status = subscriber.track_status(&track_namespace, &config.track);
+            tokio::spawn(async move {
+                match status.await {
+                    Ok(status) => log::info!("track status: {:?}", status),
+                    Err(err) => log::warn!("track status failed: {}", err),
+                }
+            });
         }
 
         let (track_writer, track_reader) =
@@ -0,0 +1,36 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// The AUTHORIZATION TOKENs a relay requires, and presents to the servers it connects to.
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Auth {
+    /// Require announces and subscriptions to carry this AUTHORIZATION TOKEN, either in the
+    /// request or in CLIENT_SETUP. May be repeated to accept several tokens.
+    #[arg(long = "auth-token")]
+    pub tokens: Vec<String>,
+
+    /// Re-check the tokens of announces and subscriptions every this many seconds, ending those
+    /// no longer allowed. By default tokens are only checked when a request arrives.
+    #[arg(long = "auth-recheck")]
+    pub recheck: Option<u64>,
+
+    /// Seconds a subscriber has to renew its token with SUBSCRIBE_UPDATE after a failed re-check.
+    #[arg(long = "auth-renew-grace", default_value = "30")]
+    pub renew_grace: u64,
+
+    /// AUTHORIZATION TOKEN presented in CLIENT_SETUP when connecting to --announce or other origins.
+    #[arg(long = "upstream-auth-token")]
+    pub upstream_token: Option<String>,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Self {
+            tokens: Vec::new(),
+            recheck: None,
+            renew_grace: 30,
+            upstream_token: None,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::net;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{Tls, Transport};
+
+/// How a client connects to a relay, shared by moq-pub, moq-sub and moq-clock.
+#[derive(Parser, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Client {
+    /// Listen for UDP packets on the given address.
+    #[arg(long, default_value = "[::]:0")]
+    #[serde(default = "Client::default_bind")]
+    pub bind: net::SocketAddr,
+
+    /// Connect to the given URL starting with https:// for WebTransport or moqt:// for QUIC.
+    #[arg(value_parser = moq_url)]
+    pub url: Url,
+
+    /// Present this AUTHORIZATION TOKEN to the relay in CLIENT_SETUP.
+    #[arg(long)]
+    #[serde(default)]
+    pub auth_token: Option<String>,
+
+    /// The TLS configuration.
+    #[command(flatten)]
+    #[serde(default)]
+    pub tls: Tls,
+
+    /// The QUIC transport tuning.
+    #[command(flatten)]
+    #[serde(default)]
+    pub transport: Transport,
+}
+
+impl Client {
+    pub fn new(url: Url) -> Self {
+        Self {
+            bind: Self::default_bind(),
+            url,
+            auth_token: None,
+            tls: Tls::default(),
+            transport: Transport::default(),
+        }
+    }
+
+    fn default_bind() -> net::SocketAddr {
+        "[::]:0".parse().unwrap()
+    }
+}
+
+fn moq_url(s: &str) -> Result<Url, String> {
+    let url = Url::try_from(s).map_err(|e| e.to_string())?;
+
+    // Make sure the scheme is moq
+    if url.scheme() != "https" && url.scheme() != "moqt" {
+        return Err("url scheme must be https:// for WebTransport & moqt:// for QUIC".to_string());
+    }
+
+    Ok(url)
+}
@@ -0,0 +1,70 @@
+//! Configuration shared by the relay, its library API and the client tools.
+//!
+//! Each struct is both a set of command-line arguments, flattened into a binary's clap parser,
+//! and a serde type, so embedders can keep the same settings in a config file instead.
+//! Missing fields take their defaults and unknown fields are ignored, so a file written for one
+//! version keeps loading in the next.
+mod auth;
+mod client;
+mod logs;
+mod namespaces;
+mod tls;
+mod transport;
+
+pub use auth::*;
+pub use client::*;
+pub use logs::*;
+pub use namespaces::*;
+pub use tls::*;
+pub use transport::*;
+
+use std::path::Path;
+
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+
+/// Load a configuration from the JSON file at `path`.
+pub fn load<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read config: {}", path.display()))?;
+    serde_json::from_str(&json)
+        .with_context(|| format!("failed to parse config: {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forward_compatible() {
+        // Fields added by a later version are ignored, and missing fields take their defaults.
+        let client: Client = serde_json::from_str(
+            r#"{
+                "url": "https://relay.example.com",
+                "transport": {"congestion_controller": "new-reno", "multipath": true},
+                "priority": 3
+            }"#,
+        )
+        .unwrap();
+
+        let mut expected = Client::new("https://relay.example.com".parse().unwrap());
+        expected.transport.congestion_controller = CongestionController::NewReno;
+        assert_eq!(client, expected);
+        assert_eq!(client.transport.max_idle_timeout, 10_000);
+    }
+
+    #[test]
+    fn matches_arguments() {
+        use clap::Parser;
+
+        // A config file and the command line agree on the defaults.
+        let args = NamespacePolicy::parse_from(["relay", "--announce-duplicates", "merge"]);
+        let file: NamespacePolicy = serde_json::from_str(r#"{"duplicates": "merge"}"#).unwrap();
+        assert_eq!(args, file);
+
+        let args = Auth::parse_from(["relay"]);
+        assert_eq!(args, serde_json::from_str::<Auth>("{}").unwrap());
+        assert_eq!(Logs::parse_from(["relay"]), Logs::default());
+        assert_eq!(Transport::parse_from(["relay"]), Transport::default());
+    }
+}
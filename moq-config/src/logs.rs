@@ -0,0 +1,100 @@
+use std::{path::PathBuf, time::Duration};
+
+use clap::Parser;
+use moq_transport::{
+    coding::TrackNamespace,
+    mlog::{MlogConfig, MlogEvents, MlogFormat, MlogSampling},
+};
+use serde::{Deserialize, Serialize};
+
+/// Where to write per-connection qlog and mlog files, and how to rotate and retain them.
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Logs {
+    /// Directory to write qlog files (one per connection)
+    #[arg(long)]
+    pub qlog_dir: Option<PathBuf>,
+
+    /// Directory to write mlog files (one per connection)
+    #[arg(long)]
+    pub mlog_dir: Option<PathBuf>,
+
+    /// Rotate an mlog file once it reaches this many bytes.
+    #[arg(long)]
+    pub mlog_max_size: Option<u64>,
+
+    /// Gzip mlog files when they are rotated or the connection closes.
+    #[arg(long)]
+    pub mlog_compress: bool,
+
+    /// Keep at most this many files in --mlog-dir, removing the oldest first.
+    #[arg(long)]
+    pub mlog_max_files: Option<usize>,
+
+    /// Remove files in --mlog-dir that have not been modified for this many seconds.
+    #[arg(long)]
+    pub mlog_max_age: Option<u64>,
+
+    /// Write mlog files as "json" (JSON-SEQ) or the more compact "cbor".
+    /// Convert CBOR files back to JSON with moq-mlog.
+    #[arg(long, default_value = "json")]
+    pub mlog_format: MlogFormat,
+
+    /// Log "all" events, only "control" messages, or only "data" plane objects.
+    #[arg(long, default_value = "all")]
+    pub mlog_events: MlogEvents,
+
+    /// Log one data plane object in this many per track, plus the first and last object of each
+    /// group and any anomaly, to keep mlog on for busy tracks. All objects are logged by default.
+    #[arg(long)]
+    pub mlog_sample: Option<u64>,
+
+    /// Sample the tracks under a namespace prefix at another rate, as `prefix=rate`, ex.
+    /// `live/debug=1`. The longest matching prefix applies. Can be repeated.
+    #[arg(long, value_parser = sample_namespace)]
+    pub mlog_sample_namespace: Vec<String>,
+
+    /// Directory to record the control messages of every session to, with their timing, as
+    /// scripts (one per connection) that moq-test can replay.
+    #[arg(long)]
+    pub script_dir: Option<PathBuf>,
+}
+
+impl Logs {
+    /// The rotation, retention and format of the mlog files.
+    pub fn mlog(&self) -> MlogConfig {
+        MlogConfig {
+            max_file_size: self.mlog_max_size,
+            compress: self.mlog_compress,
+            max_files: self.mlog_max_files,
+            max_age: self.mlog_max_age.map(Duration::from_secs),
+            format: self.mlog_format,
+            events: self.mlog_events,
+            sampling: MlogSampling {
+                rate: self.mlog_sample.unwrap_or(1),
+                // Checked when parsed, invalid ones from a config file are ignored.
+                namespaces: self
+                    .mlog_sample_namespace
+                    .iter()
+                    .filter_map(|value| parse_sample_namespace(value).ok())
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Parse a `prefix=rate` value of --mlog-sample-namespace.
+pub fn parse_sample_namespace(value: &str) -> Result<(TrackNamespace, u64), String> {
+    let (prefix, rate) = value
+        .rsplit_once('=')
+        .ok_or_else(|| format!("expected prefix=rate: {}", value))?;
+    let rate = rate
+        .parse()
+        .map_err(|_| format!("invalid sample rate: {}", rate))?;
+    Ok((TrackNamespace::from_utf8_path(prefix), rate))
+}
+
+fn sample_namespace(value: &str) -> Result<String, String> {
+    parse_sample_namespace(value)?;
+    Ok(value.to_string())
+}
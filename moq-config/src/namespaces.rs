@@ -0,0 +1,56 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// What to do when a namespace is announced while another publisher already serves it.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Refuse the new announce with a duplicate error.
+    #[default]
+    Reject,
+
+    /// The new publisher takes over, and the previous announces are cancelled.
+    Replace,
+
+    /// Every publisher serves the namespace, each with its own tracks.
+    /// A subscription tries the newest publisher first.
+    Merge,
+}
+
+/// How many namespaces publishers may announce, and what happens when they collide.
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamespacePolicy {
+    /// Maximum number of namespaces a single session may announce at once.
+    /// Further announces are rejected with PUBLISH_NAMESPACE_ERROR until one ends.
+    #[arg(long = "announce-max")]
+    pub max_per_session: Option<usize>,
+
+    /// Maximum number of announces from a single session registered with the coordinator at once.
+    #[arg(long = "announce-concurrency")]
+    pub concurrency: Option<usize>,
+
+    /// Maximum number of announces registered with the coordinator at once, across all sessions.
+    #[arg(long = "announce-concurrency-total")]
+    pub concurrency_total: Option<usize>,
+
+    /// What to do when a namespace is announced while another publisher serves it: reject the
+    /// new announce, replace the old publisher, or merge both, each serving its own tracks.
+    #[arg(long = "announce-duplicates", value_enum, default_value = "reject")]
+    pub duplicates: DuplicatePolicy,
+
+    /// Mark a namespace stale once its publisher, keeping its session open, sends no objects on
+    /// the subscribed tracks for this many milliseconds. Zero disables it.
+    #[arg(long = "announce-idle-timeout", default_value_t = 0)]
+    pub idle_timeout: u64,
+
+    /// Withdraw stale namespaces from the announce destinations and gossip peers, until their
+    /// publisher resumes.
+    #[arg(long = "announce-idle-withdraw")]
+    pub idle_withdraw: bool,
+
+    /// End the subscriptions to stale namespaces with PUBLISH_DONE, so subscribers needn't poll
+    /// TRACK_STATUS to learn their publisher went quiet.
+    #[arg(long = "announce-idle-end-subscriptions")]
+    pub idle_end_subscriptions: bool,
+}
@@ -0,0 +1,37 @@
+use std::path;
+
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// Where to find the TLS certificates, keys and roots.
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[group(id = "tls")]
+#[serde(default)]
+pub struct Tls {
+    /// Use the certificates at this path, encoded as PEM.
+    ///
+    /// You can use this option multiple times for multiple certificates.
+    /// The first match for the provided SNI will be used, otherwise the last cert will be used.
+    /// You also need to provide the private key multiple times via `key``.
+    #[arg(long = "tls-cert")]
+    pub cert: Vec<path::PathBuf>,
+
+    /// Use the private key at this path, encoded as PEM.
+    ///
+    /// There must be a key for every certificate provided via `cert`.
+    #[arg(long = "tls-key")]
+    pub key: Vec<path::PathBuf>,
+
+    /// Use the TLS root at this path, encoded as PEM.
+    ///
+    /// This value can be provided multiple times for multiple roots.
+    /// If this is empty, system roots will be used instead
+    #[arg(long = "tls-root")]
+    pub root: Vec<path::PathBuf>,
+
+    /// Danger: Disable TLS certificate verification.
+    ///
+    /// Fine for local development and between relays, but should be used in caution in production.
+    #[arg(long = "tls-disable-verify")]
+    pub disable_verify: bool,
+}
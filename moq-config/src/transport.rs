@@ -0,0 +1,96 @@
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+/// A congestion control algorithm.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CongestionController {
+    #[default]
+    Bbr,
+    Cubic,
+    NewReno,
+}
+
+/// QUIC transport tuning, shared by every connection on an endpoint.
+#[derive(Parser, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Transport {
+    /// The congestion control algorithm.
+    #[arg(long, value_enum, default_value = "bbr")]
+    pub congestion_controller: CongestionController,
+
+    /// The initial congestion window in bytes, instead of the algorithm's default.
+    #[arg(long)]
+    pub initial_window: Option<u64>,
+
+    /// Close connections idle for this many milliseconds, or never if 0.
+    #[arg(long, default_value = "10000")]
+    pub max_idle_timeout: u64,
+
+    /// Send a keep-alive after this many milliseconds without traffic, or never if 0.
+    #[arg(long, default_value = "4000")]
+    pub keep_alive_interval: u64,
+
+    /// Bytes of incoming datagrams to buffer before dropping them, instead of Quinn's default.
+    #[arg(long)]
+    pub datagram_receive_buffer: Option<usize>,
+
+    /// Bytes of outgoing datagrams to buffer before dropping them, instead of Quinn's default.
+    #[arg(long)]
+    pub datagram_send_buffer: Option<usize>,
+
+    /// Bytes the peer may send on each incoming stream ahead of what has been read, instead of
+    /// Quinn's default. Smaller windows push back on the sender sooner when reading falls behind.
+    #[arg(long)]
+    pub stream_receive_window: Option<u64>,
+
+    /// Delay, jitter, loss and a bandwidth cap applied to the packets we send, for development.
+    #[command(flatten)]
+    pub emulation: Emulation,
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self {
+            congestion_controller: CongestionController::Bbr,
+            initial_window: None,
+            max_idle_timeout: 10_000,
+            keep_alive_interval: 4_000,
+            datagram_receive_buffer: None,
+            datagram_send_buffer: None,
+            stream_receive_window: None,
+            emulation: Emulation::default(),
+        }
+    }
+}
+
+/// Delay, jitter, loss and a bandwidth cap applied to every packet an endpoint sends.
+/// For development only; everything is off by default.
+#[derive(Parser, Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Emulation {
+    /// Development only: delay the packets we send by this many milliseconds.
+    #[arg(long = "emulate-delay", default_value = "0")]
+    pub delay: u64,
+
+    /// Development only: delay each packet by up to this many more milliseconds, at random.
+    /// Packets are still sent in order.
+    #[arg(long = "emulate-jitter", default_value = "0")]
+    pub jitter: u64,
+
+    /// Development only: drop this percentage of the packets we send, at random.
+    #[arg(long = "emulate-loss", default_value = "0")]
+    pub loss: f64,
+
+    /// Development only: send at most this many kilobits per second, or without a cap if 0.
+    /// Packets queued for longer than 100ms behind the cap are dropped.
+    #[arg(long = "emulate-bandwidth", default_value = "0")]
+    pub bandwidth: u64,
+}
+
+impl Emulation {
+    /// Whether any packets are delayed or dropped.
+    pub fn is_enabled(&self) -> bool {
+        self.delay > 0 || self.jitter > 0 || self.loss > 0.0 || self.bandwidth > 0
+    }
+}
@@ -0,0 +1,134 @@
+/*
+ * C API for Media over QUIC clients, exported by the moq-ffi crate.
+ *
+ * Every function returning int32_t returns MOQ_OK or a negative MOQ_ERROR_* code. Handles are
+ * freed by their *_close function. Functions that block must not be called from an object
+ * callback.
+ */
+
+#ifndef MOQ_H
+#define MOQ_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The call succeeded. */
+#define MOQ_OK 0
+
+/* An argument was null, not UTF-8, or not a valid URL. */
+#define MOQ_ERROR_INVALID -1
+
+/* The session to the relay couldn't be set up. */
+#define MOQ_ERROR_CONNECT -2
+
+/* The track, subscription or session has ended. */
+#define MOQ_ERROR_CLOSED -3
+
+/* A track with this name was already created. */
+#define MOQ_ERROR_DUPLICATE -4
+
+/* The library panicked, ex. when called from an object callback. The panic is logged. */
+#define MOQ_ERROR_PANIC -5
+
+/* How many objects a subscription without a callback queues, see moq_subscribe. */
+#define MOQ_MAX_QUEUED 1024
+
+/* How moq_connect reaches the relay. Every field may be left zeroed. */
+typedef struct MoqConnectOptions {
+    /* The path of a PEM root certificate to trust instead of the platform's roots, or NULL. */
+    const char *tls_root;
+
+    /* Danger: don't verify the relay's certificate at all. */
+    bool tls_disable_verify;
+
+    /* The authorization token presented in CLIENT_SETUP, or NULL. */
+    const char *auth_token;
+} MoqConnectOptions;
+
+/* An object received on a subscription. */
+typedef struct MoqObject {
+    uint64_t group_id;
+    uint64_t object_id;
+    const uint8_t *payload;
+    size_t payload_len;
+
+    /* Private, freed by moq_object_free. */
+    void *owner;
+} MoqObject;
+
+/*
+ * Called with each object of a subscription, on a thread of the session. The object is only
+ * valid for the duration of the call.
+ */
+typedef void (*MoqObjectCallback)(void *user_data, const MoqObject *object);
+
+typedef struct MoqSession MoqSession;
+typedef struct MoqBroadcast MoqBroadcast;
+typedef struct MoqTrack MoqTrack;
+typedef struct MoqSubscription MoqSubscription;
+
+/*
+ * Connect to the relay at `url`, ex. "https://relay.example.com", blocking until the session is
+ * set up, and write its handle to `out`. `options` may be NULL.
+ */
+int32_t moq_connect(const char *url, const MoqConnectOptions *options, MoqSession **out);
+
+/* Close the session, ending its announces and subscriptions. Their handles must still be closed. */
+void moq_close(MoqSession *session);
+
+/* Announce `namespace`, ex. "live/room", and write the handle to create its tracks to `out`. */
+int32_t moq_announce(MoqSession *session, const char *namespace_, MoqBroadcast **out);
+
+/* Unannounce the namespace. */
+void moq_broadcast_close(MoqBroadcast *broadcast);
+
+/* Create the track `name` in an announced namespace, and write its handle to `out`. */
+int32_t moq_track_create(MoqBroadcast *broadcast, const char *name, MoqTrack **out);
+
+/*
+ * Write an object of `payload_len` bytes to the track, copying the payload. It starts a new
+ * group if `new_group` is set or no group was started yet, ex. on each keyframe.
+ */
+int32_t moq_track_write(MoqTrack *track, const uint8_t *payload, size_t payload_len,
+                        bool new_group);
+
+/* End the track. */
+void moq_track_close(MoqTrack *track);
+
+/*
+ * Subscribe to the track `name` in `namespace`, and write the subscription's handle to `out`.
+ *
+ * Each object is passed to `callback` with `user_data` if it's set, from another thread until
+ * the subscription is closed, and otherwise queued for moq_subscription_read. Up to
+ * MOQ_MAX_QUEUED objects are queued: once full, the oldest group is dropped, or the oldest
+ * object if only one group is queued.
+ */
+int32_t moq_subscribe(MoqSession *session, const char *namespace_, const char *name,
+                      MoqObjectCallback callback, void *user_data, MoqSubscription **out);
+
+/*
+ * Block until the subscription's next object and write it to `out`, to be freed with
+ * moq_object_free. Returns MOQ_ERROR_CLOSED once the track ended, or right away if the
+ * subscription has a callback.
+ */
+int32_t moq_subscription_read(MoqSubscription *subscription, MoqObject *out);
+
+/* Free the payload of an object returned by moq_subscription_read. */
+void moq_object_free(MoqObject *object);
+
+/*
+ * Unsubscribe, waiting for a callback already running to return, so `user_data` may be freed
+ * afterwards. Must not be called from its own object callback.
+ */
+void moq_subscription_close(MoqSubscription *subscription);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MOQ_H */
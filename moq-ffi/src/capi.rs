@@ -0,0 +1,561 @@
+use std::{
+    ffi::{c_char, c_void, CStr},
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    ptr, slice,
+};
+
+use bytes::Bytes;
+use moq_transport::serve::ServeError;
+use url::Url;
+
+use crate::{Broadcast, ConnectOptions, Deliver, Object, Session, Subscription, Track};
+
+/// The call succeeded.
+pub const MOQ_OK: i32 = 0;
+
+/// An argument was null, not UTF-8, or not a valid URL.
+pub const MOQ_ERROR_INVALID: i32 = -1;
+
+/// The session to the relay couldn't be set up.
+pub const MOQ_ERROR_CONNECT: i32 = -2;
+
+/// The track, subscription or session has ended.
+pub const MOQ_ERROR_CLOSED: i32 = -3;
+
+/// A track with this name was already created.
+pub const MOQ_ERROR_DUPLICATE: i32 = -4;
+
+/// The library panicked, ex. when called from an object callback. The panic is logged.
+pub const MOQ_ERROR_PANIC: i32 = -5;
+
+/// How many objects a subscription without a callback queues, see [moq_subscribe].
+pub const MOQ_MAX_QUEUED: usize = Subscription::MAX_QUEUED;
+
+/// How [moq_connect] reaches the relay. Every field may be left zeroed.
+#[repr(C)]
+pub struct MoqConnectOptions {
+    /// The path of a PEM root certificate to trust instead of the platform's roots, or null.
+    pub tls_root: *const c_char,
+
+    /// Danger: don't verify the relay's certificate at all.
+    pub tls_disable_verify: bool,
+
+    /// The authorization token presented in CLIENT_SETUP, or null.
+    pub auth_token: *const c_char,
+}
+
+/// An object received on a subscription.
+#[repr(C)]
+pub struct MoqObject {
+    pub group_id: u64,
+    pub object_id: u64,
+    pub payload: *const u8,
+    pub payload_len: usize,
+
+    // The payload's buffer, kept until moq_object_free. Null for objects passed to a callback.
+    owner: *mut c_void,
+}
+
+impl MoqObject {
+    // Borrow `object`'s payload, for as long as `object` lives.
+    fn borrowed(object: &Object) -> Self {
+        Self {
+            group_id: object.group_id,
+            object_id: object.object_id,
+            payload: object.payload.as_ptr(),
+            payload_len: object.payload.len(),
+            owner: ptr::null_mut(),
+        }
+    }
+
+    // Take `object`'s payload, until moq_object_free.
+    fn owned(object: Object) -> Self {
+        let payload = Box::new(object.payload);
+        Self {
+            group_id: object.group_id,
+            object_id: object.object_id,
+            payload: payload.as_ptr(),
+            payload_len: payload.len(),
+            owner: Box::into_raw(payload).cast(),
+        }
+    }
+}
+
+/// Called with each object of a subscription, on a thread of the session. The object is only
+/// valid for the duration of the call.
+pub type MoqObjectCallback =
+    Option<unsafe extern "C" fn(user_data: *mut c_void, object: *const MoqObject)>;
+
+/// A session to a relay, see [moq_connect].
+pub struct MoqSession(Session);
+
+/// An announced namespace, see [moq_announce].
+pub struct MoqBroadcast(Broadcast);
+
+/// A track of an announced namespace, see [moq_track_create].
+pub struct MoqTrack(Track);
+
+/// A subscription to a track, see [moq_subscribe].
+pub struct MoqSubscription(Subscription);
+
+// The user data of a callback, which the caller promises may be used from another thread.
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+impl UserData {
+    fn get(&self) -> *mut c_void {
+        self.0
+    }
+}
+
+// A C string argument, None if null or not UTF-8.
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Option<&'a str> {
+    match ptr.is_null() {
+        true => None,
+        false => CStr::from_ptr(ptr).to_str().ok(),
+    }
+}
+
+// Write a new handle to `out`, if it's not null.
+unsafe fn set_out<T>(out: *mut *mut T, handle: T) -> i32 {
+    match out.is_null() {
+        true => MOQ_ERROR_INVALID,
+        false => {
+            *out = Box::into_raw(Box::new(handle));
+            MOQ_OK
+        }
+    }
+}
+
+// Run the body of a C function, returning MOQ_ERROR_PANIC instead of unwinding into the caller,
+// which would abort the process.
+fn catch(f: impl FnOnce() -> i32) -> i32 {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        log::error!("panicked in the C API");
+        MOQ_ERROR_PANIC
+    })
+}
+
+fn error_code(err: &ServeError) -> i32 {
+    match err {
+        ServeError::Duplicate => MOQ_ERROR_DUPLICATE,
+        _ => MOQ_ERROR_CLOSED,
+    }
+}
+
+/// Connect to the relay at `url`, ex. `https://relay.example.com`, blocking until the session is
+/// set up, and write its handle to `out`. `options` may be null.
+///
+/// # Safety
+///
+/// `url` must be a NUL-terminated string, `options` null or valid, and `out` writable. Must not
+/// be called from an object callback.
+#[no_mangle]
+pub unsafe extern "C" fn moq_connect(
+    url: *const c_char,
+    options: *const MoqConnectOptions,
+    out: *mut *mut MoqSession,
+) -> i32 {
+    catch(|| {
+        let Some(url) = str_arg(url).and_then(|url| Url::parse(url).ok()) else {
+            return MOQ_ERROR_INVALID;
+        };
+
+        let mut config = ConnectOptions::default();
+        if let Some(options) = options.as_ref() {
+            config.tls_root = str_arg(options.tls_root).map(PathBuf::from);
+            config.tls_disable_verify = options.tls_disable_verify;
+            config.auth_token = str_arg(options.auth_token).map(str::to_string);
+        }
+
+        match Session::connect(&url, config) {
+            Ok(session) => set_out(out, MoqSession(session)),
+            Err(err) => {
+                log::warn!("failed to connect to {}: {:#}", url, err);
+                MOQ_ERROR_CONNECT
+            }
+        }
+    })
+}
+
+/// Close the session, ending its announces and subscriptions. Their handles must still be closed.
+///
+/// # Safety
+///
+/// `session` must be null or returned by [moq_connect], and not used afterwards. Must not be
+/// called from an object callback.
+#[no_mangle]
+pub unsafe extern "C" fn moq_close(session: *mut MoqSession) {
+    catch(|| {
+        if !session.is_null() {
+            drop(Box::from_raw(session));
+        }
+        MOQ_OK
+    });
+}
+
+/// Announce `namespace`, ex. `live/room`, and write the handle to create its tracks to `out`.
+///
+/// # Safety
+///
+/// `session` must be valid, `namespace` a NUL-terminated string, and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn moq_announce(
+    session: *mut MoqSession,
+    namespace: *const c_char,
+    out: *mut *mut MoqBroadcast,
+) -> i32 {
+    catch(|| {
+        let (Some(session), Some(namespace)) = (session.as_ref(), str_arg(namespace)) else {
+            return MOQ_ERROR_INVALID;
+        };
+
+        set_out(out, MoqBroadcast(session.0.announce(namespace)))
+    })
+}
+
+/// Unannounce the namespace.
+///
+/// # Safety
+///
+/// `broadcast` must be null or returned by [moq_announce], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn moq_broadcast_close(broadcast: *mut MoqBroadcast) {
+    catch(|| {
+        if !broadcast.is_null() {
+            drop(Box::from_raw(broadcast));
+        }
+        MOQ_OK
+    });
+}
+
+/// Create the track `name` in an announced namespace, and write its handle to `out`.
+///
+/// # Safety
+///
+/// `broadcast` must be valid, `name` a NUL-terminated string, and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn moq_track_create(
+    broadcast: *mut MoqBroadcast,
+    name: *const c_char,
+    out: *mut *mut MoqTrack,
+) -> i32 {
+    catch(|| {
+        let (Some(broadcast), Some(name)) = (broadcast.as_mut(), str_arg(name)) else {
+            return MOQ_ERROR_INVALID;
+        };
+
+        match broadcast.0.track(name) {
+            Ok(track) => set_out(out, MoqTrack(track)),
+            Err(err) => error_code(&err),
+        }
+    })
+}
+
+/// Write an object of `payload_len` bytes to the track, copying the payload. It starts a new
+/// group if `new_group` is set or no group was started yet, ex. on each keyframe.
+///
+/// # Safety
+///
+/// `track` must be valid, and `payload` readable for `payload_len` bytes, or null if it's 0.
+#[no_mangle]
+pub unsafe extern "C" fn moq_track_write(
+    track: *mut MoqTrack,
+    payload: *const u8,
+    payload_len: usize,
+    new_group: bool,
+) -> i32 {
+    catch(|| {
+        let Some(track) = track.as_mut() else {
+            return MOQ_ERROR_INVALID;
+        };
+        let payload = match (payload.is_null(), payload_len) {
+            (_, 0) => Bytes::new(),
+            (true, _) => return MOQ_ERROR_INVALID,
+            (false, len) => Bytes::copy_from_slice(slice::from_raw_parts(payload, len)),
+        };
+
+        match track.0.write(payload, new_group) {
+            Ok(()) => MOQ_OK,
+            Err(err) => error_code(&err),
+        }
+    })
+}
+
+/// End the track.
+///
+/// # Safety
+///
+/// `track` must be null or returned by [moq_track_create], and not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn moq_track_close(track: *mut MoqTrack) {
+    catch(|| {
+        if !track.is_null() {
+            drop(Box::from_raw(track));
+        }
+        MOQ_OK
+    });
+}
+
+/// Subscribe to the track `name` in `namespace`, and write the subscription's handle to `out`.
+///
+/// Each object is passed to `callback` with `user_data` if it's set, and otherwise queued for
+/// [moq_subscription_read], up to [MOQ_MAX_QUEUED] objects before the oldest group is dropped.
+///
+/// # Safety
+///
+/// `session` must be valid, `namespace` and `name` NUL-terminated strings, and `out` writable.
+/// The callback may be called from another thread until the subscription is closed, with
+/// `user_data` valid for as long.
+#[no_mangle]
+pub unsafe extern "C" fn moq_subscribe(
+    session: *mut MoqSession,
+    namespace: *const c_char,
+    name: *const c_char,
+    callback: MoqObjectCallback,
+    user_data: *mut c_void,
+    out: *mut *mut MoqSubscription,
+) -> i32 {
+    catch(|| {
+        let (Some(session), Some(namespace), Some(name)) =
+            (session.as_ref(), str_arg(namespace), str_arg(name))
+        else {
+            return MOQ_ERROR_INVALID;
+        };
+
+        let deliver = callback.map(|callback| {
+            let user_data = UserData(user_data);
+            Box::new(move |object: Object| {
+                let object = MoqObject::borrowed(&object);
+                callback(user_data.get(), &object);
+            }) as Deliver
+        });
+
+        set_out(
+            out,
+            MoqSubscription(session.0.subscribe(namespace, name, deliver)),
+        )
+    })
+}
+
+/// Block until the subscription's next object and write it to `out`, to be freed with
+/// [moq_object_free]. Returns [MOQ_ERROR_CLOSED] once the track ended, or right away if the
+/// subscription has a callback.
+///
+/// # Safety
+///
+/// `subscription` must be valid, and `out` writable. Must not be called from an object callback.
+#[no_mangle]
+pub unsafe extern "C" fn moq_subscription_read(
+    subscription: *mut MoqSubscription,
+    out: *mut MoqObject,
+) -> i32 {
+    catch(|| {
+        let Some(subscription) = subscription.as_mut() else {
+            return MOQ_ERROR_INVALID;
+        };
+        if out.is_null() {
+            return MOQ_ERROR_INVALID;
+        }
+
+        match subscription.0.read() {
+            Some(object) => {
+                *out = MoqObject::owned(object);
+                MOQ_OK
+            }
+            None => MOQ_ERROR_CLOSED,
+        }
+    })
+}
+
+/// Free the payload of an object returned by [moq_subscription_read].
+///
+/// # Safety
+///
+/// `object` must be null or written by [moq_subscription_read], and its payload not used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn moq_object_free(object: *mut MoqObject) {
+    catch(|| {
+        let Some(object) = object.as_mut() else {
+            return MOQ_OK;
+        };
+
+        if !object.owner.is_null() {
+            drop(Box::from_raw(object.owner.cast::<Bytes>()));
+        }
+        object.owner = ptr::null_mut();
+        object.payload = ptr::null();
+        object.payload_len = 0;
+        MOQ_OK
+    });
+}
+
+/// Unsubscribe, waiting for a callback already running to return, so `user_data` may be freed
+/// afterwards.
+///
+/// # Safety
+///
+/// `subscription` must be null or returned by [moq_subscribe], and not used afterwards. Must not
+/// be called from its own object callback.
+#[no_mangle]
+pub unsafe extern "C" fn moq_subscription_close(subscription: *mut MoqSubscription) {
+    catch(|| {
+        if !subscription.is_null() {
+            drop(Box::from_raw(subscription));
+        }
+        MOQ_OK
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::CString,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    };
+
+    use moq_test::{MemoryCoordinator, TestRelay, TIMEOUT};
+
+    use super::*;
+
+    unsafe extern "C" fn count(user_data: *mut c_void, object: *const MoqObject) {
+        let count = &*(user_data as *const AtomicUsize);
+        if (*object).payload_len > 0 {
+            count.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn catches_panics() {
+        assert_eq!(catch(|| panic!("bug")), MOQ_ERROR_PANIC);
+        assert_eq!(catch(|| MOQ_OK), MOQ_OK);
+    }
+
+    #[test]
+    fn publishes_and_subscribes() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let coordinator = MemoryCoordinator::new();
+        let relay = runtime.block_on(TestRelay::start(&coordinator)).unwrap();
+
+        let url = CString::new(relay.ip_url().to_string()).unwrap();
+        let root = CString::new(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../moq-test/testdata/localhost.crt"
+        ))
+        .unwrap();
+        let options = MoqConnectOptions {
+            tls_root: root.as_ptr(),
+            // The certificate is for localhost, not the relay's IP address.
+            tls_disable_verify: true,
+            auth_token: ptr::null(),
+        };
+        let namespace = CString::new("live").unwrap();
+        let name = CString::new("video").unwrap();
+
+        unsafe {
+            let mut session = ptr::null_mut();
+            assert_eq!(
+                moq_connect(ptr::null(), &options, &mut session),
+                MOQ_ERROR_INVALID
+            );
+            // Connecting from an object callback would block a thread of the runtime.
+            let entered = runtime.enter();
+            assert_eq!(
+                moq_connect(url.as_ptr(), &options, &mut session),
+                MOQ_ERROR_CONNECT
+            );
+            drop(entered);
+            assert_eq!(moq_connect(url.as_ptr(), &options, &mut session), MOQ_OK);
+
+            let mut broadcast = ptr::null_mut();
+            assert_eq!(
+                moq_announce(session, namespace.as_ptr(), &mut broadcast),
+                MOQ_OK
+            );
+            let mut track = ptr::null_mut();
+            assert_eq!(
+                moq_track_create(broadcast, name.as_ptr(), &mut track),
+                MOQ_OK
+            );
+            assert_eq!(
+                moq_track_create(broadcast, name.as_ptr(), &mut ptr::null_mut()),
+                MOQ_ERROR_DUPLICATE
+            );
+
+            let deadline = Instant::now() + TIMEOUT;
+            while relay.admin().namespaces().is_empty() {
+                assert!(Instant::now() < deadline, "namespace never announced");
+                std::thread::sleep(Duration::from_millis(10));
+            }
+
+            let mut viewer = ptr::null_mut();
+            assert_eq!(moq_connect(url.as_ptr(), &options, &mut viewer), MOQ_OK);
+            let counted = AtomicUsize::new(0);
+            let (mut pulled, mut pushed) = (ptr::null_mut(), ptr::null_mut());
+            assert_eq!(
+                moq_subscribe(
+                    viewer,
+                    namespace.as_ptr(),
+                    name.as_ptr(),
+                    None,
+                    ptr::null_mut(),
+                    &mut pulled,
+                ),
+                MOQ_OK
+            );
+            assert_eq!(
+                moq_subscribe(
+                    viewer,
+                    namespace.as_ptr(),
+                    name.as_ptr(),
+                    Some(count),
+                    &counted as *const AtomicUsize as *mut c_void,
+                    &mut pushed,
+                ),
+                MOQ_OK
+            );
+
+            // Objects written before the relay subscribes upstream are missed, so keep writing.
+            for group_id in 0.. {
+                assert!(Instant::now() < deadline, "callback never called");
+                let payload = group_id.to_string();
+                assert_eq!(
+                    moq_track_write(track, payload.as_ptr(), payload.len(), true),
+                    MOQ_OK
+                );
+                if counted.load(Ordering::SeqCst) >= 3 {
+                    break;
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+
+            let mut object = std::mem::zeroed::<MoqObject>();
+            assert_eq!(moq_subscription_read(pulled, &mut object), MOQ_OK);
+            let payload = slice::from_raw_parts(object.payload, object.payload_len);
+            assert_eq!(payload, object.group_id.to_string().as_bytes());
+            moq_object_free(&mut object);
+            assert!(object.payload.is_null());
+
+            // The callback isn't called anymore once closed.
+            moq_subscription_close(pushed);
+            let closed = counted.load(Ordering::SeqCst);
+            for _ in 0..3 {
+                let payload = b"after";
+                assert_eq!(
+                    moq_track_write(track, payload.as_ptr(), payload.len(), true),
+                    MOQ_OK
+                );
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            assert_eq!(counted.load(Ordering::SeqCst), closed);
+            moq_subscription_close(pulled);
+            moq_close(viewer);
+            moq_track_close(track);
+            moq_broadcast_close(broadcast);
+            moq_close(session);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+//! A blocking client API for Media over QUIC, and a C API over it for native apps, ex. an OBS
+//! plugin or a mobile SDK, that can't use the async Rust crates.
+//!
+//! A [Session] runs on a Tokio runtime of its own, so it can be driven from any thread. With the
+//! `capi` feature (enabled by default), the functions declared in `include/moq.h` are exported
+//! from the `cdylib` and `staticlib` builds of this crate.
+
+#[cfg(feature = "capi")]
+mod capi;
+mod session;
+
+#[cfg(feature = "capi")]
+pub use capi::*;
+pub use session::*;
@@ -0,0 +1,466 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    path::PathBuf,
+    sync::{Arc, Condvar, Mutex, PoisonError},
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_native_ietf::{quic, tls};
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    serve::{self, ServeError, TrackReaderMode},
+    session::{Publisher, SessionError, Subscribe, Subscriber},
+};
+use tokio::{runtime, sync::mpsc, task::JoinHandle};
+use url::Url;
+
+/// How [Session::connect] reaches the relay.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectOptions {
+    /// Trust this root certificate, encoded as PEM, instead of the platform's roots.
+    pub tls_root: Option<PathBuf>,
+
+    /// Danger: don't verify the relay's certificate at all.
+    pub tls_disable_verify: bool,
+
+    /// The authorization token presented in CLIENT_SETUP.
+    pub auth_token: Option<String>,
+}
+
+/// Called with each object of a [Subscription], see [Session::subscribe].
+pub type Deliver = Box<dyn FnMut(Object) + Send>;
+
+/// An object received on a [Subscription].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Object {
+    pub group_id: u64,
+    pub object_id: u64,
+    pub payload: Bytes,
+}
+
+/// A session to a relay, running on a runtime of its own so it can be driven from threads that
+/// know nothing about async Rust, ex. through the C API.
+///
+/// Every method must be called outside of a Tokio runtime. The session ends when this is dropped.
+pub struct Session {
+    // Taken when dropped, as that must not block on a thread of the runtime.
+    runtime: Option<runtime::Runtime>,
+    handle: runtime::Handle,
+    publisher: Publisher,
+    subscriber: Subscriber,
+    session: JoinHandle<Result<(), SessionError>>,
+}
+
+impl Session {
+    // Enough for the session and the tasks reading subscriptions, without taking over the app.
+    const WORKER_THREADS: usize = 2;
+
+    /// Connect to the relay at `url`, blocking until the session is set up.
+    pub fn connect(url: &Url, options: ConnectOptions) -> anyhow::Result<Self> {
+        // Blocking on a runtime from another one would panic.
+        anyhow::ensure!(
+            runtime::Handle::try_current().is_err(),
+            "can't connect from a Tokio runtime, ex. in an object callback"
+        );
+
+        let runtime = runtime::Builder::new_multi_thread()
+            .worker_threads(Self::WORKER_THREADS)
+            .thread_name("moq-ffi")
+            .enable_all()
+            .build()
+            .context("failed to start runtime")?;
+
+        let tls = tls::Config::load(&tls::Args {
+            root: options.tls_root.into_iter().collect(),
+            disable_verify: options.tls_disable_verify,
+            ..Default::default()
+        })?;
+        let token = options
+            .auth_token
+            .map(|token| Token::new(0, token.into_bytes()));
+
+        let (session, publisher, subscriber) = runtime.block_on(async {
+            let quic = quic::Endpoint::new(quic::Config::new("[::]:0".parse()?, None, tls))?;
+
+            log::info!("connecting to relay: url={}", url);
+            let (session, connection_id, _) = quic.client.connect(url, None).await?;
+            log::info!("connected with CID: {}", connection_id);
+
+            let session = moq_transport::session::Session::connect_with_token(session, None, token)
+                .await
+                .context("failed to set up MoQ Transport session")?;
+            anyhow::Ok(session)
+        })?;
+        let session = runtime.spawn(session.run());
+
+        Ok(Self {
+            handle: runtime.handle().clone(),
+            runtime: Some(runtime),
+            publisher,
+            subscriber,
+            session,
+        })
+    }
+
+    /// Announce `namespace`, serving the tracks created on the returned [Broadcast].
+    pub fn announce(&self, namespace: &str) -> Broadcast {
+        let namespace = TrackNamespace::from_utf8_path(namespace);
+        let (tracks, requests, reader) = serve::Tracks::new(namespace.clone()).produce();
+
+        let mut publisher = self.publisher.clone();
+        let announce = self.handle.spawn(async move {
+            if let Err(err) = publisher.announce(reader).await {
+                log::warn!("announce of {} ended: {}", namespace, err);
+            }
+        });
+
+        Broadcast {
+            tracks,
+            names: HashSet::new(),
+            _requests: requests,
+            announce,
+        }
+    }
+
+    /// Subscribe to `name` in `namespace`. Each object is passed to `deliver` on a thread of the
+    /// session if set, and queued for [Subscription::read] otherwise. Once
+    /// [Subscription::MAX_QUEUED] objects are queued, the oldest group is dropped.
+    pub fn subscribe(&self, namespace: &str, name: &str, deliver: Option<Deliver>) -> Subscription {
+        let track = serve::Track::new(TrackNamespace::from_utf8_path(namespace), name.into());
+        let (writer, reader) = track.produce();
+
+        // SUBSCRIBE is queued on the control stream, so this doesn't need the runtime.
+        let subscribe = self.subscriber.clone().subscribe_handle(writer);
+
+        // Closing the subscription takes the callback, waiting for it to return if it's running.
+        let callback = deliver.map(|deliver| Arc::new(Mutex::new(Some(deliver))));
+
+        let objects = Arc::new(ObjectQueue::default());
+        let deliver: Deliver = match callback.clone() {
+            Some(callback) => Box::new(move |object| {
+                let mut callback = callback.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some(callback) = callback.as_mut() {
+                    callback(object);
+                }
+            }),
+            None => {
+                let queue = QueueWriter(objects.clone());
+                Box::new(move |object| queue.0.push(object))
+            }
+        };
+        if callback.is_some() {
+            objects.close();
+        }
+
+        let receive = self.handle.spawn(async move {
+            if let Err(err) = receive(reader, deliver).await {
+                log::debug!("subscription ended: {}", err);
+            }
+        });
+
+        Subscription {
+            _subscribe: subscribe,
+            objects,
+            callback,
+            receive,
+        }
+    }
+
+    /// Close the session, ending its announces and subscriptions.
+    pub fn close(self) {
+        drop(self)
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.session.abort();
+
+        // Dropping the runtime waits for its threads, which panics from a thread of a runtime.
+        if let Some(runtime) = self.runtime.take() {
+            match runtime::Handle::try_current() {
+                Ok(_) => runtime.shutdown_background(),
+                Err(_) => drop(runtime),
+            }
+        }
+    }
+}
+
+/// An announced namespace, see [Session::announce]. Unannounced when dropped.
+pub struct Broadcast {
+    tracks: serve::TracksWriter,
+    // The tracks created so far, which TracksWriter would silently replace.
+    names: HashSet<String>,
+    // Held so subscriptions to tracks not created yet wait for them.
+    _requests: serve::TracksRequest,
+    announce: JoinHandle<()>,
+}
+
+impl Broadcast {
+    /// Create a track in the namespace, written to with [Track::write].
+    pub fn track(&mut self, name: &str) -> Result<Track, ServeError> {
+        if self.names.contains(name) {
+            return Err(ServeError::Duplicate);
+        }
+
+        let track = self.tracks.create(name).ok_or(ServeError::Cancel)?;
+        self.names.insert(name.to_string());
+        Ok(Track {
+            subgroups: track.subgroups()?,
+            group: None,
+        })
+    }
+}
+
+impl Drop for Broadcast {
+    fn drop(&mut self) {
+        self.announce.abort();
+    }
+}
+
+/// A track of a [Broadcast], sent as one subgroup per group.
+pub struct Track {
+    subgroups: serve::SubgroupsWriter,
+    group: Option<serve::SubgroupWriter>,
+}
+
+impl Track {
+    /// Append an object to the current group, or to a new group if `new_group` is set or no
+    /// group was started yet, ex. on each keyframe.
+    pub fn write(&mut self, payload: Bytes, new_group: bool) -> Result<(), ServeError> {
+        let group = match &mut self.group {
+            Some(group) if !new_group => group,
+            group => group.insert(self.subgroups.append(0)?),
+        };
+
+        group.write(payload)
+    }
+}
+
+/// A subscription to a track, see [Session::subscribe]. Unsubscribed when dropped, after which
+/// its callback is never called again.
+pub struct Subscription {
+    _subscribe: Subscribe,
+    objects: Arc<ObjectQueue>,
+    callback: Option<Arc<Mutex<Option<Deliver>>>>,
+    receive: JoinHandle<()>,
+}
+
+impl Subscription {
+    /// How many objects are queued for [Self::read] before the oldest group is dropped.
+    pub const MAX_QUEUED: usize = 1024;
+
+    /// Block until the next object, or None once the track ended. Objects passed to a
+    /// callback are never returned here.
+    pub fn read(&mut self) -> Option<Object> {
+        self.objects.pop()
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // Blocks until a callback already running on the session's threads returns.
+        if let Some(callback) = &self.callback {
+            callback
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+        }
+        self.receive.abort();
+    }
+}
+
+// The objects waiting for Subscription::read, closed once the track ended.
+#[derive(Default)]
+struct ObjectQueue {
+    state: Mutex<ObjectQueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct ObjectQueueState {
+    objects: VecDeque<Object>,
+    // Objects of older groups are dropped, as their group was.
+    first_group: u64,
+    closed: bool,
+}
+
+impl ObjectQueue {
+    // Queue an object, dropping the oldest group if the queue is full. If only one group is
+    // queued, its oldest object is dropped instead.
+    fn push(&self, object: Object) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if object.group_id < state.first_group {
+            return;
+        }
+
+        if state.objects.len() >= Subscription::MAX_QUEUED {
+            let oldest = state.objects.front().map_or(0, |object| object.group_id);
+            match state.objects.iter().all(|object| object.group_id == oldest) {
+                true => drop(state.objects.pop_front()),
+                false => {
+                    state.objects.retain(|object| object.group_id != oldest);
+                    state.first_group = oldest + 1;
+                    log::debug!("reading too slowly, dropped group {}", oldest);
+                }
+            }
+        }
+
+        if object.group_id >= state.first_group {
+            state.objects.push_back(object);
+        }
+        self.ready.notify_one();
+    }
+
+    // Wait for the next object, or None once closed and empty.
+    fn pop(&self) -> Option<Object> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(object) = state.objects.pop_front() {
+                return Some(object);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn close(&self) {
+        self.state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .closed = true;
+        self.ready.notify_all();
+    }
+}
+
+// Closes the queue once the subscription's task is done with it, however it ends.
+struct QueueWriter(Arc<ObjectQueue>);
+
+impl Drop for QueueWriter {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+// Pass every object of `track` to `deliver`, in the order they are received.
+async fn receive(track: serve::TrackReader, mut deliver: Deliver) -> Result<(), ServeError> {
+    match track.mode().await? {
+        TrackReaderMode::Datagrams(mut datagrams) => {
+            while let Some(datagram) = datagrams.read().await? {
+                deliver(datagram.into());
+            }
+        }
+        TrackReaderMode::Subgroups(mut subgroups) => {
+            let mut datagrams = subgroups.datagrams();
+            let (send, mut objects) = mpsc::unbounded_channel();
+            let mut tasks = FuturesUnordered::new();
+
+            loop {
+                tokio::select! {
+                    res = subgroups.next() => match res? {
+                        Some(subgroup) => tasks.push(receive_subgroup(subgroup, send.clone())),
+                        None => break,
+                    },
+                    res = datagrams.read() => match res? {
+                        Some(datagram) => deliver(datagram.into()),
+                        None => break,
+                    },
+                    Some(object) = objects.recv() => deliver(object),
+                    // A subgroup cut short, ex. skipped by the relay, doesn't end the track.
+                    Some(res) = tasks.next() => if let Err(err) = res {
+                        log::debug!("subscription lost a subgroup: {}", err);
+                    },
+                }
+            }
+
+            // Deliver what the subgroups still had.
+            drop(send);
+            while tasks.next().await.is_some() {}
+            while let Ok(object) = objects.try_recv() {
+                deliver(object);
+            }
+        }
+        TrackReaderMode::Stream(_) => return Err(ServeError::Mode),
+    }
+
+    Ok(())
+}
+
+async fn receive_subgroup(
+    mut subgroup: serve::SubgroupReader,
+    send: mpsc::UnboundedSender<Object>,
+) -> Result<(), ServeError> {
+    while let Some(mut object) = subgroup.next().await? {
+        let _ = send.send(Object {
+            group_id: subgroup.group_id,
+            object_id: object.object_id,
+            payload: object.read_all().await?,
+        });
+    }
+
+    Ok(())
+}
+
+impl From<serve::Datagram> for Object {
+    fn from(datagram: serve::Datagram) -> Self {
+        Self {
+            group_id: datagram.group_id,
+            object_id: datagram.object_id,
+            payload: datagram.payload,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(group_id: u64, object_id: u64) -> Object {
+        Object {
+            group_id,
+            object_id,
+            payload: Bytes::new(),
+        }
+    }
+
+    #[test]
+    fn drops_oldest_group() {
+        let queue = ObjectQueue::default();
+        for object_id in 0..Subscription::MAX_QUEUED as u64 - 1 {
+            queue.push(object(0, object_id));
+        }
+        queue.push(object(1, 0));
+
+        // Full, so group 0 is dropped, along with its objects arriving late.
+        queue.push(object(1, 1));
+        queue.push(object(0, 5000));
+        queue.close();
+
+        let objects: Vec<_> = std::iter::from_fn(|| queue.pop()).collect();
+        assert_eq!(objects, [object(1, 0), object(1, 1)]);
+    }
+
+    #[test]
+    fn drops_oldest_object() {
+        let queue = Arc::new(ObjectQueue::default());
+        let writer = QueueWriter(queue.clone());
+        for object_id in 0..Subscription::MAX_QUEUED as u64 + 1 {
+            writer.0.push(object(0, object_id));
+        }
+
+        // Dropping the writer closes the queue.
+        drop(writer);
+        assert_eq!(queue.pop(), Some(object(0, 1)));
+        assert_eq!(
+            std::iter::from_fn(|| queue.pop()).count(),
+            Subscription::MAX_QUEUED - 1
+        );
+    }
+}
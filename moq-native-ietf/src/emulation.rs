@@ -0,0 +1,219 @@
+//! Network emulation for development, so clients can be tested against a local relay that
+//! behaves like a real network, without tc/netem privileges.
+//!
+//! Applied to the UDP packets an endpoint sends, underneath QUIC and WebTransport, so QUIC's own
+//! loss recovery and congestion control react to it as they would on a real path.
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use quinn::udp::{EcnCodepoint, RecvMeta, Transmit};
+use quinn::{AsyncUdpSocket, UdpPoller};
+use tokio::{sync::mpsc, time::Instant};
+
+pub use moq_config::Emulation;
+
+// How long packets may queue behind the bandwidth cap before being dropped, like a router buffer.
+const QUEUE: Duration = Duration::from_millis(100);
+
+/// Wrap `socket` to apply `emulation` to the packets sent on it.
+pub(crate) fn wrap(
+    emulation: &Emulation,
+    socket: Arc<dyn AsyncUdpSocket>,
+) -> Arc<dyn AsyncUdpSocket> {
+    log::warn!(
+        "emulating a network for packets sent from {:?}: {:?}",
+        socket.local_addr(),
+        emulation
+    );
+
+    let (queue, packets) = mpsc::unbounded_channel();
+    tokio::spawn(send(socket.clone(), packets));
+
+    let now = Instant::now();
+    Arc::new(EmulatedSocket {
+        inner: socket,
+        emulation: emulation.clone(),
+        schedule: Mutex::new(Schedule {
+            free: now,
+            last: now,
+        }),
+        queue,
+    })
+}
+
+#[derive(Debug)]
+struct Schedule {
+    // When the emulated link finishes sending the packets queued so far.
+    free: Instant,
+    // When the latest packet is due, so jitter doesn't reorder packets.
+    last: Instant,
+}
+
+#[derive(Debug)]
+struct Packet {
+    due: Instant,
+    destination: SocketAddr,
+    ecn: Option<EcnCodepoint>,
+    src_ip: Option<IpAddr>,
+    contents: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct EmulatedSocket {
+    inner: Arc<dyn AsyncUdpSocket>,
+    emulation: Emulation,
+    schedule: Mutex<Schedule>,
+    queue: mpsc::UnboundedSender<Packet>,
+}
+
+impl EmulatedSocket {
+    // When `size` bytes sent now arrive, or None if the packet is lost.
+    fn due(&self, size: usize) -> Option<Instant> {
+        if self.emulation.loss > 0.0 && rand::random::<f64>() * 100.0 < self.emulation.loss {
+            return None;
+        }
+
+        let now = Instant::now();
+        let mut schedule = self.schedule.lock().unwrap();
+
+        let mut sent = now;
+        if self.emulation.bandwidth > 0 {
+            let start = schedule.free.max(now);
+            if start - now > QUEUE {
+                return None;
+            }
+
+            let bits = size as f64 * 8.0;
+            schedule.free =
+                start + Duration::from_secs_f64(bits / (self.emulation.bandwidth as f64 * 1000.0));
+            sent = schedule.free;
+        }
+
+        let jitter = match self.emulation.jitter {
+            0 => Duration::ZERO,
+            jitter => Duration::from_secs_f64(rand::random::<f64>() * jitter as f64 / 1000.0),
+        };
+        let due = (sent + Duration::from_millis(self.emulation.delay) + jitter).max(schedule.last);
+        schedule.last = due;
+
+        Some(due)
+    }
+}
+
+impl AsyncUdpSocket for EmulatedSocket {
+    fn create_io_poller(self: Arc<Self>) -> Pin<Box<dyn UdpPoller>> {
+        // Packets are queued rather than written, so sending never blocks.
+        Box::pin(Writable)
+    }
+
+    fn try_send(&self, transmit: &Transmit) -> io::Result<()> {
+        let size = transmit
+            .segment_size
+            .unwrap_or(transmit.contents.len())
+            .max(1);
+
+        for segment in transmit.contents.chunks(size) {
+            let Some(due) = self.due(segment.len()) else {
+                log::trace!(
+                    "emulated loss of {} bytes to {}",
+                    segment.len(),
+                    transmit.destination
+                );
+                continue;
+            };
+
+            let packet = Packet {
+                due,
+                destination: transmit.destination,
+                ecn: transmit.ecn,
+                src_ip: transmit.src_ip,
+                contents: segment.to_vec(),
+            };
+            if self.queue.send(packet).is_err() {
+                return Err(io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "emulation stopped",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn poll_recv(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [io::IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        self.inner.poll_recv(cx, bufs, meta)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+
+    fn max_transmit_segments(&self) -> usize {
+        // Each packet is scheduled on its own.
+        1
+    }
+
+    fn max_receive_segments(&self) -> usize {
+        self.inner.max_receive_segments()
+    }
+
+    fn may_fragment(&self) -> bool {
+        self.inner.may_fragment()
+    }
+}
+
+#[derive(Debug)]
+struct Writable;
+
+impl UdpPoller for Writable {
+    fn poll_writable(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+// Send each packet once it's due, until the emulated socket is dropped.
+async fn send(socket: Arc<dyn AsyncUdpSocket>, mut packets: mpsc::UnboundedReceiver<Packet>) {
+    let mut writable = socket.clone().create_io_poller();
+
+    while let Some(packet) = packets.recv().await {
+        tokio::time::sleep_until(packet.due).await;
+
+        let transmit = Transmit {
+            destination: packet.destination,
+            ecn: packet.ecn,
+            contents: &packet.contents,
+            segment_size: None,
+            src_ip: packet.src_ip,
+        };
+
+        loop {
+            match socket.try_send(&transmit) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if let Err(err) =
+                        std::future::poll_fn(|cx| writable.as_mut().poll_writable(cx)).await
+                    {
+                        log::debug!("emulated socket closed: {}", err);
+                        return;
+                    }
+                }
+                Err(err) => {
+                    log::debug!("failed to send emulated packet: {}", err);
+                    break;
+                }
+                Ok(()) => break,
+            }
+        }
+    }
+}
@@ -1,2 +1,3 @@
+pub mod emulation;
 pub mod quic;
 pub mod tls;
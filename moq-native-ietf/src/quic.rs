@@ -1,11 +1,14 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     fmt,
     fs::File,
     io::BufWriter,
     net::{self, IpAddr},
     path::PathBuf,
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
     time,
 };
 
@@ -13,6 +16,7 @@ use anyhow::Context;
 use clap::Parser;
 use url::Url;
 
+use crate::emulation::{self, Emulation};
 use crate::tls;
 
 use futures::future::BoxFuture;
@@ -43,17 +47,71 @@ impl fmt::Display for AddressFamily {
     }
 }
 
-/// Build a TransportConfig with our standard settings
+pub use moq_config::{CongestionController, Transport};
+
+/// Build a TransportConfig with the given settings.
 ///
 /// This is used both for the base endpoint config and when creating
 /// per-connection configs with qlog enabled.
-fn build_transport_config() -> quinn::TransportConfig {
+fn transport_config(settings: &Transport) -> anyhow::Result<quinn::TransportConfig> {
     let mut transport = quinn::TransportConfig::default();
-    transport.max_idle_timeout(Some(time::Duration::from_secs(10).try_into().unwrap()));
-    transport.keep_alive_interval(Some(time::Duration::from_secs(4))); // TODO make this smarter
-    transport.congestion_controller_factory(Arc::new(quinn::congestion::BbrConfig::default()));
+
+    let idle_timeout = match settings.max_idle_timeout {
+        0 => None,
+        ms => Some(
+            time::Duration::from_millis(ms)
+                .try_into()
+                .context("max idle timeout too large")?,
+        ),
+    };
+    transport.max_idle_timeout(idle_timeout);
+
+    let keep_alive = match settings.keep_alive_interval {
+        0 => None,
+        ms => Some(time::Duration::from_millis(ms)),
+    };
+    transport.keep_alive_interval(keep_alive); // TODO make this smarter
+
+    let congestion: Arc<dyn quinn::congestion::ControllerFactory + Send + Sync> =
+        match settings.congestion_controller {
+            CongestionController::Bbr => {
+                let mut config = quinn::congestion::BbrConfig::default();
+                if let Some(window) = settings.initial_window {
+                    config.initial_window(window);
+                }
+                Arc::new(config)
+            }
+            CongestionController::Cubic => {
+                let mut config = quinn::congestion::CubicConfig::default();
+                if let Some(window) = settings.initial_window {
+                    config.initial_window(window);
+                }
+                Arc::new(config)
+            }
+            CongestionController::NewReno => {
+                let mut config = quinn::congestion::NewRenoConfig::default();
+                if let Some(window) = settings.initial_window {
+                    config.initial_window(window);
+                }
+                Arc::new(config)
+            }
+        };
+    transport.congestion_controller_factory(congestion);
+
+    if let Some(size) = settings.datagram_receive_buffer {
+        transport.datagram_receive_buffer_size(Some(size));
+    }
+    if let Some(size) = settings.datagram_send_buffer {
+        transport.datagram_send_buffer_size(size);
+    }
+    if let Some(window) = settings.stream_receive_window {
+        transport.stream_receive_window(
+            quinn::VarInt::from_u64(window).context("stream receive window too large")?,
+        );
+    }
+
     transport.mtu_discovery_config(None); // Disable MTU discovery
-    transport
+    Ok(transport)
 }
 
 #[derive(Parser, Clone)]
@@ -68,6 +126,9 @@ pub struct Args {
 
     #[command(flatten)]
     pub tls: tls::Args,
+
+    #[command(flatten)]
+    pub transport: Transport,
 }
 
 impl Default for Args {
@@ -76,14 +137,16 @@ impl Default for Args {
             bind: "[::]:0".parse().unwrap(),
             qlog_dir: None,
             tls: Default::default(),
+            transport: Default::default(),
         }
     }
 }
 
 impl Args {
     pub fn load(&self) -> anyhow::Result<Config> {
-        let tls = self.tls.load()?;
-        Ok(Config::new(self.bind, self.qlog_dir.clone(), tls))
+        let tls = tls::Config::load(&self.tls)?;
+        Ok(Config::new(self.bind, self.qlog_dir.clone(), tls)
+            .with_transport(self.transport.clone()))
     }
 }
 
@@ -93,6 +156,17 @@ pub struct Config {
     pub qlog_dir: Option<PathBuf>,
     pub tls: tls::Config,
     pub tags: HashSet<String>,
+
+    /// Send and accept 0-RTT data on resumed TLS sessions.
+    ///
+    /// A client reconnecting to a server it saw recently then sends its first request (the
+    /// WebTransport CONNECT, or CLIENT_SETUP for moqt://) without waiting for the handshake.
+    /// 0-RTT data can be replayed by an attacker, including any authorization token in
+    /// CLIENT_SETUP, so this is off by default.
+    pub zero_rtt: bool,
+
+    /// Congestion control, timeouts and buffer sizes.
+    pub transport: Transport,
 }
 
 impl Config {
@@ -105,6 +179,8 @@ impl Config {
             qlog_dir,
             tls,
             tags: HashSet::new(),
+            zero_rtt: false,
+            transport: Transport::default(),
         }
     }
 
@@ -119,6 +195,8 @@ impl Config {
             qlog_dir,
             tls,
             tags: HashSet::new(),
+            zero_rtt: false,
+            transport: Transport::default(),
         }
     }
 
@@ -126,6 +204,16 @@ impl Config {
         self.tags.insert(tag);
         self
     }
+
+    pub fn with_zero_rtt(mut self, zero_rtt: bool) -> Self {
+        self.zero_rtt = zero_rtt;
+        self
+    }
+
+    pub fn with_transport(mut self, transport: Transport) -> Self {
+        self.transport = transport;
+        self
+    }
 }
 
 pub struct Endpoint {
@@ -154,9 +242,10 @@ impl Endpoint {
         }
 
         // Build transport config with our standard settings
-        let transport = Arc::new(build_transport_config());
+        let transport = Arc::new(transport_config(&config.transport)?);
 
         let mut server_config = None;
+        let zero_rtt = config.zero_rtt;
 
         if let Some(mut config) = config.tls.server {
             config.alpn_protocols = vec![
@@ -165,6 +254,11 @@ impl Endpoint {
             ];
             config.key_log = Arc::new(rustls::KeyLogFile::new());
 
+            // QUIC requires either no early data or an unlimited amount.
+            if zero_rtt {
+                config.max_early_data_size = u32::MAX;
+            }
+
             let config: quinn::crypto::rustls::QuicServerConfig = config.try_into()?;
             let mut config = quinn::ServerConfig::with_crypto(Arc::new(config));
             config.transport_config(transport.clone());
@@ -174,24 +268,53 @@ impl Endpoint {
 
         // There's a bit more boilerplate to make a generic endpoint.
         let runtime = quinn::default_runtime().context("no async runtime")?;
+        // Its connection IDs are signed with a random key, which keeps another process sharing
+        // the socket after a handover from sending stateless resets to our connections.
         let endpoint_config = quinn::EndpointConfig::default();
         let socket = config.socket;
 
-        // Create the generic QUIC endpoint.
-        let quic = quinn::Endpoint::new(endpoint_config, server_config.clone(), socket, runtime)
-            .context("failed to create QUIC endpoint")?;
+        // Keep a handle to the socket, so it can be handed over to another process.
+        let listener = Arc::new(socket.try_clone().context("failed to clone socket")?);
+
+        // Create the generic QUIC endpoint, emulating a network underneath if asked to.
+        let emulation = Some(config.transport.emulation.clone()).filter(Emulation::is_enabled);
+        let socket = runtime.wrap_udp_socket(socket)?;
+        let socket = match &emulation {
+            Some(emulation) => emulation::wrap(emulation, socket),
+            None => socket,
+        };
+        let quic = quinn::Endpoint::new_with_abstract_socket(
+            endpoint_config,
+            server_config.clone(),
+            socket,
+            runtime,
+        )
+        .context("failed to create QUIC endpoint")?;
 
         let server = server_config.map(|base_server_config| Server {
             quic: quic.clone(),
+            socket: listener,
+            draining: Default::default(),
             accept: Default::default(),
             qlog_dir: config.qlog_dir.map(Arc::new),
+            qlog_requests: Default::default(),
             base_server_config: Arc::new(base_server_config),
+            transport_settings: Arc::new(config.transport),
+            zero_rtt,
         });
 
+        // Session tickets are cached by the rustls config, which is shared by every clone.
+        let mut client_config = config.tls.client;
+        client_config.enable_early_data = zero_rtt;
+
         let client = Client {
             quic,
-            config: config.tls.client,
+            config: client_config,
             transport,
+            emulation,
+            last: Default::default(),
+            connected: Default::default(),
+            alternate: Default::default(),
         };
 
         Ok(Self {
@@ -202,22 +325,235 @@ impl Endpoint {
     }
 }
 
+/// A handle to an established QUIC connection, for reading its statistics while it is open.
+#[derive(Clone)]
+pub struct Connection(quinn::Connection);
+
+impl Connection {
+    /// A snapshot of the connection's path and byte counters.
+    pub fn stats(&self) -> ConnectionStats {
+        let stats = self.0.stats();
+
+        ConnectionStats {
+            rtt: stats.path.rtt,
+            cwnd: stats.path.cwnd,
+            congestion_events: stats.path.congestion_events,
+            sent_packets: stats.path.sent_packets,
+            lost_packets: stats.path.lost_packets,
+            lost_bytes: stats.path.lost_bytes,
+            mtu: stats.path.current_mtu,
+            bytes_sent: stats.udp_tx.bytes,
+            bytes_received: stats.udp_rx.bytes,
+        }
+    }
+
+    /// The largest WebTransport datagram payload the peer accepts, or None if it doesn't support
+    /// datagrams. This leaves room for the session ID that prefixes each datagram.
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.0
+            .max_datagram_size()
+            .map(|size| size.saturating_sub(8))
+    }
+
+    pub fn remote_address(&self) -> net::SocketAddr {
+        self.0.remote_address()
+    }
+
+    /// The server name (SNI) the client asked for in the TLS handshake, if it sent one.
+    pub fn server_name(&self) -> Option<String> {
+        self.0
+            .handshake_data()?
+            .downcast::<quinn::crypto::rustls::HandshakeData>()
+            .ok()?
+            .server_name
+    }
+
+    /// Why the connection closed, or None if it's still open.
+    pub fn close_reason(&self) -> Option<CloseReason> {
+        self.0.close_reason().as_ref().map(Into::into)
+    }
+}
+
+/// Why a QUIC connection closed, from [Connection::close_reason].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+    /// The peer's application closed the connection, ex. the client went away.
+    Application { code: u64, reason: String },
+    /// The peer's QUIC stack aborted the connection with a transport error.
+    PeerTransport { code: u64, reason: String },
+    /// We aborted the connection because the peer violated the QUIC specification.
+    Transport { code: u64, reason: String },
+    /// Nothing was received for longer than the idle timeout.
+    TimedOut,
+    /// The peer no longer knows the connection, usually because it restarted.
+    Reset,
+    /// We closed the connection.
+    Local,
+    /// The peer doesn't implement any supported QUIC version.
+    VersionMismatch,
+    /// Not enough connection IDs were available.
+    CidsExhausted,
+}
+
+impl CloseReason {
+    /// A short name for the kind of close, suitable as a metrics label.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Application { .. } => "application",
+            Self::PeerTransport { .. } => "peer_transport",
+            Self::Transport { .. } => "transport",
+            Self::TimedOut => "timed_out",
+            Self::Reset => "reset",
+            Self::Local => "local",
+            Self::VersionMismatch => "version_mismatch",
+            Self::CidsExhausted => "cids_exhausted",
+        }
+    }
+
+    /// The error code sent or received, if any.
+    pub fn code(&self) -> Option<u64> {
+        match self {
+            Self::Application { code, .. }
+            | Self::PeerTransport { code, .. }
+            | Self::Transport { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// The reason phrase sent or received, if any.
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            Self::Application { reason, .. }
+            | Self::PeerTransport { reason, .. }
+            | Self::Transport { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.code(), self.reason()) {
+            (Some(code), Some(reason)) => write!(f, "{}({:#x}, {:?})", self.label(), code, reason),
+            _ => write!(f, "{}", self.label()),
+        }
+    }
+}
+
+impl From<&quinn::ConnectionError> for CloseReason {
+    fn from(err: &quinn::ConnectionError) -> Self {
+        use quinn::ConnectionError;
+
+        match err {
+            ConnectionError::ApplicationClosed(close) => Self::Application {
+                code: close.error_code.into(),
+                reason: String::from_utf8_lossy(&close.reason).into_owned(),
+            },
+            ConnectionError::ConnectionClosed(close) => Self::PeerTransport {
+                code: close.error_code.into(),
+                reason: String::from_utf8_lossy(&close.reason).into_owned(),
+            },
+            ConnectionError::TransportError(err) => Self::Transport {
+                code: err.code.into(),
+                reason: err.reason.clone(),
+            },
+            ConnectionError::TimedOut => Self::TimedOut,
+            ConnectionError::Reset => Self::Reset,
+            ConnectionError::LocallyClosed => Self::Local,
+            ConnectionError::VersionMismatch => Self::VersionMismatch,
+            ConnectionError::CidsExhausted => Self::CidsExhausted,
+        }
+    }
+}
+
+/// Statistics for a QUIC connection, from [Connection::stats].
+///
+/// Quinn doesn't report ECN counts separately; ECN-CE marks are included in `congestion_events`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// The current best estimate of the round trip time.
+    pub rtt: time::Duration,
+    /// The congestion window, in bytes.
+    pub cwnd: u64,
+    /// How many times the congestion controller reacted to loss or ECN marks.
+    pub congestion_events: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    pub lost_bytes: u64,
+    /// The current path MTU, in bytes.
+    pub mtu: u16,
+    /// UDP payload bytes sent and received, including retransmissions.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+// An accepted session, its connection ID, and a handle for reading connection statistics.
+type Accepted = (web_transport::Session, String, Connection);
+
+/// Peers whose next connections are accepted with qlog, ex. to investigate one misbehaving
+/// on a connection accepted without it. Shared by every clone.
+///
+/// qlog can only be enabled when a connection is accepted, so a request never applies to
+/// connections already established.
+#[derive(Clone, Default)]
+pub struct QlogRequests {
+    requests: Arc<Mutex<HashMap<IpAddr, QlogRequest>>>,
+}
+
+struct QlogRequest {
+    dir: PathBuf,
+    until: time::Instant,
+}
+
+impl QlogRequests {
+    /// Write qlog files to `dir` for the connections from `ip` accepted within `duration`.
+    pub fn request(&self, ip: IpAddr, dir: PathBuf, duration: time::Duration) {
+        let until = time::Instant::now() + duration;
+        self.requests
+            .lock()
+            .unwrap()
+            .insert(ip, QlogRequest { dir, until });
+    }
+
+    // The directory to write the qlog of a connection from `ip` to, if requested.
+    fn dir(&self, ip: IpAddr) -> Option<PathBuf> {
+        let mut requests = self.requests.lock().unwrap();
+        let now = time::Instant::now();
+        requests.retain(|_, request| request.until > now);
+        requests.get(&ip).map(|request| request.dir.clone())
+    }
+}
+
 pub struct Server {
     quic: quinn::Endpoint,
-    accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<(web_transport::Session, String)>>>,
+    socket: Arc<net::UdpSocket>,
+    draining: Arc<AtomicBool>,
+    accept: FuturesUnordered<BoxFuture<'static, anyhow::Result<Accepted>>>,
     qlog_dir: Option<Arc<PathBuf>>,
+    qlog_requests: QlogRequests,
     base_server_config: Arc<quinn::ServerConfig>,
+    transport_settings: Arc<Transport>,
+    zero_rtt: bool,
 }
 
 impl Server {
-    pub async fn accept(&mut self) -> Option<(web_transport::Session, String)> {
+    /// Accept the next session, along with its connection ID and a [Connection] for statistics.
+    pub async fn accept(&mut self) -> Option<(web_transport::Session, String, Connection)> {
         loop {
             tokio::select! {
                 res = self.quic.accept() => {
                     let conn = res?;
-                    let qlog_dir = self.qlog_dir.clone();
+
+                    // Leave new connections to the process we handed over to, without answering.
+                    if self.draining.load(atomic::Ordering::Relaxed) {
+                        conn.ignore();
+                        continue;
+                    }
+
+                    let qlog_dir = self.qlog_dir.clone().or_else(|| self.qlog_requests.dir(conn.remote_address().ip()).map(Arc::new));
                     let base_server_config = self.base_server_config.clone();
-                    self.accept.push(Self::accept_session(conn, qlog_dir, base_server_config).boxed());
+                    let transport_settings = self.transport_settings.clone();
+                    self.accept.push(Self::accept_session(conn, qlog_dir, base_server_config, transport_settings, self.zero_rtt).boxed());
                 },
                 res = self.accept.next(), if !self.accept.is_empty() => {
                     match res? {
@@ -236,7 +572,9 @@ impl Server {
         conn: quinn::Incoming,
         qlog_dir: Option<Arc<PathBuf>>,
         base_server_config: Arc<quinn::ServerConfig>,
-    ) -> anyhow::Result<(web_transport::Session, String)> {
+        transport_settings: Arc<Transport>,
+        zero_rtt: bool,
+    ) -> anyhow::Result<(web_transport::Session, String, Connection)> {
         // Capture the original destination connection ID BEFORE accepting
         // This is the actual QUIC CID that can be used for qlog/mlog correlation
         let orig_dst_cid = conn.orig_dst_cid();
@@ -248,7 +586,7 @@ impl Server {
             let qlog_path = qlog_dir.join(format!("{}_server.qlog", connection_id_hex));
 
             // Create transport config with our standard settings plus qlog
-            let mut transport = build_transport_config();
+            let mut transport = transport_config(&transport_settings)?;
 
             let file = File::create(&qlog_path).context("failed to create qlog file")?;
             let writer = BufWriter::new(file);
@@ -293,8 +631,14 @@ impl Server {
             server_name,
         );
 
-        // Wait for the QUIC connection to be established.
-        let conn = conn.await.context("failed to establish QUIC connection")?;
+        // Wait for the QUIC connection to be established, unless we can read 0-RTT data right away.
+        let conn = match zero_rtt {
+            true => match conn.into_0rtt() {
+                Ok((conn, _)) => conn,
+                Err(conn) => conn.await.context("failed to establish QUIC connection")?,
+            },
+            false => conn.await.context("failed to establish QUIC connection")?,
+        };
 
         log::debug!(
             "established QUIC connection: cid={} stable_id={} ip={} alpn={} server={}",
@@ -305,6 +649,8 @@ impl Server {
             server_name,
         );
 
+        let stats = Connection(conn.clone());
+
         let session = match alpn.as_bytes() {
             web_transport_quinn::ALPN => {
                 // Wait for the CONNECT request.
@@ -323,7 +669,12 @@ impl Server {
             _ => anyhow::bail!("unsupported ALPN: {}", alpn),
         };
 
-        Ok((session.into(), connection_id_hex))
+        Ok((session.into(), connection_id_hex, stats))
+    }
+
+    /// A handle for enabling qlog on the next connections from a peer, when not writing it for all.
+    pub fn qlog_requests(&self) -> QlogRequests {
+        self.qlog_requests.clone()
     }
 
     pub fn local_addr(&self) -> anyhow::Result<net::SocketAddr> {
@@ -331,13 +682,69 @@ impl Server {
             .local_addr()
             .context("failed to get local address")
     }
+
+    /// A handle for handing this server's socket over to another process, see [Handover].
+    pub fn handover(&self) -> Handover {
+        Handover {
+            socket: self.socket.clone(),
+            draining: self.draining.clone(),
+        }
+    }
 }
 
+/// Hands a server's UDP socket over to a new process, for upgrading without dropping connections.
+///
+/// The new process receives [Handover::socket], ex. by inheriting its file descriptor, and
+/// passes it to [Config::with_socket]. Once it is accepting, call [Handover::drain] so new
+/// connections go to it, while connections already established here continue until closed.
+///
+/// Both processes read from the socket until this one exits, so some packets of the remaining
+/// connections are lost; send GOAWAY to move their sessions to the new process quickly.
+/// Neither process answers the other's packets: connection IDs are signed with a key of their
+/// endpoint, so packets of unknown connections are dropped rather than sent a stateless reset,
+/// and a draining server ignores new connections rather than refusing them.
+#[derive(Clone)]
+pub struct Handover {
+    socket: Arc<net::UdpSocket>,
+    draining: Arc<AtomicBool>,
+}
+
+impl Handover {
+    /// The socket the server listens on.
+    pub fn socket(&self) -> &net::UdpSocket {
+        &self.socket
+    }
+
+    /// Stop accepting new connections.
+    pub fn drain(&self) {
+        // Without a server config, Quinn would answer them with a stateless reset.
+        self.draining.store(true, atomic::Ordering::Relaxed);
+    }
+}
+
+/// How long a connection attempt runs before the next address is tried alongside it, per RFC 8305.
+const CONNECTION_ATTEMPT_DELAY: time::Duration = time::Duration::from_millis(250);
+
+// The URL and optional address passed to Client::connect.
+type Target = (Url, Option<net::SocketAddr>);
+
 #[derive(Clone)]
 pub struct Client {
     quic: quinn::Endpoint,
     config: rustls::ClientConfig,
     transport: Arc<quinn::TransportConfig>,
+
+    // Applied to the sockets the endpoint is rebound to, as well as the first.
+    emulation: Option<Emulation>,
+
+    // The last URL and address passed to connect, for reconnect.
+    last: Arc<Mutex<Option<Target>>>,
+
+    // The address each host was last reached at, tried first next time.
+    connected: Arc<Mutex<HashMap<String, net::SocketAddr>>>,
+
+    // A socket for the address family our own socket can't reach, if one was needed.
+    alternate: Arc<Mutex<Option<quinn::Endpoint>>>,
 }
 
 impl Client {
@@ -364,39 +771,45 @@ impl Client {
         }
     }
 
+    /// Connect to the given URL, or `socket_addr` if provided instead of resolving the host.
+    ///
+    /// When the host resolves to several addresses, handshakes to them are raced as in RFC 8305
+    /// (happy eyeballs), alternating between IPv6 and IPv4, so a broken network for one family
+    /// only costs a short delay. Addresses our socket's family can't reach use a second socket.
+    ///
+    /// For https:// URLs both WebTransport and native QUIC are offered, and the server decides;
+    /// moqt:// URLs only use native QUIC.
+    ///
+    /// If 0-RTT is enabled and a session ticket for the server is cached, the first request is
+    /// sent as early data. A WebTransport CONNECT rejected by the server is retried once the
+    /// handshake completes; for moqt://, a rejected CLIENT_SETUP fails the session setup.
+    ///
+    /// Returns the session, the connection ID used for qlog/mlog correlation, and a [Connection]
+    /// for reading statistics.
     pub async fn connect(
         &self,
         url: &Url,
         socket_addr: Option<net::SocketAddr>,
-    ) -> anyhow::Result<(web_transport::Session, String)> {
+    ) -> anyhow::Result<(web_transport::Session, String, Connection)> {
+        *self.last.lock().unwrap() = Some((url.clone(), socket_addr));
+
         let mut config = self.config.clone();
 
-        // TODO support connecting to both ALPNs at the same time
-        config.alpn_protocols = vec![match url.scheme() {
-            "https" => web_transport_quinn::ALPN.to_vec(),
-            "moqt" => moq_transport::setup::ALPN.to_vec(),
+        // Offer native QUIC alongside WebTransport for https://, so the same URL reaches either kind of server.
+        // The server picks the protocol, and we follow whichever it negotiated.
+        config.alpn_protocols = match url.scheme() {
+            "https" => vec![
+                web_transport_quinn::ALPN.to_vec(),
+                moq_transport::setup::ALPN.to_vec(),
+            ],
+            "moqt" => vec![moq_transport::setup::ALPN.to_vec()],
             _ => anyhow::bail!("url scheme must be 'https' or 'moqt'"),
-        }];
+        };
+        let preferred_alpn = config.alpn_protocols[0].clone();
 
         config.key_log = Arc::new(rustls::KeyLogFile::new());
 
-        let config: quinn::crypto::rustls::QuicClientConfig = config.try_into()?;
-        let mut config = quinn::ClientConfig::new(Arc::new(config));
-        config.transport_config(self.transport.clone());
-
-        // Capture the initial destination CID that will be sent to the server
-        // This is the CID used for qlog/mlog correlation on the server side
-        let cid_capture: Arc<Mutex<Option<quinn::ConnectionId>>> = Arc::new(Mutex::new(None));
-        let cid_capture_clone = cid_capture.clone();
-        config.initial_dst_cid_provider(Arc::new(move || {
-            // Generate a random CID (Quinn's default behavior)
-            use rand::Rng;
-            let mut rng = rand::thread_rng();
-            let random_bytes: [u8; 16] = rng.gen();
-            let cid = quinn::ConnectionId::new(&random_bytes);
-            *cid_capture_clone.lock().unwrap() = Some(cid);
-            cid
-        }));
+        let config: Arc<quinn::crypto::rustls::QuicClientConfig> = Arc::new(config.try_into()?);
 
         let host = match url.host().context("missing host")? {
             url::Host::Domain(d) => d.to_string(),
@@ -405,49 +818,95 @@ impl Client {
         };
         let port = url.port().unwrap_or(443);
 
-        // Look up the DNS entry and filter by socket address family.
-        let addr = match socket_addr {
-            Some(addr) => addr,
-            None => {
-                // Default DNS resolution logic
-                self.resolve_dns(&host, port, self.address_family()?)
-                    .await?
+        let addrs = match socket_addr {
+            Some(addr) => vec![addr],
+            None => self.resolve_dns(&host, port).await?,
+        };
+
+        let (connection, zero_rtt, connection_id_hex) = self.race(config, &addrs, &host).await?;
+
+        let stats = Connection(connection.clone());
+
+        // With 0-RTT the protocol is the one from the resumed session; assume ours if it's not known yet.
+        let alpn = connection
+            .handshake_data()
+            .and_then(|data| data.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+            .and_then(|data| data.protocol)
+            .unwrap_or(preferred_alpn);
+
+        if url.scheme() == "https" && alpn == moq_transport::setup::ALPN {
+            log::debug!("server chose native QUIC over WebTransport: host={}", host);
+        }
+
+        let session = match (alpn.as_slice(), zero_rtt) {
+            (web_transport_quinn::ALPN, None) => {
+                web_transport_quinn::connect_with(connection, url).await?
+            }
+            (web_transport_quinn::ALPN, Some(accepted)) => {
+                match web_transport_quinn::connect_with(connection.clone(), url).await {
+                    Ok(session) => session,
+                    Err(err) if accepted.await => return Err(err.into()),
+                    // The CONNECT was sent as early data and dropped; resend it now that the handshake is done.
+                    Err(_) => {
+                        log::debug!("0-RTT rejected, retrying CONNECT: host={}", host);
+                        web_transport_quinn::connect_with(connection, url).await?
+                    }
+                }
             }
+            (moq_transport::setup::ALPN, _) => connection.into(),
+            _ => anyhow::bail!("unsupported ALPN: {}", String::from_utf8_lossy(&alpn)),
         };
 
-        let connection = self.quic.connect_with(config, addr, &host)?.await?;
+        Ok((session.into(), connection_id_hex, stats))
+    }
 
-        // Extract the CID that was used
-        let connection_id_hex = cid_capture
+    /// Connect again to the URL most recently passed to [Self::connect].
+    ///
+    /// The cached session ticket is reused, so the handshake is abbreviated, and with 0-RTT
+    /// enabled the first request is sent without waiting for it.
+    pub async fn reconnect(&self) -> anyhow::Result<(web_transport::Session, String, Connection)> {
+        let (url, socket_addr) = self
+            .last
             .lock()
             .unwrap()
-            .as_ref()
-            .context("CID not captured")?
-            .to_string();
+            .clone()
+            .context("no previous connection")?;
+
+        log::info!("reconnecting: url={}", url);
+        self.connect(&url, socket_addr).await
+    }
 
-        let session = match url.scheme() {
-            "https" => web_transport_quinn::connect_with(connection, url).await?,
-            "moqt" => connection.into(),
-            _ => unreachable!(),
+    /// Move the endpoint to a new UDP socket, ex. after the network changed.
+    ///
+    /// Open connections migrate to the new socket. The local port changes, so don't use this on
+    /// an endpoint that also accepts connections.
+    pub fn rebind(&self) -> anyhow::Result<net::SocketAddr> {
+        let ip: IpAddr = match self.local_addr()?.is_ipv4() {
+            true => net::Ipv4Addr::UNSPECIFIED.into(),
+            false => net::Ipv6Addr::UNSPECIFIED.into(),
         };
 
-        Ok((session.into(), connection_id_hex))
-    }
+        let socket = net::UdpSocket::bind((ip, 0)).context("failed to bind socket")?;
+        match &self.emulation {
+            Some(emulation) => {
+                let runtime = quinn::default_runtime().context("no async runtime")?;
+                let socket = runtime.wrap_udp_socket(socket)?;
+                self.quic
+                    .rebind_abstract(emulation::wrap(emulation, socket))
+            }
+            None => self.quic.rebind(socket),
+        }
+        .context("failed to rebind")?;
 
-    /// Default DNS resolution logic that filters results by address family.
-    async fn resolve_dns(
-        &self,
-        host: &str,
-        port: u16,
-        address_family: AddressFamily,
-    ) -> anyhow::Result<net::SocketAddr> {
-        let local_addr = self.local_addr()?;
+        self.local_addr()
+    }
 
-        // Collect all DNS results
+    /// Resolve the host, ordering the results to alternate between IPv6 and IPv4.
+    ///
+    /// The first address is the one we last connected to, or else IPv6 as RFC 8305 recommends.
+    async fn resolve_dns(&self, host: &str, port: u16) -> anyhow::Result<Vec<net::SocketAddr>> {
         let addrs: Vec<net::SocketAddr> = match Self::parse_socket_addr(host, port) {
-            Ok(addr) => {
-                vec![addr]
-            }
+            Ok(addr) => vec![addr],
             Err(_) => tokio::net::lookup_host((host, port))
                 .await
                 .context("failed DNS lookup")?
@@ -458,13 +917,10 @@ impl Client {
             anyhow::bail!("DNS lookup for host '{}' returned no addresses", host);
         }
 
-        // Log all DNS results for debugging
-        log::debug!(
-            "DNS lookup for {}, family {:?}: found {} results",
-            host,
-            address_family,
-            addrs.len()
-        );
+        let last = self.connected.lock().unwrap().get(host).copied();
+        let addrs = interleave(addrs, last);
+
+        log::debug!("DNS lookup for {}: found {} results", host, addrs.len());
         for (i, addr) in addrs.iter().enumerate() {
             log::debug!(
                 "  DNS[{}]: {} ({})",
@@ -474,48 +930,141 @@ impl Client {
             );
         }
 
-        // Filter DNS results to match our local socket's address family
-        let compatible_addr = match address_family {
-            AddressFamily::Ipv4 => {
-                // IPv4 socket: filter to IPv4 addresses
-                addrs
-                    .iter()
-                    .find(|a| a.is_ipv4())
-                    .cloned()
-                    .context(format!(
-                        "No IPv4 address found for host '{}' (local socket is IPv4: {})",
-                        host, local_addr
-                    ))?
-            }
-            AddressFamily::Ipv6DualStack => {
-                // IPv6 socket on Linux: dual-stack, use first result
-                log::debug!(
-                    "Using first DNS result (Linux IPv6 dual-stack): {}",
-                    addrs[0]
-                );
-                addrs[0]
-            }
-            AddressFamily::Ipv6 => {
-                // IPv6 socket non-Linux: filter to IPv6 addresses
-                addrs
-                    .iter()
-                    .find(|a| a.is_ipv6())
-                    .cloned()
-                    .context(format!(
-                        "No IPv6 address found for host '{}' (local socket is IPv6: {})",
-                        host, local_addr
-                    ))?
+        Ok(addrs)
+    }
+
+    /// Race QUIC handshakes to each address, returning the first to complete.
+    ///
+    /// Each attempt gets a head start of [CONNECTION_ATTEMPT_DELAY] before the next one begins,
+    /// or less if it fails first. The losing attempts are dropped, which closes them.
+    /// When 0-RTT is possible for the first address, it is used straight away without racing.
+    async fn race(
+        &self,
+        config: Arc<quinn::crypto::rustls::QuicClientConfig>,
+        addrs: &[net::SocketAddr],
+        host: &str,
+    ) -> anyhow::Result<(quinn::Connection, Option<quinn::ZeroRttAccepted>, String)> {
+        let mut remaining = addrs.iter().copied();
+        let mut attempts = FuturesUnordered::new();
+        let mut error = None;
+
+        if let Some(addr) = remaining.next() {
+            let zero_rtt = self.config.enable_early_data;
+            attempts.push(self.attempt(config.clone(), addr, host, zero_rtt));
+        }
+
+        while !attempts.is_empty() {
+            tokio::select! {
+                Some(res) = attempts.next() => match res {
+                    Ok((connection, zero_rtt, cid, addr)) => {
+                        log::debug!("connected: host={} addr={}", host, addr);
+                        self.connected.lock().unwrap().insert(host.to_string(), addr);
+                        return Ok((connection, zero_rtt, cid));
+                    }
+                    Err(err) => {
+                        log::debug!("connection attempt failed: host={} err={:#}", host, err);
+                        error = Some(err);
+
+                        // Don't wait out the delay for an address that already failed.
+                        if let Some(addr) = remaining.next() {
+                            attempts.push(self.attempt(config.clone(), addr, host, false));
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if remaining.len() > 0 => {
+                    let addr = remaining.next().unwrap();
+                    log::debug!("racing next address: host={} addr={}", host, addr);
+                    attempts.push(self.attempt(config.clone(), addr, host, false));
+                }
             }
+        }
+
+        Err(error.unwrap_or_else(|| anyhow::anyhow!("no addresses for host '{}'", host)))
+    }
+
+    async fn attempt(
+        &self,
+        crypto: Arc<quinn::crypto::rustls::QuicClientConfig>,
+        addr: net::SocketAddr,
+        host: &str,
+        zero_rtt: bool,
+    ) -> anyhow::Result<(
+        quinn::Connection,
+        Option<quinn::ZeroRttAccepted>,
+        String,
+        net::SocketAddr,
+    )> {
+        let mut config = quinn::ClientConfig::new(crypto);
+        config.transport_config(self.transport.clone());
+
+        // Capture the initial destination CID that will be sent to the server
+        // This is the CID used for qlog/mlog correlation on the server side
+        let cid_capture: Arc<Mutex<Option<quinn::ConnectionId>>> = Arc::new(Mutex::new(None));
+        let cid_capture_clone = cid_capture.clone();
+        config.initial_dst_cid_provider(Arc::new(move || {
+            // Generate a random CID (Quinn's default behavior)
+            use rand::Rng;
+            let mut rng = rand::thread_rng();
+            let random_bytes: [u8; 16] = rng.gen();
+            let cid = quinn::ConnectionId::new(&random_bytes);
+            *cid_capture_clone.lock().unwrap() = Some(cid);
+            cid
+        }));
+
+        let endpoint = self.endpoint_for(addr)?;
+        log::debug!("Connecting from {} to {}", endpoint.local_addr()?, addr);
+        let connecting = endpoint.connect_with(config, addr, host)?;
+
+        // Only possible when resuming a session with early data enabled.
+        let (connection, zero_rtt) = match zero_rtt {
+            true => match connecting.into_0rtt() {
+                Ok((connection, accepted)) => {
+                    log::debug!("sending 0-RTT data: host={}", host);
+                    (connection, Some(accepted))
+                }
+                Err(connecting) => (connecting.await?, None),
+            },
+            false => (connecting.await?, None),
         };
 
-        log::debug!(
-            "Connecting from {} to {} (selected from {} DNS results)",
-            local_addr,
-            compatible_addr,
-            addrs.len()
-        );
+        // Extract the CID that was used
+        let connection_id_hex = cid_capture
+            .lock()
+            .unwrap()
+            .as_ref()
+            .context("CID not captured")?
+            .to_string();
 
-        Ok(compatible_addr)
+        Ok((connection, zero_rtt, connection_id_hex, addr))
+    }
+
+    /// The endpoint to reach `addr` from: our own socket when it supports the address family,
+    /// otherwise a client-only endpoint bound to the other family, created on first use.
+    fn endpoint_for(&self, addr: net::SocketAddr) -> anyhow::Result<quinn::Endpoint> {
+        let compatible = match self.address_family()? {
+            AddressFamily::Ipv4 => addr.is_ipv4(),
+            AddressFamily::Ipv6 => addr.is_ipv6(),
+            AddressFamily::Ipv6DualStack => true,
+        };
+        if compatible {
+            return Ok(self.quic.clone());
+        }
+
+        let mut alternate = self.alternate.lock().unwrap();
+        if let Some(endpoint) = alternate.as_ref() {
+            return Ok(endpoint.clone());
+        }
+
+        let ip: IpAddr = match addr.is_ipv4() {
+            true => net::Ipv4Addr::UNSPECIFIED.into(),
+            false => net::Ipv6Addr::UNSPECIFIED.into(),
+        };
+        let endpoint = quinn::Endpoint::client((ip, 0).into())
+            .context("failed to bind socket for the other address family")?;
+        log::debug!("bound {} for {}", endpoint.local_addr()?, addr);
+
+        *alternate = Some(endpoint.clone());
+        Ok(endpoint)
     }
 
     fn parse_socket_addr(host: &str, port: u16) -> Result<net::SocketAddr, net::AddrParseError> {
@@ -523,3 +1072,27 @@ impl Client {
         host.parse::<net::SocketAddr>()
     }
 }
+
+// Alternate between address families, starting with the family of `first` (moved to the front
+// if present) or else IPv6, keeping the resolver's order within each family.
+fn interleave(addrs: Vec<net::SocketAddr>, first: Option<net::SocketAddr>) -> Vec<net::SocketAddr> {
+    let prefer_ipv4 = first.is_some_and(|first| first.is_ipv4());
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addrs
+        .into_iter()
+        .partition(|addr| addr.is_ipv4() == prefer_ipv4);
+
+    if let Some(index) = preferred.iter().position(|addr| Some(*addr) == first) {
+        let first = preferred.remove(index);
+        preferred.insert(0, first);
+    }
+
+    let mut ordered = Vec::with_capacity(preferred.len() + other.len());
+    let mut preferred = preferred.drain(..);
+    let mut other = other.drain(..);
+    loop {
+        match (preferred.next(), other.next()) {
+            (None, None) => return ordered,
+            (a, b) => ordered.extend(a.into_iter().chain(b)),
+        }
+    }
+}
@@ -1,5 +1,4 @@
 use anyhow::Context;
-use clap::Parser;
 use ring::digest::{digest, SHA256};
 use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
 use rustls::server::{ClientHello, ResolvesServerCert};
@@ -10,36 +9,8 @@ use std::io::{self, Cursor, Read};
 use std::path;
 use std::sync::Arc;
 
-#[derive(Parser, Clone, Default)]
-#[group(id = "tls")]
-pub struct Args {
-    /// Use the certificates at this path, encoded as PEM.
-    ///
-    /// You can use this option multiple times for multiple certificates.
-    /// The first match for the provided SNI will be used, otherwise the last cert will be used.
-    /// You also need to provide the private key multiple times via `key``.
-    #[arg(long = "tls-cert")]
-    pub cert: Vec<path::PathBuf>,
-
-    /// Use the private key at this path, encoded as PEM.
-    ///
-    /// There must be a key for every certificate provided via `cert`.
-    #[arg(long = "tls-key")]
-    pub key: Vec<path::PathBuf>,
-
-    /// Use the TLS root at this path, encoded as PEM.
-    ///
-    /// This value can be provided multiple times for multiple roots.
-    /// If this is empty, system roots will be used instead
-    #[arg(long = "tls-root")]
-    pub root: Vec<path::PathBuf>,
-
-    /// Danger: Disable TLS certificate verification.
-    ///
-    /// Fine for local development and between relays, but should be used in caution in production.
-    #[arg(long = "tls-disable-verify")]
-    pub disable_verify: bool,
-}
+/// Where to find the certificates, keys and roots, loaded into a [Config].
+pub use moq_config::Tls as Args;
 
 #[derive(Clone)]
 pub struct Config {
@@ -48,24 +19,24 @@ pub struct Config {
     pub fingerprints: Vec<String>,
 }
 
-impl Args {
-    pub fn load(&self) -> anyhow::Result<Config> {
+impl Config {
+    pub fn load(args: &Args) -> anyhow::Result<Self> {
         let provider = Arc::new(rustls::crypto::ring::default_provider());
         let mut serve = ServeCerts::default();
 
         // Load the certificate and key files based on their index.
         anyhow::ensure!(
-            self.cert.len() == self.key.len(),
+            args.cert.len() == args.key.len(),
             "--tls-cert and --tls-key counts differ"
         );
-        for (chain, key) in self.cert.iter().zip(self.key.iter()) {
+        for (chain, key) in args.cert.iter().zip(args.key.iter()) {
             serve.load(chain, key)?;
         }
 
         // Create a list of acceptable root certificates.
         let mut roots = RootCertStore::empty();
 
-        if self.root.is_empty() {
+        if args.root.is_empty() {
             // Add the platform's native root certificates.
             for cert in
                 rustls_native_certs::load_native_certs().context("could not load platform certs")?
@@ -74,7 +45,7 @@ impl Args {
             }
         } else {
             // Add the specified root certificates.
-            for root in &self.root {
+            for root in &args.root {
                 let root = fs::File::open(root).context("failed to open root cert file")?;
                 let mut root = io::BufReader::new(root);
 
@@ -94,7 +65,7 @@ impl Args {
             .with_no_client_auth();
 
         // Allow disabling TLS verification altogether.
-        if self.disable_verify {
+        if args.disable_verify {
             let noop = NoCertificateVerification(provider.clone());
             client.dangerous().set_certificate_verifier(Arc::new(noop));
         }
@@ -102,7 +73,7 @@ impl Args {
         let fingerprints = serve.fingerprints();
 
         // Create the TLS configuration we'll use as a server (relay <- browser)
-        let server = if !self.key.is_empty() {
+        let server = if !args.key.is_empty() {
             Some(
                 rustls::ServerConfig::builder_with_provider(provider)
                     .with_protocol_versions(&[&rustls::version::TLS13])?
@@ -0,0 +1,27 @@
+//! Qualify a link through a MoQ relay, measuring its throughput, loss and latency.
+//!
+//! A [Sender] publishes [Probe]s at a fixed bitrate, each carrying its send time and sequence
+//! number, and a [Receiver] turns the probes it reads into [Report]s. Relays serve a built-in
+//! namespace that sends probes on request and reports on those uploaded to it, see [download]
+//! and [upload] for the track names.
+mod probe;
+mod receiver;
+mod sender;
+
+pub use probe::*;
+pub use receiver::*;
+pub use sender::*;
+
+/// The relay's track sending probes at `bitrate`, in bits per second.
+pub fn download(bitrate: u64) -> String {
+    format!("download/{}", bitrate)
+}
+
+/// The relay's track reporting on the upload `id`, announced as the `upload/<id>` namespace under
+/// the relay's, with probes in its [UPLOAD_TRACK].
+pub fn upload(id: &str) -> String {
+    format!("upload/{}", id)
+}
+
+/// The track of an upload namespace carrying its probes.
+pub const UPLOAD_TRACK: &str = "data";
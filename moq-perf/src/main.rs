@@ -0,0 +1,185 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::Parser;
+
+use moq_native_ietf::{quic, tls};
+use moq_perf::{Receiver, Report, Sender};
+use moq_transport::{
+    coding::{Token, TrackNamespace, TupleField},
+    serve::{self, ServeError},
+    session::{Publisher, Session, Subscriber},
+};
+
+#[derive(Parser, Clone)]
+pub struct Cli {
+    /// How to connect to the relay.
+    #[command(flatten)]
+    pub client: moq_config::Client,
+
+    /// The relay's built-in perf namespace, set with its --perf-namespace.
+    #[arg(long, default_value = "perf")]
+    pub namespace: String,
+
+    /// Upload probes to the relay, instead of downloading them from it.
+    #[arg(long)]
+    pub upload: bool,
+
+    /// The bitrate to test, in bits per second. The relay caps downloads to its --perf-max-bitrate.
+    #[arg(long, default_value = "10000000")]
+    pub bitrate: u64,
+
+    /// Stop after this many seconds.
+    #[arg(long, default_value = "10")]
+    pub duration: u64,
+
+    /// The size of each probe, in bytes.
+    #[arg(long, default_value = "1200")]
+    pub object_size: usize,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+
+    // Disable tracing so we don't get a bunch of Quinn spam.
+    let tracer = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(tracing::Level::WARN)
+        .finish();
+    tracing::subscriber::set_global_default(tracer).unwrap();
+
+    let cli = Cli::parse();
+    let tls = tls::Config::load(&cli.client.tls)?;
+
+    let quic = quic::Endpoint::new(
+        quic::Config::new(cli.client.bind, None, tls).with_transport(cli.client.transport.clone()),
+    )?;
+
+    log::info!("connecting to relay: url={}", cli.client.url);
+    let (session, connection_id, _) = quic.client.connect(&cli.client.url, None).await?;
+
+    log::info!(
+        "connected with CID: {} (use this to look up qlog/mlog on server)",
+        connection_id
+    );
+
+    let token = cli
+        .client
+        .auth_token
+        .clone()
+        .map(|token| Token::new(0, token.into_bytes()));
+    let (session, publisher, subscriber) = Session::connect_with_token(session, None, token)
+        .await
+        .context("failed to create MoQ Transport session")?;
+
+    let namespace = TrackNamespace::from_utf8_path(&cli.namespace);
+    let total = Arc::new(Mutex::new(Report::default()));
+    let test = async {
+        match cli.upload {
+            true => upload(&cli, namespace, publisher, subscriber, total.clone()).await,
+            false => download(&cli, namespace, subscriber, total.clone()).await,
+        }
+    };
+
+    tokio::select! {
+        res = session.run() => res.context("session error")?,
+        res = test => res?,
+        _ = tokio::time::sleep(Duration::from_secs(cli.duration)) => {},
+    }
+
+    println!("total  {}", total.lock().unwrap());
+
+    Ok(())
+}
+
+// Print a report on the probes the relay sends us every second.
+async fn download(
+    cli: &Cli,
+    namespace: TrackNamespace,
+    mut subscriber: Subscriber,
+    total: Arc<Mutex<Report>>,
+) -> anyhow::Result<()> {
+    let (writer, reader) = serve::Track::new(namespace, moq_perf::download(cli.bitrate)).produce();
+    let receiver = Receiver::new();
+
+    let print = async {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        ticks.tick().await;
+        for second in 1.. {
+            ticks.tick().await;
+            let report = receiver.interval();
+            println!("{:>4}s  {}", second, report);
+            total.lock().unwrap().merge(&report);
+        }
+    };
+
+    tokio::select! {
+        res = subscriber.subscribe(writer) => res.context("failed to subscribe")?,
+        res = receiver.clone().run(reader) => res?,
+        _ = print => {},
+    }
+
+    Ok(())
+}
+
+// Announce an upload and send probes to the relay once it subscribes, printing the reports it
+// sends back every second.
+async fn upload(
+    cli: &Cli,
+    namespace: TrackNamespace,
+    mut publisher: Publisher,
+    mut subscriber: Subscriber,
+    total: Arc<Mutex<Report>>,
+) -> anyhow::Result<()> {
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    let mut upload = namespace.clone();
+    upload.add(TupleField::from_utf8("upload"));
+    upload.add(TupleField::from_utf8(&id));
+
+    let (_writer, mut requests, reader) = serve::Tracks::new(upload).produce();
+    let send = async {
+        while let Some(track) = requests.next().await {
+            if track.name != moq_perf::UPLOAD_TRACK {
+                track.close(ServeError::not_found_ctx("not a perf track"))?;
+                continue;
+            }
+
+            let sender = Sender::new(cli.bitrate).with_object_size(cli.object_size);
+            sender.run(track.subgroups()?).await?;
+        }
+
+        Ok::<_, anyhow::Error>(())
+    };
+
+    let (writer, reports) = serve::Track::new(namespace, moq_perf::upload(&id)).produce();
+    let print = async {
+        let mut subgroups = match reports.mode().await? {
+            serve::TrackReaderMode::Subgroups(subgroups) => subgroups,
+            _ => anyhow::bail!("reports are sent in subgroups"),
+        };
+
+        let mut second = 0;
+        while let Some(mut subgroup) = subgroups.next().await? {
+            while let Some(payload) = subgroup.read_next().await? {
+                let report: Report = serde_json::from_slice(&payload)?;
+                second += 1;
+                println!("{:>4}s  {}", second, report);
+                total.lock().unwrap().merge(&report);
+            }
+        }
+
+        Ok(())
+    };
+
+    tokio::select! {
+        res = publisher.announce(reader) => res.context("failed to announce upload")?,
+        res = subscriber.subscribe(writer) => res.context("failed to subscribe to reports")?,
+        res = send => res?,
+        res = print => res?,
+    }
+
+    Ok(())
+}
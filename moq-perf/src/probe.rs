@@ -0,0 +1,66 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+/// The start of every test object: its sequence number and send time, followed by padding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Probe {
+    pub sequence: u64,
+    pub sent_at: SystemTime,
+}
+
+impl Probe {
+    /// The size of the header, the smallest object.
+    pub const SIZE: usize = 16;
+
+    /// Encode the probe, padded with zeros to `size` bytes.
+    pub fn encode(&self, size: usize) -> Bytes {
+        let micros = self
+            .sent_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut buf = BytesMut::with_capacity(size.max(Self::SIZE));
+        buf.put_u64(self.sequence);
+        buf.put_u64(micros);
+        buf.resize(size.max(Self::SIZE), 0);
+        buf.freeze()
+    }
+
+    pub fn decode(mut payload: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            payload.len() >= Self::SIZE,
+            "short probe: {} bytes",
+            payload.len()
+        );
+
+        let sequence = payload.get_u64();
+        let micros = payload.get_u64();
+        Ok(Self {
+            sequence,
+            sent_at: UNIX_EPOCH + Duration::from_micros(micros),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let probe = Probe {
+            sequence: 42,
+            sent_at: UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+        };
+
+        let payload = probe.encode(1200);
+        assert_eq!(payload.len(), 1200);
+        assert_eq!(Probe::decode(&payload).unwrap(), probe);
+
+        // Never smaller than the header.
+        assert_eq!(probe.encode(4).len(), Probe::SIZE);
+        assert!(Probe::decode(&payload[..8]).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Context;
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_transport::serve::{SubgroupReader, TrackReader, TrackReaderMode};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::Probe;
+
+/// What a [Receiver] measured over some period.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// The length of the period, in milliseconds.
+    pub elapsed_ms: u64,
+
+    /// The probes received, and their total size.
+    pub objects: u64,
+    pub bytes: u64,
+
+    /// The probes missing between the first and the latest one received.
+    pub lost: u64,
+
+    /// The one-way latency of the probes, in milliseconds, which assumes the clocks of the sender
+    /// and receiver are synchronized.
+    pub latency_avg_ms: f64,
+    pub latency_max_ms: f64,
+}
+
+impl Report {
+    /// The throughput, in bits per second.
+    pub fn bitrate(&self) -> u64 {
+        match self.elapsed_ms {
+            0 => 0,
+            elapsed => self.bytes * 8 * 1000 / elapsed,
+        }
+    }
+
+    /// The fraction of the probes sent that were lost.
+    pub fn loss(&self) -> f64 {
+        match self.objects + self.lost {
+            0 => 0.0,
+            sent => self.lost as f64 / sent as f64,
+        }
+    }
+
+    /// Add the measurements of the following period.
+    pub fn merge(&mut self, next: &Report) {
+        let objects = self.objects + next.objects;
+        if objects > 0 {
+            self.latency_avg_ms = (self.latency_avg_ms * self.objects as f64
+                + next.latency_avg_ms * next.objects as f64)
+                / objects as f64;
+        }
+        self.latency_max_ms = self.latency_max_ms.max(next.latency_max_ms);
+
+        self.elapsed_ms += next.elapsed_ms;
+        self.objects = objects;
+        self.bytes += next.bytes;
+        self.lost += next.lost;
+    }
+}
+
+/// Formatted like `9.98 Mbit/s, 0.0% loss, 2.1 ms avg / 5.3 ms max latency`.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.2} Mbit/s, {:.1}% loss, {:.1} ms avg / {:.1} ms max latency",
+            self.bitrate() as f64 / 1_000_000.0,
+            self.loss() * 100.0,
+            self.latency_avg_ms,
+            self.latency_max_ms
+        )
+    }
+}
+
+/// Measures the probes read from a track, in total and over each interval.
+#[derive(Clone)]
+pub struct Receiver {
+    state: Arc<Mutex<ReceiverState>>,
+}
+
+struct ReceiverState {
+    total: Stats,
+    interval: Stats,
+
+    // The lowest and highest sequence numbers received, giving the number of probes sent since we
+    // started receiving.
+    sequences: Option<(u64, u64)>,
+}
+
+#[derive(Clone)]
+struct Stats {
+    start: Instant,
+    objects: u64,
+    bytes: u64,
+    latency_sum: Duration,
+    latency_max: Duration,
+
+    // The probes lost before the period started.
+    lost_before: u64,
+}
+
+impl Stats {
+    fn new(lost_before: u64) -> Self {
+        Self {
+            start: Instant::now(),
+            objects: 0,
+            bytes: 0,
+            latency_sum: Duration::ZERO,
+            latency_max: Duration::ZERO,
+            lost_before,
+        }
+    }
+
+    fn report(&self, lost: u64) -> Report {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        Report {
+            elapsed_ms: self.start.elapsed().as_millis() as u64,
+            objects: self.objects,
+            bytes: self.bytes,
+            lost: lost.saturating_sub(self.lost_before),
+            latency_avg_ms: match self.objects {
+                0 => 0.0,
+                objects => ms(self.latency_sum) / objects as f64,
+            },
+            latency_max_ms: ms(self.latency_max),
+        }
+    }
+}
+
+impl ReceiverState {
+    // Probes may arrive out of order across groups, so some counted lost may turn up later.
+    fn lost(&self) -> u64 {
+        let sent = self
+            .sequences
+            .map_or(0, |(lowest, highest)| highest - lowest + 1);
+        sent.saturating_sub(self.total.objects)
+    }
+}
+
+impl Default for Receiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(ReceiverState {
+                total: Stats::new(0),
+                interval: Stats::new(0),
+                sequences: None,
+            })),
+        }
+    }
+
+    /// Count a probe received now.
+    pub fn receive(&self, payload: &[u8]) -> anyhow::Result<()> {
+        let probe = Probe::decode(payload)?;
+        let latency = SystemTime::now()
+            .duration_since(probe.sent_at)
+            .unwrap_or_default();
+
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        state.sequences = Some(match state.sequences {
+            Some((lowest, highest)) => (lowest.min(probe.sequence), highest.max(probe.sequence)),
+            None => (probe.sequence, probe.sequence),
+        });
+        for stats in [&mut state.total, &mut state.interval] {
+            stats.objects += 1;
+            stats.bytes += payload.len() as u64;
+            stats.latency_sum += latency;
+            stats.latency_max = stats.latency_max.max(latency);
+        }
+
+        Ok(())
+    }
+
+    /// Everything measured so far.
+    pub fn total(&self) -> Report {
+        let state = self.state.lock().unwrap();
+        state.total.report(state.lost())
+    }
+
+    /// What was measured since the previous interval, which starts a new one.
+    pub fn interval(&self) -> Report {
+        let mut state = self.state.lock().unwrap();
+        let lost = state.lost();
+        let report = state.interval.report(lost);
+        state.interval = Stats::new(lost);
+        report
+    }
+
+    /// Read the probes of `track` until it ends.
+    pub async fn run(self, track: TrackReader) -> anyhow::Result<()> {
+        let mut subgroups = match track.mode().await.context("failed to read track")? {
+            TrackReaderMode::Subgroups(subgroups) => subgroups,
+            _ => anyhow::bail!("probes are sent in subgroups"),
+        };
+
+        let mut tasks = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                res = subgroups.next() => match res? {
+                    Some(subgroup) => tasks.push(self.clone().read(subgroup)),
+                    None => break,
+                },
+                Some(res) = tasks.next() => res?,
+            }
+        }
+
+        while let Some(res) = tasks.next().await {
+            res?;
+        }
+
+        Ok(())
+    }
+
+    async fn read(self, mut subgroup: SubgroupReader) -> anyhow::Result<()> {
+        while let Some(payload) = subgroup.read_next().await? {
+            self.receive(&payload)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(sequence: u64, sent_at: SystemTime) -> bytes::Bytes {
+        Probe { sequence, sent_at }.encode(100)
+    }
+
+    #[test]
+    fn reports() {
+        let receiver = Receiver::new();
+        let sent_at = SystemTime::now() - Duration::from_millis(20);
+        for sequence in [0, 1, 3, 4] {
+            receiver.receive(&probe(sequence, sent_at)).unwrap();
+        }
+
+        let report = receiver.interval();
+        assert_eq!((report.objects, report.bytes, report.lost), (4, 400, 1));
+        assert!((0.2..0.3).contains(&report.loss()));
+        assert!(report.latency_avg_ms >= 20.0 && report.latency_max_ms >= report.latency_avg_ms);
+
+        // The late probe makes up for the loss, in total and in the next interval.
+        receiver.receive(&probe(2, sent_at)).unwrap();
+        receiver.receive(&probe(5, sent_at)).unwrap();
+        let report = receiver.interval();
+        assert_eq!((report.objects, report.lost), (2, 0));
+        assert_eq!(receiver.total().objects, 6);
+        assert_eq!(receiver.total().lost, 0);
+
+        // A sender clock ahead of ours doesn't make up negative latency.
+        receiver
+            .receive(&probe(6, SystemTime::now() + Duration::from_secs(1)))
+            .unwrap();
+        assert!(receiver.interval().latency_max_ms < 1.0);
+        assert!(receiver.receive(&[0; 4]).is_err());
+    }
+
+    #[test]
+    fn bitrate() {
+        let report = Report {
+            elapsed_ms: 2000,
+            objects: 900,
+            bytes: 2_500_000,
+            lost: 100,
+            ..Default::default()
+        };
+        assert_eq!(report.bitrate(), 10_000_000);
+        assert_eq!(report.loss(), 0.1);
+        assert_eq!(
+            report.to_string(),
+            "10.00 Mbit/s, 10.0% loss, 0.0 ms avg / 0.0 ms max latency"
+        );
+
+        let mut total = Report {
+            latency_avg_ms: 4.0,
+            latency_max_ms: 8.0,
+            ..report.clone()
+        };
+        total.merge(&Report {
+            elapsed_ms: 2000,
+            objects: 100,
+            bytes: 0,
+            lost: 0,
+            latency_avg_ms: 14.0,
+            latency_max_ms: 20.0,
+        });
+        assert_eq!(total.bitrate(), 5_000_000);
+        assert_eq!((total.objects, total.lost), (1000, 100));
+        assert_eq!((total.latency_avg_ms, total.latency_max_ms), (5.0, 20.0));
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use moq_transport::serve::{Subgroup, SubgroupWriter, SubgroupsWriter};
+use tokio::time::Instant;
+
+use crate::Probe;
+
+/// Publishes probes at a fixed bitrate, starting a group every second.
+pub struct Sender {
+    bitrate: u64,
+    object_size: usize,
+}
+
+impl Sender {
+    // Probes are written in bursts this often, since timers are too coarse to space each one.
+    const TICK: Duration = Duration::from_millis(10);
+    const GROUP: Duration = Duration::from_secs(1);
+
+    /// Send `bitrate` bits per second.
+    pub fn new(bitrate: u64) -> Self {
+        Self {
+            bitrate,
+            object_size: 1200,
+        }
+    }
+
+    /// Split the data into objects of `size` bytes, 1200 by default.
+    pub fn with_object_size(mut self, size: usize) -> Self {
+        self.object_size = size.max(Probe::SIZE);
+        self
+    }
+
+    /// Send probes until the track is closed, ex. once every subscriber leaves.
+    pub async fn run(self, mut subgroups: SubgroupsWriter) -> anyhow::Result<()> {
+        let start = Instant::now();
+        let mut ticks = tokio::time::interval(Self::TICK);
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut group: Option<(u64, SubgroupWriter)> = None;
+        let mut sequence = 0;
+        let mut sent = 0;
+
+        loop {
+            ticks.tick().await;
+            let elapsed = start.elapsed();
+
+            let group_id = (elapsed.as_nanos() / Self::GROUP.as_nanos()) as u64;
+            let writer = match &mut group {
+                Some((id, writer)) if *id == group_id => writer,
+                _ => {
+                    let writer = subgroups
+                        .create(Subgroup {
+                            group_id,
+                            subgroup_id: 0,
+                            priority: 0,
+                        })
+                        .context("failed to create group")?;
+                    &mut group.insert((group_id, writer)).1
+                }
+            };
+
+            // Catch up to the bytes due by now, so the rate holds however late the tick is.
+            let due = (self.bitrate as u128 * elapsed.as_micros() / 8_000_000) as u64;
+            while sent < due {
+                let probe = Probe {
+                    sequence,
+                    sent_at: SystemTime::now(),
+                };
+                writer
+                    .write(probe.encode(self.object_size))
+                    .context("failed to write probe")?;
+
+                sequence += 1;
+                sent += self.object_size as u64;
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::{ffi::OsString, path::PathBuf, process::Stdio, str::FromStr};
+
+use anyhow::Context;
+use bytes::BytesMut;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use url::Url;
+
+use crate::Media;
+
+/// Where to read media from.
+///
+/// Anything other than stdin is remuxed to fragmented MP4 by an ffmpeg child process,
+/// which also paces file playback in real time and keeps timestamps increasing across loops.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Input {
+    /// Fragmented MP4 on stdin, ex. piped from ffmpeg.
+    Stdin,
+
+    /// Loop a local media file forever.
+    File(PathBuf),
+
+    /// Listen for an RTMP push, ex. from OBS, at this URL.
+    Rtmp(Url),
+}
+
+impl FromStr for Input {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Self::Stdin);
+        }
+
+        if s.starts_with("rtmp://") {
+            let url = Url::parse(s).context("invalid RTMP URL")?;
+            return Ok(Self::Rtmp(url));
+        }
+
+        Ok(Self::File(s.into()))
+    }
+}
+
+impl Input {
+    /// The ffmpeg arguments that produce fragmented MP4 on stdout, or None for stdin.
+    pub fn ffmpeg_args(&self) -> Option<Vec<OsString>> {
+        let mut args: Vec<OsString> = vec!["-hide_banner".into(), "-v".into(), "error".into()];
+
+        match self {
+            Self::Stdin => return None,
+            Self::File(path) => {
+                args.extend(["-stream_loop", "-1", "-re", "-i"].map(OsString::from));
+                args.push(path.into());
+            }
+            Self::Rtmp(url) => {
+                args.extend(["-listen", "1", "-i", url.as_str()].map(OsString::from));
+            }
+        }
+
+        // The same output as dev/pub, which Media knows how to parse.
+        args.extend(
+            [
+                "-c",
+                "copy",
+                "-f",
+                "mp4",
+                "-movflags",
+                "cmaf+separate_moof+delay_moov+skip_trailer+frag_every_frame",
+                "-",
+            ]
+            .map(OsString::from),
+        );
+
+        Some(args)
+    }
+
+    /// Read from the input until it ends, publishing everything to `media`.
+    /// `ffmpeg` is the binary used for file and RTMP inputs.
+    pub async fn run(self, ffmpeg: &str, mut media: Media) -> anyhow::Result<()> {
+        let args = match self.ffmpeg_args() {
+            Some(args) => args,
+            None => return read(tokio::io::stdin(), &mut media).await,
+        };
+
+        if let Self::File(path) = &self {
+            anyhow::ensure!(path.is_file(), "input file not found: {}", path.display());
+        }
+
+        match &self {
+            Self::Rtmp(url) => log::info!("waiting for RTMP push: url={}", url),
+            _ => log::info!("reading input: {:?}", self),
+        }
+
+        let mut child = tokio::process::Command::new(ffmpeg)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to run {}", ffmpeg))?;
+
+        let stdout = child.stdout.take().context("missing ffmpeg stdout")?;
+        read(stdout, &mut media).await?;
+
+        let status = child.wait().await?;
+        anyhow::ensure!(status.success(), "ffmpeg exited: {}", status);
+
+        log::info!("input ended");
+        Ok(())
+    }
+}
+
+async fn read<R: AsyncRead + Unpin>(mut input: R, media: &mut Media) -> anyhow::Result<()> {
+    let mut buf = BytesMut::new();
+    loop {
+        let size = input
+            .read_buf(&mut buf)
+            .await
+            .context("failed to read input")?;
+        if size == 0 {
+            return Ok(());
+        }
+
+        media.parse(&mut buf).context("failed to parse media")?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!("-".parse::<Input>().unwrap(), Input::Stdin);
+        assert_eq!(
+            "dev/bbb.fmp4".parse::<Input>().unwrap(),
+            Input::File("dev/bbb.fmp4".into())
+        );
+
+        let input: Input = "rtmp://0.0.0.0:1935/live/obs".parse().unwrap();
+        let args = input.ffmpeg_args().unwrap();
+        assert!(args.contains(&"-listen".into()));
+        assert!(args.contains(&"rtmp://0.0.0.0:1935/live/obs".into()));
+        assert!(Input::Stdin.ffmpeg_args().is_none());
+    }
+}
@@ -1,2 +1,5 @@
+mod input;
 mod media;
+
+pub use input::*;
 pub use media::*;
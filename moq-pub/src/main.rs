@@ -1,42 +1,36 @@
-use bytes::BytesMut;
-use std::net;
-use url::Url;
-
 use anyhow::Context;
 use clap::Parser;
-use tokio::io::AsyncReadExt;
 
-use moq_native_ietf::quic;
-use moq_pub::Media;
-use moq_transport::{coding::TrackNamespace, serve, session::Publisher};
+use moq_native_ietf::{quic, tls};
+use moq_pub::{Input, Media};
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    serve,
+    session::Session,
+};
 
 #[derive(Parser, Clone)]
 pub struct Cli {
-    /// Listen for UDP packets on the given address.
-    #[arg(long, default_value = "[::]:0")]
-    pub bind: net::SocketAddr,
-
-    /// Advertise this frame rate in the catalog (informational)
-    // TODO auto-detect this from the input when not provided
-    #[arg(long, default_value = "24")]
-    pub fps: u8,
-
-    /// Advertise this bit rate in the catalog (informational)
-    // TODO auto-detect this from the input when not provided
-    #[arg(long, default_value = "1500000")]
-    pub bitrate: u32,
-
-    /// Connect to the given URL starting with https://
-    #[arg()]
-    pub url: Url,
+    /// How to connect to the relay.
+    #[command(flatten)]
+    pub client: moq_config::Client,
 
     /// The name of the broadcast
     #[arg(long)]
     pub name: String,
 
-    /// The TLS configuration.
-    #[command(flatten)]
-    pub tls: moq_native_ietf::tls::Args,
+    /// Mark the media tracks as live-only, so relays never cache them or serve them via FETCH.
+    #[arg(long)]
+    pub live_only: bool,
+
+    /// Read fragmented MP4 from stdin ("-"), loop a local file, or listen for an RTMP push at an rtmp:// URL.
+    /// Files and RTMP are remuxed by ffmpeg, which must be installed.
+    #[arg(long, default_value = "-")]
+    pub input: Input,
+
+    /// The ffmpeg binary used for file and RTMP inputs.
+    #[arg(long, default_value = "ffmpeg")]
+    pub ffmpeg: String,
 }
 
 #[tokio::main]
@@ -53,31 +47,33 @@ async fn main() -> anyhow::Result<()> {
 
     let (writer, _, reader) =
         serve::Tracks::new(TrackNamespace::from_utf8_path(&cli.name)).produce();
-    let media = Media::new(writer)?;
+    let media = Media::new(writer)?.with_live_only(cli.live_only);
 
-    let tls = cli.tls.load()?;
+    let tls = tls::Config::load(&cli.client.tls)?;
 
-    let quic = quic::Endpoint::new(moq_native_ietf::quic::Config::new(
-        cli.bind,
-        None,
-        tls.clone(),
-    ))?;
+    let quic = quic::Endpoint::new(
+        quic::Config::new(cli.client.bind, None, tls).with_transport(cli.client.transport.clone()),
+    )?;
 
-    log::info!("connecting to relay: url={}", cli.url);
-    let (session, connection_id) = quic.client.connect(&cli.url, None).await?;
+    log::info!("connecting to relay: url={}", cli.client.url);
+    let (session, connection_id, _) = quic.client.connect(&cli.client.url, None).await?;
 
     log::info!(
         "connected with CID: {} (use this to look up qlog/mlog on server)",
         connection_id
     );
 
-    let (session, mut publisher) = Publisher::connect(session)
+    let token = cli
+        .client
+        .auth_token
+        .map(|token| Token::new(0, token.into_bytes()));
+    let (session, mut publisher, _) = Session::connect_with_token(session, None, token)
         .await
         .context("failed to create MoQ Transport publisher")?;
 
     tokio::select! {
         res = session.run() => res.context("session error")?,
-        res = run_media(media) => {
+        res = cli.input.run(&cli.ffmpeg, media) => {
             res.context("media error")?
         },
         res = publisher.announce(reader) => res.context("publisher error")?,
@@ -85,15 +81,3 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
-
-async fn run_media(mut media: Media) -> anyhow::Result<()> {
-    let mut input = tokio::io::stdin();
-    let mut buf = BytesMut::new();
-    loop {
-        input
-            .read_buf(&mut buf)
-            .await
-            .context("failed to read from stdin")?;
-        media.parse(&mut buf).context("failed to parse media")?;
-    }
-}
@@ -18,12 +18,20 @@ pub struct Media {
     init: SubgroupsWriter,
     catalog: SubgroupsWriter,
 
+    // The published catalog and the group it was written to, so measured
+    // changes can be appended as delta updates.
+    catalog_root: Option<moq_catalog::Root>,
+    catalog_group: Option<SubgroupWriter>,
+
     // The ftyp and moov atoms at the start of the file.
     ftyp: Option<Bytes>,
     moov: Option<mp4::MoovBox>,
 
     // The current track name
     current: Option<u32>,
+
+    // Mark every track as live-only, so relays never cache or serve it via FETCH
+    live_only: bool,
 }
 
 impl Media {
@@ -42,12 +50,21 @@ impl Media {
             broadcast,
             catalog,
             init,
+            catalog_root: None,
+            catalog_group: None,
             ftyp: None,
             moov: None,
             current: None,
+            live_only: false,
         })
     }
 
+    /// Mark all media tracks as live-only in the catalog and towards the relay.
+    pub fn with_live_only(mut self, live_only: bool) -> Self {
+        self.live_only = live_only;
+        self
+    }
+
     pub fn reset(&mut self) {
         for track in self.tracks.values_mut() {
             track.end_group();
@@ -124,10 +141,18 @@ impl Media {
                 anyhow::ensure!(self.current.is_none(), "multiple moof atoms");
                 self.current.replace(fragment.track);
 
+                // Measure the frame rate and bitrate before counting this fragment.
+                let measurement = track.stats.fragment(fragment.timestamp, track.timescale);
+                let (index, handler) = (track.catalog_index, track.handler);
+
                 // Publish the moof header, creating a new segment if it's a keyframe.
                 track
                     .header(atom, fragment)
                     .context("failed to publish moof")?;
+
+                if let Some(measurement) = measurement {
+                    self.update_catalog(index, handler, measurement)?;
+                }
             }
             mp4::BoxType::MdatBox => {
                 // Get the track ID from the previous moof.
@@ -175,6 +200,7 @@ impl Media {
                 namespace: Some(self.broadcast.namespace.to_utf8_path()),
                 packaging: Some(moq_catalog::TrackPackaging::Cmaf),
                 render_group: Some(1),
+                delivery: self.live_only.then_some(moq_catalog::TrackDelivery::Live),
                 ..Default::default()
             };
 
@@ -242,12 +268,16 @@ impl Media {
             }
 
             track.selection_params = selection_params;
+            let bitrate = track.selection_params.bitrate;
 
             tracks.push(track);
 
             // Store the track publisher in a map so we can update it later.
             let track = self.broadcast.create(&name).context("broadcast closed")?;
-            let track = Track::new(track, handler, timescale);
+            track.set_fetchable(!self.live_only)?;
+            // Let relays pace delivery to the catalog bitrate.
+            track.set_bitrate(bitrate.map(Into::into))?;
+            let track = Track::new(track, handler, timescale, tracks.len() - 1);
             self.tracks.insert(id, track);
         }
 
@@ -265,7 +295,70 @@ impl Media {
         log::info!("catalog: {}", catalog_str);
 
         // Create a single fragment for the segment.
-        self.catalog.append(0)?.write(catalog_str.into())?;
+        // Keep the group open so delta updates can be appended to it.
+        let mut group = self.catalog.append(0)?;
+        group.write(catalog_str.into())?;
+
+        self.catalog_root = Some(catalog);
+        self.catalog_group = Some(group);
+
+        Ok(())
+    }
+
+    // Apply a measurement to the catalog, publishing a JSON Patch delta update if it changed.
+    fn update_catalog(
+        &mut self,
+        index: usize,
+        handler: TrackType,
+        measurement: Measurement,
+    ) -> anyhow::Result<()> {
+        let root = self.catalog_root.as_mut().context("missing catalog")?;
+        let params = &mut root
+            .tracks
+            .get_mut(index)
+            .context("missing catalog track")?
+            .selection_params;
+
+        let mut patch = Vec::new();
+
+        if handler == TrackType::Video && params.framerate != Some(measurement.framerate) {
+            params.framerate = Some(measurement.framerate);
+            patch.push(serde_json::json!({
+                "op": "add",
+                "path": format!("/tracks/{}/selectionParams/framerate", index),
+                "value": measurement.framerate,
+            }));
+        }
+
+        // Ignore small fluctuations so we don't publish an update for every window.
+        let bitrate_changed = match params.bitrate {
+            Some(bitrate) => {
+                bitrate.abs_diff(measurement.bitrate) as u64 * 100
+                    > bitrate as u64 * BITRATE_CHANGE_PERCENT
+            }
+            None => true,
+        };
+
+        if bitrate_changed {
+            params.bitrate = Some(measurement.bitrate);
+            patch.push(serde_json::json!({
+                "op": "add",
+                "path": format!("/tracks/{}/selectionParams/bitrate", index),
+                "value": measurement.bitrate,
+            }));
+        }
+
+        if patch.is_empty() {
+            return Ok(());
+        }
+
+        let patch_str = serde_json::to_string(&patch)?;
+        log::info!("catalog update: {}", patch_str);
+
+        self.catalog_group
+            .as_mut()
+            .context("missing catalog group")?
+            .write(patch_str.into())?;
 
         Ok(())
     }
@@ -328,19 +421,29 @@ struct Track {
 
     // The type of track, ex. "vide" or "soun"
     handler: TrackType,
+
+    // The index of this track in the catalog
+    catalog_index: usize,
+
+    // Measured frame rate and bitrate
+    stats: TrackStats,
 }
 
 impl Track {
-    fn new(track: TrackWriter, handler: TrackType, timescale: u64) -> Self {
+    fn new(track: TrackWriter, handler: TrackType, timescale: u64, catalog_index: usize) -> Self {
         Self {
             track: track.subgroups().unwrap(),
             current: None,
             timescale,
             handler,
+            catalog_index,
+            stats: Default::default(),
         }
     }
 
     pub fn header(&mut self, raw: Bytes, fragment: Fragment) -> anyhow::Result<()> {
+        self.stats.add(raw.len(), fragment.samples);
+
         if let Some(current) = self.current.as_mut() {
             // Use the existing segment
             current.write(raw)?;
@@ -377,6 +480,8 @@ impl Track {
     }
 
     pub fn data(&mut self, raw: Bytes) -> anyhow::Result<()> {
+        self.stats.add(raw.len(), 0);
+
         let segment = self.current.as_mut().context("missing current fragment")?;
         segment.write(raw)?;
 
@@ -388,6 +493,73 @@ impl Track {
     }
 }
 
+// Measure over at least this many seconds of media time.
+const STATS_WINDOW_SECS: u64 = 2;
+
+// Only publish a bitrate update if it changed by more than this percentage.
+const BITRATE_CHANGE_PERCENT: u64 = 10;
+
+struct Measurement {
+    // Frames (samples) per second
+    framerate: u64,
+
+    // Bits per second, including the moof headers
+    bitrate: u32,
+}
+
+// Measures the frame rate and bitrate of a track from fragment timestamps and sizes.
+#[derive(Default)]
+struct TrackStats {
+    // The timestamp of the first fragment in the current window, in timescale units.
+    start: Option<u64>,
+
+    // The bytes and samples received since the start of the window.
+    bytes: u64,
+    samples: u64,
+}
+
+impl TrackStats {
+    // Called with the timestamp of each new fragment, before it is counted.
+    // Returns a measurement once the window covers enough media time.
+    fn fragment(&mut self, timestamp: u64, timescale: u64) -> Option<Measurement> {
+        let start = match self.start {
+            // The timestamps went backwards, so start over.
+            Some(start) if timestamp < start => {
+                self.reset(timestamp);
+                return None;
+            }
+            Some(start) => start,
+            None => {
+                self.reset(timestamp);
+                return None;
+            }
+        };
+
+        let elapsed = timestamp - start;
+        if elapsed < STATS_WINDOW_SECS * timescale || self.samples == 0 {
+            return None;
+        }
+
+        let framerate = (self.samples * timescale + elapsed / 2) / elapsed;
+        let bitrate = (self.bytes * 8 * timescale / elapsed).min(u32::MAX as u64) as u32;
+
+        self.reset(timestamp);
+
+        Some(Measurement { framerate, bitrate })
+    }
+
+    fn add(&mut self, bytes: usize, samples: u32) {
+        self.bytes += bytes as u64;
+        self.samples += samples as u64;
+    }
+
+    fn reset(&mut self, timestamp: u64) {
+        self.start = Some(timestamp);
+        self.bytes = 0;
+        self.samples = 0;
+    }
+}
+
 struct Fragment {
     // The track for this fragment.
     track: u32,
@@ -397,6 +569,9 @@ struct Fragment {
 
     // True if this fragment is a keyframe.
     keyframe: bool,
+
+    // The number of samples (frames) in this fragment.
+    samples: u32,
 }
 
 impl Fragment {
@@ -411,10 +586,18 @@ impl Fragment {
         // Detect if we should start a new segment.
         let keyframe = sample_keyframe(&moof);
 
+        let samples = moof
+            .trafs
+            .iter()
+            .filter_map(|traf| traf.trun.as_ref())
+            .map(|trun| trun.sample_count)
+            .sum();
+
         Ok(Self {
             track,
             timestamp,
             keyframe,
+            samples,
         })
     }
 
@@ -443,8 +626,10 @@ fn sample_keyframe(moof: &mp4::MoofBox) -> bool {
                 None => default_flags,
             };
 
-            if i == 0 && trun.first_sample_flags.is_some() {
-                flags = trun.first_sample_flags.unwrap();
+            if i == 0 {
+                if let Some(first_flags) = trun.first_sample_flags {
+                    flags = first_flags;
+                }
             }
 
             // https://chromium.googlesource.com/chromium/src/media/+/master/formats/mp4/track_run_iterator.cc#177
@@ -0,0 +1,993 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap, StatusCode},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use moq_native_ietf::quic;
+use moq_transport::coding::{Telemetry, TrackNamespace};
+use moq_transport::session::{
+    AnnounceSnapshot, ClockSkewStats, GoAway, Publisher, RequestState, Subscriber,
+    SubscriptionSnapshot,
+};
+use serde::Serialize;
+use tokio::sync::watch;
+
+use crate::{
+    AnnounceProgress, Archive, ArchiveStats, CacheStats, CaptureMetrics, CaptureStats,
+    CloseMetrics, CloseStats, CoordinatorMetrics, CoordinatorStats, FlagRollout, Flags, GroupCache,
+    Locals, LogFilter, LookupCache, LookupCacheStats, PreviewEntry, Previews, QuotaStats, Quotas,
+    SessionAnnounceLimiter, TeardownMetrics, TeardownStats, ValidationStats, Validators,
+};
+
+/// Handle for inspecting and controlling a running relay.
+#[derive(Clone)]
+pub struct Admin {
+    sessions: Arc<Mutex<AdminSessions>>,
+    locals: Locals,
+    reregister: Arc<watch::Sender<u64>>,
+    teardown: TeardownMetrics,
+    closes: CloseMetrics,
+    captures: CaptureMetrics,
+    coordinator: CoordinatorMetrics,
+    flags: Flags,
+    quotas: Quotas,
+    validators: Validators,
+    cache: Option<GroupCache>,
+    archive: Option<Archive>,
+    previews: Option<Previews>,
+    log_filter: Option<LogFilter>,
+    lookups: Option<LookupCache>,
+}
+
+#[derive(Default)]
+struct AdminSessions {
+    next_id: u64,
+    active: HashMap<u64, AdminSession>,
+}
+
+struct AdminSession {
+    connection_id: String,
+    // Our side of the session: a subscriber if the peer publishes, a publisher if it subscribes.
+    subscriber: Option<Subscriber>,
+    publisher: Option<Publisher>,
+    connected_at: SystemTime,
+    announces: Option<SessionAnnounceLimiter>,
+    webtransport: web_transport::Session,
+    connection: quic::Connection,
+    goaway: GoAway,
+}
+
+/// An active session, as listed by the admin API.
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub connection_id: String,
+    /// The roles the peer plays: "publisher" if it may announce to us, "subscriber" if it may subscribe.
+    pub roles: Vec<&'static str>,
+    /// Connection time, in seconds since the Unix epoch.
+    pub connected_at: u64,
+    /// Announce registration progress, if the peer may publish.
+    pub announces: Option<AnnounceProgress>,
+    pub connection: ConnectionInfo,
+    /// Whether the session's mlog is being captured because of its symptoms.
+    pub capturing: bool,
+    /// The peer's clock, if it publishes objects with capture timestamps.
+    pub clock: Option<ClockInfo>,
+}
+
+/// The clock of a publishing peer, estimated from the capture timestamps of its objects.
+#[derive(Serialize)]
+pub struct ClockInfo {
+    /// Milliseconds the peer's clock is ahead of ours, or behind if negative. For a peer
+    /// forwarding several publishers, that of the latest one to send a timestamp.
+    pub offset_ms: f64,
+    /// Objects received with a capture timestamp.
+    pub samples: u64,
+    /// Milliseconds from their capture until we received them, corrected for the offset.
+    pub latency_avg_ms: f64,
+    pub latency_max_ms: f64,
+}
+
+impl ClockInfo {
+    fn new(stats: ClockSkewStats) -> Option<Self> {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+
+        Some(Self {
+            offset_ms: stats.offset_us? as f64 / 1000.0,
+            samples: stats.samples,
+            latency_avg_ms: ms(stats.latency_avg),
+            latency_max_ms: ms(stats.latency_max),
+        })
+    }
+}
+
+/// QUIC statistics of a session, as listed by the admin API.
+#[derive(Serialize)]
+pub struct ConnectionInfo {
+    pub remote_address: net::SocketAddr,
+    pub rtt_ms: f64,
+    pub cwnd: u64,
+    pub congestion_events: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+    pub lost_bytes: u64,
+    pub mtu: u16,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+impl From<&quic::Connection> for ConnectionInfo {
+    fn from(connection: &quic::Connection) -> Self {
+        let stats = connection.stats();
+
+        Self {
+            remote_address: connection.remote_address(),
+            rtt_ms: stats.rtt.as_secs_f64() * 1000.0,
+            cwnd: stats.cwnd,
+            congestion_events: stats.congestion_events,
+            sent_packets: stats.sent_packets,
+            lost_packets: stats.lost_packets,
+            lost_bytes: stats.lost_bytes,
+            mtu: stats.mtu,
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+        }
+    }
+}
+
+/// The subscriptions and announces of a session, as listed by the admin API.
+#[derive(Serialize)]
+pub struct SessionActivity {
+    /// The peer's subscriptions to our tracks.
+    pub subscriptions: Vec<SubscriptionActivity>,
+    /// Our subscriptions to the peer's tracks.
+    pub upstream_subscriptions: Vec<SubscriptionActivity>,
+    /// Namespaces the peer announced to us.
+    pub announces: Vec<AnnounceActivity>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriptionActivity {
+    pub id: u64,
+    pub namespace: String,
+    pub track: String,
+    pub track_alias: Option<u64>,
+    /// "pending" until SUBSCRIBE_OK, then "active".
+    pub state: &'static str,
+    /// The latest object fully delivered, as `[group, object]`.
+    pub position: Option<[u64; 2]>,
+    /// How many groups a viewer is behind the newest group the relay has received for the track,
+    /// if the relay is receiving it. Only set for the peer's subscriptions.
+    pub lag_groups: Option<u64>,
+    /// The playback telemetry the subscriber last reported, if any.
+    pub telemetry: Option<TelemetryActivity>,
+}
+
+#[derive(Serialize)]
+pub struct TelemetryActivity {
+    pub buffer_length_ms: Option<u64>,
+    pub playback_rate: Option<f64>,
+    pub session_id: Option<String>,
+}
+
+impl From<Telemetry> for TelemetryActivity {
+    fn from(telemetry: Telemetry) -> Self {
+        Self {
+            buffer_length_ms: telemetry
+                .buffer_length
+                .map(|length| length.as_millis() as u64),
+            playback_rate: telemetry.playback_rate,
+            session_id: telemetry.session_id,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AnnounceActivity {
+    pub request_id: u64,
+    pub namespace: String,
+    /// "pending" until PUBLISH_NAMESPACE_OK, then "active".
+    pub state: &'static str,
+}
+
+fn request_state(state: RequestState) -> &'static str {
+    match state {
+        RequestState::Pending => "pending",
+        RequestState::Active => "active",
+    }
+}
+
+impl From<SubscriptionSnapshot> for SubscriptionActivity {
+    fn from(snapshot: SubscriptionSnapshot) -> Self {
+        Self {
+            id: snapshot.id,
+            namespace: snapshot.track_namespace.to_utf8_path(),
+            track: snapshot.track_name,
+            track_alias: snapshot.track_alias,
+            state: request_state(snapshot.state),
+            position: snapshot
+                .position
+                .map(|position| [position.group_id, position.object_id]),
+            lag_groups: None,
+            telemetry: snapshot.telemetry.map(Into::into),
+        }
+    }
+}
+
+impl From<AnnounceSnapshot> for AnnounceActivity {
+    fn from(snapshot: AnnounceSnapshot) -> Self {
+        Self {
+            request_id: snapshot.request_id,
+            namespace: snapshot.namespace.to_utf8_path(),
+            state: request_state(snapshot.state),
+        }
+    }
+}
+
+/// A locally announced namespace, as listed by the admin API.
+#[derive(Serialize)]
+pub struct NamespaceInfo {
+    pub namespace: String,
+    pub subscribers: usize,
+    /// Whether the publisher went quiet, see [crate::LivenessConfig].
+    pub stale: bool,
+}
+
+impl Admin {
+    pub fn new(locals: Locals, flags: Flags) -> Self {
+        let (reregister, _) = watch::channel(0);
+
+        Self {
+            sessions: Default::default(),
+            locals,
+            reregister: Arc::new(reregister),
+            teardown: Default::default(),
+            closes: Default::default(),
+            captures: Default::default(),
+            coordinator: Default::default(),
+            flags,
+            quotas: Quotas::default(),
+            validators: Validators::default(),
+            cache: None,
+            archive: None,
+            previews: None,
+            log_filter: None,
+            lookups: None,
+        }
+    }
+
+    /// Report the hit rates of `cache`.
+    pub fn with_cache(mut self, cache: GroupCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Report the size of `archive`.
+    pub fn with_archive(mut self, archive: Archive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// List the tracks sampled by `previews`.
+    pub fn with_previews(mut self, previews: Previews) -> Self {
+        self.previews = Some(previews);
+        self
+    }
+
+    /// Report the hit rate of `lookups`.
+    pub fn with_lookup_cache(mut self, lookups: LookupCache) -> Self {
+        self.lookups = Some(lookups);
+        self
+    }
+
+    /// Report and change the process' log filter with `log_filter`.
+    pub fn with_log_filter(mut self, log_filter: LogFilter) -> Self {
+        self.log_filter = Some(log_filter);
+        self
+    }
+
+    /// Enforce and report `quotas`.
+    pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Check the objects published to the relay with `validators`, and report their failures.
+    pub fn with_validators(mut self, validators: Validators) -> Self {
+        self.validators = validators;
+        self
+    }
+
+    /// Track an accepted session until the returned guard is dropped.
+    ///
+    /// `subscriber` and `publisher` are our side of the session: we have a subscriber if the peer
+    /// publishes to us, and a publisher if it may subscribe to our tracks.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_session(
+        &self,
+        connection_id: String,
+        webtransport: web_transport::Session,
+        connection: quic::Connection,
+        goaway: GoAway,
+        subscriber: Option<Subscriber>,
+        publisher: Option<Publisher>,
+        announces: Option<SessionAnnounceLimiter>,
+    ) -> AdminSessionGuard {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+
+        sessions.active.insert(
+            id,
+            AdminSession {
+                connection_id,
+                subscriber,
+                publisher,
+                connected_at: SystemTime::now(),
+                announces,
+                webtransport,
+                connection,
+                goaway,
+            },
+        );
+
+        AdminSessionGuard {
+            admin: self.clone(),
+            id,
+        }
+    }
+
+    /// List the active sessions, oldest first.
+    pub fn sessions(&self) -> Vec<SessionInfo> {
+        let sessions = self.sessions.lock().unwrap();
+
+        let mut list: Vec<_> = sessions
+            .active
+            .iter()
+            .map(|(id, session)| {
+                let mut roles = Vec::new();
+                if session.subscriber.is_some() {
+                    roles.push("publisher");
+                }
+                if session.publisher.is_some() {
+                    roles.push("subscriber");
+                }
+
+                SessionInfo {
+                    id: *id,
+                    connection_id: session.connection_id.clone(),
+                    roles,
+                    connected_at: session
+                        .connected_at
+                        .duration_since(UNIX_EPOCH)
+                        .map(|time| time.as_secs())
+                        .unwrap_or_default(),
+                    announces: session.announces.as_ref().map(|limiter| limiter.progress()),
+                    connection: (&session.connection).into(),
+                    capturing: self.captures.is_capturing(&session.connection_id),
+                    clock: session
+                        .subscriber
+                        .as_ref()
+                        .and_then(|subscriber| ClockInfo::new(subscriber.clock_skew().stats())),
+                }
+            })
+            .collect();
+
+        list.sort_by_key(|session| session.id);
+        list
+    }
+
+    /// List the subscriptions and announces of the session with the given id, if it exists.
+    pub fn session_activity(&self, id: u64) -> Option<SessionActivity> {
+        let sessions = self.sessions.lock().unwrap();
+        let session = sessions.active.get(&id)?;
+
+        let subscriptions = session
+            .publisher
+            .as_ref()
+            .map(|publisher| publisher.subscriptions())
+            .unwrap_or_default();
+        let (upstream_subscriptions, announces) = session
+            .subscriber
+            .as_ref()
+            .map(|subscriber| (subscriber.subscriptions(), subscriber.announces()))
+            .unwrap_or_default();
+
+        // The newest group received from upstream for each track, across every session.
+        let mut received = HashMap::new();
+        for subscription in sessions
+            .active
+            .values()
+            .filter_map(|session| session.subscriber.as_ref())
+            .flat_map(|subscriber| subscriber.subscriptions())
+        {
+            if let Some(position) = subscription.position {
+                let key = (subscription.track_namespace, subscription.track_name);
+                let group_id = received.entry(key).or_insert(position.group_id);
+                *group_id = position.group_id.max(*group_id);
+            }
+        }
+
+        let subscriptions = subscriptions
+            .into_iter()
+            .map(|snapshot| {
+                let key = (
+                    snapshot.track_namespace.clone(),
+                    snapshot.track_name.clone(),
+                );
+                let lag_groups = received.get(&key).map(|received| {
+                    let delivered = snapshot.position.map(|position| position.group_id);
+                    received.saturating_sub(delivered.unwrap_or(0))
+                });
+
+                SubscriptionActivity {
+                    lag_groups,
+                    ..snapshot.into()
+                }
+            })
+            .collect();
+
+        Some(SessionActivity {
+            subscriptions,
+            upstream_subscriptions: upstream_subscriptions.into_iter().map(Into::into).collect(),
+            announces: announces.into_iter().map(Into::into).collect(),
+        })
+    }
+
+    /// Close the session with the given id, returning false if it doesn't exist.
+    pub fn close_session(&self, id: u64) -> bool {
+        let webtransport = match self.sessions.lock().unwrap().active.get(&id) {
+            Some(session) => session.webtransport.clone(),
+            None => return false,
+        };
+
+        log::info!("closing session {} from admin API", id);
+        webtransport.close(0, "closed by admin");
+        true
+    }
+
+    /// Start capturing the mlog of the session with the given id, see [CaptureMetrics::request].
+    /// Returns None if the session doesn't exist, and false if its mlog can't be captured.
+    pub fn capture_session(&self, id: u64) -> Option<bool> {
+        let connection_id = self
+            .sessions
+            .lock()
+            .unwrap()
+            .active
+            .get(&id)?
+            .connection_id
+            .clone();
+
+        log::info!("capturing session {} from admin API", id);
+        Some(self.captures.request(&connection_id))
+    }
+
+    /// Send GOAWAY to every session, asking its peer to reconnect to `uri`, or the same URI if empty.
+    /// Returns the number of sessions told.
+    pub fn goaway(&self, uri: &str) -> usize {
+        let sessions = self.sessions.lock().unwrap();
+        for session in sessions.active.values() {
+            session.goaway.clone().send(uri);
+        }
+
+        log::info!(
+            "sent GOAWAY to {} sessions, uri={:?}",
+            sessions.active.len(),
+            uri
+        );
+        sessions.active.len()
+    }
+
+    /// List the locally announced namespaces and their subscriber counts.
+    pub fn namespaces(&self) -> Vec<NamespaceInfo> {
+        let mut list: Vec<_> = self
+            .locals
+            .namespaces()
+            .into_iter()
+            .map(|(namespace, subscribers)| NamespaceInfo {
+                stale: self.locals.is_stale(&namespace),
+                namespace: namespace.to_utf8_path(),
+                subscribers,
+            })
+            .collect();
+
+        list.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        list
+    }
+
+    /// Cancel every announce of `namespace`, see [Locals::revoke].
+    /// Returns the number of cancelled announces. The publishers may announce it again.
+    pub fn revoke_namespace(&self, namespace: &TrackNamespace) -> usize {
+        self.locals.revoke(namespace)
+    }
+
+    /// Ask every consumer to re-advertise its namespaces with the coordinator.
+    /// Returns the number of namespaces currently announced.
+    pub fn reregister(&self) -> usize {
+        self.reregister.send_modify(|generation| *generation += 1);
+        self.locals.namespaces().len()
+    }
+
+    /// Receive re-registration requests triggered by [Admin::reregister].
+    pub fn reregister_requests(&self) -> watch::Receiver<u64> {
+        self.reregister.subscribe()
+    }
+
+    /// Counters updated by every session's teardown.
+    pub fn teardown_metrics(&self) -> TeardownMetrics {
+        self.teardown.clone()
+    }
+
+    /// How many sessions and namespaces have been torn down.
+    pub fn teardown_stats(&self) -> TeardownStats {
+        self.teardown.stats()
+    }
+
+    /// Counters updated whenever a session closes.
+    pub fn close_metrics(&self) -> CloseMetrics {
+        self.closes.clone()
+    }
+
+    /// Why sessions closed, and the most recent closes.
+    pub fn close_stats(&self) -> CloseStats {
+        self.closes.stats()
+    }
+
+    /// Records of the sessions captured because of their symptoms.
+    pub fn capture_metrics(&self) -> CaptureMetrics {
+        self.captures.clone()
+    }
+
+    /// The sessions being captured, and the most recent captures.
+    pub fn capture_stats(&self) -> CaptureStats {
+        self.captures.stats()
+    }
+
+    /// Latency counters for calls to the coordinator.
+    pub fn coordinator_metrics(&self) -> CoordinatorMetrics {
+        self.coordinator.clone()
+    }
+
+    /// How many coordinator calls were made, and how many failed or timed out.
+    pub fn coordinator_stats(&self) -> CoordinatorStats {
+        self.coordinator.stats()
+    }
+
+    /// How many namespace lookups were answered from the cache, if enabled.
+    pub fn lookup_cache_stats(&self) -> Option<LookupCacheStats> {
+        self.lookups.as_ref().map(LookupCache::stats)
+    }
+
+    /// How many groups each cache tier holds and serves, if the cache is enabled.
+    pub fn cache_stats(&self) -> Option<CacheStats> {
+        self.cache.as_ref().map(GroupCache::stats)
+    }
+
+    /// How many groups the archive holds and serves, if enabled.
+    pub fn archive_stats(&self) -> Option<ArchiveStats> {
+        self.archive.as_ref().map(Archive::stats)
+    }
+
+    /// The tracks sampled for previews, if enabled.
+    pub fn previews(&self) -> Option<Vec<PreviewEntry>> {
+        self.previews.as_ref().map(Previews::list)
+    }
+
+    /// The process' log filter, if it can be changed.
+    pub fn log_filter(&self) -> Option<&LogFilter> {
+        self.log_filter.as_ref()
+    }
+
+    /// The experiment flags shared by every session.
+    pub fn flags(&self) -> Flags {
+        self.flags.clone()
+    }
+
+    /// The namespace quotas shared by every session.
+    pub fn quotas(&self) -> Quotas {
+        self.quotas.clone()
+    }
+
+    /// How much of each namespace quota is used, and how many subscriptions it rejected.
+    pub fn quota_stats(&self) -> BTreeMap<String, QuotaStats> {
+        self.quotas.stats()
+    }
+
+    /// The object validators shared by every session.
+    pub fn validators(&self) -> Validators {
+        self.validators.clone()
+    }
+
+    /// How many objects were checked under each validated prefix, and how many failed.
+    pub fn validation_stats(&self) -> BTreeMap<String, ValidationStats> {
+        self.validators.stats()
+    }
+}
+
+/// Removes a session from the admin registry on drop.
+pub struct AdminSessionGuard {
+    admin: Admin,
+    id: u64,
+}
+
+impl Drop for AdminSessionGuard {
+    fn drop(&mut self) {
+        self.admin.sessions.lock().unwrap().active.remove(&self.id);
+    }
+}
+
+pub struct AdminConfig {
+    /// Listen for plain HTTP on this address. Bind to localhost unless a token is set.
+    pub bind: net::SocketAddr,
+
+    /// Optional bearer token required for every request.
+    pub token: Option<String>,
+}
+
+#[derive(Clone)]
+struct AdminState {
+    admin: Admin,
+    token: Option<Arc<String>>,
+}
+
+/// JSON admin API for operating a relay.
+///
+/// - `GET /sessions` lists active sessions and their QUIC statistics
+/// - `GET /sessions/:id/activity` lists a session's subscriptions, with the telemetry their subscribers
+///   reported, and announces
+/// - `POST /sessions/:id/close` closes a session
+/// - `POST /sessions/:id/capture` captures a session's mlog, as if one of its symptoms triggered it
+/// - `GET /sessions/closed` counts closed sessions by reason and lists the most recent
+/// - `GET /sessions/captures` lists the sessions whose mlog is captured because of their symptoms
+/// - `GET /namespaces` lists announced namespaces and their subscriber counts
+/// - `DELETE /namespaces/*namespace` cancels the announces of a namespace, ex. `/namespaces/live/room`
+/// - `POST /coordinator/reregister` re-advertises every namespace with the coordinator
+/// - `GET /coordinator/stats` reports coordinator call latencies, errors and timeouts
+/// - `GET /cache` reports the size and hit rate of each cache tier
+/// - `GET /archive` reports the size of the archive, and how many groups it served and expired
+/// - `GET /previews` lists the tracks sampled for previews, and their latest sample
+/// - `GET /quotas` reports the usage of each namespace quota, and the subscriptions it rejected
+/// - `GET /validation` reports the objects checked under each validated prefix, and those that failed
+/// - `GET /teardown` reports how many publisher sessions and namespaces have been torn down
+/// - `GET /flags` lists experiment flags and their rollouts
+/// - `PUT /flags/:name` sets the rollout of a flag, `DELETE /flags/:name` disables it
+/// - `GET /log` returns the log filter, `PUT /log` replaces it, ex. `info,moq_relay_ietf=debug`
+#[derive(Clone)]
+pub struct AdminServer {
+    app: Router,
+    bind: net::SocketAddr,
+}
+
+impl AdminServer {
+    pub fn new(config: AdminConfig, admin: Admin) -> Self {
+        let state = AdminState {
+            admin,
+            token: config.token.map(Arc::new),
+        };
+
+        let app = Router::new()
+            .route("/sessions", get(list_sessions))
+            .route("/sessions/:id/activity", get(session_activity))
+            .route("/sessions/:id/close", post(close_session))
+            .route("/sessions/:id/capture", post(capture_session))
+            .route("/sessions/closed", get(close_stats))
+            .route("/sessions/captures", get(capture_stats))
+            .route("/namespaces", get(list_namespaces))
+            .route("/namespaces/*namespace", delete(revoke_namespace))
+            .route("/coordinator/reregister", post(reregister))
+            .route("/coordinator/stats", get(coordinator_stats))
+            .route("/coordinator/lookups", get(lookup_cache_stats))
+            .route("/cache", get(cache_stats))
+            .route("/archive", get(archive_stats))
+            .route("/previews", get(list_previews))
+            .route("/quotas", get(quota_stats))
+            .route("/validation", get(validation_stats))
+            .route("/teardown", get(teardown_stats))
+            .route("/flags", get(list_flags))
+            .route("/flags/:name", put(set_flag).delete(remove_flag))
+            .route("/log", get(log_filter).put(set_log_filter))
+            .with_state(state);
+
+        Self {
+            app,
+            bind: config.bind,
+        }
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind(self.bind).await?;
+        log::info!("admin API listening on {}", listener.local_addr()?);
+
+        axum::serve(listener, self.app).await?;
+        Ok(())
+    }
+}
+
+fn authorize(state: &AdminState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let expected = match &state.token {
+        Some(token) => token,
+        None => return Ok(()),
+    };
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if crate::web::constant_time_eq(token.as_bytes(), expected.as_bytes()) => {
+            Ok(())
+        }
+        _ => Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string())),
+    }
+}
+
+async fn list_sessions(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SessionInfo>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.sessions()))
+}
+
+async fn session_activity(
+    Path(id): Path<u64>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<SessionActivity>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    match state.admin.session_activity(id) {
+        Some(activity) => Ok(Json(activity)),
+        None => Err((StatusCode::NOT_FOUND, format!("Session not found: {}", id))),
+    }
+}
+
+async fn close_session(
+    Path(id): Path<u64>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    match state.admin.close_session(id) {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((StatusCode::NOT_FOUND, format!("Session not found: {}", id))),
+    }
+}
+
+async fn capture_session(
+    Path(id): Path<u64>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    match state.admin.capture_session(id) {
+        Some(true) => Ok(StatusCode::NO_CONTENT),
+        Some(false) => Err((StatusCode::NOT_FOUND, "capture disabled".to_string())),
+        None => Err((StatusCode::NOT_FOUND, format!("Session not found: {}", id))),
+    }
+}
+
+async fn close_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<CloseStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.close_stats()))
+}
+
+async fn capture_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<CaptureStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.capture_stats()))
+}
+
+async fn list_namespaces(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<NamespaceInfo>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.namespaces()))
+}
+
+async fn revoke_namespace(
+    Path(namespace): Path<String>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    match state
+        .admin
+        .revoke_namespace(&TrackNamespace::from_utf8_path(&namespace))
+    {
+        0 => Err((
+            StatusCode::NOT_FOUND,
+            format!("Namespace not found: {}", namespace),
+        )),
+        count => {
+            log::info!(
+                "revoked {} announces of namespace {} from admin API",
+                count,
+                namespace
+            );
+            Ok(StatusCode::NO_CONTENT)
+        }
+    }
+}
+
+async fn quota_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<BTreeMap<String, QuotaStats>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.quota_stats()))
+}
+
+async fn validation_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<BTreeMap<String, ValidationStats>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.validation_stats()))
+}
+
+async fn teardown_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<TeardownStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.teardown_stats()))
+}
+
+async fn coordinator_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<CoordinatorStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.coordinator_stats()))
+}
+
+async fn lookup_cache_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<LookupCacheStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    state
+        .admin
+        .lookup_cache_stats()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "lookup cache disabled".to_string()))
+}
+
+async fn cache_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<CacheStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    state
+        .admin
+        .cache_stats()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "cache disabled".to_string()))
+}
+
+async fn archive_stats(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<ArchiveStats>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    state
+        .admin
+        .archive_stats()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "archive disabled".to_string()))
+}
+
+async fn list_previews(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<PreviewEntry>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    state
+        .admin
+        .previews()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, "previews disabled".to_string()))
+}
+
+async fn list_flags(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<BTreeMap<String, FlagRollout>>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    Ok(Json(state.admin.flags.list()))
+}
+
+async fn set_flag(
+    Path(name): Path<String>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    Json(rollout): Json<FlagRollout>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    state
+        .admin
+        .flags
+        .set(&name, rollout)
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn remove_flag(
+    Path(name): Path<String>,
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    match state.admin.flags.remove(&name) {
+        true => Ok(StatusCode::NO_CONTENT),
+        false => Err((StatusCode::NOT_FOUND, format!("Flag not found: {}", name))),
+    }
+}
+
+async fn log_filter(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<String, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+    state
+        .admin
+        .log_filter()
+        .map(LogFilter::spec)
+        .ok_or((StatusCode::NOT_FOUND, "log filter disabled".to_string()))
+}
+
+async fn set_log_filter(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+    spec: String,
+) -> Result<StatusCode, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    let filter = state
+        .admin
+        .log_filter()
+        .ok_or((StatusCode::NOT_FOUND, "log filter disabled".to_string()))?;
+    filter
+        .set(spec.trim())
+        .map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Serialize)]
+struct ReregisterResponse {
+    namespaces: usize,
+}
+
+async fn reregister(
+    State(state): State<AdminState>,
+    headers: HeaderMap,
+) -> Result<Json<ReregisterResponse>, (StatusCode, String)> {
+    authorize(&state, &headers)?;
+
+    let namespaces = state.admin.reregister();
+    log::info!("re-registering {} namespaces from admin API", namespaces);
+
+    Ok(Json(ReregisterResponse { namespaces }))
+}
@@ -0,0 +1,153 @@
+use std::sync::Arc;
+
+use moq_transport::coding::TrackNamespace;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{Coordinator, CoordinatorError, CoordinatorResult, NamespaceRegistration};
+
+type Queued = (
+    TrackNamespace,
+    oneshot::Sender<CoordinatorResult<NamespaceRegistration>>,
+);
+
+/// Registers the namespaces a session announces with the coordinator in batches.
+///
+/// Announces queue up while the previous batch is being registered, and are then registered
+/// together with [Coordinator::register_namespaces], so a session announcing hundreds of
+/// namespaces makes a handful of coordinator calls instead of one each.
+#[derive(Clone)]
+pub struct AnnounceBatcher {
+    queue: mpsc::UnboundedSender<Queued>,
+}
+
+impl AnnounceBatcher {
+    /// The most namespaces registered in a single call.
+    pub const MAX_BATCH: usize = 64;
+
+    /// Register batches with `coordinator` on a task of its own, until every clone is dropped.
+    pub fn spawn(coordinator: Arc<dyn Coordinator>) -> Self {
+        let (queue, queued) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run(coordinator, queued));
+
+        Self { queue }
+    }
+
+    /// Queue `namespace` for the next batch, waiting until it's registered.
+    pub async fn register(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<NamespaceRegistration> {
+        let (reply, registered) = oneshot::channel();
+        self.queue
+            .send((namespace.clone(), reply))
+            .map_err(|_| anyhow::anyhow!("announce batcher stopped"))?;
+
+        registered
+            .await
+            .map_err(|_| CoordinatorError::Other(anyhow::anyhow!("announce batcher stopped")))?
+    }
+
+    async fn run(coordinator: Arc<dyn Coordinator>, mut queued: mpsc::UnboundedReceiver<Queued>) {
+        while let Some(first) = queued.recv().await {
+            let mut batch = vec![first];
+            while batch.len() < Self::MAX_BATCH {
+                match queued.try_recv() {
+                    Ok(next) => batch.push(next),
+                    Err(_) => break,
+                }
+            }
+
+            let namespaces: Vec<_> = batch
+                .iter()
+                .map(|(namespace, _)| namespace.clone())
+                .collect();
+            if namespaces.len() > 1 {
+                log::debug!("registering {} namespaces in one batch", namespaces.len());
+            }
+
+            let results = coordinator.register_namespaces(&namespaces).await;
+            for ((_, reply), res) in batch.into_iter().zip(results) {
+                // Dropped, and so unregistered, if the announce is already gone.
+                reply.send(res).ok();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NamespaceOrigin;
+    use async_trait::async_trait;
+    use moq_native_ietf::quic;
+    use std::{sync::Mutex, time::Duration};
+
+    /// Records the size of each batch, taking a while to register each.
+    #[derive(Default)]
+    struct Batches(Mutex<Vec<usize>>);
+
+    #[async_trait]
+    impl Coordinator for Batches {
+        async fn register_namespace(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            unreachable!("registered in batches")
+        }
+
+        async fn register_namespaces(
+            &self,
+            namespaces: &[TrackNamespace],
+        ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+            self.0.lock().unwrap().push(namespaces.len());
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            namespaces
+                .iter()
+                .map(|_| Ok(NamespaceRegistration::new(())))
+                .collect()
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            Err(CoordinatorError::NamespaceNotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn registers_queued_announces_together() {
+        let coordinator = Arc::new(Batches::default());
+        let batcher = AnnounceBatcher::spawn(coordinator.clone());
+
+        // The first announce is registered alone, the rest queue up behind it.
+        let first = {
+            let batcher = batcher.clone();
+            tokio::spawn(
+                async move { batcher.register(&TrackNamespace::from_utf8_path("0")).await },
+            )
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        let rest: Vec<_> = (1..10)
+            .map(|i| {
+                let batcher = batcher.clone();
+                tokio::spawn(async move {
+                    let namespace = TrackNamespace::from_utf8_path(&i.to_string());
+                    batcher.register(&namespace).await
+                })
+            })
+            .collect();
+
+        assert!(first.await.unwrap().is_ok());
+        for registration in rest {
+            assert!(registration.await.unwrap().is_ok());
+        }
+
+        assert_eq!(*coordinator.0.lock().unwrap(), vec![1, 9]);
+    }
+}
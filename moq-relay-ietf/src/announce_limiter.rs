@@ -0,0 +1,246 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits on how many announces are registered with the coordinator concurrently.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnnounceLimits {
+    /// Maximum number of announces a single session may be registering at once.
+    pub per_session: Option<usize>,
+
+    /// Maximum number of announces being registered across all sessions.
+    /// Waiting announces are admitted in FIFO order, and each session queues at most
+    /// `per_session` of them, so a session announcing hundreds of namespaces can't starve others.
+    pub total: Option<usize>,
+
+    /// Maximum number of namespaces a single session may have announced at once.
+    /// Further announces are rejected until one of them ends.
+    pub max_namespaces: Option<usize>,
+}
+
+/// Relay-wide announce limiter, handing out a [SessionAnnounceLimiter] per session.
+#[derive(Clone)]
+pub struct AnnounceLimiter {
+    limits: AnnounceLimits,
+    total: Option<Arc<Semaphore>>,
+}
+
+impl AnnounceLimiter {
+    pub fn new(limits: AnnounceLimits) -> Self {
+        Self {
+            limits,
+            total: limits.total.map(|max| Arc::new(Semaphore::new(max))),
+        }
+    }
+
+    /// Create the limiter for a new session, sharing the relay-wide limit.
+    pub fn session(&self) -> SessionAnnounceLimiter {
+        SessionAnnounceLimiter {
+            session: self
+                .limits
+                .per_session
+                .map(|max| Arc::new(Semaphore::new(max))),
+            total: self.total.clone(),
+            namespaces: self
+                .limits
+                .max_namespaces
+                .map(|max| Arc::new(Semaphore::new(max))),
+            progress: Default::default(),
+        }
+    }
+}
+
+impl Default for AnnounceLimiter {
+    fn default() -> Self {
+        Self::new(AnnounceLimits::default())
+    }
+}
+
+#[derive(Default)]
+struct ProgressCounters {
+    pending: AtomicU64,
+    registering: AtomicU64,
+    registered: AtomicU64,
+    failed: AtomicU64,
+}
+
+/// A snapshot of announce processing for a session.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AnnounceProgress {
+    /// Announces waiting for a registration slot.
+    pub pending: u64,
+
+    /// Announces currently being registered with the coordinator.
+    pub registering: u64,
+
+    /// Announces registered successfully since the session started.
+    pub registered: u64,
+
+    /// Announces that failed to register since the session started.
+    pub failed: u64,
+}
+
+/// Per-session announce limiter and progress counters.
+#[derive(Clone)]
+pub struct SessionAnnounceLimiter {
+    session: Option<Arc<Semaphore>>,
+    total: Option<Arc<Semaphore>>,
+    namespaces: Option<Arc<Semaphore>>,
+    progress: Arc<ProgressCounters>,
+}
+
+impl SessionAnnounceLimiter {
+    /// Reserve one of the session's namespaces, held for as long as the announce is served.
+    /// Returns None once [AnnounceLimits::max_namespaces] are announced.
+    pub fn reserve(&self) -> Option<NamespaceSlot> {
+        let permit = match &self.namespaces {
+            Some(semaphore) => Some(semaphore.clone().try_acquire_owned().ok()?),
+            None => None,
+        };
+
+        Some(NamespaceSlot { _permit: permit })
+    }
+
+    /// Wait for a registration slot.
+    ///
+    /// The session slot is acquired before the relay-wide one, so a session only competes
+    /// for as many relay-wide slots as it is allowed to use.
+    pub async fn acquire(&self) -> AnnouncePermit {
+        self.progress.pending.fetch_add(1, Ordering::Relaxed);
+
+        // The semaphores are never closed, so acquiring can't fail.
+        let session = match &self.session {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+        let total = match &self.total {
+            Some(semaphore) => semaphore.clone().acquire_owned().await.ok(),
+            None => None,
+        };
+
+        self.progress.pending.fetch_sub(1, Ordering::Relaxed);
+        self.progress.registering.fetch_add(1, Ordering::Relaxed);
+
+        AnnouncePermit {
+            _session: session,
+            _total: total,
+            progress: self.progress.clone(),
+            registered: false,
+        }
+    }
+
+    pub fn progress(&self) -> AnnounceProgress {
+        AnnounceProgress {
+            pending: self.progress.pending.load(Ordering::Relaxed),
+            registering: self.progress.registering.load(Ordering::Relaxed),
+            registered: self.progress.registered.load(Ordering::Relaxed),
+            failed: self.progress.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// One of the session's announced namespaces, released on drop.
+pub struct NamespaceSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// A registration slot, released on drop.
+/// Dropping without calling [AnnouncePermit::registered] counts the announce as failed.
+pub struct AnnouncePermit {
+    _session: Option<OwnedSemaphorePermit>,
+    _total: Option<OwnedSemaphorePermit>,
+    progress: Arc<ProgressCounters>,
+    registered: bool,
+}
+
+impl AnnouncePermit {
+    /// Mark the announce as registered and release the slot.
+    pub fn registered(mut self) {
+        self.registered = true;
+    }
+}
+
+impl Drop for AnnouncePermit {
+    fn drop(&mut self) {
+        self.progress.registering.fetch_sub(1, Ordering::Relaxed);
+
+        let counter = match self.registered {
+            true => &self.progress.registered,
+            false => &self.progress.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn limits_and_progress() {
+        let limiter = AnnounceLimiter::new(AnnounceLimits {
+            per_session: Some(1),
+            total: Some(2),
+            max_namespaces: None,
+        });
+        let session = limiter.session();
+
+        let first = session.acquire().await;
+
+        // The second announce from the same session waits for the first to finish.
+        let waiting = {
+            let session = session.clone();
+            tokio::spawn(async move { session.acquire().await.registered() })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(
+            session.progress(),
+            AnnounceProgress {
+                pending: 1,
+                registering: 1,
+                registered: 0,
+                failed: 0,
+            }
+        );
+
+        // Another session still gets a relay-wide slot.
+        let other = limiter.session();
+        other.acquire().await.registered();
+        assert_eq!(other.progress().registered, 1);
+
+        drop(first);
+        waiting.await.unwrap();
+        assert_eq!(
+            session.progress(),
+            AnnounceProgress {
+                pending: 0,
+                registering: 0,
+                registered: 1,
+                failed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn max_namespaces() {
+        let limiter = AnnounceLimiter::new(AnnounceLimits {
+            max_namespaces: Some(1),
+            ..Default::default()
+        });
+        let session = limiter.session();
+
+        let slot = session.reserve().unwrap();
+        assert!(session.reserve().is_none());
+
+        // The limit is per session.
+        assert!(limiter.session().reserve().is_some());
+
+        drop(slot);
+        assert!(session.reserve().is_some());
+    }
+}
@@ -9,7 +9,10 @@ pub struct Api {
 
 impl Api {
     pub fn new(url: Url, node: Url) -> Self {
-        let origin = moq_api::Origin { url: node };
+        let origin = moq_api::Origin {
+            url: node,
+            metadata: Default::default(),
+        };
         let client = moq_api::Client::new(url);
 
         Self { client, origin }
@@ -0,0 +1,722 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    ops::RangeInclusive,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use bytes::{Bytes, BytesMut};
+use moq_transport::{
+    coding::{Decode, Encode, Location, TrackNamespace},
+    serve::{FullTrackName, TrackReader},
+    session::FetchedObject,
+};
+use serde::Serialize;
+
+use crate::cache::{
+    append_group, blocking, read_group, record, whole_groups, write_group, Recorder,
+};
+
+/// Where the [Archive] keeps groups, and for how long.
+#[derive(Clone, Debug)]
+pub struct ArchiveConfig {
+    /// Groups are written to this directory, and served again after a restart.
+    pub dir: PathBuf,
+
+    /// Only the tracks of namespaces starting with one of these prefixes are archived.
+    pub namespaces: Vec<TrackNamespace>,
+
+    /// Groups archived longer ago than this are deleted.
+    pub max_age: Duration,
+
+    /// Bytes kept on disk before the oldest groups are deleted.
+    pub max_bytes: u64,
+}
+
+/// A snapshot of the [Archive].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ArchiveStats {
+    /// Groups currently archived.
+    pub groups: usize,
+    /// Bytes currently archived.
+    pub bytes: u64,
+    /// Archived groups served by a fetch.
+    pub hits: u64,
+    /// Groups deleted once older than the retention, or over the disk budget.
+    pub expired: u64,
+}
+
+type GroupKey = (FullTrackName, u64);
+
+struct ArchivedGroup {
+    bytes: u64,
+    archived: SystemTime,
+    // The key of the group in ArchiveState::order.
+    seq: u64,
+}
+
+#[derive(Default)]
+struct ArchiveState {
+    tracks: HashMap<FullTrackName, BTreeMap<u64, ArchivedGroup>>,
+
+    // Groups still arriving, written out once a later group starts or the track ends.
+    pending: HashMap<FullTrackName, BTreeMap<u64, Vec<FetchedObject>>>,
+
+    // Complete groups, until their file is written.
+    writing: HashMap<FullTrackName, BTreeMap<u64, Arc<Vec<FetchedObject>>>>,
+
+    // Archived groups, oldest first.
+    order: BTreeMap<u64, GroupKey>,
+    next_seq: u64,
+
+    stats: ArchiveStats,
+}
+
+// File operations decided while holding the lock of the [Archive], and done after releasing it.
+#[derive(Default)]
+struct ArchiveOps {
+    writes: Vec<(GroupKey, Arc<Vec<FetchedObject>>)>,
+    // Stragglers of groups already written, added at the end of their file.
+    appends: Vec<(GroupKey, FetchedObject)>,
+    // Files to delete, and whether their track's directory is left empty.
+    removes: Vec<(PathBuf, bool)>,
+}
+
+/// Groups of selected namespaces persisted to disk, so late subscribers can FETCH the last
+/// minutes of a broadcast, like a DVR.
+///
+/// Each track is a directory of group files, in the format of the [crate::GroupCache]'s disk tier,
+/// so payloads, ex. CMAF chunks, are kept exactly as received. The index of archived groups is
+/// rebuilt from the directory on startup. Groups are deleted, oldest first, once older than
+/// the retention or over the disk budget.
+#[derive(Clone)]
+pub struct Archive {
+    state: Arc<Mutex<ArchiveState>>,
+    config: Arc<ArchiveConfig>,
+}
+
+impl Archive {
+    pub fn new(config: ArchiveConfig) -> anyhow::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+
+        // Pick up the groups archived by previous runs.
+        let mut groups = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let dir = entry?.path();
+            let Some(track) = dir
+                .file_name()
+                .and_then(|name| decode_track(name.to_str()?))
+            else {
+                log::warn!("ignoring unknown archive entry: {}", dir.display());
+                continue;
+            };
+
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let path = entry.path();
+                let group_id = path
+                    .extension()
+                    .filter(|ext| *ext == "group")
+                    .and_then(|_| path.file_stem()?.to_str()?.parse::<u64>().ok());
+
+                if let Some(group_id) = group_id {
+                    let metadata = entry.metadata()?;
+                    groups.push((
+                        metadata.modified()?,
+                        group_id,
+                        track.clone(),
+                        metadata.len(),
+                    ));
+                }
+            }
+        }
+        groups.sort_by_key(|(archived, group_id, ..)| (*archived, *group_id));
+
+        let archive = Self {
+            state: Default::default(),
+            config: Arc::new(config),
+        };
+
+        let mut ops = ArchiveOps::default();
+        let mut state = archive.state.lock().unwrap();
+        for (archived, group_id, track, bytes) in groups {
+            archive.index(&mut state, (track, group_id), bytes, archived);
+        }
+        archive.expire(&mut state, &mut ops);
+
+        log::info!(
+            "archiving groups in {}, {} restored",
+            archive.config.dir.display(),
+            state.order.len()
+        );
+        drop(state);
+        remove_files(ops.removes);
+
+        Ok(archive)
+    }
+
+    /// Whether the tracks of `namespace` are archived.
+    pub fn archives(&self, namespace: &TrackNamespace) -> bool {
+        self.config
+            .namespaces
+            .iter()
+            .any(|prefix| namespace.has_prefix(prefix))
+    }
+
+    pub fn stats(&self) -> ArchiveStats {
+        let state = self.state.lock().unwrap();
+        ArchiveStats {
+            groups: state.order.len(),
+            ..state.stats
+        }
+    }
+
+    /// The groups of `track` archived whole, like [crate::GroupCache::cached_groups]. The groups
+    /// still arriving aren't included.
+    pub async fn archived_groups(&self, track: &FullTrackName) -> Option<RangeInclusive<u64>> {
+        if !self.archives(&track.namespace) {
+            return None;
+        }
+
+        let mut ops = ArchiveOps::default();
+        let groups = {
+            let mut state = self.state.lock().unwrap();
+            self.expire(&mut state, &mut ops);
+            state
+                .tracks
+                .get(track)
+                .and_then(|groups| whole_groups(groups.keys().copied()))
+        };
+        self.flush(ops).await;
+
+        groups
+    }
+
+    /// The archived objects between `start` and `end`, in ascending order, like [crate::GroupCache::get].
+    /// Includes the groups still arriving.
+    pub async fn get(
+        &self,
+        track: &FullTrackName,
+        start: Location,
+        end: Location,
+    ) -> Vec<FetchedObject> {
+        if start.group_id > end.group_id || !self.archives(&track.namespace) {
+            return Vec::new();
+        }
+
+        let range = start.group_id..=end.group_id;
+        let mut groups = BTreeMap::new();
+
+        let mut ops = ArchiveOps::default();
+        let archived: Vec<u64> = {
+            let mut state = self.state.lock().unwrap();
+            self.expire(&mut state, &mut ops);
+
+            if let Some(writing) = state.writing.get(track) {
+                for (group_id, objects) in writing.range(range.clone()) {
+                    groups.insert(*group_id, objects.to_vec());
+                }
+            }
+            if let Some(pending) = state.pending.get(track) {
+                for (group_id, objects) in pending.range(range.clone()) {
+                    groups.insert(*group_id, objects.clone());
+                }
+            }
+            state
+                .tracks
+                .get(track)
+                .map(|groups| groups.range(range).map(|(id, _)| *id).collect())
+                .unwrap_or_default()
+        };
+
+        self.flush(ops).await;
+
+        // The files are read without holding the lock.
+        let mut hits = 0;
+        for group_id in archived {
+            if groups.contains_key(&group_id) {
+                continue;
+            }
+
+            let read = match self.path(track, group_id) {
+                Ok(path) => blocking(move || read_group(&path)).await,
+                Err(err) => Err(err),
+            };
+            match read {
+                Ok(objects) => {
+                    groups.insert(group_id, sort_objects(objects));
+                    hits += 1;
+                }
+                // Expired meanwhile.
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => log::warn!(
+                    "failed to read archived group {} of {}/{}: {}",
+                    group_id,
+                    track.namespace,
+                    track.name,
+                    err
+                ),
+            }
+        }
+        self.state.lock().unwrap().stats.hits += hits;
+
+        groups
+            .into_values()
+            .flatten()
+            .filter(|object| {
+                let location = Location::new(object.group_id, object.object_id);
+                location >= start && (end.object_id == 0 || location < end)
+            })
+            .collect()
+    }
+
+    /// Archive the objects of `track` as they are received, until it ends.
+    /// Tracks outside the archived namespaces are ignored.
+    pub async fn record(self, track: TrackReader) {
+        if !self.archives(&track.namespace) {
+            return;
+        }
+
+        let name = FullTrackName {
+            namespace: track.namespace.clone(),
+            name: track.name.clone(),
+        };
+        record(&self, track).await;
+
+        // The last groups are complete once the track ends.
+        let mut ops = ArchiveOps::default();
+        {
+            let mut state = self.state.lock().unwrap();
+            for (group_id, objects) in state.pending.remove(&name).unwrap_or_default() {
+                Self::archive(&mut state, (name.clone(), group_id), objects, &mut ops);
+            }
+        }
+        self.flush(ops).await;
+    }
+
+    // Tracks are stored in directories named after their encoded name, so they can be indexed again.
+    fn path(&self, track: &FullTrackName, group_id: u64) -> io::Result<PathBuf> {
+        let mut buf = BytesMut::new();
+        track
+            .namespace
+            .encode(&mut buf)
+            .and_then(|_| track.name.encode(&mut buf))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+        Ok(self
+            .config
+            .dir
+            .join(hex::encode(buf))
+            .join(format!("{}.group", group_id)))
+    }
+
+    // Write a complete group to disk once the lock is released. It's served from memory until then.
+    fn archive(
+        state: &mut ArchiveState,
+        key: GroupKey,
+        objects: Vec<FetchedObject>,
+        ops: &mut ArchiveOps,
+    ) {
+        let objects = Arc::new(objects);
+        state
+            .writing
+            .entry(key.0.clone())
+            .or_default()
+            .insert(key.1, objects.clone());
+        ops.writes.push((key, objects));
+    }
+
+    fn index(&self, state: &mut ArchiveState, key: GroupKey, bytes: u64, archived: SystemTime) {
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.order.insert(seq, key.clone());
+        state.stats.bytes += bytes;
+
+        let replaced = state.tracks.entry(key.0).or_default().insert(
+            key.1,
+            ArchivedGroup {
+                bytes,
+                archived,
+                seq,
+            },
+        );
+
+        // Archived again, ex. after it expired and a straggler arrived.
+        if let Some(replaced) = replaced {
+            state.order.remove(&replaced.seq);
+            state.stats.bytes -= replaced.bytes;
+        }
+    }
+
+    // Delete the oldest groups while they're past the retention or over budget.
+    fn expire(&self, state: &mut ArchiveState, ops: &mut ArchiveOps) {
+        let now = SystemTime::now();
+
+        while let Some((seq, key)) = state.order.first_key_value() {
+            let Some(group) = state
+                .tracks
+                .get(&key.0)
+                .and_then(|groups| groups.get(&key.1))
+            else {
+                let seq = *seq;
+                state.order.remove(&seq);
+                continue;
+            };
+
+            let old = now
+                .duration_since(group.archived)
+                .is_ok_and(|age| age >= self.config.max_age);
+            if !old && state.stats.bytes <= self.config.max_bytes {
+                break;
+            }
+
+            let key = key.clone();
+            self.remove(state, &key, ops);
+            state.stats.expired += 1;
+        }
+    }
+
+    fn remove(&self, state: &mut ArchiveState, key: &GroupKey, ops: &mut ArchiveOps) {
+        let Some(groups) = state.tracks.get_mut(&key.0) else {
+            return;
+        };
+        let Some(group) = groups.remove(&key.1) else {
+            return;
+        };
+        let emptied = groups.is_empty();
+        if emptied {
+            state.tracks.remove(&key.0);
+        }
+        state.order.remove(&group.seq);
+        state.stats.bytes -= group.bytes;
+
+        if let Ok(path) = self.path(&key.0, key.1) {
+            ops.removes.push((path, emptied));
+        }
+    }
+
+    // Do the file operations decided while holding the lock, on the blocking pool.
+    async fn flush(&self, mut ops: ArchiveOps) {
+        for (key, objects) in std::mem::take(&mut ops.writes) {
+            let res = match self.path(&key.0, key.1) {
+                Ok(path) => {
+                    blocking(move || {
+                        fs::create_dir_all(path.parent().unwrap())?;
+                        write_group(&path, &objects)?;
+                        Ok((fs::metadata(&path)?.len(), path))
+                    })
+                    .await
+                }
+                Err(err) => Err(err),
+            };
+
+            let mut state = self.state.lock().unwrap();
+            let writing = state.writing.get_mut(&key.0);
+            let written = writing.and_then(|writing| writing.remove(&key.1));
+            if state.writing.get(&key.0).is_some_and(BTreeMap::is_empty) {
+                state.writing.remove(&key.0);
+            }
+
+            match res {
+                // Dropped while it was being written, ex. as its publisher restarted.
+                Ok((_, path)) if written.is_none() => ops.removes.push((path, false)),
+                Ok((bytes, _)) => {
+                    self.index(&mut state, key, bytes, SystemTime::now());
+                    self.expire(&mut state, &mut ops);
+                }
+                Err(err) => log::warn!(
+                    "failed to archive group {} of {}/{}: {}",
+                    key.1,
+                    key.0.namespace,
+                    key.0.name,
+                    err
+                ),
+            }
+        }
+
+        for (key, object) in std::mem::take(&mut ops.appends) {
+            let res = match self.path(&key.0, key.1) {
+                Ok(path) => blocking(move || Ok((append_group(&path, &[object])?, path))).await,
+                Err(err) => Err(err),
+            };
+
+            let mut state = self.state.lock().unwrap();
+            let group = state
+                .tracks
+                .get_mut(&key.0)
+                .and_then(|groups| groups.get_mut(&key.1));
+
+            match (res, group) {
+                (Ok((bytes, _)), Some(group)) => {
+                    group.bytes += bytes;
+                    state.stats.bytes += bytes;
+                    self.expire(&mut state, &mut ops);
+                }
+                // Expired while the straggler was added.
+                (Ok((_, path)), None) => ops.removes.push((path, false)),
+                (Err(err), _) => log::warn!(
+                    "failed to add a straggler to archived group {} of {}/{}: {}",
+                    key.1,
+                    key.0.namespace,
+                    key.0.name,
+                    err
+                ),
+            }
+        }
+
+        if ops.removes.is_empty() {
+            return;
+        }
+        let removes = ops.removes;
+        let res = blocking(move || {
+            remove_files(removes);
+            Ok(())
+        })
+        .await;
+        if let Err(err) = res {
+            log::warn!("failed to remove archived groups: {}", err);
+        }
+    }
+}
+
+impl Recorder for Archive {
+    async fn insert(&self, track: &FullTrackName, object: FetchedObject) {
+        let mut ops = ArchiveOps::default();
+        {
+            let mut state = self.state.lock().unwrap();
+            let key = (track.clone(), object.group_id);
+
+            // A straggler for a group already written out is added to the end of its file, so it
+            // isn't read and written again whole.
+            let written = state
+                .tracks
+                .get(track)
+                .is_some_and(|groups| groups.contains_key(&key.1))
+                || state
+                    .writing
+                    .get(track)
+                    .is_some_and(|groups| groups.contains_key(&key.1));
+            if written {
+                ops.appends.push((key, object));
+            } else {
+                let pending = state.pending.entry(track.clone()).or_default();
+                insert_object(pending.entry(key.1).or_default(), object);
+
+                // Publishers move on to the next group once one is complete.
+                let later = pending.split_off(&key.1);
+                let complete = std::mem::replace(pending, later);
+                for (group_id, objects) in complete {
+                    Self::archive(&mut state, (track.clone(), group_id), objects, &mut ops);
+                }
+            }
+        }
+        self.flush(ops).await;
+    }
+
+    async fn begin(&self, track: &FullTrackName, group_id: u64) {
+        let mut ops = ArchiveOps::default();
+        {
+            let mut state = self.state.lock().unwrap();
+
+            let last = [
+                state
+                    .tracks
+                    .get(track)
+                    .and_then(|groups| groups.keys().last()),
+                state
+                    .writing
+                    .get(track)
+                    .and_then(|groups| groups.keys().last()),
+                state
+                    .pending
+                    .get(track)
+                    .and_then(|groups| groups.keys().last()),
+            ]
+            .into_iter()
+            .flatten()
+            .max()
+            .copied();
+            if last.is_none_or(|last| last <= group_id) {
+                return;
+            }
+
+            // Otherwise its groups would be merged into those of the previous instance.
+            log::info!(
+                "publisher of {}/{} restarted at group {}, dropping its archived groups",
+                track.namespace,
+                track.name,
+                group_id
+            );
+            state.pending.remove(track);
+            state.writing.remove(track);
+            let stale: Vec<u64> = state
+                .tracks
+                .get(track)
+                .map(|groups| groups.keys().copied().collect())
+                .unwrap_or_default();
+            for stale in stale {
+                self.remove(&mut state, &(track.clone(), stale), &mut ops);
+            }
+        }
+        self.flush(ops).await;
+    }
+}
+
+// Delete the files of removed groups, and the directories of tracks left without any.
+fn remove_files(removes: Vec<(PathBuf, bool)>) {
+    for (path, emptied) in removes {
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => log::warn!(
+                "failed to remove archived group {}: {}",
+                path.display(),
+                err
+            ),
+            _ => {}
+        }
+        if emptied {
+            let _ = fs::remove_dir(path.parent().unwrap());
+        }
+    }
+}
+
+fn insert_object(objects: &mut Vec<FetchedObject>, object: FetchedObject) {
+    match objects.binary_search_by_key(&object.object_id, |object| object.object_id) {
+        Ok(index) => objects[index] = object,
+        Err(index) => objects.insert(index, object),
+    }
+}
+
+// The objects of a group file by object ID, as stragglers are appended. A later copy of an object
+// replaces the earlier one.
+fn sort_objects(objects: Vec<FetchedObject>) -> Vec<FetchedObject> {
+    let objects: BTreeMap<u64, FetchedObject> = objects
+        .into_iter()
+        .map(|object| (object.object_id, object))
+        .collect();
+    objects.into_values().collect()
+}
+
+fn decode_track(name: &str) -> Option<FullTrackName> {
+    let mut buf = Bytes::from(hex::decode(name).ok()?);
+    let namespace = TrackNamespace::decode(&mut buf).ok()?;
+    let name = String::decode(&mut buf).ok()?;
+    Some(FullTrackName { namespace, name })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::fixtures::{object, payloads};
+
+    #[tokio::test]
+    async fn retention() {
+        let dir = std::env::temp_dir().join(format!("moq-relay-archive-{}", std::process::id()));
+        let config = ArchiveConfig {
+            dir: dir.clone(),
+            namespaces: vec![TrackNamespace::from_utf8_path("live")],
+            max_age: Duration::from_secs(3600),
+            max_bytes: u64::MAX,
+        };
+        let archive = Archive::new(config.clone()).unwrap();
+
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live/room"),
+            name: "video".to_string(),
+        };
+        let all = (Location::new(0, 0), Location::new(2, 0));
+
+        // Groups are written out once the next one starts.
+        archive.insert(&track, object(0, 0, "zero")).await;
+        archive.insert(&track, object(0, 1, "-0")).await;
+        archive.insert(&track, object(1, 0, "one")).await;
+        archive.insert(&track, object(2, 0, "two")).await;
+        assert_eq!(archive.stats().groups, 2);
+        assert_eq!(
+            payloads(archive.get(&track, all.0, all.1).await),
+            ["zero", "-0", "one", "two"]
+        );
+        assert_eq!(archive.stats().hits, 2);
+        assert_eq!(archive.archived_groups(&track).await, Some(1..=1));
+
+        // Other namespaces aren't archived.
+        let other = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("vod"),
+            name: "video".to_string(),
+        };
+        assert!(!archive.archives(&other.namespace));
+        assert!(archive.get(&other, all.0, all.1).await.is_empty());
+
+        // Archived groups survive a restart, until they're over budget.
+        let bytes = archive.stats().bytes;
+        drop(archive);
+        let archive = Archive::new(ArchiveConfig {
+            max_bytes: bytes - 1,
+            ..config.clone()
+        })
+        .unwrap();
+        let stats = archive.stats();
+        assert_eq!((stats.groups, stats.expired), (1, 1));
+        assert_eq!(payloads(archive.get(&track, all.0, all.1).await), ["one"]);
+
+        // Or older than the retention.
+        drop(archive);
+        let archive = Archive::new(ArchiveConfig {
+            max_age: Duration::ZERO,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(archive.stats().groups, 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stragglers_and_restarts() {
+        let dir =
+            std::env::temp_dir().join(format!("moq-relay-archive-restarts-{}", std::process::id()));
+        let archive = Archive::new(ArchiveConfig {
+            dir: dir.clone(),
+            namespaces: vec![TrackNamespace::from_utf8_path("live")],
+            max_age: Duration::from_secs(3600),
+            max_bytes: u64::MAX,
+        })
+        .unwrap();
+
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: "video".to_string(),
+        };
+        let all = (Location::new(0, 0), Location::new(1, 0));
+
+        archive.begin(&track, 0).await;
+        archive.insert(&track, object(0, 0, "zero")).await;
+        archive.insert(&track, object(1, 0, "one")).await;
+        let bytes = archive.stats().bytes;
+
+        // Stragglers are added to the group already written, in order.
+        archive.insert(&track, object(0, 2, "-2")).await;
+        archive.insert(&track, object(0, 1, "-1")).await;
+        assert!(archive.stats().bytes > bytes);
+        assert_eq!(
+            payloads(archive.get(&track, all.0, all.1).await),
+            ["zero", "-1", "-2", "one"]
+        );
+
+        // A publisher starting over from an earlier group replaces them.
+        archive.begin(&track, 0).await;
+        assert_eq!(archive.stats().groups, 0);
+        assert_eq!(archive.stats().bytes, 0);
+        assert!(archive.get(&track, all.0, all.1).await.is_empty());
+        archive.insert(&track, object(0, 0, "again")).await;
+        archive.insert(&track, object(1, 0, "one")).await;
+        assert_eq!(
+            payloads(archive.get(&track, all.0, all.1).await),
+            ["again", "one"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
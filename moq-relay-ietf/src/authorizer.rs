@@ -0,0 +1,246 @@
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use async_trait::async_trait;
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    session::SubscribedAuthorization,
+};
+
+/// Decides whether a session or request may proceed, based on its AUTHORIZATION TOKEN parameters.
+///
+/// Requests are authorized with their own tokens followed by the tokens the client presented in
+/// CLIENT_SETUP, so a client can authenticate once for the whole session or per request.
+/// Every method allows by default.
+#[async_trait]
+pub trait Authorizer: Send + Sync {
+    /// Called once the SETUP handshake completes. Rejected sessions are closed with UNAUTHORIZED.
+    async fn authorize_session(&self, _tokens: &[Token]) -> bool {
+        true
+    }
+
+    /// Called for each PUBLISH_NAMESPACE. Rejected announces receive PUBLISH_NAMESPACE_ERROR.
+    async fn authorize_announce(&self, _namespace: &TrackNamespace, _tokens: &[Token]) -> bool {
+        true
+    }
+
+    /// Called for each SUBSCRIBE. Rejected subscriptions receive SUBSCRIBE_ERROR.
+    async fn authorize_subscribe(
+        &self,
+        _namespace: &TrackNamespace,
+        _track_name: &str,
+        _tokens: &[Token],
+    ) -> bool {
+        true
+    }
+
+    /// When `tokens` stop being valid, if known, so long-lived requests are re-checked then
+    /// rather than waiting for the next [Reauthorize::interval].
+    async fn expires(&self, _tokens: &[Token]) -> Option<SystemTime> {
+        None
+    }
+}
+
+/// When to re-check the tokens of announces and subscriptions that outlive them.
+///
+/// Subscriptions that fail a re-check end with UNAUTHORIZED in PUBLISH_DONE, unless the subscriber
+/// renews its token with SUBSCRIBE_UPDATE within `grace`. Announces can't be renewed, so they are
+/// cancelled right away and have to be announced again with a fresh token.
+#[derive(Clone, Copy, Debug)]
+pub struct Reauthorize {
+    /// Re-check this often. If None, only when [Authorizer::expires] says the tokens expire.
+    pub interval: Option<Duration>,
+
+    /// How long a subscriber has to renew its token after a failed re-check.
+    pub grace: Duration,
+}
+
+impl Reauthorize {
+    // Tokens already past their expiry, yet still allowed, aren't re-checked more often than this.
+    const MIN_INTERVAL: Duration = Duration::from_secs(1);
+}
+
+/// Allows announces and subscriptions carrying one of a fixed set of token values.
+pub struct StaticTokenAuthorizer {
+    tokens: Vec<Vec<u8>>,
+}
+
+impl StaticTokenAuthorizer {
+    pub fn new(tokens: Vec<Vec<u8>>) -> Self {
+        Self { tokens }
+    }
+
+    fn allows(&self, tokens: &[Token]) -> bool {
+        tokens.iter().any(|token| {
+            self.tokens
+                .iter()
+                .any(|allowed| crate::web::constant_time_eq(&token.value, allowed))
+        })
+    }
+}
+
+#[async_trait]
+impl Authorizer for StaticTokenAuthorizer {
+    async fn authorize_announce(&self, _namespace: &TrackNamespace, tokens: &[Token]) -> bool {
+        self.allows(tokens)
+    }
+
+    async fn authorize_subscribe(
+        &self,
+        _namespace: &TrackNamespace,
+        _track_name: &str,
+        tokens: &[Token],
+    ) -> bool {
+        self.allows(tokens)
+    }
+}
+
+/// An [Authorizer] paired with the tokens a session presented in CLIENT_SETUP.
+#[derive(Clone)]
+pub struct SessionAuthorizer {
+    authorizer: Arc<dyn Authorizer>,
+    session_tokens: Arc<Vec<Token>>,
+    reauthorize: Option<Reauthorize>,
+}
+
+impl SessionAuthorizer {
+    pub fn new(authorizer: Arc<dyn Authorizer>, session_tokens: Vec<Token>) -> Self {
+        Self {
+            authorizer,
+            session_tokens: Arc::new(session_tokens),
+            reauthorize: None,
+        }
+    }
+
+    /// Re-check long-lived announces and subscriptions, see [Self::subscribe_expired].
+    pub fn with_reauthorize(mut self, reauthorize: Option<Reauthorize>) -> Self {
+        self.reauthorize = reauthorize;
+        self
+    }
+
+    pub async fn authorize_announce(&self, namespace: &TrackNamespace, tokens: &[Token]) -> bool {
+        self.authorizer
+            .authorize_announce(namespace, &self.tokens(tokens))
+            .await
+    }
+
+    pub async fn authorize_subscribe(
+        &self,
+        namespace: &TrackNamespace,
+        track_name: &str,
+        tokens: &[Token],
+    ) -> bool {
+        self.authorizer
+            .authorize_subscribe(namespace, track_name, &self.tokens(tokens))
+            .await
+    }
+
+    /// Resolves once a subscription is no longer authorized and wasn't renewed within the grace
+    /// period, for the caller to end it. Never resolves unless re-authorization is enabled.
+    pub async fn subscribe_expired(
+        &self,
+        namespace: &TrackNamespace,
+        track_name: &str,
+        authorization: &SubscribedAuthorization,
+    ) {
+        let Some(reauthorize) = self.reauthorize else {
+            return std::future::pending().await;
+        };
+
+        loop {
+            self.next_check(reauthorize, &authorization.tokens()).await;
+            if self
+                .authorize_subscribe(namespace, track_name, &authorization.tokens())
+                .await
+            {
+                continue;
+            }
+
+            log::info!(
+                "authorization lapsed for {}/{}, waiting {:?} for renewal",
+                namespace,
+                track_name,
+                reauthorize.grace
+            );
+            tokio::time::sleep(reauthorize.grace).await;
+            if !self
+                .authorize_subscribe(namespace, track_name, &authorization.tokens())
+                .await
+            {
+                return;
+            }
+        }
+    }
+
+    /// Resolves once an announce is no longer authorized by `tokens`, for the caller to cancel it.
+    /// Never resolves unless re-authorization is enabled.
+    pub async fn announce_expired(&self, namespace: &TrackNamespace, tokens: &[Token]) {
+        let Some(reauthorize) = self.reauthorize else {
+            return std::future::pending().await;
+        };
+
+        loop {
+            self.next_check(reauthorize, tokens).await;
+            if !self.authorize_announce(namespace, tokens).await {
+                return;
+            }
+        }
+    }
+
+    // Wait until `tokens` are due to be re-checked, forever if they never are.
+    async fn next_check(&self, reauthorize: Reauthorize, tokens: &[Token]) {
+        let expires = self
+            .authorizer
+            .expires(&self.tokens(tokens))
+            .await
+            .map(|expires| {
+                let remaining = expires
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                remaining.max(Reauthorize::MIN_INTERVAL)
+            });
+
+        let delay = match (reauthorize.interval, expires) {
+            (Some(interval), Some(expires)) => interval.min(expires),
+            (interval, expires) => match interval.or(expires) {
+                Some(delay) => delay,
+                None => return std::future::pending().await,
+            },
+        };
+
+        tokio::time::sleep(delay).await;
+    }
+
+    fn tokens(&self, request: &[Token]) -> Vec<Token> {
+        request
+            .iter()
+            .chain(self.session_tokens.iter())
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn static_tokens() {
+        let authorizer = Arc::new(StaticTokenAuthorizer::new(vec![b"secret".to_vec()]));
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let good = Token::new(0, b"secret".to_vec());
+        let bad = Token::new(0, b"wrong".to_vec());
+
+        // A token from CLIENT_SETUP covers every request on the session.
+        let session = SessionAuthorizer::new(authorizer.clone(), vec![good.clone()]);
+        assert!(session.authorize_subscribe(&namespace, "video", &[]).await);
+
+        // Otherwise the request has to carry its own.
+        let session = SessionAuthorizer::new(authorizer, vec![bad.clone()]);
+        assert!(!session.authorize_announce(&namespace, &[]).await);
+        assert!(!session.authorize_announce(&namespace, &[bad]).await);
+        assert!(session.authorize_announce(&namespace, &[good]).await);
+    }
+}
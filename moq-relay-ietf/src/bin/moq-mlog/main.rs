@@ -0,0 +1,37 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::Parser;
+
+/// Convert an mlog file to JSON-SEQ, for qlog tooling.
+///
+/// Reads JSON or CBOR mlog files, gzipped or not.
+#[derive(Parser)]
+struct Cli {
+    /// The mlog file to convert.
+    input: PathBuf,
+
+    /// Write to this file instead of stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    let output: Box<dyn Write> = match &cli.output {
+        Some(path) => Box::new(
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?,
+        ),
+        None => Box::new(io::stdout().lock()),
+    };
+    let mut output = BufWriter::new(output);
+
+    moq_transport::mlog::convert_to_json(&cli.input, &mut output)
+        .with_context(|| format!("failed to convert {}", cli.input.display()))?;
+    output.flush()?;
+
+    Ok(())
+}
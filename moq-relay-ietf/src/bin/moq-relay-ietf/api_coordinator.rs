@@ -8,6 +8,8 @@
 //! - Automatic TTL refresh to maintain registrations
 //! - High availability when using the moq-api server
 
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{Context, Result};
@@ -25,6 +27,12 @@ use moq_relay_ietf::{
 /// moq-api server uses 600 seconds (10 minutes) TTL
 const DEFAULT_REGISTRATION_TTL_SECS: u64 = 600;
 
+/// Looked up to check the API answers.
+const HEALTH_NAMESPACE: &str = ".health";
+
+/// Added to the metadata of a stale namespace, so other relays don't route to it.
+const STALE_METADATA: (&str, &str) = ("stale", "true");
+
 /// Configuration for the API coordinator
 #[derive(Debug, Clone)]
 pub struct ApiCoordinatorConfig {
@@ -36,6 +44,8 @@ pub struct ApiCoordinatorConfig {
     pub registration_ttl_secs: u64,
     /// Interval for refreshing registrations (should be less than TTL)
     pub refresh_interval_secs: u64,
+    /// Metadata registered with each namespace, returned to other relays by lookups
+    pub metadata: Vec<(String, String)>,
 }
 
 impl ApiCoordinatorConfig {
@@ -47,6 +57,7 @@ impl ApiCoordinatorConfig {
             registration_ttl_secs: DEFAULT_REGISTRATION_TTL_SECS,
             // Refresh at half the TTL to ensure we don't expire
             refresh_interval_secs: DEFAULT_REGISTRATION_TTL_SECS / 2,
+            metadata: Vec::new(),
         }
     }
 
@@ -56,12 +67,33 @@ impl ApiCoordinatorConfig {
         self.refresh_interval_secs = ttl_secs / 2;
         self
     }
+
+    /// Set the metadata registered with each namespace
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// The origin registered for each namespace, marked if it's stale
+    fn origin(&self, stale: bool) -> Origin {
+        let mut metadata: std::collections::BTreeMap<_, _> =
+            self.metadata.iter().cloned().collect();
+        if stale {
+            metadata.insert(STALE_METADATA.0.to_string(), STALE_METADATA.1.to_string());
+        }
+
+        Origin {
+            url: self.relay_url.clone(),
+            metadata,
+        }
+    }
 }
 
 /// Handle that unregisters a namespace when dropped and manages TTL refresh
 struct NamespaceUnregisterHandle {
     namespace: TrackNamespace,
     client: Client,
+    stale: Stale,
     /// Channel to signal the refresh task to stop (wrapped in Option so we can take it in drop)
     shutdown_tx: Option<tokio::sync::oneshot::Sender<()>>,
 }
@@ -73,6 +105,8 @@ impl Drop for NamespaceUnregisterHandle {
             let _ = tx.send(());
         }
 
+        self.stale.lock().unwrap().remove(&self.namespace);
+
         let namespace = self.namespace.clone();
         let client = self.client.clone();
 
@@ -111,8 +145,13 @@ pub struct ApiCoordinator {
     client: Client,
     /// Configuration
     config: ApiCoordinatorConfig,
+    /// The registered namespaces marked stale, read by the refresh tasks
+    stale: Stale,
 }
 
+/// The namespaces of this relay currently marked stale.
+type Stale = Arc<Mutex<HashSet<TrackNamespace>>>;
+
 impl ApiCoordinator {
     /// Create a new API-based coordinator.
     ///
@@ -124,14 +163,19 @@ impl ApiCoordinator {
     pub fn new(config: ApiCoordinatorConfig) -> Self {
         let client = Client::new(config.api_url.clone());
 
-        Self { client, config }
+        Self {
+            client,
+            config,
+            stale: Default::default(),
+        }
     }
 
     /// Start a background task to refresh namespace registration
     fn start_refresh_task(
         client: Client,
         namespace: TrackNamespace,
-        relay_url: Url,
+        config: ApiCoordinatorConfig,
+        stale: Stale,
         refresh_interval: Duration,
         mut shutdown_rx: tokio::sync::oneshot::Receiver<()>,
     ) {
@@ -143,7 +187,7 @@ impl ApiCoordinator {
                 tokio::select! {
                     _ = interval.tick() => {
                         let namespace_str = namespace.to_utf8_path();
-                        let origin = Origin { url: relay_url.clone() };
+                        let origin = config.origin(stale.lock().unwrap().contains(&namespace));
 
                         match client.patch_origin(&namespace_str, origin).await {
                             Ok(()) => {
@@ -171,9 +215,8 @@ impl Coordinator for ApiCoordinator {
         namespace: &TrackNamespace,
     ) -> CoordinatorResult<NamespaceRegistration> {
         let namespace_str = namespace.to_utf8_path();
-        let origin = Origin {
-            url: self.config.relay_url.clone(),
-        };
+        self.stale.lock().unwrap().remove(namespace);
+        let origin = self.config.origin(false);
 
         log::info!(
             "registering namespace in API: {} -> {}",
@@ -183,7 +226,7 @@ impl Coordinator for ApiCoordinator {
 
         // Register the namespace with the API
         self.client
-            .set_origin(&namespace_str, origin)
+            .set_origin(&namespace_str, origin.clone())
             .await
             .context("failed to register namespace in API")
             .map_err(CoordinatorError::Other)?;
@@ -195,7 +238,8 @@ impl Coordinator for ApiCoordinator {
         Self::start_refresh_task(
             self.client.clone(),
             namespace.clone(),
-            self.config.relay_url.clone(),
+            self.config.clone(),
+            self.stale.clone(),
             Duration::from_secs(self.config.refresh_interval_secs),
             shutdown_rx,
         );
@@ -203,10 +247,61 @@ impl Coordinator for ApiCoordinator {
         let handle = NamespaceUnregisterHandle {
             namespace: namespace.clone(),
             client: self.client.clone(),
+            stale: self.stale.clone(),
             shutdown_tx: Some(shutdown_tx),
         };
 
-        Ok(NamespaceRegistration::new(handle))
+        Ok(NamespaceRegistration::new(handle).with_metadata(self.config.metadata.clone()))
+    }
+
+    async fn refresh_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        let namespace_str = namespace.to_utf8_path();
+        let origin = self
+            .config
+            .origin(self.stale.lock().unwrap().contains(namespace));
+
+        log::info!(
+            "re-registering namespace in API: {} -> {}",
+            namespace_str,
+            self.config.relay_url
+        );
+
+        self.client
+            .set_origin(&namespace_str, origin)
+            .await
+            .context("failed to re-register namespace in API")
+            .map_err(CoordinatorError::Other)?;
+
+        Ok(())
+    }
+
+    async fn set_namespace_stale(
+        &self,
+        namespace: &TrackNamespace,
+        stale: bool,
+    ) -> CoordinatorResult<()> {
+        let namespace_str = namespace.to_utf8_path();
+        match stale {
+            true => self.stale.lock().unwrap().insert(namespace.clone()),
+            false => self.stale.lock().unwrap().remove(namespace),
+        };
+
+        log::info!(
+            "marking namespace in API: {}, stale: {}",
+            namespace_str,
+            stale
+        );
+
+        // The API only replaces an origin once it's deleted. It may be gone already, and if
+        // deleting it failed otherwise, so does setting it.
+        let _ = self.client.delete_origin(&namespace_str).await;
+        self.client
+            .set_origin(&namespace_str, self.config.origin(stale))
+            .await
+            .context("failed to mark namespace stale in API")
+            .map_err(CoordinatorError::Other)?;
+
+        Ok(())
     }
 
     async fn unregister_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
@@ -238,12 +333,18 @@ impl Coordinator for ApiCoordinator {
             .map_err(CoordinatorError::Other)?;
 
         match result {
+            // Served by a relay whose publisher went quiet, as if it was gone.
+            Some(origin) if is_stale(&origin) => {
+                log::debug!("namespace is stale: {}", namespace_str);
+                Err(CoordinatorError::NamespaceNotFound)
+            }
             Some(origin) => {
                 log::debug!("found namespace {} at {}", namespace_str, origin.url);
-                Ok((
+                let found = origin.metadata.into_iter().fold(
                     NamespaceOrigin::new(namespace.clone(), origin.url, None),
-                    None,
-                ))
+                    NamespaceOrigin::with_metadata,
+                );
+                Ok((found, None))
             }
             None => {
                 log::debug!("namespace not found: {}", namespace_str);
@@ -252,6 +353,17 @@ impl Coordinator for ApiCoordinator {
         }
     }
 
+    async fn health(&self) -> CoordinatorResult<()> {
+        // Any answer will do, the namespace doesn't have to exist.
+        self.client
+            .get_origin(HEALTH_NAMESPACE)
+            .await
+            .context("failed to reach API")
+            .map_err(CoordinatorError::Other)?;
+
+        Ok(())
+    }
+
     async fn shutdown(&self) -> CoordinatorResult<()> {
         log::info!("shutting down API coordinator");
         // The moq-api client uses reqwest which handles connection cleanup internally
@@ -259,6 +371,11 @@ impl Coordinator for ApiCoordinator {
     }
 }
 
+/// Whether the relay serving `origin` marked it stale.
+fn is_stale(origin: &Origin) -> bool {
+    origin.metadata.get(STALE_METADATA.0).map(String::as_str) == Some(STALE_METADATA.1)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -289,4 +406,21 @@ mod tests {
         assert_eq!(config.registration_ttl_secs, 120);
         assert_eq!(config.refresh_interval_secs, 60);
     }
+
+    #[test]
+    fn test_stale_origin() {
+        let api_url = Url::parse("http://localhost:8080").unwrap();
+        let relay_url = Url::parse("https://relay.example.com").unwrap();
+
+        let config = ApiCoordinatorConfig::new(api_url, relay_url)
+            .with_metadata(vec![("region".to_string(), "eu".to_string())]);
+
+        let live = config.origin(false);
+        assert!(!is_stale(&live));
+        assert_eq!(live.metadata.len(), 1);
+
+        let stale = config.origin(true);
+        assert!(is_stale(&stale));
+        assert_eq!(stale.metadata.get("region").unwrap(), "eu");
+    }
 }
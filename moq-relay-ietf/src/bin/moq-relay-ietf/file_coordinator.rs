@@ -4,7 +4,7 @@
 //! namespace registration across multiple relay instances. No separate
 //! server process is required.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
@@ -25,7 +25,7 @@ use moq_relay_ietf::{
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct CoordinatorData {
     /// Maps namespace path (e.g., "/foo/bar") to relay URL
-    namespaces: HashMap<String, String>,
+    namespaces: HashMap<String, NamespaceEntry>,
 }
 
 impl CoordinatorData {
@@ -34,6 +34,44 @@ impl CoordinatorData {
     }
 }
 
+/// The relay serving a namespace, as a bare URL unless it registered metadata,
+/// so relays that don't know about metadata can still read the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+enum NamespaceEntry {
+    Url(String),
+    Origin {
+        url: String,
+        metadata: BTreeMap<String, String>,
+    },
+}
+
+impl NamespaceEntry {
+    fn new(url: &str, metadata: &[(String, String)]) -> Self {
+        match metadata.is_empty() {
+            true => Self::Url(url.to_string()),
+            false => Self::Origin {
+                url: url.to_string(),
+                metadata: metadata.iter().cloned().collect(),
+            },
+        }
+    }
+
+    fn origin(&self, namespace: TrackNamespace) -> Result<NamespaceOrigin> {
+        let (url, metadata) = match self {
+            Self::Url(url) => (url, None),
+            Self::Origin { url, metadata } => (url, Some(metadata)),
+        };
+
+        let origin = NamespaceOrigin::new(namespace, Url::parse(url)?, None);
+        Ok(metadata
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .fold(origin, NamespaceOrigin::with_metadata))
+    }
+}
+
 /// Handle that unregisters a namespace when dropped
 struct NamespaceUnregisterHandle {
     namespace: TrackNamespace,
@@ -48,6 +86,37 @@ impl Drop for NamespaceUnregisterHandle {
     }
 }
 
+/// Synchronous helper for registering namespaces, all under a single lock and write
+fn register_namespace_sync(
+    file_path: &Path,
+    namespaces: &[TrackNamespace],
+    relay_url: &str,
+    metadata: &[(String, String)],
+) -> Result<()> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(file_path)?;
+
+    file.lock_exclusive()?;
+
+    let mut data = read_data(&file)?;
+    for namespace in namespaces {
+        let key = CoordinatorData::namespace_key(namespace);
+
+        log::info!("registering namespace: {} -> {}", key, relay_url);
+        data.namespaces
+            .insert(key, NamespaceEntry::new(relay_url, metadata));
+    }
+
+    write_data(&file, &data)?;
+    file.unlock()?;
+
+    Ok(())
+}
+
 /// Synchronous helper for unregistering namespace (used in Drop)
 fn unregister_namespace_sync(file_path: &Path, namespace: &TrackNamespace) -> Result<()> {
     let file = OpenOptions::new()
@@ -108,6 +177,8 @@ pub struct FileCoordinator {
     file_path: PathBuf,
     /// URL of this relay (used when registering namespaces)
     relay_url: Url,
+    /// Metadata registered with each namespace, returned to other relays by lookups
+    metadata: Vec<(String, String)>,
 }
 
 impl FileCoordinator {
@@ -120,8 +191,15 @@ impl FileCoordinator {
         Self {
             file_path: file_path.as_ref().to_path_buf(),
             relay_url,
+            metadata: Vec::new(),
         }
     }
+
+    /// Register each namespace with this metadata, ex. the relay's region or capacity.
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
 }
 
 #[async_trait]
@@ -133,38 +211,86 @@ impl Coordinator for FileCoordinator {
         let namespace = namespace.clone();
         let relay_url = self.relay_url.to_string();
         let file_path = self.file_path.clone();
+        let metadata = self.metadata.clone();
 
         // Run blocking file I/O in a separate thread
         let ns_clone = namespace.clone();
         tokio::task::spawn_blocking(move || {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(false)
-                .open(&file_path)?;
+            register_namespace_sync(&file_path, &[ns_clone], &relay_url, &metadata)
+        })
+        .await??;
+
+        let handle = NamespaceUnregisterHandle {
+            namespace,
+            file_path: self.file_path.clone(),
+        };
 
-            file.lock_exclusive()?;
+        Ok(NamespaceRegistration::new(handle).with_metadata(self.metadata.clone()))
+    }
 
-            let mut data = read_data(&file)?;
-            let key = CoordinatorData::namespace_key(&ns_clone);
+    // Registered with a single write of the file.
+    async fn register_namespaces(
+        &self,
+        namespaces: &[TrackNamespace],
+    ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+        let relay_url = self.relay_url.to_string();
+        let file_path = self.file_path.clone();
+        let metadata = self.metadata.clone();
 
-            log::info!("registering namespace: {} -> {}", key, relay_url);
-            data.namespaces.insert(key, relay_url);
+        let batch = namespaces.to_vec();
+        let res = tokio::task::spawn_blocking(move || {
+            register_namespace_sync(&file_path, &batch, &relay_url, &metadata)
+        })
+        .await;
+
+        let err = match res {
+            Ok(Ok(())) => {
+                return namespaces
+                    .iter()
+                    .map(|namespace| {
+                        let handle = NamespaceUnregisterHandle {
+                            namespace: namespace.clone(),
+                            file_path: self.file_path.clone(),
+                        };
+                        Ok(NamespaceRegistration::new(handle).with_metadata(self.metadata.clone()))
+                    })
+                    .collect()
+            }
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
 
-            write_data(&file, &data)?;
-            file.unlock()?;
+        // The whole batch failed, so each namespace fails with the same error.
+        namespaces
+            .iter()
+            .map(|_| Err(anyhow::anyhow!("{}", err).into()))
+            .collect()
+    }
 
-            Ok::<_, anyhow::Error>(())
+    async fn refresh_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        let namespace = namespace.clone();
+        let relay_url = self.relay_url.to_string();
+        let file_path = self.file_path.clone();
+        let metadata = self.metadata.clone();
+
+        tokio::task::spawn_blocking(move || {
+            register_namespace_sync(&file_path, &[namespace], &relay_url, &metadata)
         })
         .await??;
 
-        let handle = NamespaceUnregisterHandle {
-            namespace,
-            file_path: self.file_path.clone(),
-        };
+        Ok(())
+    }
 
-        Ok(NamespaceRegistration::new(handle))
+    // Other relays can't tell a stale entry apart, so it's removed until the namespace is live again.
+    async fn set_namespace_stale(
+        &self,
+        namespace: &TrackNamespace,
+        stale: bool,
+    ) -> CoordinatorResult<()> {
+        match stale {
+            true => self.unregister_namespace(namespace).await,
+            false => self.refresh_namespace(namespace).await,
+        }
     }
 
     // FIXME(itzmanish): Not being called currently but we need to call this on publish_namespace_done
@@ -203,15 +329,14 @@ impl Coordinator for FileCoordinator {
                 log::debug!("looking up namespace: {}", key);
 
                 // Try exact match first
-                if let Some(relay_url) = data.namespaces.get(&key) {
+                if let Some(entry) = data.namespaces.get(&key) {
                     file.unlock()?;
-                    let url = Url::parse(relay_url)?;
-                    return Ok(Some((NamespaceOrigin::new(namespace, url, None), None)));
+                    return Ok(Some((entry.origin(namespace)?, None)));
                 }
 
                 // Try prefix matching (find longest matching prefix)
-                let mut best_match: Option<(String, String)> = None;
-                for (registered_key, url) in &data.namespaces {
+                let mut best_match: Option<(String, NamespaceEntry)> = None;
+                for (registered_key, entry) in &data.namespaces {
                     // FIXME(itzmanish): it would be much better to compare on TupleField
                     // instead of working on strings
                     let is_prefix = registered_key
@@ -220,10 +345,10 @@ impl Coordinator for FileCoordinator {
                         .all(|(a, b)| a == b);
                     match best_match {
                         Some((ns, _)) if is_prefix && ns.len() < registered_key.len() => {
-                            best_match = Some((registered_key.clone(), url.clone()));
+                            best_match = Some((registered_key.clone(), entry.clone()));
                         }
                         None if is_prefix => {
-                            best_match = Some((registered_key.clone(), url.clone()));
+                            best_match = Some((registered_key.clone(), entry.clone()));
                         }
                         _ => {}
                     }
@@ -231,10 +356,9 @@ impl Coordinator for FileCoordinator {
 
                 file.unlock()?;
 
-                if let Some((matched_key, relay_url)) = best_match {
+                if let Some((matched_key, entry)) = best_match {
                     let matched_ns = TrackNamespace::from_utf8_path(&matched_key);
-                    let url = Url::parse(&relay_url)?;
-                    return Ok(Some((NamespaceOrigin::new(matched_ns, url, None), None)));
+                    return Ok(Some((entry.origin(matched_ns)?, None)));
                 }
 
                 Ok(None)
@@ -245,8 +369,67 @@ impl Coordinator for FileCoordinator {
         result.ok_or(CoordinatorError::NamespaceNotFound)
     }
 
+    async fn health(&self) -> CoordinatorResult<()> {
+        let file_path = self.file_path.clone();
+
+        tokio::task::spawn_blocking(move || -> Result<()> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&file_path)
+                .with_context(|| format!("failed to open {}", file_path.display()))?;
+
+            file.lock_shared()?;
+            let res = read_data(&file);
+            file.unlock()?;
+            res.map(|_| ())
+        })
+        .await??;
+
+        Ok(())
+    }
+
     async fn shutdown(&self) -> CoordinatorResult<()> {
         // Nothing to clean up - file will be unlocked automatically
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_round_trip() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let metadata = vec![
+            ("capacity".to_string(), "100".to_string()),
+            ("region".to_string(), "eu".to_string()),
+        ];
+
+        let mut data = CoordinatorData::default();
+        data.namespaces.insert(
+            "live".to_string(),
+            NamespaceEntry::new("https://a.example/", &metadata),
+        );
+        data.namespaces.insert(
+            "vod".to_string(),
+            NamespaceEntry::new("https://b.example/", &[]),
+        );
+        let json = serde_json::to_string(&data).unwrap();
+
+        // Entries without metadata stay bare URLs, as older relays wrote them.
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["namespaces"]["vod"], "https://b.example/");
+
+        let data: CoordinatorData = serde_json::from_str(&json).unwrap();
+        let origin = data.namespaces["live"].origin(namespace.clone()).unwrap();
+        assert_eq!(origin.url().as_str(), "https://a.example/");
+        assert_eq!(origin.metadata(), Some(metadata));
+
+        let origin = data.namespaces["vod"].origin(namespace).unwrap();
+        assert_eq!(origin.metadata(), None);
+    }
+}
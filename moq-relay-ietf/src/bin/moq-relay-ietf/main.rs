@@ -1,88 +1,71 @@
 mod api_coordinator;
 mod file_coordinator;
 
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 use std::{net, path::PathBuf};
 
-use clap::Parser;
+use anyhow::Context;
+use clap::{parser::ValueSource, CommandFactory, FromArgMatches, Parser};
+use tokio::task::JoinSet;
 use url::Url;
 
 use api_coordinator::{ApiCoordinator, ApiCoordinatorConfig};
 use file_coordinator::FileCoordinator;
-use moq_relay_ietf::{Coordinator, Relay, RelayConfig, Web, WebConfig};
+#[cfg(unix)]
+use moq_native_ietf::quic;
+use moq_native_ietf::tls;
+use moq_relay_ietf::{
+    AdminServer, Coordinator, GossipCoordinator, HandoverFileConfig, LogFilter, RegistryConfig,
+    RegistryServer, Relay, RelayFileConfig, ShardedCoordinator, Web, WebConfig,
+};
+#[cfg(unix)]
+use moq_relay_ietf::{HandoverTimeouts, Inherited};
 
 #[derive(Parser, Clone)]
 pub struct Cli {
-    /// Listen on this address
-    #[arg(long, default_value = "[::]:443")]
-    pub bind: net::SocketAddr,
-
-    /// The TLS configuration.
-    #[command(flatten)]
-    pub tls: moq_native_ietf::tls::Args,
-
-    /// Directory to write qlog files (one per connection)
-    #[arg(long)]
-    pub qlog_dir: Option<PathBuf>,
-
-    /// Directory to write mlog files (one per connection)
-    #[arg(long)]
-    pub mlog_dir: Option<PathBuf>,
-
-    /// Forward all announces to the provided server for authentication/routing.
-    /// If not provided, the relay accepts every unique announce.
+    /// Load the settings from this TOML file instead of the other flags, ex. `[admin]` with
+    /// `bind = "127.0.0.1:8080"` for --admin-bind. Fields are overridden by MOQ_RELAY_*
+    /// environment variables, ex. MOQ_RELAY_ADMIN__TOKEN for `token` under `[admin]`.
     #[arg(long)]
-    pub announce: Option<Url>,
+    pub config: Option<PathBuf>,
 
-    /// The URL of the moq-api server in order to run a cluster.
-    /// Must be used in conjunction with --node to advertise the origin
-    #[arg(long)]
-    pub api: Option<Url>,
-
-    /// The hostname that we advertise to other origins.
-    /// The provided certificate must be valid for this address.
-    #[arg(long)]
-    pub node: Option<Url>,
+    #[command(flatten)]
+    pub relay: RelayFileConfig,
+}
 
-    /// Enable development mode.
-    /// This hosts a HTTPS web server via TCP to serve the fingerprint of the certificate.
-    #[arg(long)]
-    pub dev: bool,
+impl Cli {
+    // Parse the flags, or load the settings from --config if given.
+    fn load() -> anyhow::Result<RelayFileConfig> {
+        let matches = Cli::command().get_matches();
+        let cli = Cli::from_arg_matches(&matches)?;
 
-    /// Serve qlog files over HTTPS at /qlog/:cid
-    /// Requires --dev to enable the web server. Only serves files by exact CID - no index.
-    #[arg(long)]
-    pub qlog_serve: bool,
+        let Some(path) = cli.config else {
+            cli.relay.validate()?;
+            return Ok(cli.relay);
+        };
 
-    /// Serve mlog files over HTTPS at /mlog/:cid
-    /// Requires --dev to enable the web server. Only serves files by exact CID - no index.
-    #[arg(long)]
-    pub mlog_serve: bool,
-
-    /// Path to the shared coordinator file for multi-relay coordination.
-    /// Multiple relay instances can share namespace/track registration via this file.
-    /// User doesn't have to explicitly create and populate anything. This path will be
-    /// used by file coordinator to store namespace/track registration information.
-    /// User need to make sure if multiple relay's are being used all of them have same path
-    /// to this file.
-    #[arg(long, default_value = "/tmp/moq-coordinator.json")]
-    pub coordinator_file: PathBuf,
-
-    /// URL of the moq-api server for coordination (e.g., "http://localhost:8080").
-    /// When specified, uses moq-api HTTP server instead of file-based coordination.
-    /// This is useful when running a cluster of relays with a centralized API server.
-    #[arg(long)]
-    pub api_url: Option<Url>,
+        // Half the settings from each would be hard to follow, so the file replaces the flags.
+        let command = Cli::command();
+        if let Some(flag) = command.get_arguments().find(|arg| {
+            arg.get_id() != "config"
+                && matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+        }) {
+            anyhow::bail!(
+                "--config can't be combined with other flags, got --{}",
+                flag.get_long().unwrap_or_default()
+            );
+        }
 
-    /// TTL in seconds for namespace registrations in the API.
-    /// Only used when --api-url is specified.
-    #[arg(long, default_value = "600")]
-    pub api_ttl: u64,
+        RelayFileConfig::load(&path)
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    // Like env_logger::init(), but the admin API can change the filter.
+    let log_filter = LogFilter::init()?;
 
     // Disable tracing so we don't get a bunch of Quinn spam.
     let tracer = tracing_subscriber::FmtSubscriber::builder()
@@ -90,73 +73,229 @@ async fn main() -> anyhow::Result<()> {
         .finish();
     tracing::subscriber::set_global_default(tracer).unwrap();
 
-    let cli = Cli::parse();
-    let tls = cli.tls.load()?;
+    let config = Cli::load()?;
+    let tls = tls::Config::load(&config.tls)?;
 
-    if tls.server.is_none() {
-        anyhow::bail!("missing TLS certificates");
-    }
+    // Started by a handover, so the previous relay holds our TCP ports until it exits.
+    #[cfg(unix)]
+    let inherited = Inherited::take()?;
+    #[cfg(unix)]
+    let retry = inherited.is_some();
+    #[cfg(not(unix))]
+    let retry = false;
 
-    // Determine qlog directory for both relay and web server
-    let qlog_dir_for_relay = cli.qlog_dir.clone();
-    let qlog_dir_for_web = if cli.qlog_serve {
-        cli.qlog_dir.clone()
-    } else {
-        None
-    };
-
-    // Determine mlog directory for both relay and web server
-    let mlog_dir_for_relay = cli.mlog_dir.clone();
-    let mlog_dir_for_web = if cli.mlog_serve {
-        cli.mlog_dir.clone()
-    } else {
-        None
-    };
+    // The servers besides the relay, failing the relay if one does.
+    let mut servers = JoinSet::new();
 
     // Build the relay URL from the node or bind address
-    let relay_url = cli
+    let relay_url = config
         .node
         .clone()
-        .unwrap_or_else(|| Url::parse(&format!("https://{}", cli.bind)).unwrap());
+        .unwrap_or_else(|| Url::parse(&format!("https://{}", config.bind[0])).unwrap());
+
+    // Serve the origin registry ourselves, if asked to
+    let registry_url = match config.coordinator.registry_bind {
+        Some(bind) => {
+            let registry = RegistryServer::new(RegistryConfig {
+                bind,
+                ttl: Duration::from_secs(config.coordinator.api_ttl),
+            });
+
+            spawn_server(&mut servers, "origin registry", retry, move || {
+                registry.clone().run()
+            });
+
+            // Reach our own registry over loopback if it listens on every address.
+            let ip = match bind.ip() {
+                ip if !ip.is_unspecified() => ip,
+                net::IpAddr::V4(_) => net::Ipv4Addr::LOCALHOST.into(),
+                net::IpAddr::V6(_) => net::Ipv6Addr::LOCALHOST.into(),
+            };
+            Some(Url::parse(&format!(
+                "http://{}/",
+                net::SocketAddr::new(ip, bind.port())
+            ))?)
+        }
+        None => None,
+    };
+
+    // Create the coordinator based on the configuration
+    // Priority: coordinator-shard > api-url > registry-bind > gossip-peer > file coordinator
+    let coordinator: Arc<dyn Coordinator> = if !config.coordinator.shards.is_empty() {
+        let shards = config
+            .coordinator
+            .shards
+            .iter()
+            .map(|api_url| {
+                let api_config = ApiCoordinatorConfig::new(api_url.clone(), relay_url.clone())
+                    .with_ttl(config.coordinator.api_ttl)
+                    .with_metadata(config.coordinator.metadata.clone());
+                let shard: Arc<dyn Coordinator> = Arc::new(ApiCoordinator::new(api_config));
+                (api_url.to_string(), shard)
+            })
+            .collect();
 
-    // Create the coordinator based on CLI arguments
-    // Priority: api-url > file coordinator
-    let coordinator: Arc<dyn Coordinator> = if let Some(api_url) = &cli.api_url {
-        let config = ApiCoordinatorConfig::new(api_url.clone(), relay_url).with_ttl(cli.api_ttl);
-        let api_coordinator = ApiCoordinator::new(config);
+        log::info!(
+            "sharding API coordinator across {} servers",
+            config.coordinator.shards.len()
+        );
+        Arc::new(ShardedCoordinator::new(shards)?.with_depth(config.coordinator.shard_depth))
+    } else if let Some(api_url) = config
+        .coordinator
+        .api_url
+        .as_ref()
+        .or(registry_url.as_ref())
+    {
+        let api_config = ApiCoordinatorConfig::new(api_url.clone(), relay_url)
+            .with_ttl(config.coordinator.api_ttl)
+            .with_metadata(config.coordinator.metadata.clone());
+        let api_coordinator = ApiCoordinator::new(api_config);
         log::info!("using API coordinator: {}", api_url);
         Arc::new(api_coordinator)
+    } else if !config.announce.gossip.is_empty() {
+        log::info!("learning namespaces by gossip, without a coordinator");
+        Arc::new(GossipCoordinator)
     } else {
-        log::info!("using file coordinator: {}", cli.coordinator_file.display());
-        Arc::new(FileCoordinator::new(&cli.coordinator_file, relay_url))
+        let file = &config.coordinator.file;
+        log::info!("using file coordinator: {}", file.display());
+        Arc::new(
+            FileCoordinator::new(file, relay_url)
+                .with_metadata(config.coordinator.metadata.clone()),
+        )
     };
 
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut relay_config = config.relay(tls.clone(), coordinator)?;
+
+    // Listen on the sockets of the relay we are replacing, if started by a handover.
+    #[cfg(unix)]
+    if let Some(inherited) = &inherited {
+        log::info!("taking over {} sockets", inherited.sockets.len());
+
+        relay_config.bind.clear();
+        relay_config.endpoints = inherited
+            .sockets
+            .iter()
+            .map(|socket| {
+                let endpoint = quic::Config::with_socket(
+                    socket.try_clone()?,
+                    config.logs.qlog_dir.clone(),
+                    tls.clone(),
+                );
+                quic::Endpoint::new(
+                    endpoint
+                        .with_zero_rtt(config.zero_rtt)
+                        .with_transport(config.transport.clone()),
+                )
+            })
+            .collect::<anyhow::Result<_>>()?;
+    }
+
     // Create a QUIC server for media.
-    let relay = Relay::new(RelayConfig {
-        tls: tls.clone(),
-        bind: Some(cli.bind),
-        endpoints: vec![],
-        qlog_dir: qlog_dir_for_relay,
-        mlog_dir: mlog_dir_for_relay,
-        node: cli.node,
-        announce: cli.announce,
-        coordinator,
-    })?;
-
-    if cli.dev {
-        // Create a web server too.
-        // Currently this only contains the certificate fingerprint (for development only).
-        let web = Web::new(WebConfig {
-            bind: cli.bind,
-            tls,
-            qlog_dir: qlog_dir_for_web,
-            mlog_dir: mlog_dir_for_web,
-        });
+    let relay = Relay::new(relay_config)?;
 
-        tokio::spawn(async move {
-            web.run().await.expect("failed to run web server");
+    if let Some(admin_config) = config.admin() {
+        let admin = AdminServer::new(admin_config, relay.admin().with_log_filter(log_filter));
+        spawn_server(&mut servers, "admin API", retry, move || {
+            admin.clone().run()
         });
     }
 
-    relay.run().await
+    // Create a web server too.
+    // This serves the certificate fingerprint (for development only), health checks and previews.
+    if let Some(web_config) = config.web(tls) {
+        let web_config = WebConfig {
+            log_index: relay.log_index(),
+            ..web_config
+        };
+        let web = Web::new(web_config).with_health(relay.health());
+        let web = match relay.previews() {
+            Some(previews) => web.with_previews(previews),
+            None => web,
+        };
+
+        servers.spawn(async move { web.run().await.context("failed to run web server") });
+    }
+
+    // Tell the previous relay we are accepting, so it can hand its sessions over.
+    #[cfg(unix)]
+    if let Some(inherited) = inherited {
+        inherited.ready()?;
+    }
+
+    let handover = hand_over_on_signal(&relay, &config.handover);
+    tokio::pin!(handover);
+
+    let run = relay.run();
+    tokio::pin!(run);
+
+    // Keep serving until a handover succeeds, then until the remaining sessions leave.
+    loop {
+        tokio::select! {
+            res = &mut run => return res,
+            res = &mut handover => return res,
+            Some(res) = servers.join_next() => res??,
+        }
+    }
+}
+
+// Run a server in the background, retrying while a previous relay still holds its port if `retry`.
+fn spawn_server<F, Fut>(
+    servers: &mut JoinSet<anyhow::Result<()>>,
+    name: &'static str,
+    retry: bool,
+    run: F,
+) where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = anyhow::Result<()>> + Send,
+{
+    servers.spawn(async move {
+        loop {
+            match run().await {
+                Err(err) if retry => {
+                    log::debug!("failed to run {}, retrying: {:#}", name, err);
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                }
+                res => return res.with_context(|| format!("failed to run {}", name)),
+            }
+        }
+    });
+}
+
+// Hand the relay over to a new process on each SIGUSR2, returning once one succeeds.
+#[cfg(unix)]
+fn hand_over_on_signal(
+    relay: &Relay,
+    config: &HandoverFileConfig,
+) -> impl Future<Output = anyhow::Result<()>> {
+    let admin = relay.admin();
+    let handovers = relay.handovers();
+    let timeouts = HandoverTimeouts {
+        ready: Duration::from_secs(config.ready_timeout),
+        drain: Duration::from_secs(config.drain_timeout),
+    };
+
+    async move {
+        let mut signal =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined2())?;
+
+        loop {
+            signal.recv().await;
+            log::info!("handing over to a new relay");
+
+            match moq_relay_ietf::hand_over(&admin, &handovers, timeouts).await {
+                Ok(()) => return Ok(()),
+                Err(err) => log::error!("failed to hand over, still serving: {:#}", err),
+            }
+        }
+    }
+}
+
+// Handovers pass sockets between processes, which needs Unix.
+#[cfg(not(unix))]
+fn hand_over_on_signal(
+    _relay: &Relay,
+    _config: &HandoverFileConfig,
+) -> impl Future<Output = anyhow::Result<()>> {
+    std::future::pending()
 }
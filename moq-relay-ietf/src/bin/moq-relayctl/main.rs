@@ -0,0 +1,189 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use clap::{Parser, Subcommand};
+use moq_relay_ietf::{migrate_registrations, read_file_registrations, MigrateOptions};
+use reqwest::{Method, StatusCode};
+use url::Url;
+
+/// Operate a running relay through its admin API, see --admin-bind.
+#[derive(Parser)]
+struct Cli {
+    /// The admin API of the relay.
+    #[arg(long, default_value = "http://127.0.0.1:8080")]
+    url: Url,
+
+    /// The bearer token required by the admin API, see --admin-token.
+    #[arg(long, env = "MOQ_RELAY_ADMIN__TOKEN", hide_env_values = true)]
+    token: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List active sessions and their QUIC statistics.
+    Sessions,
+
+    /// List a session's subscriptions and announces.
+    Activity { id: u64 },
+
+    /// Close a session.
+    Close { id: u64 },
+
+    /// Capture a session's mlog, which requires the relay to run with --capture.
+    Capture { id: u64 },
+
+    /// List announced namespaces and their subscriber counts.
+    Namespaces,
+
+    /// Cancel the announces of a namespace, ex. `live/room`.
+    Revoke { namespace: String },
+
+    /// Show the log filter, or replace it, ex. `info,moq_relay_ietf=debug`.
+    Log { filter: Option<String> },
+
+    /// Re-advertise every namespace with the coordinator.
+    Reregister,
+
+    /// Print any other admin endpoint, ex. `cache`, `quotas` or `sessions/closed`.
+    Get { path: String },
+
+    /// Copy the registrations of a file coordinator to a moq-api server, without the admin API.
+    Migrate {
+        /// The file coordinator's file, see --coordinator-file.
+        #[arg(long, default_value = "/tmp/moq-coordinator.json")]
+        from: PathBuf,
+
+        /// The moq-api server, or relay registry, see --api-url.
+        #[arg(long)]
+        to: Url,
+
+        /// Report what would change without writing anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Replace namespaces the server registers to another relay, instead of reporting them.
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let client = Client {
+        http: reqwest::Client::new(),
+        url: cli.url,
+        token: cli.token,
+    };
+
+    let output = match cli.command {
+        Command::Sessions => client.get("sessions").await?,
+        Command::Activity { id } => client.get(&format!("sessions/{}/activity", id)).await?,
+        Command::Close { id } => {
+            client
+                .send(Method::POST, &format!("sessions/{}/close", id), None)
+                .await?;
+            format!("closed session {}", id)
+        }
+        Command::Capture { id } => {
+            client
+                .send(Method::POST, &format!("sessions/{}/capture", id), None)
+                .await?;
+            format!("capturing session {}", id)
+        }
+        Command::Namespaces => client.get("namespaces").await?,
+        Command::Revoke { namespace } => {
+            let namespace = namespace.trim_matches('/');
+            client
+                .send(Method::DELETE, &format!("namespaces/{}", namespace), None)
+                .await?;
+            format!("revoked namespace {}", namespace)
+        }
+        Command::Log { filter: None } => client.get("log").await?,
+        Command::Log {
+            filter: Some(filter),
+        } => {
+            client
+                .send(Method::PUT, "log", Some(filter.clone()))
+                .await?;
+            format!("log filter set to {:?}", filter)
+        }
+        Command::Reregister => {
+            client
+                .send(Method::POST, "coordinator/reregister", None)
+                .await?
+        }
+        Command::Get { path } => client.get(path.trim_start_matches('/')).await?,
+        Command::Migrate {
+            from,
+            to,
+            dry_run,
+            overwrite,
+        } => {
+            let registrations = read_file_registrations(&from)?;
+            let options = MigrateOptions { dry_run, overwrite };
+            let report =
+                migrate_registrations(registrations, &moq_api::Client::new(to), options).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+
+            anyhow::ensure!(
+                report.unresolved() == 0,
+                "{} namespaces are registered to another relay, pass --overwrite to replace them",
+                report.unresolved()
+            );
+            return Ok(());
+        }
+    };
+
+    println!("{}", output);
+    Ok(())
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: Url,
+    token: Option<String>,
+}
+
+impl Client {
+    // GET an endpoint, pretty-printing JSON responses.
+    async fn get(&self, path: &str) -> anyhow::Result<String> {
+        let body = self.send(Method::GET, path, None).await?;
+        Ok(match serde_json::from_str::<serde_json::Value>(&body) {
+            Ok(json) => serde_json::to_string_pretty(&json)?,
+            Err(_) => body,
+        })
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<String>,
+    ) -> anyhow::Result<String> {
+        let url = self.url.join(path).context("invalid admin API path")?;
+        let mut request = self.http.request(method, url.clone());
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.body(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("failed to reach admin API at {}", url))?;
+        let status = response.status();
+        let body = response.text().await?;
+
+        match status {
+            status if status.is_success() => Ok(body),
+            StatusCode::UNAUTHORIZED => anyhow::bail!("unauthorized, pass --token"),
+            status => anyhow::bail!("{}: {}", status, body.trim()),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use moq_transport::{coding::TrackNamespace, serve::TrackReader};
+
+use crate::{Locals, SubscriberGuard};
+
+/// The tracks published to another relay in this process, see [crate::Relay::bridge].
+///
+/// A relay given a bridge, ex. an edge tier in front of an ingest tier, serves subscriptions to
+/// them as if they were published to it, handing them over in memory instead of subscribing
+/// through the network. The edge's policies apply to its subscribers, and the ingest's to its
+/// publishers. Bridged namespaces are subscribable, but not announced to the edge's subscribers.
+#[derive(Clone)]
+pub struct Bridge {
+    /// The local tracks of the bridged relay, then of the relays it's bridged to in turn.
+    tiers: Vec<Locals>,
+}
+
+impl Bridge {
+    pub(crate) fn new(locals: Locals, upstream: Option<Bridge>) -> Self {
+        let mut tiers = vec![locals];
+        tiers.extend(upstream.into_iter().flat_map(|bridge| bridge.tiers));
+        Self { tiers }
+    }
+
+    /// Subscribe to `track_name` in `namespace` from the nearest tier publishing it, counted as
+    /// a subscriber there until the guard is dropped.
+    pub async fn subscribe(
+        &self,
+        namespace: &TrackNamespace,
+        track_name: &str,
+        trace_id: Option<String>,
+    ) -> Option<(TrackReader, SubscriberGuard)> {
+        for locals in &self.tiers {
+            if let Some(track) = locals
+                .subscribe(namespace, track_name, trace_id.clone())
+                .await
+            {
+                return Some(track);
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,1008 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        Arc, Mutex,
+    },
+};
+
+use bytes::{Bytes, BytesMut};
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_transport::{
+    coding::{Decode, Encode, Location},
+    data::{FetchObject, ObjectStatus},
+    serve::{
+        Backpressure, DatagramsReader, FullTrackName, ServeError, SubgroupReader, SubgroupsReader,
+        TrackReader, TrackReaderMode,
+    },
+    session::FetchedObject,
+};
+use serde::Serialize;
+
+/// How many subgroups the recorder may fall behind by before it skips some.
+const RECORD_SUBGROUPS: usize = 64;
+
+/// Budgets for the [GroupCache].
+#[derive(Clone, Debug, Default)]
+pub struct CacheConfig {
+    /// Bytes of payload kept in memory. Zero disables the cache.
+    pub memory_budget: u64,
+
+    /// Spill groups evicted from memory into this directory, rather than dropping them.
+    pub disk_dir: Option<PathBuf>,
+
+    /// Bytes of payload kept on disk before the least recently used groups are deleted.
+    pub disk_budget: u64,
+}
+
+impl CacheConfig {
+    pub fn enabled(&self) -> bool {
+        self.memory_budget > 0
+    }
+}
+
+/// A snapshot of one tier of the [GroupCache].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CacheTierStats {
+    /// Groups currently held by this tier.
+    pub groups: usize,
+    /// Bytes of payload currently held by this tier.
+    pub bytes: u64,
+    /// Groups served from this tier.
+    pub hits: u64,
+    /// The share of lookups served from this tier.
+    pub hit_rate: f64,
+    /// Groups moved out of this tier: spilled to disk for memory, deleted for disk.
+    pub evictions: u64,
+}
+
+/// A snapshot of the [GroupCache].
+///
+/// Every cached group served by a fetch counts as a hit on its tier,
+/// and a fetch that found nothing cached counts as a single miss.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub memory: CacheTierStats,
+    pub disk: CacheTierStats,
+    pub lookups: u64,
+    pub misses: u64,
+}
+
+type GroupKey = (FullTrackName, u64);
+
+enum Tier {
+    Memory(Vec<FetchedObject>),
+    Disk(PathBuf),
+}
+
+struct CachedGroup {
+    tier: Tier,
+    bytes: u64,
+    used: u64,
+    // The largest object ID, known without reading the group back from disk.
+    largest: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    tracks: HashMap<FullTrackName, BTreeMap<u64, CachedGroup>>,
+
+    // Groups in each tier by when they were last used, oldest first.
+    memory: BTreeMap<u64, GroupKey>,
+    disk: BTreeMap<u64, GroupKey>,
+    tick: u64,
+    next_file: u64,
+
+    // The objects of groups spilled to disk, until their file is written.
+    spilling: HashMap<PathBuf, Arc<Vec<FetchedObject>>>,
+
+    // The group the recording of each track being recorded began at.
+    recording: HashMap<FullTrackName, u64>,
+
+    stats: CacheStats,
+}
+
+/// Recent groups of the tracks passing through the relay, so they can be served by FETCH.
+///
+/// Groups are kept in memory up to a budget. Beyond it, the least recently used groups spill to
+/// files in a local directory, tracked by an in-memory index, and are deleted once the disk budget
+/// is exceeded too. Reading a group from disk moves it back into memory. The index isn't persisted,
+/// so the directory is emptied on startup.
+#[derive(Clone)]
+pub struct GroupCache {
+    state: Arc<Mutex<CacheState>>,
+    config: Arc<CacheConfig>,
+}
+
+impl GroupCache {
+    pub fn new(config: CacheConfig) -> anyhow::Result<Self> {
+        if let Some(dir) = &config.disk_dir {
+            fs::create_dir_all(dir)?;
+
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "group") {
+                    fs::remove_file(path)?;
+                }
+            }
+            log::info!("caching groups on disk: {}", dir.display());
+        }
+
+        Ok(Self {
+            state: Default::default(),
+            config: Arc::new(config),
+        })
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let state = self.state.lock().unwrap();
+        let mut stats = state.stats;
+        stats.memory.groups = state.memory.len();
+        stats.disk.groups = state.disk.len();
+        stats.lookups = stats.memory.hits + stats.disk.hits + stats.misses;
+
+        if stats.lookups > 0 {
+            stats.memory.hit_rate = stats.memory.hits as f64 / stats.lookups as f64;
+            stats.disk.hit_rate = stats.disk.hits as f64 / stats.lookups as f64;
+        }
+
+        stats
+    }
+
+    /// Add a completely received object to its group.
+    pub async fn insert(&self, track: &FullTrackName, object: FetchedObject) {
+        let key = (track.clone(), object.group_id);
+
+        loop {
+            let mut ops = DiskOps::default();
+            let spilled = {
+                let mut state = self.state.lock().unwrap();
+
+                // A group on disk is read back into memory first.
+                match self.promote_spilling(&mut state, &key, &mut ops) {
+                    Some(path) => Some(path),
+                    None => {
+                        let mut objects = match self.remove(&mut state, &key, &mut ops) {
+                            Some(CachedGroup {
+                                tier: Tier::Memory(objects),
+                                ..
+                            }) => objects,
+                            _ => Vec::new(),
+                        };
+                        match objects
+                            .binary_search_by_key(&object.object_id, |object| object.object_id)
+                        {
+                            Ok(index) => objects[index] = object.clone(),
+                            Err(index) => objects.insert(index, object.clone()),
+                        }
+                        self.put(&mut state, key.clone(), objects, &mut ops);
+                        None
+                    }
+                }
+            };
+
+            self.flush(ops).await;
+            match spilled {
+                Some(path) => self.read_back(&key, &path).await,
+                None => return,
+            };
+        }
+    }
+
+    /// The cached objects between `start` and `end`, in ascending order.
+    ///
+    /// As in FETCH, `end` is exclusive, and an object ID of zero includes all of its group.
+    pub async fn get(
+        &self,
+        track: &FullTrackName,
+        start: Location,
+        end: Location,
+    ) -> Vec<FetchedObject> {
+        let groups: Vec<u64> = match self.state.lock().unwrap().tracks.get(track) {
+            Some(groups) if start.group_id <= end.group_id => groups
+                .range(start.group_id..=end.group_id)
+                .map(|(group_id, _)| *group_id)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut found = Vec::new();
+        let mut hits = 0;
+        for group_id in groups {
+            // Evicted since, ex. by reading an earlier group back from disk.
+            let Some((objects, on_disk)) = self.read(&(track.clone(), group_id)).await else {
+                continue;
+            };
+
+            found.extend(objects.into_iter().filter(|object| {
+                let location = Location::new(object.group_id, object.object_id);
+                location >= start && (end.object_id == 0 || location < end)
+            }));
+
+            let mut state = self.state.lock().unwrap();
+            match on_disk {
+                true => state.stats.disk.hits += 1,
+                false => state.stats.memory.hits += 1,
+            }
+            hits += 1;
+        }
+
+        if hits == 0 {
+            self.state.lock().unwrap().stats.misses += 1;
+        }
+
+        found
+    }
+
+    /// The largest location of `track` cached by its current recording, ex. to report the live
+    /// edge in SUBSCRIBE_OK. Groups cached before, ex. of an earlier upstream subscription, may
+    /// be larger than the publisher's live edge now. Not counted as a lookup.
+    pub fn largest(&self, track: &FullTrackName) -> Option<Location> {
+        let state = self.state.lock().unwrap();
+        let began = *state.recording.get(track)?;
+        let (group_id, group) = state.tracks.get(track)?.last_key_value()?;
+        (*group_id >= began).then(|| Location::new(*group_id, group.largest))
+    }
+
+    /// The groups of `track` cached whole, advertised when subscribing upstream so they aren't sent
+    /// again. The largest group may still be arriving, so it isn't included. Not counted as a lookup.
+    pub fn cached_groups(&self, track: &FullTrackName) -> Option<RangeInclusive<u64>> {
+        let state = self.state.lock().unwrap();
+        let mut groups = state.tracks.get(track)?.keys().copied();
+        groups.next_back();
+        whole_groups(groups)
+    }
+
+    /// Cache the objects of `track` as they are received, until it ends.
+    pub async fn record(self, track: TrackReader) {
+        record(&self, track).await
+    }
+
+    // The objects of a cached group, and whether they came from disk. The group is moved to memory
+    // as the most recently used.
+    async fn read(&self, key: &GroupKey) -> Option<(Vec<FetchedObject>, bool)> {
+        let mut ops = DiskOps::default();
+        let (on_disk, path, objects) = {
+            let mut state = self.state.lock().unwrap();
+
+            let on_disk = matches!(state.group(key)?.tier, Tier::Disk(_));
+            let path = self.promote_spilling(&mut state, key, &mut ops);
+            let objects = match (&path, state.group(key)) {
+                (
+                    None,
+                    Some(CachedGroup {
+                        tier: Tier::Memory(objects),
+                        ..
+                    }),
+                ) => Some(objects.clone()),
+                _ => None,
+            };
+            if path.is_none() {
+                Self::touch(&mut state, key);
+            }
+            (on_disk, path, objects)
+        };
+
+        self.flush(ops).await;
+
+        match path {
+            Some(path) => Some((self.read_back(key, &path).await?, true)),
+            None => Some((objects?, on_disk)),
+        }
+    }
+
+    // Move a group on disk back into memory if its file is still being written, as the objects are
+    // at hand. Otherwise returns the file to read it from, which is done without holding the lock.
+    fn promote_spilling(
+        &self,
+        state: &mut CacheState,
+        key: &GroupKey,
+        ops: &mut DiskOps,
+    ) -> Option<PathBuf> {
+        let Tier::Disk(path) = &state.group(key)?.tier else {
+            return None;
+        };
+
+        match state.spilling.get(path) {
+            Some(objects) => {
+                let objects = objects.to_vec();
+                self.remove(state, key, ops);
+                self.put(state, key.clone(), objects, ops);
+                None
+            }
+            None => Some(path.clone()),
+        }
+    }
+
+    // Read a group spilled to `path`, without holding the lock, and move it back into memory unless
+    // it was evicted or moved meanwhile. A group that can't be read is dropped.
+    async fn read_back(&self, key: &GroupKey, path: &Path) -> Option<Vec<FetchedObject>> {
+        let read = path.to_path_buf();
+        let objects = blocking(move || read_group(&read))
+            .await
+            .map_err(|err| log::warn!("failed to read cached group {}: {}", path.display(), err))
+            .ok();
+
+        let mut ops = DiskOps::default();
+        {
+            let mut state = self.state.lock().unwrap();
+
+            let current = state.group(key).map(|group| &group.tier);
+            if matches!(current, Some(Tier::Disk(current)) if current == path) {
+                self.remove(&mut state, key, &mut ops);
+                if let Some(objects) = &objects {
+                    self.put(&mut state, key.clone(), objects.clone(), &mut ops);
+                }
+            }
+        }
+
+        self.flush(ops).await;
+        objects
+    }
+
+    // Mark a group in memory as the most recently used.
+    fn touch(state: &mut CacheState, key: &GroupKey) {
+        let Some(group) = state
+            .tracks
+            .get_mut(&key.0)
+            .and_then(|groups| groups.get_mut(&key.1))
+        else {
+            return;
+        };
+
+        if let Tier::Memory(_) = group.tier {
+            state.memory.remove(&group.used);
+            state.tick += 1;
+            group.used = state.tick;
+            state.memory.insert(group.used, key.clone());
+        }
+    }
+
+    // Remove a group from its tier, deleting its file if it's on disk.
+    fn remove(
+        &self,
+        state: &mut CacheState,
+        key: &GroupKey,
+        ops: &mut DiskOps,
+    ) -> Option<CachedGroup> {
+        let groups = state.tracks.get_mut(&key.0)?;
+        let group = groups.remove(&key.1)?;
+        if groups.is_empty() {
+            state.tracks.remove(&key.0);
+        }
+
+        match &group.tier {
+            Tier::Memory(_) => {
+                state.memory.remove(&group.used);
+                state.stats.memory.bytes -= group.bytes;
+            }
+            Tier::Disk(path) => {
+                state.disk.remove(&group.used);
+                state.stats.disk.bytes -= group.bytes;
+
+                // A file still being written is deleted once it is.
+                if state.spilling.remove(path).is_none() {
+                    ops.removes.push(path.clone());
+                }
+            }
+        }
+
+        Some(group)
+    }
+
+    // Put a group in memory as the most recently used, spilling others to stay within budget.
+    fn put(
+        &self,
+        state: &mut CacheState,
+        key: GroupKey,
+        objects: Vec<FetchedObject>,
+        ops: &mut DiskOps,
+    ) {
+        let bytes = objects
+            .iter()
+            .map(|object| object.payload.len() as u64)
+            .sum();
+        let largest = objects.last().map_or(0, |object| object.object_id);
+
+        state.tick += 1;
+        let used = state.tick;
+        state.stats.memory.bytes += bytes;
+        state.memory.insert(used, key.clone());
+        state.tracks.entry(key.0).or_default().insert(
+            key.1,
+            CachedGroup {
+                tier: Tier::Memory(objects),
+                bytes,
+                used,
+                largest,
+            },
+        );
+
+        // The group we just used stays, even if it's larger than the budget on its own.
+        while state.stats.memory.bytes > self.config.memory_budget && state.memory.len() > 1 {
+            let Some((_, key)) = state.memory.pop_first() else {
+                break;
+            };
+            self.spill(state, key, ops);
+        }
+
+        while state.stats.disk.bytes > self.config.disk_budget {
+            let Some((_, key)) = state.disk.first_key_value() else {
+                break;
+            };
+
+            let key = key.clone();
+            self.remove(state, &key, ops);
+            state.stats.disk.evictions += 1;
+        }
+    }
+
+    // Move a group from memory to disk, or drop it if there's no disk tier. Its file is written
+    // once the lock is released.
+    fn spill(&self, state: &mut CacheState, key: GroupKey, ops: &mut DiskOps) {
+        let Some(groups) = state.tracks.get_mut(&key.0) else {
+            return;
+        };
+        let Some(mut group) = groups.remove(&key.1) else {
+            return;
+        };
+        state.stats.memory.bytes -= group.bytes;
+        state.stats.memory.evictions += 1;
+
+        let Tier::Memory(objects) = std::mem::replace(&mut group.tier, Tier::Memory(Vec::new()))
+        else {
+            unreachable!("spilled a group that isn't in memory");
+        };
+
+        let Some(dir) = &self.config.disk_dir else {
+            if groups.is_empty() {
+                state.tracks.remove(&key.0);
+            }
+            return;
+        };
+
+        state.next_file += 1;
+        let path = dir.join(format!("{}.group", state.next_file));
+        let objects = Arc::new(objects);
+        state.spilling.insert(path.clone(), objects.clone());
+        ops.writes.push((key.clone(), path.clone(), objects));
+
+        group.tier = Tier::Disk(path);
+        state.stats.disk.bytes += group.bytes;
+        state.disk.insert(group.used, key.clone());
+        groups.insert(key.1, group);
+    }
+
+    // Do the file operations decided while holding the lock, on the blocking pool.
+    async fn flush(&self, ops: DiskOps) {
+        let mut removes = ops.removes;
+
+        for (key, path, objects) in ops.writes {
+            let write = path.clone();
+            let res = blocking(move || write_group(&write, &objects)).await;
+
+            let mut state = self.state.lock().unwrap();
+            let mut ops = DiskOps::default();
+
+            // Read back or evicted while it was being written.
+            if state.spilling.remove(&path).is_none() {
+                removes.push(path);
+                continue;
+            }
+
+            if let Err(err) = res {
+                log::warn!("failed to spill group to {}: {}", path.display(), err);
+                self.remove(&mut state, &key, &mut ops);
+                removes.append(&mut ops.removes);
+            }
+        }
+
+        if removes.is_empty() {
+            return;
+        }
+        let res = blocking(move || {
+            for path in removes {
+                if let Err(err) = fs::remove_file(&path) {
+                    log::warn!("failed to remove cached group {}: {}", path.display(), err);
+                }
+            }
+            Ok(())
+        })
+        .await;
+        if let Err(err) = res {
+            log::warn!("failed to remove cached groups: {}", err);
+        }
+    }
+}
+
+impl CacheState {
+    fn group(&self, key: &GroupKey) -> Option<&CachedGroup> {
+        self.tracks.get(&key.0)?.get(&key.1)
+    }
+}
+
+// File operations decided while holding the lock of the [GroupCache], and done after releasing it.
+#[derive(Default)]
+struct DiskOps {
+    writes: Vec<(GroupKey, PathBuf, Arc<Vec<FetchedObject>>)>,
+    removes: Vec<PathBuf>,
+}
+
+/// Stores the objects recorded from a track, see [record].
+pub(crate) trait Recorder {
+    async fn insert(&self, track: &FullTrackName, object: FetchedObject);
+
+    /// A recording of `track` starts with an object of `group_id`, ex. after its publisher
+    /// reconnected. Groups recorded earlier with a later ID come from a previous instance of the
+    /// publisher, which started over from an earlier group.
+    async fn begin(&self, _track: &FullTrackName, _group_id: u64) {}
+
+    /// A recording of `track` that began ended, ex. as its upstream subscription did.
+    fn end(&self, _track: &FullTrackName) {}
+
+    /// Whether to record live-only tracks too, which can't be fetched.
+    fn live_only(&self) -> bool {
+        false
+    }
+}
+
+impl Recorder for GroupCache {
+    async fn insert(&self, track: &FullTrackName, object: FetchedObject) {
+        GroupCache::insert(self, track, object).await
+    }
+
+    async fn begin(&self, track: &FullTrackName, group_id: u64) {
+        let mut ops = DiskOps::default();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.recording.insert(track.clone(), group_id);
+
+            let Some(groups) = state.tracks.get(track) else {
+                return;
+            };
+            if groups
+                .last_key_value()
+                .is_none_or(|(last, _)| *last <= group_id)
+            {
+                return;
+            }
+
+            log::info!(
+                "publisher of {}/{} restarted at group {}, dropping its cached groups",
+                track.namespace,
+                track.name,
+                group_id
+            );
+            let stale: Vec<u64> = groups.keys().copied().collect();
+            for stale in stale {
+                self.remove(&mut state, &(track.clone(), stale), &mut ops);
+            }
+        }
+        self.flush(ops).await;
+    }
+
+    fn end(&self, track: &FullTrackName) {
+        self.state.lock().unwrap().recording.remove(track);
+    }
+}
+
+// One track being recorded, see [record].
+struct Recording<'a, R> {
+    recorder: &'a R,
+    name: FullTrackName,
+    track: &'a TrackReader,
+    started: AtomicBool,
+}
+
+impl<R: Recorder> Recording<'_, R> {
+    async fn insert(&self, object: FetchedObject) {
+        // Live-only tracks are marked by the publisher, possibly after we started recording.
+        if !self.track.is_fetchable() && !self.recorder.live_only() {
+            return;
+        }
+
+        if !self.started.swap(true, atomic::Ordering::Relaxed) {
+            self.recorder.begin(&self.name, object.group_id).await;
+        }
+        self.recorder.insert(&self.name, object).await;
+    }
+}
+
+/// Pass the objects of `track` to `recorder` as they are received, until it ends.
+pub(crate) async fn record<R: Recorder>(recorder: &R, track: TrackReader) {
+    let recording = Recording {
+        recorder,
+        name: FullTrackName {
+            namespace: track.namespace.clone(),
+            name: track.name.clone(),
+        },
+        track: &track,
+        started: AtomicBool::new(false),
+    };
+
+    let res = match track.mode().await {
+        Ok(TrackReaderMode::Subgroups(subgroups)) => {
+            let subgroups = subgroups.with_backpressure(Backpressure::Buffer(RECORD_SUBGROUPS));
+            record_subgroups(&recording, subgroups).await
+        }
+        Ok(TrackReaderMode::Datagrams(datagrams)) => record_datagrams(&recording, datagrams).await,
+        // Tracks sent as a single stream aren't cached.
+        Ok(TrackReaderMode::Stream(_)) => Ok(()),
+        Err(err) => Err(err),
+    };
+
+    if let Err(err) = res {
+        log::debug!(
+            "stopped recording {}/{}: {}",
+            recording.name.namespace,
+            recording.name.name,
+            err
+        );
+    }
+
+    if recording.started.into_inner() {
+        recorder.end(&recording.name);
+    }
+}
+
+async fn record_subgroups<R: Recorder>(
+    recording: &Recording<'_, R>,
+    mut subgroups: SubgroupsReader,
+) -> Result<(), ServeError> {
+    let mut datagrams = Some(subgroups.datagrams());
+    let mut tasks = FuturesUnordered::new();
+
+    loop {
+        tokio::select! {
+            res = async { datagrams.as_mut().unwrap().read().await }, if datagrams.is_some() => match res {
+                Ok(Some(datagram)) => insert_datagram(recording, datagram).await,
+                Ok(None) | Err(_) => datagrams = None,
+            },
+            res = subgroups.next() => match res? {
+                Some(subgroup) => tasks.push(record_subgroup(recording, subgroup)),
+                None => break,
+            },
+            _ = tasks.next(), if !tasks.is_empty() => {},
+        }
+    }
+
+    // Finish the subgroups that were still arriving.
+    while tasks.next().await.is_some() {}
+    Ok(())
+}
+
+async fn record_subgroup<R: Recorder>(recording: &Recording<'_, R>, mut subgroup: SubgroupReader) {
+    loop {
+        let mut object = match subgroup.next().await {
+            Ok(Some(object)) => object,
+            Ok(None) | Err(_) => return,
+        };
+
+        let Ok(payload) = object.read_all().await else {
+            return;
+        };
+
+        recording
+            .insert(FetchedObject {
+                group_id: subgroup.group_id,
+                subgroup_id: subgroup.subgroup_id,
+                object_id: object.object_id,
+                priority: subgroup.priority,
+                status: object.status,
+                extension_headers: object.extension_headers.clone(),
+                payload,
+            })
+            .await;
+    }
+}
+
+async fn record_datagrams<R: Recorder>(
+    recording: &Recording<'_, R>,
+    mut datagrams: DatagramsReader,
+) -> Result<(), ServeError> {
+    while let Some(datagram) = datagrams.read().await? {
+        insert_datagram(recording, datagram).await;
+    }
+
+    Ok(())
+}
+
+async fn insert_datagram<R: Recorder>(
+    recording: &Recording<'_, R>,
+    datagram: moq_transport::serve::Datagram,
+) {
+    recording
+        .insert(FetchedObject {
+            group_id: datagram.group_id,
+            subgroup_id: 0,
+            object_id: datagram.object_id,
+            priority: datagram.priority,
+            status: ObjectStatus::NormalObject,
+            extension_headers: datagram.extension_headers,
+            payload: datagram.payload,
+        })
+        .await;
+}
+
+// Run file operations on the blocking pool rather than the async workers.
+pub(crate) async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> io::Result<T> + Send + 'static,
+) -> io::Result<T> {
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(io::Error::other)?
+}
+
+// Groups are stored as they are sent in a fetch stream, each object followed by its payload.
+pub(crate) fn write_group(path: &Path, objects: &[FetchedObject]) -> io::Result<()> {
+    fs::write(path, encode_group(objects)?)
+}
+
+// Add objects at the end of a group written by [write_group], returning the bytes written.
+// [read_group] returns them in the order written, not sorted by object ID.
+pub(crate) fn append_group(path: &Path, objects: &[FetchedObject]) -> io::Result<u64> {
+    let buf = encode_group(objects)?;
+    fs::OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .write_all(&buf)?;
+    Ok(buf.len() as u64)
+}
+
+fn encode_group(objects: &[FetchedObject]) -> io::Result<BytesMut> {
+    let mut buf = BytesMut::new();
+
+    for object in objects {
+        FetchObject {
+            group_id: object.group_id,
+            subgroup_id: object.subgroup_id,
+            object_id: object.object_id,
+            publisher_priority: object.priority,
+            extension_headers: object.extension_headers.clone(),
+            payload_length: object.payload.len(),
+            status: Some(object.status),
+        }
+        .encode(&mut buf)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        buf.extend_from_slice(&object.payload);
+    }
+
+    Ok(buf)
+}
+
+pub(crate) fn read_group(path: &Path) -> io::Result<Vec<FetchedObject>> {
+    let mut buf = Bytes::from(fs::read(path)?);
+    let mut objects = Vec::new();
+
+    while !buf.is_empty() {
+        let object = FetchObject::decode(&mut buf)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        if buf.len() < object.payload_length {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+
+        objects.push(FetchedObject {
+            group_id: object.group_id,
+            subgroup_id: object.subgroup_id,
+            object_id: object.object_id,
+            priority: object.publisher_priority,
+            status: object.status.unwrap_or(ObjectStatus::NormalObject),
+            extension_headers: object.extension_headers,
+            payload: buf.split_to(object.payload_length),
+        });
+    }
+
+    Ok(objects)
+}
+
+// The consecutive run of ascending `groups` ending at the last one, without its first group, which
+// recording may have joined mid-group or which follows a lost group.
+pub(crate) fn whole_groups(
+    groups: impl DoubleEndedIterator<Item = u64>,
+) -> Option<RangeInclusive<u64>> {
+    let mut groups = groups.rev();
+    let last = groups.next()?;
+
+    let mut first = last;
+    for group_id in groups {
+        if group_id + 1 != first {
+            break;
+        }
+        first = group_id;
+    }
+
+    (first < last).then(|| first + 1..=last)
+}
+
+// Objects to store in tests, shared with the archive and previews.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    pub fn object(group_id: u64, object_id: u64, payload: &'static str) -> FetchedObject {
+        FetchedObject {
+            group_id,
+            subgroup_id: 0,
+            object_id,
+            priority: 0,
+            status: ObjectStatus::NormalObject,
+            extension_headers: Default::default(),
+            payload: payload.into(),
+        }
+    }
+
+    pub fn payloads(objects: Vec<FetchedObject>) -> Vec<Bytes> {
+        objects.into_iter().map(|object| object.payload).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fixtures::{object, payloads};
+    use super::*;
+    use moq_transport::coding::TrackNamespace;
+
+    #[test]
+    fn whole_runs() {
+        assert_eq!(whole_groups([1, 3, 4, 5].into_iter()), Some(4..=5));
+        assert_eq!(whole_groups([4, 5].into_iter()), Some(5..=5));
+        assert_eq!(whole_groups([2, 5].into_iter()), None);
+        assert_eq!(whole_groups(std::iter::empty()), None);
+    }
+
+    #[tokio::test]
+    async fn tiers() {
+        let dir = std::env::temp_dir().join(format!("moq-relay-cache-{}", std::process::id()));
+        let cache = GroupCache::new(CacheConfig {
+            memory_budget: 10,
+            disk_dir: Some(dir.clone()),
+            disk_budget: 12,
+        })
+        .unwrap();
+
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: "video".to_string(),
+        };
+        let whole = |group_id| (Location::new(group_id, 0), Location::new(group_id, 0));
+
+        // The older group spills to disk once memory is over budget.
+        cache.begin(&track, 0).await;
+        cache.insert(&track, object(0, 0, "zero")).await;
+        cache.insert(&track, object(0, 1, "-0")).await;
+        cache.insert(&track, object(1, 0, "one---")).await;
+        let stats = cache.stats();
+        assert_eq!((stats.memory.groups, stats.memory.bytes), (1, 6));
+        assert_eq!((stats.disk.groups, stats.disk.bytes), (1, 6));
+
+        // Reading it brings it back into memory, spilling the other one.
+        let (start, end) = whole(0);
+        assert_eq!(
+            payloads(cache.get(&track, start, end).await),
+            ["zero", "-0"]
+        );
+        let (start, end) = whole(0);
+        assert_eq!(
+            payloads(cache.get(&track, start, end).await),
+            ["zero", "-0"]
+        );
+        let stats = cache.stats();
+        assert_eq!((stats.memory.hits, stats.disk.hits), (1, 1));
+        assert_eq!(stats.memory.evictions, 2);
+
+        // The end of the range is exclusive, unless it covers the whole group.
+        let range = cache
+            .get(&track, Location::new(0, 1), Location::new(1, 1))
+            .await;
+        assert_eq!(payloads(range), ["-0", "one---"]);
+        assert_eq!(cache.largest(&track), Some(Location::new(1, 0)));
+
+        // Groups are deleted from disk, least recently used first, once over budget.
+        cache.insert(&track, object(2, 0, "two---")).await;
+        cache.insert(&track, object(3, 0, "three-")).await;
+        let stats = cache.stats();
+        assert_eq!(stats.disk.groups, 2);
+        assert_eq!(stats.disk.evictions, 1);
+        let (start, end) = whole(0);
+        assert!(cache.get(&track, start, end).await.is_empty());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        assert_eq!(cache.largest(&track), Some(Location::new(3, 0)));
+        assert_eq!(cache.cached_groups(&track), Some(2..=2));
+
+        let stats = cache.stats();
+        assert_eq!(stats.misses, 1);
+        assert_eq!(stats.lookups, 5);
+        assert_eq!(stats.disk.hit_rate, 0.4);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn forgets_restarted_publisher() {
+        let cache = GroupCache::new(CacheConfig {
+            memory_budget: 1024,
+            ..Default::default()
+        })
+        .unwrap();
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: "video".to_string(),
+        };
+        for group_id in 5..8 {
+            cache.insert(&track, object(group_id, 0, "frame")).await;
+        }
+
+        // Resuming at the latest group keeps the cached ones.
+        cache.begin(&track, 7).await;
+        assert_eq!(cache.cached_groups(&track), Some(6..=6));
+
+        // Starting over from an earlier group means they're from before a restart.
+        cache.begin(&track, 0).await;
+        assert_eq!(cache.cached_groups(&track), None);
+        assert_eq!(cache.largest(&track), None);
+        assert_eq!(cache.stats().memory.bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn largest_of_current_recording() {
+        let cache = GroupCache::new(CacheConfig {
+            memory_budget: 1024,
+            ..Default::default()
+        })
+        .unwrap();
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: "video".to_string(),
+        };
+        cache.begin(&track, 5).await;
+        for group_id in 5..8 {
+            cache.insert(&track, object(group_id, 0, "frame")).await;
+        }
+        assert_eq!(cache.largest(&track), Some(Location::new(7, 0)));
+
+        // Once the upstream subscription ends, until the next one records a group.
+        cache.end(&track);
+        assert_eq!(cache.largest(&track), None);
+        cache.begin(&track, 8).await;
+        assert_eq!(cache.largest(&track), None);
+        cache.insert(&track, object(8, 0, "frame")).await;
+        assert_eq!(cache.largest(&track), Some(Location::new(8, 0)));
+    }
+
+    #[tokio::test]
+    async fn evicts_while_reading() {
+        let dir = std::env::temp_dir().join(format!("moq-relay-evict-{}", std::process::id()));
+        let cache = GroupCache::new(CacheConfig {
+            memory_budget: 10,
+            disk_dir: Some(dir.clone()),
+            disk_budget: 12,
+        })
+        .unwrap();
+
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: "video".to_string(),
+        };
+        cache.insert(&track, object(0, 0, "zero--")).await;
+        cache.insert(&track, object(1, 0, "one---")).await;
+        cache.insert(&track, object(2, 0, "two-----")).await;
+        assert_eq!(cache.stats().disk.groups, 2);
+
+        // Reading group 0 back spills group 2, which evicts group 1 before it's read.
+        let range = cache
+            .get(&track, Location::new(0, 0), Location::new(1, 0))
+            .await;
+        assert_eq!(payloads(range), ["zero--"]);
+
+        let stats = cache.stats();
+        assert_eq!((stats.memory.groups, stats.disk.groups), (1, 1));
+        assert_eq!((stats.disk.hits, stats.disk.evictions), (1, 1));
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,570 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    net,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use moq_native_ietf::quic;
+use moq_transport::{
+    mlog::MlogWriter,
+    session::{Publisher, Subscriber},
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+/// Symptoms that start capturing a session's mlog, each counted over [CaptureConfig::window_secs].
+/// Unset symptoms never start a capture.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureTriggers {
+    /// Streams of subscriptions to our tracks that ended with an error, ex. reset by the peer.
+    pub stream_resets: Option<u64>,
+
+    /// Objects and groups not delivered to the peer, ex. past their delivery timeout.
+    pub drops: Option<u64>,
+
+    /// Streams and datagrams from the peer dropped for being malformed or oversized.
+    pub protocol_violations: Option<u64>,
+
+    /// The percentage of packets lost, once enough were sent to tell.
+    pub loss_percent: Option<f64>,
+}
+
+/// When to capture a session's mlog, for sessions otherwise logged on standby.
+///
+/// Loaded from a JSON file like `{"triggers": {"stream_resets": 20, "loss_percent": 10}, "duration_secs": 60}`.
+/// Requires an mlog directory, which the captures are written to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CaptureConfig {
+    pub triggers: CaptureTriggers,
+
+    /// The period over which symptoms are counted.
+    pub window_secs: u64,
+
+    /// How long a capture lasts once started.
+    pub duration_secs: u64,
+
+    /// Also write qlog for the peer's next connections, while the capture lasts.
+    /// qlog can't be enabled on the connection that triggered the capture.
+    pub qlog: bool,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            triggers: Default::default(),
+            window_secs: 10,
+            duration_secs: 60,
+            qlog: true,
+        }
+    }
+}
+
+impl CaptureConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(self.window_secs > 0, "capture window must be positive");
+        anyhow::ensure!(self.duration_secs > 0, "capture duration must be positive");
+        if let Some(loss) = self.triggers.loss_percent {
+            anyhow::ensure!(
+                (0.0..=100.0).contains(&loss),
+                "invalid capture loss_percent: {}",
+                loss
+            );
+        }
+        Ok(())
+    }
+
+    /// Load the capture config from a JSON file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read capture config: {}", path.display()))?;
+        let config: Self = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse capture config: {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.duration_secs)
+    }
+}
+
+/// Running totals of a session's symptoms, see [CaptureTriggers].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Symptoms {
+    pub stream_resets: u64,
+    pub drops: u64,
+    pub protocol_violations: u64,
+    pub sent_packets: u64,
+    pub lost_packets: u64,
+}
+
+impl Symptoms {
+    // Too few packets to tell loss from noise.
+    const MIN_PACKETS: u64 = 100;
+
+    fn since(&self, earlier: &Self) -> Self {
+        Self {
+            stream_resets: self.stream_resets.saturating_sub(earlier.stream_resets),
+            drops: self.drops.saturating_sub(earlier.drops),
+            protocol_violations: self
+                .protocol_violations
+                .saturating_sub(earlier.protocol_violations),
+            sent_packets: self.sent_packets.saturating_sub(earlier.sent_packets),
+            lost_packets: self.lost_packets.saturating_sub(earlier.lost_packets),
+        }
+    }
+}
+
+impl CaptureTriggers {
+    /// The first symptom over its threshold among those counted over a window, if any.
+    pub fn fired(&self, symptoms: &Symptoms) -> Option<&'static str> {
+        let over = |threshold: Option<u64>, count: u64| threshold.is_some_and(|max| count >= max);
+
+        if over(self.stream_resets, symptoms.stream_resets) {
+            return Some("stream_resets");
+        }
+        if over(self.drops, symptoms.drops) {
+            return Some("drops");
+        }
+        if over(self.protocol_violations, symptoms.protocol_violations) {
+            return Some("protocol_violations");
+        }
+
+        if let Some(max) = self.loss_percent {
+            if symptoms.sent_packets >= Symptoms::MIN_PACKETS {
+                let loss = symptoms.lost_packets as f64 * 100.0 / symptoms.sent_packets as f64;
+                if loss >= max {
+                    return Some("loss_percent");
+                }
+            }
+        }
+
+        None
+    }
+}
+
+// Samples of a session's symptoms over the last window.
+struct SymptomWindow {
+    period: Duration,
+    samples: VecDeque<(Instant, Symptoms)>,
+}
+
+impl SymptomWindow {
+    fn new(period: Duration) -> Self {
+        Self {
+            period,
+            samples: VecDeque::new(),
+        }
+    }
+
+    // Add a sample, returning the symptoms since the oldest one still in the window.
+    fn push(&mut self, now: Instant, symptoms: Symptoms) -> Symptoms {
+        while let Some((time, _)) = self.samples.front() {
+            match now.duration_since(*time) > self.period {
+                true => self.samples.pop_front(),
+                false => break,
+            };
+        }
+        self.samples.push_back((now, symptoms));
+
+        let (_, oldest) = self.samples.front().unwrap();
+        symptoms.since(oldest)
+    }
+
+    fn clear(&mut self) {
+        self.samples.clear();
+    }
+}
+
+/// A capture of a session's mlog, as listed by the admin API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionCapture {
+    pub connection_id: String,
+    pub remote_address: net::SocketAddr,
+    /// The symptom that started the capture, see [CaptureTriggers], or `admin` if it was requested
+    /// with [CaptureMetrics::request].
+    pub symptom: &'static str,
+    /// The mlog file the capture is written to.
+    pub path: PathBuf,
+    /// Whether qlog was requested for the peer's next connections.
+    pub qlog_requested: bool,
+    /// Start time, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// End time, in seconds since the Unix epoch, once the capture ended.
+    pub ended_at: Option<u64>,
+}
+
+/// Relay-wide record of the sessions captured because of their symptoms, see [CaptureConfig].
+#[derive(Clone, Default)]
+pub struct CaptureMetrics {
+    state: Arc<Mutex<CaptureState>>,
+}
+
+#[derive(Default)]
+struct CaptureState {
+    next_id: u64,
+    active: BTreeMap<u64, SessionCapture>,
+    recent: VecDeque<SessionCapture>,
+    symptoms: BTreeMap<&'static str, u64>,
+
+    // The sessions watched by a monitor, and whether a capture was requested for them.
+    monitored: HashMap<String, bool>,
+}
+
+/// A snapshot of [CaptureMetrics].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CaptureStats {
+    /// How many captures started, by symptom.
+    pub symptoms: BTreeMap<&'static str, u64>,
+    /// The captures in progress, oldest first.
+    pub active: Vec<SessionCapture>,
+    /// The most recent captures that ended, newest first.
+    pub recent: Vec<SessionCapture>,
+}
+
+impl CaptureMetrics {
+    /// How many captures are kept for [CaptureStats::recent].
+    pub const RECENT: usize = 100;
+
+    /// List a capture as active until the returned guard is dropped.
+    pub fn start(&self, capture: SessionCapture) -> CaptureGuard {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+
+        *state.symptoms.entry(capture.symptom).or_default() += 1;
+        state.active.insert(id, capture);
+
+        CaptureGuard {
+            metrics: self.clone(),
+            id,
+        }
+    }
+
+    /// Ask the monitor of the session with the given connection ID to start a capture, as if a
+    /// trigger fired. Returns false if the session isn't monitored, ex. without a [CaptureConfig].
+    pub fn request(&self, connection_id: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.monitored.get_mut(connection_id) {
+            Some(requested) => {
+                *requested = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Whether a capture was requested since the last call, by the monitor of the session.
+    fn take_request(&self, connection_id: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state
+            .monitored
+            .get_mut(connection_id)
+            .is_some_and(std::mem::take)
+    }
+
+    /// Whether the session with the given connection ID is being captured.
+    pub fn is_capturing(&self, connection_id: &str) -> bool {
+        let state = self.state.lock().unwrap();
+        state
+            .active
+            .values()
+            .any(|capture| capture.connection_id == connection_id)
+    }
+
+    pub fn stats(&self) -> CaptureStats {
+        let state = self.state.lock().unwrap();
+
+        CaptureStats {
+            symptoms: state.symptoms.clone(),
+            active: state.active.values().cloned().collect(),
+            recent: state.recent.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Lists a capture as active in [CaptureMetrics] until dropped.
+pub struct CaptureGuard {
+    metrics: CaptureMetrics,
+    id: u64,
+}
+
+impl Drop for CaptureGuard {
+    fn drop(&mut self) {
+        let mut state = self.metrics.state.lock().unwrap();
+        if let Some(mut capture) = state.active.remove(&self.id) {
+            capture.ended_at = Some(unix_time());
+            if state.recent.len() == CaptureMetrics::RECENT {
+                state.recent.pop_back();
+            }
+            state.recent.push_front(capture);
+        }
+    }
+}
+
+/// Watches a session's symptoms, capturing its mlog for a while when a trigger fires.
+pub(crate) struct CaptureMonitor {
+    pub config: CaptureConfig,
+    pub connection_id: String,
+    pub connection: quic::Connection,
+    pub mlog: Arc<Mutex<MlogWriter>>,
+    pub mlog_path: PathBuf,
+
+    // Our side of the session, see [crate::Admin::register_session].
+    pub publisher: Option<Publisher>,
+    pub subscriber: Option<Subscriber>,
+
+    pub metrics: CaptureMetrics,
+
+    /// Where to request qlog for the peer's next connections, and the directory to write it to.
+    pub qlog: Option<(quic::QlogRequests, PathBuf)>,
+}
+
+impl CaptureMonitor {
+    const SAMPLE: Duration = Duration::from_secs(1);
+
+    /// Watch the session until dropped, ex. when the session ends.
+    pub async fn run(self) {
+        self.metrics
+            .state
+            .lock()
+            .unwrap()
+            .monitored
+            .insert(self.connection_id.clone(), false);
+
+        let mut totals = SymptomTotals::default();
+        let mut window = SymptomWindow::new(self.config.window());
+        let mut capture: Option<(Instant, CaptureGuard)> = None;
+
+        let mut interval = tokio::time::interval(Self::SAMPLE);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            let now = interval.tick().await;
+            let symptoms = totals.sample(&self);
+            let requested = self.metrics.take_request(&self.connection_id);
+
+            if let Some((until, _)) = &capture {
+                if now >= *until {
+                    capture = None;
+                    self.stop();
+
+                    // Only symptoms seen after the capture may start another one.
+                    window.clear();
+                } else {
+                    continue;
+                }
+            }
+
+            let recent = window.push(now, symptoms);
+            let symptom = match requested {
+                true => Some("admin"),
+                false => self.config.triggers.fired(&recent),
+            };
+            if let Some(symptom) = symptom {
+                if let Some(guard) = self.start(symptom, &recent) {
+                    capture = Some((now + self.config.duration(), guard));
+                }
+            }
+        }
+    }
+
+    fn start(&self, symptom: &'static str, recent: &Symptoms) -> Option<CaptureGuard> {
+        if let Err(err) = self.mlog.lock().unwrap().start() {
+            log::warn!("failed to start mlog capture: {}", err);
+            return None;
+        }
+
+        let remote_address = self.connection.remote_address();
+        if let Some((requests, dir)) = &self.qlog {
+            requests.request(remote_address.ip(), dir.clone(), self.config.duration());
+        }
+
+        log::warn!(
+            "capturing mlog: cid={} symptom={} recent={:?} path={}",
+            self.connection_id,
+            symptom,
+            recent,
+            self.mlog_path.display()
+        );
+
+        Some(self.metrics.start(SessionCapture {
+            connection_id: self.connection_id.clone(),
+            remote_address,
+            symptom,
+            path: self.mlog_path.clone(),
+            qlog_requested: self.qlog.is_some(),
+            started_at: unix_time(),
+            ended_at: None,
+        }))
+    }
+
+    fn stop(&self) {
+        if let Err(err) = self.mlog.lock().unwrap().stop() {
+            log::warn!("failed to stop mlog capture: {}", err);
+        }
+        log::info!("mlog capture ended: cid={}", self.connection_id);
+    }
+}
+
+impl Drop for CaptureMonitor {
+    fn drop(&mut self) {
+        let mut state = self.metrics.state.lock().unwrap();
+        state.monitored.remove(&self.connection_id);
+    }
+}
+
+// Running totals of symptoms counted per subscription, which keep growing as subscriptions end.
+#[derive(Default)]
+struct SymptomTotals {
+    subscriptions: HashMap<u64, (u64, u64)>,
+    stream_resets: u64,
+    drops: u64,
+}
+
+impl SymptomTotals {
+    fn sample(&mut self, monitor: &CaptureMonitor) -> Symptoms {
+        let mut seen = HashMap::new();
+
+        if let Some(publisher) = &monitor.publisher {
+            for subscription in publisher.subscriptions() {
+                let Some(stats) = publisher.subscription_stats(subscription.id) else {
+                    continue;
+                };
+                let stats = stats.get();
+                let counts = (
+                    stats.streams_reset,
+                    stats.objects_skipped + stats.groups_skipped,
+                );
+
+                let (resets, drops) = self
+                    .subscriptions
+                    .get(&subscription.id)
+                    .copied()
+                    .unwrap_or_default();
+                self.stream_resets += counts.0.saturating_sub(resets);
+                self.drops += counts.1.saturating_sub(drops);
+                seen.insert(subscription.id, counts);
+            }
+        }
+        self.subscriptions = seen;
+
+        let connection = monitor.connection.stats();
+        Symptoms {
+            stream_resets: self.stream_resets,
+            drops: self.drops,
+            protocol_violations: monitor
+                .subscriber
+                .as_ref()
+                .map_or(0, Subscriber::violations),
+            sent_packets: connection.sent_packets,
+            lost_packets: connection.lost_packets,
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn triggers() {
+        let triggers = CaptureTriggers {
+            stream_resets: Some(5),
+            loss_percent: Some(10.0),
+            ..Default::default()
+        };
+
+        assert_eq!(triggers.fired(&Symptoms::default()), None);
+        assert_eq!(
+            triggers.fired(&Symptoms {
+                stream_resets: 5,
+                ..Default::default()
+            }),
+            Some("stream_resets")
+        );
+
+        // Unset triggers never fire.
+        assert_eq!(
+            triggers.fired(&Symptoms {
+                drops: 1000,
+                ..Default::default()
+            }),
+            None
+        );
+
+        // Loss only counts once enough packets were sent.
+        let lossy = |sent_packets, lost_packets| Symptoms {
+            sent_packets,
+            lost_packets,
+            ..Default::default()
+        };
+        assert_eq!(triggers.fired(&lossy(10, 5)), None);
+        assert_eq!(triggers.fired(&lossy(200, 10)), None);
+        assert_eq!(triggers.fired(&lossy(200, 20)), Some("loss_percent"));
+    }
+
+    #[test]
+    fn window() {
+        let mut window = SymptomWindow::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let resets = |stream_resets| Symptoms {
+            stream_resets,
+            ..Default::default()
+        };
+
+        assert_eq!(window.push(start, resets(3)), resets(0));
+        assert_eq!(
+            window.push(start + Duration::from_secs(5), resets(8)),
+            resets(5)
+        );
+
+        // Symptoms older than the window no longer count.
+        assert_eq!(
+            window.push(start + Duration::from_secs(12), resets(10)),
+            resets(2)
+        );
+    }
+
+    #[test]
+    fn records_captures() {
+        let metrics = CaptureMetrics::default();
+        let capture = SessionCapture {
+            connection_id: "abc".into(),
+            remote_address: "127.0.0.1:4443".parse().unwrap(),
+            symptom: "drops",
+            path: "abc_server.mlog".into(),
+            qlog_requested: false,
+            started_at: 0,
+            ended_at: None,
+        };
+
+        let guard = metrics.start(capture);
+        assert!(metrics.is_capturing("abc"));
+        assert_eq!(metrics.stats().active.len(), 1);
+
+        drop(guard);
+        assert!(!metrics.is_capturing("abc"));
+
+        let stats = metrics.stats();
+        assert_eq!(stats.symptoms, BTreeMap::from([("drops", 1)]));
+        assert!(stats.active.is_empty());
+        assert!(stats.recent[0].ended_at.is_some());
+    }
+}
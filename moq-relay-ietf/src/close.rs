@@ -0,0 +1,140 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net,
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use moq_native_ietf::quic::CloseReason;
+use serde::Serialize;
+
+/// Relay-wide counts of why sessions closed, shared by every session.
+///
+/// Closes are counted by [CloseReason::label], so operators can tell clients leaving
+/// (`application`) from network trouble (`timed_out`, `reset`) and misbehaving peers
+/// (`transport`, `peer_transport`).
+#[derive(Clone, Default)]
+pub struct CloseMetrics {
+    state: Arc<Mutex<CloseState>>,
+}
+
+#[derive(Default)]
+struct CloseState {
+    reasons: BTreeMap<&'static str, u64>,
+    recent: VecDeque<SessionClose>,
+}
+
+/// A closed session, as listed by the admin API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionClose {
+    pub connection_id: String,
+    pub remote_address: net::SocketAddr,
+    /// The kind of close, see [CloseReason::label].
+    pub reason: &'static str,
+    /// The error code sent or received, if any.
+    pub code: Option<u64>,
+    /// The reason phrase sent or received, if any.
+    pub message: Option<String>,
+    /// The MoQ error we closed the session with, if we closed it because of one.
+    pub error: Option<String>,
+    /// Close time, in seconds since the Unix epoch.
+    pub closed_at: u64,
+}
+
+/// A snapshot of [CloseMetrics].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CloseStats {
+    /// How many sessions closed, by kind of close.
+    pub reasons: BTreeMap<&'static str, u64>,
+    /// The most recent closes, newest first.
+    pub recent: Vec<SessionClose>,
+}
+
+impl CloseMetrics {
+    /// How many closes are kept for [CloseStats::recent].
+    pub const RECENT: usize = 100;
+
+    /// Count a closed session, along with the error we closed it with, if any.
+    pub fn record(
+        &self,
+        connection_id: String,
+        remote_address: net::SocketAddr,
+        reason: &CloseReason,
+        error: Option<String>,
+    ) {
+        let close = SessionClose {
+            connection_id,
+            remote_address,
+            reason: reason.label(),
+            code: reason.code(),
+            message: reason.reason().map(Into::into),
+            error,
+            closed_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        *state.reasons.entry(close.reason).or_default() += 1;
+        if state.recent.len() == Self::RECENT {
+            state.recent.pop_back();
+        }
+        state.recent.push_front(close);
+    }
+
+    pub fn stats(&self) -> CloseStats {
+        let state = self.state.lock().unwrap();
+
+        CloseStats {
+            reasons: state.reasons.clone(),
+            recent: state.recent.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_reasons() {
+        let metrics = CloseMetrics::default();
+        let addr = "127.0.0.1:4443".parse().unwrap();
+
+        for _ in 0..CloseMetrics::RECENT {
+            metrics.record("idle".into(), addr, &CloseReason::TimedOut, None);
+        }
+        metrics.record(
+            "gone".into(),
+            addr,
+            &CloseReason::Application {
+                code: 0,
+                reason: "bye".into(),
+            },
+            None,
+        );
+        metrics.record(
+            "broken".into(),
+            addr,
+            &CloseReason::Local,
+            Some("protocol violation".into()),
+        );
+
+        let stats = metrics.stats();
+        assert_eq!(
+            stats.reasons,
+            BTreeMap::from([("application", 1), ("local", 1), ("timed_out", 100)])
+        );
+
+        // The oldest closes were dropped to make room.
+        assert_eq!(stats.recent.len(), CloseMetrics::RECENT);
+        assert_eq!(stats.recent[0].reason, "local");
+        assert_eq!(stats.recent[0].error.as_deref(), Some("protocol violation"));
+        assert_eq!(stats.recent[1].connection_id, "gone");
+        assert_eq!(stats.recent[1].code, Some(0));
+        assert_eq!(stats.recent[1].message.as_deref(), Some("bye"));
+        assert_eq!(stats.recent[2].reason, "timed_out");
+        assert_eq!(stats.recent[2].message, None);
+    }
+}
@@ -1,13 +1,23 @@
-use std::sync::Arc;
+use std::{
+    collections::{hash_map, HashMap},
+    sync::Arc,
+};
 
-use anyhow::Context;
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use moq_transport::{
-    serve::Tracks,
-    session::{Announced, SessionError, Subscriber},
+    coding::TrackNamespace,
+    message::PublishNamespaceErrorCode,
+    serve::{FullTrackName, ServeError, Tracks},
+    session::{Announced, SessionError, Subscribe, SubscribeNamespace, Subscriber},
 };
+use tokio::{sync::watch, time::Instant};
 
-use crate::{Coordinator, Locals, Producer};
+use crate::{
+    AnnounceBatcher, AnnounceFeed, AnnouncePath, AnnounceProgress, Archive, Coordinator,
+    CoordinatorError, GroupCache, Liveness, LivenessConfig, Locals, Previews, Priorities,
+    SessionAnnounceLimiter, SessionAuthorizer, SessionInterests, SessionTeardown, TeardownMetrics,
+    Tenant,
+};
 
 /// Consumer of tracks from a remote Publisher
 #[derive(Clone)]
@@ -15,7 +25,20 @@ pub struct Consumer {
     subscriber: Subscriber,
     locals: Locals,
     coordinator: Arc<dyn Coordinator>,
-    forward: Option<Producer>, // Forward all announcements to this subscriber
+    forward: Option<AnnounceFeed>, // Forward all announcements to the destinations watching this feed
+    instance: Option<String>,
+    announce_limiter: SessionAnnounceLimiter,
+    batcher: AnnounceBatcher,
+    reregister: Option<watch::Receiver<u64>>,
+    authorizer: Option<SessionAuthorizer>,
+    teardown: SessionTeardown,
+    cache: Option<GroupCache>,
+    archive: Option<Archive>,
+    previews: Option<Previews>,
+    priorities: Priorities,
+    interests: Option<SessionInterests>,
+    tenant: Option<Tenant>,
+    liveness: Option<LivenessConfig>,
 }
 
 impl Consumer {
@@ -23,22 +46,129 @@ impl Consumer {
         subscriber: Subscriber,
         locals: Locals,
         coordinator: Arc<dyn Coordinator>,
-        forward: Option<Producer>,
+        forward: Option<AnnounceFeed>,
+        announce_limiter: SessionAnnounceLimiter,
     ) -> Self {
         Self {
             subscriber,
             locals,
+            batcher: AnnounceBatcher::spawn(coordinator.clone()),
             coordinator,
             forward,
+            instance: None,
+            announce_limiter,
+            reregister: None,
+            authorizer: None,
+            teardown: SessionTeardown::new(TeardownMetrics::default()),
+            cache: None,
+            archive: None,
+            previews: None,
+            priorities: Priorities::default(),
+            interests: None,
+            tenant: None,
+            liveness: None,
         }
     }
 
+    /// Count this session's teardown in `metrics`, shared with other sessions.
+    pub fn with_teardown_metrics(mut self, metrics: TeardownMetrics) -> Self {
+        self.teardown = SessionTeardown::new(metrics);
+        self
+    }
+
+    /// The teardown that releases this session's announces once it ends.
+    pub fn teardown(&self) -> SessionTeardown {
+        self.teardown.clone()
+    }
+
+    /// Refuse announces forwarded or gossiped through the relay `instance` already, ex. our own
+    /// coming back around a chain of `--announce` destinations.
+    pub fn with_instance(mut self, instance: String) -> Self {
+        self.instance = Some(instance);
+        self
+    }
+
+    /// Check every announce with `authorizer` before registering it.
+    pub fn with_authorizer(mut self, authorizer: SessionAuthorizer) -> Self {
+        self.authorizer = Some(authorizer);
+        self
+    }
+
+    /// Re-advertise announced namespaces with the coordinator whenever `requests` changes.
+    pub fn with_reregister(mut self, requests: watch::Receiver<u64>) -> Self {
+        self.reregister = Some(requests);
+        self
+    }
+
+    /// Record the tracks this session publishes into `cache`, so they can be fetched later.
+    pub fn with_cache(mut self, cache: GroupCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Record the tracks this session publishes in archived namespaces into `archive`.
+    pub fn with_archive(mut self, archive: Archive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Subscribe to the tracks `previews` samples as soon as their namespace is announced.
+    pub fn with_previews(mut self, previews: Previews) -> Self {
+        self.previews = Some(previews);
+        self
+    }
+
+    /// Subscribe to the catalogs `priorities` classifies audio tracks with as soon as their
+    /// namespace is announced.
+    pub fn with_priorities(mut self, priorities: Priorities) -> Self {
+        self.priorities = priorities;
+        self
+    }
+
+    /// Pass on the namespace prefixes other sessions subscribed to, so the publisher knows what to announce.
+    pub fn with_interests(mut self, interests: SessionInterests) -> Self {
+        self.interests = Some(interests);
+        self
+    }
+
+    /// Refuse announces of namespaces `tenant` doesn't own, and only pass on interest in them.
+    pub fn with_tenant(mut self, tenant: Tenant) -> Self {
+        self.tenant = Some(tenant);
+        self
+    }
+
+    /// Mark announced namespaces stale while the publisher sends nothing on their subscribed tracks.
+    pub fn with_liveness(mut self, liveness: LivenessConfig) -> Self {
+        self.liveness = Some(liveness);
+        self
+    }
+
+    /// Progress of announce registration for this session.
+    pub fn announce_progress(&self) -> AnnounceProgress {
+        self.announce_limiter.progress()
+    }
+
     /// Run the consumer to serve announce requests.
     pub async fn run(mut self) -> Result<(), SessionError> {
         let mut tasks = FuturesUnordered::new();
 
+        let mut interests = self.interests.clone().map(|interests| {
+            let mut changes = interests.subscribe();
+            // Catch up on the prefixes from before this session.
+            changes.mark_changed();
+            (interests, changes)
+        });
+        let mut forwarded = HashMap::new();
+
         loop {
             tokio::select! {
+                // Keep a SUBSCRIBE_NAMESPACE open for each prefix somebody else is interested in
+                res = async { interests.as_mut().unwrap().1.changed().await }, if interests.is_some() => {
+                    match res {
+                        Ok(()) => self.forward_interests(&interests.as_ref().unwrap().0, &mut forwarded),
+                        Err(_) => interests = None,
+                    }
+                },
                 // Handle a new announce request
                 Some(announce) = self.subscriber.announced() => {
                     let this = self.clone();
@@ -59,70 +189,353 @@ impl Consumer {
         }
     }
 
+    fn forward_interests(
+        &mut self,
+        interests: &SessionInterests,
+        forwarded: &mut HashMap<TrackNamespace, SubscribeNamespace>,
+    ) {
+        let mut wanted = interests.wanted();
+        if let Some(tenant) = &self.tenant {
+            // Other tenants' interests are none of this publisher's business.
+            wanted.retain(|prefix| tenant.overlaps(prefix));
+        }
+
+        // Dropping the handle sends UNSUBSCRIBE_NAMESPACE.
+        forwarded.retain(|prefix, _| wanted.contains(prefix));
+
+        for prefix in wanted {
+            if let hash_map::Entry::Vacant(entry) = forwarded.entry(prefix) {
+                log::info!("forwarding subscribe namespace: {}", entry.key());
+                let subscribe = self.subscriber.subscribe_namespace(entry.key().clone());
+                entry.insert(subscribe);
+            }
+        }
+    }
+
     /// Serve an announce request.
-    async fn serve(mut self, mut announce: Announced) -> Result<(), anyhow::Error> {
+    async fn serve(self, announce: Announced) -> Result<(), anyhow::Error> {
+        let namespace = announce.namespace.clone();
+        let teardown = self.teardown.clone();
+
+        let res = self.serve_announce(announce).await;
+
+        // Only reached if the announce ended on its own; when the session dies this future is
+        // dropped and the session teardown releases the namespace instead.
+        teardown.release(&namespace);
+
+        res
+    }
+
+    async fn serve_announce(mut self, mut announce: Announced) -> Result<(), anyhow::Error> {
         let mut tasks = FuturesUnordered::new();
 
+        // The relays the announce was forwarded or gossiped through, if any.
+        let path = match AnnouncePath::from_params(&announce.params) {
+            Ok(path) => path,
+            Err(err) => {
+                announce.reject(
+                    PublishNamespaceErrorCode::NotSupported,
+                    "invalid announce path",
+                )?;
+                return Err(err);
+            }
+        };
+        if self
+            .instance
+            .as_ref()
+            .is_some_and(|instance| path.contains(instance))
+        {
+            let namespace = announce.namespace.clone();
+            announce.reject(PublishNamespaceErrorCode::Uninterested, "announce loop")?;
+            anyhow::bail!(
+                "announce looped back to this relay: {} via {}",
+                namespace,
+                path
+            );
+        }
+
+        if let Some(tenant) = &self.tenant {
+            if !tenant.owns(&announce.namespace) {
+                let namespace = announce.namespace.clone();
+                announce.reject(
+                    PublishNamespaceErrorCode::Unauthorized,
+                    "outside the tenant",
+                )?;
+                anyhow::bail!("announce outside the tenant for {}", namespace);
+            }
+        }
+
+        if let Some(authorizer) = &self.authorizer {
+            if !authorizer
+                .authorize_announce(&announce.namespace, &announce.authorization_tokens)
+                .await
+            {
+                let namespace = announce.namespace.clone();
+                announce.reject(PublishNamespaceErrorCode::Unauthorized, "unauthorized")?;
+                anyhow::bail!("unauthorized announce for {}", namespace);
+            }
+        }
+
+        // Held until the announce ends, so it counts towards the session's namespaces.
+        let Some(_slot) = self.announce_limiter.reserve() else {
+            let namespace = announce.namespace.clone();
+            announce.reject(
+                PublishNamespaceErrorCode::LimitExceeded,
+                "too many announced namespaces",
+            )?;
+            anyhow::bail!("announce limit exceeded for {}", namespace);
+        };
+
         // Produce the tracks for this announce and return the reader
-        let (_, mut request, reader) = Tracks::new(announce.namespace.clone()).produce();
+        let (mut writer, mut request, reader) = Tracks::new(announce.namespace.clone()).produce();
+        let mut tracks = reader.clone();
+
+        // Upstream subscriptions, each shared by every downstream subscriber of the track.
+        let mut forwards = FuturesUnordered::new();
 
         // NOTE(mpandit): once the track is pulled from origin, internally it will be relayed
         // from this metal only, because now coordinator will have entry for the namespace.
 
         // should we allow the same namespace being served from multiple relays??
 
-        // Register namespace with the coordinator
-        let _namespace_registration = self
-            .coordinator
-            .register_namespace(&reader.namespace)
-            .await?;
+        // Wait for a registration slot, so a burst of announces doesn't stampede the coordinator
+        let permit = self.announce_limiter.acquire().await;
+
+        // Register the local tracks, held by the teardown until the announce or session ends
+        let register = match self.locals.register(reader.clone()).await {
+            Ok(register) => register,
+            Err(err) => {
+                announce.reject(
+                    PublishNamespaceErrorCode::Duplicate,
+                    "namespace already announced",
+                )?;
+                return Err(err);
+            }
+        };
 
-        // Register the local tracks, unregister on drop
-        let _register = self.locals.register(reader.clone()).await?;
+        // Register namespace with the coordinator, unless another publisher of it already did,
+        // along with the session's other announces queued up meanwhile
+        let namespace_registration = match register
+            .coordinator(|| self.batcher.register(&reader.namespace))
+            .await
+        {
+            Ok(registration) => registration,
+            Err(err) => {
+                let code = match err {
+                    CoordinatorError::NamespaceAlreadyRegistered => {
+                        PublishNamespaceErrorCode::Duplicate
+                    }
+                    CoordinatorError::Timeout => PublishNamespaceErrorCode::Timeout,
+                    _ => PublishNamespaceErrorCode::InternalError,
+                };
+                announce.reject(code, &err.to_string())?;
+                return Err(err.into());
+            }
+        };
+        let mut replaced = std::pin::pin!(register.replaced());
+        let stale = register.stale();
+        self.teardown
+            .hold(reader.namespace.clone(), register, namespace_registration);
 
         // Accept the announce with an OK response
         announce.ok()?;
+        permit.registered();
+
+        // Forward the announce, if needed, until it ends
+        let mut _forwarded = self
+            .forward
+            .as_ref()
+            .map(|feed| feed.add_forwarded(reader.namespace.clone(), path.clone()));
 
-        // Forward the announce, if needed
-        if let Some(mut forward) = self.forward {
+        // Check the subscribed tracks for new objects, if the publisher's liveness is tracked.
+        let mut liveness = self.liveness.map(|config| {
+            let liveness = Liveness::new(config.timeout);
+            let mut interval = tokio::time::interval(liveness.interval());
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            (config, liveness, interval)
+        });
+
+        // Tell the coordinator in the background, so a slow one doesn't hold up the announce,
+        // one change at a time so the latest wins.
+        if liveness.is_some() {
+            let mut changes = stale.subscribe();
+            let coordinator = self.coordinator.clone();
+            let namespace = announce.namespace.clone();
             tasks.push(
                 async move {
-                    log::info!("forwarding announce: {:?}", reader.info);
-                    forward
-                        .announce(reader)
-                        .await
-                        .context("failed forwarding announce")
+                    while changes.changed().await.is_ok() {
+                        let is_stale = *changes.borrow_and_update();
+                        if let Err(err) =
+                            coordinator.set_namespace_stale(&namespace, is_stale).await
+                        {
+                            log::warn!(
+                                "failed to mark namespace stale: {}, error: {}",
+                                namespace,
+                                err
+                            );
+                        }
+                    }
                 }
                 .boxed(),
             );
         }
 
+        let mut reregister = self.reregister.take();
+
+        // Sample the previewed tracks without waiting for a subscriber, until the announce ends.
+        if let Some(previews) = &self.previews {
+            for name in previews.tracks(&announce.namespace) {
+                if let Some(track) = tracks.subscribe(announce.namespace.clone(), name) {
+                    tasks.push(previews.clone().record(track).boxed());
+                }
+            }
+        }
+
+        // Read the catalog to tell which tracks are audio, until the announce ends.
+        if let Some(name) = self.priorities.catalog(&announce.namespace) {
+            if let Some(track) = tracks.subscribe(announce.namespace.clone(), &name) {
+                tasks.push(self.priorities.clone().record(track).boxed());
+            }
+        }
+
+        // Cancel the announce once its tokens no longer authorize it.
+        let authorizer = self.authorizer.clone();
+        let (namespace, tokens) = (
+            announce.namespace.clone(),
+            announce.authorization_tokens.clone(),
+        );
+        let mut expired = std::pin::pin!(async move {
+            match authorizer {
+                Some(authorizer) => authorizer.announce_expired(&namespace, &tokens).await,
+                None => std::future::pending().await,
+            }
+        });
+
         // Serve subscribe requests
         loop {
             tokio::select! {
                 // If the announce is closed, return the error
                 Err(err) = announce.closed() => return Err(err.into()),
 
+                // Another publisher took over the namespace, or it was revoked, cancel this announce
+                _ = &mut replaced => {
+                    log::info!("announce replaced or revoked: {}", announce.namespace);
+                    announce.close(ServeError::Cancel)?;
+                    return Ok(());
+                },
+
+                _ = &mut expired => {
+                    let namespace = announce.namespace.clone();
+                    announce.close(ServeError::AuthExpired)?;
+                    anyhow::bail!("authorization expired for announce {}", namespace);
+                },
+
+                // Re-advertise the namespace when requested by the admin API
+                res = async { reregister.as_mut().unwrap().changed().await }, if reregister.is_some() => {
+                    if res.is_err() {
+                        // The admin handle was dropped, no more requests will arrive
+                        reregister = None;
+                        continue;
+                    }
+
+                    log::info!("re-registering namespace: {}", announce.namespace);
+                    if let Err(err) = self.coordinator.refresh_namespace(&announce.namespace).await {
+                        log::warn!("failed to re-register namespace: {}, error: {}", announce.namespace, err);
+                    }
+                },
+
+                // Mark the namespace stale while the publisher sends nothing, until it resumes.
+                _ = async { liveness.as_mut().unwrap().2.tick().await }, if liveness.is_some() => {
+                    let (config, watched, _) = liveness.as_mut().unwrap();
+                    let Some(is_stale) = watched.check(Instant::now()) else {
+                        continue;
+                    };
+
+                    match is_stale {
+                        true => log::warn!("publisher went quiet, namespace is stale: {}", announce.namespace),
+                        false => log::info!("publisher resumed, namespace is live: {}", announce.namespace),
+                    }
+                    stale.send_replace(is_stale);
+
+                    // Dropping the feed's guard withdraws the announce from the destinations.
+                    if config.withdraw {
+                        _forwarded = match is_stale {
+                            true => None,
+                            false => self.forward.as_ref().map(|feed| feed.add_forwarded(announce.namespace.clone(), path.clone())),
+                        };
+                    }
+                },
+
                 // Wait for the next subscriber and serve the track.
                 Some(track) = request.next() => {
                     let mut subscriber = self.subscriber.clone();
 
-                    // Spawn a new task to handle the subscribe
-                    tasks.push(async move {
-                        let info = track.clone();
-                        log::info!("forwarding subscribe: {:?}", info);
+                    // Cache the track alongside the subscribers reading it.
+                    let recorded = tracks.get_track_reader(&track.namespace, &track.name);
+                    if let Some((cache, recorded)) = self.cache.clone().zip(recorded.clone()) {
+                        tasks.push(cache.record(recorded).boxed());
+                    }
+                    if let Some((archive, recorded)) = self.archive.clone().zip(recorded.clone()) {
+                        tasks.push(archive.record(recorded).boxed());
+                    }
 
-                        // Forward the subscribe request
-                        if let Err(err) = subscriber.subscribe(track).await {
-                            log::warn!("failed forwarding subscribe: {:?}, error: {}", info, err)
-                        }
+                    let name = FullTrackName {
+                        namespace: track.namespace.clone(),
+                        name: track.name.clone(),
+                    };
+                    if let Some(((_, watched, _), recorded)) = liveness.as_mut().zip(recorded) {
+                        watched.watch(name.clone(), recorded);
+                    }
+                    log::info!("forwarding subscribe: {:?}", track.info);
 
-                        Ok(())
-                    }.boxed());
+                    // Forward the subscribe request, until it ends or nobody is left reading it.
+                    let counted = self.locals.is_subscribed(&name);
+                    let subscribe = subscriber.subscribe_handle(track);
+                    forwards.push(forward(subscribe, self.locals.clone(), name, counted));
                 },
-                res = tasks.next(), if !tasks.is_empty() => res.unwrap()?,
+                Some((name, subscribe)) = forwards.next() => match subscribe {
+                    // Forget the idle track under the lock, so the next subscriber requests it again.
+                    Some(subscribe) => match self.locals.release(&name, || writer.remove(&name.namespace, &name.name)) {
+                        Some(_) => {
+                            log::info!("no subscribers left, unsubscribing: {:?}", subscribe.info);
+                            if let Some((_, watched, _)) = &mut liveness {
+                                watched.unwatch(&name);
+                            }
+                        },
+                        // Somebody subscribed in the meantime, keep forwarding.
+                        None => forwards.push(forward(subscribe, self.locals.clone(), name, true)),
+                    },
+                    // Forget the ended track, so the next subscriber requests it again.
+                    None => {
+                        writer.remove(&name.namespace, &name.name);
+                        if let Some((_, watched, _)) = &mut liveness {
+                            watched.unwatch(&name);
+                        }
+                    }
+                },
+                _ = tasks.next(), if !tasks.is_empty() => {},
                 else => return Ok(()),
             }
         }
     }
 }
+
+// Wait until the upstream subscription ends, or until it might be idle and is handed back.
+async fn forward(
+    subscribe: Subscribe,
+    locals: Locals,
+    name: FullTrackName,
+    counted: bool,
+) -> (FullTrackName, Option<Subscribe>) {
+    let idle = tokio::select! {
+        res = subscribe.closed() => {
+            if let Err(err) = res {
+                log::warn!("failed forwarding subscribe: {:?}, error: {}", subscribe.info, err);
+            }
+            false
+        },
+        _ = locals.idle(&name), if counted => true,
+    };
+
+    (name, idle.then_some(subscribe))
+}
@@ -13,6 +13,9 @@ pub enum CoordinatorError {
     #[error("namespace already registered")]
     NamespaceAlreadyRegistered,
 
+    #[error("coordinator timed out")]
+    Timeout,
+
     #[error("Internal Error: {0}")]
     Other(anyhow::Error),
 }
@@ -44,7 +47,7 @@ pub type CoordinatorResult<T> = std::result::Result<T, CoordinatorError>;
 /// or the namespace is no longer served, cleanup happens automatically.
 pub struct NamespaceRegistration {
     _inner: Box<dyn Send + Sync>,
-    _metadata: Option<Vec<(String, String)>>,
+    metadata: Option<Vec<(String, String)>>,
 }
 
 impl NamespaceRegistration {
@@ -55,15 +58,20 @@ impl NamespaceRegistration {
     pub fn new<T: Send + Sync + 'static>(inner: T) -> Self {
         Self {
             _inner: Box::new(inner),
-            _metadata: None,
+            metadata: None,
         }
     }
 
     /// Add metadata as list of key value pair of string: string
     pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
-        self._metadata = Some(metadata);
+        self.metadata = Some(metadata);
         self
     }
+
+    /// The metadata advertised with the namespace, returned to other relays by [Coordinator::lookup].
+    pub fn metadata(&self) -> Option<&[(String, String)]> {
+        self.metadata.as_deref()
+    }
 }
 
 /// Result of a namespace lookup.
@@ -79,7 +87,8 @@ pub struct NamespaceOrigin {
     /// The socket address of the relay if the relay is not approachable
     /// via DNS lookup, This is to bypass DNS lookups.
     socket_addr: Option<SocketAddr>,
-    /// Additional metadata associated with this namespace
+    /// Additional metadata associated with this namespace, as registered by the origin,
+    /// ex. its region, capacity or auth domain. Consumed by [crate::RoutingPolicy].
     metadata: Option<Vec<(String, String)>>,
 }
 
@@ -120,6 +129,15 @@ impl NamespaceOrigin {
     pub fn metadata(&self) -> Option<Vec<(String, String)>> {
         self.metadata.clone()
     }
+
+    /// Get a single metadata value, ex. the origin's `region`.
+    pub fn metadata_value(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .as_ref()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
 }
 
 /// Coordinator handles namespace registration/discovery across relays.
@@ -146,6 +164,10 @@ pub trait Coordinator: Send + Sync {
     /// 3. Start any refresh/heartbeat tasks
     /// 4. Return a handle that unregisters on drop
     ///
+    /// Any metadata describing this relay, ex. its region or capacity, should be stored with
+    /// the namespace so [Self::lookup] can return it, and attached with
+    /// [NamespaceRegistration::with_metadata].
+    ///
     /// # Arguments
     ///
     /// * `namespace` - The namespace being registered
@@ -159,6 +181,27 @@ pub trait Coordinator: Send + Sync {
         namespace: &TrackNamespace,
     ) -> CoordinatorResult<NamespaceRegistration>;
 
+    /// Register several namespaces at once, returning a result for each, in the same order.
+    ///
+    /// Called with the announces a session queued up while its previous batch was registered,
+    /// see [crate::AnnounceBatcher]. Coordinators that can register many namespaces in one
+    /// round trip should override this; the default registers them all concurrently.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespaces` - The namespaces being registered
+    async fn register_namespaces(
+        &self,
+        namespaces: &[TrackNamespace],
+    ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+        futures::future::join_all(
+            namespaces
+                .iter()
+                .map(|namespace| self.register_namespace(namespace)),
+        )
+        .await
+    }
+
     /// Unregister a namespace.
     ///
     /// Called when a publisher sends PUBLISH_NAMESPACE_DONE.
@@ -170,6 +213,38 @@ pub trait Coordinator: Send + Sync {
     /// * `namespace` - The namespace to unregister
     async fn unregister_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()>;
 
+    /// Re-advertise a namespace that is already registered.
+    ///
+    /// Called when an operator triggers re-registration, for example after the
+    /// external registry lost its state. The existing registration handle stays valid.
+    /// Coordinators without external state can rely on the default, which does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace to re-advertise
+    async fn refresh_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        Ok(())
+    }
+
+    /// Mark a registered namespace stale, or live again, see [crate::LivenessConfig].
+    ///
+    /// Called when the publisher of a namespace keeps its session open but stops sending objects,
+    /// and again once it resumes. The existing registration handle stays valid. Coordinators
+    /// should stop routing other relays to a stale namespace, or let them know it's stale through
+    /// its metadata. Coordinators without external state can rely on the default, which does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace whose publisher went quiet, or resumed
+    /// * `stale` - Whether the namespace is stale now
+    async fn set_namespace_stale(
+        &self,
+        _namespace: &TrackNamespace,
+        _stale: bool,
+    ) -> CoordinatorResult<()> {
+        Ok(())
+    }
+
     /// Lookup where a namespace is served from.
     ///
     /// Called when a subscriber requests a namespace.
@@ -184,13 +259,22 @@ pub trait Coordinator: Send + Sync {
     ///
     /// # Returns
     ///
-    /// - `Ok(NamespaceOrigin, Option<quic::Client>)` - Namespace origin and optional client if available
+    /// - `Ok(NamespaceOrigin, Option<quic::Client>)` - Namespace origin, with the metadata it
+    ///   was registered with, and optional client if available
     /// - `Err` - Namespace not found anywhere
     async fn lookup(
         &self,
         namespace: &TrackNamespace,
     ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)>;
 
+    /// Check that the coordinator answers, ex. that its external registry is reachable.
+    ///
+    /// Called by readiness checks, so a relay that can't register or look up namespaces
+    /// doesn't get traffic. Coordinators without external state can rely on the default.
+    async fn health(&self) -> CoordinatorResult<()> {
+        Ok(())
+    }
+
     /// Graceful shutdown of the coordinator.
     ///
     /// Called when the relay is shutting down. Implementations should:
@@ -201,3 +285,49 @@ pub trait Coordinator: Send + Sync {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// Takes a while to register each namespace.
+    struct Slow;
+
+    #[async_trait]
+    impl Coordinator for Slow {
+        async fn register_namespace(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            Ok(NamespaceRegistration::new(()))
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            Err(CoordinatorError::NamespaceNotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn registers_batches_concurrently() {
+        let namespaces: Vec<_> = (0..10)
+            .map(|i| TrackNamespace::from_utf8_path(&i.to_string()))
+            .collect();
+
+        let start = Instant::now();
+        let results = Slow.register_namespaces(&namespaces).await;
+        assert_eq!(results.len(), 10);
+        assert!(results.iter().all(Result::is_ok));
+
+        // One at a time would take a second.
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
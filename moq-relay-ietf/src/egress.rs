@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use crate::Tenant;
+
+/// How fast the relay sends to each downstream session, so one subscriber on a fast link can't
+/// take all of the relay's bandwidth. See [moq_transport::session::Publisher::set_egress_bitrate].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EgressConfig {
+    /// The bitrate of each session, in bits per second. Unlimited if unset.
+    pub bitrate: Option<u64>,
+
+    /// The bitrate of the sessions of each tenant, keyed by tenant ID, replacing [Self::bitrate].
+    pub tenants: BTreeMap<String, u64>,
+}
+
+impl EgressConfig {
+    /// The bitrate of a session of `tenant`, or of a session without one.
+    pub fn bitrate(&self, tenant: Option<&Tenant>) -> Option<u64> {
+        tenant
+            .and_then(|tenant| self.tenants.get(&tenant.id).copied())
+            .or(self.bitrate)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TenantIsolation;
+
+    #[test]
+    fn tenants() {
+        let egress = EgressConfig {
+            bitrate: Some(10_000_000),
+            tenants: BTreeMap::from([("a".to_string(), 50_000_000)]),
+        };
+        let tenant = |id| Tenant::new(id, TenantIsolation::Scoped);
+
+        assert_eq!(egress.bitrate(None), Some(10_000_000));
+        assert_eq!(egress.bitrate(Some(&tenant("a"))), Some(50_000_000));
+        assert_eq!(egress.bitrate(Some(&tenant("b"))), Some(10_000_000));
+        assert_eq!(EgressConfig::default().bitrate(Some(&tenant("a"))), None);
+    }
+}
@@ -0,0 +1,1246 @@
+use std::{collections::BTreeMap, net, path::Path, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use clap::Parser;
+use moq_native_ietf::tls;
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    session::ObjectLimits,
+};
+use serde::{Deserialize, Deserializer};
+use url::Url;
+
+use crate::{
+    AdminConfig, AnnounceLimits, ArchiveConfig, Authorizer, CacheConfig, CaptureConfig,
+    Coordinator, CoordinatorTimeouts, EgressConfig, Flags, ForwardDestination, GossipConfig,
+    LivenessConfig, LookupCacheConfig, MetadataPolicy, PerfConfig, PinnedTracks, PreviewConfig,
+    Priorities, Quotas, Reauthorize, ReconnectPolicy, RelayConfig, RoutingPolicy,
+    ServerNameTenants, StaticTokenAuthorizer, TenantResolver, Validators, WebConfig,
+};
+
+/// Every setting of the relay binary, parsed from its command-line flags or from a TOML file.
+///
+/// In a file, the flags are grouped into tables, ex. `admin_token` becomes `token` under
+/// `[admin]`. Missing fields take the same defaults as the flags, and unknown fields are ignored
+/// with a warning.
+///
+/// Environment variables override the file: `MOQ_RELAY_` followed by the path of the field in
+/// upper case, with tables separated by `__`, ex. `MOQ_RELAY_ADMIN__TOKEN` or
+/// `MOQ_RELAY_TRANSPORT__EMULATION__DELAY`. Values are parsed as TOML, so lists are written
+/// like `["[::]:443", "[::]:4443"]`, and anything else is taken as a string.
+#[derive(Parser, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RelayFileConfig {
+    /// Listen on this address. Repeat to listen on several addresses or ports;
+    /// the first one is advertised and used for the development web server.
+    #[arg(long, default_value = "[::]:443")]
+    pub bind: Vec<net::SocketAddr>,
+
+    /// The hostname that we advertise to other origins.
+    /// The provided certificate must be valid for this address.
+    #[arg(long)]
+    pub node: Option<Url>,
+
+    /// The URL of the moq-api server in order to run a cluster.
+    /// Must be used in conjunction with --node to advertise the origin
+    #[arg(long)]
+    pub api: Option<Url>,
+
+    /// Enable development mode.
+    /// This hosts a HTTPS web server via TCP to serve the fingerprint of the certificate,
+    /// on the same address and port as QUIC with the same certificate.
+    #[arg(long)]
+    pub dev: bool,
+
+    /// Accept 0-RTT data from clients resuming a TLS session, and send it when reconnecting upstream.
+    /// Early data can be replayed, including authorization tokens in CLIENT_SETUP.
+    #[arg(long)]
+    pub zero_rtt: bool,
+
+    /// The TLS configuration.
+    #[command(flatten)]
+    pub tls: moq_config::Tls,
+
+    /// The QUIC transport tuning.
+    #[command(flatten)]
+    pub transport: moq_config::Transport,
+
+    /// Where to write qlog and mlog files, and how to rotate them.
+    #[command(flatten)]
+    pub logs: moq_config::Logs,
+
+    /// Only write a session's mlog to --mlog-dir while it shows the symptoms configured in this
+    /// JSON file, ex. `{"triggers": {"stream_resets": 20, "loss_percent": 10}, "duration_secs": 60}`.
+    /// Captures are listed by the admin API.
+    #[arg(long, requires = "mlog_dir")]
+    pub mlog_capture: Option<PathBuf>,
+
+    /// Where announces are forwarded.
+    #[command(flatten)]
+    pub announce: AnnounceFileConfig,
+
+    /// Limits on announces, and what to do with duplicates.
+    #[command(flatten)]
+    pub namespaces: moq_config::NamespacePolicy,
+
+    /// How namespaces are registered with, and looked up from, other relays.
+    #[command(flatten)]
+    pub coordinator: CoordinatorFileConfig,
+
+    /// Which origins subscriptions are fetched from.
+    #[command(flatten)]
+    pub routing: RoutingFileConfig,
+
+    /// Limits for objects received from publishers and origins.
+    #[command(flatten)]
+    pub objects: ObjectsFileConfig,
+
+    /// Caching recent groups to serve FETCH.
+    #[command(flatten)]
+    pub cache: CacheFileConfig,
+
+    /// Archiving the groups of selected namespaces to disk, to serve FETCH for longer.
+    #[command(flatten)]
+    pub archive: ArchiveFileConfig,
+
+    /// Sampling selected tracks into previews, served by the web server.
+    #[command(flatten)]
+    pub preview: PreviewFileConfig,
+
+    /// The built-in namespace for measuring links with moq-perf.
+    #[command(flatten)]
+    pub perf: PerfFileConfig,
+
+    /// Limiting what each session is sent.
+    #[command(flatten)]
+    pub egress: EgressFileConfig,
+
+    /// The tracks kept subscribed upstream.
+    #[command(flatten)]
+    pub pin: PinFileConfig,
+
+    /// The authorization tokens required and presented upstream.
+    #[command(flatten)]
+    pub auth: moq_config::Auth,
+
+    /// Load experiment flags from this JSON file, ex. `{"buffered_delivery": {"percent": 10}}`.
+    /// Flags can also be changed at runtime through the admin API.
+    #[arg(long)]
+    pub flags: Option<PathBuf>,
+
+    /// Load per-namespace subscription quotas from this JSON file,
+    /// ex. `{"tenant": {"max_subscribers": 100, "max_egress_mbps": 500}}`.
+    /// Subscriptions beyond a quota are rejected; usage is reported by the admin API.
+    #[arg(long)]
+    pub quotas: Option<PathBuf>,
+
+    /// Send audio before video in each namespace by the policies in this JSON file,
+    /// ex. `{"live": {"audio_tracks": ["audio*"], "catalog": ".catalog"}}`.
+    /// Audio is classified by track name, or by the namespace's catalog.
+    #[arg(long)]
+    pub priorities: Option<PathBuf>,
+
+    /// Check the objects published to each namespace by the policies in this JSON file,
+    /// ex. `{"live": {"tracks": ["*.json"], "schema": {"type": "object"}, "sample_rate": 0.1}}`.
+    /// Invalid objects are dropped before reaching subscribers; failures are reported by the admin API.
+    #[arg(long)]
+    pub validators: Option<PathBuf>,
+
+    /// Isolate tenants by the TLS server name sessions connect to, loaded from this JSON file,
+    /// ex. `{"tenants": {"a.example.com": "a"}, "unscoped": ["relay.example.com"]}`.
+    /// Sessions to other server names are rejected. Every session may use every namespace if unset.
+    #[arg(long)]
+    pub tenants: Option<PathBuf>,
+
+    /// The web server, for development and health checks.
+    #[command(flatten)]
+    pub web: WebFileConfig,
+
+    /// The JSON admin API.
+    #[command(flatten)]
+    pub admin: AdminFileConfig,
+
+    /// Handing sessions over to a new relay process on SIGUSR2.
+    #[command(flatten)]
+    pub handover: HandoverFileConfig,
+}
+
+impl Default for RelayFileConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec!["[::]:443".parse().unwrap()],
+            node: None,
+            api: None,
+            dev: false,
+            zero_rtt: false,
+            tls: Default::default(),
+            transport: Default::default(),
+            logs: Default::default(),
+            mlog_capture: None,
+            announce: Default::default(),
+            namespaces: Default::default(),
+            coordinator: Default::default(),
+            routing: Default::default(),
+            objects: Default::default(),
+            cache: Default::default(),
+            archive: Default::default(),
+            preview: Default::default(),
+            perf: Default::default(),
+            egress: Default::default(),
+            pin: Default::default(),
+            auth: Default::default(),
+            flags: None,
+            quotas: None,
+            priorities: None,
+            validators: None,
+            tenants: None,
+            web: Default::default(),
+            admin: Default::default(),
+            handover: Default::default(),
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AnnounceFileConfig {
+    /// Forward all announces to the provided server for authentication/routing.
+    /// Repeat to forward to several servers, each over its own connection. Only namespaces under
+    /// the comma-separated prefixes in the URL fragment are forwarded, ex. `https://analytics.example.com#live,vod`.
+    /// If not provided, the relay accepts every unique announce.
+    #[arg(id = "announce", long = "announce")]
+    pub forward: Vec<ForwardDestination>,
+
+    /// Keep forwarded namespaces announced for this many seconds after their last publisher left,
+    /// so publishers reconnecting in the meantime aren't seen leaving by the --announce servers.
+    #[arg(id = "announce_linger", long = "announce-linger", default_value = "5")]
+    pub linger: u64,
+
+    /// Gossip announces with this relay, ex. `https://relay2.example.com`: ours and those gossiped
+    /// to us are announced to it, so a small cluster can run without a coordinator. Each peer must
+    /// list the others by the URL they give as --node. Requires --node. May be repeated.
+    #[arg(id = "gossip_peer", long = "gossip-peer")]
+    pub gossip: Vec<Url>,
+
+    /// Don't pass on gossiped announces that went through this many relays, counting their origin.
+    #[arg(id = "gossip_max_hops", long = "gossip-max-hops", default_value = "4")]
+    pub gossip_max_hops: usize,
+
+    /// Milliseconds to wait before reconnecting to an --announce server or gossip peer after
+    /// the first failure. The delay grows with each failure in a row.
+    #[arg(
+        id = "announce_reconnect_min",
+        long = "announce-reconnect-min",
+        default_value = "100"
+    )]
+    pub reconnect_min: u64,
+
+    /// Milliseconds the reconnect delay grows to at most.
+    #[arg(
+        id = "announce_reconnect_max",
+        long = "announce-reconnect-max",
+        default_value = "5000"
+    )]
+    pub reconnect_max: u64,
+
+    /// How much the reconnect delay is multiplied by after each failure.
+    #[arg(
+        id = "announce_reconnect_multiplier",
+        long = "announce-reconnect-multiplier",
+        default_value = "2"
+    )]
+    pub reconnect_multiplier: f64,
+
+    /// Randomize each reconnect delay by up to this share either way, ex. 0.1 for 10%.
+    #[arg(
+        id = "announce_reconnect_jitter",
+        long = "announce-reconnect-jitter",
+        default_value = "0.1"
+    )]
+    pub reconnect_jitter: f64,
+}
+
+impl AnnounceFileConfig {
+    fn reconnect(&self) -> ReconnectPolicy {
+        ReconnectPolicy {
+            min_delay: Duration::from_millis(self.reconnect_min),
+            max_delay: Duration::from_millis(self.reconnect_max),
+            multiplier: self.reconnect_multiplier,
+            jitter: self.reconnect_jitter,
+        }
+    }
+}
+
+impl Default for AnnounceFileConfig {
+    fn default() -> Self {
+        Self {
+            forward: Vec::new(),
+            linger: 5,
+            gossip: Vec::new(),
+            gossip_max_hops: 4,
+            reconnect_min: 100,
+            reconnect_max: 5000,
+            reconnect_multiplier: 2.0,
+            reconnect_jitter: 0.1,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CoordinatorFileConfig {
+    /// Path to the shared coordinator file for multi-relay coordination.
+    /// Multiple relay instances can share namespace/track registration via this file.
+    /// User doesn't have to explicitly create and populate anything. This path will be
+    /// used by file coordinator to store namespace/track registration information.
+    /// User need to make sure if multiple relay's are being used all of them have same path
+    /// to this file.
+    #[arg(
+        id = "coordinator_file",
+        long = "coordinator-file",
+        default_value = "/tmp/moq-coordinator.json"
+    )]
+    pub file: PathBuf,
+
+    /// URL of the moq-api server for coordination (e.g., "http://localhost:8080").
+    /// When specified, uses moq-api HTTP server instead of file-based coordination.
+    /// This is useful when running a cluster of relays with a centralized API server.
+    #[arg(long)]
+    pub api_url: Option<Url>,
+
+    /// URLs of moq-api servers to shard namespaces across, instead of a single --api-url.
+    /// Each namespace is registered with one of them, picked by hashing its leading fields,
+    /// so every relay must list the same servers. May be repeated.
+    #[arg(id = "coordinator_shard", long = "coordinator-shard")]
+    pub shards: Vec<Url>,
+
+    /// How many leading fields of a namespace pick its shard. Namespaces sharing them are
+    /// registered on the same shard, so it must not exceed the length of announced namespaces.
+    #[arg(
+        id = "coordinator_shard_depth",
+        long = "coordinator-shard-depth",
+        default_value = "1"
+    )]
+    pub shard_depth: usize,
+
+    /// TTL in seconds for namespace registrations in the API.
+    /// Only used when --api-url, --coordinator-shard or --registry-bind is specified.
+    #[arg(long, default_value = "600")]
+    pub api_ttl: u64,
+
+    /// Act as the coordination server for a small cluster: serve the moq-api HTTP interface
+    /// from memory on this address, e.g. 0.0.0.0:8080, and point other relays' --api-url at it.
+    /// Unless --api-url is also given, this relay registers with it too.
+    #[arg(long)]
+    pub registry_bind: Option<net::SocketAddr>,
+
+    /// Metadata registered with each namespace as KEY=VALUE, ex. region=eu-west or capacity=100.
+    /// Other relays can route on it with --route-require and --route-minimum. May be repeated.
+    #[arg(id = "origin_metadata", long = "origin-metadata", value_parser = parse_key_value::<String>)]
+    #[serde(deserialize_with = "key_values")]
+    pub metadata: Vec<(String, String)>,
+
+    /// Milliseconds to wait for the coordinator to register a namespace.
+    /// Slower registrations finish in the background while the announce is served locally.
+    #[arg(
+        id = "coordinator_register_timeout",
+        long = "coordinator-register-timeout",
+        default_value = "5000"
+    )]
+    pub register_timeout: u64,
+
+    /// Milliseconds to wait for the coordinator to look up a namespace.
+    /// Slower lookups fail the subscription with a retryable TIMEOUT error.
+    #[arg(
+        id = "coordinator_lookup_timeout",
+        long = "coordinator-lookup-timeout",
+        default_value = "2000"
+    )]
+    pub lookup_timeout: u64,
+
+    /// Milliseconds to reuse the origin the coordinator found for a namespace, rather than
+    /// looking it up for every subscription. Zero disables the lookup cache.
+    #[arg(
+        id = "coordinator_lookup_ttl",
+        long = "coordinator-lookup-ttl",
+        default_value = "0"
+    )]
+    pub lookup_ttl: u64,
+
+    /// Milliseconds a namespace the coordinator doesn't know is reported missing without asking
+    /// again, when the lookup cache is enabled.
+    #[arg(
+        id = "coordinator_lookup_negative_ttl",
+        long = "coordinator-lookup-negative-ttl",
+        default_value = "1000"
+    )]
+    pub lookup_negative_ttl: u64,
+}
+
+impl Default for CoordinatorFileConfig {
+    fn default() -> Self {
+        Self {
+            file: "/tmp/moq-coordinator.json".into(),
+            api_url: None,
+            shards: Vec::new(),
+            shard_depth: 1,
+            api_ttl: 600,
+            registry_bind: None,
+            metadata: Vec::new(),
+            register_timeout: 5000,
+            lookup_timeout: 2000,
+            lookup_ttl: 0,
+            lookup_negative_ttl: 1000,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RoutingFileConfig {
+    /// Only fetch from origins whose metadata has KEY=VALUE, ex. region=eu-west. May be repeated.
+    #[arg(id = "route_require", long = "route-require", value_parser = parse_key_value::<String>)]
+    #[serde(deserialize_with = "key_values")]
+    pub require: Vec<(String, String)>,
+
+    /// Only fetch from origins whose metadata KEY is a number of at least VALUE, ex. capacity=1.
+    /// May be repeated.
+    #[arg(id = "route_minimum", long = "route-minimum", value_parser = parse_key_value::<u64>)]
+    #[serde(deserialize_with = "key_values")]
+    pub minimum: Vec<(String, u64)>,
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ObjectsFileConfig {
+    /// Maximum size in bytes of an object received from a publisher or origin.
+    /// Larger objects are rejected before their payload is buffered.
+    #[arg(id = "max_object_size", long = "max-object-size")]
+    pub max_size: Option<usize>,
+
+    /// Maximum number of bytes of an object to buffer ahead of the fastest subscriber.
+    /// When exceeded, the relay stops reading from the publisher's stream until subscribers catch up.
+    #[arg(id = "max_object_buffer", long = "max-object-buffer")]
+    pub max_buffer: Option<usize>,
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CacheFileConfig {
+    /// Cache up to this many bytes of recent groups in memory, to serve FETCH requests.
+    /// The cache is disabled unless set.
+    #[arg(id = "cache_memory", long = "cache-memory")]
+    pub memory: Option<u64>,
+
+    /// Spill groups evicted from the memory cache to this directory. Emptied on startup.
+    #[arg(id = "cache_dir", long = "cache-dir", requires = "cache_memory")]
+    pub dir: Option<PathBuf>,
+
+    /// Maximum number of bytes of groups kept in --cache-dir.
+    #[arg(
+        id = "cache_disk",
+        long = "cache-disk",
+        default_value = "1073741824",
+        requires = "cache_dir"
+    )]
+    pub disk: u64,
+
+    /// Send each session's FETCH responses at most at this bitrate, in bits per second, so
+    /// replays leave room for live subgroups. Unlimited unless set.
+    #[arg(id = "fetch_bitrate", long = "fetch-bitrate")]
+    pub fetch_bitrate: Option<u64>,
+}
+
+impl Default for CacheFileConfig {
+    fn default() -> Self {
+        Self {
+            memory: None,
+            dir: None,
+            disk: 1 << 30,
+            fetch_bitrate: None,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct EgressFileConfig {
+    /// Send each session at most this many bits per second, dropping the least important
+    /// subgroups when a subscriber wants more. Unlimited unless set.
+    #[arg(id = "egress_bitrate", long = "egress-bitrate")]
+    pub bitrate: Option<u64>,
+
+    /// The bitrate of a tenant's sessions as TENANT=BITRATE, ex. a=50000000, replacing
+    /// --egress-bitrate. May be repeated.
+    #[arg(id = "egress_tenant", long = "egress-tenant", value_parser = parse_key_value::<u64>)]
+    #[serde(deserialize_with = "key_values")]
+    pub tenants: Vec<(String, u64)>,
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PinFileConfig {
+    /// Subscribe to tracks of an origin's namespace at startup as NAMESPACE=TRACK[,TRACK...],
+    /// ex. live/main=video,audio, and keep them subscribed, so the first viewer doesn't wait on
+    /// the origin. Resubscribed like --announce servers are reconnected. May be repeated.
+    #[arg(id = "pin_track", long = "pin-track", value_parser = parse_key_value::<String>)]
+    #[serde(deserialize_with = "key_values")]
+    pub tracks: Vec<(String, String)>,
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ArchiveFileConfig {
+    /// Keep the groups of the --archive-namespace prefixes in this directory, so they can be
+    /// fetched once they've left the cache, or after a restart. Archiving is disabled unless set.
+    #[arg(
+        id = "archive_dir",
+        long = "archive-dir",
+        requires = "archive_namespaces"
+    )]
+    pub dir: Option<PathBuf>,
+
+    /// Archive the tracks of namespaces starting with this prefix, ex. `live/sports`.
+    /// Repeat to archive several prefixes.
+    #[arg(
+        id = "archive_namespaces",
+        long = "archive-namespace",
+        requires = "archive_dir"
+    )]
+    pub namespaces: Vec<String>,
+
+    /// Delete archived groups after this many seconds.
+    #[arg(
+        id = "archive_max_age",
+        long = "archive-max-age",
+        default_value = "600"
+    )]
+    pub max_age: u64,
+
+    /// Maximum number of bytes of groups kept in --archive-dir.
+    #[arg(
+        id = "archive_max_bytes",
+        long = "archive-max-bytes",
+        default_value = "10737418240"
+    )]
+    pub max_bytes: u64,
+}
+
+impl Default for ArchiveFileConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            namespaces: Vec::new(),
+            max_age: 600,
+            max_bytes: 10 << 30,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PreviewFileConfig {
+    /// Sample the --preview-track tracks of namespaces starting with this prefix, ex. `live`,
+    /// and serve the latest sample at /preview/<namespace>/<track>. Repeat for several prefixes.
+    /// Requires --dev or --health to enable the web server.
+    #[arg(id = "preview_namespaces", long = "preview-namespace")]
+    pub namespaces: Vec<String>,
+
+    /// The name of the tracks sampled in each previewed namespace. Repeat for several tracks.
+    #[arg(id = "preview_tracks", long = "preview-track", default_value = "video")]
+    pub tracks: Vec<String>,
+
+    /// Sample each track at most once every this many seconds.
+    #[arg(
+        id = "preview_interval",
+        long = "preview-interval",
+        default_value = "5"
+    )]
+    pub interval: u64,
+}
+
+impl Default for PreviewFileConfig {
+    fn default() -> Self {
+        Self {
+            namespaces: Vec::new(),
+            tracks: vec!["video".to_string()],
+            interval: 5,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct PerfFileConfig {
+    /// Serve this namespace, ex. `perf`, sending test data to moq-perf clients and reporting on
+    /// the test data they upload, to measure throughput, loss and latency through the relay.
+    #[arg(id = "perf_namespace", long = "perf-namespace")]
+    pub namespace: Option<String>,
+
+    /// Send test data at most at this bitrate, in bits per second.
+    #[arg(
+        id = "perf_max_bitrate",
+        long = "perf-max-bitrate",
+        default_value = "100000000"
+    )]
+    pub max_bitrate: u64,
+}
+
+impl Default for PerfFileConfig {
+    fn default() -> Self {
+        Self {
+            namespace: None,
+            max_bitrate: 100_000_000,
+        }
+    }
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct WebFileConfig {
+    /// Serve /healthz and /readyz over HTTPS on the first --bind address, for load balancers and
+    /// orchestrators. Runs the web server even without --dev.
+    #[arg(long)]
+    pub health: bool,
+
+    /// Serve qlog files over HTTPS at /qlog/:cid
+    /// Requires --dev or --health to enable the web server. An index at /qlog/ also requires --log-token.
+    #[arg(long)]
+    pub qlog_serve: bool,
+
+    /// Serve mlog files over HTTPS at /mlog/:cid
+    /// Requires --dev or --health to enable the web server. The /mlog/ index and tail also require --log-token.
+    #[arg(long)]
+    pub mlog_serve: bool,
+
+    /// Bearer token for the /qlog/ and /mlog/ index and the /mlog/:cid/tail live stream.
+    /// These endpoints are only enabled when a token is provided. The index can be searched
+    /// with `since` and `until`, in seconds since the Unix epoch, and a `namespace` prefix.
+    #[arg(long)]
+    pub log_token: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AdminFileConfig {
+    /// Serve the JSON admin API over plain HTTP on this address, e.g. 127.0.0.1:8080.
+    /// Lists sessions and namespaces, closes sessions, revokes namespaces, and triggers coordinator
+    /// re-registration. See moq-relayctl.
+    #[arg(id = "admin_bind", long = "admin-bind")]
+    pub bind: Option<net::SocketAddr>,
+
+    /// Bearer token required by the admin API, unless it binds to a loopback address.
+    #[arg(id = "admin_token", long = "admin-token")]
+    pub token: Option<String>,
+}
+
+#[derive(Parser, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct HandoverFileConfig {
+    /// On SIGUSR2, give up on the new relay process if it isn't accepting after this many seconds.
+    /// The new process is started from the same executable and arguments, taking over our sockets.
+    #[arg(
+        id = "handover_ready_timeout",
+        long = "handover-ready-timeout",
+        default_value = "10"
+    )]
+    pub ready_timeout: u64,
+
+    /// After handing over, wait this many seconds for sessions to reconnect after GOAWAY before exiting.
+    #[arg(
+        id = "handover_drain_timeout",
+        long = "handover-drain-timeout",
+        default_value = "30"
+    )]
+    pub drain_timeout: u64,
+}
+
+impl Default for HandoverFileConfig {
+    fn default() -> Self {
+        Self {
+            ready_timeout: 10,
+            drain_timeout: 30,
+        }
+    }
+}
+
+impl RelayFileConfig {
+    // The prefix of the environment variables overriding the file.
+    const ENV_PREFIX: &'static str = "MOQ_RELAY_";
+
+    /// Load the TOML file at `path`, overridden by the `MOQ_RELAY_` environment variables.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let toml = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config: {}", path.display()))?;
+        Self::parse_toml(&toml, std::env::vars())
+            .with_context(|| format!("invalid config: {}", path.display()))
+    }
+
+    /// Parse a TOML document, overridden by the `MOQ_RELAY_` variables among `env`.
+    pub fn parse_toml(
+        toml: &str,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> anyhow::Result<Self> {
+        let mut table: toml::Table = toml.parse()?;
+
+        // Overrides that parsed as something else, retried as strings if that's what the field is.
+        let mut retry = BTreeMap::new();
+
+        for (key, value) in env {
+            let Some(path) = key.strip_prefix(Self::ENV_PREFIX) else {
+                continue;
+            };
+            let path: Vec<String> = path.split("__").map(str::to_lowercase).collect();
+            let parsed = parse_env_value(&value);
+            if !parsed.is_str() {
+                retry.insert(path.join("."), (path.clone(), value));
+            }
+            set(&mut table, &path, parsed).with_context(|| format!("invalid {}", key))?;
+        }
+
+        loop {
+            let mut ignored = Vec::new();
+            let mut track = |path: serde_ignored::Path| ignored.push(path.to_string());
+            let deserializer =
+                serde_ignored::Deserializer::new(toml::Value::Table(table.clone()), &mut track);
+
+            match serde_path_to_error::deserialize::<_, Self>(deserializer) {
+                Ok(config) => {
+                    for field in ignored {
+                        log::warn!("ignoring unknown config field: {}", field);
+                    }
+                    config.validate()?;
+                    return Ok(config);
+                }
+                Err(err) => {
+                    let field = err.path().to_string();
+                    match retry.remove(&field) {
+                        Some((path, value)) => set(&mut table, &path, toml::Value::String(value))?,
+                        None => anyhow::bail!("{}: {}", field, err.into_inner().message()),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check the settings that depend on each other, naming the offending field.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            !self.bind.is_empty(),
+            "bind: at least one address is required"
+        );
+        anyhow::ensure!(
+            self.tls.cert.len() == self.tls.key.len(),
+            "tls.key: expected one key for each of the {} certificates",
+            self.tls.cert.len()
+        );
+        anyhow::ensure!(
+            !self.tls.cert.is_empty(),
+            "tls.cert: missing TLS certificates"
+        );
+
+        // Emulating a slow network is only meant for testing clients against a local relay.
+        anyhow::ensure!(
+            !self.transport.emulation.is_enabled() || self.dev,
+            "transport.emulation: network emulation requires dev"
+        );
+
+        anyhow::ensure!(
+            self.mlog_capture.is_none() || self.logs.mlog_dir.is_some(),
+            "mlog_capture: capturing mlog requires logs.mlog_dir"
+        );
+        for value in &self.logs.mlog_sample_namespace {
+            moq_config::parse_sample_namespace(value)
+                .map_err(|err| anyhow::anyhow!("logs.mlog_sample_namespace: {}", err))?;
+        }
+        // Checked objects are buffered whole, so their size must be bounded.
+        anyhow::ensure!(
+            self.validators.is_none() || self.objects.max_size.is_some(),
+            "objects.max_size: checking objects with validators requires a maximum object size"
+        );
+        anyhow::ensure!(
+            self.cache.dir.is_none() || self.cache.memory.is_some(),
+            "cache.dir: spilling to disk requires cache.memory"
+        );
+        anyhow::ensure!(
+            self.cache.fetch_bitrate != Some(0),
+            "cache.fetch_bitrate: must be positive"
+        );
+        anyhow::ensure!(
+            self.egress.bitrate != Some(0),
+            "egress.bitrate: must be positive"
+        );
+        for (tenant, bitrate) in &self.egress.tenants {
+            anyhow::ensure!(*bitrate > 0, "egress.tenants.{}: must be positive", tenant);
+        }
+        for (namespace, names) in &self.pin.tracks {
+            anyhow::ensure!(
+                names.split(',').all(|name| !name.is_empty()),
+                "pin.tracks.{}: track names must not be empty",
+                namespace
+            );
+        }
+        anyhow::ensure!(
+            self.archive.dir.is_none() || !self.archive.namespaces.is_empty(),
+            "archive.namespaces: archiving requires at least one namespace prefix"
+        );
+        anyhow::ensure!(
+            self.preview.namespaces.is_empty() || self.dev || self.web.health,
+            "preview.namespaces: serving previews requires dev or web.health"
+        );
+        anyhow::ensure!(
+            self.announce.gossip.is_empty() || self.node.is_some(),
+            "announce.gossip: gossiping requires node"
+        );
+        anyhow::ensure!(
+            self.announce.reconnect_max >= self.announce.reconnect_min,
+            "announce.reconnect_max: shorter than announce.reconnect_min"
+        );
+        anyhow::ensure!(
+            self.announce.reconnect_multiplier >= 1.0,
+            "announce.reconnect_multiplier: must be at least 1"
+        );
+        anyhow::ensure!(
+            (0.0..1.0).contains(&self.announce.reconnect_jitter),
+            "announce.reconnect_jitter: must be at least 0 and less than 1"
+        );
+        anyhow::ensure!(
+            self.coordinator.shards.is_empty() || self.coordinator.api_url.is_none(),
+            "coordinator.shards: sharding replaces coordinator.api_url"
+        );
+        anyhow::ensure!(
+            self.coordinator.shard_depth > 0,
+            "coordinator.shard_depth: at least one field is required"
+        );
+        // Anyone who can reach the admin API can close sessions and revoke namespaces.
+        anyhow::ensure!(
+            self.admin.token.is_some()
+                || self.admin.bind.is_none_or(|bind| bind.ip().is_loopback()),
+            "admin.token: required unless admin.bind is a loopback address"
+        );
+
+        Ok(())
+    }
+
+    /// The relay configuration, loading the flags, quotas, capture triggers and tenants files.
+    ///
+    /// The relay listens on `bind`; clear it and set `endpoints` to listen on sockets of its own.
+    pub fn relay(
+        &self,
+        tls: tls::Config,
+        coordinator: Arc<dyn Coordinator>,
+    ) -> anyhow::Result<RelayConfig> {
+        let routing: Option<Arc<dyn RoutingPolicy>> =
+            match self.routing.require.is_empty() && self.routing.minimum.is_empty() {
+                true => None,
+                false => {
+                    let mut policy = MetadataPolicy::new();
+                    for (key, value) in &self.routing.require {
+                        policy = policy.with_required(key, value);
+                    }
+                    for (key, min) in &self.routing.minimum {
+                        policy = policy.with_minimum(key, *min);
+                    }
+                    Some(Arc::new(policy))
+                }
+            };
+
+        let authorizer: Option<Arc<dyn Authorizer>> = match self.auth.tokens.is_empty() {
+            true => None,
+            false => Some(Arc::new(StaticTokenAuthorizer::new(
+                self.auth
+                    .tokens
+                    .iter()
+                    .map(|token| token.as_bytes().to_vec())
+                    .collect(),
+            ))),
+        };
+
+        let flags = match &self.flags {
+            Some(path) => Flags::load(path)?,
+            None => Flags::default(),
+        };
+
+        let quotas = match &self.quotas {
+            Some(path) => Quotas::load(path)?,
+            None => Quotas::default(),
+        };
+
+        let priorities = match &self.priorities {
+            Some(path) => Priorities::load(path)?,
+            None => Priorities::default(),
+        };
+
+        let validators = match &self.validators {
+            Some(path) => Validators::load(path)?,
+            None => Validators::default(),
+        };
+
+        let capture = match &self.mlog_capture {
+            Some(path) => Some(CaptureConfig::load(path)?),
+            None => None,
+        };
+
+        let tenants = match &self.tenants {
+            Some(path) => Some(Arc::new(ServerNameTenants::load(path)?) as Arc<dyn TenantResolver>),
+            None => None,
+        };
+
+        Ok(RelayConfig {
+            tls,
+            bind: self.bind.clone(),
+            endpoints: Vec::new(),
+            qlog_dir: self.logs.qlog_dir.clone(),
+            mlog_dir: self.logs.mlog_dir.clone(),
+            script_dir: self.logs.script_dir.clone(),
+            mlog: self.logs.mlog(),
+            capture,
+            node: self.node.clone(),
+            announce: self.announce.forward.clone(),
+            announce_linger: Duration::from_secs(self.announce.linger),
+            reconnect: self.announce.reconnect(),
+            gossip: self
+                .node
+                .clone()
+                .filter(|_| !self.announce.gossip.is_empty())
+                .map(|node| GossipConfig {
+                    node,
+                    peers: self.announce.gossip.clone(),
+                    max_hops: self.announce.gossip_max_hops,
+                }),
+            coordinator,
+            routing,
+            coordinator_timeouts: CoordinatorTimeouts {
+                register: Duration::from_millis(self.coordinator.register_timeout),
+                lookup: Duration::from_millis(self.coordinator.lookup_timeout),
+            },
+            lookup_cache: (self.coordinator.lookup_ttl > 0).then(|| LookupCacheConfig {
+                ttl: Duration::from_millis(self.coordinator.lookup_ttl),
+                negative_ttl: Duration::from_millis(self.coordinator.lookup_negative_ttl),
+            }),
+            object_limits: ObjectLimits {
+                max_object_size: self.objects.max_size,
+                max_buffered: self.objects.max_buffer,
+            },
+            announce_limits: AnnounceLimits {
+                per_session: self.namespaces.concurrency,
+                total: self.namespaces.concurrency_total,
+                max_namespaces: self.namespaces.max_per_session,
+            },
+            duplicates: self.namespaces.duplicates,
+            liveness: (self.namespaces.idle_timeout > 0).then(|| LivenessConfig {
+                timeout: Duration::from_millis(self.namespaces.idle_timeout),
+                withdraw: self.namespaces.idle_withdraw,
+                end_subscriptions: self.namespaces.idle_end_subscriptions,
+            }),
+            cache: CacheConfig {
+                memory_budget: self.cache.memory.unwrap_or(0),
+                disk_dir: self.cache.dir.clone(),
+                disk_budget: self.cache.disk,
+            },
+            fetch_bitrate: self.cache.fetch_bitrate,
+            egress: EgressConfig {
+                bitrate: self.egress.bitrate,
+                tenants: self.egress.tenants.iter().cloned().collect(),
+            },
+            archive: self.archive.dir.clone().map(|dir| ArchiveConfig {
+                dir,
+                namespaces: self
+                    .archive
+                    .namespaces
+                    .iter()
+                    .map(|prefix| TrackNamespace::from_utf8_path(prefix))
+                    .collect(),
+                max_age: Duration::from_secs(self.archive.max_age),
+                max_bytes: self.archive.max_bytes,
+            }),
+            previews: (!self.preview.namespaces.is_empty()).then(|| PreviewConfig {
+                namespaces: self
+                    .preview
+                    .namespaces
+                    .iter()
+                    .map(|prefix| TrackNamespace::from_utf8_path(prefix))
+                    .collect(),
+                tracks: self.preview.tracks.clone(),
+                interval: Duration::from_secs(self.preview.interval),
+            }),
+            perf: self.perf.namespace.as_ref().map(|namespace| PerfConfig {
+                namespace: TrackNamespace::from_utf8_path(namespace),
+                max_bitrate: self.perf.max_bitrate,
+            }),
+            pinned_tracks: self
+                .pin
+                .tracks
+                .iter()
+                .map(|(namespace, names)| PinnedTracks {
+                    namespace: TrackNamespace::from_utf8_path(namespace),
+                    names: names.split(',').map(str::to_string).collect(),
+                })
+                .collect(),
+            authorizer,
+            reauthorize: self.auth.recheck.map(|interval| Reauthorize {
+                interval: Some(Duration::from_secs(interval)),
+                grace: Duration::from_secs(self.auth.renew_grace),
+            }),
+            upstream_auth_token: self
+                .auth
+                .upstream_token
+                .clone()
+                .map(|token| Token::new(0, token.into_bytes())),
+            zero_rtt: self.zero_rtt,
+            transport: self.transport.clone(),
+            flags,
+            quotas,
+            priorities,
+            validators,
+            bridge: None,
+            tenants,
+        })
+    }
+
+    /// The web server, if enabled with `dev` or `web.health`, on the first `bind` address.
+    pub fn web(&self, tls: tls::Config) -> Option<WebConfig> {
+        (self.dev || self.web.health).then(|| WebConfig {
+            bind: self.bind[0],
+            tls,
+            qlog_dir: self.logs.qlog_dir.clone().filter(|_| self.web.qlog_serve),
+            mlog_dir: self.logs.mlog_dir.clone().filter(|_| self.web.mlog_serve),
+            log_token: self.web.log_token.clone(),
+            log_index: None,
+        })
+    }
+
+    /// The admin API, if enabled with `admin.bind`.
+    pub fn admin(&self) -> Option<AdminConfig> {
+        Some(AdminConfig {
+            bind: self.admin.bind?,
+            token: self.admin.token.clone(),
+        })
+    }
+}
+
+// Parse an environment variable as a TOML value, or take it as a string.
+fn parse_env_value(value: &str) -> toml::Value {
+    format!("value = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()))
+}
+
+// Set the field at `path`, creating the tables leading to it.
+fn set(table: &mut toml::Table, path: &[String], value: toml::Value) -> anyhow::Result<()> {
+    let (field, tables) = path.split_last().context("empty field")?;
+
+    let mut table = table;
+    for (i, name) in tables.iter().enumerate() {
+        table = table
+            .entry(name.clone())
+            .or_insert_with(|| toml::Value::Table(Default::default()))
+            .as_table_mut()
+            .with_context(|| format!("{} is not a table", path[..=i].join(".")))?;
+    }
+
+    table.insert(field.clone(), value);
+    Ok(())
+}
+
+// Parse a KEY=VALUE argument.
+fn parse_key_value<T>(arg: &str) -> Result<(String, T), String>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match arg.split_once('=') {
+        Some((key, value)) if !key.is_empty() => value
+            .parse()
+            .map(|value| (key.to_string(), value))
+            .map_err(|err| format!("invalid value for {}: {}", key, err)),
+        _ => Err(format!("expected KEY=VALUE, got {:?}", arg)),
+    }
+}
+
+// Deserialize KEY=VALUE arguments from a table, ex. `{ region = "eu-west" }`.
+fn key_values<'de, D, T>(deserializer: D) -> Result<Vec<(String, T)>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let table = BTreeMap::<String, T>::deserialize(deserializer)?;
+    Ok(table.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOML: &str = r#"
+        bind = ["[::]:4443"]
+        dev = true
+
+        [tls]
+        cert = ["relay.crt"]
+        key = ["relay.key"]
+
+        [transport]
+        congestion_controller = "cubic"
+
+        [coordinator]
+        api_url = "http://localhost:8080"
+        metadata = { region = "eu-west" }
+
+        [routing]
+        minimum = { capacity = 10 }
+
+        [egress]
+        tenants = { a = 50000000 }
+
+        [pin]
+        tracks = { "live/main" = "video,audio" }
+
+        [admin]
+        bind = "127.0.0.1:8080"
+        token = "secret"
+    "#;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_file() {
+        let config = RelayFileConfig::parse_toml(TOML, []).unwrap();
+        assert_eq!(config.bind, vec!["[::]:4443".parse().unwrap()]);
+        assert_eq!(
+            config.transport.congestion_controller,
+            moq_config::CongestionController::Cubic
+        );
+        assert_eq!(
+            config.coordinator.metadata,
+            vec![("region".to_string(), "eu-west".to_string())]
+        );
+        assert_eq!(config.routing.minimum, vec![("capacity".to_string(), 10)]);
+        assert_eq!(config.egress.tenants, vec![("a".to_string(), 50_000_000)]);
+        assert_eq!(
+            config.pin.tracks,
+            vec![("live/main".to_string(), "video,audio".to_string())]
+        );
+        assert_eq!(config.admin().unwrap().token.as_deref(), Some("secret"));
+
+        // Everything else takes the same defaults as the flags.
+        assert_eq!(config.coordinator.api_ttl, 600);
+        assert_eq!(config.handover, HandoverFileConfig::default());
+        assert_eq!(
+            RelayFileConfig::parse_from(["moq-relay-ietf"]),
+            RelayFileConfig::default()
+        );
+    }
+
+    #[test]
+    fn env_overrides() {
+        let config = RelayFileConfig::parse_toml(
+            TOML,
+            env(&[
+                ("MOQ_RELAY_ADMIN__TOKEN", "1234"),
+                ("MOQ_RELAY_CACHE__MEMORY", "1048576"),
+                ("MOQ_RELAY_TRANSPORT__EMULATION__DELAY", "50"),
+                ("MOQ_RELAY_BIND", r#"["[::]:443", "[::]:4443"]"#),
+                ("OTHER_ADMIN__TOKEN", "ignored"),
+            ]),
+        )
+        .unwrap();
+
+        // Numbers stay strings for string fields.
+        assert_eq!(config.admin.token.as_deref(), Some("1234"));
+        assert_eq!(config.cache.memory, Some(1 << 20));
+        assert_eq!(config.transport.emulation.delay, 50);
+        assert_eq!(config.bind.len(), 2);
+    }
+
+    #[test]
+    fn names_invalid_fields() {
+        let err = RelayFileConfig::parse_toml(TOML, env(&[("MOQ_RELAY_ADMIN__BIND", "nowhere")]))
+            .unwrap_err();
+        assert!(err.to_string().starts_with("admin.bind:"), "{}", err);
+
+        let err = RelayFileConfig::parse_toml("[cache]\ndisk = \"lots\"", []).unwrap_err();
+        assert!(err.to_string().starts_with("cache.disk:"), "{}", err);
+
+        let err =
+            RelayFileConfig::parse_toml(TOML, env(&[("MOQ_RELAY_CACHE__FETCH_BITRATE", "0")]))
+                .unwrap_err();
+        assert!(
+            err.to_string().starts_with("cache.fetch_bitrate:"),
+            "{}",
+            err
+        );
+
+        let err =
+            RelayFileConfig::parse_toml(TOML, env(&[("MOQ_RELAY_CACHE__DIR", "/var/cache/moq")]))
+                .unwrap_err();
+        assert!(err.to_string().starts_with("cache.dir:"), "{}", err);
+
+        let err = RelayFileConfig::parse_toml(
+            TOML,
+            env(&[("MOQ_RELAY_ARCHIVE__DIR", "/var/lib/moq/archive")]),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("archive.namespaces:"),
+            "{}",
+            err
+        );
+
+        let err = RelayFileConfig::parse_toml(
+            TOML,
+            env(&[
+                ("MOQ_RELAY_DEV", "false"),
+                ("MOQ_RELAY_TRANSPORT__EMULATION__LOSS", "5.0"),
+            ]),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("transport.emulation:"),
+            "{}",
+            err
+        );
+
+        let err = RelayFileConfig::parse_toml(
+            TOML,
+            env(&[(
+                "MOQ_RELAY_COORDINATOR__SHARDS",
+                r#"["http://api1:8080", "http://api2:8080"]"#,
+            )]),
+        )
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with("coordinator.shards:"),
+            "{}",
+            err
+        );
+
+        let err = RelayFileConfig::parse_toml(
+            TOML,
+            env(&[("MOQ_RELAY_VALIDATORS", "/etc/moq/validators.json")]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().starts_with("objects.max_size:"), "{}", err);
+
+        let open = TOML
+            .replace("token = \"secret\"", "")
+            .replace("127.0.0.1", "0.0.0.0");
+        let err = RelayFileConfig::parse_toml(&open, []).unwrap_err();
+        assert!(err.to_string().starts_with("admin.token:"), "{}", err);
+
+        let err =
+            RelayFileConfig::parse_toml(TOML, env(&[("MOQ_RELAY_ANNOUNCE__RECONNECT_MAX", "50")]))
+                .unwrap_err();
+        assert!(
+            err.to_string().starts_with("announce.reconnect_max:"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use anyhow::Context;
+use moq_transport::coding::TrackNamespace;
+use serde::{Deserialize, Serialize};
+
+/// Serve subscriptions with a bounded buffer of subgroups instead of skipping to the latest one.
+pub const FLAG_BUFFERED_DELIVERY: &str = "buffered_delivery";
+
+/// Who an experiment flag is enabled for.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FlagRollout {
+    /// The percentage of namespaces the flag is enabled for, from 0 to 100.
+    ///
+    /// Namespaces are bucketed by a stable hash, so the same namespace gets the same answer on
+    /// every relay and across restarts, and raising the percentage only ever adds namespaces.
+    pub percent: u8,
+
+    /// Force the flag on or off for namespaces under a prefix, ex. a tenant, regardless of `percent`.
+    /// Keys are namespace paths like `tenant` or `tenant/room`; the longest matching prefix wins.
+    pub namespaces: BTreeMap<String, bool>,
+}
+
+impl FlagRollout {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.percent <= 100,
+            "rollout percent must be at most 100: {}",
+            self.percent
+        );
+        Ok(())
+    }
+
+    fn enabled(&self, flag: &str, namespace: &TrackNamespace) -> bool {
+        let forced = self
+            .namespaces
+            .iter()
+            .map(|(prefix, enabled)| (TrackNamespace::from_utf8_path(prefix).fields, enabled))
+            .filter(|(prefix, _)| namespace.fields.starts_with(prefix))
+            .max_by_key(|(prefix, _)| prefix.len());
+
+        match forced {
+            Some((_, enabled)) => *enabled,
+            None => bucket(flag, namespace) < self.percent,
+        }
+    }
+}
+
+/// Experiment flags, gating new relay behaviours for a subset of namespaces.
+///
+/// Flags are loaded from a JSON file mapping each flag name to its [FlagRollout], and can be
+/// changed at runtime through the admin API. Unknown flags are disabled everywhere.
+#[derive(Clone, Default)]
+pub struct Flags {
+    rollouts: Arc<RwLock<BTreeMap<String, FlagRollout>>>,
+}
+
+impl Flags {
+    pub fn new(rollouts: BTreeMap<String, FlagRollout>) -> anyhow::Result<Self> {
+        for (flag, rollout) in &rollouts {
+            rollout
+                .validate()
+                .with_context(|| format!("invalid flag: {}", flag))?;
+        }
+
+        Ok(Self {
+            rollouts: Arc::new(RwLock::new(rollouts)),
+        })
+    }
+
+    /// Load flags from a JSON file, ex. `{"buffered_delivery": {"percent": 10}}`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read flags: {}", path.display()))?;
+        let rollouts = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse flags: {}", path.display()))?;
+        Self::new(rollouts)
+    }
+
+    /// Whether `flag` is enabled for `namespace`.
+    pub fn enabled(&self, flag: &str, namespace: &TrackNamespace) -> bool {
+        self.rollouts
+            .read()
+            .unwrap()
+            .get(flag)
+            .is_some_and(|rollout| rollout.enabled(flag, namespace))
+    }
+
+    /// Add or replace the rollout of `flag`.
+    pub fn set(&self, flag: &str, rollout: FlagRollout) -> anyhow::Result<()> {
+        rollout.validate()?;
+        log::info!("setting flag {}: {:?}", flag, rollout);
+        self.rollouts
+            .write()
+            .unwrap()
+            .insert(flag.to_string(), rollout);
+        Ok(())
+    }
+
+    /// Disable `flag` everywhere, returning false if it wasn't set.
+    pub fn remove(&self, flag: &str) -> bool {
+        log::info!("removing flag {}", flag);
+        self.rollouts.write().unwrap().remove(flag).is_some()
+    }
+
+    /// Every flag and its rollout.
+    pub fn list(&self) -> BTreeMap<String, FlagRollout> {
+        self.rollouts.read().unwrap().clone()
+    }
+}
+
+// FNV-1a over the flag and namespace, so each flag picks a different set of namespaces.
+// Unlike the std hasher, the result never changes between builds or relays.
+fn bucket(flag: &str, namespace: &TrackNamespace) -> u8 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut write = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+
+    write(flag.as_bytes());
+    for field in &namespace.fields {
+        write(&[0]);
+        write(&field.value);
+    }
+
+    (hash % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rollout() {
+        let flags = Flags::default();
+        let namespaces: Vec<_> = (0..1000)
+            .map(|i| TrackNamespace::from_utf8_path(&format!("tenant{}/room{}", i % 10, i)))
+            .chain((0..1000).map(|i| TrackNamespace::from_utf8_path(&format!("room{}", i))))
+            .collect();
+        let count = |flags: &Flags| {
+            namespaces
+                .iter()
+                .filter(|namespace| flags.enabled(FLAG_BUFFERED_DELIVERY, namespace))
+                .count()
+        };
+
+        assert_eq!(count(&flags), 0);
+
+        let mut rollout = FlagRollout {
+            percent: 50,
+            ..Default::default()
+        };
+        flags.set(FLAG_BUFFERED_DELIVERY, rollout.clone()).unwrap();
+        let half = count(&flags);
+        assert!((800..1200).contains(&half), "{}", half);
+
+        // Stable, and only grows as the percentage does.
+        assert_eq!(count(&flags), half);
+        rollout.percent = 100;
+        flags.set(FLAG_BUFFERED_DELIVERY, rollout.clone()).unwrap();
+        assert_eq!(count(&flags), namespaces.len());
+
+        // Overrides win over the percentage, the longest prefix first.
+        rollout.percent = 0;
+        rollout.namespaces.insert("tenant1".to_string(), true);
+        rollout
+            .namespaces
+            .insert("tenant1/room1".to_string(), false);
+        rollout.namespaces.insert("tenant2".to_string(), true);
+        flags.set(FLAG_BUFFERED_DELIVERY, rollout).unwrap();
+        assert_eq!(count(&flags), 199);
+        assert!(!flags.enabled(
+            FLAG_BUFFERED_DELIVERY,
+            &TrackNamespace::from_utf8_path("tenant1/room1")
+        ));
+        assert!(flags.enabled(
+            FLAG_BUFFERED_DELIVERY,
+            &TrackNamespace::from_utf8_path("tenant1/room11")
+        ));
+
+        assert!(flags
+            .set(
+                FLAG_BUFFERED_DELIVERY,
+                FlagRollout {
+                    percent: 101,
+                    ..Default::default()
+                }
+            )
+            .is_err());
+
+        assert!(flags.remove(FLAG_BUFFERED_DELIVERY));
+        assert_eq!(count(&flags), 0);
+    }
+}
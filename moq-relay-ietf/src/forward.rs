@@ -0,0 +1,595 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use moq_native_ietf::quic;
+use moq_transport::{
+    coding::{Decode, Encode, KeyValuePairs, Token, TrackNamespace},
+    message::PublishNamespaceErrorCode,
+    serve::ServeError,
+    session::{Publisher, SessionError, Subscriber},
+};
+use rand::Rng;
+use tokio::{sync::watch, task::JoinHandle, time::Instant};
+use url::Url;
+
+use crate::{Health, Session};
+
+/// How long to wait before reconnecting to an announce destination or gossip peer that failed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    /// The delay after the first failure.
+    pub min_delay: Duration,
+
+    /// The delay stops growing here. A session lasting longer than this resets it, so a
+    /// destination that ends every session right away is retried ever more slowly.
+    pub max_delay: Duration,
+
+    /// Each failure in a row multiplies the delay by this much.
+    pub multiplier: f64,
+
+    /// Randomize each delay by up to this share either way, so relays restarted together don't
+    /// reconnect in lockstep.
+    pub jitter: f64,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.1,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.min_delay <= self.max_delay,
+            "reconnect max delay is shorter than the min delay"
+        );
+        anyhow::ensure!(
+            self.multiplier >= 1.0,
+            "reconnect multiplier is less than 1"
+        );
+        anyhow::ensure!(
+            (0.0..1.0).contains(&self.jitter),
+            "reconnect jitter must be at least 0 and less than 1"
+        );
+        Ok(())
+    }
+}
+
+/// The delays between the reconnects of one destination.
+pub(crate) struct Backoff {
+    policy: ReconnectPolicy,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self {
+            delay: policy.min_delay,
+            policy,
+        }
+    }
+
+    /// The delay before the next reconnect, after a session that lasted `connected`, if any.
+    pub fn next(&mut self, connected: Option<Duration>) -> Duration {
+        if connected.is_some_and(|connected| connected > self.policy.max_delay) {
+            self.delay = self.policy.min_delay;
+        }
+
+        let delay = self.delay;
+        self.delay = self
+            .delay
+            .mul_f64(self.policy.multiplier)
+            .min(self.policy.max_delay);
+
+        let jitter = self.policy.jitter;
+        if jitter > 0.0 {
+            delay.mul_f64(rand::thread_rng().gen_range(1.0 - jitter..1.0 + jitter))
+        } else {
+            delay
+        }
+    }
+}
+
+/// A server the relay forwards announces to, ex. for authentication, routing or analytics.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ForwardDestination {
+    pub url: Url,
+
+    /// Only namespaces under these prefixes are forwarded, or every namespace if empty.
+    pub prefixes: Vec<TrackNamespace>,
+}
+
+impl ForwardDestination {
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Only forward the namespaces under `prefix`, and those under any other prefix added.
+    pub fn with_prefix(mut self, prefix: TrackNamespace) -> Self {
+        self.prefixes.push(prefix);
+        self
+    }
+
+    /// Whether announces of `namespace` are forwarded to this destination.
+    pub fn matches(&self, namespace: &TrackNamespace) -> bool {
+        self.prefixes.is_empty()
+            || self
+                .prefixes
+                .iter()
+                .any(|prefix| namespace.has_prefix(prefix))
+    }
+}
+
+/// Parses a URL, with the prefixes to forward as a comma-separated fragment,
+/// ex. `https://analytics.example.com#live,vod/sports`.
+impl FromStr for ForwardDestination {
+    type Err = url::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut url = Url::parse(s)?;
+        let prefixes = url
+            .fragment()
+            .unwrap_or_default()
+            .split(',')
+            .filter(|prefix| !prefix.is_empty())
+            .map(TrackNamespace::from_utf8_path)
+            .collect();
+        url.set_fragment(None);
+
+        Ok(Self { url, prefixes })
+    }
+}
+
+/// Deserialized from the same string as [FromStr], ex. in a config file.
+impl<'de> serde::Deserialize<'de> for ForwardDestination {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// The PUBLISH_NAMESPACE parameter listing the relays a forwarded or gossiped announce went
+/// through. Its type is odd, so the value is bytes.
+pub const ANNOUNCE_PATH_PARAM: u64 = 0x4d51;
+
+/// The relays a forwarded or gossiped announce went through, by instance ID, starting with its
+/// origin.
+///
+/// Relays refuse announces that already went through them, so a chain of `--announce`
+/// destinations or gossip peers that leads back to a relay can't loop.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AnnouncePath(Vec<String>);
+
+impl AnnouncePath {
+    /// Read the path from the parameters of a PUBLISH_NAMESPACE, empty if it wasn't forwarded.
+    pub fn from_params(params: &KeyValuePairs) -> anyhow::Result<Self> {
+        let mut value = match params.get_bytesvalue(ANNOUNCE_PATH_PARAM) {
+            Some(value) => value,
+            None => return Ok(Self::default()),
+        };
+
+        let mut relays = Vec::new();
+        while !value.is_empty() {
+            relays.push(String::decode(&mut value).context("invalid announce path")?);
+        }
+
+        Ok(Self(relays))
+    }
+
+    /// Add the path to the parameters of a PUBLISH_NAMESPACE.
+    pub fn to_params(&self, params: &mut KeyValuePairs) {
+        let mut value = Vec::new();
+        for relay in &self.0 {
+            relay
+                .encode(&mut value)
+                .expect("encoding to a Vec can't fail");
+        }
+
+        params.set_bytesvalue(ANNOUNCE_PATH_PARAM, value);
+    }
+
+    /// The number of relays the announce went through, or 0 if it was announced to us directly.
+    pub fn hops(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether the announce went through the relay with this instance ID.
+    pub fn contains(&self, instance: &str) -> bool {
+        self.0.iter().any(|relay| relay == instance)
+    }
+
+    /// The path once the relay with this instance ID passes the announce on.
+    pub fn through(&self, instance: &str) -> Self {
+        let mut relays = self.0.clone();
+        relays.push(instance.to_string());
+        Self(relays)
+    }
+}
+
+/// Formatted like `relay-a -> relay-b`.
+impl fmt::Display for AnnouncePath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(" -> "))
+    }
+}
+
+/// Relay-wide record of the namespaces publishers announce, watched by each forward destination
+/// and gossip peer.
+///
+/// A namespace is listed once however many publishers announce it, so destinations aren't told
+/// about duplicates, ex. under [crate::DuplicatePolicy::Merge] or while a publisher reconnects.
+#[derive(Clone, Default)]
+pub struct AnnounceFeed {
+    paths: Arc<watch::Sender<HashMap<TrackNamespace, Vec<AnnouncePath>>>>,
+}
+
+impl AnnounceFeed {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// List `namespace` as announced until the guard is dropped.
+    pub fn add(&self, namespace: TrackNamespace) -> FeedGuard {
+        self.add_forwarded(namespace, AnnouncePath::default())
+    }
+
+    /// Like [Self::add], for an announce forwarded to us through the relays on `path`.
+    pub fn add_forwarded(&self, namespace: TrackNamespace, path: AnnouncePath) -> FeedGuard {
+        self.paths.send_modify(|paths| {
+            paths
+                .entry(namespace.clone())
+                .or_default()
+                .push(path.clone());
+        });
+
+        FeedGuard {
+            paths: self.paths.clone(),
+            namespace,
+            path,
+        }
+    }
+
+    /// The namespaces currently announced.
+    pub fn namespaces(&self) -> HashSet<TrackNamespace> {
+        self.paths.borrow().keys().cloned().collect()
+    }
+
+    /// The namespaces currently announced, each with the shortest path it was forwarded through.
+    pub fn paths(&self) -> HashMap<TrackNamespace, AnnouncePath> {
+        self.paths
+            .borrow()
+            .iter()
+            .filter_map(|(namespace, paths)| {
+                let path = paths.iter().min_by_key(|path| path.hops())?;
+                Some((namespace.clone(), path.clone()))
+            })
+            .collect()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<HashMap<TrackNamespace, Vec<AnnouncePath>>> {
+        self.paths.subscribe()
+    }
+}
+
+/// Keeps a namespace listed in the [AnnounceFeed] until dropped.
+pub struct FeedGuard {
+    paths: Arc<watch::Sender<HashMap<TrackNamespace, Vec<AnnouncePath>>>>,
+    namespace: TrackNamespace,
+    path: AnnouncePath,
+}
+
+impl Drop for FeedGuard {
+    fn drop(&mut self) {
+        self.paths.send_modify(|paths| {
+            if let Some(listed) = paths.get_mut(&self.namespace) {
+                if let Some(index) = listed.iter().position(|path| path == &self.path) {
+                    listed.remove(index);
+                }
+                if listed.is_empty() {
+                    paths.remove(&self.namespace);
+                }
+            }
+        });
+    }
+}
+
+/// Creates the relay session run over each connection to a destination.
+pub(crate) type ForwardSession =
+    Arc<dyn Fn(moq_transport::session::Session, Publisher, Subscriber) -> Session + Send + Sync>;
+
+/// Keeps a destination told about the namespaces in the [AnnounceFeed], reconnecting on its own
+/// schedule when the connection fails.
+pub(crate) struct Forwarder {
+    pub destination: ForwardDestination,
+
+    /// Our ID in the path of forwarded announces.
+    pub instance: String,
+    pub client: quic::Client,
+    pub auth_token: Option<Token>,
+    pub feed: AnnounceFeed,
+
+    /// How long a namespace stays announced after its last publisher left, so a publisher
+    /// reconnecting within this long doesn't make the destination see it leave and return.
+    pub linger: Duration,
+
+    /// Told whether we're connected to the destination.
+    pub health: Health,
+
+    pub reconnect: ReconnectPolicy,
+}
+
+impl Forwarder {
+    pub async fn run(self, session: ForwardSession) -> anyhow::Result<()> {
+        let url = &self.destination.url;
+        let mut backoff = Backoff::new(self.reconnect);
+
+        loop {
+            let mut connected = None;
+
+            match self.connect().await {
+                Ok((moq, publisher, subscriber)) => {
+                    // Every namespace in the feed is announced again over the new session.
+                    log::info!("forwarding announces to {}", url);
+                    let start = Instant::now();
+                    self.health.set_connected(url, true);
+
+                    let session = session(moq, publisher.clone(), subscriber);
+                    let res = tokio::select! {
+                        res = session.run() => res.map_err(Into::into),
+                        res = self.forward(&publisher) => res,
+                    };
+                    self.health.set_connected(url, false);
+                    connected = Some(start.elapsed());
+                    if let Err(err) = res {
+                        log::warn!("forwarding announces to {} failed: {}", url, err);
+                    }
+                }
+                Err(err) => log::warn!("failed to connect to {}: {:#}", url, err),
+            }
+
+            let delay = backoff.next(connected);
+            log::debug!("reconnecting to {} in {:?}", url, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    async fn connect(
+        &self,
+    ) -> anyhow::Result<(moq_transport::session::Session, Publisher, Subscriber)> {
+        let (session, _, _) = self
+            .client
+            .connect(&self.destination.url, None)
+            .await
+            .context("failed to establish forward connection")?;
+
+        moq_transport::session::Session::connect_with_token(session, None, self.auth_token.clone())
+            .await
+            .context("failed to establish forward session")
+    }
+
+    // Announce the feed's namespaces over one session, until the destination ends it.
+    async fn forward(&self, publisher: &Publisher) -> anyhow::Result<()> {
+        let mut changes = self.feed.subscribe();
+        changes.mark_changed();
+
+        let mut announced = HashMap::<TrackNamespace, Announced>::new();
+        let mut withdrawing = HashMap::<TrackNamespace, Instant>::new();
+
+        loop {
+            let next_withdrawal = withdrawing.values().min().copied();
+
+            tokio::select! {
+                res = changes.changed() => res?,
+                _ = tokio::time::sleep_until(next_withdrawal.unwrap_or_else(Instant::now)), if next_withdrawal.is_some() => {},
+            }
+
+            let wanted: HashMap<_, _> = self
+                .feed
+                .paths()
+                .into_iter()
+                .filter(|(namespace, _)| self.destination.matches(namespace))
+                .collect();
+
+            for (namespace, path) in &wanted {
+                withdrawing.remove(namespace);
+
+                // Announces the destination rejected aren't retried until the next session.
+                if !announced.contains_key(namespace) {
+                    log::info!(
+                        "forwarding announce to {}: {}",
+                        self.destination.url,
+                        namespace
+                    );
+
+                    let path = path.through(&self.instance);
+                    let mut params = KeyValuePairs::new();
+                    path.to_params(&mut params);
+
+                    let mut publisher = publisher.clone();
+                    let (forwarded, url) = (namespace.clone(), self.destination.url.clone());
+                    let task = tokio::spawn(async move {
+                        let res = publisher
+                            .announce_namespace_with_params(forwarded.clone(), None, params)
+                            .await;
+                        match res {
+                            Err(err) if is_loop(&err) => log::warn!(
+                                "forwarding loop: {} refused {}, which already went through it: {}",
+                                url,
+                                forwarded,
+                                path
+                            ),
+                            Err(err) => {
+                                log::warn!("forwarded announce ended: {}: {}", forwarded, err)
+                            }
+                            Ok(()) => {}
+                        }
+                    });
+                    announced.insert(namespace.clone(), Announced(task));
+                }
+            }
+
+            let now = Instant::now();
+            for namespace in announced.keys() {
+                if !wanted.contains_key(namespace) {
+                    withdrawing
+                        .entry(namespace.clone())
+                        .or_insert(now + self.linger);
+                }
+            }
+
+            // Dropping the task sends PUBLISH_NAMESPACE_DONE.
+            withdrawing.retain(|namespace, deadline| {
+                if *deadline > now {
+                    return true;
+                }
+                announced.remove(namespace);
+                false
+            });
+        }
+    }
+}
+
+/// Whether a forwarded or gossiped announce was refused by a relay it already went through.
+pub(crate) fn is_loop(err: &SessionError) -> bool {
+    matches!(
+        err,
+        SessionError::Serve(ServeError::Closed(code))
+            if *code == PublishNamespaceErrorCode::Uninterested.code()
+    )
+}
+
+// A forwarded announce, withdrawn on drop.
+struct Announced(JoinHandle<()>);
+
+impl Drop for Announced {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destinations() {
+        let destination: ForwardDestination = "https://analytics.example.com#live,vod/sports"
+            .parse()
+            .unwrap();
+        assert_eq!(destination.url.as_str(), "https://analytics.example.com/");
+        assert!(destination.matches(&TrackNamespace::from_utf8_path("live/room")));
+        assert!(destination.matches(&TrackNamespace::from_utf8_path("vod/sports")));
+        assert!(!destination.matches(&TrackNamespace::from_utf8_path("vod/news")));
+
+        let destination: ForwardDestination = "https://auth.example.com".parse().unwrap();
+        assert!(destination.prefixes.is_empty());
+        assert!(destination.matches(&TrackNamespace::from_utf8_path("anything")));
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = ReconnectPolicy {
+            min_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            multiplier: 3.0,
+            jitter: 0.0,
+        };
+        let mut backoff = Backoff::new(policy);
+
+        let delays: Vec<_> = (0..5)
+            .map(|_| backoff.next(Some(Duration::from_millis(10))).as_millis())
+            .collect();
+        assert_eq!(delays, [100, 300, 900, 1000, 1000]);
+
+        // Reset by a session that lasted, but not by failing to connect.
+        assert_eq!(backoff.next(Some(Duration::from_secs(2))).as_millis(), 100);
+        assert_eq!(backoff.next(None).as_millis(), 300);
+
+        let mut backoff = Backoff::new(ReconnectPolicy {
+            jitter: 0.5,
+            ..policy
+        });
+        for _ in 0..20 {
+            let delay = backoff.next(None);
+            assert!(delay >= Duration::from_millis(50), "{:?}", delay);
+            assert!(delay <= Duration::from_millis(1500), "{:?}", delay);
+        }
+
+        assert!(ReconnectPolicy::default().validate().is_ok());
+        assert!(ReconnectPolicy {
+            max_delay: Duration::from_millis(10),
+            ..policy
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn paths() {
+        let path = AnnouncePath::default().through("a").through("b");
+        assert_eq!(path.hops(), 2);
+        assert!(path.contains("a") && path.contains("b") && !path.contains("c"));
+        assert_eq!(path.to_string(), "a -> b");
+
+        let mut params = KeyValuePairs::new();
+        path.to_params(&mut params);
+        assert_eq!(AnnouncePath::from_params(&params).unwrap(), path);
+
+        // Announced directly, or garbled.
+        assert_eq!(
+            AnnouncePath::from_params(&KeyValuePairs::new()).unwrap(),
+            AnnouncePath::default()
+        );
+        params.set_bytesvalue(ANNOUNCE_PATH_PARAM, vec![0x05, b'a']);
+        assert!(AnnouncePath::from_params(&params).is_err());
+    }
+
+    #[test]
+    fn feed_prefers_shortest_path() {
+        let feed = AnnounceFeed::new();
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let far = feed.add_forwarded(
+            namespace.clone(),
+            AnnouncePath::default().through("a").through("b"),
+        );
+        let near = feed.add_forwarded(namespace.clone(), AnnouncePath::default().through("a"));
+        assert_eq!(feed.paths()[&namespace].hops(), 1);
+
+        drop(near);
+        assert_eq!(feed.paths()[&namespace].hops(), 2);
+        drop(far);
+        assert!(feed.paths().is_empty());
+    }
+
+    #[test]
+    fn feed_deduplicates() {
+        let feed = AnnounceFeed::new();
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let first = feed.add(namespace.clone());
+        let second = feed.add(namespace.clone());
+        assert_eq!(feed.namespaces(), HashSet::from([namespace.clone()]));
+
+        // Listed until the last publisher leaves.
+        drop(first);
+        assert_eq!(feed.namespaces().len(), 1);
+        drop(second);
+        assert!(feed.namespaces().is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, time::Duration};
+
+use anyhow::Context;
+use async_trait::async_trait;
+use moq_native_ietf::quic;
+use moq_transport::{
+    coding::{KeyValuePairs, Token, TrackNamespace},
+    session::{Publisher, Subscriber},
+};
+use tokio::{sync::mpsc, task::JoinHandle, time::Instant};
+use url::Url;
+
+use crate::{
+    forward::{is_loop, Backoff},
+    AnnounceFeed, AnnouncePath, Coordinator, CoordinatorError, CoordinatorResult, ForwardSession,
+    NamespaceOrigin, NamespaceRegistration, ReconnectPolicy,
+};
+
+/// Relays that propagate announces to each other over MoQ sessions, instead of registering them
+/// with a coordinator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GossipConfig {
+    /// Our instance ID in the path of announces: the URL our peers know us by.
+    pub node: Url,
+
+    /// The relays we announce our namespaces to, along with those gossiped to us.
+    /// Each peer connects to us in turn to announce its own.
+    pub peers: Vec<Url>,
+
+    /// Announces that went through this many relays, counting their origin, aren't passed on.
+    pub max_hops: usize,
+}
+
+/// The coordinator of relays that only learn namespaces by gossip, ex. a small cluster without
+/// a registry: there's nothing to register, and every lookup misses.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GossipCoordinator;
+
+#[async_trait]
+impl Coordinator for GossipCoordinator {
+    async fn register_namespace(
+        &self,
+        _namespace: &TrackNamespace,
+    ) -> CoordinatorResult<NamespaceRegistration> {
+        Ok(NamespaceRegistration::new(()))
+    }
+
+    async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        Ok(())
+    }
+
+    async fn lookup(
+        &self,
+        _namespace: &TrackNamespace,
+    ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+        Err(CoordinatorError::NamespaceNotFound)
+    }
+}
+
+/// Keeps a peer told about the namespaces in the [AnnounceFeed] with the path they took,
+/// reconnecting on its own schedule when the connection fails.
+pub(crate) struct Gossiper {
+    pub peer: Url,
+
+    /// Our ID in the path of announces, the node URL the peers know us by.
+    pub instance: String,
+    pub max_hops: usize,
+    pub client: quic::Client,
+    pub auth_token: Option<Token>,
+    pub feed: AnnounceFeed,
+    pub reconnect: ReconnectPolicy,
+}
+
+impl Gossiper {
+    // How long before announcing a namespace the peer refused again, ex. as a duplicate of one
+    // it heard from another peer, which may since have gone.
+    const RETRY: Duration = Duration::from_secs(5);
+
+    pub async fn run(self, session: ForwardSession) -> anyhow::Result<()> {
+        let mut backoff = Backoff::new(self.reconnect);
+
+        loop {
+            let mut connected = None;
+
+            match self.connect().await {
+                Ok((moq, publisher, subscriber)) => {
+                    log::info!("gossiping announces to {}", self.peer);
+                    let start = Instant::now();
+
+                    let session = session(moq, publisher.clone(), subscriber);
+                    let res = tokio::select! {
+                        res = session.run() => res.map_err(Into::into),
+                        res = self.gossip(&publisher) => res,
+                    };
+                    connected = Some(start.elapsed());
+                    if let Err(err) = res {
+                        log::warn!("gossiping announces to {} failed: {}", self.peer, err);
+                    }
+                }
+                Err(err) => log::warn!("failed to connect to peer {}: {:#}", self.peer, err),
+            }
+
+            tokio::time::sleep(backoff.next(connected)).await;
+        }
+    }
+
+    async fn connect(
+        &self,
+    ) -> anyhow::Result<(moq_transport::session::Session, Publisher, Subscriber)> {
+        let (session, _, _) = self
+            .client
+            .connect(&self.peer, None)
+            .await
+            .context("failed to establish gossip connection")?;
+
+        moq_transport::session::Session::connect_with_token(session, None, self.auth_token.clone())
+            .await
+            .context("failed to establish gossip session")
+    }
+
+    // Announce the feed's namespaces over one session, until the peer ends it.
+    async fn gossip(&self, publisher: &Publisher) -> anyhow::Result<()> {
+        let mut changes = self.feed.subscribe();
+        changes.mark_changed();
+
+        // Told the namespace and path of each announce that ended, and whether to retry it.
+        let (ended_tx, mut ended) =
+            mpsc::unbounded_channel::<(TrackNamespace, AnnouncePath, bool)>();
+
+        let mut announced = HashMap::<TrackNamespace, (AnnouncePath, Gossiped)>::new();
+
+        // Refused announces, retried at the deadline or, if they looped, once their path changes.
+        let mut refused = HashMap::<TrackNamespace, (AnnouncePath, Option<Instant>)>::new();
+
+        loop {
+            let next_retry = refused.values().filter_map(|(_, retry)| *retry).min();
+
+            tokio::select! {
+                res = changes.changed() => res?,
+                Some((namespace, path, retry)) = ended.recv() => {
+                    if announced.get(&namespace).is_some_and(|(announced, _)| announced == &path) {
+                        announced.remove(&namespace);
+                        let retry = retry.then(|| Instant::now() + Self::RETRY);
+                        refused.insert(namespace, (path, retry));
+                    }
+                },
+                _ = tokio::time::sleep_until(next_retry.unwrap_or_else(Instant::now)), if next_retry.is_some() => {},
+            }
+
+            // Never back to a relay it went through, nor further than the hop limit.
+            let wanted: HashMap<_, _> = self
+                .feed
+                .paths()
+                .into_iter()
+                .filter(|(_, path)| {
+                    !path.contains(self.peer.as_str()) && path.hops() < self.max_hops
+                })
+                .map(|(namespace, path)| (namespace, path.through(&self.instance)))
+                .collect();
+
+            // Dropping the task sends PUBLISH_NAMESPACE_DONE.
+            announced.retain(|namespace, _| wanted.contains_key(namespace));
+
+            let now = Instant::now();
+            refused.retain(|namespace, (path, retry)| match retry {
+                Some(retry) => *retry > now && wanted.contains_key(namespace),
+                None => wanted.get(namespace) == Some(path),
+            });
+
+            for (namespace, path) in wanted {
+                if announced.contains_key(&namespace) || refused.contains_key(&namespace) {
+                    continue;
+                }
+
+                log::debug!(
+                    "gossiping announce to {}: {} path={:?}",
+                    self.peer,
+                    namespace,
+                    path
+                );
+
+                let mut params = KeyValuePairs::new();
+                path.to_params(&mut params);
+
+                let mut publisher = publisher.clone();
+                let ended = ended_tx.clone();
+                let (gossiped, gossiped_path) = (namespace.clone(), path.clone());
+                let task = tokio::spawn(async move {
+                    let res = publisher
+                        .announce_namespace_with_params(gossiped.clone(), None, params)
+                        .await;
+
+                    // The peer saw it before, so it's refused again until the path changes.
+                    let looped = res.as_ref().is_err_and(is_loop);
+                    if let Err(err) = &res {
+                        log::debug!("gossiped announce ended: {}: {}", gossiped, err);
+                    }
+
+                    let _ = ended.send((gossiped, gossiped_path, !looped));
+                });
+
+                announced.insert(namespace, (path, Gossiped(task)));
+            }
+        }
+    }
+}
+
+// A gossiped announce, withdrawn on drop.
+struct Gossiped(JoinHandle<()>);
+
+impl Drop for Gossiped {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
@@ -0,0 +1,203 @@
+use std::{
+    env, io, net,
+    os::{
+        fd::{AsRawFd, FromRawFd, RawFd},
+        unix::{net::UnixStream, process::CommandExt},
+    },
+    process,
+    time::Duration,
+};
+
+use anyhow::Context;
+use moq_native_ietf::quic;
+use tokio::io::AsyncReadExt;
+
+use crate::Admin;
+
+/// The listening sockets inherited from the previous relay process, as comma separated descriptors.
+pub const HANDOVER_SOCKETS: &str = "MOQ_RELAY_HANDOVER_SOCKETS";
+
+/// The descriptor of the stream used to tell the previous relay process we are accepting.
+pub const HANDOVER_READY: &str = "MOQ_RELAY_HANDOVER_READY";
+
+/// How long to wait for the new relay process, and for sessions to leave the old one.
+#[derive(Clone, Copy, Debug)]
+pub struct HandoverTimeouts {
+    /// Give up on the new process if it isn't accepting within this long, and keep serving.
+    pub ready: Duration,
+
+    /// Close the sessions that haven't reconnected after GOAWAY within this long.
+    pub drain: Duration,
+}
+
+impl Default for HandoverTimeouts {
+    fn default() -> Self {
+        Self {
+            ready: Duration::from_secs(10),
+            drain: Duration::from_secs(30),
+        }
+    }
+}
+
+/// The sockets handed over by the previous relay process, see [spawn_successor].
+pub struct Inherited {
+    /// In the order of the previous process' [crate::Relay::handovers].
+    pub sockets: Vec<net::UdpSocket>,
+    ready: UnixStream,
+}
+
+impl Inherited {
+    /// Take the sockets passed to this process, if it was started by [spawn_successor].
+    pub fn take() -> anyhow::Result<Option<Self>> {
+        let (Some(sockets), Some(ready)) =
+            (env::var_os(HANDOVER_SOCKETS), env::var_os(HANDOVER_READY))
+        else {
+            return Ok(None);
+        };
+
+        // Don't pass them on to our own children.
+        env::remove_var(HANDOVER_SOCKETS);
+        env::remove_var(HANDOVER_READY);
+
+        let sockets = parse_fds(&sockets.to_string_lossy())?;
+        let ready = parse_fds(&ready.to_string_lossy())?;
+        let &[ready] = ready.as_slice() else {
+            anyhow::bail!("expected one descriptor in {}", HANDOVER_READY);
+        };
+
+        // SAFETY: the previous process passed us these descriptors and nothing else owns them.
+        let sockets = sockets
+            .into_iter()
+            .map(|fd| unsafe { net::UdpSocket::from_raw_fd(fd) })
+            .collect();
+        let ready = unsafe { UnixStream::from_raw_fd(ready) };
+
+        Ok(Some(Self { sockets, ready }))
+    }
+
+    /// Tell the previous process that we are accepting sessions, so it can stop.
+    pub fn ready(mut self) -> anyhow::Result<()> {
+        io::Write::write_all(&mut self.ready, b"1").context("failed to notify previous relay")
+    }
+}
+
+/// Start a new relay process with the same arguments, passing it our listening sockets.
+///
+/// Returns once the new process is accepting sessions, or fails if it exits or doesn't become
+/// ready in time, in which case we should keep serving.
+pub async fn spawn_successor(
+    handovers: &[quic::Handover],
+    timeout: Duration,
+) -> anyhow::Result<process::Child> {
+    let (ready, theirs) = UnixStream::pair().context("failed to create handover stream")?;
+
+    let mut fds: Vec<RawFd> = handovers
+        .iter()
+        .map(|handover| handover.socket().as_raw_fd())
+        .collect();
+    let sockets = fds
+        .iter()
+        .map(RawFd::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    fds.push(theirs.as_raw_fd());
+
+    let exe = env::current_exe().context("failed to find relay executable")?;
+    let mut command = process::Command::new(exe);
+    command
+        .args(env::args_os().skip(1))
+        .env(HANDOVER_SOCKETS, sockets)
+        .env(HANDOVER_READY, theirs.as_raw_fd().to_string());
+
+    // SAFETY: fcntl is async-signal-safe, and only touches descriptors we own.
+    unsafe {
+        command.pre_exec(move || {
+            // Let the new process inherit the descriptors, which are close-on-exec by default.
+            for &fd in &fds {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+
+    let mut child = command.spawn().context("failed to start new relay")?;
+    log::info!("started new relay: pid={}", child.id());
+
+    // Close our copy, so the stream ends if the new process exits.
+    drop(theirs);
+
+    ready.set_nonblocking(true)?;
+    let mut ready = tokio::net::UnixStream::from_std(ready)?;
+
+    let mut buf = [0u8; 1];
+    let err = match tokio::time::timeout(timeout, ready.read(&mut buf)).await {
+        Ok(Ok(1)) => return Ok(child),
+        Ok(Ok(_)) => anyhow::anyhow!("new relay exited before accepting"),
+        Ok(Err(err)) => anyhow::Error::new(err).context("failed to wait for new relay"),
+        Err(_) => anyhow::anyhow!("new relay not ready after {:?}", timeout),
+    };
+
+    let _ = child.kill();
+    Err(err)
+}
+
+/// Hand the relay over to a new process: start it, stop accepting, and send GOAWAY to every
+/// session so it reconnects to the new process. Returns once every session has left, or after
+/// [HandoverTimeouts::drain], when the relay may exit.
+///
+/// Runs alongside [crate::Relay::run], which keeps serving the remaining sessions.
+pub async fn hand_over(
+    admin: &Admin,
+    handovers: &[quic::Handover],
+    timeouts: HandoverTimeouts,
+) -> anyhow::Result<()> {
+    spawn_successor(handovers, timeouts.ready).await?;
+
+    for handover in handovers {
+        handover.drain();
+    }
+    admin.goaway("");
+
+    let drained = async {
+        while !admin.sessions().is_empty() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+
+    match tokio::time::timeout(timeouts.drain, drained).await {
+        Ok(()) => log::info!("every session moved to the new relay"),
+        Err(_) => log::warn!(
+            "closing {} sessions still connected after {:?}",
+            admin.sessions().len(),
+            timeouts.drain
+        ),
+    }
+
+    Ok(())
+}
+
+fn parse_fds(value: &str) -> anyhow::Result<Vec<RawFd>> {
+    value
+        .split(',')
+        .map(|fd| {
+            fd.trim()
+                .parse()
+                .with_context(|| format!("invalid descriptor: {:?}", fd))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptors() {
+        assert_eq!(parse_fds("3").unwrap(), vec![3]);
+        assert_eq!(parse_fds("3, 4,5").unwrap(), vec![3, 4, 5]);
+        assert!(parse_fds("").is_err());
+        assert!(parse_fds("3,x").is_err());
+    }
+}
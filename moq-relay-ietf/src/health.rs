@@ -0,0 +1,171 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use serde::Serialize;
+use url::Url;
+
+use crate::Coordinator;
+
+/// Whether the relay is ready for traffic, served by the web server at /readyz.
+///
+/// The relay is ready once it accepts sessions, its coordinator answers, and it's connected to
+/// every destination announces are forwarded to.
+#[derive(Clone)]
+pub struct Health {
+    listening: Arc<AtomicBool>,
+    coordinator: Arc<dyn Coordinator>,
+    upstreams: Arc<Mutex<BTreeMap<Url, bool>>>,
+}
+
+/// A snapshot of [Health].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+
+    /// Whether the relay accepts sessions.
+    pub listening: bool,
+
+    /// Why the coordinator didn't answer, if it didn't.
+    pub coordinator_error: Option<String>,
+
+    /// The destinations announces are forwarded to.
+    pub upstreams: Vec<UpstreamReadiness>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UpstreamReadiness {
+    pub url: Url,
+    pub connected: bool,
+}
+
+impl Health {
+    pub(crate) fn new(coordinator: Arc<dyn Coordinator>, upstreams: &[Url]) -> Self {
+        Self {
+            listening: Default::default(),
+            coordinator,
+            upstreams: Arc::new(Mutex::new(
+                upstreams.iter().map(|url| (url.clone(), false)).collect(),
+            )),
+        }
+    }
+
+    pub(crate) fn set_listening(&self, listening: bool) {
+        self.listening.store(listening, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_connected(&self, upstream: &Url, connected: bool) {
+        self.upstreams
+            .lock()
+            .unwrap()
+            .insert(upstream.clone(), connected);
+    }
+
+    /// Check the coordinator, and report whether the relay is ready.
+    pub async fn check(&self) -> Readiness {
+        let coordinator_error = self
+            .coordinator
+            .health()
+            .await
+            .err()
+            .map(|err| err.to_string());
+        let listening = self.listening.load(Ordering::Relaxed);
+
+        let upstreams: Vec<_> = self
+            .upstreams
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(url, connected)| UpstreamReadiness {
+                url: url.clone(),
+                connected: *connected,
+            })
+            .collect();
+
+        Readiness {
+            ready: listening
+                && coordinator_error.is_none()
+                && upstreams.iter().all(|upstream| upstream.connected),
+            listening,
+            coordinator_error,
+            upstreams,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CoordinatorError, CoordinatorResult, NamespaceOrigin, NamespaceRegistration};
+    use async_trait::async_trait;
+    use moq_native_ietf::quic;
+    use moq_transport::coding::TrackNamespace;
+
+    // Answers health checks while `up` is set.
+    struct Flaky {
+        up: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl Coordinator for Flaky {
+        async fn register_namespace(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            Ok(NamespaceRegistration::new(()))
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            Err(CoordinatorError::NamespaceNotFound)
+        }
+
+        async fn health(&self) -> CoordinatorResult<()> {
+            match self.up.load(Ordering::Relaxed) {
+                true => Ok(()),
+                false => Err(CoordinatorError::Timeout),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn readiness() {
+        let up = Arc::new(AtomicBool::new(true));
+        let upstream: Url = "https://announce.example.com".parse().unwrap();
+        let health = Health::new(
+            Arc::new(Flaky { up: up.clone() }),
+            std::slice::from_ref(&upstream),
+        );
+
+        // Not ready until listening and connected upstream.
+        let readiness = health.check().await;
+        assert!(!readiness.ready);
+        assert!(!readiness.listening);
+        assert_eq!(
+            readiness.upstreams,
+            vec![UpstreamReadiness {
+                url: upstream.clone(),
+                connected: false
+            }]
+        );
+
+        health.set_listening(true);
+        health.set_connected(&upstream, true);
+        assert!(health.check().await.ready);
+
+        up.store(false, Ordering::Relaxed);
+        let readiness = health.check().await;
+        assert!(!readiness.ready);
+        assert!(readiness.coordinator_error.is_some());
+    }
+}
@@ -0,0 +1,145 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use moq_transport::coding::TrackNamespace;
+use tokio::sync::watch;
+
+// The number of interests each session holds in each prefix.
+type InterestCounts = HashMap<TrackNamespace, HashMap<u64, usize>>;
+
+/// Relay-wide record of the namespace prefixes downstream subscribers asked for with
+/// SUBSCRIBE_NAMESPACE, handing out a [SessionInterests] per session.
+///
+/// Every publishing session is told about every prefix, so origins can announce only the
+/// namespaces someone is watching.
+#[derive(Clone)]
+pub struct NamespaceInterests {
+    counts: Arc<watch::Sender<InterestCounts>>,
+    next_session: Arc<AtomicU64>,
+}
+
+impl NamespaceInterests {
+    pub fn new() -> Self {
+        Self {
+            counts: Arc::new(watch::Sender::new(Default::default())),
+            next_session: Default::default(),
+        }
+    }
+
+    /// Create the handle for a new session, shared by its producer and consumer.
+    pub fn session(&self) -> SessionInterests {
+        SessionInterests {
+            counts: self.counts.clone(),
+            session: self.next_session.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// The prefixes anybody is interested in.
+    pub fn prefixes(&self) -> HashSet<TrackNamespace> {
+        self.counts.borrow().keys().cloned().collect()
+    }
+}
+
+impl Default for NamespaceInterests {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A session's view of the [NamespaceInterests].
+#[derive(Clone)]
+pub struct SessionInterests {
+    counts: Arc<watch::Sender<InterestCounts>>,
+    session: u64,
+}
+
+impl SessionInterests {
+    /// Record interest in `prefix` from this session, until the guard is dropped.
+    pub fn add(&self, prefix: TrackNamespace) -> InterestGuard {
+        self.counts.send_modify(|counts| {
+            *counts
+                .entry(prefix.clone())
+                .or_default()
+                .entry(self.session)
+                .or_default() += 1;
+        });
+
+        InterestGuard {
+            counts: self.counts.clone(),
+            session: self.session,
+            prefix,
+        }
+    }
+
+    /// The prefixes other sessions are interested in, so a session isn't sent its own interest back.
+    pub fn wanted(&self) -> HashSet<TrackNamespace> {
+        self.counts
+            .borrow()
+            .iter()
+            .filter(|(_, sessions)| sessions.keys().any(|session| *session != self.session))
+            .map(|(prefix, _)| prefix.clone())
+            .collect()
+    }
+
+    pub(crate) fn subscribe(&self) -> watch::Receiver<InterestCounts> {
+        self.counts.subscribe()
+    }
+}
+
+/// Interest in a prefix from one session, removed on drop.
+pub struct InterestGuard {
+    counts: Arc<watch::Sender<InterestCounts>>,
+    session: u64,
+    prefix: TrackNamespace,
+}
+
+impl Drop for InterestGuard {
+    fn drop(&mut self) {
+        self.counts.send_modify(|counts| {
+            let Some(sessions) = counts.get_mut(&self.prefix) else {
+                return;
+            };
+
+            if let Some(count) = sessions.get_mut(&self.session) {
+                *count -= 1;
+                if *count == 0 {
+                    sessions.remove(&self.session);
+                }
+            }
+
+            if sessions.is_empty() {
+                counts.remove(&self.prefix);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wanted_by_others() {
+        let interests = NamespaceInterests::new();
+        let a = interests.session();
+        let b = interests.session();
+        let live = TrackNamespace::from_utf8_path("live");
+
+        let first = a.add(live.clone());
+        let second = a.add(live.clone());
+        assert!(a.wanted().is_empty());
+        assert_eq!(b.wanted(), HashSet::from([live.clone()]));
+
+        drop(first);
+        assert_eq!(b.wanted(), HashSet::from([live.clone()]));
+
+        drop(second);
+        assert!(b.wanted().is_empty());
+        assert!(interests.prefixes().is_empty());
+    }
+}
@@ -18,7 +18,7 @@
 //!
 //! // Configure and create the relay
 //! let relay = Relay::new(RelayConfig {
-//!     bind: "[::]:443".parse().unwrap(),
+//!     bind: vec!["[::]:443".parse().unwrap()],
 //!     tls: tls_config,
 //!     coordinator,
 //!     // ... other options
@@ -28,22 +28,92 @@
 //! relay.run().await?;
 //! ```
 
+mod admin;
+mod announce_batch;
+mod announce_limiter;
 mod api;
+mod archive;
+mod authorizer;
+mod bridge;
+mod cache;
+mod capture;
+mod close;
 mod consumer;
 mod coordinator;
+mod egress;
+mod file_config;
+mod flags;
+mod forward;
+mod gossip;
+#[cfg(unix)]
+mod handover;
+mod health;
+mod interests;
+mod liveness;
 mod local;
+mod log_filter;
+mod log_index;
+mod lookup_cache;
+mod migrate;
+mod perf;
+mod pin;
+mod preview;
+mod priority;
 mod producer;
+mod quota;
+mod registry;
 mod relay;
 mod remote;
+mod routing;
 mod session;
+mod sharded_coordinator;
+mod teardown;
+mod tenant;
+mod timed_coordinator;
+mod validate;
 mod web;
 
+pub use admin::*;
+pub use announce_batch::*;
+pub use announce_limiter::*;
 pub use api::*;
+pub use archive::*;
+pub use authorizer::*;
+pub use bridge::*;
+pub use cache::*;
+pub use capture::*;
+pub use close::*;
 pub use consumer::*;
 pub use coordinator::*;
+pub use egress::*;
+pub use file_config::*;
+pub use flags::*;
+pub use forward::*;
+pub use gossip::*;
+#[cfg(unix)]
+pub use handover::*;
+pub use health::*;
+pub use interests::*;
+pub use liveness::*;
 pub use local::*;
+pub use log_filter::*;
+pub use log_index::*;
+pub use lookup_cache::*;
+pub use migrate::*;
+pub use perf::*;
+pub use pin::*;
+pub use preview::*;
+pub use priority::*;
 pub use producer::*;
+pub use quota::*;
+pub use registry::*;
 pub use relay::*;
 pub use remote::*;
+pub use routing::*;
 pub use session::*;
+pub use sharded_coordinator::*;
+pub use teardown::*;
+pub use tenant::*;
+pub use timed_coordinator::*;
+pub use validate::*;
 pub use web::*;
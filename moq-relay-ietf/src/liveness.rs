@@ -0,0 +1,146 @@
+use std::{collections::HashMap, time::Duration};
+
+use moq_transport::{
+    coding::Location,
+    serve::{FullTrackName, TrackReader},
+};
+use tokio::time::Instant;
+
+/// When a publisher that keeps its session open, but stopped sending objects, is considered gone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LivenessConfig {
+    /// How long a namespace may go without a new object on any of its subscribed tracks before
+    /// it's stale. A namespace nobody subscribes to is never stale, having nothing to send.
+    pub timeout: Duration,
+
+    /// Withdraw stale namespaces from the `announce` destinations and gossip peers with
+    /// PUBLISH_NAMESPACE_DONE, and announce them again once they're live.
+    pub withdraw: bool,
+
+    /// End the subscriptions to a namespace once it's stale with PUBLISH_DONE, so subscribers
+    /// learn of it without polling TRACK_STATUS.
+    pub end_subscriptions: bool,
+}
+
+/// Watches the subscribed tracks of an announced namespace for new objects.
+pub(crate) struct Liveness {
+    timeout: Duration,
+
+    /// The live edge of each track when it last moved.
+    tracks: HashMap<FullTrackName, (TrackReader, Option<Location>)>,
+
+    /// When the last object arrived, or since when one was expected.
+    active: Instant,
+    stale: bool,
+}
+
+impl Liveness {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            timeout,
+            tracks: HashMap::new(),
+            active: Instant::now(),
+            stale: false,
+        }
+    }
+
+    /// How often to [Self::check], so a namespace goes stale at most a quarter late.
+    pub fn interval(&self) -> Duration {
+        (self.timeout / 4).max(Duration::from_millis(10))
+    }
+
+    /// Watch a newly subscribed track. The first gives the publisher the whole timeout to start.
+    pub fn watch(&mut self, name: FullTrackName, track: TrackReader) {
+        if self.tracks.is_empty() {
+            self.active = Instant::now();
+        }
+
+        let latest = track.latest();
+        self.tracks.insert(name, (track, latest));
+    }
+
+    /// Stop watching a track nobody subscribes to anymore.
+    pub fn unwatch(&mut self, name: &FullTrackName) {
+        self.tracks.remove(name);
+    }
+
+    /// Look for new objects, returning whether the namespace is stale if that changed.
+    ///
+    /// A stale namespace is live again once an object arrives, or once nobody subscribes to it.
+    pub fn check(&mut self, now: Instant) -> Option<bool> {
+        let mut moved = self.tracks.is_empty();
+        for (track, latest) in self.tracks.values_mut() {
+            let current = track.latest();
+            if current != *latest {
+                *latest = current;
+                moved = true;
+            }
+        }
+
+        if moved {
+            self.active = now;
+        }
+
+        let stale = match self.stale {
+            true => !moved,
+            false => now.duration_since(self.active) >= self.timeout,
+        };
+
+        (stale != self.stale).then(|| {
+            self.stale = stale;
+            stale
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moq_transport::{
+        coding::TrackNamespace,
+        serve::{Subgroup, Track},
+    };
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[tokio::test]
+    async fn goes_stale() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let (writer, reader) = Track::new(namespace.clone(), "video".to_string()).produce();
+        let name = FullTrackName {
+            namespace,
+            name: "video".to_string(),
+        };
+
+        let mut liveness = Liveness::new(100 * MS);
+        assert_eq!(liveness.interval(), 25 * MS);
+
+        // Nothing is expected without a subscription.
+        let start = Instant::now();
+        assert_eq!(liveness.check(start + 500 * MS), None);
+
+        liveness.watch(name.clone(), reader);
+        let start = Instant::now();
+        assert_eq!(liveness.check(start + 50 * MS), None);
+        assert_eq!(liveness.check(start + 100 * MS), Some(true));
+        assert_eq!(liveness.check(start + 200 * MS), None);
+
+        // Live again with the first object.
+        let mut subgroups = writer.subgroups().unwrap();
+        let mut subgroup = subgroups
+            .create(Subgroup {
+                group_id: 0,
+                subgroup_id: 0,
+                priority: 0,
+            })
+            .unwrap();
+        subgroup.write("frame".into()).unwrap();
+        assert_eq!(liveness.check(start + 250 * MS), Some(false));
+        assert_eq!(liveness.check(start + 300 * MS), None);
+        assert_eq!(liveness.check(start + 350 * MS), Some(true));
+
+        // Or once nobody is left waiting for one.
+        liveness.unwatch(&name);
+        assert_eq!(liveness.check(start + 400 * MS), Some(false));
+    }
+}
@@ -1,17 +1,76 @@
 use std::collections::hash_map;
 use std::collections::HashMap;
 
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex, Weak,
+};
 
 use moq_transport::{
     coding::TrackNamespace,
-    serve::{ServeError, TracksReader},
+    serve::{FullTrackName, ServeError, TrackReader, TracksReader},
 };
+use tokio::sync::{watch, Notify};
+
+use crate::NamespaceRegistration;
+
+pub use moq_config::DuplicatePolicy;
+
+/// The publishers serving a namespace, oldest first.
+#[derive(Default)]
+struct LocalNamespace {
+    publishers: Vec<LocalPublisher>,
+
+    /// The coordinator registration shared by the publishers, see [Registration::coordinator].
+    coordinator: Arc<tokio::sync::Mutex<Weak<NamespaceRegistration>>>,
+}
+
+struct LocalPublisher {
+    id: u64,
+    tracks: TracksReader,
+    replaced: Arc<Notify>,
+
+    /// Set while the publisher sends nothing on its subscribed tracks, see [Registration::stale].
+    stale: watch::Sender<bool>,
+}
+
+/// Creates the tracks of a namespace registered with [Locals::register_lazy], given the
+/// namespace subscribed to. Returns tracks for that namespace or a prefix of it, or None if
+/// there's nothing to produce.
+type TracksFactory = Arc<dyn Fn(&TrackNamespace) -> Option<TracksReader> + Send + Sync>;
+
+/// A namespace prefix whose tracks are produced on demand.
+struct LazyNamespace {
+    id: u64,
+    factory: TracksFactory,
+
+    /// The tracks produced so far, by their namespace.
+    produced: HashMap<TrackNamespace, TracksReader>,
+}
 
 /// Registry of local tracks
 #[derive(Clone)]
 pub struct Locals {
-    lookup: Arc<Mutex<HashMap<TrackNamespace, TracksReader>>>,
+    lookup: Arc<Mutex<HashMap<TrackNamespace, LocalNamespace>>>,
+
+    /// Namespace prefixes registered with [Self::register_lazy].
+    lazy: Arc<Mutex<HashMap<TrackNamespace, LazyNamespace>>>,
+
+    /// What to do with an announce for a namespace that is already registered.
+    duplicates: DuplicatePolicy,
+
+    /// Identifies each registration, so it only removes itself.
+    next_id: Arc<AtomicU64>,
+
+    /// Number of subscriptions currently served from each registered namespace.
+    subscribers: Arc<Mutex<HashMap<TrackNamespace, usize>>>,
+
+    /// Number of subscriptions currently sharing each track's upstream subscription.
+    tracks: Arc<Mutex<HashMap<FullTrackName, usize>>>,
+
+    /// Notified whenever a track loses its last subscription.
+    idle: Arc<Notify>,
 }
 
 impl Default for Locals {
@@ -25,64 +84,617 @@ impl Locals {
     pub fn new() -> Self {
         Self {
             lookup: Default::default(),
+            lazy: Default::default(),
+            duplicates: DuplicatePolicy::default(),
+            next_id: Default::default(),
+            subscribers: Default::default(),
+            tracks: Default::default(),
+            idle: Default::default(),
         }
     }
 
+    /// Handle announces of an already registered namespace with `policy`.
+    pub fn with_duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.duplicates = policy;
+        self
+    }
+
     /// Register new local tracks.
     pub async fn register(&mut self, tracks: TracksReader) -> anyhow::Result<Registration> {
         let namespace = tracks.namespace.clone();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let replaced = Arc::new(Notify::new());
+        let stale = watch::Sender::new(false);
 
         // Insert the tracks(TracksReader) into the lookup table
-        match self.lookup.lock().unwrap().entry(namespace.clone()) {
-            hash_map::Entry::Vacant(entry) => entry.insert(tracks),
-            hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
+        let mut lookup = self.lookup.lock().unwrap();
+        let entry = match lookup.entry(namespace.clone()) {
+            hash_map::Entry::Vacant(entry) => entry.insert(Default::default()),
+            hash_map::Entry::Occupied(entry) => match self.duplicates {
+                DuplicatePolicy::Reject => return Err(ServeError::Duplicate.into()),
+                DuplicatePolicy::Replace => {
+                    let entry = entry.into_mut();
+                    for publisher in entry.publishers.drain(..) {
+                        log::info!("replacing publisher of namespace: {}", namespace);
+                        publisher.replaced.notify_one();
+                    }
+                    entry
+                }
+                DuplicatePolicy::Merge => entry.into_mut(),
+            },
         };
 
+        entry.publishers.push(LocalPublisher {
+            id,
+            tracks,
+            replaced: replaced.clone(),
+            stale: stale.clone(),
+        });
+
         let registration = Registration {
             locals: self.clone(),
             namespace,
+            id,
+            replaced,
+            stale,
+            coordinator: entry.coordinator.clone(),
         };
 
         Ok(registration)
     }
 
+    /// Serve the namespaces under `prefix` without an announce, producing their tracks with
+    /// `factory` on the first subscribe, ex. to generate content on demand. Announced
+    /// publishers take precedence. The prefix is unregistered once the handle is dropped.
+    ///
+    /// The produced tracks are kept for later subscribes until their [TracksRequest] is dropped,
+    /// after which the next subscribe produces them again.
+    ///
+    /// [TracksRequest]: moq_transport::serve::TracksRequest
+    pub fn register_lazy(
+        &self,
+        prefix: TrackNamespace,
+        factory: impl Fn(&TrackNamespace) -> Option<TracksReader> + Send + Sync + 'static,
+    ) -> anyhow::Result<LazyRegistration> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        match self.lazy.lock().unwrap().entry(prefix.clone()) {
+            hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
+            hash_map::Entry::Vacant(entry) => entry.insert(LazyNamespace {
+                id,
+                factory: Arc::new(factory),
+                produced: HashMap::new(),
+            }),
+        };
+
+        Ok(LazyRegistration {
+            locals: self.clone(),
+            prefix,
+            id,
+        })
+    }
+
+    // The tracks produced for `namespace` by the longest lazy prefix, producing them if needed.
+    fn produce(&self, namespace: &TrackNamespace) -> Option<TracksReader> {
+        let factory = {
+            let mut lazy = self.lazy.lock().unwrap();
+            let entry = longest_prefix_mut(&mut lazy, namespace)?;
+            entry.produced.retain(|_, tracks| !tracks.is_closed());
+            if let Some(tracks) = longest_prefix(&entry.produced, namespace) {
+                return Some(tracks.clone());
+            }
+            entry.factory.clone()
+        };
+
+        // Called without the lock, so the factory may use the registry itself.
+        let tracks = factory(namespace)?;
+        log::info!("produced tracks for namespace: {}", tracks.namespace);
+
+        let mut lazy = self.lazy.lock().unwrap();
+        let entry = longest_prefix_mut(&mut lazy, namespace)?;
+        let tracks = entry
+            .produced
+            .entry(tracks.namespace.clone())
+            .or_insert(tracks);
+
+        Some(tracks.clone())
+    }
+
+    /// Remove every publisher of exactly `namespace`, telling each one as if it was replaced, see
+    /// [Registration::replaced]. Returns the number of publishers removed.
+    pub fn revoke(&self, namespace: &TrackNamespace) -> usize {
+        let entry = self.lookup.lock().unwrap().remove(namespace);
+        let publishers = entry.map(|entry| entry.publishers).unwrap_or_default();
+
+        for publisher in &publishers {
+            log::info!("revoking publisher of namespace: {}", namespace);
+            publisher.replaced.notify_one();
+        }
+
+        publishers.len()
+    }
+
     /// Retrieve local tracks by namespace using hierarchical prefix matching.
-    /// Returns the TracksReader for the longest matching namespace prefix.
+    /// Returns the TracksReader for the longest matching namespace prefix, from its newest publisher.
     pub fn retrieve(&self, namespace: &TrackNamespace) -> Option<TracksReader> {
-        let lookup = self.lookup.lock().unwrap();
+        self.retrieve_all(namespace).into_iter().next()
+    }
 
-        // Find the longest matching prefix
-        let mut best_match: Option<TracksReader> = None;
-        let mut best_len = 0;
+    /// Like [Self::retrieve], but returns every publisher of the namespace, newest first.
+    /// There is more than one only with [DuplicatePolicy::Merge].
+    pub fn retrieve_all(&self, namespace: &TrackNamespace) -> Vec<TracksReader> {
+        let lookup = self.lookup.lock().unwrap();
 
-        for (registered_ns, tracks) in lookup.iter() {
-            // Check if registered_ns is a prefix of namespace
-            if namespace.fields.len() >= registered_ns.fields.len() {
-                let is_prefix = registered_ns
-                    .fields
+        longest_prefix(&lookup, namespace)
+            .map(|local| {
+                local
+                    .publishers
                     .iter()
-                    .zip(namespace.fields.iter())
-                    .all(|(a, b)| a == b);
+                    .rev()
+                    .map(|publisher| publisher.tracks.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
 
-                if is_prefix && registered_ns.fields.len() > best_len {
-                    best_match = Some(tracks.clone());
-                    best_len = registered_ns.fields.len();
-                }
+    /// Whether the publisher [Self::retrieve] serves `namespace` from went stale, see
+    /// [crate::LivenessConfig].
+    pub fn is_stale(&self, namespace: &TrackNamespace) -> bool {
+        let lookup = self.lookup.lock().unwrap();
+
+        longest_prefix(&lookup, namespace)
+            .and_then(|local| local.publishers.last())
+            .is_some_and(|publisher| *publisher.stale.borrow())
+    }
+
+    /// Watch whether the publisher [Self::retrieve] serves `namespace` from is stale, or None
+    /// without one. The sender is dropped once that publisher unregisters.
+    pub fn watch_stale(&self, namespace: &TrackNamespace) -> Option<watch::Receiver<bool>> {
+        let lookup = self.lookup.lock().unwrap();
+
+        longest_prefix(&lookup, namespace)
+            .and_then(|local| local.publishers.last())
+            .map(|publisher| publisher.stale.subscribe())
+    }
+
+    /// List the registered namespaces with the number of subscriptions served from each.
+    pub fn namespaces(&self) -> Vec<(TrackNamespace, usize)> {
+        let lookup = self.lookup.lock().unwrap();
+        let subscribers = self.subscribers.lock().unwrap();
+
+        lookup
+            .keys()
+            .map(|namespace| {
+                let count = subscribers.get(namespace).copied().unwrap_or_default();
+                (namespace.clone(), count)
+            })
+            .collect()
+    }
+
+    /// List the local tracks with the number of subscriptions sharing each.
+    pub fn tracks(&self) -> Vec<(FullTrackName, usize)> {
+        self.tracks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(track, count)| (track.clone(), *count))
+            .collect()
+    }
+
+    /// Count a subscription to `track`, served from the registered `namespace`, until the guard is dropped.
+    pub fn subscribed(&self, namespace: &TrackNamespace, track: &FullTrackName) -> SubscriberGuard {
+        *self
+            .subscribers
+            .lock()
+            .unwrap()
+            .entry(namespace.clone())
+            .or_default() += 1;
+        *self
+            .tracks
+            .lock()
+            .unwrap()
+            .entry(track.clone())
+            .or_default() += 1;
+
+        SubscriberGuard {
+            locals: self.clone(),
+            namespace: namespace.clone(),
+            track: track.clone(),
+        }
+    }
+
+    /// Subscribe to `track_name` in `namespace` from its local publishers, or from the tracks
+    /// produced for it without any, see [Self::register_lazy]. Counted with [Self::subscribed]
+    /// until the guard is dropped.
+    ///
+    /// Merged publishers serve different tracks, so each is tried until one has it.
+    pub async fn subscribe(
+        &self,
+        namespace: &TrackNamespace,
+        track_name: &str,
+        trace_id: Option<String>,
+    ) -> Option<(TrackReader, SubscriberGuard)> {
+        let name = FullTrackName {
+            namespace: namespace.clone(),
+            name: track_name.to_string(),
+        };
+
+        let locals = self.retrieve_all(namespace);
+        let locals_empty = locals.is_empty();
+        let last = locals.len().saturating_sub(1);
+        for (index, mut local) in locals.into_iter().enumerate() {
+            // Counted before requesting the track, so the upstream subscription isn't dropped as idle.
+            let subscriber = self.subscribed(&local.namespace, &name);
+
+            // Pass the full requested namespace, not the announced prefix
+            let Some(track) =
+                local.subscribe_with_trace_id(namespace.clone(), track_name, trace_id.clone())
+            else {
+                continue;
+            };
+
+            if index < last && track.mode().await.is_err() {
+                log::debug!(
+                    "track not served by this publisher, trying the next: {:?}",
+                    track.info
+                );
+                continue;
             }
+
+            return Some((track, subscriber));
         }
 
-        best_match
+        if !locals_empty {
+            return None;
+        }
+
+        let mut tracks = self.produce(namespace)?;
+        let subscriber = self.subscribed(&tracks.namespace, &name);
+        let track = tracks.subscribe_with_trace_id(namespace.clone(), track_name, trace_id)?;
+        Some((track, subscriber))
+    }
+
+    /// Whether `track` has subscriptions counted with [Self::subscribed].
+    pub fn is_subscribed(&self, track: &FullTrackName) -> bool {
+        self.tracks.lock().unwrap().contains_key(track)
+    }
+
+    /// Run `release` if `track` has no subscriptions, holding off new ones until it returns.
+    pub fn release<T>(&self, track: &FullTrackName, release: impl FnOnce() -> T) -> Option<T> {
+        let tracks = self.tracks.lock().unwrap();
+        if tracks.contains_key(track) {
+            return None;
+        }
+
+        Some(release())
+    }
+
+    /// Wait until `track` has no subscriptions, so its upstream subscription can be dropped.
+    pub async fn idle(&self, track: &FullTrackName) {
+        loop {
+            let notified = self.idle.notified();
+            if !self.tracks.lock().unwrap().contains_key(track) {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+// The registered namespace with the longest prefix of `namespace`.
+fn longest_prefix<'a, T>(
+    lookup: &'a HashMap<TrackNamespace, T>,
+    namespace: &TrackNamespace,
+) -> Option<&'a T> {
+    let mut best_match: Option<&T> = None;
+    let mut best_len = 0;
+
+    for (registered_ns, local) in lookup.iter() {
+        // Check if registered_ns is a prefix of namespace
+        if namespace.fields.len() >= registered_ns.fields.len() {
+            let is_prefix = registered_ns
+                .fields
+                .iter()
+                .zip(namespace.fields.iter())
+                .all(|(a, b)| a == b);
+
+            if is_prefix && registered_ns.fields.len() > best_len {
+                best_match = Some(local);
+                best_len = registered_ns.fields.len();
+            }
+        }
+    }
+
+    best_match
+}
+
+// Like [longest_prefix], borrowing the match mutably.
+fn longest_prefix_mut<'a, T>(
+    lookup: &'a mut HashMap<TrackNamespace, T>,
+    namespace: &TrackNamespace,
+) -> Option<&'a mut T> {
+    lookup
+        .iter_mut()
+        .filter(|(prefix, _)| namespace.has_prefix(prefix))
+        .max_by_key(|(prefix, _)| prefix.fields.len())
+        .map(|(_, value)| value)
+}
+
+pub struct SubscriberGuard {
+    locals: Locals,
+    namespace: TrackNamespace,
+    track: FullTrackName,
+}
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        let mut subscribers = self.locals.subscribers.lock().unwrap();
+        if let hash_map::Entry::Occupied(mut entry) = subscribers.entry(self.namespace.clone()) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+
+        let mut tracks = self.locals.tracks.lock().unwrap();
+        if let hash_map::Entry::Occupied(mut entry) = tracks.entry(self.track.clone()) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+                self.locals.idle.notify_waiters();
+            }
+        }
     }
 }
 
 pub struct Registration {
     locals: Locals,
     namespace: TrackNamespace,
+    id: u64,
+    replaced: Arc<Notify>,
+    stale: watch::Sender<bool>,
+    coordinator: Arc<tokio::sync::Mutex<Weak<NamespaceRegistration>>>,
+}
+
+impl Registration {
+    /// Set to mark this publisher stale, or clear once it's live again, see [Locals::is_stale].
+    /// Shared, so it can be set after the registration is handed to a teardown.
+    pub fn stale(&self) -> watch::Sender<bool> {
+        self.stale.clone()
+    }
+
+    /// Resolves once a newer publisher of the namespace took over, see [DuplicatePolicy::Replace],
+    /// or the namespace was revoked, see [Locals::revoke].
+    pub fn replaced(&self) -> impl Future<Output = ()> + Send + 'static {
+        let replaced = self.replaced.clone();
+        async move { replaced.notified().await }
+    }
+
+    /// Register the namespace with the coordinator using `register`, unless another publisher of
+    /// the namespace already did, in which case its registration is shared.
+    ///
+    /// The namespace stays registered until every publisher's handle is dropped, so a replaced
+    /// publisher leaving doesn't unregister its successor.
+    pub async fn coordinator<F, Fut, E>(&self, register: F) -> Result<NamespaceRegistration, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<NamespaceRegistration, E>>,
+    {
+        let mut shared = self.coordinator.lock().await;
+        if let Some(registration) = shared.upgrade() {
+            return Ok(NamespaceRegistration::new(registration));
+        }
+
+        let registration = Arc::new(register().await?);
+        *shared = Arc::downgrade(&registration);
+
+        Ok(NamespaceRegistration::new(registration))
+    }
 }
 
 /// Deregister local tracks on drop.
 impl Drop for Registration {
     fn drop(&mut self) {
-        self.locals.lookup.lock().unwrap().remove(&self.namespace);
+        let mut lookup = self.locals.lookup.lock().unwrap();
+        if let hash_map::Entry::Occupied(mut entry) = lookup.entry(self.namespace.clone()) {
+            entry
+                .get_mut()
+                .publishers
+                .retain(|publisher| publisher.id != self.id);
+            if entry.get().publishers.is_empty() {
+                entry.remove();
+            }
+        }
+    }
+}
+
+/// Unregisters a prefix registered with [Locals::register_lazy] once dropped.
+pub struct LazyRegistration {
+    locals: Locals,
+    prefix: TrackNamespace,
+    id: u64,
+}
+
+impl Drop for LazyRegistration {
+    fn drop(&mut self) {
+        let mut lazy = self.locals.lazy.lock().unwrap();
+        if let hash_map::Entry::Occupied(entry) = lazy.entry(self.prefix.clone()) {
+            if entry.get().id == self.id {
+                entry.remove();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use moq_transport::serve::{Tracks, TracksRequest};
+
+    #[tokio::test]
+    async fn subscriber_counts() {
+        let mut locals = Locals::new();
+        let namespace = TrackNamespace::from_utf8_path("test/ns");
+        let (_writer, _request, reader) = Tracks::new(namespace.clone()).produce();
+        let _registration = locals.register(reader).await.unwrap();
+
+        assert_eq!(locals.namespaces(), vec![(namespace.clone(), 0)]);
+
+        let video = FullTrackName {
+            namespace: namespace.clone(),
+            name: "video".to_string(),
+        };
+        let audio = FullTrackName {
+            namespace: namespace.clone(),
+            name: "audio".to_string(),
+        };
+
+        let first = locals.subscribed(&namespace, &video);
+        let second = locals.subscribed(&namespace, &video);
+        let third = locals.subscribed(&namespace, &audio);
+        assert_eq!(locals.namespaces(), vec![(namespace.clone(), 3)]);
+
+        let mut tracks = locals.tracks();
+        tracks.sort_by(|a, b| a.0.name.cmp(&b.0.name));
+        assert_eq!(tracks, vec![(audio.clone(), 1), (video.clone(), 2)]);
+
+        // The track stays busy until its last subscription is dropped.
+        drop(first);
+        let idle = locals.idle(&video);
+        tokio::pin!(idle);
+        assert!(futures::poll!(idle.as_mut()).is_pending());
+
+        drop(second);
+        idle.await;
+        assert_eq!(locals.tracks(), vec![(audio, 1)]);
+
+        drop(third);
+        assert_eq!(locals.namespaces(), vec![(namespace, 0)]);
+    }
+
+    #[tokio::test]
+    async fn stale_publishers() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let tracks = || Tracks::new(namespace.clone()).produce().2;
+        let room = TrackNamespace::from_utf8_path("live/room");
+
+        let mut locals = Locals::new().with_duplicate_policy(DuplicatePolicy::Merge);
+        let first = locals.register(tracks()).await.unwrap();
+        assert!(!locals.is_stale(&room));
+
+        let mut stale = locals.watch_stale(&room).unwrap();
+        first.stale().send_replace(true);
+        assert!(locals.is_stale(&room));
+        assert!(stale.has_changed().unwrap() && *stale.borrow_and_update());
+        assert!(!locals.is_stale(&TrackNamespace::from_utf8_path("vod")));
+
+        // Subscriptions are served by the newest publisher, so only it counts.
+        let second = locals.register(tracks()).await.unwrap();
+        assert!(!locals.is_stale(&room));
+        drop(second);
+        assert!(locals.is_stale(&room));
+    }
+
+    #[tokio::test]
+    async fn duplicate_policies() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let tracks = || Tracks::new(namespace.clone()).produce().2;
+
+        let mut locals = Locals::new();
+        let _first = locals.register(tracks()).await.unwrap();
+        assert!(locals.register(tracks()).await.is_err());
+
+        // The newer publisher takes over and the old one is told.
+        let mut locals = Locals::new().with_duplicate_policy(DuplicatePolicy::Replace);
+        let first = locals.register(tracks()).await.unwrap();
+        let second = locals.register(tracks()).await.unwrap();
+        first.replaced().await;
+        assert_eq!(locals.retrieve_all(&namespace).len(), 1);
+
+        // The replaced publisher leaving doesn't deregister its successor.
+        drop(first);
+        assert_eq!(locals.namespaces().len(), 1);
+        drop(second);
+        assert!(locals.namespaces().is_empty());
+
+        // Revoking a namespace tells every publisher, and forgets it.
+        let mut locals = Locals::new().with_duplicate_policy(DuplicatePolicy::Merge);
+        let first = locals.register(tracks()).await.unwrap();
+        let second = locals.register(tracks()).await.unwrap();
+        assert_eq!(locals.revoke(&namespace), 2);
+        first.replaced().await;
+        second.replaced().await;
+        assert!(locals.namespaces().is_empty());
+        assert_eq!(locals.revoke(&namespace), 0);
+        drop((first, second));
+
+        let mut locals = Locals::new().with_duplicate_policy(DuplicatePolicy::Merge);
+        let first = locals.register(tracks()).await.unwrap();
+        let second = locals.register(tracks()).await.unwrap();
+        assert_eq!(locals.retrieve_all(&namespace).len(), 2);
+
+        // The coordinator is only asked once while the namespace has publishers.
+        let registered = AtomicU64::new(0);
+        let register = || async {
+            registered.fetch_add(1, Ordering::Relaxed);
+            Ok::<_, ()>(NamespaceRegistration::new(()))
+        };
+        let first_coordinator = first.coordinator(register).await.unwrap();
+        let second_coordinator = second.coordinator(register).await.unwrap();
+        assert_eq!(registered.load(Ordering::Relaxed), 1);
+
+        drop((first, first_coordinator));
+        let third_coordinator = second.coordinator(register).await.unwrap();
+        assert_eq!(registered.load(Ordering::Relaxed), 1);
+
+        // Registered again once every handle was dropped.
+        drop((second_coordinator, third_coordinator));
+        let _fourth_coordinator = second.coordinator(register).await.unwrap();
+        assert_eq!(registered.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn produces_lazy_namespaces() {
+        let locals = Locals::new();
+        let requests: Arc<Mutex<Vec<TracksRequest>>> = Default::default();
+        let produced = requests.clone();
+        let registration = locals
+            .register_lazy(TrackNamespace::from_utf8_path("gen"), move |namespace| {
+                let (_writer, request, reader) = Tracks::new(namespace.clone()).produce();
+                produced.lock().unwrap().push(request);
+                Some(reader)
+            })
+            .unwrap();
+        assert!(locals
+            .register_lazy(TrackNamespace::from_utf8_path("gen"), |_| None)
+            .is_err());
+
+        let namespace = TrackNamespace::from_utf8_path("gen/clock");
+        let (track, _subscriber) = locals.subscribe(&namespace, "seconds", None).await.unwrap();
+        assert_eq!(track.namespace, namespace);
+        assert!(locals
+            .subscribe(&namespace, "minutes", None)
+            .await
+            .is_some());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Produced again once the previous production ended, tracks and all.
+        requests.lock().unwrap().clear();
+        assert!(locals
+            .subscribe(&namespace, "seconds", None)
+            .await
+            .is_some());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Announced publishers come first.
+        let (_writer, _request, reader) = Tracks::new(namespace.clone()).produce();
+        let registered = locals.clone().register(reader).await.unwrap();
+        assert!(locals.subscribe(&namespace, "hours", None).await.is_some());
+        assert_eq!(requests.lock().unwrap().len(), 1);
+        drop(registered);
+
+        drop(registration);
+        let other = TrackNamespace::from_utf8_path("gen/other");
+        assert!(locals.subscribe(&other, "seconds", None).await.is_none());
     }
 }
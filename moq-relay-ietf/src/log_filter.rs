@@ -0,0 +1,129 @@
+use std::sync::{Arc, RwLock};
+
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// The log filter of the process, which the admin API can change at runtime.
+///
+/// Takes the place of `env_logger::init()`: the initial filter is read from `RUST_LOG`, with the
+/// same directive syntax, ex. `info,moq_relay_ietf=debug`.
+#[derive(Clone)]
+pub struct LogFilter {
+    state: Arc<RwLock<LogFilterState>>,
+}
+
+struct LogFilterState {
+    spec: String,
+    filter: env_filter::Filter,
+}
+
+impl LogFilter {
+    /// Install the process logger, filtered by `RUST_LOG` until [LogFilter::set] changes it.
+    pub fn init() -> anyhow::Result<Self> {
+        let spec = std::env::var("RUST_LOG").unwrap_or_default();
+        let filter = Self::parse(&spec).unwrap_or_else(|err| {
+            eprintln!("ignoring RUST_LOG: {}", err);
+            env_filter::Builder::new().build()
+        });
+
+        log::set_max_level(filter.filter());
+        let this = Self {
+            state: Arc::new(RwLock::new(LogFilterState { spec, filter })),
+        };
+
+        // Format like env_logger::init(), leaving the filtering to us.
+        let inner = env_logger::Builder::new()
+            .filter_level(LevelFilter::Trace)
+            .build();
+        log::set_boxed_logger(Box::new(FilteredLogger {
+            inner,
+            filter: this.clone(),
+        }))?;
+
+        Ok(this)
+    }
+
+    /// The directives currently in effect.
+    pub fn spec(&self) -> String {
+        self.state.read().unwrap().spec.clone()
+    }
+
+    /// Replace the directives, ex. `debug` or `warn,moq_transport=trace`.
+    pub fn set(&self, spec: &str) -> anyhow::Result<()> {
+        let filter = Self::parse(spec)?;
+        log::set_max_level(filter.filter());
+
+        let previous = std::mem::replace(
+            &mut *self.state.write().unwrap(),
+            LogFilterState {
+                spec: spec.to_string(),
+                filter,
+            },
+        );
+
+        // Logged once the lock is released, since the logger takes it too.
+        log::info!("log filter changed: {:?} -> {:?}", previous.spec, spec);
+
+        Ok(())
+    }
+
+    // env_filter skips invalid directives with a warning, so reject them up front.
+    fn parse(spec: &str) -> anyhow::Result<env_filter::Filter> {
+        let (directives, regex) = match spec.split_once('/') {
+            Some((directives, regex)) => (directives, Some(regex)),
+            None => (spec, None),
+        };
+        anyhow::ensure!(
+            !regex.is_some_and(|regex| regex.contains('/')),
+            "invalid log filter: too many '/'s"
+        );
+
+        for directive in directives.split(',').map(str::trim) {
+            if let Some((module, level)) = directive.split_once('=') {
+                anyhow::ensure!(
+                    !module.is_empty() && level.trim().parse::<LevelFilter>().is_ok(),
+                    "invalid log directive: {:?}",
+                    directive
+                );
+            }
+        }
+
+        Ok(env_filter::Builder::new().parse(spec).build())
+    }
+}
+
+struct FilteredLogger {
+    inner: env_logger::Logger,
+    filter: LogFilter,
+}
+
+impl Log for FilteredLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.filter.state.read().unwrap().filter.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.filter.state.read().unwrap().filter.matches(record) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let filter = LogFilter::parse("warn,moq_transport=trace").unwrap();
+        assert_eq!(filter.filter(), LevelFilter::Trace);
+        assert_eq!(LogFilter::parse("").unwrap().filter(), LevelFilter::Error);
+
+        assert!(LogFilter::parse("moq_transport=loud").is_err());
+        assert!(LogFilter::parse("=debug").is_err());
+        assert!(LogFilter::parse("info/a/b").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    net,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use moq_transport::{
+    coding::TrackNamespace,
+    session::{Publisher, Subscriber},
+};
+use serde::Serialize;
+
+/// The sessions qlog and mlog files were written for, by connection ID, so the web server's log
+/// index can be searched by namespace and tell who each peer was.
+///
+/// Only sessions since the relay started are known, and only the most recent closed ones.
+#[derive(Clone, Default)]
+pub struct LogIndex {
+    state: Arc<Mutex<LogIndexState>>,
+}
+
+#[derive(Default)]
+struct LogIndexState {
+    sessions: HashMap<String, LogSession>,
+
+    /// Closed sessions, oldest first, forgotten past [LogIndex::CAPACITY].
+    closed: VecDeque<String>,
+}
+
+struct LogSession {
+    peer: net::SocketAddr,
+    connected_at: SystemTime,
+    closed_at: Option<SystemTime>,
+    namespaces: HashSet<TrackNamespace>,
+
+    // Our side of the session while it's open, to find the namespaces it uses.
+    subscriber: Option<Subscriber>,
+    publisher: Option<Publisher>,
+}
+
+impl LogSession {
+    // Add the namespaces the peer currently announces to us, or subscribes to on either side.
+    fn refresh(&mut self) {
+        if let Some(subscriber) = &self.subscriber {
+            let announces = subscriber.announces().into_iter().map(|a| a.namespace);
+            let subscriptions = subscriber
+                .subscriptions()
+                .into_iter()
+                .map(|s| s.track_namespace);
+            self.namespaces.extend(announces.chain(subscriptions));
+        }
+
+        if let Some(publisher) = &self.publisher {
+            let subscriptions = publisher.subscriptions().into_iter();
+            self.namespaces
+                .extend(subscriptions.map(|s| s.track_namespace));
+        }
+    }
+}
+
+/// The session of a log file, as listed by the web server's log index.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LogSessionInfo {
+    pub peer: net::SocketAddr,
+    /// Connection time, in seconds since the Unix epoch.
+    pub connected_at: u64,
+    /// How long the session lasted, or has lasted so far while it's open.
+    pub duration_secs: f64,
+    pub open: bool,
+    /// The namespaces the peer announced or subscribed to, as seen whenever the index was read
+    /// and when the session closed, so a subscription that came and went in between is missed.
+    pub namespaces: Vec<String>,
+}
+
+impl LogIndex {
+    /// How many closed sessions are remembered.
+    pub const CAPACITY: usize = 10_000;
+
+    /// Index the session of connection `cid` until the returned guard is dropped.
+    ///
+    /// `subscriber` and `publisher` are our side of the session, as for [crate::Admin::register_session].
+    pub fn open(
+        &self,
+        cid: String,
+        peer: net::SocketAddr,
+        subscriber: Option<Subscriber>,
+        publisher: Option<Publisher>,
+    ) -> LogSessionGuard {
+        self.state.lock().unwrap().sessions.insert(
+            cid.clone(),
+            LogSession {
+                peer,
+                connected_at: SystemTime::now(),
+                closed_at: None,
+                namespaces: HashSet::new(),
+                subscriber,
+                publisher,
+            },
+        );
+
+        LogSessionGuard {
+            index: self.clone(),
+            cid,
+        }
+    }
+
+    fn close(&self, cid: &str) {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(session) = state.sessions.get_mut(cid) else {
+            return;
+        };
+        session.refresh();
+        session.closed_at = Some(SystemTime::now());
+        session.subscriber = None;
+        session.publisher = None;
+
+        state.closed.push_back(cid.to_string());
+        while state.closed.len() > Self::CAPACITY {
+            if let Some(oldest) = state.closed.pop_front() {
+                state.sessions.remove(&oldest);
+            }
+        }
+    }
+
+    /// The session of connection `cid`, if it's known.
+    pub fn get(&self, cid: &str) -> Option<LogSessionInfo> {
+        let mut state = self.state.lock().unwrap();
+        let session = state.sessions.get_mut(cid)?;
+        session.refresh();
+
+        let end = session.closed_at.unwrap_or_else(SystemTime::now);
+        let mut namespaces: Vec<_> = session
+            .namespaces
+            .iter()
+            .map(|ns| ns.to_utf8_path())
+            .collect();
+        namespaces.sort();
+
+        Some(LogSessionInfo {
+            peer: session.peer,
+            connected_at: session
+                .connected_at
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or_default(),
+            duration_secs: end
+                .duration_since(session.connected_at)
+                .unwrap_or(Duration::ZERO)
+                .as_secs_f64(),
+            open: session.closed_at.is_none(),
+            namespaces,
+        })
+    }
+
+    /// Whether the session of connection `cid` used a namespace under `prefix`.
+    pub fn has_namespace(&self, cid: &str, prefix: &TrackNamespace) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(session) = state.sessions.get_mut(cid) else {
+            return false;
+        };
+        session.refresh();
+
+        session
+            .namespaces
+            .iter()
+            .any(|namespace| namespace.has_prefix(prefix))
+    }
+}
+
+/// Marks the session closed in the [LogIndex] once dropped.
+pub struct LogSessionGuard {
+    index: LogIndex,
+    cid: String,
+}
+
+impl Drop for LogSessionGuard {
+    fn drop(&mut self) {
+        self.index.close(&self.cid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forgets_oldest() {
+        let index = LogIndex::default();
+        let peer = "127.0.0.1:4443".parse().unwrap();
+
+        let open = index.open("open".to_string(), peer, None, None);
+        let info = index.get("open").unwrap();
+        assert!(info.open);
+        assert_eq!(info.peer, peer);
+
+        for i in 0..=LogIndex::CAPACITY {
+            drop(index.open(i.to_string(), peer, None, None));
+        }
+        assert!(!index.get("1").unwrap().open);
+        assert_eq!(index.get("0"), None);
+
+        // Open sessions are never forgotten.
+        assert!(index.get("open").is_some());
+        drop(open);
+        assert!(!index.get("open").unwrap().open);
+    }
+}
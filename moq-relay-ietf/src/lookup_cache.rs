@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use moq_native_ietf::quic;
+use moq_transport::coding::TrackNamespace;
+use serde::Serialize;
+use url::Url;
+
+use crate::{Coordinator, CoordinatorError, CoordinatorResult, NamespaceOrigin};
+
+/// How long the coordinator's namespace lookups are reused for subscriptions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LookupCacheConfig {
+    /// How long an origin found by the coordinator is used without asking again.
+    /// It's dropped sooner if connecting to it fails.
+    pub ttl: Duration,
+
+    /// How long a namespace the coordinator doesn't know stays unknown, so a namespace
+    /// announced elsewhere meanwhile can't be subscribed to until then. Zero disables caching
+    /// misses.
+    pub negative_ttl: Duration,
+}
+
+/// Caches the origins of namespaces, so each subscription doesn't cost a coordinator round trip.
+///
+/// Lookups failing for any reason but an unknown namespace, ex. a timeout, aren't cached.
+#[derive(Clone)]
+pub struct LookupCache {
+    config: LookupCacheConfig,
+    state: Arc<Mutex<LookupCacheState>>,
+}
+
+#[derive(Default)]
+struct LookupCacheState {
+    entries: HashMap<TrackNamespace, Entry>,
+    hits: u64,
+    negative_hits: u64,
+    misses: u64,
+    invalidations: u64,
+}
+
+struct Entry {
+    expires: Instant,
+
+    /// None if the coordinator didn't know the namespace.
+    origin: Option<(NamespaceOrigin, Option<quic::Client>)>,
+}
+
+/// A snapshot of the [LookupCache].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct LookupCacheStats {
+    /// Namespaces currently cached, found or not.
+    pub entries: usize,
+    /// Lookups answered with a cached origin.
+    pub hits: u64,
+    /// Lookups answered with a cached miss.
+    pub negative_hits: u64,
+    /// Lookups passed on to the coordinator.
+    pub misses: u64,
+    /// The share of lookups answered from the cache.
+    pub hit_rate: f64,
+    /// Origins dropped before their TTL because connecting to them failed.
+    pub invalidations: u64,
+}
+
+impl LookupCache {
+    pub fn new(config: LookupCacheConfig) -> Self {
+        Self {
+            config,
+            state: Default::default(),
+        }
+    }
+
+    /// Look up the origin of `namespace`, asking `coordinator` unless it's cached.
+    pub async fn lookup(
+        &self,
+        coordinator: &dyn Coordinator,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+        {
+            let mut state = self.state.lock().unwrap();
+            let now = Instant::now();
+
+            let cached = state
+                .entries
+                .get(namespace)
+                .filter(|entry| entry.expires > now)
+                .map(|entry| entry.origin.clone());
+
+            match cached {
+                Some(Some(origin)) => {
+                    state.hits += 1;
+                    return Ok(origin);
+                }
+                Some(None) => {
+                    state.negative_hits += 1;
+                    return Err(CoordinatorError::NamespaceNotFound);
+                }
+                None => {
+                    state.misses += 1;
+                    state.entries.retain(|_, entry| entry.expires > now);
+                }
+            }
+        }
+
+        let res = coordinator.lookup(namespace).await;
+
+        let (ttl, origin) = match &res {
+            Ok(origin) => (self.config.ttl, Some(origin.clone())),
+            Err(CoordinatorError::NamespaceNotFound) => (self.config.negative_ttl, None),
+            Err(_) => return res,
+        };
+
+        if !ttl.is_zero() {
+            self.state.lock().unwrap().entries.insert(
+                namespace.clone(),
+                Entry {
+                    expires: Instant::now() + ttl,
+                    origin,
+                },
+            );
+        }
+
+        res
+    }
+
+    /// Drop the namespaces served by the origin at `url`, returning how many, so they're looked
+    /// up again, ex. after the connection to it failed.
+    pub fn invalidate_origin(&self, url: &Url) -> usize {
+        let mut state = self.state.lock().unwrap();
+
+        let before = state.entries.len();
+        state.entries.retain(|_, entry| match &entry.origin {
+            Some((origin, _)) => origin.url() != *url,
+            None => true,
+        });
+
+        let invalidated = before - state.entries.len();
+        state.invalidations += invalidated as u64;
+        invalidated
+    }
+
+    pub fn stats(&self) -> LookupCacheStats {
+        let state = self.state.lock().unwrap();
+        let lookups = state.hits + state.negative_hits + state.misses;
+
+        LookupCacheStats {
+            entries: state.entries.len(),
+            hits: state.hits,
+            negative_hits: state.negative_hits,
+            misses: state.misses,
+            hit_rate: match lookups {
+                0 => 0.0,
+                lookups => (state.hits + state.negative_hits) as f64 / lookups as f64,
+            },
+            invalidations: state.invalidations,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NamespaceRegistration;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    /// Knows the namespaces under `live`, served by one origin, and counts lookups.
+    #[derive(Default)]
+    struct Counting {
+        lookups: AtomicU64,
+        unavailable: bool,
+    }
+
+    #[async_trait]
+    impl Coordinator for Counting {
+        async fn register_namespace(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            Ok(NamespaceRegistration::new(()))
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            if self.unavailable {
+                return Err(CoordinatorError::Timeout);
+            }
+            if !namespace.to_utf8_path().starts_with("/live") {
+                return Err(CoordinatorError::NamespaceNotFound);
+            }
+
+            let url = Url::parse("https://origin.example.com").unwrap();
+            Ok((NamespaceOrigin::new(namespace.clone(), url, None), None))
+        }
+    }
+
+    fn cache(ttl: u64, negative_ttl: u64) -> LookupCache {
+        LookupCache::new(LookupCacheConfig {
+            ttl: Duration::from_millis(ttl),
+            negative_ttl: Duration::from_millis(negative_ttl),
+        })
+    }
+
+    #[tokio::test]
+    async fn caches_lookups() {
+        let coordinator = Counting::default();
+        let cache = cache(50, 50);
+        let live = TrackNamespace::from_utf8_path("live/a");
+        let vod = TrackNamespace::from_utf8_path("vod/a");
+
+        for _ in 0..3 {
+            assert!(cache.lookup(&coordinator, &live).await.is_ok());
+            assert!(matches!(
+                cache.lookup(&coordinator, &vod).await,
+                Err(CoordinatorError::NamespaceNotFound)
+            ));
+        }
+        assert_eq!(coordinator.lookups.load(Ordering::Relaxed), 2);
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.negative_hits, stats.misses), (2, 2, 2));
+        assert!((stats.hit_rate - 4.0 / 6.0).abs() < 1e-9);
+
+        // Both expire.
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        cache.lookup(&coordinator, &live).await.unwrap();
+        assert!(cache.lookup(&coordinator, &vod).await.is_err());
+        assert_eq!(coordinator.lookups.load(Ordering::Relaxed), 4);
+        assert_eq!(cache.stats().entries, 2);
+    }
+
+    #[tokio::test]
+    async fn skips_failures() {
+        let coordinator = Counting {
+            unavailable: true,
+            ..Default::default()
+        };
+        let cache = cache(1000, 0);
+        let live = TrackNamespace::from_utf8_path("live/a");
+
+        for _ in 0..2 {
+            assert!(matches!(
+                cache.lookup(&coordinator, &live).await,
+                Err(CoordinatorError::Timeout)
+            ));
+        }
+        assert_eq!(coordinator.lookups.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.stats().entries, 0);
+
+        // Misses aren't cached with a zero negative TTL either.
+        let coordinator = Counting::default();
+        let vod = TrackNamespace::from_utf8_path("vod/a");
+        for _ in 0..2 {
+            assert!(cache.lookup(&coordinator, &vod).await.is_err());
+        }
+        assert_eq!(coordinator.lookups.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidates_origins() {
+        let coordinator = Counting::default();
+        let cache = cache(1000, 1000);
+
+        for path in ["live/a", "live/b", "vod/a"] {
+            let _ = cache
+                .lookup(&coordinator, &TrackNamespace::from_utf8_path(path))
+                .await;
+        }
+
+        let other = Url::parse("https://other.example.com").unwrap();
+        assert_eq!(cache.invalidate_origin(&other), 0);
+
+        let origin = Url::parse("https://origin.example.com").unwrap();
+        assert_eq!(cache.invalidate_origin(&origin), 2);
+
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.invalidations), (1, 2));
+
+        cache
+            .lookup(&coordinator, &TrackNamespace::from_utf8_path("live/a"))
+            .await
+            .unwrap();
+        assert_eq!(coordinator.lookups.load(Ordering::Relaxed), 4);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context;
+use moq_api::Origin;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// How [migrate_registrations] treats the namespaces the target already has.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MigrateOptions {
+    /// Report what would change without writing anything.
+    pub dry_run: bool,
+
+    /// Replace the target's registration of a namespace registered to another origin, instead of
+    /// reporting the conflict and leaving it.
+    pub overwrite: bool,
+}
+
+/// What [migrate_registrations] did, or would do in a dry run.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    pub dry_run: bool,
+
+    /// Namespaces registered with the target.
+    pub migrated: Vec<String>,
+
+    /// Namespaces the target already had, with the same origin.
+    pub unchanged: Vec<String>,
+
+    /// Namespaces the target has with another origin.
+    pub conflicts: Vec<MigrationConflict>,
+}
+
+impl MigrationReport {
+    /// The conflicts left as they were, which still need an operator's attention.
+    pub fn unresolved(&self) -> usize {
+        self.conflicts
+            .iter()
+            .filter(|conflict| !conflict.overwritten)
+            .count()
+    }
+}
+
+/// A namespace the source and the target register to different origins.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MigrationConflict {
+    pub namespace: String,
+
+    /// The relay the source registers the namespace to.
+    pub source: Url,
+
+    /// The relay the target registers it to, maybe the same one with other metadata.
+    pub target: Url,
+
+    /// Whether the source's registration replaced the target's, see [MigrateOptions::overwrite].
+    pub overwritten: bool,
+}
+
+// The file written by the file coordinator of the moq-relay-ietf binary, see --coordinator-file.
+#[derive(Deserialize)]
+struct CoordinatorFile {
+    #[serde(default)]
+    namespaces: BTreeMap<String, CoordinatorFileEntry>,
+}
+
+// A bare URL, unless the relay registered metadata with the namespace.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CoordinatorFileEntry {
+    Url(Url),
+    Origin {
+        url: Url,
+        #[serde(default)]
+        metadata: BTreeMap<String, String>,
+    },
+}
+
+impl From<CoordinatorFileEntry> for Origin {
+    fn from(entry: CoordinatorFileEntry) -> Self {
+        match entry {
+            CoordinatorFileEntry::Url(url) => Origin {
+                url,
+                metadata: Default::default(),
+            },
+            CoordinatorFileEntry::Origin { url, metadata } => Origin { url, metadata },
+        }
+    }
+}
+
+/// Read every registration of a file coordinator, keyed by namespace.
+pub fn read_file_registrations(path: &Path) -> anyhow::Result<BTreeMap<String, Origin>> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read coordinator file: {}", path.display()))?;
+    if json.trim().is_empty() {
+        return Ok(BTreeMap::new());
+    }
+
+    let file: CoordinatorFile = serde_json::from_str(&json)
+        .with_context(|| format!("failed to parse coordinator file: {}", path.display()))?;
+
+    Ok(file
+        .namespaces
+        .into_iter()
+        .map(|(namespace, entry)| (namespace, entry.into()))
+        .collect())
+}
+
+/// Copy `registrations` to the moq-api server (or relay registry) behind `target`, so a cluster
+/// can move from the file coordinator to the API coordinator without editing JSON by hand.
+///
+/// Namespaces the target registers to another origin are reported as conflicts, and only
+/// replaced with [MigrateOptions::overwrite]. The target expires registrations nobody refreshes,
+/// so switch the relays over within its TTL, or re-register their namespaces afterwards.
+pub async fn migrate_registrations(
+    registrations: BTreeMap<String, Origin>,
+    target: &moq_api::Client,
+    options: MigrateOptions,
+) -> anyhow::Result<MigrationReport> {
+    let mut report = MigrationReport {
+        dry_run: options.dry_run,
+        ..Default::default()
+    };
+
+    for (namespace, origin) in registrations {
+        let existing = target
+            .get_origin(&namespace)
+            .await
+            .with_context(|| format!("failed to look up {}", namespace))?;
+
+        match existing {
+            Some(existing) if existing == origin => report.unchanged.push(namespace),
+            Some(existing) => {
+                let overwritten = options.overwrite;
+                if overwritten && !options.dry_run {
+                    // The target refuses a second origin until the first one is gone.
+                    target
+                        .delete_origin(&namespace)
+                        .await
+                        .with_context(|| format!("failed to delete {}", namespace))?;
+                    target
+                        .set_origin(&namespace, origin.clone())
+                        .await
+                        .with_context(|| format!("failed to register {}", namespace))?;
+                }
+
+                log::warn!(
+                    "conflicting registration of {}: {} in the source, {} in the target{}",
+                    namespace,
+                    origin.url,
+                    existing.url,
+                    if overwritten { ", overwritten" } else { "" }
+                );
+                report.conflicts.push(MigrationConflict {
+                    namespace,
+                    source: origin.url,
+                    target: existing.url,
+                    overwritten,
+                });
+            }
+            None => {
+                if !options.dry_run {
+                    target
+                        .set_origin(&namespace, origin)
+                        .await
+                        .with_context(|| format!("failed to register {}", namespace))?;
+                }
+                report.migrated.push(namespace);
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net, time::Duration};
+
+    use super::*;
+    use crate::{RegistryConfig, RegistryServer};
+
+    fn origin(url: &str) -> Origin {
+        Origin {
+            url: url.parse().unwrap(),
+            metadata: Default::default(),
+        }
+    }
+
+    #[test]
+    fn reads_file() {
+        let path = std::env::temp_dir().join(format!("moq-migrate-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"{"namespaces": {
+                "live": "https://a.example/",
+                "vod": {"url": "https://b.example/", "metadata": {"region": "eu"}}
+            }}"#,
+        )
+        .unwrap();
+
+        let registrations = read_file_registrations(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(registrations["live"] == origin("https://a.example/"));
+        assert_eq!(registrations["vod"].url.as_str(), "https://b.example/");
+        assert_eq!(registrations["vod"].metadata["region"], "eu");
+    }
+
+    #[tokio::test]
+    async fn migrates() {
+        // Any free port will do.
+        let bind = net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let registry = RegistryServer::new(RegistryConfig {
+            bind,
+            ttl: Duration::from_secs(60),
+        });
+        tokio::spawn(registry.run());
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let target = moq_api::Client::new(format!("http://{}/", bind).parse().unwrap());
+        target
+            .set_origin("live/a", origin("https://a.example/"))
+            .await
+            .unwrap();
+        target
+            .set_origin("live/b", origin("https://c.example/"))
+            .await
+            .unwrap();
+
+        let registrations = BTreeMap::from([
+            ("live/a".to_string(), origin("https://a.example/")),
+            ("live/b".to_string(), origin("https://b.example/")),
+            ("live/c".to_string(), origin("https://b.example/")),
+        ]);
+
+        // A dry run reports without writing.
+        let options = MigrateOptions {
+            dry_run: true,
+            ..Default::default()
+        };
+        let report = migrate_registrations(registrations.clone(), &target, options)
+            .await
+            .unwrap();
+        assert_eq!(report.migrated, ["live/c"]);
+        assert_eq!(report.unchanged, ["live/a"]);
+        assert_eq!(report.conflicts[0].target.as_str(), "https://c.example/");
+        assert_eq!(report.unresolved(), 1);
+        assert!(target.get_origin("live/c").await.unwrap().is_none());
+
+        // Conflicts are left alone unless overwriting.
+        let report = migrate_registrations(registrations.clone(), &target, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(report.unresolved(), 1);
+        assert!(target.get_origin("live/c").await.unwrap() == Some(origin("https://b.example/")));
+        assert!(target.get_origin("live/b").await.unwrap() == Some(origin("https://c.example/")));
+
+        let options = MigrateOptions {
+            overwrite: true,
+            ..Default::default()
+        };
+        let report = migrate_registrations(registrations, &target, options)
+            .await
+            .unwrap();
+        assert_eq!(report.unchanged, ["live/a", "live/c"]);
+        assert!(report.conflicts[0].overwritten);
+        assert!(target.get_origin("live/b").await.unwrap() == Some(origin("https://b.example/")));
+    }
+}
@@ -0,0 +1,125 @@
+use std::time::Duration;
+
+use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
+use moq_perf::{Receiver, Sender};
+use moq_transport::{
+    coding::{TrackNamespace, TupleField},
+    serve::{self, ServeError, TrackWriter},
+};
+
+use crate::Locals;
+
+/// The built-in namespace for qualifying links with `moq-perf`, see [moq_perf].
+#[derive(Clone, Debug)]
+pub struct PerfConfig {
+    /// The namespace, ex. `perf`. Uploads are announced under it.
+    pub namespace: TrackNamespace,
+
+    /// Downloads requested at a higher bitrate are sent at this one, in bits per second.
+    pub max_bitrate: u64,
+}
+
+/// Sends probes on request, and reports on the probes uploaded under the namespace.
+pub(crate) struct Perf {
+    pub config: PerfConfig,
+    pub locals: Locals,
+}
+
+impl Perf {
+    // How long a report waits for its upload to be announced, since the subscription to the report
+    // can overtake it.
+    const UPLOAD_WAIT: Duration = Duration::from_secs(5);
+
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        let (_writer, mut requests, reader) =
+            serve::Tracks::new(self.config.namespace.clone()).produce();
+
+        // Served locally only: a link is qualified by connecting to the relay under test.
+        let _registration = self.locals.register(reader).await?;
+        log::info!("serving perf namespace: {}", self.config.namespace);
+
+        let mut tasks = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                Some(track) = requests.next() => {
+                    let name = track.name.clone();
+                    let task = Self::serve(self.config.clone(), self.locals.clone(), track);
+                    tasks.push(task.map(move |res| (name, res)));
+                },
+                Some((name, res)) = tasks.next() => {
+                    if let Err(err) = res {
+                        log::debug!("perf track ended: {}: {}", name, err);
+                    }
+                },
+                else => return Ok(()),
+            }
+        }
+    }
+
+    async fn serve(config: PerfConfig, locals: Locals, track: TrackWriter) -> anyhow::Result<()> {
+        let (kind, arg) = track.name.split_once('/').unwrap_or_default();
+        match kind {
+            "download" => match arg.parse::<u64>() {
+                Ok(bitrate) => {
+                    let bitrate = bitrate.min(config.max_bitrate);
+                    log::info!("perf download: bitrate={}", bitrate);
+                    Sender::new(bitrate).run(track.subgroups()?).await
+                }
+                Err(_) => Ok(track.close(ServeError::not_found_ctx("invalid perf bitrate"))?),
+            },
+            "upload" if !arg.is_empty() => {
+                let mut upload = config.namespace.clone();
+                upload.add(TupleField::from_utf8("upload"));
+                upload.add(TupleField::from_utf8(arg));
+                Self::report(locals, upload, track).await
+            }
+            _ => Ok(track.close(ServeError::not_found_ctx("unknown perf track"))?),
+        }
+    }
+
+    // Subscribe to the probes of the `upload` namespace and report on them every second.
+    async fn report(
+        locals: Locals,
+        upload: TrackNamespace,
+        track: TrackWriter,
+    ) -> anyhow::Result<()> {
+        let deadline = tokio::time::Instant::now() + Self::UPLOAD_WAIT;
+        let mut tracks = loop {
+            // Our own namespace is a prefix of the upload's, so only an exact match will do.
+            match locals.retrieve(&upload) {
+                Some(tracks) if tracks.namespace == upload => break tracks,
+                _ if tokio::time::Instant::now() >= deadline => {
+                    track.close(ServeError::not_found_ctx("upload not announced"))?;
+                    return Ok(());
+                }
+                _ => tokio::time::sleep(Duration::from_millis(50)).await,
+            }
+        };
+
+        let probes = tracks
+            .subscribe(upload.clone(), moq_perf::UPLOAD_TRACK)
+            .ok_or_else(|| anyhow::anyhow!("upload ended: {}", upload))?;
+        log::info!("perf upload: {}", upload);
+
+        let receiver = Receiver::new();
+        tokio::select! {
+            res = receiver.clone().run(probes) => res,
+            res = Self::send_reports(&receiver, track.subgroups()?) => res,
+        }
+    }
+
+    // Write what `receiver` measured over each second as JSON, a group per report.
+    async fn send_reports(
+        receiver: &Receiver,
+        mut reports: serve::SubgroupsWriter,
+    ) -> anyhow::Result<()> {
+        let mut ticks = tokio::time::interval(Duration::from_secs(1));
+        ticks.tick().await;
+
+        loop {
+            ticks.tick().await;
+            let report = serde_json::to_vec(&receiver.interval())?;
+            reports.append(0)?.write(report.into())?;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use moq_transport::{
+    coding::TrackNamespace,
+    serve::{FullTrackName, ServeError, TrackReader, TrackReaderMode},
+};
+use tokio::time::Instant;
+
+use crate::{Backoff, GroupCache, Locals, ReconnectPolicy, RemotesConsumer};
+
+/// Tracks the relay subscribes to upstream at startup and keeps subscribed, so the first viewer
+/// is served from the relay right away instead of waiting on the origin.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PinnedTracks {
+    pub namespace: TrackNamespace,
+
+    /// The names of the tracks in the namespace.
+    pub names: Vec<String>,
+}
+
+/// Keeps one pinned track subscribed upstream, resubscribing whenever the origin is available
+/// again after it couldn't be found, refused the track or went away.
+pub(crate) struct Pinner {
+    pub track: FullTrackName,
+    pub locals: Locals,
+    pub remotes: RemotesConsumer,
+
+    /// Records the track's groups while it's pinned, to serve FETCH from.
+    pub cache: Option<GroupCache>,
+    pub reconnect: ReconnectPolicy,
+}
+
+impl Pinner {
+    pub async fn run(self) -> anyhow::Result<()> {
+        let mut backoff = Backoff::new(self.reconnect);
+
+        loop {
+            let start = Instant::now();
+            let pinned = match self.pin().await {
+                Ok(pinned) => pinned,
+                Err(err) => {
+                    log::warn!(
+                        "failed to pin {}/{}: {}",
+                        self.track.namespace,
+                        self.track.name,
+                        err
+                    );
+                    false
+                }
+            };
+
+            let delay = backoff.next(pinned.then(|| start.elapsed()));
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    // Subscribe upstream and hold the track until it ends, returning whether there was a track.
+    async fn pin(&self) -> anyhow::Result<bool> {
+        let (namespace, name) = (&self.track.namespace, &self.track.name);
+
+        // Published to this relay, which already serves it without the origin.
+        if self.locals.retrieve(namespace).is_some() {
+            return Ok(false);
+        }
+
+        let Some(remote) = self.remotes.route(namespace).await? else {
+            return Ok(false);
+        };
+        let Some(track) = remote.subscribe(namespace, name, None, None, None)? else {
+            return Ok(false);
+        };
+        log::info!("pinned {}/{} from {}", namespace, name, remote.url);
+
+        // Holding the track keeps the upstream subscription open, shared by every viewer.
+        let res = match self.cache.clone() {
+            Some(cache) => tokio::join!(cache.record(track.reader.clone()), hold(&track.reader)).1,
+            None => hold(&track.reader).await,
+        };
+        log::info!("unpinned {}/{}: {:?}", namespace, name, res);
+
+        Ok(true)
+    }
+}
+
+// Read `track` until it ends, so we know to subscribe again.
+async fn hold(track: &TrackReader) -> Result<(), ServeError> {
+    match track.mode().await? {
+        TrackReaderMode::Subgroups(mut subgroups) => while subgroups.next().await?.is_some() {},
+        TrackReaderMode::Datagrams(mut datagrams) => while datagrams.read().await?.is_some() {},
+        TrackReaderMode::Stream(mut stream) => while stream.next().await?.is_some() {},
+    }
+
+    Ok(())
+}
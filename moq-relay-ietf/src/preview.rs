@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use moq_transport::{
+    coding::TrackNamespace,
+    data::ObjectStatus,
+    serve::{FullTrackName, TrackReader},
+    session::FetchedObject,
+};
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::cache::{record, Recorder};
+
+/// Which tracks [Previews] samples, and how often.
+#[derive(Clone, Debug)]
+pub struct PreviewConfig {
+    /// Only namespaces starting with one of these prefixes are sampled.
+    pub namespaces: Vec<TrackNamespace>,
+
+    /// The tracks sampled in each of those namespaces, ex. `video`.
+    pub tracks: Vec<String>,
+
+    /// The time between two samples of a track.
+    pub interval: Duration,
+}
+
+/// The latest object sampled from a track.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Preview {
+    pub group_id: u64,
+    pub payload: Bytes,
+    pub sampled_at: SystemTime,
+}
+
+/// A track sampled by [Previews], as listed by the admin API.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PreviewEntry {
+    pub namespace: String,
+    pub track: String,
+    pub group_id: u64,
+    pub size: usize,
+    /// Sample time, in seconds since the Unix epoch.
+    pub sampled_at: u64,
+}
+
+struct Sample {
+    preview: Preview,
+    at: Instant,
+}
+
+/// Thumbnails of live tracks, for channel-grid UIs that can't subscribe to every full track.
+///
+/// The relay subscribes to the configured tracks of every matching namespace as soon as it's
+/// announced, and keeps the first object of a group, ex. a keyframe, at most once per interval.
+/// The latest sample is served by the web server at `/preview/<namespace>/<track>`.
+#[derive(Clone)]
+pub struct Previews {
+    samples: Arc<Mutex<HashMap<FullTrackName, Sample>>>,
+    config: Arc<PreviewConfig>,
+}
+
+impl Previews {
+    pub fn new(config: PreviewConfig) -> Self {
+        Self {
+            samples: Default::default(),
+            config: Arc::new(config),
+        }
+    }
+
+    /// The tracks to sample in `namespace`, if any.
+    pub fn tracks(&self, namespace: &TrackNamespace) -> &[String] {
+        match self
+            .config
+            .namespaces
+            .iter()
+            .any(|prefix| namespace.has_prefix(prefix))
+        {
+            true => &self.config.tracks,
+            false => &[],
+        }
+    }
+
+    /// The latest sample of `track`, if it's sampled.
+    pub fn get(&self, track: &FullTrackName) -> Option<Preview> {
+        let samples = self.samples.lock().unwrap();
+        samples.get(track).map(|sample| sample.preview.clone())
+    }
+
+    /// The sampled tracks, sorted by name.
+    pub fn list(&self) -> Vec<PreviewEntry> {
+        let samples = self.samples.lock().unwrap();
+        let mut entries: Vec<_> = samples
+            .iter()
+            .map(|(track, sample)| PreviewEntry {
+                namespace: track.namespace.to_utf8_path(),
+                track: track.name.clone(),
+                group_id: sample.preview.group_id,
+                size: sample.preview.payload.len(),
+                sampled_at: sample
+                    .preview
+                    .sampled_at
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs(),
+            })
+            .collect();
+
+        entries.sort_by(|a, b| (&a.namespace, &a.track).cmp(&(&b.namespace, &b.track)));
+        entries
+    }
+
+    /// Sample `track` until it ends, then forget its preview.
+    pub async fn record(self, track: TrackReader) {
+        let name = FullTrackName {
+            namespace: track.namespace.clone(),
+            name: track.name.clone(),
+        };
+
+        record(&self, track).await;
+        self.samples.lock().unwrap().remove(&name);
+    }
+}
+
+impl Recorder for Previews {
+    async fn insert(&self, track: &FullTrackName, object: FetchedObject) {
+        // Only the start of a group can be decoded on its own.
+        if object.subgroup_id != 0
+            || object.object_id != 0
+            || object.status != ObjectStatus::NormalObject
+            || object.payload.is_empty()
+        {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        if let Some(sample) = samples.get(track) {
+            if now.duration_since(sample.at) < self.config.interval {
+                return;
+            }
+        }
+
+        samples.insert(
+            track.clone(),
+            Sample {
+                preview: Preview {
+                    group_id: object.group_id,
+                    payload: object.payload,
+                    sampled_at: SystemTime::now(),
+                },
+                at: now,
+            },
+        );
+    }
+
+    // Live-only tracks are the ones worth previewing.
+    fn live_only(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::fixtures::object;
+
+    #[tokio::test]
+    async fn samples() {
+        let previews = Previews::new(PreviewConfig {
+            namespaces: vec![TrackNamespace::from_utf8_path("live")],
+            tracks: vec!["video".to_string()],
+            interval: Duration::from_millis(200),
+        });
+        assert_eq!(
+            previews.tracks(&TrackNamespace::from_utf8_path("live/room")),
+            ["video".to_string()]
+        );
+        assert!(previews
+            .tracks(&TrackNamespace::from_utf8_path("vod"))
+            .is_empty());
+
+        let track = FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live/room"),
+            name: "video".to_string(),
+        };
+        let sampled = |previews: &Previews| previews.get(&track).map(|preview| preview.group_id);
+
+        // Only the first object of a group is sampled.
+        previews.insert(&track, object(0, 1, "delta")).await;
+        assert_eq!(sampled(&previews), None);
+        previews.insert(&track, object(0, 0, "key")).await;
+        assert_eq!(sampled(&previews), Some(0));
+
+        // At most once per interval.
+        previews.insert(&track, object(1, 0, "key")).await;
+        assert_eq!(sampled(&previews), Some(0));
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        previews.insert(&track, object(2, 0, "key")).await;
+        assert_eq!(sampled(&previews), Some(2));
+
+        let entries = previews.list();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].namespace, "/live/room");
+        assert_eq!(entries[0].size, 3);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use moq_transport::{
+    coding::TrackNamespace,
+    data::ObjectStatus,
+    serve::{FullTrackName, TrackReader},
+    session::FetchedObject,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::cache::{record, Recorder};
+
+/// How the subscriptions to the tracks under a namespace prefix are prioritized, for deployments
+/// whose clients don't set sensible priorities themselves.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PriorityPolicy {
+    /// Names of the tracks classified as audio, where `*` matches anything, ex. `audio*`.
+    pub audio_tracks: Vec<String>,
+
+    /// Also classify as audio the tracks listed with an audio sample rate, channel configuration
+    /// or MIME type by the catalog in this track, ex. `.catalog`. The relay subscribes to it as
+    /// soon as the namespace is announced.
+    pub catalog: Option<String>,
+
+    /// The subscriber priority subscriptions to audio are sent with at least, whatever the
+    /// subscriber asks for. Lower values are more important.
+    pub audio_priority: u8,
+}
+
+impl Default for PriorityPolicy {
+    fn default() -> Self {
+        Self {
+            audio_tracks: vec!["audio*".to_string()],
+            catalog: Some(".catalog".to_string()),
+            audio_priority: 0,
+        }
+    }
+}
+
+/// Per-namespace priority policies, so audio is sent before video under congestion.
+///
+/// Policies are loaded from a JSON file mapping namespace prefixes to their [PriorityPolicy],
+/// and only the longest matching prefix applies. Namespaces without a policy are sent with the
+/// priorities their subscribers ask for.
+#[derive(Clone, Default)]
+pub struct Priorities {
+    state: Arc<Mutex<PrioritiesState>>,
+}
+
+#[derive(Default)]
+struct PrioritiesState {
+    policies: BTreeMap<String, PriorityPolicy>,
+
+    /// The tracks each namespace's catalog lists as audio.
+    audio: HashMap<TrackNamespace, HashSet<String>>,
+}
+
+impl PrioritiesState {
+    // The policy of the longest prefix matching `namespace`.
+    fn policy(&self, namespace: &TrackNamespace) -> Option<&PriorityPolicy> {
+        self.policies
+            .iter()
+            .filter(|(prefix, _)| {
+                namespace
+                    .fields
+                    .starts_with(&TrackNamespace::from_utf8_path(prefix).fields)
+            })
+            .max_by_key(|(prefix, _)| TrackNamespace::from_utf8_path(prefix).fields.len())
+            .map(|(_, policy)| policy)
+    }
+}
+
+impl Priorities {
+    pub fn new(policies: BTreeMap<String, PriorityPolicy>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PrioritiesState {
+                policies,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Load policies from a JSON file, ex. `{"live": {"audio_tracks": ["mic*"], "audio_priority": 1}}`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read priorities: {}", path.display()))?;
+        let policies = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse priorities: {}", path.display()))?;
+        Ok(Self::new(policies))
+    }
+
+    /// The catalog track to classify the tracks of `namespace` with, if any.
+    pub fn catalog(&self, namespace: &TrackNamespace) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.policy(namespace)?.catalog.clone()
+    }
+
+    /// The subscriber priority to send subscriptions to `track` with at least, if it's audio.
+    pub fn boost(&self, namespace: &TrackNamespace, track: &str) -> Option<u8> {
+        let state = self.state.lock().unwrap();
+        let policy = state.policy(namespace)?;
+
+        let listed = state
+            .audio
+            .iter()
+            .filter(|(catalogued, _)| namespace.has_prefix(catalogued))
+            .any(|(_, tracks)| tracks.contains(track));
+        let named = policy
+            .audio_tracks
+            .iter()
+            .any(|pattern| matches(pattern, track));
+
+        (listed || named).then_some(policy.audio_priority)
+    }
+
+    /// Classify tracks with the catalog in `track` until it ends, then forget them.
+    pub async fn record(self, track: TrackReader) {
+        let namespace = track.namespace.clone();
+
+        record(&self, track).await;
+        self.state.lock().unwrap().audio.remove(&namespace);
+    }
+}
+
+impl Recorder for Priorities {
+    async fn insert(&self, track: &FullTrackName, object: FetchedObject) {
+        if object.status != ObjectStatus::NormalObject || object.payload.is_empty() {
+            return;
+        }
+
+        let catalog: moq_catalog::Root = match serde_json::from_slice(&object.payload) {
+            Ok(catalog) => catalog,
+            Err(err) => {
+                log::debug!("ignoring invalid catalog of {}: {}", track.namespace, err);
+                return;
+            }
+        };
+
+        let audio: HashSet<_> = catalog
+            .tracks
+            .into_iter()
+            .filter(|track| {
+                let params = &track.selection_params;
+                params.samplerate.is_some()
+                    || params.channel_config.is_some()
+                    || params
+                        .mime_type
+                        .as_ref()
+                        .is_some_and(|mime| mime.starts_with("audio/"))
+            })
+            .map(|track| track.name)
+            .collect();
+
+        log::debug!("audio tracks of {}: {:?}", track.namespace, audio);
+        self.state
+            .lock()
+            .unwrap()
+            .audio
+            .insert(track.namespace.clone(), audio);
+    }
+
+    // Catalogs are usually live-only, and are only read here, never served.
+    fn live_only(&self) -> bool {
+        true
+    }
+}
+
+// Whether `name` matches `pattern`, where each `*` matches any characters.
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let mut parts: Vec<_> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No wildcard, so the whole name must match.
+        return rest.is_empty();
+    };
+
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        assert!(matches("audio", "audio"));
+        assert!(!matches("audio", "audio2"));
+        assert!(matches("audio*", "audio-en"));
+        assert!(matches("*.opus", "mic.opus"));
+        assert!(matches("a*i*o", "audio"));
+        assert!(!matches("a*i*o", "audit"));
+        assert!(!matches("*audio*", "video"));
+        assert!(matches("*", ""));
+        // The prefix and suffix can't overlap.
+        assert!(!matches("ab*ba", "aba"));
+    }
+
+    #[tokio::test]
+    async fn boosts() {
+        let priorities = Priorities::new(BTreeMap::from([
+            ("live".to_string(), PriorityPolicy::default()),
+            (
+                "live/radio".to_string(),
+                PriorityPolicy {
+                    audio_tracks: vec!["*.opus".to_string()],
+                    audio_priority: 5,
+                    ..Default::default()
+                },
+            ),
+        ]));
+        let room = TrackNamespace::from_utf8_path("live/room");
+        let radio = TrackNamespace::from_utf8_path("live/radio/1");
+
+        assert_eq!(priorities.boost(&room, "audio"), Some(0));
+        assert_eq!(priorities.boost(&room, "video"), None);
+        assert_eq!(priorities.boost(&radio, "audio"), None);
+        assert_eq!(priorities.boost(&radio, "mic.opus"), Some(5));
+        assert_eq!(
+            priorities.boost(&TrackNamespace::from_utf8_path("vod"), "audio"),
+            None
+        );
+        assert_eq!(priorities.catalog(&room).as_deref(), Some(".catalog"));
+
+        // Tracks the catalog lists with audio parameters are boosted too.
+        let catalog = serde_json::json!({
+            "version": 1,
+            "streamingFormat": 1,
+            "streamingFormatVersion": "0.2",
+            "supportsDeltaUpdates": false,
+            "commonTrackFields": {},
+            "tracks": [
+                {"name": "1.m4s", "selectionParams": {"codec": "avc1.64001f", "width": 1280}},
+                {"name": "2.m4s", "selectionParams": {"codec": "opus", "samplerate": 48000}},
+            ],
+        });
+        let object = FetchedObject {
+            group_id: 0,
+            subgroup_id: 0,
+            object_id: 0,
+            priority: 0,
+            status: ObjectStatus::NormalObject,
+            extension_headers: Default::default(),
+            payload: serde_json::to_vec(&catalog).unwrap().into(),
+        };
+        let track = FullTrackName {
+            namespace: room.clone(),
+            name: ".catalog".to_string(),
+        };
+        priorities.insert(&track, object).await;
+
+        assert_eq!(priorities.boost(&room, "2.m4s"), Some(0));
+        assert_eq!(priorities.boost(&room, "1.m4s"), None);
+    }
+}
@@ -1,10 +1,23 @@
+use std::collections::HashSet;
+use std::ops::RangeInclusive;
+
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
 use moq_transport::{
-    serve::{ServeError, TracksReader},
-    session::{Publisher, SessionError, Subscribed, TrackStatusRequested},
+    coding::TrackNamespace,
+    message::GroupOrder,
+    serve::{Backpressure, FullTrackName, ServeError, TrackReader, TracksReader},
+    session::{
+        FetchRequested, Interest, Publisher, SessionError, Subscribed, TrackStatusRequested,
+    },
+};
+
+use crate::{
+    Archive, Bridge, CoordinatorError, Flags, GroupCache, LivenessConfig, Locals, Priorities,
+    Quotas, RemotesConsumer, SessionAuthorizer, SessionInterests, Tenant, FLAG_BUFFERED_DELIVERY,
 };
 
-use crate::{Locals, RemotesConsumer};
+/// How many subgroups a subscription may fall behind by under [FLAG_BUFFERED_DELIVERY].
+const BUFFERED_DELIVERY_SUBGROUPS: usize = 8;
 
 /// Producer of tracks to a remote Subscriber
 #[derive(Clone)]
@@ -12,17 +25,100 @@ pub struct Producer {
     publisher: Publisher,
     locals: Locals,
     remotes: Option<RemotesConsumer>,
+    authorizer: Option<SessionAuthorizer>,
+    flags: Flags,
+    quotas: Quotas,
+    priorities: Priorities,
+    bridge: Option<Bridge>,
+    cache: Option<GroupCache>,
+    archive: Option<Archive>,
+    interests: Option<SessionInterests>,
+    tenant: Option<Tenant>,
+    end_stale: bool,
 }
 
 impl Producer {
     pub fn new(publisher: Publisher, locals: Locals, remotes: Option<RemotesConsumer>) -> Self {
+        // Served from the cache and archive, see [Self::serve_fetch].
+        publisher.accept_fetches();
+
         Self {
             publisher,
             locals,
             remotes,
+            authorizer: None,
+            flags: Flags::default(),
+            quotas: Quotas::default(),
+            priorities: Priorities::default(),
+            bridge: None,
+            cache: None,
+            archive: None,
+            interests: None,
+            tenant: None,
+            end_stale: false,
         }
     }
 
+    /// Check every subscribe request with `authorizer` before serving it.
+    pub fn with_authorizer(mut self, authorizer: SessionAuthorizer) -> Self {
+        self.authorizer = Some(authorizer);
+        self
+    }
+
+    /// Gate experimental delivery behaviour on `flags`.
+    pub fn with_flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Reject subscriptions beyond the quotas of their namespace, shared with other sessions.
+    pub fn with_quotas(mut self, quotas: Quotas) -> Self {
+        self.quotas = quotas;
+        self
+    }
+
+    /// Send the subscriptions to audio tracks under a `priorities` policy before the others.
+    pub fn with_priorities(mut self, priorities: Priorities) -> Self {
+        self.priorities = priorities;
+        self
+    }
+
+    /// Serve subscriptions to tracks published to the relay behind `bridge`, after local ones.
+    pub fn with_bridge(mut self, bridge: Bridge) -> Self {
+        self.bridge = Some(bridge);
+        self
+    }
+
+    /// Serve FETCH requests from `cache`.
+    pub fn with_cache(mut self, cache: GroupCache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Serve FETCH requests from `archive` too, for groups older than the cache holds.
+    pub fn with_archive(mut self, archive: Archive) -> Self {
+        self.archive = Some(archive);
+        self
+    }
+
+    /// Record the namespace prefixes the remote subscribes to in `interests`, to pass on to publishers.
+    pub fn with_interests(mut self, interests: SessionInterests) -> Self {
+        self.interests = Some(interests);
+        self
+    }
+
+    /// Refuse requests for namespaces `tenant` doesn't own.
+    pub fn with_tenant(mut self, tenant: Tenant) -> Self {
+        self.tenant = Some(tenant);
+        self
+    }
+
+    /// End the subscriptions to namespaces once their publisher is stale, if `liveness` says so.
+    pub fn with_liveness(mut self, liveness: LivenessConfig) -> Self {
+        self.end_stale = liveness.end_subscriptions;
+        self
+    }
+
     /// Announce new tracks to the remote server.
     pub async fn announce(&mut self, tracks: TracksReader) -> Result<(), SessionError> {
         self.publisher.announce(tracks).await
@@ -37,6 +133,8 @@ impl Producer {
         loop {
             let mut publisher_subscribed = self.publisher.clone();
             let mut publisher_track_status = self.publisher.clone();
+            let mut publisher_fetch = self.publisher.clone();
+            let mut publisher_interest = self.publisher.clone();
 
             tokio::select! {
                 // Handle a new subscribe request
@@ -69,39 +167,197 @@ impl Producer {
                         }
                     }.boxed())
                 },
+                // Handle a new fetch request
+                Some(fetch_requested) = publisher_fetch.fetch_requested() => {
+                    let this = self.clone();
+
+                    // Spawn a new task to send the fetched objects
+                    tasks.push(async move {
+                        let info = fetch_requested.request_msg.clone();
+                        log::info!("serving fetch: {:?}", info);
+
+                        if let Err(err) = this.serve_fetch(fetch_requested).await {
+                            log::warn!("failed serving fetch: {:?}, error: {}", info, err)
+                        }
+                    }.boxed())
+                },
+                // Handle a new subscribe namespace request
+                Some(interest) = publisher_interest.interest() => {
+                    let this = self.clone();
+
+                    tasks.push(async move {
+                        let info = interest.info.clone();
+                        log::info!("serving subscribe namespace: {:?}", info);
+
+                        if let Err(err) = this.serve_interest(interest).await {
+                            log::warn!("failed serving subscribe namespace: {:?}, error: {}", info, err)
+                        }
+                    }.boxed())
+                },
                 _= tasks.next(), if !tasks.is_empty() => {},
                 else => return Ok(()),
             };
         }
     }
 
+    /// Whether the remote may use `namespace`, according to its tenant.
+    fn owns(&self, namespace: &TrackNamespace) -> bool {
+        self.tenant
+            .as_ref()
+            .is_none_or(|tenant| tenant.owns(namespace))
+    }
+
+    /// Hold the remote's interest in a prefix until it unsubscribes.
+    async fn serve_interest(self, mut interest: Interest) -> Result<(), anyhow::Error> {
+        let Some(interests) = &self.interests else {
+            interest.close(ServeError::not_found_ctx(
+                "namespace interests not supported",
+            ))?;
+            return Ok(());
+        };
+
+        // Narrow interest in a prefix covering the tenant to the tenant's own namespaces.
+        let prefix = match &self.tenant {
+            Some(tenant) if tenant.root().has_prefix(&interest.prefix) => tenant.root(),
+            Some(tenant) if !tenant.owns(&interest.prefix) => {
+                let prefix = interest.prefix.clone();
+                interest.close(ServeError::Unauthorized)?;
+                anyhow::bail!("subscribe namespace outside the tenant for {}", prefix);
+            }
+            _ => interest.prefix.clone(),
+        };
+
+        let _guard = interests.add(prefix);
+        interest.ok()?;
+        interest.closed().await?;
+
+        Ok(())
+    }
+
     /// Serve a subscribe request.
-    async fn serve_subscribe(self, subscribed: Subscribed) -> Result<(), anyhow::Error> {
+    async fn serve_subscribe(self, mut subscribed: Subscribed) -> Result<(), anyhow::Error> {
         let namespace = subscribed.track_namespace.clone();
         let track_name = subscribed.track_name.clone();
 
-        // Check local tracks first, and serve from local if possible
-        if let Some(mut local) = self.locals.retrieve(&namespace) {
-            // Pass the full requested namespace, not the announced prefix
-            if let Some(track) = local.subscribe(namespace.clone(), &track_name) {
-                log::info!("serving subscribe from local: {:?}", track.info);
-                return Ok(subscribed.serve(track).await?);
+        if !self.owns(&namespace) {
+            subscribed.close(ServeError::Unauthorized)?;
+            anyhow::bail!(
+                "subscribe outside the tenant for {}/{}",
+                namespace,
+                track_name
+            );
+        }
+
+        if let Some(authorizer) = &self.authorizer {
+            if !authorizer
+                .authorize_subscribe(&namespace, &track_name, &subscribed.authorization_tokens)
+                .await
+            {
+                subscribed.close(ServeError::Unauthorized)?;
+                anyhow::bail!("unauthorized subscribe for {}/{}", namespace, track_name);
+            }
+        }
+
+        // Held while the subscription is served, counting it against the namespace's quota.
+        let _quota = match self.quotas.admit(&namespace, subscribed.stats()) {
+            Ok(slot) => slot,
+            Err(err) => {
+                subscribed.close(err.clone())?;
+                return Err(err.into());
+            }
+        };
+        if let Some(quota) = self.quotas.subscription_quota(&namespace) {
+            subscribed.set_quota(quota);
+        }
+        if let Some(priority) = self.priorities.boost(&namespace, &track_name) {
+            subscribed.boost_priority(priority);
+        }
+
+        // Reuse the trace ID from a downstream relay, or start a new trace if we are the edge.
+        let trace_id = subscribed
+            .trace_id()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string());
+        log::info!(
+            "subscribe id={} for {}/{} trace_id={}",
+            subscribed.id,
+            namespace,
+            track_name,
+            trace_id
+        );
+        if let Some(telemetry) = subscribed.telemetry() {
+            log::debug!(
+                "subscribe id={} telemetry: {} trace_id={}",
+                subscribed.id,
+                telemetry,
+                trace_id
+            );
+        }
+
+        // Check local tracks first, and serve from local if possible.
+        if let Some((track, _subscriber)) = self
+            .locals
+            .subscribe(&namespace, &track_name, Some(trace_id.clone()))
+            .await
+        {
+            log::info!(
+                "serving subscribe from local: {:?} trace_id={}",
+                track.info,
+                trace_id
+            );
+            let track = self.delivery(&namespace, track);
+            return Ok(self.serve_track(subscribed, track).await?);
+        }
+
+        // Then the tracks of the relay bridged to in this process, if any.
+        if let Some(bridge) = &self.bridge {
+            if let Some((track, _subscriber)) = bridge
+                .subscribe(&namespace, &track_name, Some(trace_id.clone()))
+                .await
+            {
+                log::info!(
+                    "serving subscribe from bridge: {:?} trace_id={}",
+                    track.info,
+                    trace_id
+                );
+                let track = self.delivery(&namespace, track);
+                return Ok(self.serve_track(subscribed, track).await?);
             }
         }
 
-        if let Some(remotes) = self.remotes {
+        if let Some(remotes) = &self.remotes {
             // Check remote tracks second, and serve from remote if possible
             match remotes.route(&namespace).await {
                 Ok(remote) => {
                     if let Some(remote) = remote {
-                        if let Some(track) = remote.subscribe(&namespace, &track_name)? {
-                            log::info!("serving subscribe from remote: {:?}", track.info);
-                            return Ok(subscribed.serve(track.reader).await?);
+                        let name = FullTrackName {
+                            namespace: namespace.clone(),
+                            name: track_name.clone(),
+                        };
+                        if let Some(track) = remote.subscribe(
+                            &namespace,
+                            &track_name,
+                            Some(trace_id.clone()),
+                            self.cached_groups(&name).await,
+                            subscribed.telemetry(),
+                        )? {
+                            log::info!(
+                                "serving subscribe from remote: {:?} trace_id={}",
+                                track.info,
+                                trace_id
+                            );
+                            let track = self.delivery(&namespace, track.reader);
+                            return Ok(self.serve_track(subscribed, track).await?);
                         }
                     }
                 }
                 Err(e) => {
                     log::error!("failed to route to remote: {}", e);
+
+                    // Let the subscriber retry instead of reporting a track that may well exist.
+                    if let Some(CoordinatorError::Timeout) = e.downcast_ref() {
+                        subscribed.close(ServeError::Timeout)?;
+                        return Err(e);
+                    }
                 }
             }
         }
@@ -114,16 +370,225 @@ impl Producer {
         Err(err.into())
     }
 
+    /// The groups of `track` this relay can already serve by FETCH, so the upstream doesn't send
+    /// them again, ex. after the session to it was briefly lost. Only one range can be advertised,
+    /// so the cached and archived groups are merged if they touch, otherwise the later one wins.
+    async fn cached_groups(&self, track: &FullTrackName) -> Option<RangeInclusive<u64>> {
+        let cached = self
+            .cache
+            .as_ref()
+            .and_then(|cache| cache.cached_groups(track));
+        let archived = match &self.archive {
+            Some(archive) => archive.archived_groups(track).await,
+            None => None,
+        };
+
+        match (cached, archived) {
+            (Some(cached), Some(archived))
+                if *cached.start() <= archived.end() + 1
+                    && *archived.start() <= cached.end() + 1 =>
+            {
+                Some(*cached.start().min(archived.start())..=*cached.end().max(archived.end()))
+            }
+            (cached, archived) => cached
+                .into_iter()
+                .chain(archived)
+                .max_by_key(|groups| *groups.end()),
+        }
+    }
+
+    /// Serve `track` to the subscriber, ending the subscription if its authorization lapses or,
+    /// with [Self::with_liveness], once its publisher goes stale.
+    async fn serve_track(
+        &self,
+        mut subscribed: Subscribed,
+        track: TrackReader,
+    ) -> Result<(), SessionError> {
+        // The cache may know a later live edge of the same upstream subscription than the track,
+        // ex. once groups were skipped while the track was read from behind.
+        let name = FullTrackName {
+            namespace: subscribed.track_namespace.clone(),
+            name: subscribed.track_name.clone(),
+        };
+        if let Some(largest) = self.cache.as_ref().and_then(|cache| cache.largest(&name)) {
+            subscribed.set_largest_location(largest);
+        }
+
+        let stale = match self.end_stale {
+            true => self.locals.watch_stale(&name.namespace),
+            false => None,
+        };
+        if self.authorizer.is_none() && stale.is_none() {
+            return subscribed.serve(track).await;
+        }
+
+        let authorization = subscribed.authorization();
+        let closer = subscribed.closer();
+
+        let expired = async {
+            match &self.authorizer {
+                Some(authorizer) => {
+                    authorizer
+                        .subscribe_expired(&name.namespace, &name.name, &authorization)
+                        .await
+                }
+                None => std::future::pending().await,
+            }
+        };
+
+        // Never resolves once the publisher is gone, as the subscription ends along with it.
+        let went_stale = async {
+            if let Some(mut stale) = stale {
+                if stale.wait_for(|stale| *stale).await.is_ok() {
+                    return;
+                }
+            }
+            std::future::pending().await
+        };
+
+        let mut serve = std::pin::pin!(subscribed.serve(track));
+        tokio::select! {
+            res = &mut serve => return res,
+            _ = expired => {
+                log::info!("authorization expired for {}/{}", name.namespace, name.name);
+                authorization.expire()?;
+            },
+            _ = went_stale => {
+                log::info!("publisher of {}/{} is stale, ending subscription", name.namespace, name.name);
+                closer.close(ServeError::Timeout)?;
+            },
+        }
+
+        serve.await
+    }
+
+    /// Apply the experimental delivery behaviour enabled for `namespace`, if any.
+    fn delivery(&self, namespace: &TrackNamespace, track: TrackReader) -> TrackReader {
+        if self.flags.enabled(FLAG_BUFFERED_DELIVERY, namespace) {
+            log::debug!("buffered delivery enabled for {}", namespace);
+            return track.with_backpressure(Backpressure::Buffer(BUFFERED_DELIVERY_SUBGROUPS));
+        }
+
+        track
+    }
+
+    /// Serve a fetch request from the cache and the archive.
+    ///
+    /// Only objects this relay cached or archived are returned, so a fetch is refused without
+    /// either or when none of the range is stored. Live-only tracks are refused with a distinct error so the
+    /// subscriber knows the content will never be available.
+    async fn serve_fetch(self, mut fetch_requested: FetchRequested) -> Result<(), anyhow::Error> {
+        let fetch = fetch_requested
+            .request_msg
+            .standalone_fetch
+            .clone()
+            .ok_or_else(|| ServeError::internal_ctx("standalone fetch without track"))?;
+
+        if !self.owns(&fetch.track_namespace) {
+            let err = ServeError::Unauthorized;
+            fetch_requested.respond_error(err.code(), &err.to_string())?;
+            anyhow::bail!(
+                "fetch outside the tenant for {}/{}",
+                fetch.track_namespace,
+                fetch.track_name
+            );
+        }
+
+        let track = self
+            .locals
+            .retrieve(&fetch.track_namespace)
+            .and_then(|mut local| {
+                local.get_track_reader(&fetch.track_namespace, &fetch.track_name)
+            });
+
+        let name = FullTrackName {
+            namespace: fetch.track_namespace.clone(),
+            name: fetch.track_name.clone(),
+        };
+
+        let err = match (&self.cache, &self.archive, track) {
+            (_, _, Some(track)) if !track.is_fetchable() => ServeError::LiveOnly,
+            (None, None, Some(_)) => ServeError::not_implemented_ctx("FETCH without a relay cache"),
+            (None, None, None) => ServeError::not_found_ctx(format!(
+                "track '{}/{}' not found for fetch",
+                fetch.track_namespace, fetch.track_name
+            )),
+            (cache, archive, _) => {
+                let (start, end) = (fetch.start_location, fetch.end_location);
+                let cached = match cache {
+                    Some(cache) => cache.get(&name, start, end).await,
+                    None => Vec::new(),
+                };
+
+                // The archive reaches further back, but the cache wins for groups in both.
+                let groups: HashSet<u64> = cached.iter().map(|object| object.group_id).collect();
+                let archived = match archive {
+                    Some(archive) => archive.get(&name, start, end).await,
+                    None => Vec::new(),
+                };
+                let mut objects: Vec<_> = archived
+                    .into_iter()
+                    .filter(|archived| !groups.contains(&archived.group_id))
+                    .chain(cached)
+                    .collect();
+
+                if !objects.is_empty() {
+                    match fetch_requested.request_msg.group_order {
+                        GroupOrder::Descending => objects.sort_by_key(|object| {
+                            (std::cmp::Reverse(object.group_id), object.object_id)
+                        }),
+                        _ => objects.sort_by_key(|object| (object.group_id, object.object_id)),
+                    }
+
+                    fetch_requested.respond(objects, false).await?;
+                    return Ok(());
+                }
+
+                ServeError::not_found_ctx(format!(
+                    "no cached objects of '{}/{}' in range",
+                    fetch.track_namespace, fetch.track_name
+                ))
+            }
+        };
+
+        fetch_requested.respond_error(err.code(), &err.to_string())?;
+        Err(err.into())
+    }
+
     /// Serve a track_status request.
     async fn serve_track_status(
         self,
         mut track_status_requested: TrackStatusRequested,
     ) -> Result<(), anyhow::Error> {
+        if !self.owns(&track_status_requested.request_msg.track_namespace) {
+            let err = ServeError::Unauthorized;
+            track_status_requested.respond_error(err.code(), &err.to_string())?;
+            anyhow::bail!(
+                "track_status outside the tenant for {}/{}",
+                track_status_requested.request_msg.track_namespace,
+                track_status_requested.request_msg.track_name
+            );
+        }
+
         // Check local tracks first, and serve from local if possible
         if let Some(mut local_tracks) = self
             .locals
             .retrieve(&track_status_requested.request_msg.track_namespace)
         {
+            // The publisher keeps its session open but sends nothing, see [crate::LivenessConfig].
+            if self
+                .locals
+                .is_stale(&track_status_requested.request_msg.track_namespace)
+            {
+                track_status_requested
+                    .respond_error(ServeError::Timeout.code(), "publisher stale")?;
+                log::info!(
+                    "track_status of stale namespace: {}",
+                    track_status_requested.request_msg.track_namespace
+                );
+                return Ok(());
+            }
+
             if let Some(track) = local_tracks.get_track_reader(
                 &track_status_requested.request_msg.track_namespace,
                 &track_status_requested.request_msg.track_name,
@@ -0,0 +1,379 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use moq_transport::{
+    coding::TrackNamespace,
+    serve::{ServeError, SubscriptionQuota},
+    session::DeliveryStats,
+};
+use serde::{Deserialize, Serialize};
+
+/// Limits on the subscriptions to the namespaces under a prefix, ex. a tenant.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NamespaceQuota {
+    /// Maximum number of subscriptions served at once, across every session.
+    pub max_subscribers: Option<usize>,
+
+    /// New subscriptions are rejected while the subscriptions being served are sent at this many
+    /// megabits per second or more, combined.
+    pub max_egress_mbps: Option<f64>,
+
+    /// Each subscription is ended once this many bytes of its objects are waiting to be sent,
+    /// ex. because the subscriber can't keep up.
+    pub max_subscription_buffered_bytes: Option<u64>,
+
+    /// Each subscription is ended once it's sent at more than this many megabits per second.
+    pub max_subscription_mbps: Option<f64>,
+}
+
+impl NamespaceQuota {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(mbps) = self.max_egress_mbps {
+            anyhow::ensure!(
+                mbps.is_finite() && mbps > 0.0,
+                "max egress must be positive: {}",
+                mbps
+            );
+        }
+        if let Some(mbps) = self.max_subscription_mbps {
+            anyhow::ensure!(
+                mbps.is_finite() && mbps > 0.0,
+                "max subscription rate must be positive: {}",
+                mbps
+            );
+        }
+        Ok(())
+    }
+
+    /// The limits each subscription is served under.
+    pub fn subscription(&self) -> SubscriptionQuota {
+        SubscriptionQuota {
+            max_buffered_bytes: self.max_subscription_buffered_bytes,
+            max_bytes_per_second: self
+                .max_subscription_mbps
+                .map(|mbps| (mbps * 1_000_000.0 / 8.0) as u64),
+        }
+    }
+}
+
+/// The usage of a quota, as reported by the admin API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct QuotaStats {
+    pub quota: NamespaceQuota,
+
+    /// Subscriptions currently served.
+    pub subscribers: usize,
+
+    /// The rate the subscriptions are sent at, combined, in megabits per second.
+    pub egress_mbps: f64,
+
+    /// Subscriptions rejected since the relay started, because of `max_subscribers`.
+    pub rejected_subscribers: u64,
+
+    /// Subscriptions rejected since the relay started, because of `max_egress_mbps`.
+    pub rejected_egress: u64,
+}
+
+/// Per-namespace quotas, shared by every session, so one popular namespace can't starve the others.
+///
+/// Quotas are loaded from a JSON file mapping namespace prefixes to their [NamespaceQuota].
+/// The subscriptions to every namespace under a prefix count against its quota, and only the
+/// longest matching prefix applies. Namespaces without a quota are unlimited.
+#[derive(Clone, Default)]
+pub struct Quotas {
+    state: Arc<Mutex<QuotasState>>,
+}
+
+#[derive(Default)]
+struct QuotasState {
+    quotas: BTreeMap<String, NamespaceQuota>,
+    usage: HashMap<String, Usage>,
+    next_id: u64,
+}
+
+impl QuotasState {
+    // The quota of the longest prefix matching `namespace`.
+    fn quota(&self, namespace: &TrackNamespace) -> Option<(String, NamespaceQuota)> {
+        self.quotas
+            .iter()
+            .filter(|(prefix, _)| {
+                namespace
+                    .fields
+                    .starts_with(&TrackNamespace::from_utf8_path(prefix).fields)
+            })
+            .max_by_key(|(prefix, _)| TrackNamespace::from_utf8_path(prefix).fields.len())
+            .map(|(prefix, quota)| (prefix.clone(), quota.clone()))
+    }
+}
+
+// Reports the send rate of a subscription, in bits per second.
+type SendRate = Box<dyn Fn() -> u64 + Send>;
+
+#[derive(Default)]
+struct Usage {
+    subscriptions: HashMap<u64, SendRate>,
+    rejected_subscribers: u64,
+    rejected_egress: u64,
+}
+
+impl Usage {
+    fn egress(&self) -> u64 {
+        self.subscriptions.values().map(|rate| rate()).sum()
+    }
+}
+
+impl Quotas {
+    pub fn new(quotas: BTreeMap<String, NamespaceQuota>) -> anyhow::Result<Self> {
+        for (prefix, quota) in &quotas {
+            quota
+                .validate()
+                .with_context(|| format!("invalid quota: {}", prefix))?;
+        }
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(QuotasState {
+                quotas,
+                ..Default::default()
+            })),
+        })
+    }
+
+    /// Load quotas from a JSON file, ex. `{"tenant": {"max_subscribers": 100, "max_egress_mbps": 500}}`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read quotas: {}", path.display()))?;
+        let quotas = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse quotas: {}", path.display()))?;
+        Self::new(quotas)
+    }
+
+    /// Count a subscription to `namespace` against its quota, until the returned slot is dropped.
+    /// Returns [ServeError::QuotaExceeded] if the quota is used up.
+    pub fn admit(
+        &self,
+        namespace: &TrackNamespace,
+        stats: DeliveryStats,
+    ) -> Result<Option<QuotaSlot>, ServeError> {
+        self.admit_with(namespace, Box::new(move || stats.get().send_rate))
+    }
+
+    fn admit_with(
+        &self,
+        namespace: &TrackNamespace,
+        rate: SendRate,
+    ) -> Result<Option<QuotaSlot>, ServeError> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some((prefix, quota)) = state.quota(namespace) else {
+            return Ok(None);
+        };
+
+        let id = state.next_id;
+        let usage = state.usage.entry(prefix.clone()).or_default();
+
+        if quota
+            .max_subscribers
+            .is_some_and(|max| usage.subscriptions.len() >= max)
+        {
+            usage.rejected_subscribers += 1;
+            log::warn!("subscriber quota of {} used up: {}", prefix, namespace);
+            return Err(ServeError::QuotaExceeded("subscribers".to_string()));
+        }
+
+        if quota
+            .max_egress_mbps
+            .is_some_and(|max| usage.egress() as f64 >= max * 1_000_000.0)
+        {
+            usage.rejected_egress += 1;
+            log::warn!("egress quota of {} used up: {}", prefix, namespace);
+            return Err(ServeError::QuotaExceeded("egress".to_string()));
+        }
+
+        usage.subscriptions.insert(id, rate);
+        state.next_id += 1;
+
+        Ok(Some(QuotaSlot {
+            quotas: self.clone(),
+            prefix,
+            id,
+        }))
+    }
+
+    /// The limits each subscription to `namespace` is served under, if it has a quota.
+    pub fn subscription_quota(&self, namespace: &TrackNamespace) -> Option<SubscriptionQuota> {
+        let state = self.state.lock().unwrap();
+        let (_, quota) = state.quota(namespace)?;
+        Some(quota.subscription()).filter(SubscriptionQuota::is_limited)
+    }
+
+    /// The usage of every quota, keyed by prefix.
+    pub fn stats(&self) -> BTreeMap<String, QuotaStats> {
+        let state = self.state.lock().unwrap();
+
+        state
+            .quotas
+            .iter()
+            .map(|(prefix, quota)| {
+                let mut stats = QuotaStats {
+                    quota: quota.clone(),
+                    ..Default::default()
+                };
+                if let Some(usage) = state.usage.get(prefix) {
+                    stats.subscribers = usage.subscriptions.len();
+                    stats.egress_mbps = usage.egress() as f64 / 1_000_000.0;
+                    stats.rejected_subscribers = usage.rejected_subscribers;
+                    stats.rejected_egress = usage.rejected_egress;
+                }
+                (prefix.clone(), stats)
+            })
+            .collect()
+    }
+}
+
+/// A subscription counted against a quota, released on drop.
+pub struct QuotaSlot {
+    quotas: Quotas,
+    prefix: String,
+    id: u64,
+}
+
+impl Drop for QuotaSlot {
+    fn drop(&mut self) {
+        let mut state = self.quotas.state.lock().unwrap();
+        if let Some(usage) = state.usage.get_mut(&self.prefix) {
+            usage.subscriptions.remove(&self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use super::*;
+
+    fn quotas(prefix: &str, quota: NamespaceQuota) -> Quotas {
+        Quotas::new(BTreeMap::from([(prefix.to_string(), quota)])).unwrap()
+    }
+
+    fn admit(quotas: &Quotas, namespace: &str) -> Result<Option<QuotaSlot>, ServeError> {
+        quotas.admit_with(&TrackNamespace::from_utf8_path(namespace), Box::new(|| 0))
+    }
+
+    #[test]
+    fn max_subscribers() {
+        let quotas = quotas(
+            "tenant",
+            NamespaceQuota {
+                max_subscribers: Some(2),
+                ..Default::default()
+            },
+        );
+
+        // Every namespace under the prefix shares the quota.
+        let first = admit(&quotas, "tenant/room1").unwrap().unwrap();
+        let _second = admit(&quotas, "tenant/room2").unwrap().unwrap();
+        assert_eq!(
+            admit(&quotas, "tenant/room1").err(),
+            Some(ServeError::QuotaExceeded("subscribers".to_string()))
+        );
+
+        // Other namespaces are unlimited.
+        assert!(admit(&quotas, "other").unwrap().is_none());
+
+        drop(first);
+        assert!(admit(&quotas, "tenant/room1").unwrap().is_some());
+
+        let stats = &quotas.stats()["tenant"];
+        assert_eq!(stats.subscribers, 1);
+        assert_eq!(stats.rejected_subscribers, 1);
+    }
+
+    #[test]
+    fn max_egress() {
+        let quotas = quotas(
+            "tenant",
+            NamespaceQuota {
+                max_egress_mbps: Some(1.0),
+                ..Default::default()
+            },
+        );
+        let namespace = TrackNamespace::from_utf8_path("tenant/room");
+
+        let rate = Arc::new(AtomicU64::new(0));
+        let _slot = {
+            let rate = rate.clone();
+            quotas.admit_with(&namespace, Box::new(move || rate.load(Ordering::Relaxed)))
+        }
+        .unwrap();
+
+        // Admitted until the subscriptions already served reach the limit.
+        assert!(admit(&quotas, "tenant/room").unwrap().is_some());
+        rate.store(1_000_000, Ordering::Relaxed);
+        assert_eq!(
+            admit(&quotas, "tenant/room").err(),
+            Some(ServeError::QuotaExceeded("egress".to_string()))
+        );
+
+        let stats = &quotas.stats()["tenant"];
+        assert_eq!(stats.egress_mbps, 1.0);
+        assert_eq!(stats.rejected_egress, 1);
+    }
+
+    #[test]
+    fn longest_prefix() {
+        let quotas = Quotas::new(BTreeMap::from([
+            (
+                "tenant".to_string(),
+                NamespaceQuota {
+                    max_subscribers: Some(1),
+                    ..Default::default()
+                },
+            ),
+            ("tenant/vip".to_string(), NamespaceQuota::default()),
+        ]))
+        .unwrap();
+
+        let _slot = admit(&quotas, "tenant/room").unwrap();
+        assert!(admit(&quotas, "tenant/room").is_err());
+        assert!(admit(&quotas, "tenant/vip/room").is_ok());
+
+        assert!(Quotas::new(BTreeMap::from([(
+            "tenant".to_string(),
+            NamespaceQuota {
+                max_egress_mbps: Some(0.0),
+                ..Default::default()
+            },
+        )]))
+        .is_err());
+    }
+
+    #[test]
+    fn subscription_quota() {
+        let quotas = quotas(
+            "tenant",
+            NamespaceQuota {
+                max_subscription_buffered_bytes: Some(1_000_000),
+                max_subscription_mbps: Some(8.0),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(
+            quotas.subscription_quota(&TrackNamespace::from_utf8_path("tenant/room")),
+            Some(SubscriptionQuota {
+                max_buffered_bytes: Some(1_000_000),
+                max_bytes_per_second: Some(1_000_000),
+            })
+        );
+        assert_eq!(
+            quotas.subscription_quota(&TrackNamespace::from_utf8_path("other")),
+            None
+        );
+    }
+}
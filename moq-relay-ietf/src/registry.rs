@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    net,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use moq_api::Origin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum RegistryError {
+    #[error("not found")]
+    NotFound,
+
+    /// The namespace is registered to a different origin.
+    #[error("duplicate")]
+    Duplicate,
+}
+
+impl RegistryError {
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Duplicate => StatusCode::CONFLICT,
+        }
+    }
+}
+
+/// An in-memory registry of which relay serves each namespace.
+///
+/// Follows the semantics of the moq-api server, without needing redis: an origin expires unless
+/// it is refreshed within the TTL, and a namespace can't be claimed by a second origin until the
+/// first one is deleted or expires.
+#[derive(Clone)]
+pub struct Registry {
+    origins: Arc<Mutex<HashMap<String, (Origin, Instant)>>>,
+    ttl: Duration,
+}
+
+impl Registry {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            origins: Default::default(),
+            ttl,
+        }
+    }
+
+    pub fn get(&self, namespace: &str) -> Option<Origin> {
+        let origins = self.origins.lock().unwrap();
+        let (origin, expires) = origins.get(namespace)?;
+        (*expires > Instant::now()).then(|| origin.clone())
+    }
+
+    /// Register `origin` for `namespace`. Registering the same origin again is allowed,
+    /// but doesn't refresh it.
+    pub fn set(&self, namespace: &str, origin: Origin) -> Result<(), RegistryError> {
+        let now = Instant::now();
+        let mut origins = self.origins.lock().unwrap();
+        origins.retain(|_, (_, expires)| *expires > now);
+
+        match origins.get(namespace) {
+            Some((current, _)) if *current == origin => Ok(()),
+            Some(_) => Err(RegistryError::Duplicate),
+            None => {
+                origins.insert(namespace.to_string(), (origin, now + self.ttl));
+                Ok(())
+            }
+        }
+    }
+
+    /// Reset the TTL of `namespace`, as long as it is still registered to `origin`.
+    pub fn refresh(&self, namespace: &str, origin: &Origin) -> Result<(), RegistryError> {
+        let now = Instant::now();
+        let mut origins = self.origins.lock().unwrap();
+
+        match origins.get_mut(namespace) {
+            Some((_, expires)) if *expires <= now => Err(RegistryError::NotFound),
+            Some((current, _)) if current != origin => Err(RegistryError::Duplicate),
+            Some((_, expires)) => {
+                *expires = now + self.ttl;
+                Ok(())
+            }
+            None => Err(RegistryError::NotFound),
+        }
+    }
+
+    pub fn delete(&self, namespace: &str) -> Result<(), RegistryError> {
+        let now = Instant::now();
+        match self.origins.lock().unwrap().remove(namespace) {
+            Some((_, expires)) if expires > now => Ok(()),
+            _ => Err(RegistryError::NotFound),
+        }
+    }
+}
+
+pub struct RegistryConfig {
+    /// Listen for plain HTTP on this address.
+    pub bind: net::SocketAddr,
+
+    /// How long an origin stays registered without a refresh.
+    pub ttl: Duration,
+}
+
+/// Serves a [Registry] over the moq-api HTTP interface, so a relay can coordinate a small
+/// cluster without a separate moq-api deployment.
+///
+/// Other relays, and this one, point their API coordinator at it.
+///
+/// - `GET /origin/*namespace` returns the origin serving a namespace
+/// - `POST /origin/*namespace` registers an origin
+/// - `PATCH /origin/*namespace` refreshes the registration
+/// - `DELETE /origin/*namespace` removes it
+#[derive(Clone)]
+pub struct RegistryServer {
+    app: Router,
+    bind: net::SocketAddr,
+}
+
+impl RegistryServer {
+    pub fn new(config: RegistryConfig) -> Self {
+        let app = Router::new()
+            .route(
+                "/origin/*namespace",
+                get(get_origin)
+                    .post(set_origin)
+                    .patch(refresh_origin)
+                    .delete(delete_origin),
+            )
+            .with_state(Registry::new(config.ttl));
+
+        Self {
+            app,
+            bind: config.bind,
+        }
+    }
+
+    pub async fn run(self) -> anyhow::Result<()> {
+        let listener = tokio::net::TcpListener::bind(self.bind).await?;
+        log::info!("origin registry listening on {}", listener.local_addr()?);
+
+        axum::serve(listener, self.app).await?;
+        Ok(())
+    }
+}
+
+async fn get_origin(
+    Path(namespace): Path<String>,
+    State(registry): State<Registry>,
+) -> Result<Json<Origin>, StatusCode> {
+    registry
+        .get(&namespace)
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn set_origin(
+    Path(namespace): Path<String>,
+    State(registry): State<Registry>,
+    Json(origin): Json<Origin>,
+) -> Result<(), StatusCode> {
+    log::debug!(
+        "registering origin: namespace={} url={}",
+        namespace,
+        origin.url
+    );
+    registry.set(&namespace, origin).map_err(|err| err.status())
+}
+
+async fn refresh_origin(
+    Path(namespace): Path<String>,
+    State(registry): State<Registry>,
+    Json(origin): Json<Origin>,
+) -> Result<(), StatusCode> {
+    registry
+        .refresh(&namespace, &origin)
+        .map_err(|err| err.status())
+}
+
+async fn delete_origin(
+    Path(namespace): Path<String>,
+    State(registry): State<Registry>,
+) -> Result<(), StatusCode> {
+    log::debug!("deleting origin: namespace={}", namespace);
+    registry.delete(&namespace).map_err(|err| err.status())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registry() {
+        let registry = Registry::new(Duration::from_millis(100));
+        let a = Origin {
+            url: "https://a.example:443".parse().unwrap(),
+            metadata: Default::default(),
+        };
+        let b = Origin {
+            url: "https://b.example:443".parse().unwrap(),
+            metadata: Default::default(),
+        };
+
+        assert!(registry.get("live").is_none());
+        registry.set("live", a.clone()).unwrap();
+        assert!(registry.get("live") == Some(a.clone()));
+
+        // The same origin may register again, but not a different one.
+        registry.set("live", a.clone()).unwrap();
+        assert_eq!(
+            registry.set("live", b.clone()),
+            Err(RegistryError::Duplicate)
+        );
+        assert_eq!(registry.refresh("live", &b), Err(RegistryError::Duplicate));
+
+        // Refreshing keeps it alive past the original TTL.
+        std::thread::sleep(Duration::from_millis(60));
+        registry.refresh("live", &a).unwrap();
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(registry.get("live") == Some(a.clone()));
+
+        // Once expired, anyone can take it.
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(registry.get("live").is_none());
+        assert_eq!(registry.refresh("live", &a), Err(RegistryError::NotFound));
+        registry.set("live", b.clone()).unwrap();
+
+        registry.delete("live").unwrap();
+        assert_eq!(registry.delete("live"), Err(RegistryError::NotFound));
+        assert!(registry.get("live").is_none());
+    }
+}
@@ -1,13 +1,26 @@
-use std::{future::Future, net, path::PathBuf, pin::Pin, sync::Arc};
+use std::{future::Future, net, path::PathBuf, pin::Pin, sync::Arc, time::Duration};
 
 use anyhow::Context;
 
 use futures::{stream::FuturesUnordered, FutureExt, StreamExt};
-use moq_native_ietf::quic::{self, Endpoint};
+use moq_native_ietf::quic::{self, CloseReason, Endpoint};
+use moq_transport::{
+    coding::Token,
+    mlog,
+    serve::FullTrackName,
+    session::{ClockSkew, ObjectLimits, ScriptWriter, SessionError},
+};
 use url::Url;
 
 use crate::{
-    Consumer, Coordinator, Locals, Producer, Remotes, RemotesConsumer, RemotesProducer, Session,
+    Admin, AnnounceFeed, AnnounceLimiter, AnnounceLimits, Archive, ArchiveConfig, Authorizer,
+    Bridge, CacheConfig, CaptureConfig, CaptureMonitor, CloseMetrics, Consumer, Coordinator,
+    CoordinatorTimeouts, DuplicatePolicy, EgressConfig, Flags, ForwardDestination, ForwardSession,
+    Forwarder, GossipConfig, Gossiper, GroupCache, Health, LivenessConfig, Locals, LogIndex,
+    LookupCache, LookupCacheConfig, NamespaceInterests, Perf, PerfConfig, PinnedTracks, Pinner,
+    PreviewConfig, Previews, Priorities, Producer, Quotas, Reauthorize, ReconnectPolicy, Remotes,
+    RemotesConsumer, RemotesProducer, RoutingPolicy, Session, SessionAuthorizer, SessionTenant,
+    TenantResolver, TimedCoordinator, Validators,
 };
 
 // A type alias for boxed future
@@ -15,19 +28,32 @@ type ServerFuture = Pin<
     Box<
         dyn Future<
             Output = (
-                anyhow::Result<(web_transport::Session, String)>,
+                anyhow::Result<(web_transport::Session, String, quic::Connection)>,
                 quic::Server,
             ),
         >,
     >,
 >;
 
+/// Endpoints with this tag accept sessions.
+pub const ENDPOINT_SERVER: &str = "server";
+
+/// Endpoints with this tag connect to the forward destinations, see [RelayConfig::announce].
+pub const ENDPOINT_FORWARD: &str = "forward";
+
+/// Endpoints with this tag connect to other origins, to serve their namespaces.
+pub const ENDPOINT_REMOTE: &str = "remote";
+
 /// Configuration for the relay.
 pub struct RelayConfig {
-    /// Listen on this address
-    pub bind: Option<net::SocketAddr>,
+    /// Listen on these addresses, each with its own endpoint sharing the same settings.
+    pub bind: Vec<net::SocketAddr>,
 
-    /// Optional list of endpoints if provided, we won't use bind
+    /// Optional list of endpoints if provided, we won't use bind.
+    ///
+    /// Each endpoint plays the roles it is tagged with, [ENDPOINT_SERVER], [ENDPOINT_FORWARD] and
+    /// [ENDPOINT_REMOTE], or every role if it has no tags. The first endpoint for a role is used
+    /// for outbound connections.
     pub endpoints: Vec<Endpoint>,
 
     /// The TLS configuration.
@@ -39,8 +65,31 @@ pub struct RelayConfig {
     /// Directory to write mlog files (one per connection)
     pub mlog_dir: Option<PathBuf>,
 
-    /// Forward all announcements to the (optional) URL.
-    pub announce: Option<Url>,
+    /// Rotation, compression, and retention policy for files in `mlog_dir`.
+    pub mlog: mlog::MlogConfig,
+
+    /// Only write a session's mlog while it shows these symptoms, instead of every session's
+    /// for its whole life. Requires `mlog_dir`.
+    pub capture: Option<CaptureConfig>,
+
+    /// Directory to record the control messages of each session to, as a replayable script
+    /// (one per connection), see [ScriptWriter].
+    pub script_dir: Option<PathBuf>,
+
+    /// Forward all announcements to these destinations, each only told about the namespaces
+    /// matching its prefixes, once however many publishers announce them.
+    pub announce: Vec<ForwardDestination>,
+
+    /// How long a forwarded namespace stays announced after its last publisher left, so a
+    /// publisher reconnecting within this long isn't seen leaving by the destinations.
+    pub announce_linger: Duration,
+
+    /// How long to wait before reconnecting to an `announce` destination or gossip peer.
+    pub reconnect: ReconnectPolicy,
+
+    /// Propagate announces to and from these peers, with loop suppression, so a small cluster
+    /// needs no coordinator. Announces go through [RelayConfig::coordinator] too.
+    pub gossip: Option<GossipConfig>,
 
     /// Our hostname which we advertise to other origins.
     /// We use QUIC, so the certificate must be valid for this address.
@@ -48,39 +97,181 @@ pub struct RelayConfig {
 
     /// The coordinator for namespace/track registration and discovery.
     pub coordinator: Arc<dyn Coordinator>,
+
+    /// Decides which origins found by the coordinator we fetch from, based on their metadata.
+    /// Every origin is used if unset.
+    pub routing: Option<Arc<dyn RoutingPolicy>>,
+
+    /// How long to wait for each coordinator call before falling back.
+    pub coordinator_timeouts: CoordinatorTimeouts,
+
+    /// Reuse the coordinator's namespace lookups for subscriptions, rather than looking up the
+    /// origin for every one. Disabled by default.
+    pub lookup_cache: Option<LookupCacheConfig>,
+
+    /// Limits for objects received from publishers and upstream origins.
+    pub object_limits: ObjectLimits,
+
+    /// Limits on concurrent announce registrations, per session and across the relay.
+    pub announce_limits: AnnounceLimits,
+
+    /// What to do when a namespace is announced while another publisher already serves it.
+    pub duplicates: DuplicatePolicy,
+
+    /// Mark announced namespaces stale while their publisher keeps its session open but sends
+    /// no objects. Disabled by default.
+    pub liveness: Option<LivenessConfig>,
+
+    /// Budgets for caching recent groups to serve FETCH. Disabled by default.
+    pub cache: CacheConfig,
+
+    /// Persist the groups of selected namespaces to disk, to serve FETCH for longer than the cache.
+    pub archive: Option<ArchiveConfig>,
+
+    /// Pace each session's FETCH responses to this many bits per second, so replays can't starve
+    /// live subgroups, which are always sent first. Unlimited by default.
+    pub fetch_bitrate: Option<u64>,
+
+    /// Limit what each accepted session is sent, by default or per tenant, dropping the least
+    /// important subgroups first. Unlimited by default.
+    pub egress: EgressConfig,
+
+    /// Sample selected tracks into previews, served by [crate::Web::with_previews].
+    pub previews: Option<PreviewConfig>,
+
+    /// Serve a built-in namespace for measuring links with `moq-perf`. Disabled by default.
+    pub perf: Option<PerfConfig>,
+
+    /// Subscribe to these tracks upstream at startup, and again whenever their origin is
+    /// available, so the first viewer doesn't wait on the origin. Retried by [Self::reconnect].
+    pub pinned_tracks: Vec<PinnedTracks>,
+
+    /// Checks the authorization tokens of accepted sessions, their announces and subscriptions.
+    /// Everything is allowed if unset.
+    pub authorizer: Option<Arc<dyn Authorizer>>,
+
+    /// Re-check the tokens of long-lived announces and subscriptions with the authorizer.
+    /// Tokens are only checked once, when the request arrives, if unset.
+    pub reauthorize: Option<Reauthorize>,
+
+    /// Decides the tenant of each accepted session, confining it to the tenant's namespaces.
+    /// Every session may use every namespace if unset.
+    pub tenants: Option<Arc<dyn TenantResolver>>,
+
+    /// Authorization token presented in CLIENT_SETUP when connecting to forward destinations or other origins.
+    pub upstream_auth_token: Option<Token>,
+
+    /// Send and accept 0-RTT data on resumed TLS sessions. Only applies to the `bind` endpoints.
+    pub zero_rtt: bool,
+
+    /// Congestion control, timeouts and buffer sizes. Only applies to the `bind` endpoints.
+    pub transport: quic::Transport,
+
+    /// Experiment flags gating new behaviour per namespace. They can be changed later through [Admin::flags].
+    pub flags: Flags,
+
+    /// Limits on the subscriptions to each namespace, so one popular namespace can't starve the others.
+    pub quotas: Quotas,
+
+    /// Policies sending audio before video in each namespace, whatever priorities subscribers ask for.
+    pub priorities: Priorities,
+
+    /// Checks of the objects published to each namespace, run before they fan out to subscribers.
+    pub validators: Validators,
+
+    /// Serve subscriptions to the tracks published to another relay in this process, from
+    /// [Relay::bridge], when they aren't published here.
+    pub bridge: Option<Bridge>,
 }
 
 /// MoQ Relay server.
 pub struct Relay {
-    quic_endpoints: Vec<Endpoint>,
-    announce_url: Option<Url>,
+    servers: Vec<quic::Server>,
+    forward_client: quic::Client,
+    announce: Vec<ForwardDestination>,
+    announce_linger: Duration,
+    reconnect: ReconnectPolicy,
+    gossip: Option<GossipConfig>,
+    instance: String,
     mlog_dir: Option<PathBuf>,
+    script_dir: Option<PathBuf>,
+    mlog: mlog::MlogConfig,
+    capture: Option<CaptureConfig>,
+    qlog_dir: Option<PathBuf>,
+    log_index: Option<LogIndex>,
     locals: Locals,
     remotes: Option<(RemotesProducer, RemotesConsumer)>,
     coordinator: Arc<dyn Coordinator>,
+    object_limits: ObjectLimits,
+    announce_limiter: AnnounceLimiter,
+    liveness: Option<LivenessConfig>,
+    cache: Option<GroupCache>,
+    archive: Option<Archive>,
+    fetch_bitrate: Option<u64>,
+    egress: EgressConfig,
+    previews: Option<Previews>,
+    priorities: Priorities,
+    bridge: Option<Bridge>,
+    perf: Option<PerfConfig>,
+    pinned_tracks: Vec<PinnedTracks>,
+    interests: NamespaceInterests,
+    admin: Admin,
+    health: Health,
+    authorizer: Option<Arc<dyn Authorizer>>,
+    reauthorize: Option<Reauthorize>,
+    tenants: Option<Arc<dyn TenantResolver>>,
+    upstream_auth_token: Option<Token>,
 }
 
 impl Relay {
     pub fn new(config: RelayConfig) -> anyhow::Result<Self> {
-        if config.bind.is_some() && !config.endpoints.is_empty() {
+        if !config.bind.is_empty() && !config.endpoints.is_empty() {
             anyhow::bail!("cannot specify both bind and endpoints");
         }
 
-        let endpoints = if config.bind.is_some() {
-            let endpoint = quic::Endpoint::new(quic::Config::new(
-                config.bind.unwrap(),
-                config.qlog_dir.clone(),
-                config.tls.clone(),
-            ))?;
-            vec![endpoint]
-        } else {
-            config.endpoints
+        let endpoints = match config.bind.is_empty() {
+            true => config.endpoints,
+            false => config
+                .bind
+                .iter()
+                .map(|bind| {
+                    quic::Endpoint::new(
+                        quic::Config::new(*bind, config.qlog_dir.clone(), config.tls.clone())
+                            .with_zero_rtt(config.zero_rtt)
+                            .with_transport(config.transport.clone()),
+                    )
+                })
+                .collect::<anyhow::Result<_>>()?,
         };
 
         if endpoints.is_empty() {
             anyhow::bail!("no endpoints available to start the server");
         }
 
+        let client = |role: &str| {
+            endpoints
+                .iter()
+                .find(|endpoint| has_role(endpoint, role))
+                .map(|endpoint| endpoint.client.clone())
+                .with_context(|| format!("no endpoint for {} connections", role))
+        };
+        let forward_client = client(ENDPOINT_FORWARD)?;
+        let remote_client = client(ENDPOINT_REMOTE)?;
+
+        let servers: Vec<quic::Server> = endpoints
+            .into_iter()
+            .filter(|endpoint| has_role(endpoint, ENDPOINT_SERVER))
+            .map(|endpoint| {
+                endpoint
+                    .server
+                    .context("missing TLS certificate for server")
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        if servers.is_empty() {
+            anyhow::bail!("no endpoints available to start the server");
+        }
+
         // Validate mlog directory if provided
         if let Some(mlog_dir) = &config.mlog_dir {
             if !mlog_dir.exists() {
@@ -91,35 +282,177 @@ impl Relay {
             }
             log::info!("mlog output enabled: {}", mlog_dir.display());
         }
+        if config.capture.is_some() && config.mlog_dir.is_none() {
+            anyhow::bail!("capturing mlog requires an mlog directory");
+        }
+        if let Some(script_dir) = &config.script_dir {
+            if !script_dir.is_dir() {
+                anyhow::bail!("script path is not a directory: {}", script_dir.display());
+            }
+            log::info!("recording control messages to: {}", script_dir.display());
+        }
 
-        let locals = Locals::new();
+        config.reconnect.validate()?;
 
-        // FIXME(itzmanish): have a generic filter to find endpoints for forward, remote etc.
-        let remote_clients = endpoints
+        let cache = match config.cache.enabled() {
+            true => Some(GroupCache::new(config.cache)?),
+            false => None,
+        };
+
+        let archive = config.archive.map(Archive::new).transpose()?;
+        let previews = config.previews.map(Previews::new);
+
+        let locals = Locals::new().with_duplicate_policy(config.duplicates);
+        let admin = match cache.clone() {
+            Some(cache) => Admin::new(locals.clone(), config.flags).with_cache(cache),
+            None => Admin::new(locals.clone(), config.flags),
+        }
+        .with_quotas(config.quotas)
+        .with_validators(config.validators);
+        let admin = match archive.clone() {
+            Some(archive) => admin.with_archive(archive),
+            None => admin,
+        };
+        let admin = match previews.clone() {
+            Some(previews) => admin.with_previews(previews),
+            None => admin,
+        };
+
+        let lookups = config.lookup_cache.map(LookupCache::new);
+        let admin = match lookups.clone() {
+            Some(lookups) => admin.with_lookup_cache(lookups),
+            None => admin,
+        };
+
+        // Bound every coordinator call, so a slow coordinator can't stall announces or subscriptions.
+        let coordinator: Arc<dyn Coordinator> = Arc::new(TimedCoordinator::new(
+            config.coordinator,
+            config.coordinator_timeouts,
+            admin.coordinator_metrics(),
+        ));
+
+        let upstreams: Vec<_> = config
+            .announce
             .iter()
-            .map(|endpoint| endpoint.client.clone())
-            .collect::<Vec<_>>();
+            .map(|destination| destination.url.clone())
+            .collect();
+        let health = Health::new(coordinator.clone(), &upstreams);
 
         // Create remote manager - uses coordinator for namespace lookups
         let remotes = Remotes {
-            coordinator: config.coordinator.clone(),
-            quic: remote_clients[0].clone(),
+            coordinator: coordinator.clone(),
+            quic: remote_client,
+            object_limits: config.object_limits,
+            auth_token: config.upstream_auth_token.clone(),
+            routing: config.routing,
+            lookups,
         }
         .produce();
 
+        // Our ID in the path of forwarded announces, so those that come back around are refused.
+        // Gossip peers know us by our node URL already.
+        let instance = match &config.gossip {
+            Some(gossip) => gossip.node.to_string(),
+            None => uuid::Uuid::new_v4().simple().to_string(),
+        };
+
+        let log_index =
+            (config.mlog_dir.is_some() || config.qlog_dir.is_some()).then(LogIndex::default);
+
         Ok(Self {
-            quic_endpoints: endpoints,
-            announce_url: config.announce,
+            servers,
+            forward_client,
+            announce: config.announce,
+            announce_linger: config.announce_linger,
+            reconnect: config.reconnect,
+            gossip: config.gossip,
+            instance,
             mlog_dir: config.mlog_dir,
+            script_dir: config.script_dir,
+            mlog: config.mlog,
+            capture: config.capture,
+            log_index,
+            qlog_dir: config.qlog_dir,
             locals,
             remotes: Some(remotes),
-            coordinator: config.coordinator,
+            coordinator,
+            object_limits: config.object_limits,
+            announce_limiter: AnnounceLimiter::new(config.announce_limits),
+            liveness: config.liveness,
+            cache,
+            archive,
+            fetch_bitrate: config.fetch_bitrate,
+            egress: config.egress,
+            previews,
+            priorities: config.priorities,
+            bridge: config.bridge,
+            perf: config.perf,
+            pinned_tracks: config.pinned_tracks,
+            interests: NamespaceInterests::new(),
+            admin,
+            health,
+            authorizer: config.authorizer,
+            reauthorize: config.reauthorize,
+            tenants: config.tenants,
+            upstream_auth_token: config.upstream_auth_token,
         })
     }
 
+    /// The addresses the relay accepts sessions on.
+    pub fn local_addrs(&self) -> anyhow::Result<Vec<net::SocketAddr>> {
+        self.servers
+            .iter()
+            .map(|server| server.local_addr())
+            .collect()
+    }
+
+    /// Handles for passing each listening socket to a new relay process, see [crate::spawn_successor].
+    pub fn handovers(&self) -> Vec<quic::Handover> {
+        self.servers.iter().map(quic::Server::handover).collect()
+    }
+
+    /// A handle for inspecting and controlling the relay, used by the admin API.
+    pub fn admin(&self) -> Admin {
+        self.admin.clone()
+    }
+
+    /// The registry of the tracks published to this relay, ex. to serve namespaces produced on
+    /// demand with [Locals::register_lazy].
+    pub fn locals(&self) -> Locals {
+        self.locals.clone()
+    }
+
+    /// Whether the relay is ready for traffic, served by [crate::Web::with_health].
+    pub fn health(&self) -> Health {
+        self.health.clone()
+    }
+
+    /// The sessions the qlog and mlog files were written for, if either is enabled, to search
+    /// the web server's log index with.
+    pub fn log_index(&self) -> Option<LogIndex> {
+        self.log_index.clone()
+    }
+
+    /// The tracks published to this relay, and to the relays it's bridged to in turn, for
+    /// another relay in this process to serve, see [RelayConfig::bridge].
+    pub fn bridge(&self) -> Bridge {
+        Bridge::new(self.locals.clone(), self.bridge.clone())
+    }
+
+    /// The latest samples of previewed tracks, served by [crate::Web::with_previews].
+    pub fn previews(&self) -> Option<Previews> {
+        self.previews.clone()
+    }
+
+    /// Our ID in the path of the announces we forward or gossip.
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
     /// Run the relay server.
     pub async fn run(self) -> anyhow::Result<()> {
         let mut tasks = FuturesUnordered::new();
+        log::info!("relay instance: {}", self.instance);
 
         // Split remotes producer/consumer and spawn producer task
         let remotes = self.remotes.map(|(producer, consumer)| {
@@ -127,62 +460,148 @@ impl Relay {
             consumer
         });
 
-        // Start the forwarder, if any
-        let forward_producer = if let Some(url) = &self.announce_url {
-            log::info!("forwarding announces to {}", url);
+        // Periodically prune old mlog files, if a retention policy is configured
+        if let Some(dir) = self.mlog_dir.clone().filter(|_| self.mlog.has_retention()) {
+            tasks.push(Self::run_mlog_retention(dir, self.mlog.clone()).boxed());
+        }
 
-            // Establish a QUIC connection to the forward URL
-            let (session, _quic_client_initial_cid) = self.quic_endpoints[0]
-                .client
-                .connect(url, None)
-                .await
-                .context("failed to establish forward connection")?;
+        if let Some(config) = self.perf.clone() {
+            let perf = Perf {
+                config,
+                locals: self.locals.clone(),
+            };
+            tasks.push(perf.run().boxed());
+        }
 
-            // Create the MoQ session over the connection
-            let (session, publisher, subscriber) =
-                moq_transport::session::Session::connect(session, None)
-                    .await
-                    .context("failed to establish forward session")?;
+        // Keep the pinned tracks subscribed upstream, each retried on its own.
+        if let Some(remotes) = &remotes {
+            for pinned in &self.pinned_tracks {
+                for name in &pinned.names {
+                    let pinner = Pinner {
+                        track: FullTrackName {
+                            namespace: pinned.namespace.clone(),
+                            name: name.clone(),
+                        },
+                        locals: self.locals.clone(),
+                        remotes: remotes.clone(),
+                        cache: self.cache.clone(),
+                        reconnect: self.reconnect,
+                    };
+                    tasks.push(pinner.run().boxed());
+                }
+            }
+        }
 
-            // Create a normal looking session, except we never forward or register announces.
+        // Start a forwarder for each destination, reconnecting on its own when it fails,
+        // and for each gossip peer, all watching the same feed
+        let forward = (!self.announce.is_empty() || self.gossip.is_some()).then(AnnounceFeed::new);
+        if let Some(feed) = &forward {
+            let locals = self.locals.clone();
+            let remotes = remotes.clone();
             let coordinator = self.coordinator.clone();
-            let session = Session {
-                session,
-                producer: Some(Producer::new(
-                    publisher,
-                    self.locals.clone(),
-                    remotes.clone(),
-                )),
-                consumer: Some(Consumer::new(
+            let object_limits = self.object_limits;
+            let fetch_bitrate = self.fetch_bitrate;
+            let announce_limiter = self.announce_limiter.clone();
+            let liveness = self.liveness;
+            let admin = self.admin.clone();
+            let cache = self.cache.clone();
+            let archive = self.archive.clone();
+            let previews = self.previews.clone();
+            let priorities = self.priorities.clone();
+            let interests = self.interests.clone();
+            let instance = self.instance.clone();
+
+            // Create a normal looking session, except we never forward or register announces.
+            let session: ForwardSession = Arc::new(move |session, publisher, subscriber| {
+                subscriber.set_object_limits(object_limits);
+                subscriber.set_object_validator(Some(Arc::new(admin.validators())));
+                publisher.set_fetch_bitrate(fetch_bitrate);
+
+                let interests = interests.session();
+                let producer = Producer::new(publisher, locals.clone(), remotes.clone())
+                    .with_flags(admin.flags())
+                    .with_quotas(admin.quotas())
+                    .with_priorities(priorities.clone())
+                    .with_interests(interests.clone());
+                let consumer = Consumer::new(
                     subscriber,
-                    self.locals.clone(),
-                    coordinator,
+                    locals.clone(),
+                    coordinator.clone(),
                     None,
-                )),
-            };
+                    announce_limiter.session(),
+                )
+                .with_reregister(admin.reregister_requests())
+                .with_teardown_metrics(admin.teardown_metrics())
+                .with_priorities(priorities.clone())
+                .with_interests(interests)
+                .with_instance(instance.clone());
 
-            let forward_producer = session.producer.clone();
+                let (producer, consumer) = match cache.clone() {
+                    Some(cache) => (
+                        producer.with_cache(cache.clone()),
+                        consumer.with_cache(cache),
+                    ),
+                    None => (producer, consumer),
+                };
+                let (producer, consumer) = match archive.clone() {
+                    Some(archive) => (
+                        producer.with_archive(archive.clone()),
+                        consumer.with_archive(archive),
+                    ),
+                    None => (producer, consumer),
+                };
+                let consumer = match previews.clone() {
+                    Some(previews) => consumer.with_previews(previews),
+                    None => consumer,
+                };
+                let (producer, consumer) = match liveness {
+                    Some(liveness) => (
+                        producer.with_liveness(liveness),
+                        consumer.with_liveness(liveness),
+                    ),
+                    None => (producer, consumer),
+                };
 
-            tasks.push(async move { session.run().await.context("forwarding failed") }.boxed());
+                Session {
+                    session,
+                    producer: Some(producer),
+                    consumer: Some(consumer),
+                }
+            });
 
-            forward_producer
-        } else {
-            None
-        };
+            for destination in self.announce {
+                let forwarder = Forwarder {
+                    destination,
+                    instance: self.instance.clone(),
+                    client: self.forward_client.clone(),
+                    auth_token: self.upstream_auth_token.clone(),
+                    feed: feed.clone(),
+                    linger: self.announce_linger,
+                    health: self.health.clone(),
+                    reconnect: self.reconnect,
+                };
+                tasks.push(forwarder.run(session.clone()).boxed());
+            }
 
-        let servers: Vec<quic::Server> = self
-            .quic_endpoints
-            .into_iter()
-            .map(|endpoint| {
-                endpoint
-                    .server
-                    .context("missing TLS certificate for server")
-            })
-            .collect::<anyhow::Result<_>>()?;
+            if let Some(gossip) = &self.gossip {
+                for peer in &gossip.peers {
+                    let gossiper = Gossiper {
+                        peer: peer.clone(),
+                        instance: self.instance.clone(),
+                        max_hops: gossip.max_hops,
+                        client: self.forward_client.clone(),
+                        auth_token: self.upstream_auth_token.clone(),
+                        feed: feed.clone(),
+                        reconnect: self.reconnect,
+                    };
+                    tasks.push(gossiper.run(session.clone()).boxed());
+                }
+            }
+        }
 
         // This will hold the futures for all our listening servers.
         let mut accepts: FuturesUnordered<ServerFuture> = FuturesUnordered::new();
-        for mut server in servers {
+        for mut server in self.servers {
             log::info!("listening on {}", server.local_addr()?);
 
             // Create a future, box it, and push it to the collection.
@@ -194,11 +613,14 @@ impl Relay {
                 .boxed(),
             );
         }
+        self.health.set_listening(true);
 
         loop {
             tokio::select! {
                 // This branch polls all the `accept` futures concurrently.
                 Some((conn_result, mut server)) = accepts.next() => {
+                    let qlog_requests = server.qlog_requests();
+
                     // An accept operation has completed.
                     // First, immediately queue up the next accept() call for this server.
                     accepts.push(
@@ -209,40 +631,226 @@ impl Relay {
                         .boxed(),
                     );
 
-                    let (conn, connection_id) = conn_result.context("failed to accept QUIC connection")?;
+                    let (conn, connection_id, connection) = conn_result.context("failed to accept QUIC connection")?;
 
                     // Construct mlog path from connection ID if mlog directory is configured
-                    let mlog_path = self.mlog_dir.as_ref()
-                        .map(|dir| dir.join(format!("{}_server.mlog", connection_id)));
+                    let mlog_path = self.mlog_dir.as_ref().map(|dir| dir.join(format!("{}_server.mlog", connection_id)));
+                    let mlog = mlog_path.as_ref().and_then(|path| match self.capture {
+                        // Only written once the capture monitor sees symptoms.
+                        Some(_) => Some(mlog::MlogWriter::standby(path, self.mlog.clone())),
+                        None => mlog::MlogWriter::with_config(path, self.mlog.clone())
+                            .map_err(|e| log::warn!("failed to create mlog: {}", e))
+                            .ok(),
+                    });
+                    let capture = self.capture.clone().zip(mlog_path);
+                    let qlog_dir = self.qlog_dir.clone().or_else(|| self.mlog_dir.clone());
+                    let script_path = self.script_dir.as_ref().map(|dir| dir.join(format!("{}_server.script.jsonl", connection_id)));
 
                     let locals = self.locals.clone();
                     let remotes = remotes.clone();
-                    let forward = forward_producer.clone();
+                    let forward = forward.clone();
+                    let instance = self.instance.clone();
                     let coordinator = self.coordinator.clone();
+                    let object_limits = self.object_limits;
+                    let fetch_bitrate = self.fetch_bitrate;
+                    let egress = self.egress.clone();
+                    let announce_limiter = self.announce_limiter.session();
+                    let liveness = self.liveness;
+                    let admin = self.admin.clone();
+                    let authorizer = self.authorizer.clone();
+                    let reauthorize = self.reauthorize;
+                    let tenants = self.tenants.clone();
+                    let cache = self.cache.clone();
+                    let archive = self.archive.clone();
+                    let previews = self.previews.clone();
+                    let priorities = self.priorities.clone();
+                    let bridge = self.bridge.clone();
+                    let log_index = self.log_index.clone();
+                    let interests = self.interests.session();
+                    let webtransport = conn.clone();
 
                     // Spawn a new task to handle the connection
                     tasks.push(async move {
                         // Create the MoQ session over the connection (setup handshake etc)
-                        let (session, publisher, subscriber) = match moq_transport::session::Session::accept(conn, mlog_path).await {
+                        let (session, publisher, subscriber) = match moq_transport::session::Session::accept_with_mlog(conn, mlog).await {
                             Ok(session) => session,
                             Err(err) => {
                                 log::warn!("failed to accept MoQ session: {}", err);
+                                close(&admin.close_metrics(), &connection_id, webtransport, &connection, Some(err));
+                                return Ok(());
+                            }
+                        };
+
+                        // Deliver datagrams the peer can't take on streams instead.
+                        let session = session.with_max_datagram_size(connection.max_datagram_size());
+
+                        // Record the control messages, so the session can be replayed.
+                        let session = match script_path.map(ScriptWriter::new) {
+                            Some(Ok(script)) => session.with_script(script),
+                            Some(Err(err)) => {
+                                log::warn!("failed to create script: {}", err);
+                                session
+                            }
+                            None => session,
+                        };
+
+                        let authorizer = match authorizer {
+                            Some(authorizer) => {
+                                if !authorizer.authorize_session(session.authorization_tokens()).await {
+                                    log::warn!("rejecting unauthorized MoQ session: {}", connection_id);
+                                    close(&admin.close_metrics(), &connection_id, webtransport, &connection, Some(SessionError::Unauthorized));
+                                    return Ok(());
+                                }
+
+                                Some(SessionAuthorizer::new(authorizer, session.authorization_tokens().to_vec()).with_reauthorize(reauthorize))
+                            }
+                            None => None,
+                        };
+
+                        let tenant = match tenants.map(|tenants| tenants.resolve(connection.server_name().as_deref(), session.authorization_tokens())) {
+                            Some(SessionTenant::Tenant(tenant)) => Some(tenant),
+                            Some(SessionTenant::Any) | None => None,
+                            Some(SessionTenant::Unknown) => {
+                                log::warn!("rejecting MoQ session without a tenant: {} server_name={:?}", connection_id, connection.server_name());
+                                close(&admin.close_metrics(), &connection_id, webtransport, &connection, Some(SessionError::Unauthorized));
                                 return Ok(());
                             }
                         };
 
+                        // Rewrite the tenant's namespaces, so they can't collide with other tenants'.
+                        let session = match tenant.as_ref().and_then(|tenant| tenant.scope()) {
+                            Some(scope) => session.with_scope(scope),
+                            None => session,
+                        };
+
+                        if let Some(subscriber) = &subscriber {
+                            subscriber.set_object_limits(object_limits);
+                            subscriber.set_object_validator(Some(Arc::new(admin.validators())));
+                        }
+                        if let Some(publisher) = &publisher {
+                            publisher.set_fetch_bitrate(fetch_bitrate);
+                            publisher.set_egress_bitrate(egress.bitrate(tenant.as_ref()));
+                        }
+                        let clock = subscriber.as_ref().map(|subscriber| subscriber.clock_skew());
+
+                        let capture = capture.zip(session.mlog()).map(|((config, mlog_path), mlog)| CaptureMonitor {
+                            qlog: config.qlog.then_some(qlog_requests).zip(qlog_dir),
+                            config,
+                            connection_id: connection_id.clone(),
+                            connection: connection.clone(),
+                            mlog,
+                            mlog_path,
+                            publisher: publisher.clone(),
+                            subscriber: subscriber.clone(),
+                            metrics: admin.capture_metrics(),
+                        });
+
+                        // Our subscriber consumes the peer's announces, so the peer is a publisher, and vice versa.
+                        let _admin_session = admin.register_session(
+                            connection_id.clone(),
+                            webtransport.clone(),
+                            connection.clone(),
+                            session.goaway(),
+                            subscriber.clone(),
+                            publisher.clone(),
+                            subscriber.as_ref().map(|_| announce_limiter.clone()),
+                        );
+                        let _log_session = log_index.map(|index| index.open(connection_id.clone(), connection.remote_address(), subscriber.clone(), publisher.clone()));
+                        let reregister = admin.reregister_requests();
+                        let teardown_metrics = admin.teardown_metrics();
+
                         // Create our MoQ relay session
                         let moq_session = session;
                         let session = Session {
                             session: moq_session,
-                            producer: publisher.map(|publisher| Producer::new(publisher, locals.clone(), remotes)),
-                            consumer: subscriber.map(|subscriber| Consumer::new(subscriber, locals, coordinator, forward)),
+                            producer: publisher.map(|publisher| {
+                                let producer = Producer::new(publisher, locals.clone(), remotes)
+                                    .with_flags(admin.flags())
+                                    .with_quotas(admin.quotas())
+                                    .with_priorities(priorities.clone())
+                                    .with_interests(interests.clone());
+                                let producer = match cache.clone() {
+                                    Some(cache) => producer.with_cache(cache),
+                                    None => producer,
+                                };
+                                let producer = match archive.clone() {
+                                    Some(archive) => producer.with_archive(archive),
+                                    None => producer,
+                                };
+                                let producer = match tenant.clone() {
+                                    Some(tenant) => producer.with_tenant(tenant),
+                                    None => producer,
+                                };
+                                let producer = match bridge {
+                                    Some(bridge) => producer.with_bridge(bridge),
+                                    None => producer,
+                                };
+                                let producer = match liveness {
+                                    Some(liveness) => producer.with_liveness(liveness),
+                                    None => producer,
+                                };
+                                match authorizer.clone() {
+                                    Some(authorizer) => producer.with_authorizer(authorizer),
+                                    None => producer,
+                                }
+                            }),
+                            consumer: subscriber.map(|subscriber| {
+                                let consumer = Consumer::new(subscriber, locals, coordinator, forward, announce_limiter)
+                                    .with_reregister(reregister)
+                                    .with_teardown_metrics(teardown_metrics)
+                                    .with_priorities(priorities)
+                                    .with_interests(interests)
+                                    .with_instance(instance);
+                                let consumer = match cache {
+                                    Some(cache) => consumer.with_cache(cache),
+                                    None => consumer,
+                                };
+                                let consumer = match archive {
+                                    Some(archive) => consumer.with_archive(archive),
+                                    None => consumer,
+                                };
+                                let consumer = match previews {
+                                    Some(previews) => consumer.with_previews(previews),
+                                    None => consumer,
+                                };
+                                let consumer = match tenant {
+                                    Some(tenant) => consumer.with_tenant(tenant),
+                                    None => consumer,
+                                };
+                                let consumer = match liveness {
+                                    Some(liveness) => consumer.with_liveness(liveness),
+                                    None => consumer,
+                                };
+                                match authorizer {
+                                    Some(authorizer) => consumer.with_authorizer(authorizer),
+                                    None => consumer,
+                                }
+                            }),
                         };
 
-                        if let Err(err) = session.run().await {
+                        let run = async {
+                            match capture {
+                                Some(capture) => tokio::select! {
+                                    res = session.run() => res,
+                                    _ = capture.run() => unreachable!("capture monitor stopped"),
+                                },
+                                None => session.run().await,
+                            }
+                        };
+                        let res = match clock {
+                            Some(clock) => tokio::select! {
+                                res = run => res,
+                                _ = Self::follow_rtt(connection.clone(), clock) => unreachable!("rtt follower stopped"),
+                            },
+                            None => run.await,
+                        };
+                        if let Err(err) = &res {
                             log::warn!("failed to run MoQ session: {}", err);
                         }
 
+                        let reason = close(&admin.close_metrics(), &connection_id, webtransport, &connection, res.err());
+                        log::info!("session ended: cid={} reason={} stats={:?}", connection_id, reason, connection.stats());
+
                         Ok(())
                     }.boxed());
                 },
@@ -250,4 +858,618 @@ impl Relay {
             }
         }
     }
+
+    // Tell the clock skew estimate of a publishing peer the one-way delay of its path, half the RTT.
+    async fn follow_rtt(connection: quic::Connection, clock: ClockSkew) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+
+        loop {
+            interval.tick().await;
+            clock.set_one_way_delay(connection.stats().rtt / 2);
+        }
+    }
+
+    async fn run_mlog_retention(dir: PathBuf, config: mlog::MlogConfig) -> anyhow::Result<()> {
+        let mut interval = tokio::time::interval(Duration::from_secs(60));
+
+        loop {
+            interval.tick().await;
+
+            let dir = dir.clone();
+            let config = config.clone();
+            match tokio::task::spawn_blocking(move || mlog::enforce_retention(&dir, &config))
+                .await?
+            {
+                Ok(0) => {}
+                Ok(removed) => log::debug!("removed {} expired mlog files", removed),
+                Err(err) => log::warn!("failed to enforce mlog retention: {}", err),
+            }
+        }
+    }
+}
+
+// Close the connection with `err` unless the peer or the network already closed it,
+// and record why it closed.
+fn close(
+    closes: &CloseMetrics,
+    connection_id: &str,
+    webtransport: web_transport::Session,
+    connection: &quic::Connection,
+    err: Option<SessionError>,
+) -> CloseReason {
+    let reason = match connection.close_reason() {
+        Some(reason) => reason,
+        None => {
+            if let Some(err) = &err {
+                webtransport.close(err.code() as u32, &err.to_string());
+            }
+            CloseReason::Local
+        }
+    };
+
+    // Our error only explains the close if we were the ones to close.
+    let err = err
+        .filter(|_| reason == CloseReason::Local)
+        .map(|err| err.to_string());
+    closes.record(
+        connection_id.to_string(),
+        connection.remote_address(),
+        &reason,
+        err,
+    );
+
+    reason
+}
+
+// Whether the endpoint plays `role`, as one of its tags or because it has none.
+fn has_role(endpoint: &Endpoint, role: &str) -> bool {
+    endpoint.tags.is_empty() || endpoint.tags.contains(role)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use moq_native_ietf::tls;
+    use moq_transport::{
+        coding::TrackNamespace,
+        message::PublishNamespaceErrorCode,
+        serve::{self, ServeError, TrackReaderMode},
+        session::{ResilientSubscriber, SessionError},
+    };
+
+    use crate::{CoordinatorError, CoordinatorResult, NamespaceOrigin, NamespaceRegistration};
+
+    /// Serves every namespace locally.
+    struct Standalone;
+
+    #[async_trait]
+    impl Coordinator for Standalone {
+        async fn register_namespace(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            Ok(NamespaceRegistration::new(()))
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            Err(CoordinatorError::NamespaceNotFound)
+        }
+    }
+
+    fn tls() -> tls::Config {
+        let fixture =
+            |name: &str| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata")).join(name);
+
+        tls::Config::load(&tls::Args {
+            cert: vec![fixture("localhost.crt")],
+            key: vec![fixture("localhost.key")],
+            root: vec![fixture("localhost.crt")],
+            disable_verify: true,
+        })
+        .unwrap()
+    }
+
+    async fn connect_session(
+        addr: net::SocketAddr,
+    ) -> anyhow::Result<(
+        moq_transport::session::Session,
+        moq_transport::session::Publisher,
+        moq_transport::session::Subscriber,
+    )> {
+        let client = quic::Endpoint::new(quic::Config::new(
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            tls(),
+        ))?
+        .client;
+
+        let url = format!("https://localhost:{}", addr.port()).parse()?;
+        let (session, _, _) = client.connect(&url, Some(addr)).await?;
+        Ok(moq_transport::session::Session::connect(session, None).await?)
+    }
+
+    async fn connect(
+        addr: net::SocketAddr,
+    ) -> (
+        moq_transport::session::Publisher,
+        moq_transport::session::Subscriber,
+    ) {
+        let (session, publisher, subscriber) = connect_session(addr).await.unwrap();
+        tokio::spawn(session.run());
+
+        (publisher, subscriber)
+    }
+
+    fn config() -> RelayConfig {
+        RelayConfig {
+            bind: vec!["127.0.0.1:0".parse().unwrap()],
+            endpoints: vec![],
+            tls: tls(),
+            qlog_dir: None,
+            mlog_dir: None,
+            script_dir: None,
+            mlog: Default::default(),
+            capture: None,
+            announce: Vec::new(),
+            announce_linger: Duration::ZERO,
+            reconnect: Default::default(),
+            gossip: None,
+            node: None,
+            coordinator: Arc::new(Standalone),
+            routing: None,
+            coordinator_timeouts: Default::default(),
+            lookup_cache: None,
+            object_limits: Default::default(),
+            announce_limits: Default::default(),
+            duplicates: Default::default(),
+            liveness: None,
+            cache: Default::default(),
+            fetch_bitrate: None,
+            egress: Default::default(),
+            archive: None,
+            previews: None,
+            perf: None,
+            pinned_tracks: Vec::new(),
+            authorizer: None,
+            reauthorize: None,
+            upstream_auth_token: None,
+            zero_rtt: false,
+            transport: Default::default(),
+            flags: Default::default(),
+            quotas: Default::default(),
+            priorities: Default::default(),
+            validators: Default::default(),
+            bridge: None,
+            tenants: None,
+        }
+    }
+
+    /// Announce `namespace` with a "clock" track, writing a datagram every 20ms.
+    fn publish(publisher: moq_transport::session::Publisher, namespace: TrackNamespace) {
+        publish_groups(publisher, namespace, 0, "tick");
+    }
+
+    /// Like [publish], numbering the groups from `first_group`.
+    fn publish_groups(
+        mut publisher: moq_transport::session::Publisher,
+        namespace: TrackNamespace,
+        first_group: u64,
+        payload: &'static str,
+    ) {
+        let (mut tracks, _, tracks_reader) = serve::Tracks::new(namespace).produce();
+        let mut datagrams = tracks.create("clock").unwrap().datagrams().unwrap();
+        tokio::spawn(async move { publisher.announce(tracks_reader).await });
+        tokio::spawn(async move {
+            // Hold the writer so the track isn't closed.
+            let _tracks = tracks;
+            for group_id in first_group.. {
+                let datagram = serve::Datagram {
+                    group_id,
+                    object_id: 0,
+                    priority: 0,
+                    payload: payload.into(),
+                    extension_headers: Default::default(),
+                };
+                if datagrams.write(datagram).is_err() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        });
+    }
+
+    /// Subscribe to the "clock" track, retrying until the announce has reached the relay.
+    async fn receive(
+        subscriber: &mut moq_transport::session::Subscriber,
+        namespace: TrackNamespace,
+    ) -> serve::Datagram {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let (writer, reader) =
+                    serve::Track::new(namespace.clone(), "clock".into()).produce();
+                let subscribe = subscriber.subscribe_handle(writer);
+
+                let received = async {
+                    match reader.mode().await? {
+                        TrackReaderMode::Datagrams(mut datagrams) => datagrams.read().await,
+                        _ => Ok(None),
+                    }
+                };
+
+                tokio::select! {
+                    Ok(Some(datagram)) = received => return datagram,
+                    _ = subscribe.closed() => {},
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .expect("no datagram relayed")
+    }
+
+    #[tokio::test]
+    async fn listeners_share_locals() {
+        let relay = Relay::new(RelayConfig {
+            bind: vec!["127.0.0.1:0".parse().unwrap(); 2],
+            ..config()
+        })
+        .unwrap();
+
+        let addrs = relay.local_addrs().unwrap();
+        assert_eq!(addrs.len(), 2);
+        assert_ne!(addrs[0], addrs[1]);
+
+        // The relay future isn't Send, so it runs alongside the clients rather than spawned.
+        let clients = async {
+            // Publish on the first listener, and subscribe through the second.
+            let namespace = TrackNamespace::from_utf8_path("live");
+            let (publisher, _) = connect(addrs[0]).await;
+            publish(publisher, namespace.clone());
+
+            let (_, mut subscriber) = connect(addrs[1]).await;
+            receive(&mut subscriber, namespace).await
+        };
+
+        let datagram = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            datagram = clients => datagram,
+        };
+        assert_eq!(datagram.payload, "tick");
+    }
+
+    #[tokio::test]
+    async fn local_subscriptions_share_upstream() {
+        let relay = Relay::new(config()).unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let clients = async {
+            let (publisher, _) = connect(addr).await;
+            publish(publisher.clone(), namespace.clone());
+
+            let (_, mut first) = connect(addr).await;
+            let (_, mut second) = connect(addr).await;
+            receive(&mut first, namespace.clone()).await;
+
+            // Poll the publisher, as subscriptions come and go asynchronously.
+            let upstream = |count: usize| {
+                let publisher = publisher.clone();
+                async move {
+                    while publisher.subscriptions().len() != count {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                }
+            };
+
+            // Both downstream subscriptions are fed by a single upstream one...
+            let mut held = Vec::new();
+            for subscriber in [&mut first, &mut second] {
+                let (writer, reader) =
+                    serve::Track::new(namespace.clone(), "clock".into()).produce();
+                let subscribe = subscriber.subscribe_handle(writer);
+                let mode = tokio::time::timeout(Duration::from_secs(5), reader.mode())
+                    .await
+                    .expect("no objects received")
+                    .unwrap();
+                held.push((subscribe, mode));
+            }
+            tokio::time::timeout(Duration::from_secs(5), upstream(1))
+                .await
+                .expect("upstream subscriptions not shared");
+
+            // ...which is dropped once the last one leaves.
+            held.pop();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(publisher.subscriptions().len(), 1);
+
+            held.clear();
+            tokio::time::timeout(Duration::from_secs(5), upstream(0))
+                .await
+                .expect("idle upstream subscription kept");
+        };
+
+        tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            _ = clients => {},
+        };
+    }
+
+    #[tokio::test]
+    async fn interest_reaches_publisher() {
+        let relay = Relay::new(config()).unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+
+        let clients = async {
+            let (mut publisher, _) = connect(addr).await;
+            let (_, mut subscriber) = connect(addr).await;
+
+            // Nothing is announced until a subscriber shows interest.
+            let prefix = TrackNamespace::from_utf8_path("live");
+            let subscribe_namespace = subscriber.subscribe_namespace(prefix.clone());
+
+            let mut interest = tokio::time::timeout(Duration::from_secs(5), publisher.interest())
+                .await
+                .expect("no interest forwarded")
+                .unwrap();
+            assert_eq!(interest.prefix, prefix);
+
+            let namespace = TrackNamespace::from_utf8_path("live/room");
+            assert!(interest.matches(&namespace));
+            assert!(!interest.matches(&TrackNamespace::from_utf8_path("vod")));
+            interest.ok().unwrap();
+            publish(publisher, namespace.clone());
+
+            subscribe_namespace.ok().await.unwrap();
+            let datagram = receive(&mut subscriber, namespace).await;
+
+            // The publisher can watch for the subscriber losing interest.
+            drop(subscribe_namespace);
+            tokio::time::timeout(Duration::from_secs(5), interest.closed())
+                .await
+                .expect("interest not closed")
+                .unwrap();
+
+            datagram
+        };
+
+        let datagram = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            datagram = clients => datagram,
+        };
+        assert_eq!(datagram.payload, "tick");
+    }
+
+    #[tokio::test]
+    async fn announce_rejections() {
+        let relay = Relay::new(RelayConfig {
+            announce_limits: AnnounceLimits {
+                max_namespaces: Some(1),
+                ..Default::default()
+            },
+            ..config()
+        })
+        .unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+
+        let clients = async {
+            let namespace = TrackNamespace::from_utf8_path("live");
+            let (publisher, _) = connect(addr).await;
+            publish(publisher.clone(), namespace.clone());
+
+            let (other, mut subscriber) = connect(addr).await;
+            receive(&mut subscriber, namespace.clone()).await;
+
+            // Announce `path` and return the PUBLISH_NAMESPACE_ERROR code.
+            let rejected = |mut publisher: moq_transport::session::Publisher, path: &str| {
+                let (_, _, reader) =
+                    serve::Tracks::new(TrackNamespace::from_utf8_path(path)).produce();
+                async move {
+                    match tokio::time::timeout(Duration::from_secs(5), publisher.announce(reader))
+                        .await
+                        .expect("announce not rejected")
+                    {
+                        Err(SessionError::Serve(ServeError::Closed(code))) => {
+                            PublishNamespaceErrorCode::from_code(code)
+                        }
+                        res => panic!("unexpected announce result: {:?}", res),
+                    }
+                }
+            };
+
+            (
+                rejected(publisher, "vod").await,
+                rejected(other, "live").await,
+            )
+        };
+
+        let (limited, duplicate) = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            res = clients => res,
+        };
+        assert_eq!(limited, Some(PublishNamespaceErrorCode::LimitExceeded));
+        assert_eq!(duplicate, Some(PublishNamespaceErrorCode::Duplicate));
+    }
+
+    #[tokio::test]
+    async fn duplicate_namespaces() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        // The second publisher takes over, and the first one's announce is cancelled.
+        let relay = Relay::new(RelayConfig {
+            duplicates: DuplicatePolicy::Replace,
+            ..config()
+        })
+        .unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+
+        let clients = async {
+            let (mut first, _) = connect(addr).await;
+            let (_tracks, _, reader) = serve::Tracks::new(namespace.clone()).produce();
+            let announced = tokio::spawn(async move { first.announce(reader).await });
+
+            let (_, mut subscriber) = connect(addr).await;
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let (second, _) = connect(addr).await;
+            publish_groups(second, namespace.clone(), 0, "second");
+
+            let cancelled = tokio::time::timeout(Duration::from_secs(5), announced)
+                .await
+                .expect("announce not cancelled")
+                .unwrap();
+            (cancelled, receive(&mut subscriber, namespace.clone()).await)
+        };
+
+        let (cancelled, datagram) = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            res = clients => res,
+        };
+        assert!(matches!(
+            cancelled,
+            Err(SessionError::Serve(ServeError::Cancel))
+        ));
+        assert_eq!(datagram.payload, "second");
+
+        // Both publishers serve the namespace, and the subscription finds the one with the track.
+        let relay = Relay::new(RelayConfig {
+            duplicates: DuplicatePolicy::Merge,
+            ..config()
+        })
+        .unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+
+        let clients = async {
+            let (first, _) = connect(addr).await;
+            publish_groups(first, namespace.clone(), 0, "first");
+
+            let (_, mut subscriber) = connect(addr).await;
+            receive(&mut subscriber, namespace.clone()).await;
+
+            // Without a "clock" track.
+            let (mut second, _) = connect(addr).await;
+            let (_tracks, _, reader) = serve::Tracks::new(namespace.clone()).produce();
+            tokio::spawn(async move { second.announce(reader).await });
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let (_, mut subscriber) = connect(addr).await;
+            receive(&mut subscriber, namespace.clone()).await
+        };
+
+        let datagram = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            res = clients => res,
+        };
+        assert_eq!(datagram.payload, "first");
+    }
+
+    #[tokio::test]
+    async fn goaway_reaches_sessions() {
+        let relay = Relay::new(config()).unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+        let admin = relay.admin();
+
+        let clients = async {
+            let (session, _, _) = connect_session(addr).await.unwrap();
+            let goaway = session.goaway();
+            tokio::spawn(session.run());
+
+            while admin.sessions().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert_eq!(admin.goaway(""), 1);
+
+            tokio::time::timeout(Duration::from_secs(5), goaway.received())
+                .await
+                .expect("no GOAWAY received")
+        };
+
+        let uri = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            uri = clients => uri,
+        };
+        assert_eq!(uri.as_deref(), Some(""));
+    }
+
+    #[tokio::test]
+    async fn resilient_subscriber_survives_publisher_restart() {
+        let relay = Relay::new(config()).unwrap();
+        let addr = relay.local_addrs().unwrap()[0];
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let clients = async {
+            let (session, publisher, _) = connect_session(addr).await.unwrap();
+            let first = tokio::spawn(session.run());
+            publish_groups(publisher, namespace.clone(), 0, "first");
+
+            // The first target is down, so the subscriber fails over to the relay.
+            let mut resilient = ResilientSubscriber::new(
+                vec!["down".to_string(), "relay".to_string()],
+                move |target: String| async move {
+                    if target == "down" {
+                        anyhow::bail!("connection refused");
+                    }
+                    let (session, _, subscriber) = connect_session(addr).await?;
+                    anyhow::Ok((session, subscriber))
+                },
+            )
+            .with_backoff(Duration::from_millis(10), Duration::from_millis(100));
+
+            let (writer, reader) = serve::Track::new(namespace.clone(), "clock".into()).produce();
+            tokio::spawn(async move { resilient.subscribe(writer).await });
+
+            tokio::time::timeout(Duration::from_secs(10), async {
+                let TrackReaderMode::Datagrams(mut datagrams) = reader.mode().await.unwrap() else {
+                    panic!("expected datagrams");
+                };
+                assert_eq!(datagrams.read().await.unwrap().unwrap().payload, "first");
+
+                // Restart the publisher, which ends the subscription at the relay.
+                first.abort();
+                let (publisher, _) = connect(addr).await;
+                publish_groups(publisher, namespace.clone(), 1000, "second");
+
+                // The same reader carries on with the new publisher's groups.
+                loop {
+                    let datagram = datagrams.read().await.unwrap().unwrap();
+                    if datagram.payload == "second" {
+                        return datagram;
+                    }
+                }
+            })
+            .await
+            .expect("subscription not resumed")
+        };
+
+        let datagram = tokio::select! {
+            res = relay.run() => panic!("relay exited: {:?}", res),
+            datagram = clients => datagram,
+        };
+        assert!(datagram.group_id >= 1000);
+    }
+
+    #[tokio::test]
+    async fn roles() {
+        let mut endpoint = quic::Endpoint::new(quic::Config::new(
+            "127.0.0.1:0".parse().unwrap(),
+            None,
+            tls(),
+        ))
+        .unwrap();
+
+        // An untagged endpoint does everything.
+        assert!(has_role(&endpoint, ENDPOINT_SERVER));
+        assert!(has_role(&endpoint, ENDPOINT_REMOTE));
+
+        endpoint.tags.insert(ENDPOINT_FORWARD.to_string());
+        assert!(has_role(&endpoint, ENDPOINT_FORWARD));
+        assert!(!has_role(&endpoint, ENDPOINT_SERVER));
+    }
 }
@@ -11,12 +11,13 @@ use futures::stream::FuturesUnordered;
 use futures::FutureExt;
 use futures::StreamExt;
 use moq_native_ietf::quic;
-use moq_transport::coding::TrackNamespace;
+use moq_transport::coding::{Telemetry, Token, TrackNamespace};
 use moq_transport::serve::{Track, TrackReader, TrackWriter};
+use moq_transport::session::ObjectLimits;
 use moq_transport::watch::State;
 use url::Url;
 
-use crate::Coordinator;
+use crate::{Coordinator, LookupCache, RoutingPolicy};
 
 /// Information about remote origins.
 pub struct Remotes {
@@ -25,6 +26,18 @@ pub struct Remotes {
 
     // A QUIC endpoint we'll use to fetch from other origins.
     pub quic: quic::Client,
+
+    /// Limits for objects received from other origins.
+    pub object_limits: ObjectLimits,
+
+    /// Authorization token presented to other origins in CLIENT_SETUP.
+    pub auth_token: Option<Token>,
+
+    /// Decides which origins we fetch from, based on their metadata. Every origin is used if unset.
+    pub routing: Option<Arc<dyn RoutingPolicy>>,
+
+    /// Reuses the coordinator's lookups for a while. Every subscription looks up its origin if unset.
+    pub lookups: Option<LookupCache>,
 }
 
 impl Remotes {
@@ -90,6 +103,11 @@ impl RemotesProducer {
                         // Run the remote producer
                         if let Err(err) = remote.run().await {
                             log::warn!("failed serving remote: {:?}, error: {}", info, err);
+
+                            // Look the namespaces up again, in case they moved elsewhere.
+                            if let Some(lookups) = &info.lookups {
+                                lookups.invalidate_origin(&url);
+                            }
                         }
 
                         url
@@ -134,8 +152,23 @@ impl RemotesConsumer {
         &self,
         namespace: &TrackNamespace,
     ) -> anyhow::Result<Option<RemoteConsumer>> {
-        // Always fetch the origin instead of using the (potentially invalid) cache.
-        let (origin, client) = self.coordinator.lookup(namespace).await?;
+        // The cached origin may be stale, but connecting to it fails and invalidates it.
+        let (origin, client) = match &self.lookups {
+            Some(lookups) => lookups.lookup(self.coordinator.as_ref(), namespace).await?,
+            None => self.coordinator.lookup(namespace).await?,
+        };
+
+        if let Some(routing) = &self.routing {
+            if !routing.allows(namespace, &origin) {
+                log::info!(
+                    "routing policy rejected origin: namespace={} url={} metadata={:?}",
+                    namespace,
+                    origin.url(),
+                    origin.metadata()
+                );
+                return Ok(None);
+            }
+        }
 
         // Check if we already have a remote for this origin
         let state = self.state.lock();
@@ -234,8 +267,14 @@ impl RemoteProducer {
             &self.quic
         };
         // TODO reuse QUIC and MoQ sessions
-        let (session, _quic_client_initial_cid) = client.connect(&self.url, self.addr).await?;
-        let (session, subscriber) = moq_transport::session::Subscriber::connect(session).await?;
+        let (session, _quic_client_initial_cid, _) = client.connect(&self.url, self.addr).await?;
+        let (session, _, subscriber) = moq_transport::session::Session::connect_with_token(
+            session,
+            None,
+            self.auth_token.clone(),
+        )
+        .await?;
+        subscriber.set_object_limits(self.object_limits);
 
         // Run the session
         let mut session = session.run().boxed();
@@ -255,6 +294,7 @@ impl RemoteProducer {
 
                     let info = track.info.clone();
                     let mut subscriber = subscriber.clone();
+                    log::info!("subscribing to remote {}: {:?}", self.url, info);
 
                     tasks.push(async move {
                         if let Err(err) = subscriber.subscribe(track).await {
@@ -316,10 +356,15 @@ impl RemoteConsumer {
     }
 
     /// Request a track from the broadcast.
+    /// The trace ID, the groups already cached here and the subscriber's telemetry are forwarded
+    /// upstream only if this request creates a new remote track.
     pub fn subscribe(
         &self,
         namespace: &TrackNamespace,
         name: &str,
+        trace_id: Option<String>,
+        cached_groups: Option<ops::RangeInclusive<u64>>,
+        telemetry: Option<Telemetry>,
     ) -> anyhow::Result<Option<RemoteTrackReader>> {
         let key = (namespace.clone(), name.to_string());
         let state = self.state.lock();
@@ -334,7 +379,11 @@ impl RemoteConsumer {
             None => return Ok(None),
         };
 
-        let (writer, reader) = Track::new(namespace.clone(), name.to_string()).produce();
+        let (writer, reader) = Track::new(namespace.clone(), name.to_string())
+            .with_trace_id(trace_id)
+            .with_cached_groups(cached_groups)
+            .with_telemetry(telemetry)
+            .produce();
         let reader = RemoteTrackReader::new(reader, self.state.clone());
 
         // Insert the track into our Map so we deduplicate future requests.
@@ -0,0 +1,99 @@
+use moq_transport::coding::TrackNamespace;
+
+use crate::NamespaceOrigin;
+
+/// Decides whether to fetch a namespace from the origin the coordinator found for it,
+/// based on the metadata the origin registered, ex. its region, capacity or auth domain.
+///
+/// Rejected origins are treated as if the namespace wasn't found. Everything is allowed by default.
+pub trait RoutingPolicy: Send + Sync {
+    fn allows(&self, _namespace: &TrackNamespace, _origin: &NamespaceOrigin) -> bool {
+        true
+    }
+}
+
+/// Allows origins whose metadata has the given values, and at least the given numeric ones.
+///
+/// ```rust,ignore
+/// // Only fetch from relays in our region with spare capacity.
+/// let policy = MetadataPolicy::new()
+///     .with_required("region", "eu-west")
+///     .with_minimum("capacity", 1);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MetadataPolicy {
+    required: Vec<(String, String)>,
+    minimums: Vec<(String, u64)>,
+}
+
+impl MetadataPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require the origin's `key` to be exactly `value`.
+    pub fn with_required(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.required.push((key.into(), value.into()));
+        self
+    }
+
+    /// Require the origin's `key` to be a number of at least `min`.
+    pub fn with_minimum(mut self, key: impl Into<String>, min: u64) -> Self {
+        self.minimums.push((key.into(), min));
+        self
+    }
+}
+
+impl RoutingPolicy for MetadataPolicy {
+    fn allows(&self, _namespace: &TrackNamespace, origin: &NamespaceOrigin) -> bool {
+        let required = self
+            .required
+            .iter()
+            .all(|(key, value)| origin.metadata_value(key) == Some(value.as_str()));
+
+        let minimums = self.minimums.iter().all(|(key, min)| {
+            origin
+                .metadata_value(key)
+                .and_then(|value| value.parse::<u64>().ok())
+                .is_some_and(|value| value >= *min)
+        });
+
+        required && minimums
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metadata_policy() {
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let origin = |metadata: &[(&str, &str)]| {
+            metadata.iter().fold(
+                NamespaceOrigin::new(
+                    namespace.clone(),
+                    "https://a.example".parse().unwrap(),
+                    None,
+                ),
+                |origin, (key, value)| origin.with_metadata((key.to_string(), value.to_string())),
+            )
+        };
+
+        let policy = MetadataPolicy::new()
+            .with_required("region", "eu")
+            .with_minimum("capacity", 10);
+
+        assert!(policy.allows(&namespace, &origin(&[("region", "eu"), ("capacity", "10")])));
+        assert!(!policy.allows(&namespace, &origin(&[("region", "us"), ("capacity", "10")])));
+        assert!(!policy.allows(&namespace, &origin(&[("region", "eu"), ("capacity", "9")])));
+        assert!(!policy.allows(
+            &namespace,
+            &origin(&[("region", "eu"), ("capacity", "lots")])
+        ));
+        assert!(!policy.allows(&namespace, &origin(&[])));
+
+        // An empty policy allows origins without metadata.
+        assert!(MetadataPolicy::new().allows(&namespace, &origin(&[])));
+    }
+}
@@ -12,6 +12,8 @@ pub struct Session {
 impl Session {
     /// Run the session, producer, and consumer as necessary.
     pub async fn run(self) -> Result<(), SessionError> {
+        let teardown = self.consumer.as_ref().map(|consumer| consumer.teardown());
+
         let mut tasks = FuturesUnordered::new();
         tasks.push(self.session.run().boxed());
 
@@ -23,6 +25,13 @@ impl Session {
             tasks.push(consumer.run().boxed());
         }
 
-        tasks.select_next_some().await
+        let res = tasks.select_next_some().await;
+
+        // Release the peer's announces before the tasks serving them are dropped.
+        if let Some(teardown) = teardown {
+            teardown.run(tasks);
+        }
+
+        res
     }
 }
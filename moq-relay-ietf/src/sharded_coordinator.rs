@@ -0,0 +1,727 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use moq_native_ietf::quic;
+use moq_transport::coding::TrackNamespace;
+
+use crate::{
+    Coordinator, CoordinatorError, CoordinatorResult, NamespaceOrigin, NamespaceRegistration,
+};
+
+/// Pick the shard responsible for a namespace, by rendezvous hashing its first `depth` fields
+/// with the name of each shard.
+///
+/// Every relay configured with the same shard names picks the same shard, whatever their order.
+/// Adding or removing a shard only moves the namespaces it gains or loses. Namespaces sharing
+/// their first `depth` fields land on the same shard, so prefix lookups find them.
+pub fn shard_for<S: AsRef<str>>(
+    namespace: &TrackNamespace,
+    depth: usize,
+    shards: &[S],
+) -> Option<usize> {
+    shards
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, shard)| shard_score(namespace, depth, shard.as_ref()))
+        .map(|(index, _)| index)
+}
+
+// FNV-1a, which unlike the std hashers is stable across builds and platforms, finished with
+// splitmix64 so similar inputs don't get similar scores.
+fn shard_score(namespace: &TrackNamespace, depth: usize, shard: &str) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    let mut write = |bytes: &[u8]| {
+        for byte in (bytes.len() as u64).to_be_bytes().iter().chain(bytes) {
+            hash = (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+
+    write(shard.as_bytes());
+    for field in namespace.fields.iter().take(depth) {
+        write(&field.value);
+    }
+
+    let mut hash = hash.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^ (hash >> 31)
+}
+
+/// Spreads namespaces over several coordinators, ex. one per registry server, so no single
+/// registry holds every namespace of a large deployment.
+///
+/// Each namespace is registered with and looked up on the shard picked by [shard_for]. Shards
+/// can be added and removed at runtime: registrations that move are made on their new shard
+/// before leaving the old one, and lookups that miss fall back to the previous layout while
+/// other relays catch up.
+pub struct ShardedCoordinator {
+    state: Arc<Mutex<ShardedState>>,
+    depth: usize,
+    handoff: Duration,
+}
+
+#[derive(Clone)]
+struct Shard {
+    name: String,
+    coordinator: Arc<dyn Coordinator>,
+}
+
+struct ShardedState {
+    shards: Vec<Shard>,
+
+    // The shards before the last change, tried by lookups that miss.
+    previous: Vec<Shard>,
+
+    // Our registrations by ID, so they can be moved between shards.
+    registrations: HashMap<u64, Registered>,
+    next_id: u64,
+}
+
+struct Registered {
+    namespace: TrackNamespace,
+    shard: String,
+    registration: NamespaceRegistration,
+}
+
+impl ShardedState {
+    fn owner(&self, namespace: &TrackNamespace, depth: usize) -> Option<&Shard> {
+        let names: Vec<&str> = self
+            .shards
+            .iter()
+            .map(|shard| shard.name.as_str())
+            .collect();
+        shard_for(namespace, depth, &names).map(|index| &self.shards[index])
+    }
+
+    fn shard(&self, name: &str) -> Option<&Shard> {
+        self.shards.iter().find(|shard| shard.name == name)
+    }
+
+    // The shard holding our registration of the namespace, or else the one that should.
+    fn registered(&self, namespace: &TrackNamespace, depth: usize) -> Option<Shard> {
+        let name = self
+            .registrations
+            .values()
+            .find(|registered| &registered.namespace == namespace)
+            .map(|registered| registered.shard.as_str());
+
+        name.and_then(|name| self.shard(name))
+            .or_else(|| self.owner(namespace, depth))
+            .cloned()
+    }
+
+    // The registrations that belong on another shard.
+    fn misplaced(&self, depth: usize) -> Vec<u64> {
+        self.registrations
+            .iter()
+            .filter(|(_, registered)| {
+                self.owner(&registered.namespace, depth)
+                    .is_some_and(|owner| owner.name != registered.shard)
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+}
+
+impl ShardedCoordinator {
+    /// Shard over the given coordinators, each named so every relay picks the same one.
+    /// The names are usually the registry URLs.
+    pub fn new(shards: Vec<(String, Arc<dyn Coordinator>)>) -> anyhow::Result<Self> {
+        anyhow::ensure!(!shards.is_empty(), "no coordinator shards");
+
+        let mut names: Vec<&str> = shards.iter().map(|(name, _)| name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        anyhow::ensure!(
+            names.len() == shards.len(),
+            "duplicate coordinator shard names"
+        );
+
+        let shards: Vec<Shard> = shards
+            .into_iter()
+            .map(|(name, coordinator)| Shard { name, coordinator })
+            .collect();
+
+        Ok(Self {
+            state: Arc::new(Mutex::new(ShardedState {
+                previous: shards.clone(),
+                shards,
+                registrations: HashMap::new(),
+                next_id: 0,
+            })),
+            depth: 1,
+            handoff: Duration::from_secs(30),
+        })
+    }
+
+    /// Hash this many leading fields of each namespace, 1 by default.
+    ///
+    /// Lookups for a namespace longer than the one announced only find it if they share the
+    /// hashed fields, so this must not exceed the length of the shortest announced namespace.
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth.max(1);
+        self
+    }
+
+    /// How long a moved registration stays on its old shard, 30s by default, so relays that
+    /// haven't picked up the new layout yet can still find it.
+    pub fn with_handoff(mut self, handoff: Duration) -> Self {
+        self.handoff = handoff;
+        self
+    }
+
+    /// The names of the shards, in the order they were added.
+    pub fn shards(&self) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .shards
+            .iter()
+            .map(|shard| shard.name.clone())
+            .collect()
+    }
+
+    /// Add a shard, moving the registrations it now owns, and return how many moved.
+    pub async fn add_shard(
+        &self,
+        name: impl Into<String>,
+        coordinator: Arc<dyn Coordinator>,
+    ) -> CoordinatorResult<usize> {
+        let name = name.into();
+
+        let misplaced = {
+            let mut state = self.state.lock().unwrap();
+            if state.shard(&name).is_some() {
+                return Err(anyhow::anyhow!("duplicate coordinator shard: {}", name).into());
+            }
+
+            state.previous = state.shards.clone();
+            state.shards.push(Shard { name, coordinator });
+            state.misplaced(self.depth)
+        };
+
+        Ok(self.migrate(misplaced).await)
+    }
+
+    /// Remove a shard, moving its registrations to the others, and return how many moved.
+    pub async fn remove_shard(&self, name: &str) -> CoordinatorResult<usize> {
+        let misplaced = {
+            let mut state = self.state.lock().unwrap();
+            if state.shard(name).is_none() {
+                return Err(anyhow::anyhow!("unknown coordinator shard: {}", name).into());
+            }
+            if state.shards.len() == 1 {
+                return Err(anyhow::anyhow!("can't remove the last coordinator shard").into());
+            }
+
+            state.previous = state.shards.clone();
+            state.shards.retain(|shard| shard.name != name);
+            state.misplaced(self.depth)
+        };
+
+        Ok(self.migrate(misplaced).await)
+    }
+
+    // Keep a registration made on `owner`, moving it if the shards changed while registering.
+    async fn hold(
+        &self,
+        namespace: &TrackNamespace,
+        owner: &Shard,
+        registration: NamespaceRegistration,
+    ) -> NamespaceRegistration {
+        let metadata = registration.metadata().map(<[_]>::to_vec);
+
+        let (id, misplaced) = {
+            let mut state = self.state.lock().unwrap();
+            state.next_id += 1;
+            let id = state.next_id;
+            state.registrations.insert(
+                id,
+                Registered {
+                    namespace: namespace.clone(),
+                    shard: owner.name.clone(),
+                    registration,
+                },
+            );
+
+            let misplaced = state
+                .owner(namespace, self.depth)
+                .is_some_and(|current| current.name != owner.name);
+            (id, misplaced)
+        };
+
+        let handle = Unregister {
+            id,
+            state: Arc::downgrade(&self.state),
+        };
+
+        if misplaced {
+            self.migrate(vec![id]).await;
+        }
+
+        match metadata {
+            Some(metadata) => NamespaceRegistration::new(handle).with_metadata(metadata),
+            None => NamespaceRegistration::new(handle),
+        }
+    }
+
+    // Register each namespace on the shard that now owns it, then release the old registration.
+    async fn migrate(&self, ids: Vec<u64>) -> usize {
+        let mut moved = 0;
+
+        for id in ids {
+            let (namespace, owner) = {
+                let state = self.state.lock().unwrap();
+                let registered = match state.registrations.get(&id) {
+                    Some(registered) => registered,
+                    None => continue,
+                };
+
+                match state.owner(&registered.namespace, self.depth) {
+                    Some(owner) if owner.name != registered.shard => {
+                        (registered.namespace.clone(), owner.clone())
+                    }
+                    _ => continue,
+                }
+            };
+
+            let registration = match owner.coordinator.register_namespace(&namespace).await {
+                Ok(registration) => registration,
+                Err(err) => {
+                    log::warn!(
+                        "failed to move {} to coordinator shard {}: {}",
+                        namespace,
+                        owner.name,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            let previous = {
+                let mut state = self.state.lock().unwrap();
+                match state.registrations.get_mut(&id) {
+                    Some(registered) => {
+                        registered.shard = owner.name.clone();
+                        Some(std::mem::replace(
+                            &mut registered.registration,
+                            registration,
+                        ))
+                    }
+                    // Unregistered in the meantime, dropping the new registration too.
+                    None => None,
+                }
+            };
+
+            if let Some(previous) = previous {
+                log::info!("moved {} to coordinator shard {}", namespace, owner.name);
+                moved += 1;
+
+                let handoff = self.handoff;
+                tokio::spawn(async move {
+                    tokio::time::sleep(handoff).await;
+                    drop(previous);
+                });
+            }
+        }
+
+        moved
+    }
+}
+
+/// Releases a registration from whichever shard it ended up on.
+struct Unregister {
+    id: u64,
+    state: Weak<Mutex<ShardedState>>,
+}
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        let registered = match self.state.upgrade() {
+            Some(state) => state.lock().unwrap().registrations.remove(&self.id),
+            None => return,
+        };
+
+        // Dropped outside the lock, since it may call back into the shard.
+        drop(registered);
+    }
+}
+
+#[async_trait]
+impl Coordinator for ShardedCoordinator {
+    async fn register_namespace(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<NamespaceRegistration> {
+        let owner = self
+            .state
+            .lock()
+            .unwrap()
+            .owner(namespace, self.depth)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("no coordinator shards"))?;
+
+        let registration = owner.coordinator.register_namespace(namespace).await?;
+        Ok(self.hold(namespace, &owner, registration).await)
+    }
+
+    // Each shard registers its part of the batch, concurrently with the others.
+    async fn register_namespaces(
+        &self,
+        namespaces: &[TrackNamespace],
+    ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+        let mut results: Vec<Option<CoordinatorResult<NamespaceRegistration>>> =
+            namespaces.iter().map(|_| None).collect();
+
+        // The indexes of the namespaces owned by each shard.
+        let mut batches: Vec<(Shard, Vec<usize>)> = Vec::new();
+        {
+            let state = self.state.lock().unwrap();
+            for (index, namespace) in namespaces.iter().enumerate() {
+                let Some(owner) = state.owner(namespace, self.depth) else {
+                    results[index] = Some(Err(anyhow::anyhow!("no coordinator shards").into()));
+                    continue;
+                };
+
+                match batches
+                    .iter_mut()
+                    .find(|(shard, _)| shard.name == owner.name)
+                {
+                    Some((_, indexes)) => indexes.push(index),
+                    None => batches.push((owner.clone(), vec![index])),
+                }
+            }
+        }
+
+        let registered = futures::future::join_all(batches.iter().map(|(shard, indexes)| {
+            let namespaces: Vec<_> = indexes.iter().map(|&i| namespaces[i].clone()).collect();
+            async move { shard.coordinator.register_namespaces(&namespaces).await }
+        }))
+        .await;
+
+        for ((shard, indexes), registrations) in batches.iter().zip(registered) {
+            for (&index, registration) in indexes.iter().zip(registrations) {
+                results[index] = Some(match registration {
+                    Ok(registration) => {
+                        Ok(self.hold(&namespaces[index], shard, registration).await)
+                    }
+                    Err(err) => Err(err),
+                });
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|res| res.expect("every namespace has a result"))
+            .collect()
+    }
+
+    async fn unregister_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        let shard = self.state.lock().unwrap().registered(namespace, self.depth);
+        match shard {
+            Some(shard) => shard.coordinator.unregister_namespace(namespace).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn refresh_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        let shard = self.state.lock().unwrap().registered(namespace, self.depth);
+        match shard {
+            Some(shard) => shard.coordinator.refresh_namespace(namespace).await,
+            None => Ok(()),
+        }
+    }
+
+    async fn set_namespace_stale(
+        &self,
+        namespace: &TrackNamespace,
+        stale: bool,
+    ) -> CoordinatorResult<()> {
+        let shard = self.state.lock().unwrap().registered(namespace, self.depth);
+        match shard {
+            Some(shard) => {
+                shard
+                    .coordinator
+                    .set_namespace_stale(namespace, stale)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn lookup(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+        let (owner, previous) = {
+            let state = self.state.lock().unwrap();
+            let owner = state
+                .owner(namespace, self.depth)
+                .cloned()
+                .ok_or(CoordinatorError::NamespaceNotFound)?;
+
+            let names: Vec<&str> = state
+                .previous
+                .iter()
+                .map(|shard| shard.name.as_str())
+                .collect();
+            let previous = shard_for(namespace, self.depth, &names)
+                .map(|index| state.previous[index].clone())
+                .filter(|previous| previous.name != owner.name);
+
+            (owner, previous)
+        };
+
+        match owner.coordinator.lookup(namespace).await {
+            Err(CoordinatorError::NamespaceNotFound) => match previous {
+                // The origin may not have moved it yet; a previous shard that's gone is a miss.
+                Some(previous) => match previous.coordinator.lookup(namespace).await {
+                    Ok(found) => Ok(found),
+                    Err(_) => Err(CoordinatorError::NamespaceNotFound),
+                },
+                None => Err(CoordinatorError::NamespaceNotFound),
+            },
+            res => res,
+        }
+    }
+
+    async fn health(&self) -> CoordinatorResult<()> {
+        let shards = self.state.lock().unwrap().shards.clone();
+
+        for shard in shards {
+            if let Err(err) = shard.coordinator.health().await {
+                return Err(anyhow::anyhow!("coordinator shard {}: {}", shard.name, err).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn shutdown(&self) -> CoordinatorResult<()> {
+        let shards = self.state.lock().unwrap().shards.clone();
+        let mut res = Ok(());
+
+        // Shut down every shard, even if one fails.
+        for shard in shards {
+            if let Err(err) = shard.coordinator.shutdown().await {
+                log::warn!(
+                    "failed to shut down coordinator shard {}: {}",
+                    shard.name,
+                    err
+                );
+                res = Err(err);
+            }
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    #[test]
+    fn hashing() {
+        let shards = ["a", "b", "c", "d"];
+        let namespaces: Vec<TrackNamespace> = (0..1000)
+            .map(|i| TrackNamespace::from_utf8_path(&format!("tenant{}/live", i)))
+            .collect();
+
+        let picked: Vec<usize> = namespaces
+            .iter()
+            .map(|namespace| shard_for(namespace, 1, &shards).unwrap())
+            .collect();
+
+        // Spread roughly evenly, and independent of the order of the shards.
+        for shard in 0..shards.len() {
+            let count = picked.iter().filter(|&&picked| picked == shard).count();
+            assert!((150..350).contains(&count), "shard {}: {}", shard, count);
+        }
+
+        let reversed = ["d", "c", "b", "a"];
+        for (namespace, picked) in namespaces.iter().zip(&picked) {
+            let index = shard_for(namespace, 1, &reversed).unwrap();
+            assert_eq!(reversed[index], shards[*picked]);
+        }
+
+        // Only the hashed fields count.
+        let namespace = TrackNamespace::from_utf8_path("tenant1/live/camera");
+        assert_eq!(
+            shard_for(&namespace, 1, &shards),
+            shard_for(&TrackNamespace::from_utf8_path("tenant1"), 1, &shards)
+        );
+
+        // Adding a shard only moves namespaces to it.
+        let more = ["a", "b", "c", "d", "e"];
+        for (namespace, picked) in namespaces.iter().zip(&picked) {
+            let index = shard_for(namespace, 1, &more).unwrap();
+            assert!(index == *picked || more[index] == "e");
+        }
+
+        assert_eq!(shard_for::<&str>(&namespace, 1, &[]), None);
+    }
+
+    /// Serves the namespaces registered with it, recording the size of each batch.
+    #[derive(Default)]
+    struct Memory {
+        registered: Arc<Mutex<Vec<TrackNamespace>>>,
+        batches: Mutex<Vec<usize>>,
+    }
+
+    struct Release(Arc<Mutex<Vec<TrackNamespace>>>, TrackNamespace);
+
+    impl Drop for Release {
+        fn drop(&mut self) {
+            let mut registered = self.0.lock().unwrap();
+            if let Some(index) = registered.iter().position(|n| n == &self.1) {
+                registered.remove(index);
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Coordinator for Memory {
+        async fn register_namespace(
+            &self,
+            namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            self.registered.lock().unwrap().push(namespace.clone());
+            Ok(NamespaceRegistration::new(Release(
+                self.registered.clone(),
+                namespace.clone(),
+            )))
+        }
+
+        async fn register_namespaces(
+            &self,
+            namespaces: &[TrackNamespace],
+        ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+            self.batches.lock().unwrap().push(namespaces.len());
+
+            let mut results = Vec::new();
+            for namespace in namespaces {
+                results.push(self.register_namespace(namespace).await);
+            }
+            results
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            match self.registered.lock().unwrap().contains(namespace) {
+                true => Ok((
+                    NamespaceOrigin::new(
+                        namespace.clone(),
+                        Url::parse("https://origin.example.com").unwrap(),
+                        None,
+                    ),
+                    None,
+                )),
+                false => Err(CoordinatorError::NamespaceNotFound),
+            }
+        }
+    }
+
+    fn count(memory: &Memory) -> usize {
+        memory.registered.lock().unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn rebalancing() {
+        let a = Arc::new(Memory::default());
+        let b = Arc::new(Memory::default());
+        let c = Arc::new(Memory::default());
+
+        let coordinator = ShardedCoordinator::new(vec![
+            ("a".to_string(), a.clone() as Arc<dyn Coordinator>),
+            ("b".to_string(), b.clone() as Arc<dyn Coordinator>),
+        ])
+        .unwrap()
+        .with_handoff(Duration::from_millis(50));
+
+        let namespaces: Vec<TrackNamespace> = (0..100)
+            .map(|i| TrackNamespace::from_utf8_path(&format!("tenant{}", i)))
+            .collect();
+
+        let mut registrations = Vec::new();
+        for namespace in &namespaces {
+            registrations.push(coordinator.register_namespace(namespace).await.unwrap());
+        }
+        assert_eq!(count(&a) + count(&b), 100);
+        assert!(count(&a) > 0 && count(&b) > 0);
+
+        // The new shard takes over some namespaces, kept on the old ones until the handoff.
+        let moved = coordinator
+            .add_shard("c", c.clone() as Arc<dyn Coordinator>)
+            .await
+            .unwrap();
+        assert!(moved > 0 && moved < 100);
+        assert_eq!(count(&c), moved);
+        assert_eq!(count(&a) + count(&b), 100);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(count(&a) + count(&b), 100 - moved);
+
+        for namespace in &namespaces {
+            assert!(coordinator.lookup(namespace).await.is_ok());
+        }
+
+        // Removing a shard moves its namespaces back.
+        assert_eq!(coordinator.remove_shard("c").await.unwrap(), moved);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(count(&c), 0);
+        assert_eq!(count(&a) + count(&b), 100);
+        assert!(coordinator.remove_shard("c").await.is_err());
+        assert_eq!(coordinator.shards(), vec!["a", "b"]);
+
+        // Dropping a registration releases it from its shard.
+        registrations.clear();
+        assert_eq!(count(&a) + count(&b), 0);
+        assert!(matches!(
+            coordinator.lookup(&namespaces[0]).await,
+            Err(CoordinatorError::NamespaceNotFound)
+        ));
+    }
+
+    #[tokio::test]
+    async fn registers_batches_per_shard() {
+        let a = Arc::new(Memory::default());
+        let b = Arc::new(Memory::default());
+        let coordinator = ShardedCoordinator::new(vec![
+            ("a".to_string(), a.clone() as Arc<dyn Coordinator>),
+            ("b".to_string(), b.clone() as Arc<dyn Coordinator>),
+        ])
+        .unwrap();
+
+        let namespaces: Vec<TrackNamespace> = (0..20)
+            .map(|i| TrackNamespace::from_utf8_path(&format!("tenant{}", i)))
+            .collect();
+        let mut registrations: Vec<_> = coordinator
+            .register_namespaces(&namespaces)
+            .await
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+
+        // Each shard got its share in one batch.
+        assert_eq!(*a.batches.lock().unwrap(), vec![count(&a)]);
+        assert_eq!(*b.batches.lock().unwrap(), vec![count(&b)]);
+        assert_eq!(count(&a) + count(&b), 20);
+
+        // The results are in the order of the namespaces.
+        drop(registrations.remove(0));
+        assert!(!a.registered.lock().unwrap().contains(&namespaces[0]));
+        assert!(!b.registered.lock().unwrap().contains(&namespaces[0]));
+        assert_eq!(count(&a) + count(&b), 19);
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+
+use moq_transport::coding::TrackNamespace;
+use serde::Serialize;
+
+use crate::{NamespaceRegistration, Registration};
+
+/// Relay-wide teardown counters, shared by every session.
+#[derive(Clone, Default)]
+pub struct TeardownMetrics {
+    counters: Arc<TeardownCounters>,
+}
+
+#[derive(Default)]
+struct TeardownCounters {
+    sessions: AtomicU64,
+    namespaces: AtomicU64,
+    unannounced: AtomicU64,
+}
+
+/// A snapshot of [TeardownMetrics].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TeardownStats {
+    /// Publisher sessions torn down after they ended.
+    pub sessions: u64,
+
+    /// Namespaces released because their publisher session ended.
+    pub namespaces: u64,
+
+    /// Namespaces released because the publisher ended the announce itself.
+    pub unannounced: u64,
+}
+
+impl TeardownMetrics {
+    pub fn stats(&self) -> TeardownStats {
+        TeardownStats {
+            sessions: self.counters.sessions.load(Ordering::Relaxed),
+            namespaces: self.counters.namespaces.load(Ordering::Relaxed),
+            unannounced: self.counters.unannounced.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What an announce holds while it is served.
+struct Held {
+    namespace: TrackNamespace,
+    registration: Registration,
+    coordinator: NamespaceRegistration,
+}
+
+/// Releases everything a publisher session announced, in a fixed order.
+///
+/// Each namespace is first removed from [crate::Locals], so no new subscription can find it,
+/// then unregistered from the coordinator, so other relays stop routing to us. Only then are the
+/// announce tasks dropped, which ends forwarded announces and notifies downstream subscribers.
+#[derive(Clone)]
+pub struct SessionTeardown {
+    held: Arc<Mutex<Vec<Held>>>,
+    metrics: TeardownMetrics,
+}
+
+impl SessionTeardown {
+    pub fn new(metrics: TeardownMetrics) -> Self {
+        Self {
+            held: Default::default(),
+            metrics,
+        }
+    }
+
+    /// Hold the registrations of an accepted announce until it ends or the session is torn down.
+    pub(crate) fn hold(
+        &self,
+        namespace: TrackNamespace,
+        registration: Registration,
+        coordinator: NamespaceRegistration,
+    ) {
+        self.held.lock().unwrap().push(Held {
+            namespace,
+            registration,
+            coordinator,
+        });
+    }
+
+    /// Release a single namespace whose announce ended while the session is still running.
+    pub(crate) fn release(&self, namespace: &TrackNamespace) {
+        let held = {
+            let mut held = self.held.lock().unwrap();
+            match held.iter().position(|held| &held.namespace == namespace) {
+                Some(index) => held.remove(index),
+                None => return,
+            }
+        };
+
+        Self::release_one(held);
+        self.metrics
+            .counters
+            .unannounced
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Tear down the ended session: release every held namespace, oldest first, then drop `tasks`.
+    pub fn run<T>(&self, tasks: T) {
+        let held = std::mem::take(&mut *self.held.lock().unwrap());
+        let count = held.len() as u64;
+
+        if count > 0 {
+            log::info!("tearing down session: releasing {} namespaces", count);
+        }
+
+        for held in held {
+            Self::release_one(held);
+        }
+
+        drop(tasks);
+        log::debug!("tearing down session: announce tasks stopped");
+
+        let counters = &self.metrics.counters;
+        counters.sessions.fetch_add(1, Ordering::Relaxed);
+        counters.namespaces.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn release_one(held: Held) {
+        let Held {
+            namespace,
+            registration,
+            coordinator,
+        } = held;
+
+        drop(registration);
+        log::debug!("removed local registration: {}", namespace);
+
+        drop(coordinator);
+        log::debug!("unregistered from coordinator: {}", namespace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Locals;
+    use moq_transport::serve::Tracks;
+
+    /// Records when a coordinator registration is dropped.
+    struct Unregister(Locals, TrackNamespace, Arc<Mutex<Vec<bool>>>);
+
+    impl Drop for Unregister {
+        fn drop(&mut self) {
+            // The local registration must already be gone.
+            let local = self.0.retrieve(&self.1).is_some();
+            self.2.lock().unwrap().push(local);
+        }
+    }
+
+    #[tokio::test]
+    async fn ordered_teardown() {
+        let mut locals = Locals::new();
+        let metrics = TeardownMetrics::default();
+        let teardown = SessionTeardown::new(metrics.clone());
+        let unregistered = Arc::new(Mutex::new(Vec::new()));
+
+        for path in ["a", "b", "c"] {
+            let namespace = TrackNamespace::from_utf8_path(path);
+            let (_, _, reader) = Tracks::new(namespace.clone()).produce();
+            let registration = locals.register(reader).await.unwrap();
+            let coordinator = NamespaceRegistration::new(Unregister(
+                locals.clone(),
+                namespace.clone(),
+                unregistered.clone(),
+            ));
+            teardown.hold(namespace, registration, coordinator);
+        }
+
+        // An announce that ends on its own is released immediately.
+        teardown.release(&TrackNamespace::from_utf8_path("b"));
+        assert_eq!(locals.namespaces().len(), 2);
+
+        teardown.run(());
+
+        assert!(locals.namespaces().is_empty());
+        assert_eq!(*unregistered.lock().unwrap(), vec![false; 3]);
+        assert_eq!(
+            metrics.stats(),
+            TeardownStats {
+                sessions: 1,
+                namespaces: 2,
+                unannounced: 1,
+            }
+        );
+    }
+}
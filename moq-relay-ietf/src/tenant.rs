@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+};
+
+use anyhow::Context;
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    session::NamespaceScope,
+};
+use serde::{Deserialize, Serialize};
+
+/// How a tenant's namespaces are kept apart from other tenants'.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantIsolation {
+    /// Tenants name their namespaces freely, and the relay files them under the tenant ID,
+    /// so tenants using the same names don't collide. Other relays see the filed names.
+    #[default]
+    Scoped,
+
+    /// Tenants' namespaces start with their tenant ID as the root field, and are used as is.
+    /// Requests for namespaces under another root are refused.
+    Root,
+}
+
+/// The tenant a session belongs to, see [TenantResolver].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tenant {
+    pub id: String,
+    pub isolation: TenantIsolation,
+}
+
+impl Tenant {
+    pub fn new(id: impl Into<String>, isolation: TenantIsolation) -> Self {
+        Self {
+            id: id.into(),
+            isolation,
+        }
+    }
+
+    /// The namespace root field of the tenant's namespaces, as seen by the relay.
+    pub fn root(&self) -> TrackNamespace {
+        TrackNamespace::from_utf8_path(&self.id)
+    }
+
+    /// The scope to confine the tenant's sessions to, if its namespaces are scoped.
+    pub fn scope(&self) -> Option<NamespaceScope> {
+        match self.isolation {
+            TenantIsolation::Scoped => Some(NamespaceScope::new(self.root())),
+            TenantIsolation::Root => None,
+        }
+    }
+
+    /// Whether the tenant's sessions may announce, subscribe to or fetch from `namespace`.
+    pub fn owns(&self, namespace: &TrackNamespace) -> bool {
+        namespace.has_prefix(&self.root())
+    }
+
+    /// Whether the namespaces under `prefix` include any of the tenant's.
+    pub fn overlaps(&self, prefix: &TrackNamespace) -> bool {
+        self.owns(prefix) || self.root().has_prefix(prefix)
+    }
+}
+
+/// Which namespaces an accepted session may use, as decided by a [TenantResolver].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SessionTenant {
+    /// Only the tenant's namespaces.
+    Tenant(Tenant),
+
+    /// Every tenant's namespaces, ex. for other relays of the cluster.
+    Any,
+
+    /// None; the session is closed as unauthorized.
+    Unknown,
+}
+
+/// Decides the tenant of each accepted session, so tenants can't use each other's namespaces.
+pub trait TenantResolver: Send + Sync {
+    /// The tenant of a session that connected to `server_name`, the TLS SNI if the client sent
+    /// one, presenting `tokens` in CLIENT_SETUP.
+    fn resolve(&self, server_name: Option<&str>, tokens: &[Token]) -> SessionTenant;
+}
+
+/// Tenants by the TLS server name (SNI) sessions connect to, ex. one hostname per tenant.
+///
+/// Loaded from a JSON file like `{"tenants": {"a.example.com": "a"}, "unscoped": ["relay.example.com"]}`.
+/// Sessions to any other server name, or without one, are rejected.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerNameTenants {
+    pub isolation: TenantIsolation,
+
+    /// The tenant ID for each server name.
+    pub tenants: BTreeMap<String, String>,
+
+    /// Server names whose sessions may use every tenant's namespaces, ex. the name other relays
+    /// of the cluster connect to.
+    pub unscoped: BTreeSet<String>,
+}
+
+impl ServerNameTenants {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (server_name, tenant) in &self.tenants {
+            anyhow::ensure!(
+                !tenant.is_empty() && !tenant.contains('/'),
+                "invalid tenant ID for {}: {:?}",
+                server_name,
+                tenant
+            );
+        }
+        Ok(())
+    }
+
+    /// Load the tenants from a JSON file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read tenants: {}", path.display()))?;
+        let tenants: Self = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse tenants: {}", path.display()))?;
+        tenants.validate()?;
+        Ok(tenants)
+    }
+}
+
+impl TenantResolver for ServerNameTenants {
+    fn resolve(&self, server_name: Option<&str>, _tokens: &[Token]) -> SessionTenant {
+        let Some(server_name) = server_name else {
+            return SessionTenant::Unknown;
+        };
+
+        if self.unscoped.contains(server_name) {
+            return SessionTenant::Any;
+        }
+
+        match self.tenants.get(server_name) {
+            Some(tenant) => SessionTenant::Tenant(Tenant::new(tenant, self.isolation)),
+            None => SessionTenant::Unknown,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_names() {
+        let tenants = ServerNameTenants {
+            isolation: TenantIsolation::Root,
+            tenants: BTreeMap::from([("a.example.com".to_string(), "a".to_string())]),
+            unscoped: BTreeSet::from(["relay.example.com".to_string()]),
+        };
+
+        let a = Tenant::new("a", TenantIsolation::Root);
+        assert_eq!(
+            tenants.resolve(Some("a.example.com"), &[]),
+            SessionTenant::Tenant(a.clone())
+        );
+        assert_eq!(
+            tenants.resolve(Some("relay.example.com"), &[]),
+            SessionTenant::Any
+        );
+        assert_eq!(
+            tenants.resolve(Some("b.example.com"), &[]),
+            SessionTenant::Unknown
+        );
+        assert_eq!(tenants.resolve(None, &[]), SessionTenant::Unknown);
+
+        let namespace = |path| TrackNamespace::from_utf8_path(path);
+        assert!(a.owns(&namespace("a/live")));
+        assert!(!a.owns(&namespace("b/live")));
+        assert!(a.overlaps(&TrackNamespace::new()));
+        assert!(!a.overlaps(&namespace("b")));
+        assert_eq!(a.scope(), None);
+
+        assert!(ServerNameTenants {
+            tenants: BTreeMap::from([("a.example.com".to_string(), "a/b".to_string())]),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+    }
+}
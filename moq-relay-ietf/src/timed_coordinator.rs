@@ -0,0 +1,473 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use moq_native_ietf::quic;
+use moq_transport::coding::TrackNamespace;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::{
+    Coordinator, CoordinatorError, CoordinatorResult, NamespaceOrigin, NamespaceRegistration,
+};
+
+/// How long to wait for each coordinator call.
+#[derive(Clone, Copy, Debug)]
+pub struct CoordinatorTimeouts {
+    /// Registering, unregistering, refreshing or marking a namespace stale, and shutting down.
+    ///
+    /// A registration that takes longer is accepted anyway and finishes in the background,
+    /// so the announce is served locally while other relays can't route to it yet.
+    pub register: Duration,
+
+    /// Looking up a namespace to route a subscription.
+    /// A lookup that takes longer fails the subscription with a retryable TIMEOUT error.
+    pub lookup: Duration,
+}
+
+impl Default for CoordinatorTimeouts {
+    fn default() -> Self {
+        Self {
+            register: Duration::from_secs(5),
+            lookup: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Relay-wide coordinator latency counters.
+#[derive(Clone, Default)]
+pub struct CoordinatorMetrics {
+    counters: Arc<CoordinatorCounters>,
+}
+
+#[derive(Default)]
+struct CoordinatorCounters {
+    register: CallCounters,
+    unregister: CallCounters,
+    refresh: CallCounters,
+    stale: CallCounters,
+    lookup: CallCounters,
+    shutdown: CallCounters,
+}
+
+#[derive(Default)]
+struct CallCounters {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    total_latency_us: AtomicU64,
+    max_latency_us: AtomicU64,
+}
+
+impl CallCounters {
+    fn record<T>(&self, latency: Duration, res: &CoordinatorResult<T>) {
+        let latency = latency.as_micros() as u64;
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.total_latency_us.fetch_add(latency, Ordering::Relaxed);
+        self.max_latency_us.fetch_max(latency, Ordering::Relaxed);
+
+        match res {
+            Err(CoordinatorError::Timeout) => self.timeouts.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed),
+            Ok(_) => 0,
+        };
+    }
+
+    fn stats(&self) -> CoordinatorCallStats {
+        CoordinatorCallStats {
+            calls: self.calls.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            total_latency_us: self.total_latency_us.load(Ordering::Relaxed),
+            max_latency_us: self.max_latency_us.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of the calls to one coordinator method.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CoordinatorCallStats {
+    /// Calls that completed or timed out.
+    pub calls: u64,
+    pub errors: u64,
+    /// Calls that took longer than their timeout. Registrations are counted here when they are
+    /// deferred, and again in `calls` once they finish in the background.
+    pub timeouts: u64,
+    /// Time spent in the coordinator, in microseconds. Timed out calls count up to their timeout.
+    pub total_latency_us: u64,
+    pub max_latency_us: u64,
+}
+
+/// A snapshot of [CoordinatorMetrics].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CoordinatorStats {
+    pub register: CoordinatorCallStats,
+    pub unregister: CoordinatorCallStats,
+    pub refresh: CoordinatorCallStats,
+    pub stale: CoordinatorCallStats,
+    pub lookup: CoordinatorCallStats,
+    pub shutdown: CoordinatorCallStats,
+}
+
+impl CoordinatorMetrics {
+    pub fn stats(&self) -> CoordinatorStats {
+        let counters = &self.counters;
+
+        CoordinatorStats {
+            register: counters.register.stats(),
+            unregister: counters.unregister.stats(),
+            refresh: counters.refresh.stats(),
+            stale: counters.stale.stats(),
+            lookup: counters.lookup.stats(),
+            shutdown: counters.shutdown.stats(),
+        }
+    }
+}
+
+/// Wraps a [Coordinator] so a slow one can't stall announces or subscriptions.
+///
+/// Every call is bounded by [CoordinatorTimeouts] and recorded in [CoordinatorMetrics].
+pub struct TimedCoordinator {
+    inner: Arc<dyn Coordinator>,
+    timeouts: CoordinatorTimeouts,
+    metrics: CoordinatorMetrics,
+}
+
+impl TimedCoordinator {
+    pub fn new(
+        inner: Arc<dyn Coordinator>,
+        timeouts: CoordinatorTimeouts,
+        metrics: CoordinatorMetrics,
+    ) -> Self {
+        Self {
+            inner,
+            timeouts,
+            metrics,
+        }
+    }
+
+    async fn timed<T>(
+        counters: &CallCounters,
+        timeout: Duration,
+        call: impl Future<Output = CoordinatorResult<T>>,
+    ) -> CoordinatorResult<T> {
+        let start = Instant::now();
+        let res = tokio::time::timeout(timeout, call)
+            .await
+            .unwrap_or(Err(CoordinatorError::Timeout));
+
+        counters.record(start.elapsed(), &res);
+        res
+    }
+}
+
+/// A registration still running in the background; dropping it cancels or undoes the registration.
+struct Deferred(tokio::task::JoinHandle<CoordinatorResult<NamespaceRegistration>>);
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+#[async_trait]
+impl Coordinator for TimedCoordinator {
+    async fn register_namespace(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<NamespaceRegistration> {
+        let inner = self.inner.clone();
+        let metrics = self.metrics.clone();
+        let deferred = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+
+        // Spawned so the registration can outlive the timeout.
+        let mut task = tokio::spawn({
+            let namespace = namespace.clone();
+            let deferred = deferred.clone();
+
+            async move {
+                let res = inner.register_namespace(&namespace).await;
+                metrics.counters.register.record(start.elapsed(), &res);
+
+                if let Err(err) = &res {
+                    if deferred.load(Ordering::Relaxed) {
+                        log::warn!("background registration of {} failed: {}", namespace, err);
+                    }
+                }
+
+                res
+            }
+        });
+
+        match tokio::time::timeout(self.timeouts.register, &mut task).await {
+            Ok(res) => res?,
+            Err(_) => {
+                deferred.store(true, Ordering::Relaxed);
+                self.metrics
+                    .counters
+                    .register
+                    .timeouts
+                    .fetch_add(1, Ordering::Relaxed);
+
+                log::warn!(
+                    "coordinator registration of {} timed out, continuing in the background",
+                    namespace
+                );
+                Ok(NamespaceRegistration::new(Deferred(task)))
+            }
+        }
+    }
+
+    // Each namespace is recorded as a registration of its own.
+    async fn register_namespaces(
+        &self,
+        namespaces: &[TrackNamespace],
+    ) -> Vec<CoordinatorResult<NamespaceRegistration>> {
+        let inner = self.inner.clone();
+        let metrics = self.metrics.clone();
+        let deferred = Arc::new(AtomicBool::new(false));
+        let start = Instant::now();
+
+        // Spawned so the batch can outlive the timeout, like a single registration.
+        let mut task = tokio::spawn({
+            let namespaces = namespaces.to_vec();
+            let deferred = deferred.clone();
+
+            async move {
+                let results = inner.register_namespaces(&namespaces).await;
+                for (namespace, res) in namespaces.iter().zip(&results) {
+                    metrics.counters.register.record(start.elapsed(), res);
+
+                    if let Err(err) = res {
+                        if deferred.load(Ordering::Relaxed) {
+                            log::warn!("background registration of {} failed: {}", namespace, err);
+                        }
+                    }
+                }
+
+                results
+            }
+        });
+
+        match tokio::time::timeout(self.timeouts.register, &mut task).await {
+            Ok(Ok(results)) => results,
+            Ok(Err(err)) => {
+                let err = CoordinatorError::from(err).to_string();
+                namespaces
+                    .iter()
+                    .map(|_| Err(anyhow::anyhow!("{}", err).into()))
+                    .collect()
+            }
+            Err(_) => {
+                deferred.store(true, Ordering::Relaxed);
+                self.metrics
+                    .counters
+                    .register
+                    .timeouts
+                    .fetch_add(namespaces.len() as u64, Ordering::Relaxed);
+
+                log::warn!(
+                    "coordinator registration of {} namespaces timed out, continuing in the background",
+                    namespaces.len()
+                );
+
+                // Each namespace holds its own part of the batch, so dropping one only
+                // unregisters that namespace once the batch is done.
+                let (replies, registrations): (Vec<_>, Vec<_>) = namespaces
+                    .iter()
+                    .map(|_| {
+                        let (reply, registered) = oneshot::channel();
+                        let registration = tokio::spawn(async move {
+                            registered.await.unwrap_or(Err(CoordinatorError::Timeout))
+                        });
+                        (
+                            reply,
+                            Ok(NamespaceRegistration::new(Deferred(registration))),
+                        )
+                    })
+                    .unzip();
+
+                tokio::spawn(async move {
+                    if let Ok(results) = task.await {
+                        for (reply, res) in replies.into_iter().zip(results) {
+                            // Dropped, and so unregistered, if the announce is already gone.
+                            reply.send(res).ok();
+                        }
+                    }
+                });
+
+                registrations
+            }
+        }
+    }
+
+    async fn unregister_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        Self::timed(
+            &self.metrics.counters.unregister,
+            self.timeouts.register,
+            self.inner.unregister_namespace(namespace),
+        )
+        .await
+    }
+
+    async fn refresh_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        Self::timed(
+            &self.metrics.counters.refresh,
+            self.timeouts.register,
+            self.inner.refresh_namespace(namespace),
+        )
+        .await
+    }
+
+    async fn set_namespace_stale(
+        &self,
+        namespace: &TrackNamespace,
+        stale: bool,
+    ) -> CoordinatorResult<()> {
+        Self::timed(
+            &self.metrics.counters.stale,
+            self.timeouts.register,
+            self.inner.set_namespace_stale(namespace, stale),
+        )
+        .await
+    }
+
+    async fn lookup(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+        Self::timed(
+            &self.metrics.counters.lookup,
+            self.timeouts.lookup,
+            self.inner.lookup(namespace),
+        )
+        .await
+    }
+
+    // Not recorded, so frequent readiness probes don't skew the lookup latencies.
+    async fn health(&self) -> CoordinatorResult<()> {
+        tokio::time::timeout(self.timeouts.lookup, self.inner.health())
+            .await
+            .unwrap_or(Err(CoordinatorError::Timeout))
+    }
+
+    async fn shutdown(&self) -> CoordinatorResult<()> {
+        Self::timed(
+            &self.metrics.counters.shutdown,
+            self.timeouts.register,
+            self.inner.shutdown(),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Takes `delay` for every call, and records which namespaces are registered.
+    struct Slow {
+        delay: Duration,
+        registered: Arc<Mutex<Vec<TrackNamespace>>>,
+    }
+
+    struct Unregister(Arc<Mutex<Vec<TrackNamespace>>>, TrackNamespace);
+
+    impl Drop for Unregister {
+        fn drop(&mut self) {
+            self.0
+                .lock()
+                .unwrap()
+                .retain(|namespace| namespace != &self.1);
+        }
+    }
+
+    #[async_trait]
+    impl Coordinator for Slow {
+        async fn register_namespace(
+            &self,
+            namespace: &TrackNamespace,
+        ) -> CoordinatorResult<NamespaceRegistration> {
+            tokio::time::sleep(self.delay).await;
+            self.registered.lock().unwrap().push(namespace.clone());
+            Ok(NamespaceRegistration::new(Unregister(
+                self.registered.clone(),
+                namespace.clone(),
+            )))
+        }
+
+        async fn unregister_namespace(&self, _namespace: &TrackNamespace) -> CoordinatorResult<()> {
+            Ok(())
+        }
+
+        async fn lookup(
+            &self,
+            _namespace: &TrackNamespace,
+        ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+            tokio::time::sleep(self.delay).await;
+            Err(CoordinatorError::NamespaceNotFound)
+        }
+    }
+
+    #[tokio::test]
+    async fn timeouts() {
+        let registered = Arc::new(Mutex::new(Vec::new()));
+        let metrics = CoordinatorMetrics::default();
+        let coordinator = TimedCoordinator::new(
+            Arc::new(Slow {
+                delay: Duration::from_millis(100),
+                registered: registered.clone(),
+            }),
+            CoordinatorTimeouts {
+                register: Duration::from_millis(10),
+                lookup: Duration::from_millis(10),
+            },
+            metrics.clone(),
+        );
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        // A slow lookup fails fast.
+        assert!(matches!(
+            coordinator.lookup(&namespace).await,
+            Err(CoordinatorError::Timeout)
+        ));
+
+        // A slow registration is accepted, and finishes in the background.
+        let registration = coordinator.register_namespace(&namespace).await.unwrap();
+        assert!(registered.lock().unwrap().is_empty());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(*registered.lock().unwrap(), vec![namespace.clone()]);
+
+        drop(registration);
+        assert!(registered.lock().unwrap().is_empty());
+
+        // Dropping it before then cancels the registration.
+        let registration = coordinator.register_namespace(&namespace).await.unwrap();
+        drop(registration);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(registered.lock().unwrap().is_empty());
+
+        // Marking a namespace stale is counted apart from refreshes.
+        coordinator
+            .set_namespace_stale(&namespace, true)
+            .await
+            .unwrap();
+
+        let stats = metrics.stats();
+        assert_eq!(stats.lookup.calls, 1);
+        assert_eq!(stats.lookup.timeouts, 1);
+        assert_eq!(stats.register.calls, 1);
+        assert_eq!(stats.register.timeouts, 2);
+        assert!(stats.register.max_latency_us >= 100_000);
+        assert_eq!(stats.stale.calls, 1);
+        assert_eq!(stats.refresh.calls, 0);
+    }
+}
@@ -0,0 +1,349 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use moq_transport::{coding::TrackNamespace, session::ObjectValidator};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::priority::matches;
+
+/// How the objects of the tracks under a namespace prefix are checked, for data tracks such as
+/// JSON timed metadata, before they fan out to subscribers.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ValidationPolicy {
+    /// Names of the tracks checked, where `*` matches anything, ex. `*.json`.
+    pub tracks: Vec<String>,
+
+    /// The JSON schema payloads must match, ex. `{"type": "object", "required": ["t"]}`.
+    /// Only the `type`, `required`, `properties` and `items` keywords are understood, others are
+    /// ignored. Without a schema, payloads only have to be JSON.
+    pub schema: Option<Value>,
+
+    /// The fraction of objects checked, from 0 to 1, bounding the cost on busy tracks.
+    pub sample_rate: f64,
+
+    /// Drop invalid objects, or only count and log them if false.
+    pub reject: bool,
+}
+
+impl Default for ValidationPolicy {
+    fn default() -> Self {
+        Self {
+            tracks: vec!["*".to_string()],
+            schema: None,
+            sample_rate: 1.0,
+            reject: true,
+        }
+    }
+}
+
+/// The objects checked under a prefix since the relay started, as reported by the admin API.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationStats {
+    pub checked: u64,
+    pub failed: u64,
+    /// Invalid objects dropped, unless the policy only flags them.
+    pub rejected: u64,
+}
+
+/// Checks the payload of an object of the named track, returning why it's invalid.
+pub type PayloadCheck = Arc<dyn Fn(&str, &[u8]) -> Result<(), String> + Send + Sync>;
+
+struct Validator {
+    policy: ValidationPolicy,
+    // Replaces the JSON check, if set.
+    check: Option<PayloadCheck>,
+    // Objects of matching tracks, sampled or not.
+    seen: u64,
+    stats: ValidationStats,
+}
+
+impl Validator {
+    fn new(policy: ValidationPolicy) -> Self {
+        Self {
+            policy,
+            check: None,
+            seen: 0,
+            stats: Default::default(),
+        }
+    }
+
+    // Whether the next object is in the sample, spreading the sampled objects evenly.
+    fn sample(&mut self) -> bool {
+        let rate = self.policy.sample_rate.clamp(0.0, 1.0);
+        let before = (self.seen as f64 * rate).floor();
+        self.seen += 1;
+        (self.seen as f64 * rate).floor() > before
+    }
+}
+
+#[derive(Default)]
+struct ValidatorsState {
+    validators: BTreeMap<String, Validator>,
+}
+
+impl ValidatorsState {
+    // The validator of the longest prefix matching `namespace`, and that prefix.
+    fn validator(&mut self, namespace: &TrackNamespace) -> Option<(&String, &mut Validator)> {
+        self.validators
+            .iter_mut()
+            .filter(|(prefix, _)| namespace.has_prefix(&TrackNamespace::from_utf8_path(prefix)))
+            .max_by_key(|(prefix, _)| TrackNamespace::from_utf8_path(prefix).fields.len())
+    }
+}
+
+/// Per-namespace checks of the objects publishers send, run before they reach subscribers.
+///
+/// Policies are loaded from a JSON file mapping namespace prefixes to their [ValidationPolicy],
+/// and only the longest matching prefix applies. Objects of the tracks it matches are received
+/// whole before being delivered, so only data tracks should be checked, not media.
+#[derive(Clone, Default)]
+pub struct Validators {
+    state: Arc<Mutex<ValidatorsState>>,
+}
+
+impl Validators {
+    pub fn new(policies: BTreeMap<String, ValidationPolicy>) -> Self {
+        let validators = policies
+            .into_iter()
+            .map(|(prefix, policy)| (prefix, Validator::new(policy)))
+            .collect();
+
+        Self {
+            state: Arc::new(Mutex::new(ValidatorsState { validators })),
+        }
+    }
+
+    /// Load policies from a JSON file, ex. `{"live": {"tracks": ["*.json"], "sample_rate": 0.1}}`.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read validators: {}", path.display()))?;
+        let policies: BTreeMap<String, ValidationPolicy> = serde_json::from_str(&json)
+            .with_context(|| format!("failed to parse validators: {}", path.display()))?;
+
+        for (prefix, policy) in &policies {
+            if !(0.0..=1.0).contains(&policy.sample_rate) {
+                anyhow::bail!("validators.{}.sample_rate: must be between 0 and 1", prefix);
+            }
+        }
+
+        Ok(Self::new(policies))
+    }
+
+    /// Check the objects under `prefix` with `check` instead of as JSON, adding a default policy
+    /// for the prefix if it has none.
+    pub fn set_check(
+        &self,
+        prefix: &str,
+        check: impl Fn(&str, &[u8]) -> Result<(), String> + Send + Sync + 'static,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let validator = state
+            .validators
+            .entry(prefix.to_string())
+            .or_insert_with(|| Validator::new(Default::default()));
+        validator.check = Some(Arc::new(check));
+    }
+
+    /// The objects checked under each prefix, keyed by prefix.
+    pub fn stats(&self) -> BTreeMap<String, ValidationStats> {
+        let state = self.state.lock().unwrap();
+        state
+            .validators
+            .iter()
+            .map(|(prefix, validator)| (prefix.clone(), validator.stats.clone()))
+            .collect()
+    }
+}
+
+impl ObjectValidator for Validators {
+    fn validates(&self, namespace: &TrackNamespace, track: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        state.validator(namespace).is_some_and(|(_, validator)| {
+            validator
+                .policy
+                .tracks
+                .iter()
+                .any(|pattern| matches(pattern, track))
+        })
+    }
+
+    fn validate(&self, namespace: &TrackNamespace, track: &str, payload: &Bytes) -> bool {
+        let (check, schema) = {
+            let mut state = self.state.lock().unwrap();
+            let Some((_, validator)) = state.validator(namespace) else {
+                return true;
+            };
+            if !validator.sample() {
+                return true;
+            }
+            (validator.check.clone(), validator.policy.schema.clone())
+        };
+
+        // Checked without the lock, as it may be slow.
+        let res = match check {
+            Some(check) => check(track, payload),
+            None => check_json(schema.as_ref(), payload),
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let Some((prefix, validator)) = state.validator(namespace) else {
+            return true;
+        };
+        validator.stats.checked += 1;
+
+        let Err(reason) = res else {
+            return true;
+        };
+        validator.stats.failed += 1;
+        log::warn!(
+            "invalid object of {}/{} under {}: {}",
+            namespace,
+            track,
+            prefix,
+            reason
+        );
+
+        if !validator.policy.reject {
+            return true;
+        }
+        validator.stats.rejected += 1;
+        false
+    }
+}
+
+// Check `payload` is JSON, matching `schema` if any.
+fn check_json(schema: Option<&Value>, payload: &[u8]) -> Result<(), String> {
+    let value: Value = serde_json::from_slice(payload).map_err(|err| err.to_string())?;
+    match schema {
+        Some(schema) => conforms(&value, schema, "$"),
+        None => Ok(()),
+    }
+}
+
+// Check `value`, at `path` in the payload, against the keywords of [ValidationPolicy::schema].
+fn conforms(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(name)) => vec![name],
+        Some(Value::Array(names)) => names.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|name| has_type(value, name)) {
+        return Err(format!("{}: expected {}", path, types.join(" or ")));
+    }
+
+    if let Some(object) = value.as_object() {
+        let required = schema.get("required").and_then(Value::as_array);
+        for key in required.into_iter().flatten().filter_map(Value::as_str) {
+            if !object.contains_key(key) {
+                return Err(format!("{}: missing {}", path, key));
+            }
+        }
+
+        let properties = schema.get("properties").and_then(Value::as_object);
+        for (key, schema) in properties.into_iter().flatten() {
+            if let Some(value) = object.get(key) {
+                conforms(value, schema, &format!("{}.{}", path, key))?;
+            }
+        }
+    }
+
+    if let (Some(array), Some(items)) = (value.as_array(), schema.get("items")) {
+        for (index, item) in array.iter().enumerate() {
+            conforms(item, items, &format!("{}[{}]", path, index))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn has_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "number" => value.is_number(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        // Unknown types aren't checked.
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schemas() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["t"],
+            "properties": {
+                "t": {"type": "integer"},
+                "cues": {"type": "array", "items": {"type": ["string", "null"]}},
+            },
+        });
+        let check = |payload: &str| check_json(Some(&schema), payload.as_bytes());
+
+        assert_eq!(check(r#"{"t": 1, "cues": ["a", null]}"#), Ok(()));
+        assert_eq!(check(r#"{"cues": []}"#), Err("$: missing t".to_string()));
+        assert_eq!(
+            check(r#"{"t": 1.5}"#),
+            Err("$.t: expected integer".to_string())
+        );
+        assert_eq!(
+            check(r#"{"t": 1, "cues": [2]}"#),
+            Err("$.cues[0]: expected string or null".to_string())
+        );
+        assert!(check("not json").is_err());
+        assert_eq!(check_json(None, b"[1, 2]"), Ok(()));
+    }
+
+    #[test]
+    fn samples() {
+        let validators = Validators::new(BTreeMap::from([(
+            "live".to_string(),
+            ValidationPolicy {
+                tracks: vec!["*.json".to_string()],
+                sample_rate: 0.5,
+                ..Default::default()
+            },
+        )]));
+        let namespace = TrackNamespace::from_utf8_path("live/room");
+        assert!(validators.validates(&namespace, "cues.json"));
+        assert!(!validators.validates(&namespace, "video"));
+        assert!(!validators.validates(&TrackNamespace::from_utf8_path("vod"), "cues.json"));
+
+        // Every other object is checked.
+        let invalid = Bytes::from_static(b"{");
+        let delivered: Vec<_> = (0..4)
+            .map(|_| validators.validate(&namespace, "cues.json", &invalid))
+            .collect();
+        assert_eq!(delivered, [true, false, true, false]);
+        assert_eq!(
+            validators.stats()["live"],
+            ValidationStats {
+                checked: 2,
+                failed: 2,
+                rejected: 2,
+            }
+        );
+
+        // Custom checks replace the JSON one.
+        validators.set_check("live/room", |_, payload| match payload {
+            b"ok" => Ok(()),
+            _ => Err("not ok".to_string()),
+        });
+        assert!(validators.validate(&namespace, "video", &Bytes::from_static(b"ok")));
+        assert!(!validators.validate(&namespace, "video", &invalid));
+        assert_eq!(validators.stats()["live/room"].failed, 1);
+    }
+}
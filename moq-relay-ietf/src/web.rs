@@ -1,20 +1,54 @@
-use std::{net, path::PathBuf, sync::Arc};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
 
 use axum::{
-    extract::{Path, State},
-    http::{Method, StatusCode},
-    response::IntoResponse,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     routing::get,
-    Router,
+    Json, Router,
+};
+use futures::{future::BoxFuture, stream, FutureExt, Stream};
+use hyper_serve::{
+    accept::Accept,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
+use moq_transport::{coding::TrackNamespace, mlog::MlogFormat, serve::FullTrackName};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt, BufReader},
+    net::TcpStream,
+    sync::mpsc,
 };
-use hyper_serve::tls_rustls::RustlsAcceptor;
 use tower_http::cors::{Any, CorsLayer};
 
+use crate::{Health, LogIndex, LogSessionInfo, Previews};
+
+/// A TLS connection to the web server that negotiated a protocol registered with [Web::protocol].
+pub type WebStream = tokio_rustls::server::TlsStream<TcpStream>;
+
 pub struct WebConfig {
     pub bind: net::SocketAddr,
     pub tls: moq_native_ietf::tls::Config,
     pub qlog_dir: Option<PathBuf>,
     pub mlog_dir: Option<PathBuf>,
+
+    /// Bearer token required for the /qlog/ and /mlog/ index and /mlog/:cid/tail endpoints.
+    /// Those endpoints are disabled when no token is configured.
+    pub log_token: Option<String>,
+
+    /// The sessions the logs were written for, so the index can be searched by namespace and
+    /// lists their peer, see [crate::Relay::log_index].
+    pub log_index: Option<LogIndex>,
 }
 
 #[derive(Clone)]
@@ -22,13 +56,73 @@ struct WebState {
     fingerprint: String,
     qlog_dir: Option<Arc<PathBuf>>,
     mlog_dir: Option<Arc<PathBuf>>,
+    log_token: Option<Arc<String>>,
+    log_index: Option<LogIndex>,
+}
+
+/// An entry in the /qlog/ or /mlog/ index.
+#[derive(Serialize)]
+struct LogEntry {
+    cid: String,
+    file: String,
+    size: u64,
+    /// Last modification time, in seconds since the Unix epoch.
+    modified: u64,
+    /// The session the log was written for, if the relay still knows it.
+    session: Option<LogSessionInfo>,
+}
+
+/// A search of the /qlog/ and /mlog/ index, from its query parameters.
+struct LogSearch {
+    /// Logs written at or after, in seconds since the Unix epoch.
+    since: Option<u64>,
+    /// Logs of sessions started at or before, in seconds since the Unix epoch.
+    until: Option<u64>,
+    /// Logs of sessions that used a namespace under this prefix, ex. `live/room`.
+    namespace: Option<TrackNamespace>,
+}
+
+impl LogSearch {
+    fn parse(query: &HashMap<String, String>) -> Result<Self, (StatusCode, String)> {
+        let time = |name: &str| {
+            query
+                .get(name)
+                .map(|value| value.parse::<u64>())
+                .transpose()
+                .map_err(|_| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid {}: expected seconds since the Unix epoch", name),
+                    )
+                })
+        };
+
+        Ok(Self {
+            since: time("since")?,
+            until: time("until")?,
+            namespace: query
+                .get("namespace")
+                .map(|namespace| TrackNamespace::from_utf8_path(namespace.trim_matches('/'))),
+        })
+    }
 }
 
+// How often the tail endpoint checks for new events.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// The ALPN protocols served over HTTP.
+const HTTP_PROTOCOLS: [&[u8]; 2] = [b"h2", b"http/1.1"];
+
 // Run a HTTP server using Axum
 // TODO remove this when Chrome adds support for self-signed certificates using WebTransport
+//
+// The server listens on TCP, so it can share the relay's UDP address and port, ex. 443 for both.
+// Other protocols can share it too, ex. a TCP fallback for clients without UDP, see [Web::protocol].
 pub struct Web {
     app: Router,
-    server: hyper_serve::Server<RustlsAcceptor>,
+    bind: net::SocketAddr,
+    tls: rustls::ServerConfig,
+    protocols: HashMap<Vec<u8>, mpsc::UnboundedSender<WebStream>>,
 }
 
 impl Web {
@@ -42,30 +136,44 @@ impl Web {
             .expect("missing certificate")
             .clone();
 
-        let mut tls = config.tls.server.expect("missing server configuration");
-        tls.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
-        let tls = hyper_serve::tls_rustls::RustlsConfig::from_config(Arc::new(tls));
+        let tls = config.tls.server.expect("missing server configuration");
 
         // Create shared state
         let state = WebState {
             fingerprint,
             qlog_dir: config.qlog_dir.map(Arc::new),
             mlog_dir: config.mlog_dir.map(Arc::new),
+            log_token: config.log_token.map(Arc::new),
+            log_index: config.log_index,
         };
 
-        // Build router with fingerprint endpoint
-        let mut app = Router::new().route("/fingerprint", get(serve_fingerprint));
+        // Build router with fingerprint and liveness endpoints
+        let mut app = Router::new()
+            .route("/fingerprint", get(serve_fingerprint))
+            .route("/healthz", get(serve_healthz));
 
         // Optionally add qlog serving endpoint
         if state.qlog_dir.is_some() {
             app = app.route("/qlog/:cid", get(serve_qlog));
             log::info!("qlog files available at /qlog/:cid");
+
+            if state.log_token.is_some() {
+                app = app.route("/qlog/", get(serve_qlog_index));
+                log::info!("qlog index available at /qlog/");
+            }
         }
 
         // Optionally add mlog serving endpoint
         if state.mlog_dir.is_some() {
             app = app.route("/mlog/:cid", get(serve_mlog));
             log::info!("mlog files available at /mlog/:cid");
+
+            if state.log_token.is_some() {
+                app = app
+                    .route("/mlog/", get(serve_mlog_index))
+                    .route("/mlog/:cid/tail", get(serve_mlog_tail));
+                log::info!("mlog index available at /mlog/, live tail at /mlog/:cid/tail");
+            }
         }
 
         // Add state and CORS layer
@@ -75,21 +183,144 @@ impl Web {
                 .allow_methods([Method::GET]),
         );
 
-        let server = hyper_serve::bind_rustls(config.bind, tls);
+        Self {
+            app,
+            bind: config.bind,
+            tls,
+            protocols: HashMap::new(),
+        }
+    }
+
+    /// Serve whether the relay is ready at /readyz, with 503 Service Unavailable until it is.
+    pub fn with_health(mut self, health: Health) -> Self {
+        self.app = self
+            .app
+            .route("/readyz", get(serve_readyz).with_state(health));
+        log::info!("readiness available at /readyz");
+        self
+    }
+
+    /// Serve the latest sample of each previewed track at /preview/<namespace>/<track>.
+    pub fn with_previews(mut self, previews: Previews) -> Self {
+        self.app = self
+            .app
+            .route("/preview/*track", get(serve_preview).with_state(previews));
+        log::info!("previews available at /preview/:namespace/:track");
+        self
+    }
+
+    /// Hand TLS connections negotiating the ALPN protocol `alpn` to the returned receiver,
+    /// instead of serving them over HTTP. Connections without ALPN are served over HTTP.
+    pub fn protocol(&mut self, alpn: &[u8]) -> mpsc::UnboundedReceiver<WebStream> {
+        assert!(
+            !HTTP_PROTOCOLS.contains(&alpn),
+            "HTTP protocols are served by the web server"
+        );
 
-        Self { app, server }
+        let (send, recv) = mpsc::unbounded_channel();
+        self.protocols.insert(alpn.to_vec(), send);
+        recv
     }
 
     pub async fn run(self) -> anyhow::Result<()> {
-        self.server.serve(self.app.into_make_service()).await?;
+        let mut tls = self.tls;
+        tls.alpn_protocols = HTTP_PROTOCOLS.iter().map(|alpn| alpn.to_vec()).collect();
+        tls.alpn_protocols.extend(self.protocols.keys().cloned());
+
+        let acceptor = ProtocolAcceptor {
+            tls: RustlsAcceptor::new(RustlsConfig::from_config(Arc::new(tls))),
+            protocols: Arc::new(self.protocols),
+        };
+
+        hyper_serve::bind(self.bind)
+            .acceptor(acceptor)
+            .serve(self.app.into_make_service())
+            .await?;
         Ok(())
     }
 }
 
+// Completes the TLS handshake, then hands connections to the listener for their ALPN protocol.
+#[derive(Clone)]
+struct ProtocolAcceptor {
+    tls: RustlsAcceptor,
+    protocols: Arc<HashMap<Vec<u8>, mpsc::UnboundedSender<WebStream>>>,
+}
+
+impl<S: Send + 'static> Accept<TcpStream, S> for ProtocolAcceptor {
+    type Stream = WebStream;
+    type Service = S;
+    type Future = BoxFuture<'static, std::io::Result<(WebStream, S)>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let handshake = self.tls.accept(stream, service);
+        let protocols = self.protocols.clone();
+
+        async move {
+            let (stream, service) = handshake.await?;
+
+            let alpn = stream.get_ref().1.alpn_protocol();
+            match alpn.and_then(|alpn| protocols.get(alpn)) {
+                Some(listener) => {
+                    // Failing the accept tells the HTTP server to leave the connection alone.
+                    let _ = listener.send(stream);
+                    Err(std::io::Error::other("handed to protocol listener"))
+                }
+                None => Ok((stream, service)),
+            }
+        }
+        .boxed()
+    }
+}
+
 async fn serve_fingerprint(State(state): State<WebState>) -> impl IntoResponse {
     state.fingerprint
 }
 
+// The process is alive as long as it answers.
+async fn serve_healthz() -> &'static str {
+    "ok"
+}
+
+async fn serve_readyz(State(health): State<Health>) -> Response {
+    let readiness = health.check().await;
+    let status = match readiness.ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(readiness)).into_response()
+}
+
+async fn serve_preview(
+    Path(track): Path<String>,
+    State(previews): State<Previews>,
+) -> Result<Response, (StatusCode, String)> {
+    // The track name is the last segment, after the namespace.
+    let (namespace, name) = track
+        .rsplit_once('/')
+        .ok_or((StatusCode::NOT_FOUND, "Missing namespace".to_string()))?;
+
+    let track = FullTrackName {
+        namespace: TrackNamespace::from_utf8_path(namespace),
+        name: name.to_string(),
+    };
+    let preview = previews.get(&track).ok_or((
+        StatusCode::NOT_FOUND,
+        format!("No preview for {}/{}", namespace, name),
+    ))?;
+
+    // The group ID tells pollers whether the sample changed.
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CACHE_CONTROL, "no-cache".to_string()),
+            (header::ETAG, format!("\"{}\"", preview.group_id)),
+        ],
+        preview.payload,
+    )
+        .into_response())
+}
+
 async fn serve_qlog(
     Path(cid): Path<String>,
     State(state): State<WebState>,
@@ -138,7 +369,7 @@ async fn serve_qlog(
 async fn serve_mlog(
     Path(cid): Path<String>,
     State(state): State<WebState>,
-) -> Result<Vec<u8>, (StatusCode, String)> {
+) -> Result<Response, (StatusCode, String)> {
     // Get mlog directory or return 404
     let mlog_dir = state.mlog_dir.as_ref().ok_or((
         StatusCode::NOT_FOUND,
@@ -148,8 +379,13 @@ async fn serve_mlog(
     // Strip _server.mlog suffix if present to get the base CID
     let base_cid = cid.strip_suffix("_server.mlog").unwrap_or(&cid);
 
-    // Construct the expected filename
-    let filename = format!("{}_server.mlog", base_cid);
+    // Construct the expected filename, falling back to the compressed copy of a closed log
+    let mut filename = format!("{}_server.mlog", base_cid);
+    let mut compressed = false;
+    if !mlog_dir.join(&filename).exists() {
+        filename.push_str(".gz");
+        compressed = true;
+    }
     let file_path = mlog_dir.join(&filename);
 
     // Security: Ensure the path is still within mlog_dir (prevent path traversal)
@@ -172,10 +408,264 @@ async fn serve_mlog(
     }
 
     // Read and return the file
-    tokio::fs::read(&canonical_file).await.map_err(|e| {
+    let body = tokio::fs::read(&canonical_file).await.map_err(|e| {
         (
             StatusCode::NOT_FOUND,
             format!("Failed to read mlog file: {}", e),
         )
-    })
+    })?;
+
+    if compressed {
+        Ok(([(header::CONTENT_ENCODING, "gzip")], body).into_response())
+    } else {
+        Ok(body.into_response())
+    }
+}
+
+// Check the bearer token from the Authorization header, or the `token` query parameter
+// since browsers can't set headers on an EventSource.
+fn authorize(
+    state: &WebState,
+    headers: &HeaderMap,
+    query: &HashMap<String, String>,
+) -> Result<(), (StatusCode, String)> {
+    let expected = state
+        .log_token
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "Log index not enabled".to_string()))?;
+
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| query.get("token").map(String::as_str));
+
+    match provided {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Invalid token".to_string())),
+    }
+}
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// List the files in `dir` whose name contains `marker` and that match `search`, newest first.
+async fn list_logs(
+    dir: &std::path::Path,
+    marker: &str,
+    index: Option<&LogIndex>,
+    search: &LogSearch,
+) -> Result<Vec<LogEntry>, (StatusCode, String)> {
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to read log directory: {}", e),
+        )
+    })?;
+
+    let mut logs = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file = entry.file_name().to_string_lossy().into_owned();
+        let Some((cid, _)) = file.split_once(marker) else {
+            continue;
+        };
+
+        let Ok(metadata) = entry.metadata().await else {
+            continue;
+        };
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+
+        let session = index.and_then(|index| index.get(cid));
+
+        // A log spans its session, or just its last write if the session is unknown.
+        let started = session
+            .as_ref()
+            .map(|session| session.connected_at)
+            .unwrap_or(modified);
+        if search.since.is_some_and(|since| modified < since)
+            || search.until.is_some_and(|until| started > until)
+        {
+            continue;
+        }
+
+        if let Some(prefix) = &search.namespace {
+            if !index.is_some_and(|index| index.has_namespace(cid, prefix)) {
+                continue;
+            }
+        }
+
+        logs.push(LogEntry {
+            cid: cid.to_string(),
+            file,
+            size: metadata.len(),
+            modified,
+            session,
+        });
+    }
+
+    logs.sort_by(|a, b| {
+        b.modified
+            .cmp(&a.modified)
+            .then_with(|| a.file.cmp(&b.file))
+    });
+    Ok(logs)
+}
+
+async fn serve_qlog_index(
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<LogEntry>>, (StatusCode, String)> {
+    authorize(&state, &headers, &query)?;
+
+    let qlog_dir = state.qlog_dir.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "Qlog serving not enabled".to_string(),
+    ))?;
+
+    let search = LogSearch::parse(&query)?;
+    Ok(Json(
+        list_logs(qlog_dir, "_server.qlog", state.log_index.as_ref(), &search).await?,
+    ))
+}
+
+async fn serve_mlog_index(
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<LogEntry>>, (StatusCode, String)> {
+    authorize(&state, &headers, &query)?;
+
+    let mlog_dir = state.mlog_dir.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "Mlog serving not enabled".to_string(),
+    ))?;
+
+    let search = LogSearch::parse(&query)?;
+    Ok(Json(
+        list_logs(mlog_dir, "_server.mlog", state.log_index.as_ref(), &search).await?,
+    ))
+}
+
+/// Stream the events of a live mlog file as server-sent events, starting from the beginning.
+///
+/// The stream follows the file as it grows, restarts from the top when the file is rotated,
+/// and ends once the file is removed (for example when it is compressed on close).
+async fn serve_mlog_tail(
+    Path(cid): Path<String>,
+    State(state): State<WebState>,
+    headers: HeaderMap,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    authorize(&state, &headers, &query)?;
+
+    let mlog_dir = state.mlog_dir.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "Mlog serving not enabled".to_string(),
+    ))?;
+
+    let base_cid = cid.strip_suffix("_server.mlog").unwrap_or(&cid);
+    let filename = format!("{}_server.mlog", base_cid);
+    let file_path = mlog_dir.join(&filename);
+
+    // Security: Ensure the path is still within mlog_dir (prevent path traversal)
+    let canonical_dir = mlog_dir.canonicalize().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Invalid mlog directory: {}", e),
+        )
+    })?;
+
+    let canonical_file = file_path.canonicalize().map_err(|_| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Mlog file not found: {}", filename),
+        )
+    })?;
+
+    if !canonical_file.starts_with(&canonical_dir) {
+        return Err((StatusCode::FORBIDDEN, "Invalid path".to_string()));
+    }
+
+    // The tail streams JSON-SEQ lines, which a binary log doesn't have.
+    if MlogFormat::detect(&canonical_file).is_ok_and(|format| format == MlogFormat::Cbor) {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "CBOR mlog files can't be tailed, convert them with moq-mlog".to_string(),
+        ));
+    }
+
+    let file = tokio::fs::File::open(&canonical_file).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            format!("Failed to open mlog file: {}", e),
+        )
+    })?;
+
+    let tail = MlogTail {
+        path: canonical_file,
+        reader: BufReader::new(file),
+        offset: 0,
+        line: String::new(),
+    };
+
+    let events = stream::unfold(tail, |mut tail| async move {
+        let line = tail.next_line().await?;
+        Some((Ok(Event::default().data(line)), tail))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+// Follows an mlog file, yielding each complete JSON-SEQ record.
+struct MlogTail {
+    path: PathBuf,
+    reader: BufReader<tokio::fs::File>,
+    offset: u64,
+
+    // A partially written record, carried over until its newline arrives.
+    line: String,
+}
+
+impl MlogTail {
+    async fn next_line(&mut self) -> Option<String> {
+        loop {
+            match self.reader.read_line(&mut self.line).await {
+                Ok(0) => {}
+                Ok(size) => {
+                    self.offset += size as u64;
+                    if self.line.ends_with('\n') {
+                        let line = std::mem::take(&mut self.line);
+                        return Some(line.trim_end().to_string());
+                    }
+                    continue;
+                }
+                Err(err) => {
+                    log::warn!("failed to tail mlog {}: {}", self.path.display(), err);
+                    return None;
+                }
+            }
+
+            // No new data; wait and check whether the file was rotated or removed.
+            tokio::time::sleep(TAIL_POLL_INTERVAL).await;
+
+            let metadata = tokio::fs::metadata(&self.path).await.ok()?;
+            if metadata.len() < self.offset {
+                let file = tokio::fs::File::open(&self.path).await.ok()?;
+                self.reader = BufReader::new(file);
+                self.offset = 0;
+                self.line.clear();
+            }
+        }
+    }
 }
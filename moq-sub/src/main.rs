@@ -1,12 +1,13 @@
-use std::net;
-
 use anyhow::Context;
 use clap::Parser;
-use url::Url;
 
-use moq_native_ietf::quic;
+use moq_native_ietf::{quic, tls};
 use moq_sub::media::Media;
-use moq_transport::{coding::TrackNamespace, serve::Tracks};
+use moq_transport::{
+    coding::{Token, TrackNamespace},
+    serve::Tracks,
+    session::Session,
+};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -21,17 +22,25 @@ async fn main() -> anyhow::Result<()> {
     let out = tokio::io::stdout();
 
     let config = Config::parse();
-    let tls = config.tls.load()?;
-    let quic = quic::Endpoint::new(quic::Config::new(config.bind, None, tls))?;
+    let tls = tls::Config::load(&config.client.tls)?;
+    let quic = quic::Endpoint::new(
+        quic::Config::new(config.client.bind, None, tls)
+            .with_transport(config.client.transport.clone()),
+    )?;
 
-    let (session, connection_id) = quic.client.connect(&config.url, None).await?;
+    let (session, connection_id, connection) =
+        quic.client.connect(&config.client.url, None).await?;
 
     log::info!(
         "connected with CID: {} (use this to look up qlog/mlog on server)",
         connection_id
     );
 
-    let (session, subscriber) = moq_transport::session::Subscriber::connect(session)
+    let token = config
+        .client
+        .auth_token
+        .map(|token| Token::new(0, token.into_bytes()));
+    let (session, _, subscriber) = Session::connect_with_token(session, None, token)
         .await
         .context("failed to create MoQ Transport session")?;
 
@@ -45,27 +54,21 @@ async fn main() -> anyhow::Result<()> {
         res = media.run() => res.context("media error")?,
     }
 
+    log::info!("connection stats: {:?}", connection.stats());
+
     Ok(())
 }
 
 #[derive(Parser, Clone)]
 pub struct Config {
-    /// Listen for UDP packets on the given address.
-    #[arg(long, default_value = "[::]:0")]
-    pub bind: net::SocketAddr,
-
-    /// Connect to the given URL starting with https://
-    #[arg(value_parser = moq_url)]
-    pub url: Url,
+    /// How to connect to the relay.
+    #[command(flatten)]
+    pub client: moq_config::Client,
 
     /// The name of the broadcast
     #[arg(long)]
     pub name: String,
 
-    /// The TLS configuration.
-    #[command(flatten)]
-    pub tls: moq_native_ietf::tls::Args,
-
     /// Request the catalog track (to get other track names)
     ///
     /// First download the track named ".catalog" to find out the
@@ -75,14 +78,3 @@ pub struct Config {
     #[arg(long)]
     pub catalog: bool,
 }
-
-fn moq_url(s: &str) -> Result<Url, String> {
-    let url = Url::try_from(s).map_err(|e| e.to_string())?;
-
-    // Make sure the scheme is moq
-    if url.scheme() != "https" && url.scheme() != "moqt" {
-        return Err("url scheme must be https:// for WebTransport & moqt:// for QUIC".to_string());
-    }
-
-    Ok(url)
-}
@@ -0,0 +1,95 @@
+//! CPU cost of relaying a track at 1 Gbps through an in-process relay.
+//!
+//! Run with `cargo bench -p moq-test --bench relay`.
+//! The publisher, relay and subscriber share the process, so the CPU time reported covers all
+//! three: QUIC encryption on each hop as well as the relay's own handling of the payload.
+
+use std::time::{Duration, Instant};
+
+use moq_test::{MemoryCoordinator, TestRelay};
+use moq_transport::{coding::TrackNamespace, serve};
+
+// Bytes per second published, 1 Gbps.
+const RATE: u64 = 125_000_000;
+const OBJECT: usize = 16 * 1024;
+const OBJECTS_PER_GROUP: u64 = 64;
+
+const WARMUP: Duration = Duration::from_secs(1);
+const MEASURE: Duration = Duration::from_secs(5);
+
+// User and system CPU time used by the process so far.
+fn cpu_time() -> Duration {
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) };
+
+    let time = |time: libc::timeval| {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    };
+    time(usage.ru_utime) + time(usage.ru_stime)
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    env_logger::init();
+    let namespace = TrackNamespace::from_utf8_path("bench");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let payload = bytes::Bytes::from(vec![0u8; OBJECT]);
+    let write = async {
+        let group = OBJECT as u64 * OBJECTS_PER_GROUP;
+        let mut interval =
+            tokio::time::interval(Duration::from_secs_f64(group as f64 / RATE as f64));
+
+        for group_id in 0.. {
+            interval.tick().await;
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            for _ in 0..OBJECTS_PER_GROUP {
+                subgroup.write(payload.clone())?;
+            }
+        }
+        anyhow::Ok(())
+    };
+
+    let subscriber = relay.connect().await?;
+    let read = async {
+        let mut video = subscriber.subscribe(namespace, "video").await?;
+
+        let start = Instant::now();
+        while start.elapsed() < WARMUP {
+            video.next().await?;
+        }
+
+        let (start, cpu) = (Instant::now(), cpu_time());
+        let mut received = 0;
+        while start.elapsed() < MEASURE {
+            match video.next().await? {
+                Some(object) => received += object.payload.len() as u64,
+                None => anyhow::bail!("track ended"),
+            }
+        }
+
+        anyhow::Ok((received, start.elapsed(), cpu_time() - cpu))
+    };
+
+    let (received, elapsed, cpu) = tokio::select! {
+        res = read => res?,
+        res = write => anyhow::bail!("publisher stopped: {:?}", res),
+    };
+
+    let gbps = received as f64 * 8.0 / elapsed.as_secs_f64() / 1e9;
+    println!(
+        "relayed {:.2} Gbps, {:.2} cores, {:.2} CPU ms per GB",
+        gbps,
+        cpu.as_secs_f64() / elapsed.as_secs_f64(),
+        cpu.as_secs_f64() * 1000.0 / (received as f64 / 1e9)
+    );
+
+    Ok(())
+}
@@ -0,0 +1,349 @@
+use std::{net, time::Duration};
+
+use bytes::BytesMut;
+use futures::{stream::FuturesUnordered, StreamExt};
+use moq_native_ietf::quic;
+use moq_transport::{
+    coding::TrackNamespace,
+    serve::{self, ServeError, TrackReaderMode},
+    session::{GoAway, ObjectLimits, Publisher, Session, SessionError, Subscribe, Subscriber},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use url::Url;
+
+use crate::{relay::tls, throttle::Limiter, Received, Throttle, TIMEOUT};
+
+/// A session to a [crate::TestRelay], able to publish and subscribe.
+pub struct TestClient {
+    pub publisher: Publisher,
+    pub subscriber: Subscriber,
+    goaway: GoAway,
+    session: JoinHandle<Result<(), SessionError>>,
+    throttle: Option<Limiter>,
+}
+
+impl TestClient {
+    /// Connect to the relay at `url`, reached at `addr`.
+    pub async fn connect(url: Url, addr: net::SocketAddr) -> anyhow::Result<Self> {
+        Self::connect_throttled(url, addr, None).await
+    }
+
+    /// Like [Self::connect], reading the objects of every subscription slowly if `throttle` is set.
+    pub async fn connect_throttled(
+        url: Url,
+        addr: net::SocketAddr,
+        throttle: Option<Throttle>,
+    ) -> anyhow::Result<Self> {
+        let mut transport = quic::Transport::default();
+        if let Some(throttle) = throttle {
+            transport.stream_receive_window = Some(throttle.buffer as u64);
+        }
+
+        let (session, publisher, subscriber) = Self::session_with(url, addr, transport).await?;
+        if let Some(throttle) = throttle {
+            subscriber.set_object_limits(ObjectLimits {
+                max_buffered: Some(throttle.buffer),
+                ..subscriber.object_limits()
+            });
+        }
+        let goaway = session.goaway();
+
+        Ok(Self {
+            publisher,
+            subscriber,
+            goaway,
+            session: tokio::spawn(session.run()),
+            throttle: throttle.map(Limiter::new),
+        })
+    }
+
+    /// Set up a session to the relay at `url` without running it, ex. for
+    /// [moq_transport::session::ResilientSubscriber].
+    pub async fn session(
+        url: Url,
+        addr: net::SocketAddr,
+    ) -> anyhow::Result<(Session, Publisher, Subscriber)> {
+        Self::session_with(url, addr, Default::default()).await
+    }
+
+    async fn session_with(
+        url: Url,
+        addr: net::SocketAddr,
+        transport: quic::Transport,
+    ) -> anyhow::Result<(Session, Publisher, Subscriber)> {
+        let mut config = quic::Config::new("127.0.0.1:0".parse()?, None, tls());
+        config.transport = transport;
+        let client = quic::Endpoint::new(config)?.client;
+
+        let (session, _, _) = client.connect(&url, Some(addr)).await?;
+        Ok(Session::connect(session, None).await?)
+    }
+
+    /// Announce `namespace`, serving the tracks created on the returned [TestPublisher].
+    pub fn publish(&self, namespace: TrackNamespace) -> TestPublisher {
+        let (tracks, _, reader) = serve::Tracks::new(namespace).produce();
+        let mut publisher = self.publisher.clone();
+
+        TestPublisher {
+            tracks,
+            clocks: Vec::new(),
+            announce: tokio::spawn(async move { publisher.announce(reader).await }),
+        }
+    }
+
+    /// Subscribe to `name` in `namespace`, retrying until the relay can serve it.
+    pub async fn subscribe(
+        &self,
+        namespace: TrackNamespace,
+        name: &str,
+    ) -> anyhow::Result<TestSubscription> {
+        self.subscribe_track(serve::Track::new(namespace, name.into()))
+            .await
+    }
+
+    /// Like [Self::subscribe], with the options set on `track`, ex. a delivery timeout.
+    pub async fn subscribe_track(&self, track: serve::Track) -> anyhow::Result<TestSubscription> {
+        let mut subscriber = self.subscriber.clone();
+
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let (writer, reader) = track.clone().produce();
+                let subscribe = subscriber.subscribe_handle(writer);
+
+                tokio::select! {
+                    Ok(mode) = reader.mode() => return TestSubscription::new(Some(subscribe), reader, mode, self.throttle.clone()),
+                    res = subscribe.closed() => log::debug!("subscribe to {}/{} ended: {:?}", track.namespace, track.name, res),
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .map_err(|_| anyhow::anyhow!("no objects received for {}/{}", track.namespace, track.name))
+    }
+
+    /// Wait for GOAWAY from the relay, returning its URI.
+    pub async fn goaway(&self) -> Option<String> {
+        self.goaway.received().await
+    }
+
+    /// Wait until the session ends.
+    pub async fn closed(&mut self) -> Result<(), SessionError> {
+        (&mut self.session)
+            .await
+            .map_err(|_| SessionError::Internal)?
+    }
+}
+
+impl Drop for TestClient {
+    fn drop(&mut self) {
+        self.session.abort();
+    }
+}
+
+/// An announced namespace, see [TestClient::publish].
+pub struct TestPublisher {
+    tracks: serve::TracksWriter,
+    clocks: Vec<JoinHandle<()>>,
+    announce: JoinHandle<Result<(), SessionError>>,
+}
+
+impl TestPublisher {
+    /// Create a track, written to with datagrams.
+    pub fn datagrams(&mut self, name: &str) -> Result<serve::DatagramsWriter, ServeError> {
+        self.create(name)?.datagrams()
+    }
+
+    /// Create a track, written to with subgroups.
+    pub fn subgroups(&mut self, name: &str) -> Result<serve::SubgroupsWriter, ServeError> {
+        self.create(name)?.subgroups()
+    }
+
+    /// Create a track with one datagram per group every 20ms, numbered from `first_group`,
+    /// carrying the group ID as payload. Written until the publisher is dropped.
+    pub fn clock(&mut self, name: &str, first_group: u64) -> Result<(), ServeError> {
+        let mut datagrams = self.datagrams(name)?;
+        self.clocks.push(tokio::spawn(async move {
+            for group_id in first_group.. {
+                let datagram = serve::Datagram {
+                    group_id,
+                    object_id: 0,
+                    priority: 0,
+                    payload: group_id.to_string().into(),
+                    extension_headers: Default::default(),
+                };
+                if datagrams.write(datagram).is_err() {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        }));
+
+        Ok(())
+    }
+
+    fn create(&mut self, name: &str) -> Result<serve::TrackWriter, ServeError> {
+        self.tracks.create(name).ok_or(ServeError::Duplicate)
+    }
+
+    /// Whether the announce has ended, ex. because it was rejected or cancelled.
+    pub fn is_finished(&self) -> bool {
+        self.announce.is_finished()
+    }
+
+    /// Wait until the announce ends.
+    pub async fn closed(&mut self) -> Result<(), SessionError> {
+        (&mut self.announce)
+            .await
+            .map_err(|_| SessionError::Internal)?
+    }
+}
+
+impl Drop for TestPublisher {
+    fn drop(&mut self) {
+        for clock in &self.clocks {
+            clock.abort();
+        }
+        self.announce.abort();
+    }
+}
+
+/// A subscription receiving the objects of a track, see [TestClient::subscribe].
+pub struct TestSubscription {
+    subscribe: Option<Subscribe>,
+    track: serve::TrackReader,
+    objects: mpsc::UnboundedReceiver<Received>,
+}
+
+impl TestSubscription {
+    /// Receive the objects of a track subscribed to some other way.
+    pub async fn read(track: serve::TrackReader) -> anyhow::Result<Self> {
+        let mode = tokio::time::timeout(TIMEOUT, track.mode())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("no objects received for {}/{}", track.namespace, track.name)
+            })??;
+
+        Ok(Self::new(None, track, mode, None))
+    }
+
+    fn new(
+        subscribe: Option<Subscribe>,
+        track: serve::TrackReader,
+        mode: TrackReaderMode,
+        throttle: Option<Limiter>,
+    ) -> Self {
+        let (send, objects) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            if let Err(err) = Self::receive(mode, send, throttle).await {
+                log::debug!("test subscription ended: {}", err);
+            }
+        });
+
+        Self {
+            subscribe,
+            track,
+            objects,
+        }
+    }
+
+    /// The next object, in the order it was received, or None if the track ended.
+    pub async fn next(&mut self) -> anyhow::Result<Option<Received>> {
+        tokio::time::timeout(TIMEOUT, self.objects.recv())
+            .await
+            .map_err(|_| anyhow::anyhow!("no object received in {:?}", TIMEOUT))
+    }
+
+    /// The next `count` objects, failing if the track ends first.
+    pub async fn take(&mut self, count: usize) -> anyhow::Result<Vec<Received>> {
+        let mut objects = Vec::with_capacity(count);
+        while objects.len() < count {
+            match self.next().await? {
+                Some(object) => objects.push(object),
+                None => anyhow::bail!("track ended after {} objects", objects.len()),
+            }
+        }
+
+        Ok(objects)
+    }
+
+    /// The next object status marker of the track, see [serve::TrackReader::next_event].
+    pub async fn next_event(&mut self) -> anyhow::Result<Option<serve::TrackEvent>> {
+        tokio::time::timeout(TIMEOUT, self.track.next_event())
+            .await
+            .map_err(|_| anyhow::anyhow!("no event received in {:?}", TIMEOUT))
+    }
+
+    /// The SUBSCRIBE handle, ex. to wait for PUBLISH_DONE. None if created with [Self::read].
+    pub fn handle(&mut self) -> Option<&mut Subscribe> {
+        self.subscribe.as_mut()
+    }
+
+    async fn receive(
+        mode: TrackReaderMode,
+        send: mpsc::UnboundedSender<Received>,
+        throttle: Option<Limiter>,
+    ) -> Result<(), ServeError> {
+        match mode {
+            TrackReaderMode::Datagrams(mut datagrams) => {
+                while let Some(datagram) = datagrams.read().await? {
+                    if let Some(throttle) = &throttle {
+                        throttle.read(datagram.payload.len()).await;
+                    }
+                    let _ = send.send(datagram.into());
+                }
+            }
+            TrackReaderMode::Subgroups(mut subgroups) => {
+                let mut datagrams = subgroups.datagrams();
+                let mut tasks = FuturesUnordered::new();
+
+                loop {
+                    tokio::select! {
+                        res = subgroups.next() => match res? {
+                            Some(subgroup) => tasks.push(Self::receive_subgroup(subgroup, send.clone(), throttle.clone())),
+                            None => break,
+                        },
+                        res = datagrams.read() => match res? {
+                            Some(datagram) => { let _ = send.send(datagram.into()); },
+                            None => break,
+                        },
+                        Some(res) = tasks.next() => res?,
+                    }
+                }
+            }
+            TrackReaderMode::Stream(_) => return Err(ServeError::Mode),
+        }
+
+        Ok(())
+    }
+
+    async fn receive_subgroup(
+        mut subgroup: serve::SubgroupReader,
+        send: mpsc::UnboundedSender<Received>,
+        throttle: Option<Limiter>,
+    ) -> Result<(), ServeError> {
+        while let Some(mut object) = subgroup.next().await? {
+            let payload = match &throttle {
+                Some(throttle) => {
+                    let mut payload = BytesMut::with_capacity(object.size);
+                    while let Some(chunk) = object.read().await? {
+                        throttle.read(chunk.len()).await;
+                        payload.extend_from_slice(&chunk);
+                    }
+                    payload.freeze()
+                }
+                None => object.read_all().await?,
+            };
+
+            let _ = send.send(Received {
+                group_id: subgroup.info.group_id,
+                subgroup_id: Some(subgroup.info.subgroup_id),
+                object_id: object.info.object_id,
+                status: object.info.status,
+                extension_headers: object.info.extension_headers.clone(),
+                payload,
+            });
+        }
+
+        Ok(())
+    }
+}
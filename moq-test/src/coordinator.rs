@@ -0,0 +1,146 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net,
+    sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use moq_native_ietf::quic;
+use moq_relay_ietf::{
+    Coordinator, CoordinatorError, CoordinatorResult, NamespaceOrigin, NamespaceRegistration,
+};
+use moq_transport::coding::TrackNamespace;
+use url::Url;
+
+type Namespaces = Arc<Mutex<HashMap<TrackNamespace, NamespaceOrigin>>>;
+type Stale = Arc<Mutex<HashSet<TrackNamespace>>>;
+
+/// A coordinator that keeps the registered namespaces in memory, shared by every relay in the test.
+///
+/// Each relay gets its own handle from [Self::relay], which registers namespaces with the
+/// address the relay listens on once it is known. Stale namespaces aren't found by lookups.
+#[derive(Clone, Default)]
+pub struct MemoryCoordinator {
+    namespaces: Namespaces,
+    stale: Stale,
+    origin: Arc<Mutex<Option<(Url, net::SocketAddr)>>>,
+}
+
+impl MemoryCoordinator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A handle for another relay, sharing the registered namespaces.
+    pub fn relay(&self) -> Self {
+        Self {
+            namespaces: self.namespaces.clone(),
+            stale: self.stale.clone(),
+            origin: Default::default(),
+        }
+    }
+
+    /// Register namespaces as served by the relay at `url`, reachable at `addr`.
+    pub fn set_origin(&self, url: Url, addr: net::SocketAddr) {
+        *self.origin.lock().unwrap() = Some((url, addr));
+    }
+
+    /// The namespaces registered by any relay.
+    pub fn namespaces(&self) -> Vec<TrackNamespace> {
+        self.namespaces.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The origin `namespace` is registered to, if any.
+    pub fn origin(&self, namespace: &TrackNamespace) -> Option<NamespaceOrigin> {
+        self.namespaces.lock().unwrap().get(namespace).cloned()
+    }
+
+    /// Whether the relay serving `namespace` marked it stale.
+    pub fn is_stale(&self, namespace: &TrackNamespace) -> bool {
+        self.stale.lock().unwrap().contains(namespace)
+    }
+}
+
+// Removes the namespace on drop, unless another relay registered it since.
+struct Unregister {
+    namespaces: Namespaces,
+    origin: NamespaceOrigin,
+}
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        let mut namespaces = self.namespaces.lock().unwrap();
+        if namespaces.get(self.origin.namespace()) == Some(&self.origin) {
+            namespaces.remove(self.origin.namespace());
+        }
+    }
+}
+
+#[async_trait]
+impl Coordinator for MemoryCoordinator {
+    async fn register_namespace(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<NamespaceRegistration> {
+        let (url, addr) = self
+            .origin
+            .lock()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("relay address not set"))?;
+
+        let origin = NamespaceOrigin::new(namespace.clone(), url, Some(addr));
+        self.namespaces
+            .lock()
+            .unwrap()
+            .insert(namespace.clone(), origin.clone());
+        self.stale.lock().unwrap().remove(namespace);
+
+        Ok(NamespaceRegistration::new(Unregister {
+            namespaces: self.namespaces.clone(),
+            origin,
+        }))
+    }
+
+    async fn unregister_namespace(&self, namespace: &TrackNamespace) -> CoordinatorResult<()> {
+        self.namespaces.lock().unwrap().remove(namespace);
+        Ok(())
+    }
+
+    async fn set_namespace_stale(
+        &self,
+        namespace: &TrackNamespace,
+        stale: bool,
+    ) -> CoordinatorResult<()> {
+        let mut marked = self.stale.lock().unwrap();
+        match stale {
+            true => marked.insert(namespace.clone()),
+            false => marked.remove(namespace),
+        };
+        Ok(())
+    }
+
+    async fn lookup(
+        &self,
+        namespace: &TrackNamespace,
+    ) -> CoordinatorResult<(NamespaceOrigin, Option<quic::Client>)> {
+        let namespaces = self.namespaces.lock().unwrap();
+        let stale = self.stale.lock().unwrap();
+
+        // Find the longest registered prefix of the namespace.
+        namespaces
+            .iter()
+            .filter(|(registered, _)| !stale.contains(*registered))
+            .filter(|(registered, _)| {
+                registered.fields.len() <= namespace.fields.len()
+                    && registered
+                        .fields
+                        .iter()
+                        .zip(namespace.fields.iter())
+                        .all(|(a, b)| a == b)
+            })
+            .max_by_key(|(registered, _)| registered.fields.len())
+            .map(|(_, origin)| (origin.clone(), None))
+            .ok_or(CoordinatorError::NamespaceNotFound)
+    }
+}
@@ -0,0 +1,104 @@
+use std::net;
+
+use bytes::{Buf, Bytes, BytesMut};
+use moq_native_ietf::quic;
+use moq_transport::{
+    coding::{Decode, Encode, KeyValuePairs, Location, TrackNamespace},
+    data::{FetchHeader, FetchObject, ObjectStatus, StreamHeaderType},
+    message::{self, FetchType, GroupOrder, Message, StandaloneFetch},
+    setup,
+};
+use url::Url;
+
+use crate::{relay::tls, replay::decode, Received, TIMEOUT};
+
+/// Send a standalone FETCH for the objects of `name` between `start` and `end` to the relay at
+/// `url`, reached at `addr`, on a session of its own, returning the objects in the order sent.
+///
+/// The session only speaks as much of the protocol as a FETCH needs, as
+/// [moq_transport::session::Subscriber] doesn't send them yet.
+pub async fn fetch(
+    url: Url,
+    addr: net::SocketAddr,
+    namespace: TrackNamespace,
+    name: &str,
+    start: Location,
+    end: Location,
+) -> anyhow::Result<Vec<Received>> {
+    let config = quic::Config::new("127.0.0.1:0".parse()?, None, tls());
+    let client = quic::Endpoint::new(config)?.client;
+    let (mut session, _, _) = client.connect(&url, Some(addr)).await?;
+    let (mut send, mut recv) = session.open_bi().await?;
+
+    let mut params = KeyValuePairs::default();
+    params.set_intvalue(setup::ParameterType::MaxRequestId.into(), 100);
+    let mut buf = BytesMut::new();
+    setup::Client {
+        versions: [setup::Version::DRAFT_14].into(),
+        params,
+    }
+    .encode(&mut buf)?;
+    Message::from(message::Fetch {
+        id: 0,
+        subscriber_priority: 127,
+        group_order: GroupOrder::Ascending,
+        fetch_type: FetchType::Standalone,
+        standalone_fetch: Some(StandaloneFetch {
+            track_namespace: namespace,
+            track_name: name.to_string(),
+            start_location: start,
+            end_location: end,
+        }),
+        joining_fetch: None,
+        params: Default::default(),
+    })
+    .encode(&mut buf)?;
+    send.write_chunk(buf.freeze()).await?;
+
+    let mut buffer = BytesMut::new();
+    tokio::time::timeout(TIMEOUT, async {
+        decode::<setup::Server>(&mut recv, &mut buffer).await?;
+        loop {
+            match decode::<Message>(&mut recv, &mut buffer).await? {
+                Message::FetchOk(_) => return anyhow::Ok(()),
+                Message::FetchError(err) => anyhow::bail!("fetch refused: {}", err.reason_phrase.0),
+                _ => continue,
+            }
+        }
+    })
+    .await??;
+
+    // The objects follow on a stream of their own.
+    let mut stream = tokio::time::timeout(TIMEOUT, session.accept_uni()).await??;
+    let mut data = BytesMut::new();
+    while let Some(chunk) = tokio::time::timeout(TIMEOUT, stream.read_chunk(usize::MAX)).await?? {
+        data.extend_from_slice(&chunk);
+    }
+
+    let mut data = data.freeze();
+    let header_type = StreamHeaderType::decode(&mut data)?;
+    anyhow::ensure!(
+        header_type == StreamHeaderType::Fetch,
+        "not a fetch stream: {:?}",
+        header_type
+    );
+    FetchHeader::decode(header_type, &mut data)?;
+
+    let mut objects = Vec::new();
+    while data.has_remaining() {
+        let object = FetchObject::decode(&mut data)?;
+        anyhow::ensure!(data.len() >= object.payload_length, "truncated object");
+        let payload: Bytes = data.split_to(object.payload_length);
+
+        objects.push(Received {
+            group_id: object.group_id,
+            subgroup_id: Some(object.subgroup_id),
+            object_id: object.object_id,
+            status: object.status.unwrap_or(ObjectStatus::NormalObject),
+            extension_headers: object.extension_headers,
+            payload,
+        });
+    }
+
+    Ok(objects)
+}
@@ -0,0 +1,119 @@
+//! Helpers for end-to-end tests of relays, publishers and subscribers over localhost QUIC.
+//!
+//! ```rust,ignore
+//! let coordinator = MemoryCoordinator::new();
+//! let relay = TestRelay::start(&coordinator).await?;
+//!
+//! let publisher = relay.connect().await?;
+//! let mut tracks = publisher.publish(TrackNamespace::from_utf8_path("live"));
+//! let clock = tracks.datagrams("clock")?;
+//!
+//! let subscriber = relay.connect().await?;
+//! let mut clock = subscriber.subscribe(TrackNamespace::from_utf8_path("live"), "clock").await?;
+//! assert_payloads(&clock.take(3).await?, &["0", "1", "2"]);
+//! ```
+//!
+//! Clients from [TestRelay::connect_throttled] read slowly, to check how the relay treats viewers
+//! on slow links next to fast ones.
+//!
+//! A [Replayer] plays the client of a session a relay recorded to its script directory, so an
+//! interop failure captured in production can be turned into a regression test.
+//!
+//! [TestRelay::fetch] sends a FETCH on a session of its own, to check what a relay serves from its
+//! cache and archive.
+
+mod client;
+mod coordinator;
+mod fetch;
+mod relay;
+mod replay;
+mod throttle;
+
+pub use client::*;
+pub use coordinator::*;
+pub use fetch::*;
+pub use relay::*;
+pub use replay::*;
+pub use throttle::Throttle;
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use moq_transport::{
+    data::{ExtensionHeaders, ObjectStatus},
+    serve,
+};
+
+/// How long the helpers wait for anything before failing the test.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An object received by a [TestSubscription].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Received {
+    pub group_id: u64,
+
+    /// None if the object was sent as a datagram.
+    pub subgroup_id: Option<u64>,
+
+    pub object_id: u64,
+
+    /// Set for objects without a payload, ex. skipped past their delivery deadline.
+    pub status: ObjectStatus,
+
+    /// As received, ex. to check the relay forwarded them unchanged.
+    pub extension_headers: ExtensionHeaders,
+
+    pub payload: Bytes,
+}
+
+impl From<serve::Datagram> for Received {
+    fn from(datagram: serve::Datagram) -> Self {
+        Self {
+            group_id: datagram.group_id,
+            subgroup_id: None,
+            object_id: datagram.object_id,
+            status: ObjectStatus::NormalObject,
+            extension_headers: datagram.extension_headers,
+            payload: datagram.payload,
+        }
+    }
+}
+
+/// Assert the objects carry `expected` payloads, in order.
+#[track_caller]
+pub fn assert_payloads(objects: &[Received], expected: &[&str]) {
+    let payloads: Vec<_> = objects
+        .iter()
+        .map(|object| String::from_utf8_lossy(&object.payload))
+        .collect();
+    assert_eq!(payloads, expected, "unexpected payloads");
+}
+
+/// Assert each group was received at most once, in increasing order.
+#[track_caller]
+pub fn assert_groups_increasing(objects: &[Received]) {
+    let groups: Vec<_> = objects.iter().map(|object| object.group_id).collect();
+    assert!(
+        groups.windows(2).all(|pair| pair[0] < pair[1]),
+        "groups not strictly increasing: {:?}",
+        groups
+    );
+}
+
+/// Assert the objects continue one another without gaps, within and across groups.
+#[track_caller]
+pub fn assert_contiguous(objects: &[Received]) {
+    for pair in objects.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        let next_object = a.group_id == b.group_id && b.object_id == a.object_id + 1;
+        let next_group = b.group_id == a.group_id + 1 && b.object_id == 0;
+        assert!(
+            next_object || next_group,
+            "gap between {}/{} and {}/{}",
+            a.group_id,
+            a.object_id,
+            b.group_id,
+            b.object_id
+        );
+    }
+}
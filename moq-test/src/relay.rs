@@ -0,0 +1,229 @@
+use std::{net, path::PathBuf, sync::Arc, thread, time::Duration};
+
+use moq_native_ietf::tls;
+use moq_relay_ietf::{Admin, Bridge, Locals, LogIndex, Relay, RelayConfig};
+use moq_transport::coding::{Location, TrackNamespace};
+use tokio::sync::oneshot;
+use url::Url;
+
+use crate::{MemoryCoordinator, Received, TestClient, Throttle};
+
+/// The self-signed certificate and key for `localhost`, trusted by every test client and relay.
+pub fn tls() -> tls::Config {
+    let fixture =
+        |name: &str| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata")).join(name);
+
+    tls::Config::load(&tls::Args {
+        cert: vec![fixture("localhost.crt")],
+        key: vec![fixture("localhost.key")],
+        root: vec![fixture("localhost.crt")],
+        disable_verify: false,
+    })
+    .expect("failed to load test certificate")
+}
+
+/// The configuration used by [TestRelay::start], listening on a random localhost port.
+pub fn config(coordinator: MemoryCoordinator) -> RelayConfig {
+    RelayConfig {
+        bind: vec!["127.0.0.1:0".parse().unwrap()],
+        endpoints: vec![],
+        tls: tls(),
+        qlog_dir: None,
+        mlog_dir: None,
+        script_dir: None,
+        mlog: Default::default(),
+        capture: None,
+        announce: Vec::new(),
+        announce_linger: Duration::ZERO,
+        reconnect: Default::default(),
+        gossip: None,
+        node: None,
+        coordinator: Arc::new(coordinator),
+        routing: None,
+        coordinator_timeouts: Default::default(),
+        lookup_cache: None,
+        object_limits: Default::default(),
+        announce_limits: Default::default(),
+        duplicates: Default::default(),
+        liveness: None,
+        cache: Default::default(),
+        fetch_bitrate: None,
+        egress: Default::default(),
+        archive: None,
+        previews: None,
+        perf: None,
+        pinned_tracks: Vec::new(),
+        authorizer: None,
+        reauthorize: None,
+        upstream_auth_token: None,
+        zero_rtt: false,
+        transport: Default::default(),
+        flags: Default::default(),
+        quotas: Default::default(),
+        priorities: Default::default(),
+        validators: Default::default(),
+        bridge: None,
+        tenants: None,
+    }
+}
+
+/// A relay running in-process on its own thread, stopped when dropped.
+///
+/// The relay gets its own runtime, so stopping it closes every session like a crashed process
+/// would, leaving the test's runtime untouched.
+pub struct TestRelay {
+    handles: Handles,
+    coordinator: MemoryCoordinator,
+    stop: Option<oneshot::Sender<()>>,
+}
+
+// The relay's handles, sent back by its thread once it's started.
+struct Handles {
+    addr: net::SocketAddr,
+    admin: Admin,
+    log_index: Option<LogIndex>,
+    bridge: Bridge,
+    locals: Locals,
+}
+
+impl TestRelay {
+    /// Start a relay registering its namespaces with `coordinator`.
+    pub async fn start(coordinator: &MemoryCoordinator) -> anyhow::Result<Self> {
+        Self::start_with(coordinator, |config| config).await
+    }
+
+    /// Like [Self::start], changing the default [config] with `configure` first.
+    pub async fn start_with(
+        coordinator: &MemoryCoordinator,
+        configure: impl FnOnce(RelayConfig) -> RelayConfig + Send + 'static,
+    ) -> anyhow::Result<Self> {
+        let coordinator = coordinator.relay();
+        let (started, ready) = oneshot::channel();
+        let (stop, stopped) = oneshot::channel();
+
+        let config = configure(config(coordinator.clone()));
+        thread::spawn(move || {
+            let runtime = match tokio::runtime::Builder::new_multi_thread()
+                .worker_threads(2)
+                .enable_all()
+                .build()
+            {
+                Ok(runtime) => runtime,
+                Err(err) => return drop(started.send(Err(err.into()))),
+            };
+
+            runtime.block_on(async move {
+                let relay = match Relay::new(config).and_then(|relay| {
+                    let handles = Handles {
+                        addr: relay.local_addrs()?[0],
+                        admin: relay.admin(),
+                        log_index: relay.log_index(),
+                        bridge: relay.bridge(),
+                        locals: relay.locals(),
+                    };
+                    Ok((relay, handles))
+                }) {
+                    Ok((relay, handles)) => {
+                        let _ = started.send(Ok(handles));
+                        relay
+                    }
+                    Err(err) => return drop(started.send(Err(err))),
+                };
+
+                tokio::select! {
+                    res = relay.run() => log::warn!("test relay exited: {:?}", res),
+                    _ = stopped => log::debug!("test relay stopped"),
+                }
+
+                // Let the endpoint close the sessions before the runtime is dropped.
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            });
+        });
+
+        let relay = Self {
+            handles: ready.await??,
+            coordinator,
+            stop: Some(stop),
+        };
+        relay.coordinator.set_origin(relay.url(), relay.addr());
+
+        Ok(relay)
+    }
+
+    /// The address the relay accepts sessions on.
+    pub fn addr(&self) -> net::SocketAddr {
+        self.handles.addr
+    }
+
+    /// The URL of the relay, which needs [Self::addr] to be reached.
+    pub fn url(&self) -> Url {
+        format!("https://localhost:{}", self.handles.addr.port())
+            .parse()
+            .unwrap()
+    }
+
+    /// The URL of the relay by IP address, for settings without a separate address like
+    /// [RelayConfig::announce].
+    pub fn ip_url(&self) -> Url {
+        format!("https://{}", self.handles.addr).parse().unwrap()
+    }
+
+    /// The relay's admin handle, ex. to list sessions or send GOAWAY.
+    pub fn admin(&self) -> &Admin {
+        &self.handles.admin
+    }
+
+    /// The sessions of the relay's qlog and mlog files, if either is enabled.
+    pub fn log_index(&self) -> Option<&LogIndex> {
+        self.handles.log_index.as_ref()
+    }
+
+    /// The relay's tracks, for another relay to serve through [RelayConfig::bridge].
+    pub fn bridge(&self) -> Bridge {
+        self.handles.bridge.clone()
+    }
+
+    /// The relay's registry of local tracks, ex. to [Locals::register_lazy] a namespace.
+    pub fn locals(&self) -> &Locals {
+        &self.handles.locals
+    }
+
+    /// The relay's handle of the shared coordinator.
+    pub fn coordinator(&self) -> &MemoryCoordinator {
+        &self.coordinator
+    }
+
+    /// Connect a new client to the relay.
+    pub async fn connect(&self) -> anyhow::Result<TestClient> {
+        TestClient::connect(self.url(), self.handles.addr).await
+    }
+
+    /// FETCH the objects of `name` between `start` and `end`, see [crate::fetch].
+    pub async fn fetch(
+        &self,
+        namespace: TrackNamespace,
+        name: &str,
+        start: Location,
+        end: Location,
+    ) -> anyhow::Result<Vec<Received>> {
+        crate::fetch(self.url(), self.handles.addr, namespace, name, start, end).await
+    }
+
+    /// Connect a new client that reads its subscriptions slowly, see [Throttle].
+    pub async fn connect_throttled(&self, throttle: Throttle) -> anyhow::Result<TestClient> {
+        TestClient::connect_throttled(self.url(), self.handles.addr, Some(throttle)).await
+    }
+
+    /// Stop the relay, closing its sessions and endpoint.
+    pub fn stop(self) {
+        drop(self)
+    }
+}
+
+impl Drop for TestRelay {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
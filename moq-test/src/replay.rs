@@ -0,0 +1,134 @@
+use std::{io, net, path::Path, time::Duration};
+
+use bytes::{Buf, BytesMut};
+use moq_native_ietf::quic;
+use moq_transport::{
+    coding::{Decode, DecodeError},
+    message::Message,
+    session::{read_script, ScriptDirection, ScriptLine},
+    setup,
+};
+use tokio::time::Instant;
+use url::Url;
+
+use crate::{relay::tls, TIMEOUT};
+
+/// Plays the client of a session recorded by a relay's `script_dir`, against another relay.
+///
+/// The messages the client sent are sent again in order, each no earlier than it was recorded.
+/// The relay's replies are checked against the recorded ones by message type, in order, since
+/// their request IDs and parameters may differ. Data streams are ignored.
+pub struct Replayer {
+    lines: Vec<ScriptLine>,
+    timing: bool,
+}
+
+impl Replayer {
+    pub fn new(lines: Vec<ScriptLine>) -> Self {
+        Self {
+            lines,
+            timing: true,
+        }
+    }
+
+    /// Load a script written by [moq_transport::session::ScriptWriter].
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Ok(Self::new(read_script(path)?))
+    }
+
+    /// Send the client's messages as soon as the replies before them arrived, ignoring their
+    /// recorded times.
+    pub fn without_timing(mut self) -> Self {
+        self.timing = false;
+        self
+    }
+
+    /// Replay the script against the relay at `url`, reached at `addr`, returning the type of
+    /// every reply.
+    pub async fn run(&self, url: Url, addr: net::SocketAddr) -> anyhow::Result<Vec<String>> {
+        anyhow::ensure!(
+            self.lines.first().is_some_and(|line| {
+                line.direction == ScriptDirection::Received && line.message == "ClientSetup"
+            }),
+            "not a script recorded by a relay"
+        );
+
+        let config = quic::Config::new("127.0.0.1:0".parse()?, None, tls());
+        let client = quic::Endpoint::new(config)?.client;
+        let (mut session, _, _) = client.connect(&url, Some(addr)).await?;
+        let (mut send, mut recv) = session.open_bi().await?;
+
+        let start = Instant::now();
+        let mut buffer = BytesMut::new();
+        let mut replies = Vec::new();
+
+        for line in &self.lines {
+            match line.direction {
+                // Sent by the recorded client, so send it again.
+                ScriptDirection::Received => {
+                    if self.timing {
+                        tokio::time::sleep_until(start + Duration::from_millis(line.time_ms)).await;
+                    }
+                    send.write_chunk(line.bytes()?).await?;
+                }
+
+                // Sent by the recorded relay, so expect it.
+                ScriptDirection::Sent => {
+                    let reply = tokio::time::timeout(TIMEOUT, async {
+                        match line.message == "ServerSetup" {
+                            true => decode::<setup::Server>(&mut recv, &mut buffer)
+                                .await
+                                .map(|_| "ServerSetup".to_string()),
+                            false => decode::<Message>(&mut recv, &mut buffer)
+                                .await
+                                .map(|msg| msg.name().to_string()),
+                        }
+                    })
+                    .await
+                    .map_err(|_| {
+                        anyhow::anyhow!(
+                            "expected {} at {}ms, got nothing",
+                            line.message,
+                            line.time_ms
+                        )
+                    })??;
+
+                    anyhow::ensure!(
+                        reply == line.message,
+                        "expected {} at {}ms, got {}",
+                        line.message,
+                        line.time_ms,
+                        reply
+                    );
+                    replies.push(reply);
+                }
+            }
+        }
+
+        Ok(replies)
+    }
+}
+
+// Decode the next message from the control stream, reading more of it as needed.
+pub(crate) async fn decode<T: Decode>(
+    recv: &mut web_transport::RecvStream,
+    buffer: &mut BytesMut,
+) -> anyhow::Result<T> {
+    loop {
+        let mut cursor = io::Cursor::new(&buffer[..]);
+        match T::decode(&mut cursor) {
+            Ok(msg) => {
+                let consumed = cursor.position() as usize;
+                buffer.advance(consumed);
+                return Ok(msg);
+            }
+            Err(DecodeError::More(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        match recv.read_chunk(usize::MAX).await? {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            None => anyhow::bail!("control stream closed"),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Artificially slow reads on a [crate::TestClient], simulating a viewer on a slow link.
+///
+/// Subscriptions read payloads no faster than `rate`, while the client's QUIC streams and
+/// object buffers only hold `buffer` bytes ahead of them, so the relay's writes to the client
+/// stall much like they would over a congested network.
+#[derive(Clone, Copy, Debug)]
+pub struct Throttle {
+    /// Payload bytes read per second, shared by all of the client's subscriptions.
+    pub rate: u64,
+
+    /// Bytes received on each stream ahead of the throttled reads.
+    pub buffer: usize,
+}
+
+impl Throttle {
+    /// Read at most `rate` bytes per second, buffering 4KiB per stream.
+    pub fn new(rate: u64) -> Self {
+        Self { rate, buffer: 4096 }
+    }
+
+    pub fn with_buffer(mut self, buffer: usize) -> Self {
+        self.buffer = buffer;
+        self
+    }
+}
+
+// Paces the reads of every subscription on a client.
+#[derive(Clone)]
+pub(crate) struct Limiter {
+    rate: u64,
+    next: Arc<Mutex<Instant>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(throttle: Throttle) -> Self {
+        Self {
+            rate: throttle.rate.max(1),
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // Wait until `bytes` more may be read.
+    pub(crate) async fn read(&self, bytes: usize) {
+        let delay = Duration::from_secs_f64(bytes as f64 / self.rate as f64);
+        let until = {
+            let mut next = self.next.lock().unwrap();
+            *next = (*next).max(Instant::now()) + delay;
+            *next
+        };
+
+        tokio::time::sleep_until(until).await;
+    }
+}
@@ -0,0 +1,1915 @@
+use async_trait::async_trait;
+use bytes::BytesMut;
+use moq_perf::{Receiver, Report, Sender};
+use moq_relay_ietf::{
+    ArchiveConfig, Authorizer, CaptureConfig, CaptureTriggers, DuplicatePolicy, ForwardDestination,
+    GossipConfig, GossipCoordinator, LivenessConfig, NamespaceQuota, PerfConfig, PinnedTracks,
+    PreviewConfig, Quotas, Reauthorize, ReconnectPolicy, RelayConfig, SessionTenant, Tenant,
+    TenantIsolation, TenantResolver, ValidationPolicy, Validators,
+};
+use moq_test::{
+    assert_contiguous, assert_groups_increasing, assert_payloads, MemoryCoordinator, Replayer,
+    TestClient, TestRelay, TestSubscription, Throttle, TIMEOUT,
+};
+use moq_transport::{
+    coding::{Encode, KeyValuePair, Location, Token, TrackNamespace},
+    data::{ExtensionHeaders, ObjectStatus},
+    serve::{self, QuotaViolation, ServeError},
+    session::{read_script, ObjectLimits, ResilientSubscriber, TrackStatusCode, TrackStatusError},
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use url::Url;
+
+#[tokio::test]
+async fn serves_local_tracks() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace, "video");
+    let write = async {
+        // Keep writing groups until the subscription is set up.
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            for payload in ["key", "delta"] {
+                subgroup.write(payload.into())?;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut video = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    let objects = video.take(2).await?;
+    assert_payloads(&objects, &["key", "delta"]);
+    assert_eq!(objects[0].group_id, objects[1].group_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn small_objects_not_held_back() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("audio")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace, "audio");
+    let write = async {
+        // Audio-like: a long subgroup of tiny objects, 50 a second.
+        let mut subgroup = subgroups.create(serve::Subgroup {
+            group_id: 0,
+            subgroup_id: 0,
+            priority: 0,
+        })?;
+        for object_id in 0.. {
+            subgroup.write(object_id.to_string().into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    // Each object is sent as it's written, not once enough have been batched.
+    let objects = tokio::select! {
+        res = async { subscribe.await?.take(10).await } => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    assert_contiguous(&objects);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn samples_previews() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live/room");
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), |config| RelayConfig {
+        previews: Some(PreviewConfig {
+            namespaces: vec![TrackNamespace::from_utf8_path("live")],
+            tracks: vec!["video".to_string()],
+            interval: Duration::from_secs(60),
+        }),
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace);
+    let mut subgroups = tracks.subgroups("video")?;
+
+    // Sampled without any subscriber, from the first object of a group.
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            for payload in ["key", "delta"] {
+                subgroup.write(payload.into())?;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+    let previews = || relay.admin().previews().unwrap_or_default();
+    tokio::select! {
+        res = wait_for(|| !previews().is_empty()) => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    let preview = previews().remove(0);
+    assert_eq!(preview.namespace, "/live/room");
+    assert_eq!(preview.track, "video");
+    assert_eq!(preview.size, "key".len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn routes_between_relays() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let coordinator = MemoryCoordinator::new();
+    let origin = TestRelay::start(&coordinator).await?;
+    let edge = TestRelay::start(&coordinator).await?;
+
+    let publisher = origin.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    let subscriber = edge.connect().await?;
+    let mut clock = subscriber.subscribe(namespace.clone(), "clock").await?;
+    assert_groups_increasing(&clock.take(3).await?);
+
+    // The subscription was routed to the relay the namespace was announced to.
+    let registered = coordinator
+        .origin(&namespace)
+        .expect("namespace not registered");
+    assert_eq!(registered.url(), origin.url());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn pins_tracks() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let coordinator = MemoryCoordinator::new();
+    let origin = TestRelay::start(&coordinator).await?;
+    let pinned = PinnedTracks {
+        namespace: namespace.clone(),
+        names: vec!["clock".to_string()],
+    };
+    let edge = TestRelay::start_with(&coordinator, move |config| RelayConfig {
+        pinned_tracks: vec![pinned],
+        reconnect: ReconnectPolicy {
+            min_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(200),
+            ..Default::default()
+        },
+        ..config
+    })
+    .await?;
+
+    // The edge subscribes once the track is published, before anyone watches it.
+    let publisher = origin.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+    let clock = serve::FullTrackName {
+        namespace: namespace.clone(),
+        name: "clock".to_string(),
+    };
+    wait_for(|| origin.locals().is_subscribed(&clock)).await?;
+
+    // Viewers share the pinned subscription.
+    let subscriber = edge.connect().await?;
+    let mut viewer = subscriber.subscribe(namespace.clone(), "clock").await?;
+    assert_groups_increasing(&viewer.take(3).await?);
+    assert_eq!(origin.locals().tracks(), vec![(clock, 1)]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forwards_announces() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let origin = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let announce = origin.ip_url();
+    let edge = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        announce: vec![ForwardDestination::new(announce)],
+        ..config
+    })
+    .await?;
+
+    let publisher = edge.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    // Served by the origin, which only knows the namespace from the forwarded announce.
+    let subscriber = origin.connect().await?;
+    let mut clock = subscriber.subscribe(namespace, "clock").await?;
+    assert_groups_increasing(&clock.take(3).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn refuses_forwarding_loops() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+
+    // Each relay forwards its announces to the other, so the second needs a known address.
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+    let second_url: Url = format!("https://{}", addr).parse()?;
+
+    // Replacing duplicates, the looped announce would take over from the publisher.
+    let first = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        announce: vec![ForwardDestination::new(second_url)],
+        duplicates: DuplicatePolicy::Replace,
+        ..config
+    })
+    .await?;
+    let first_url = first.ip_url();
+    let second = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        bind: vec![addr],
+        announce: vec![ForwardDestination::new(first_url)],
+        ..config
+    })
+    .await?;
+
+    let publisher = first.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    wait_for(|| !announced(&second).is_empty()).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Still served by the publisher, not by the second relay.
+    let subscriber = first.connect().await?;
+    let mut clock = subscriber.subscribe(namespace, "clock").await?;
+    assert_groups_increasing(&clock.take(3).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reannounces_after_reconnecting() -> anyhow::Result<()> {
+    // The destination restarts on the same address.
+    let addr = std::net::UdpSocket::bind("127.0.0.1:0")?.local_addr()?;
+    let destination = move || async move {
+        TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+            bind: vec![addr],
+            ..config
+        })
+        .await
+    };
+
+    let upstream = destination().await?;
+    let edge = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        announce: vec![ForwardDestination::new(
+            format!("https://{}", addr).parse().unwrap(),
+        )],
+        reconnect: ReconnectPolicy {
+            min_delay: Duration::from_millis(50),
+            max_delay: Duration::from_millis(200),
+            ..Default::default()
+        },
+        ..config
+    })
+    .await?;
+
+    let publisher = edge.connect().await?;
+    let _live = publisher.publish(TrackNamespace::from_utf8_path("live/room"));
+    let _vod = publisher.publish(TrackNamespace::from_utf8_path("vod/movie"));
+    wait_for(|| announced(&upstream).len() == 2).await?;
+
+    upstream.stop();
+    let upstream = loop {
+        match destination().await {
+            Ok(upstream) => break upstream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(50)).await,
+        }
+    };
+
+    // Every namespace is announced again over the new session.
+    wait_for(|| announced(&upstream).len() == 2).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn marks_quiet_publishers_stale() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live/room");
+    let upstream = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let destination = ForwardDestination::new(upstream.ip_url());
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        announce: vec![destination],
+        liveness: Some(LivenessConfig {
+            timeout: Duration::from_millis(200),
+            withdraw: true,
+            end_subscriptions: false,
+        }),
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let mut write = |group_id| {
+        subgroups
+            .create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?
+            .write("frame".into())
+    };
+    write(0)?;
+    wait_for(|| announced(&upstream).len() == 1).await?;
+
+    // Never stale without a subscriber, however long the publisher is quiet.
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert!(!relay.coordinator().is_stale(&namespace));
+
+    let subscriber = relay.connect().await?;
+    let mut video = subscriber.subscribe(namespace.clone(), "video").await?;
+    assert_eq!(video.take(1).await?[0].group_id, 0);
+
+    // The session stays open, but nothing more is sent.
+    let stale = || relay.admin().namespaces().iter().any(|info| info.stale);
+    wait_for(|| relay.coordinator().is_stale(&namespace)).await?;
+    assert!(stale());
+    wait_for(|| announced(&upstream).is_empty()).await?;
+
+    let status = subscriber
+        .subscriber
+        .clone()
+        .track_status(&namespace, "video")
+        .await;
+    assert!(
+        matches!(&status, Err(TrackStatusError::Refused { code: 0x2, .. })),
+        "{:?}",
+        status
+    );
+
+    // Live again with the next object, and announced again.
+    write(1)?;
+    assert_eq!(video.take(1).await?[0].group_id, 1);
+    wait_for(|| !relay.coordinator().is_stale(&namespace)).await?;
+    assert!(!stale());
+    wait_for(|| announced(&upstream).len() == 1).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ends_stale_subscriptions() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), |config| RelayConfig {
+        liveness: Some(LivenessConfig {
+            timeout: Duration::from_millis(200),
+            withdraw: false,
+            end_subscriptions: true,
+        }),
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    wait_for(|| announced(&relay).len() == 1).await?;
+    subgroups
+        .create(serve::Subgroup {
+            group_id: 0,
+            subgroup_id: 0,
+            priority: 0,
+        })?
+        .write("frame".into())?;
+
+    let subscriber = relay.connect().await?;
+    let mut video = subscriber.subscribe(namespace.clone(), "video").await?;
+    assert_eq!(video.take(1).await?[0].group_id, 0);
+
+    // The subscriber is told once the publisher goes quiet, without asking.
+    let subscribe = video.handle().unwrap();
+    let res = tokio::time::timeout(TIMEOUT, subscribe.closed()).await?;
+    assert_eq!(res, Err(ServeError::Closed(ServeError::Timeout.code())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn answers_track_status() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    wait_for(|| announced(&relay).len() == 1).await?;
+
+    let mut subgroup = subgroups.create(serve::Subgroup {
+        group_id: 3,
+        subgroup_id: 0,
+        priority: 0,
+    })?;
+    subgroup.write("frame".into())?;
+    subgroup.write("frame".into())?;
+
+    // The relay knows the tracks it's subscribed to, up to the objects it received.
+    let mut client = relay.connect().await?;
+    let mut video = client.subscribe(namespace.clone(), "video").await?;
+    video.take(2).await?;
+
+    let mut subscriber = client.subscriber.clone();
+    let status =
+        tokio::time::timeout(TIMEOUT, subscriber.track_status(&namespace, "video")).await??;
+    assert_eq!(status.status, TrackStatusCode::InProgress);
+    assert_eq!(status.largest_location, Some(Location::new(3, 1)));
+
+    let status =
+        tokio::time::timeout(TIMEOUT, subscriber.track_status(&namespace, "audio")).await?;
+    assert!(
+        matches!(&status, Err(TrackStatusError::Refused { code: 0x4, .. })),
+        "{:?}",
+        status
+    );
+
+    // Requests fail once the session is gone, rather than waiting forever.
+    relay.stop();
+    tokio::time::timeout(TIMEOUT, client.closed()).await?.ok();
+    let status =
+        tokio::time::timeout(TIMEOUT, subscriber.track_status(&namespace, "video")).await?;
+    assert_eq!(status, Err(TrackStatusError::Closed));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn serves_bridged_tracks() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+
+    // Three tiers with their own coordinators, so only the bridges can find the tracks.
+    let ingest = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let bridge = ingest.bridge();
+    let mid = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        bridge: Some(bridge),
+        ..config
+    })
+    .await?;
+    let bridge = mid.bridge();
+    let edge = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        bridge: Some(bridge),
+        ..config
+    })
+    .await?;
+
+    let publisher = ingest.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    wait_for(|| announced(&ingest).len() == 1).await?;
+
+    let subscriber = edge.connect().await?;
+    let subscribe = subscriber.subscribe(namespace.clone(), "video");
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write("frame".into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let objects = tokio::select! {
+        res = async { subscribe.await?.take(2).await } => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    assert_groups_increasing(&objects);
+    assert!(announced(&edge).is_empty());
+
+    // Tracks published nowhere are still not found.
+    let missing = subscriber.subscribe(TrackNamespace::from_utf8_path("vod"), "video");
+    assert!(tokio::time::timeout(TIMEOUT, missing).await?.is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn produces_tracks_on_demand() -> anyhow::Result<()> {
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    // Each requested track writes its name, a group every 20ms.
+    let _lazy =
+        relay
+            .locals()
+            .register_lazy(TrackNamespace::from_utf8_path("gen"), |namespace| {
+                let (_writer, mut request, reader) =
+                    serve::Tracks::new(namespace.clone()).produce();
+                tokio::spawn(async move {
+                    while let Some(track) = request.next().await {
+                        tokio::spawn(async move {
+                            let name = track.name.clone();
+                            let mut subgroups = track.subgroups()?;
+                            for group_id in 0.. {
+                                let mut subgroup = subgroups.create(serve::Subgroup {
+                                    group_id,
+                                    subgroup_id: 0,
+                                    priority: 0,
+                                })?;
+                                subgroup.write(name.clone().into())?;
+                                tokio::time::sleep(Duration::from_millis(20)).await;
+                            }
+                            anyhow::Ok(())
+                        });
+                    }
+                });
+                Some(reader)
+            })?;
+
+    let client = relay.connect().await?;
+    let namespace = TrackNamespace::from_utf8_path("gen/clock");
+    let objects = client
+        .subscribe(namespace, "seconds")
+        .await?
+        .take(2)
+        .await?;
+    assert_payloads(&objects, &["seconds", "seconds"]);
+    assert!(announced(&relay).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn measures_links() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("perf");
+    let perf = PerfConfig {
+        namespace: namespace.clone(),
+        max_bitrate: 1_000_000,
+    };
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        perf: Some(perf),
+        ..config
+    })
+    .await?;
+    let client = relay.connect().await?;
+
+    // Sent at the relay's maximum, 1 Mbit/s, about 100 objects a second.
+    let receiver = Receiver::new();
+    let mut download = client
+        .subscribe(namespace.clone(), &moq_perf::download(50_000_000))
+        .await?;
+    for object in download.take(50).await? {
+        receiver.receive(&object.payload)?;
+    }
+    let report = receiver.total();
+    assert_eq!(report.lost, 0);
+    assert!(report.bitrate() < 2_000_000, "{}", report);
+
+    // The relay subscribes to the uploaded probes once we subscribe to its report.
+    let mut publisher = client.publish(TrackNamespace::from_utf8_path("perf/upload/test"));
+    let probes = publisher.subgroups(moq_perf::UPLOAD_TRACK)?;
+    let sender = tokio::spawn(Sender::new(500_000).run(probes));
+
+    let mut reports = client
+        .subscribe(namespace, &moq_perf::upload("test"))
+        .await?;
+    let report: Report = serde_json::from_slice(&reports.take(1).await?[0].payload)?;
+    assert!(report.objects > 0 && report.lost == 0, "{}", report);
+    sender.abort();
+
+    Ok(())
+}
+
+// Wait until `done` holds, polling.
+async fn wait_for(mut done: impl FnMut() -> bool) -> anyhow::Result<()> {
+    tokio::time::timeout(TIMEOUT, async {
+        while !done() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+fn announced(relay: &TestRelay) -> HashSet<String> {
+    relay
+        .admin()
+        .namespaces()
+        .into_iter()
+        .map(|info| info.namespace)
+        .collect()
+}
+
+#[tokio::test]
+async fn forwards_announces_to_each_destination() -> anyhow::Result<()> {
+    let auth = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let analytics = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let destinations = vec![
+        ForwardDestination::new(auth.ip_url()),
+        ForwardDestination::new(analytics.ip_url())
+            .with_prefix(TrackNamespace::from_utf8_path("live")),
+    ];
+    let edge = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        announce: destinations,
+        announce_linger: Duration::from_secs(1),
+        ..config
+    })
+    .await?;
+
+    let publisher = edge.connect().await?;
+    let _live = publisher.publish(TrackNamespace::from_utf8_path("live/room"));
+    let _vod = publisher.publish(TrackNamespace::from_utf8_path("vod/movie"));
+
+    // Each destination only hears about the namespaces it filters for.
+    wait_for(|| announced(&auth).len() == 2).await?;
+    wait_for(|| !announced(&analytics).is_empty()).await?;
+    assert_eq!(
+        announced(&analytics),
+        HashSet::from(["/live/room".to_string()])
+    );
+
+    // The publisher reconnects, which the destinations don't notice.
+    drop(publisher);
+    wait_for(|| announced(&edge).is_empty()).await?;
+    let publisher = edge.connect().await?;
+    let _live = publisher.publish(TrackNamespace::from_utf8_path("live/room"));
+    wait_for(|| !announced(&edge).is_empty()).await?;
+    assert_eq!(analytics.admin().teardown_stats().unannounced, 0);
+
+    // Namespaces that didn't come back are withdrawn once they stop lingering.
+    wait_for(|| announced(&auth).len() == 1).await?;
+    assert_eq!(auth.admin().teardown_stats().unannounced, 1);
+    assert_eq!(analytics.admin().teardown_stats().unannounced, 0);
+
+    Ok(())
+}
+
+// A relay without a coordinator, gossiping announces to `peers`.
+async fn gossiping(node: &str, peers: Vec<Url>, max_hops: usize) -> anyhow::Result<TestRelay> {
+    let node: Url = node.parse()?;
+    TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        coordinator: Arc::new(GossipCoordinator),
+        gossip: Some(GossipConfig {
+            node,
+            peers,
+            max_hops,
+        }),
+        ..config
+    })
+    .await
+}
+
+#[tokio::test]
+async fn gossips_announces() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let edge = gossiping("https://edge.test", Vec::new(), 4).await?;
+    let middle = gossiping("https://middle.test", vec![edge.ip_url()], 4).await?;
+    let origin = gossiping("https://origin.test", vec![middle.ip_url()], 4).await?;
+
+    let publisher = origin.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    // Two hops away, and served through the middle relay.
+    wait_for(|| !announced(&edge).is_empty()).await?;
+    let subscriber = edge.connect().await?;
+    let mut clock = subscriber.subscribe(namespace, "clock").await?;
+    assert_groups_increasing(&clock.take(3).await?);
+
+    // Withdrawn along the way once the publisher leaves.
+    drop(clock);
+    drop(publisher);
+    wait_for(|| announced(&middle).is_empty() && announced(&edge).is_empty()).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn gossip_stops_at_max_hops() -> anyhow::Result<()> {
+    let edge = gossiping("https://edge.test", Vec::new(), 1).await?;
+    let middle = gossiping("https://middle.test", vec![edge.ip_url()], 1).await?;
+    let origin = gossiping("https://origin.test", vec![middle.ip_url()], 1).await?;
+
+    let publisher = origin.connect().await?;
+    let _live = publisher.publish(TrackNamespace::from_utf8_path("live"));
+
+    wait_for(|| !announced(&middle).is_empty()).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(announced(&edge).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn announces_many_namespaces() -> anyhow::Result<()> {
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let client = relay.connect().await?;
+
+    let (_tracks, readers): (Vec<_>, Vec<_>) = (0..50)
+        .map(|room| {
+            let namespace = TrackNamespace::from_utf8_path(&format!("room/{}", room));
+            let (tracks, _, reader) = serve::Tracks::new(namespace).produce();
+            (tracks, reader)
+        })
+        .unzip();
+    let mut publisher = client.publisher.clone();
+    let announce = tokio::spawn(async move { publisher.announce_many(readers).await });
+
+    wait_for(|| announced(&relay).len() == 50).await?;
+    assert!(!announce.is_finished());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_over_between_relays() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let first = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let second = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    // The same track is published to both relays.
+    let mut publishers = Vec::new();
+    for relay in [&first, &second] {
+        let publisher = relay.connect().await?;
+        let mut tracks = publisher.publish(namespace.clone());
+        tracks.clock("clock", 0)?;
+        publishers.push((publisher, tracks));
+    }
+
+    let targets = vec![first.addr(), second.addr()];
+    let (writer, reader) = serve::Track::new(namespace, "clock".into()).produce();
+    let resilient = tokio::spawn(async move {
+        ResilientSubscriber::new(targets, |addr| async move {
+            let url = format!("https://localhost:{}", addr.port()).parse()?;
+            TestClient::session(url, addr)
+                .await
+                .map(|(session, _, subscriber)| (session, subscriber))
+        })
+        .with_backoff(Duration::from_millis(50), Duration::from_millis(200))
+        .subscribe(writer)
+        .await
+    });
+
+    let mut clock = TestSubscription::read(reader).await?;
+    let mut objects = clock.take(3).await?;
+
+    first.stop();
+    objects.extend(clock.take(10).await?);
+    assert_groups_increasing(&objects);
+
+    resilient.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn slow_subscribers() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let write = async {
+        // Two 16KiB objects per group, 20 groups a second.
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            for _ in 0..2 {
+                subgroup.write(vec![0; 16 * 1024].into())?;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    // Reads 480KiB/s of the 640KiB/s published, so the relay has to skip objects to keep up.
+    let fast = relay.connect().await?;
+    let slow = relay.connect_throttled(Throttle::new(480 * 1024)).await?;
+    let receive = async {
+        let mut fast = fast.subscribe(namespace.clone(), "video").await?;
+        let mut slow = slow
+            .subscribe_track(
+                serve::Track::new(namespace.clone(), "video".into())
+                    .with_delivery_timeout(Some(Duration::from_millis(200))),
+            )
+            .await?;
+
+        let fast = fast.take(20).await?;
+
+        // Objects the slow subscriber couldn't start receiving in time are skipped, not queued.
+        loop {
+            let object = slow.next().await?.expect("track ended");
+            match object.status {
+                ObjectStatus::NormalObject => assert_eq!(object.payload.len(), 16 * 1024),
+                ObjectStatus::ObjectDoesNotExist => {
+                    assert!(object.payload.is_empty());
+                    break;
+                }
+                status => panic!("unexpected status: {:?}", status),
+            }
+        }
+
+        anyhow::Ok(fast)
+    };
+
+    let fast = tokio::select! {
+        res = receive => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    // The fast subscriber isn't held back by the slow one.
+    assert_contiguous(&fast);
+    assert!(fast
+        .iter()
+        .all(|object| object.status == ObjectStatus::NormalObject
+            && object.payload.len() == 16 * 1024));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn corrects_publisher_clock_skew() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    // The publisher's clock is 5s ahead of the relay's.
+    let ahead = Duration::from_secs(5);
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let write = async {
+        for group_id in 0.. {
+            // One object was held back for 2s before it was sent.
+            let (captured, payload) = match group_id {
+                20 => (SystemTime::now() + ahead - Duration::from_secs(2), "stale"),
+                _ => (SystemTime::now() + ahead, "fresh"),
+            };
+            let mut headers = ExtensionHeaders::new();
+            headers.set_capture_timestamp(captured)?;
+
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write_with_extensions(payload.into(), headers)?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    // Fresh objects make the deadline, however far ahead the publisher's clock is.
+    let subscriber = relay.connect().await?;
+    let receive = async {
+        let mut video = subscriber
+            .subscribe_track(
+                serve::Track::new(namespace.clone(), "video".into())
+                    .with_delivery_timeout(Some(Duration::from_millis(500))),
+            )
+            .await?;
+
+        loop {
+            let object = video.next().await?.expect("track ended");
+            match object.group_id {
+                20 => return anyhow::Ok(object),
+                _ => assert_eq!(object.payload, "fresh"),
+            }
+        }
+    };
+
+    let stale = tokio::select! {
+        res = receive => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    assert_eq!(stale.status, ObjectStatus::ObjectDoesNotExist);
+
+    let clock = relay
+        .admin()
+        .sessions()
+        .into_iter()
+        .find_map(|session| session.clock)
+        .expect("no clock estimate");
+    assert!(
+        (clock.offset_ms - 5000.0).abs() < 100.0,
+        "{}",
+        clock.offset_ms
+    );
+    assert!(clock.latency_max_ms > 1900.0, "{}", clock.latency_max_ms);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forwards_immutable_extensions() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let mut headers = ExtensionHeaders::new();
+    headers.set_immutable(&[
+        KeyValuePair::new_bytes(0x21, b"signature".to_vec()),
+        KeyValuePair::new_int(0x40, 7),
+    ])?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write_with_extensions("signed".into(), headers.clone())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let subscriber = relay.connect().await?;
+    let receive = async {
+        let mut video = subscriber.subscribe(namespace, "video").await?;
+        video.take(3).await
+    };
+
+    let objects = tokio::select! {
+        res = receive => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    // The Immutable Extensions arrive byte for byte as the publisher sent them.
+    let mut sent = BytesMut::new();
+    headers.encode(&mut sent)?;
+    for object in objects {
+        let mut received = BytesMut::new();
+        object.extension_headers.encode(&mut received)?;
+        assert_eq!(received, sent);
+        assert_eq!(
+            object.extension_headers.immutable_extensions(),
+            headers.immutable_extensions()
+        );
+    }
+
+    Ok(())
+}
+
+// Typed and application-defined extension headers, as a publisher might attach them.
+fn extension_headers() -> anyhow::Result<ExtensionHeaders> {
+    let mut headers = ExtensionHeaders::new();
+    headers.set_immutable(&[KeyValuePair::new_bytes(0x21, b"signature".to_vec())])?;
+    headers.set_capture_timestamp(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))?;
+    headers.set_intvalue(0x40, 7);
+    headers.set_bytesvalue(0x41, b"unknown".to_vec());
+    Ok(headers)
+}
+
+#[tokio::test]
+async fn forwards_extension_headers_on_subgroups() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let headers = extension_headers()?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write_with_extensions("key".into(), headers.clone())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let subscriber = relay.connect().await?;
+    let receive = async {
+        let mut video = subscriber.subscribe(namespace, "video").await?;
+        video.take(3).await
+    };
+
+    let objects = tokio::select! {
+        res = receive => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    for object in objects {
+        assert_eq!(object.subgroup_id, Some(0));
+        assert_eq!(object.extension_headers, headers);
+        assert_eq!(object.extension_headers.unknown().count(), 2);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn forwards_extension_headers_on_datagrams() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let headers = extension_headers()?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut datagrams = tracks.datagrams("audio")?;
+    let write = async {
+        for group_id in 0.. {
+            datagrams.write(serve::Datagram {
+                group_id,
+                object_id: 0,
+                priority: 0,
+                payload: "frame".into(),
+                extension_headers: headers.clone(),
+            })?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let subscriber = relay.connect().await?;
+    let receive = async {
+        let mut audio = subscriber.subscribe(namespace, "audio").await?;
+        audio.take(3).await
+    };
+
+    let objects = tokio::select! {
+        res = receive => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    for object in objects {
+        assert_eq!(object.subgroup_id, None);
+        assert_eq!(object.extension_headers, headers);
+        assert_eq!(object.extension_headers.unknown().count(), 2);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn paces_to_max_bitrate() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    // 8 Mbps, or 1MB a second.
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe_track(
+        serve::Track::new(namespace, "video".into()).with_max_bitrate(Some(8_000_000)),
+    );
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write("key".into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut video = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    // A 640KiB burst, which is spread over about 650ms rather than forwarded at once.
+    const BURST: u64 = 1000;
+    let mut subgroup = subgroups.create(serve::Subgroup {
+        group_id: BURST,
+        subgroup_id: 0,
+        priority: 0,
+    })?;
+    for _ in 0..40 {
+        subgroup.write(vec![0; 16 * 1024].into())?;
+    }
+
+    let mut first = None;
+    let mut received = 0;
+    while received < 40 {
+        let object = video.next().await?.expect("track ended");
+        if object.group_id == BURST {
+            first.get_or_insert_with(std::time::Instant::now);
+            received += 1;
+        }
+    }
+
+    let elapsed = first.unwrap().elapsed();
+    assert!(
+        elapsed >= Duration::from_millis(400),
+        "burst took {:?}",
+        elapsed
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_less_important_subgroups() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    // 800 kbps, or 100KB a second, to the relay.
+    let publisher = relay.connect().await?;
+    publisher.publisher.set_egress_bitrate(Some(800_000));
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace, "video");
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write("key".into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut video = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    // A 320KiB enhancement layer, well over the bitrate, starting to be sent at once.
+    const BURST: u64 = 1000;
+    let mut enhancement = subgroups.create(serve::Subgroup {
+        group_id: BURST,
+        subgroup_id: 0,
+        priority: 255,
+    })?;
+    for _ in 0..20 {
+        enhancement.write(vec![0; 16 * 1024].into())?;
+    }
+
+    // And then a 40KiB base layer. Only the latest subgroup is handed out, so it comes second.
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    let mut base = subgroups.create(serve::Subgroup {
+        group_id: BURST,
+        subgroup_id: 1,
+        priority: 0,
+    })?;
+    for _ in 0..20 {
+        base.write(vec![0; 2 * 1024].into())?;
+    }
+
+    // The base layer is delivered in full, and the enhancement layer cut short.
+    let mut received = BTreeMap::<u64, usize>::new();
+    while received.get(&1).copied().unwrap_or_default() < 20 {
+        let object = video.next().await?.expect("track ended");
+        if object.group_id == BURST {
+            *received.entry(object.subgroup_id.unwrap()).or_default() += 1;
+        }
+    }
+    let enhanced = received.get(&0).copied().unwrap_or_default();
+    assert!((1..20).contains(&enhanced), "{}", enhanced);
+
+    let subscription = publisher
+        .publisher
+        .subscriptions()
+        .pop()
+        .expect("no subscription");
+    let stats = publisher
+        .publisher
+        .subscription_stats(subscription.id)
+        .expect("subscription ended")
+        .get();
+    assert!(stats.egress_dropped > 0);
+    assert_eq!(stats.streams_reset, 0);
+
+    Ok(())
+}
+
+// Allows subscriptions carrying one of the tokens not revoked yet.
+#[derive(Clone, Default)]
+struct RevocableTokens(Arc<Mutex<HashSet<Vec<u8>>>>);
+
+#[async_trait]
+impl Authorizer for RevocableTokens {
+    async fn authorize_subscribe(
+        &self,
+        _namespace: &TrackNamespace,
+        _track_name: &str,
+        tokens: &[Token],
+    ) -> bool {
+        let allowed = self.0.lock().unwrap();
+        tokens.iter().any(|token| allowed.contains(&token.value))
+    }
+}
+
+#[tokio::test]
+async fn reauthorizes_subscriptions() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let tokens = RevocableTokens::default();
+    tokens.0.lock().unwrap().insert(b"first".to_vec());
+
+    let authorizer = tokens.clone();
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        authorizer: Some(Arc::new(authorizer)),
+        reauthorize: Some(Reauthorize {
+            interval: Some(Duration::from_millis(50)),
+            grace: Duration::from_millis(300),
+        }),
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    let subscriber = relay.connect().await?;
+    let mut clock = subscriber
+        .subscribe_track(
+            serve::Track::new(namespace, "clock".into())
+                .with_authorization_token(Some(Token::new(0, b"first".to_vec()))),
+        )
+        .await?;
+    clock.take(1).await?;
+
+    // Renewing within the grace period keeps the subscription going.
+    tokens.0.lock().unwrap().remove(b"first".as_slice());
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    tokens.0.lock().unwrap().insert(b"second".to_vec());
+    let subscribe = clock.handle().unwrap();
+    subscribe.renew_authorization(Token::new(0, b"second".to_vec()))?;
+    let renewed = tokio::time::timeout(Duration::from_millis(500), subscribe.closed()).await;
+    assert!(renewed.is_err(), "subscription ended: {:?}", renewed);
+
+    // Otherwise it ends with UNAUTHORIZED.
+    tokens.0.lock().unwrap().clear();
+    let res = tokio::time::timeout(TIMEOUT, subscribe.closed()).await?;
+    assert_eq!(res, Err(ServeError::Closed(ServeError::AuthExpired.code())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn emulates_network_delay() -> anyhow::Result<()> {
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), |mut config| {
+        config.transport.emulation.delay = 100;
+        config
+    })
+    .await?;
+
+    // The QUIC handshake waits for at least one delayed packet from the relay.
+    let start = std::time::Instant::now();
+    let _client = relay.connect().await?;
+    assert!(start.elapsed() >= Duration::from_millis(100));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn enforces_namespace_quotas() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("tenant/live");
+    let quotas = Quotas::new(
+        [(
+            "tenant".to_string(),
+            NamespaceQuota {
+                max_subscribers: Some(1),
+                ..Default::default()
+            },
+        )]
+        .into(),
+    )?;
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        quotas,
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    let first = relay.connect().await?;
+    let mut clock = first.subscribe(namespace.clone(), "clock").await?;
+    clock.take(1).await?;
+
+    // The tenant's only subscriber slot is taken.
+    let second = relay.connect().await?;
+    let (writer, _reader) = serve::Track::new(namespace, "clock".into()).produce();
+    let subscribe = second.subscriber.clone().subscribe_handle(writer);
+    let res = tokio::time::timeout(TIMEOUT, subscribe.closed()).await?;
+    assert_eq!(
+        res,
+        Err(ServeError::Closed(
+            ServeError::QuotaExceeded("subscribers".into()).code()
+        ))
+    );
+
+    let stats = &relay.admin().quota_stats()["tenant"];
+    assert_eq!(stats.subscribers, 1);
+    assert_eq!(stats.rejected_subscribers, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn enforces_subscription_quotas() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let quotas = Quotas::new(
+        [(
+            "live".to_string(),
+            NamespaceQuota {
+                max_subscription_mbps: Some(1.0),
+                ..Default::default()
+            },
+        )]
+        .into(),
+    )?;
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        quotas,
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    let subscriber = relay.connect().await?;
+    let (writer, _reader) = serve::Track::new(namespace, "video".into()).produce();
+    let subscribe = subscriber.subscriber.clone().subscribe_handle(writer);
+
+    // 16KiB objects 40 times a second, or about 5Mbps.
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write(vec![0; 16 * 1024].into())?;
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    // The runaway subscription is ended, rather than allowed to use up the session.
+    let res = tokio::select! {
+        res = tokio::time::timeout(TIMEOUT, subscribe.closed()) => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    // Only the error code reaches the subscriber.
+    let violated = ServeError::QuotaViolated(QuotaViolation::Rate {
+        rate: 0,
+        limit: 125_000,
+    });
+    assert_eq!(res, Err(ServeError::Closed(violated.code())));
+
+    Ok(())
+}
+
+// Sessions to "localhost" belong to tenant "a", and sessions without a server name to "b".
+struct TestTenants;
+
+impl TenantResolver for TestTenants {
+    fn resolve(&self, server_name: Option<&str>, _tokens: &[Token]) -> SessionTenant {
+        let id = match server_name {
+            Some("localhost") => "a",
+            _ => "b",
+        };
+        SessionTenant::Tenant(Tenant::new(id, TenantIsolation::Scoped))
+    }
+}
+
+#[tokio::test]
+async fn isolates_tenants() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), |config| RelayConfig {
+        tenants: Some(Arc::new(TestTenants)),
+        ..config
+    })
+    .await?;
+
+    // Both tenants publish the same namespace, without colliding.
+    let publisher_a = relay.connect().await?;
+    let mut tracks_a = publisher_a.publish(namespace.clone());
+    tracks_a.clock("clock", 0)?;
+
+    let publisher_b = TestClient::connect(relay.ip_url(), relay.addr()).await?;
+    let mut tracks_b = publisher_b.publish(namespace.clone());
+    tracks_b.clock("clock", 1000)?;
+
+    // Each tenant only receives its own track.
+    let subscriber_a = relay.connect().await?;
+    let mut clock = subscriber_a.subscribe(namespace.clone(), "clock").await?;
+    assert!(clock
+        .take(2)
+        .await?
+        .iter()
+        .all(|object| object.group_id < 1000));
+
+    let subscriber_b = TestClient::connect(relay.ip_url(), relay.addr()).await?;
+    let mut clock = subscriber_b.subscribe(namespace, "clock").await?;
+    assert!(clock
+        .take(2)
+        .await?
+        .iter()
+        .all(|object| object.group_id >= 1000));
+
+    // The relay files each tenant's namespaces under its ID.
+    let namespaces: HashSet<_> = relay.coordinator().namespaces().into_iter().collect();
+    assert_eq!(
+        namespaces,
+        HashSet::from([
+            TrackNamespace::from_utf8_path("a/live"),
+            TrackNamespace::from_utf8_path("b/live"),
+        ])
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_delivery_stats() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    let subscriber = relay.connect().await?;
+    let mut clock = subscriber.subscribe(namespace, "clock").await?;
+    clock.take(2).await?;
+
+    // The publisher sees how its track is delivered to the relay.
+    let subscription = publisher
+        .publisher
+        .subscriptions()
+        .pop()
+        .expect("no subscription");
+    let stats = publisher
+        .publisher
+        .subscription_stats(subscription.id)
+        .expect("subscription ended");
+    let stats = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let stats = stats.next().await.expect("subscription ended");
+            if stats.objects_sent >= 2 {
+                return stats;
+            }
+        }
+    })
+    .await?;
+    assert!(stats.bytes_sent > 0);
+    assert_eq!(stats.streams_reset, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_periodic_stats() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+
+    let mut events = publisher.publisher.stats_events(Duration::from_millis(50));
+
+    let subscriber = relay.connect().await?;
+    let mut clock = subscriber.subscribe(namespace, "clock").await?;
+    clock.take(2).await?;
+
+    // Every subscription is reported each period, with an up to date snapshot.
+    let event = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            let mut events = events.next().await.expect("session ended");
+            if let Some(event) = events.pop().filter(|event| event.stats.objects_sent >= 2) {
+                return event;
+            }
+        }
+    })
+    .await?;
+    assert_eq!(event.subscription.track_name, "clock");
+    assert!(event.stats.bytes_sent > 0);
+    assert_eq!(event.stats.groups_skipped, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_close_reasons() -> anyhow::Result<()> {
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let client = relay.connect().await?;
+    while relay.admin().sessions().is_empty() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    drop(client);
+
+    let closed = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            if let Some(close) = relay.admin().close_stats().recent.pop() {
+                return close;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    // The client going away is told apart from network trouble.
+    assert_eq!(closed.reason, "application");
+    assert_eq!(closed.error, None);
+    assert_eq!(relay.admin().close_stats().reasons["application"], 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn captures_misbehaving_sessions() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let dir = std::env::temp_dir().join(format!("moq-test-capture-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let mlog_dir = dir.clone();
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        mlog_dir: Some(mlog_dir),
+        capture: Some(CaptureConfig {
+            triggers: CaptureTriggers {
+                protocol_violations: Some(1),
+                ..Default::default()
+            },
+            ..Default::default()
+        }),
+        object_limits: ObjectLimits {
+            max_object_size: Some(1024),
+            ..Default::default()
+        },
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    // Nothing is written for sessions behaving well.
+    wait_for(|| relay.admin().sessions().len() == 1).await?;
+    assert_eq!(std::fs::read_dir(&dir)?.count(), 0);
+
+    let subscriber = relay.connect().await?;
+    let (writer, _reader) = serve::Track::new(namespace, "video".into()).produce();
+    let _subscribe = subscriber.subscriber.clone().subscribe_handle(writer);
+
+    // Objects over the relay's limit are protocol violations.
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write(vec![0; 4096].into())?;
+            tokio::time::sleep(Duration::from_millis(25)).await;
+        }
+        anyhow::Ok(())
+    };
+    tokio::select! {
+        res = wait_for(|| !relay.admin().capture_stats().active.is_empty()) => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    let capture = relay.admin().capture_stats().active.remove(0);
+    assert_eq!(capture.symptom, "protocol_violations");
+    assert!(capture.path.exists());
+
+    // The misbehaving session is flagged, the other one isn't.
+    let sessions = relay.admin().sessions();
+    let flagged: Vec<_> = sessions
+        .iter()
+        .filter(|session| session.capturing)
+        .collect();
+    assert_eq!(flagged.len(), 1);
+    assert_eq!(flagged[0].connection_id, capture.connection_id);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_invalid_objects() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let validators = Validators::new(BTreeMap::from([(
+        "live".to_string(),
+        ValidationPolicy {
+            tracks: vec!["*.json".to_string()],
+            schema: Some(serde_json::json!({"type": "object", "required": ["t"]})),
+            ..Default::default()
+        },
+    )]));
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        validators,
+        ..config
+    })
+    .await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("cues.json")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace, "cues.json");
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            for payload in [r#"{"t":1}"#, "{}", r#"{"t":2}"#] {
+                subgroup.write(payload.into())?;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut cues = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+
+    // The invalid object never reaches the subscriber.
+    let objects = cues.take(2).await?;
+    assert_payloads(&objects, &[r#"{"t":1}"#, r#"{"t":2}"#]);
+    assert_eq!(objects[0].group_id, objects[1].group_id);
+    assert_eq!(objects[1].object_id, 2);
+
+    let stats = &relay.admin().validation_stats()["live"];
+    assert!(stats.rejected > 0);
+    assert_eq!(stats.failed, stats.rejected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_end_of_track() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace, "video");
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write("key".into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut video = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    video.take(1).await?;
+
+    // The last group ends the track with a status instead of an object.
+    let mut subgroup = subgroups.create(serve::Subgroup {
+        group_id: 1_000_000,
+        subgroup_id: 0,
+        priority: 0,
+    })?;
+    subgroup.write("last".into())?;
+    subgroup.create_status(ObjectStatus::EndOfTrack, None)?;
+
+    let end = Location::new(1_000_000, 1);
+    assert_eq!(
+        video.next_event().await?,
+        Some(serve::TrackEvent::EndOfTrack(end))
+    );
+
+    // The publisher only goes away, but the relay knows the track ended cleanly.
+    drop(subgroup);
+    drop(subgroups);
+    let subscribe = video.handle().unwrap();
+    let done = tokio::time::timeout(TIMEOUT, subscribe.closed()).await?;
+    assert_eq!(done, Err(ServeError::Closed(ServeError::TrackEnded.code())));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revokes_namespaces() -> anyhow::Result<()> {
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    tracks.clock("clock", 0)?;
+    wait_for(|| announced(&relay).contains("/live")).await?;
+
+    // The publisher's announce is cancelled, ending it.
+    assert_eq!(relay.admin().revoke_namespace(&namespace), 1);
+    assert!(announced(&relay).is_empty());
+    wait_for(|| tracks.is_finished()).await?;
+    assert_eq!(relay.admin().revoke_namespace(&namespace), 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn captures_sessions_on_request() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("moq-test-request-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let mlog_dir = dir.clone();
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        mlog_dir: Some(mlog_dir),
+        capture: Some(CaptureConfig::default()),
+        ..config
+    })
+    .await?;
+
+    let _client = relay.connect().await?;
+    wait_for(|| relay.admin().sessions().len() == 1).await?;
+    let id = relay.admin().sessions()[0].id;
+
+    // Without triggers, only a request starts a capture.
+    wait_for(|| relay.admin().capture_session(id) == Some(true)).await?;
+    assert_eq!(relay.admin().capture_session(id + 1), None);
+    wait_for(|| !relay.admin().capture_stats().active.is_empty()).await?;
+
+    let capture = relay.admin().capture_stats().active.remove(0);
+    assert_eq!(capture.symptom, "admin");
+    assert!(capture.path.exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn replays_recorded_sessions() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("moq-test-script-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let script_dir = dir.clone();
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        script_dir: Some(script_dir),
+        ..config
+    })
+    .await?;
+
+    // Announce a namespace, then subscribe to a track nobody publishes.
+    let client = relay.connect().await?;
+    let _tracks = client.publish(TrackNamespace::from_utf8_path("live"));
+    wait_for(|| !announced(&relay).is_empty()).await?;
+
+    let missing = TrackNamespace::from_utf8_path("missing");
+    let (writer, _reader) = serve::Track::new(missing, "video".into()).produce();
+    let subscribe = client.subscriber.clone().subscribe_handle(writer);
+    assert!(subscribe.closed().await.is_err());
+
+    let path = std::fs::read_dir(&dir)?.next().unwrap()?.path();
+    let mut lines = read_script(&path)?;
+    std::fs::remove_dir_all(&dir)?;
+
+    // Another relay replies the same way.
+    let other = TestRelay::start(&MemoryCoordinator::new()).await?;
+    let replies = Replayer::new(lines.clone())
+        .run(other.url(), other.addr())
+        .await?;
+    assert_eq!(
+        replies,
+        ["ServerSetup", "PublishNamespaceOk", "SubscribeError"]
+    );
+
+    // A relay replying otherwise fails the replay.
+    let last = lines.last_mut().unwrap();
+    last.message = "SubscribeOk".to_string();
+    let err = Replayer::new(lines)
+        .without_timing()
+        .run(other.url(), other.addr())
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("got SubscribeError"), "{}", err);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fetches_archived_groups() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("moq-test-archive-{}", std::process::id()));
+    let namespace = TrackNamespace::from_utf8_path("live");
+    let archived = |dir: std::path::PathBuf| {
+        let namespace = namespace.clone();
+        move |config| RelayConfig {
+            archive: Some(ArchiveConfig {
+                dir,
+                namespaces: vec![namespace],
+                max_age: Duration::from_secs(3600),
+                max_bytes: u64::MAX,
+            }),
+            ..config
+        }
+    };
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), archived(dir.clone())).await?;
+
+    // Groups are archived while someone watches the track.
+    let publisher = relay.connect().await?;
+    let mut tracks = publisher.publish(namespace.clone());
+    let mut subgroups = tracks.subgroups("video")?;
+
+    let subscriber = relay.connect().await?;
+    let subscribe = subscriber.subscribe(namespace.clone(), "video");
+    let write = async {
+        for group_id in 0.. {
+            let mut subgroup = subgroups.create(serve::Subgroup {
+                group_id,
+                subgroup_id: 0,
+                priority: 0,
+            })?;
+            subgroup.write(format!("{}", group_id).into())?;
+            subgroup.write("-1".into())?;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        anyhow::Ok(())
+    };
+
+    let mut video = tokio::select! {
+        res = subscribe => res?,
+        res = write => panic!("publisher stopped: {:?}", res),
+    };
+    for group_id in 1000..1004 {
+        let mut subgroup = subgroups.create(serve::Subgroup {
+            group_id,
+            subgroup_id: 0,
+            priority: 0,
+        })?;
+        subgroup.write(format!("{}", group_id).into())?;
+        subgroup.write("-1".into())?;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    while video.take(1).await?[0].group_id < 1003 {}
+
+    // The publisher leaves and the relay restarts, but a late viewer can still fetch them.
+    drop((video, subgroups, tracks, publisher));
+    wait_for(|| {
+        let mut tracks = std::fs::read_dir(&dir).into_iter().flatten().flatten();
+        tracks.any(|track| track.path().join("1002.group").exists())
+    })
+    .await?;
+    relay.stop();
+
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), archived(dir.clone())).await?;
+    let objects = relay
+        .fetch(
+            namespace,
+            "video",
+            Location::new(1000, 1),
+            Location::new(1002, 1),
+        )
+        .await?;
+    assert_payloads(&objects, &["-1", "1001", "-1", "1002"]);
+    assert_eq!(relay.admin().archive_stats().unwrap().hits, 3);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+use std::{net, sync::Arc, time::Duration};
+
+use moq_relay_ietf::{RelayConfig, Web, WebConfig};
+use moq_test::{tls, MemoryCoordinator, TestRelay, TIMEOUT};
+use moq_transport::coding::TrackNamespace;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_rustls::{client::TlsStream, rustls::pki_types::ServerName, TlsConnector};
+
+// Connect to the web server over TLS, offering a single ALPN protocol.
+async fn connect(
+    addr: net::SocketAddr,
+    alpn: &[u8],
+) -> anyhow::Result<TlsStream<tokio::net::TcpStream>> {
+    let mut config = tls().client;
+    config.alpn_protocols = vec![alpn.to_vec()];
+    let connector = TlsConnector::from(Arc::new(config));
+
+    // The web server starts listening in the background.
+    let tcp = tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(tcp) => return tcp,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        }
+    })
+    .await?;
+
+    Ok(connector
+        .connect(ServerName::try_from("localhost")?, tcp)
+        .await?)
+}
+
+// Send a GET request over HTTP/1.1, returning the status line and body.
+async fn get(addr: net::SocketAddr, path: &str) -> anyhow::Result<(String, String)> {
+    let mut http = connect(addr, b"http/1.1").await?;
+    let request = format!(
+        "GET {} HTTP/1.1\r\nhost: localhost\r\nauthorization: Bearer secret\r\nconnection: close\r\n\r\n",
+        path
+    );
+    http.write_all(request.as_bytes()).await?;
+
+    let mut response = String::new();
+    http.read_to_string(&mut response).await?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or_default().to_string();
+    Ok((status, body.to_string()))
+}
+
+#[tokio::test]
+async fn shares_port_with_quic() -> anyhow::Result<()> {
+    let relay = TestRelay::start(&MemoryCoordinator::new()).await?;
+
+    // The web server listens on TCP at the relay's UDP address.
+    let mut web = Web::new(WebConfig {
+        bind: relay.addr(),
+        tls: tls(),
+        qlog_dir: None,
+        mlog_dir: None,
+        log_token: None,
+        log_index: None,
+    });
+    let mut fallback = web.protocol(b"moqt-test");
+    tokio::spawn(web.run());
+
+    let mut http = connect(relay.addr(), b"http/1.1").await?;
+    http.write_all(b"GET /fingerprint HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n")
+        .await?;
+    let mut response = String::new();
+    http.read_to_string(&mut response).await?;
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(response.ends_with(&tls().fingerprints[0]), "{}", response);
+
+    // Connections negotiating another protocol are handed over instead, with the same certificate.
+    let mut client = connect(relay.addr(), b"moqt-test").await?;
+    let mut server = tokio::time::timeout(TIMEOUT, fallback.recv())
+        .await?
+        .expect("web server stopped");
+    client.write_all(b"hello").await?;
+    let mut hello = [0; 5];
+    server.read_exact(&mut hello).await?;
+    assert_eq!(&hello, b"hello");
+
+    // QUIC is still served on the same port.
+    relay.connect().await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn searches_logs_by_namespace() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("moq-test-log-index-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+
+    let mlog_dir = dir.clone();
+    let relay = TestRelay::start_with(&MemoryCoordinator::new(), move |config| RelayConfig {
+        mlog_dir: Some(mlog_dir),
+        ..config
+    })
+    .await?;
+
+    let web = Web::new(WebConfig {
+        bind: relay.addr(),
+        tls: tls(),
+        qlog_dir: None,
+        mlog_dir: Some(dir.clone()),
+        log_token: Some("secret".to_string()),
+        log_index: relay.log_index().cloned(),
+    });
+    tokio::spawn(web.run());
+
+    // Two publishers, of which only one is in the namespace searched for.
+    let room = relay.connect().await?;
+    let _room = room.publish(TrackNamespace::from_utf8_path("live/room"));
+    let vod = relay.connect().await?;
+    let _vod = vod.publish(TrackNamespace::from_utf8_path("vod/movie"));
+    tokio::time::timeout(TIMEOUT, async {
+        while relay.admin().namespaces().len() < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    let (status, body) = get(relay.addr(), "/mlog/").await?;
+    assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+    let logs: Vec<serde_json::Value> = serde_json::from_str(&body)?;
+    assert_eq!(logs.len(), 2, "{}", body);
+
+    let (_, body) = get(relay.addr(), "/mlog/?namespace=live").await?;
+    let logs: Vec<serde_json::Value> = serde_json::from_str(&body)?;
+    assert_eq!(logs.len(), 1, "{}", body);
+    let session = &logs[0]["session"];
+    assert_eq!(session["namespaces"], serde_json::json!(["/live/room"]));
+    assert_eq!(session["open"], true);
+    assert!(session["peer"]
+        .as_str()
+        .is_some_and(|peer| peer.starts_with("127.0.0.1:")));
+
+    // Nothing was written after the end of time.
+    let (_, body) = get(relay.addr(), &format!("/mlog/?since={}", u64::MAX)).await?;
+    assert_eq!(body, "[]");
+
+    let (status, _) = get(relay.addr(), "/mlog/?until=yesterday").await?;
+    assert!(status.starts_with("HTTP/1.1 400"), "{}", status);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
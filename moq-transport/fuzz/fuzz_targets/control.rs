@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use moq_transport::message;
+
+// Decode control messages back to back, as read from the control stream.
+fuzz_target!(|data: &[u8]| {
+    let mut buf = data;
+    while message::decode_any(&mut buf).is_ok() {}
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use moq_transport::{coding::Decode, data};
+
+fuzz_target!(|data: &[u8]| {
+    let mut buf = data;
+    let _ = data::Datagram::decode(&mut buf);
+});
@@ -0,0 +1,31 @@
+#![no_main]
+
+use bytes::Buf;
+use libfuzzer_sys::fuzz_target;
+use moq_transport::{coding::Decode, data};
+
+// Decode a unidirectional stream: the header, then each object followed by its payload.
+fuzz_target!(|data: &[u8]| {
+    let mut buf = data;
+    let header = match data::StreamHeader::decode(&mut buf) {
+        Ok(header) => header,
+        Err(_) => return,
+    };
+
+    while buf.has_remaining() {
+        let payload_length = match header.header_type {
+            data::StreamHeaderType::Fetch => {
+                data::FetchObject::decode(&mut buf).map(|o| o.payload_length)
+            }
+            t if t.has_extension_headers() => {
+                data::SubgroupObjectExt::decode(&mut buf).map(|o| o.payload_length)
+            }
+            _ => data::SubgroupObject::decode(&mut buf).map(|o| o.payload_length),
+        };
+
+        match payload_length {
+            Ok(length) if length <= buf.remaining() => buf.advance(length),
+            _ => return,
+        }
+    }
+});
@@ -0,0 +1,145 @@
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+
+/// An authorization token, as understood by the application.
+/// The token type is application defined; 0 is commonly used for opaque bearer tokens.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub token_type: u64,
+    pub value: Vec<u8>,
+}
+
+impl Token {
+    pub fn new(token_type: u64, value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            token_type,
+            value: value.into(),
+        }
+    }
+}
+
+/// The contents of an AUTHORIZATION TOKEN parameter, from draft-ietf-moq-transport-14 Section 9.2.1.
+///
+/// A token may be sent by value, or registered under an alias so later requests only need to
+/// reference it. The token value takes up the remainder of the parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AuthToken {
+    /// Remove a previously registered alias.
+    Delete { alias: u64 },
+    /// Register the token under an alias and use it for this request.
+    Register { alias: u64, token: Token },
+    /// Use a previously registered token.
+    UseAlias { alias: u64 },
+    /// Use the token without registering it.
+    UseValue { token: Token },
+}
+
+impl AuthToken {
+    const DELETE: u64 = 0x0;
+    const REGISTER: u64 = 0x1;
+    const USE_ALIAS: u64 = 0x2;
+    const USE_VALUE: u64 = 0x3;
+}
+
+impl Decode for AuthToken {
+    fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
+        let alias_type = u64::decode(r)?;
+
+        let decode_token = |r: &mut R| -> Result<Token, DecodeError> {
+            let token_type = u64::decode(r)?;
+            let value = r.copy_to_bytes(r.remaining()).to_vec();
+            Ok(Token { token_type, value })
+        };
+
+        let token = match alias_type {
+            Self::DELETE => Self::Delete {
+                alias: u64::decode(r)?,
+            },
+            Self::REGISTER => Self::Register {
+                alias: u64::decode(r)?,
+                token: decode_token(r)?,
+            },
+            Self::USE_ALIAS => Self::UseAlias {
+                alias: u64::decode(r)?,
+            },
+            Self::USE_VALUE => Self::UseValue {
+                token: decode_token(r)?,
+            },
+            _ => return Err(DecodeError::InvalidValue),
+        };
+
+        // Delete and UseAlias carry nothing after the alias.
+        if r.has_remaining() {
+            return Err(DecodeError::InvalidValue);
+        }
+
+        Ok(token)
+    }
+}
+
+impl Encode for AuthToken {
+    fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+        let encode_token = |w: &mut W, token: &Token| -> Result<(), EncodeError> {
+            token.token_type.encode(w)?;
+            Self::encode_remaining(w, token.value.len())?;
+            w.put_slice(&token.value);
+            Ok(())
+        };
+
+        match self {
+            Self::Delete { alias } => {
+                Self::DELETE.encode(w)?;
+                alias.encode(w)?;
+            }
+            Self::Register { alias, token } => {
+                Self::REGISTER.encode(w)?;
+                alias.encode(w)?;
+                encode_token(w, token)?;
+            }
+            Self::UseAlias { alias } => {
+                Self::USE_ALIAS.encode(w)?;
+                alias.encode(w)?;
+            }
+            Self::UseValue { token } => {
+                Self::USE_VALUE.encode(w)?;
+                encode_token(w, token)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn encode_decode() {
+        let mut buf = BytesMut::new();
+
+        let token = AuthToken::Register {
+            alias: 7,
+            token: Token::new(0, b"secret".to_vec()),
+        };
+        token.encode(&mut buf).unwrap();
+        assert_eq!(
+            buf.to_vec(),
+            vec![0x01, 0x07, 0x00, b's', b'e', b'c', b'r', b'e', b't']
+        );
+        assert_eq!(AuthToken::decode(&mut buf).unwrap(), token);
+
+        let token = AuthToken::UseAlias { alias: 7 };
+        token.encode(&mut buf).unwrap();
+        assert_eq!(buf.to_vec(), vec![0x02, 0x07]);
+        assert_eq!(AuthToken::decode(&mut buf).unwrap(), token);
+
+        // Trailing bytes after an alias are malformed.
+        let mut buf = BytesMut::from(&[0x00, 0x07, 0x01][..]);
+        assert!(AuthToken::decode(&mut buf).is_err());
+
+        // Unknown alias type.
+        let mut buf = BytesMut::from(&[0x04, 0x07][..]);
+        assert!(AuthToken::decode(&mut buf).is_err());
+    }
+}
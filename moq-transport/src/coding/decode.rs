@@ -16,6 +16,33 @@ pub trait Decode: Sized {
     }
 }
 
+/// Decode a payload of exactly `len` bytes with `f`, as framed by a length prefix.
+///
+/// The whole payload is buffered before decoding but nothing past it, so a length inside the
+/// payload can't make the reader wait for more than the prefix allows. A payload that decodes
+/// to more or fewer than `len` bytes is invalid rather than incomplete.
+pub fn decode_exact<B: bytes::Buf, T>(
+    buf: &mut B,
+    len: usize,
+    f: impl FnOnce(&mut bytes::Bytes) -> Result<T, DecodeError>,
+) -> Result<T, DecodeError> {
+    if buf.remaining() < len {
+        return Err(DecodeError::More(len - buf.remaining()));
+    }
+
+    let mut payload = buf.copy_to_bytes(len);
+    let decoded = f(&mut payload).map_err(|err| match err {
+        DecodeError::More(needed) => DecodeError::InvalidLength(len, len + needed),
+        err => err,
+    })?;
+
+    if !payload.is_empty() {
+        return Err(DecodeError::InvalidLength(len, len - payload.len()));
+    }
+
+    Ok(decoded)
+}
+
 /// A decode error.
 #[derive(Error, Debug, Clone)]
 pub enum DecodeError {
@@ -39,6 +39,9 @@ pub enum EncodeError {
 
     #[error("field '{0}' too large")]
     FieldBoundsExceeded(String),
+
+    #[error("immutable extensions can't be modified")]
+    ImmutableExtensions,
 }
 
 impl From<io::Error> for EncodeError {
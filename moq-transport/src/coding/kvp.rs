@@ -1,4 +1,4 @@
-use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, VarInt};
 use std::fmt;
 
 #[derive(Clone, Eq, PartialEq)]
@@ -48,6 +48,17 @@ impl KeyValuePair {
             value: Value::BytesValue(value),
         }
     }
+
+    /// The number of bytes this pair takes up when encoded, or an error if it can't be encoded.
+    pub fn encoded_len(&self) -> Result<usize, EncodeError> {
+        let size = |v: u64| VarInt::try_from(v).map(VarInt::size);
+        let value = match &self.value {
+            Value::IntValue(v) => size(*v)?,
+            Value::BytesValue(v) => size(v.len() as u64)? + v.len(),
+        };
+
+        Ok(size(self.key)? + value)
+    }
 }
 
 impl Decode for KeyValuePair {
@@ -122,6 +133,8 @@ pub struct KeyValuePairs(pub Vec<KeyValuePair>);
 
 // TODO: These set/get API's all assume no duplicate keys. We can add API's to support duplicates if needed.
 impl KeyValuePairs {
+    pub const MAX_PAIRS: usize = 64;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -150,13 +163,33 @@ impl KeyValuePairs {
     pub fn get(&self, key: u64) -> Option<&KeyValuePair> {
         self.0.iter().find(|k| k.key == key)
     }
+
+    pub fn get_intvalue(&self, key: u64) -> Option<u64> {
+        match self.get(key)?.value {
+            Value::IntValue(v) => Some(v),
+            Value::BytesValue(_) => None,
+        }
+    }
+
+    pub fn get_bytesvalue(&self, key: u64) -> Option<&[u8]> {
+        match &self.get(key)?.value {
+            Value::BytesValue(v) => Some(v),
+            Value::IntValue(_) => None,
+        }
+    }
 }
 
 impl Decode for KeyValuePairs {
     fn decode<R: bytes::Buf>(mut r: &mut R) -> Result<Self, DecodeError> {
         let mut kvps = Vec::new();
 
-        let count = u64::decode(r)?;
+        let count = usize::decode(r)?;
+        if count > Self::MAX_PAIRS {
+            return Err(DecodeError::FieldBoundsExceeded(
+                "KeyValuePairs count".to_string(),
+            ));
+        }
+
         for _ in 0..count {
             let kvp = KeyValuePair::decode(&mut r)?;
             kvps.push(kvp);
@@ -168,6 +201,11 @@ impl Decode for KeyValuePairs {
 
 impl Encode for KeyValuePairs {
     fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
+        if self.0.len() > Self::MAX_PAIRS {
+            return Err(EncodeError::FieldBoundsExceeded(
+                "KeyValuePairs count".to_string(),
+            ));
+        }
         self.0.len().encode(w)?;
 
         for kvp in &self.0 {
@@ -242,6 +280,28 @@ mod tests {
         assert!(matches!(decoded.unwrap_err(), DecodeError::More(_))); // Framing will be off now
     }
 
+    #[test]
+    fn decode_too_many_pairs() {
+        // Rejected from the count alone, instead of waiting for the pairs to arrive.
+        let mut buf = BytesMut::new();
+        (KeyValuePairs::MAX_PAIRS + 1).encode(&mut buf).unwrap();
+        let decoded = KeyValuePairs::decode(&mut buf);
+        assert!(matches!(
+            decoded.unwrap_err(),
+            DecodeError::FieldBoundsExceeded(_)
+        ));
+
+        let kvps = KeyValuePairs(
+            (0..=KeyValuePairs::MAX_PAIRS as u64)
+                .map(|key| KeyValuePair::new_int(key * 2, 0))
+                .collect(),
+        );
+        assert!(matches!(
+            kvps.encode(&mut buf).unwrap_err(),
+            EncodeError::FieldBoundsExceeded(_)
+        ));
+    }
+
     #[test]
     fn encode_decode_keyvaluepairs() {
         let mut buf = BytesMut::new();
@@ -271,4 +331,32 @@ mod tests {
         let decoded = KeyValuePairs::decode(&mut buf).unwrap();
         assert_eq!(decoded, kvps);
     }
+
+    #[test]
+    fn typed_getters() {
+        let mut kvps = KeyValuePairs::new();
+        kvps.set_intvalue(2, 42);
+        kvps.set_bytesvalue(3, vec![0x01, 0x02]);
+
+        assert_eq!(kvps.get_intvalue(2), Some(42));
+        assert_eq!(kvps.get_bytesvalue(3), Some(&[0x01, 0x02][..]));
+
+        // Wrong variant or missing key
+        assert_eq!(kvps.get_intvalue(3), None);
+        assert_eq!(kvps.get_bytesvalue(2), None);
+        assert_eq!(kvps.get_intvalue(4), None);
+    }
+
+    #[test]
+    fn encoded_len() {
+        for kvp in [
+            KeyValuePair::new_int(2, 42),
+            KeyValuePair::new_int(100, 20000),
+            KeyValuePair::new_bytes(3, vec![0x01; 70]),
+        ] {
+            let mut buf = BytesMut::new();
+            kvp.encode(&mut buf).unwrap();
+            assert_eq!(kvp.encoded_len().unwrap(), buf.len());
+        }
+    }
 }
@@ -1,3 +1,4 @@
+mod auth_token;
 mod bounded_string;
 mod decode;
 mod encode;
@@ -6,16 +7,19 @@ mod integer;
 mod kvp;
 mod location;
 mod string;
+mod telemetry;
 mod track_namespace;
 mod tuple;
 mod varint;
 
+pub use auth_token::*;
 pub use bounded_string::*;
 pub use decode::*;
 pub use encode::*;
 pub use hex_dump::*;
 pub use kvp::*;
 pub use location::*;
+pub use telemetry::*;
 pub use track_namespace::*;
 pub use tuple::*;
 pub use varint::*;
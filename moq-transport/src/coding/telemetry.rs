@@ -0,0 +1,138 @@
+use std::{fmt, time::Duration};
+
+/// Playback telemetry sent by a subscriber, in the style of Common Media Client Data (CMCD,
+/// CTA-5004), so publishers and origins get quality of experience data per subscription.
+///
+/// Carried by the non-standard TELEMETRY parameter of SUBSCRIBE and SUBSCRIBE_UPDATE, encoded as
+/// CMCD's comma-separated keys, ex. `bl=2100,pr=1.25,sid="6e2fb550"`. Other keys are ignored.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Telemetry {
+    /// `bl`: how much media is buffered ahead of the playhead, in steps of 100ms.
+    pub buffer_length: Option<Duration>,
+
+    /// `pr`: the playback rate, 1.0 for real time and 0.0 while paused.
+    pub playback_rate: Option<f64>,
+
+    /// `sid`: identifies the playback session, across the tracks and sessions it uses.
+    pub session_id: Option<String>,
+}
+
+impl Telemetry {
+    /// Parse the CMCD keys, ignoring the unknown or malformed ones.
+    pub fn parse(value: &str) -> Self {
+        let mut telemetry = Self::default();
+
+        for pair in split_pairs(value) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            match key.trim() {
+                "bl" => {
+                    telemetry.buffer_length = value.parse().ok().map(Duration::from_millis);
+                }
+                "pr" => {
+                    telemetry.playback_rate = value
+                        .parse()
+                        .ok()
+                        .filter(|rate: &f64| rate.is_finite() && *rate >= 0.0);
+                }
+                "sid" => telemetry.session_id = unquote(value),
+                _ => {}
+            }
+        }
+
+        telemetry
+    }
+
+    /// Whether no keys are set, so there's nothing to send.
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+impl fmt::Display for Telemetry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pairs = Vec::new();
+
+        if let Some(buffer_length) = self.buffer_length {
+            // CMCD rounds the buffer length to the nearest 100ms.
+            let millis = (buffer_length.as_millis() as u64 + 50) / 100 * 100;
+            pairs.push(format!("bl={}", millis));
+        }
+        if let Some(rate) = self.playback_rate {
+            pairs.push(format!("pr={}", rate));
+        }
+        if let Some(session_id) = &self.session_id {
+            let escaped = session_id.replace('\\', "\\\\").replace('"', "\\\"");
+            pairs.push(format!("sid=\"{}\"", escaped));
+        }
+
+        write!(f, "{}", pairs.join(","))
+    }
+}
+
+// Split on the commas outside quoted strings.
+fn split_pairs(value: &str) -> Vec<&str> {
+    let mut pairs = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                pairs.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    pairs.push(&value[start..]);
+
+    pairs
+}
+
+// The contents of a quoted string, with its escapes resolved.
+fn unquote(value: &str) -> Option<String> {
+    let inner = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut unquoted = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            c => unquoted.push(c),
+        }
+    }
+
+    Some(unquoted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let telemetry = Telemetry {
+            buffer_length: Some(Duration::from_millis(2149)),
+            playback_rate: Some(1.25),
+            session_id: Some("a,\"b\"".to_string()),
+        };
+        let encoded = telemetry.to_string();
+        assert_eq!(encoded, r#"bl=2100,pr=1.25,sid="a,\"b\"""#);
+
+        let decoded = Telemetry::parse(&encoded);
+        assert_eq!(decoded.buffer_length, Some(Duration::from_millis(2100)));
+        assert_eq!(decoded.playback_rate, Some(1.25));
+        assert_eq!(decoded.session_id.as_deref(), Some("a,\"b\""));
+    }
+
+    #[test]
+    fn ignores_unknown() {
+        let telemetry = Telemetry::parse("br=3200,bl=abc,pr=-1,su,sid=unquoted");
+        assert!(telemetry.is_empty());
+        assert_eq!(Telemetry::default().to_string(), "");
+    }
+}
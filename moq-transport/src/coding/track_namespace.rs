@@ -51,6 +51,11 @@ impl TrackNamespace {
         }
         path
     }
+
+    /// Whether the fields of `prefix` match the leading fields of this namespace.
+    pub fn has_prefix(&self, prefix: &TrackNamespace) -> bool {
+        self.fields.starts_with(&prefix.fields)
+    }
 }
 
 impl Hash for TrackNamespace {
@@ -240,6 +245,17 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn has_prefix() {
+        let t = TrackNamespace::from_utf8_path("live/room/1");
+        assert!(t.has_prefix(&TrackNamespace::from_utf8_path("live")));
+        assert!(t.has_prefix(&TrackNamespace::from_utf8_path("live/room")));
+        assert!(t.has_prefix(&t));
+        assert!(t.has_prefix(&TrackNamespace::new()));
+        assert!(!t.has_prefix(&TrackNamespace::from_utf8_path("li")));
+        assert!(!t.has_prefix(&TrackNamespace::from_utf8_path("live/room/1/2")));
+    }
+
     #[test]
     fn try_from_str() {
         let ns: TrackNamespace = "test/path/to/resource".try_into().unwrap();
@@ -87,6 +87,10 @@ impl Hash for Tuple {
 impl Decode for Tuple {
     fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
         let count = usize::decode(r)?;
+        if count > Self::MAX_FIELDS {
+            return Err(DecodeError::FieldBoundsExceeded("Tuple fields".to_string()));
+        }
+
         let mut fields = Vec::new();
         for _ in 0..count {
             fields.push(TupleField::decode(r)?);
@@ -106,6 +110,8 @@ impl Encode for Tuple {
 }
 
 impl Tuple {
+    pub const MAX_FIELDS: usize = 32;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -38,6 +38,20 @@ impl VarInt {
     pub const fn into_inner(self) -> u64 {
         self.0
     }
+
+    /// The number of bytes this value takes up when encoded.
+    pub const fn size(self) -> usize {
+        let x = self.0;
+        if x < 2u64.pow(6) {
+            1
+        } else if x < 2u64.pow(14) {
+            2
+        } else if x < 2u64.pow(30) {
+            4
+        } else {
+            8
+        }
+    }
 }
 
 impl From<VarInt> for u64 {
@@ -312,6 +326,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::unnecessary_fallible_conversions)]
     fn encode_decode_varint() {
         let mut buf = BytesMut::new();
 
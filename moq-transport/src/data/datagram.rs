@@ -68,6 +68,12 @@ pub struct Datagram {
     pub payload: Option<bytes::Bytes>,
 }
 
+impl Datagram {
+    /// The largest header of a datagram without extension headers: its type, the track alias,
+    /// group and object IDs as varints of up to 8 bytes, and the priority.
+    pub const MAX_HEADER_SIZE: usize = 26;
+}
+
 impl Decode for Datagram {
     fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
         let datagram_type = DatagramType::decode(r)?;
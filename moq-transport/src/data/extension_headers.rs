@@ -1,6 +1,24 @@
-use crate::coding::{Decode, DecodeError, Encode, EncodeError, KeyValuePair};
+use crate::coding::{Decode, DecodeError, Encode, EncodeError, KeyValuePair, Value};
 use bytes::Buf;
 use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The Immutable Extensions header type (0xB).
+///
+/// Its value is a sequence of key-value pairs that the original publisher attached to the object.
+/// Relays must forward the contents verbatim and must not add, remove, or modify them.
+pub const IMMUTABLE_EXTENSIONS: u64 = 0xB;
+
+/// The Capture Timestamp header type (0x2), as defined by LOC.
+///
+/// Its value is the wall clock time the publisher captured the object at, in microseconds since
+/// the Unix epoch.
+pub const CAPTURE_TIMESTAMP: u64 = 0x2;
+
+/// The Prior Group ID Gap header type (0x3C).
+///
+/// Attached to the first object of a group when the publisher intentionally skipped groups before it.
+pub const PRIOR_GROUP_ID_GAP: u64 = 0x3C;
 
 /// A collection of KeyValuePair entries, where the length in bytes of key-value-pairs are encoded/decoded first.
 /// This structure is appropriate for Data plane extension headers.
@@ -10,6 +28,9 @@ pub struct ExtensionHeaders(pub Vec<KeyValuePair>);
 
 // TODO: These set/get API's all assume no duplicate keys. We can add API's to support duplicates if needed.
 impl ExtensionHeaders {
+    /// The most bytes of extension headers accepted on a single object.
+    pub const MAX_LENGTH: usize = u16::MAX as usize;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -23,12 +44,29 @@ impl ExtensionHeaders {
         }
     }
 
+    /// Insert or replace a KeyValuePair with the same key, unless it would rewrite Immutable Extensions.
+    ///
+    /// Immutable Extensions may be added by the original publisher, but once present they can't
+    /// be replaced, so a relay can't rewrite them on the forwarding path.
+    pub fn try_set(&mut self, kvp: KeyValuePair) -> Result<(), EncodeError> {
+        if kvp.key == IMMUTABLE_EXTENSIONS {
+            if let Some(existing) = self.get(IMMUTABLE_EXTENSIONS) {
+                if *existing != kvp {
+                    return Err(EncodeError::ImmutableExtensions);
+                }
+            }
+        }
+
+        self.set(kvp);
+        Ok(())
+    }
+
     pub fn set_intvalue(&mut self, key: u64, value: u64) {
-        self.set(KeyValuePair::new_int(key, value));
+        self.set(KeyValuePair::new_int(key, value))
     }
 
     pub fn set_bytesvalue(&mut self, key: u64, value: Vec<u8>) {
-        self.set(KeyValuePair::new_bytes(key, value));
+        self.set(KeyValuePair::new_bytes(key, value))
     }
 
     pub fn has(&self, key: u64) -> bool {
@@ -42,6 +80,114 @@ impl ExtensionHeaders {
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
     }
+
+    /// Iterate over every extension header, in wire order.
+    pub fn iter(&self) -> impl Iterator<Item = &KeyValuePair> {
+        self.0.iter()
+    }
+
+    /// Iterate over the extension headers without a typed accessor, ex. application-defined ones.
+    pub fn unknown(&self) -> impl Iterator<Item = &KeyValuePair> {
+        self.0.iter().filter(|k| {
+            k.key != IMMUTABLE_EXTENSIONS
+                && k.key != PRIOR_GROUP_ID_GAP
+                && k.key != CAPTURE_TIMESTAMP
+        })
+    }
+
+    /// The raw contents of the Immutable Extensions header, if present.
+    pub fn immutable_extensions(&self) -> Option<&[u8]> {
+        match &self.get(IMMUTABLE_EXTENSIONS)?.value {
+            Value::BytesValue(bytes) => Some(bytes),
+            Value::IntValue(_) => None,
+        }
+    }
+
+    /// Decode the key-value pairs carried in the Immutable Extensions header.
+    /// Returns an empty list if the header isn't present.
+    pub fn immutable(&self) -> Result<Vec<KeyValuePair>, DecodeError> {
+        let mut contents = match self.immutable_extensions() {
+            Some(contents) => contents,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut kvps = Vec::new();
+        while contents.has_remaining() {
+            kvps.push(KeyValuePair::decode(&mut contents)?);
+        }
+
+        Ok(kvps)
+    }
+
+    /// Set the Immutable Extensions header to the given key-value pairs.
+    /// Fails if the header is already present with different contents.
+    pub fn set_immutable(&mut self, extensions: &[KeyValuePair]) -> Result<(), EncodeError> {
+        let mut contents = bytes::BytesMut::new();
+        for kvp in extensions {
+            if kvp.key == IMMUTABLE_EXTENSIONS {
+                return Err(EncodeError::InvalidValue);
+            }
+            kvp.encode(&mut contents)?;
+        }
+
+        self.try_set(KeyValuePair::new_bytes(
+            IMMUTABLE_EXTENSIONS,
+            contents.to_vec(),
+        ))
+    }
+
+    /// The number of groups intentionally skipped before this object's group, if signalled.
+    pub fn prior_group_id_gap(&self) -> Option<u64> {
+        match self.get(PRIOR_GROUP_ID_GAP)?.value {
+            Value::IntValue(gap) => Some(gap),
+            Value::BytesValue(_) => None,
+        }
+    }
+
+    pub fn set_prior_group_id_gap(&mut self, gap: u64) {
+        self.set_intvalue(PRIOR_GROUP_ID_GAP, gap)
+    }
+
+    /// When the publisher captured the object by its own clock, if signalled.
+    pub fn capture_timestamp(&self) -> Option<SystemTime> {
+        match self.get(CAPTURE_TIMESTAMP)?.value {
+            Value::IntValue(micros) => UNIX_EPOCH.checked_add(Duration::from_micros(micros)),
+            Value::BytesValue(_) => None,
+        }
+    }
+
+    pub fn set_capture_timestamp(&mut self, captured: SystemTime) -> Result<(), EncodeError> {
+        let micros = captured
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| EncodeError::InvalidValue)?
+            .as_micros();
+        self.set_intvalue(CAPTURE_TIMESTAMP, micros as u64);
+        Ok(())
+    }
+
+    // Immutable Extensions may appear at most once, and may not nest.
+    fn validate_immutable(&self) -> Result<(), DecodeError> {
+        let mut immutable = self.0.iter().filter(|k| k.key == IMMUTABLE_EXTENSIONS);
+        if immutable.next().is_none() {
+            return Ok(());
+        }
+        if immutable.next().is_some() {
+            return Err(DecodeError::DuplicateParameter(IMMUTABLE_EXTENSIONS));
+        }
+
+        if self.immutable_extensions().is_none() {
+            return Err(DecodeError::InvalidParameter);
+        }
+        if self
+            .immutable()?
+            .iter()
+            .any(|k| k.key == IMMUTABLE_EXTENSIONS)
+        {
+            return Err(DecodeError::InvalidParameter);
+        }
+
+        Ok(())
+    }
 }
 
 impl Decode for ExtensionHeaders {
@@ -50,6 +196,11 @@ impl Decode for ExtensionHeaders {
         // Note: this is the difference between KeyValuePairs and ExtensionHeaders.
         // KeyValuePairs encodes the count of kvps, whereas ExtensionHeaders encodes the total byte length.
         let length = usize::decode(r)?;
+        if length > Self::MAX_LENGTH {
+            return Err(DecodeError::FieldBoundsExceeded(
+                "ExtensionHeaders length".to_string(),
+            ));
+        }
 
         // Ensure we have that many bytes available in the input
         Self::decode_remaining(r, length)?;
@@ -59,10 +210,8 @@ impl Decode for ExtensionHeaders {
             return Ok(ExtensionHeaders::new());
         }
 
-        // Copy the exact slice that contains the encoded kvps and decode from it
-        let mut buf = vec![0u8; length];
-        r.copy_to_slice(&mut buf);
-        let mut kvps_bytes = bytes::Bytes::from(buf);
+        // Decode in place from the exact slice that contains the encoded kvps, without copying it first
+        let mut kvps_bytes = (&mut *r).take(length);
 
         let mut kvps = Vec::new();
         while kvps_bytes.has_remaining() {
@@ -70,21 +219,31 @@ impl Decode for ExtensionHeaders {
             kvps.push(kvp);
         }
 
-        Ok(ExtensionHeaders(kvps))
+        let headers = ExtensionHeaders(kvps);
+        headers.validate_immutable()?;
+
+        Ok(headers)
     }
 }
 
 impl Encode for ExtensionHeaders {
     fn encode<W: bytes::BufMut>(&self, w: &mut W) -> Result<(), EncodeError> {
-        // Encode all KeyValuePair entries into a temporary buffer to compute total byte length
-        let mut tmp = bytes::BytesMut::new();
+        // Compute the total byte length up front, so the entries can be written without a temporary buffer
+        let mut length = 0;
         for kvp in &self.0 {
-            kvp.encode(&mut tmp)?;
+            length += kvp.encoded_len()?;
+        }
+        if length > Self::MAX_LENGTH {
+            return Err(EncodeError::FieldBoundsExceeded(
+                "ExtensionHeaders length".to_string(),
+            ));
         }
 
-        // Write total byte length (u64) followed by the encoded bytes
-        (tmp.len() as u64).encode(w)?;
-        w.put_slice(&tmp);
+        // Write total byte length followed by the encoded entries
+        length.encode(w)?;
+        for kvp in &self.0 {
+            kvp.encode(w)?;
+        }
 
         Ok(())
     }
@@ -108,6 +267,17 @@ mod tests {
     use super::*;
     use bytes::BytesMut;
 
+    #[test]
+    fn decode_too_long() {
+        let mut buf = BytesMut::new();
+        (ExtensionHeaders::MAX_LENGTH + 1).encode(&mut buf).unwrap();
+        let decoded = ExtensionHeaders::decode(&mut buf);
+        assert!(matches!(
+            decoded.unwrap_err(),
+            DecodeError::FieldBoundsExceeded(_)
+        ));
+    }
+
     #[test]
     fn encode_decode_extension_headers() {
         let mut buf = BytesMut::new();
@@ -137,4 +307,96 @@ mod tests {
         let decoded = ExtensionHeaders::decode(&mut buf).unwrap();
         assert_eq!(decoded, ext_hdrs);
     }
+
+    fn immutable(contents: &[KeyValuePair]) -> KeyValuePair {
+        let mut buf = BytesMut::new();
+        for kvp in contents {
+            kvp.encode(&mut buf).unwrap();
+        }
+        KeyValuePair::new_bytes(IMMUTABLE_EXTENSIONS, buf.to_vec())
+    }
+
+    #[test]
+    fn immutable_extensions_roundtrip() {
+        let mut ext_hdrs = ExtensionHeaders::new();
+        ext_hdrs.set(immutable(&[KeyValuePair::new_int(2, 7)]));
+        ext_hdrs.set_intvalue(4, 1);
+
+        let mut buf = BytesMut::new();
+        ext_hdrs.encode(&mut buf).unwrap();
+        let decoded = ExtensionHeaders::decode(&mut buf).unwrap();
+        assert_eq!(
+            decoded.immutable_extensions(),
+            ext_hdrs.immutable_extensions()
+        );
+
+        // Other headers can still change, but the immutable ones can't be replaced.
+        let mut forwarded = decoded.clone();
+        forwarded.set_intvalue(4, 2);
+        forwarded
+            .try_set(immutable(&[KeyValuePair::new_int(2, 7)]))
+            .unwrap();
+        assert!(matches!(
+            forwarded.try_set(immutable(&[KeyValuePair::new_int(2, 8)])),
+            Err(EncodeError::ImmutableExtensions)
+        ));
+        assert_eq!(
+            forwarded.immutable_extensions(),
+            ext_hdrs.immutable_extensions()
+        );
+    }
+
+    #[test]
+    fn immutable_extensions_malformed() {
+        // Duplicate Immutable Extensions
+        let ext_hdrs = ExtensionHeaders(vec![immutable(&[]), immutable(&[])]);
+        let mut buf = BytesMut::new();
+        ext_hdrs.encode(&mut buf).unwrap();
+        assert!(matches!(
+            ExtensionHeaders::decode(&mut buf),
+            Err(DecodeError::DuplicateParameter(IMMUTABLE_EXTENSIONS))
+        ));
+
+        // Nested Immutable Extensions
+        let ext_hdrs = ExtensionHeaders(vec![immutable(&[immutable(&[])])]);
+        let mut buf = BytesMut::new();
+        ext_hdrs.encode(&mut buf).unwrap();
+        assert!(matches!(
+            ExtensionHeaders::decode(&mut buf),
+            Err(DecodeError::InvalidParameter)
+        ));
+    }
+
+    #[test]
+    fn typed_extensions() {
+        let mut ext_hdrs = ExtensionHeaders::new();
+        ext_hdrs
+            .set_immutable(&[KeyValuePair::new_bytes(1, b"sig".to_vec())])
+            .unwrap();
+        ext_hdrs.set_prior_group_id_gap(3);
+        let captured = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        ext_hdrs.set_capture_timestamp(captured).unwrap();
+        ext_hdrs.set_intvalue(100, 1);
+
+        let mut buf = BytesMut::new();
+        ext_hdrs.encode(&mut buf).unwrap();
+        let decoded = ExtensionHeaders::decode(&mut buf).unwrap();
+
+        assert_eq!(
+            decoded.immutable().unwrap(),
+            vec![KeyValuePair::new_bytes(1, b"sig".to_vec())]
+        );
+        assert_eq!(decoded.prior_group_id_gap(), Some(3));
+        assert_eq!(decoded.capture_timestamp(), Some(captured));
+        assert_eq!(
+            decoded.unknown().collect::<Vec<_>>(),
+            vec![&KeyValuePair::new_int(100, 1)]
+        );
+        assert_eq!(decoded.iter().count(), 4);
+
+        // Immutable Extensions can't nest.
+        assert!(ExtensionHeaders::new()
+            .set_immutable(&[immutable(&[])])
+            .is_err());
+    }
 }
@@ -1,5 +1,5 @@
-use crate::coding::{Decode, DecodeError, Encode, EncodeError, KeyValuePairs};
-use crate::data::{ObjectStatus, StreamHeaderType};
+use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+use crate::data::{ExtensionHeaders, ObjectStatus, StreamHeaderType};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct FetchHeader {
@@ -49,7 +49,7 @@ pub struct FetchObject {
     /// Publisher priority, where **smaller** values are sent first.
     pub publisher_priority: u8,
 
-    pub extension_headers: KeyValuePairs,
+    pub extension_headers: ExtensionHeaders,
 
     pub payload_length: usize,
 
@@ -63,7 +63,7 @@ impl Decode for FetchObject {
         let subgroup_id = u64::decode(r)?;
         let object_id = u64::decode(r)?;
         let publisher_priority = u8::decode(r)?;
-        let extension_headers = KeyValuePairs::decode(r)?;
+        let extension_headers = ExtensionHeaders::decode(r)?;
         let payload_length = usize::decode(r)?;
         let status = match payload_length {
             0 => Some(ObjectStatus::decode(r)?),
@@ -108,4 +108,31 @@ impl Encode for FetchObject {
     }
 }
 
-// TODO SLG - add unit tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::BytesMut;
+
+    #[test]
+    fn encode_decode_object() {
+        let mut buf = BytesMut::new();
+
+        // Extension headers are length-prefixed, as in subgroup objects.
+        let mut ext_hdrs = ExtensionHeaders::new();
+        ext_hdrs.set_bytesvalue(123, vec![0x00, 0x01]);
+
+        let msg = FetchObject {
+            group_id: 4,
+            subgroup_id: 1,
+            object_id: 2,
+            publisher_priority: 127,
+            extension_headers: ext_hdrs,
+            payload_length: 0,
+            status: Some(ObjectStatus::EndOfGroup),
+        };
+        msg.encode(&mut buf).unwrap();
+        assert_eq!(buf[4], 5);
+        let decoded = FetchObject::decode(&mut buf).unwrap();
+        assert_eq!(decoded, msg);
+    }
+}
@@ -4,6 +4,10 @@
 //! While originally designed for live media, MoQ Transport is generic and can be used for other live applications.
 //! The specification is a work in progress and will change.
 //! See the [specification](https://datatracker.ietf.org/doc/draft-ietf-moq-transport/) and [github](https://github.com/moq-wg/moq-transport) for any updates.
+//!
+//! [session::Session::run] drives the QUIC connection and needs a tokio runtime, but the handles it
+//! feeds don't: announces, subscriptions and objects can also be polled with `poll_*` methods, ex.
+//! [serve::SubgroupReader::poll_next], from another event loop or across an FFI boundary.
 pub mod coding;
 pub mod data;
 pub mod error;
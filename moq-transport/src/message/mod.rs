@@ -14,6 +14,7 @@ mod filter_type;
 mod go_away;
 mod group_order;
 mod max_request_id;
+mod param_types;
 mod pubilsh_namespace_done;
 mod publish;
 mod publish_done;
@@ -48,6 +49,7 @@ pub use filter_type::*;
 pub use go_away::*;
 pub use group_order::*;
 pub use max_request_id::*;
+pub use param_types::*;
 pub use pubilsh_namespace_done::*;
 pub use publish::*;
 pub use publish_done::*;
@@ -73,9 +75,34 @@ pub use track_status_ok::*;
 pub use unsubscribe::*;
 pub use unsubscribe_namespace::*;
 
-use crate::coding::{Decode, DecodeError, Encode, EncodeError};
+use crate::coding::{decode_exact, Decode, DecodeError, Encode, EncodeError};
+use crate::setup;
 use std::fmt;
 
+/// Any message sent on the control stream, as decoded by [decode_any].
+#[derive(Debug)]
+pub enum ControlMessage {
+    ClientSetup(setup::Client),
+    ServerSetup(setup::Server),
+    Message(Message),
+}
+
+/// Decode whichever control message comes next, including the setup messages.
+///
+/// Lengths on the wire are checked against the input before anything is allocated, so this
+/// accepts arbitrary bytes without panicking, ex. from a fuzzer. See the `fuzz` directory.
+pub fn decode_any<B: bytes::Buf>(r: &mut B) -> Result<ControlMessage, DecodeError> {
+    let t = u64::decode(r)?;
+    let len = u16::decode(r)?;
+
+    decode_exact(r, len.into(), |payload| match t {
+        // CLIENT_SETUP and SERVER_SETUP for draft versions 11 and later
+        0x20 => setup::Client::decode_payload(payload).map(ControlMessage::ClientSetup),
+        0x21 => setup::Server::decode_payload(payload).map(ControlMessage::ServerSetup),
+        t => Message::decode_payload(t, payload).map(ControlMessage::Message),
+    })
+}
+
 // Use a macro to generate the message types rather than copy-paste.
 // This implements a decode/encode method that uses the specified type.
 macro_rules! message_types {
@@ -89,17 +116,8 @@ macro_rules! message_types {
 		impl Decode for Message {
 			fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
 				let t = u64::decode(r)?;
-				let _len = u16::decode(r)?;
-
-				// TODO: Check the length of the message.
-
-				match t {
-					$($val => {
-						let msg = $name::decode(r)?;
-						Ok(Self::$name(msg))
-					})*
-					_ => Err(DecodeError::InvalidMessage(t)),
-				}
+				let len = u16::decode(r)?;
+				decode_exact(r, len.into(), |payload| Self::decode_payload(t, payload))
 			}
 		}
 
@@ -132,6 +150,17 @@ macro_rules! message_types {
 		}
 
 		impl Message {
+			// Decode the message of type `t`, after its type and length.
+			fn decode_payload<R: bytes::Buf>(t: u64, r: &mut R) -> Result<Self, DecodeError> {
+				match t {
+					$($val => {
+						let msg = $name::decode(r)?;
+						Ok(Self::$name(msg))
+					})*
+					_ => Err(DecodeError::InvalidMessage(t)),
+				}
+			}
+
 			pub fn id(&self) -> u64 {
 				match self {
 					$(Self::$name(_) => {
@@ -222,3 +251,65 @@ message_types! {
     PublishOk = 0x1e,
     PublishError = 0x1f,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::{KeyValuePairs, ReasonPhrase};
+    use bytes::BytesMut;
+
+    #[test]
+    fn decode_any_messages() {
+        let mut buf = BytesMut::new();
+        setup::Client {
+            versions: [setup::Version::DRAFT_14].into(),
+            params: KeyValuePairs::new(),
+        }
+        .encode(&mut buf)
+        .unwrap();
+        Message::from(Unsubscribe { id: 7 })
+            .encode(&mut buf)
+            .unwrap();
+
+        assert!(matches!(
+            decode_any(&mut buf).unwrap(),
+            ControlMessage::ClientSetup(client) if client.versions.contains(&setup::Version::DRAFT_14)
+        ));
+        assert!(matches!(
+            decode_any(&mut buf).unwrap(),
+            ControlMessage::Message(Message::Unsubscribe(Unsubscribe { id: 7 }))
+        ));
+        assert!(matches!(
+            decode_any(&mut buf).unwrap_err(),
+            DecodeError::More(_)
+        ));
+    }
+
+    #[test]
+    fn decode_framed_length() {
+        let msg = Message::from(SubscribeError {
+            id: 1,
+            error_code: 0,
+            reason_phrase: ReasonPhrase("nope".to_string()),
+        });
+        let mut encoded = BytesMut::new();
+        msg.encode(&mut encoded).unwrap();
+
+        // A length covering more than the message leaves trailing bytes, which is invalid.
+        let mut buf = encoded.clone();
+        buf[2] += 1;
+        buf.extend_from_slice(&[0]);
+        assert!(matches!(
+            Message::decode(&mut buf).unwrap_err(),
+            DecodeError::InvalidLength(..)
+        ));
+
+        // A length covering less than the message can't be completed by reading more.
+        let mut buf = encoded.clone();
+        buf[2] -= 1;
+        assert!(matches!(
+            Message::decode(&mut buf).unwrap_err(),
+            DecodeError::InvalidLength(..)
+        ));
+    }
+}
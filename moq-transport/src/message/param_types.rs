@@ -0,0 +1,41 @@
+/// Version Specific Parameter Types, carried in control messages such as SUBSCRIBE
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(u64)]
+pub enum ParameterType {
+    DeliveryTimeout = 0x2,
+    AuthorizationToken = 0x3,
+    MaxCacheDuration = 0x4,
+
+    /// Non-standard: opaque trace ID used to correlate a subscription across relay hops.
+    TraceId = 0x4D51,
+
+    /// Non-standard: start at the first object of the latest group if it is still cached,
+    /// otherwise at the next group. Sent alongside the NextGroupStart filter, which peers that
+    /// don't understand it fall back to.
+    GroupStart = 0x4D52,
+
+    /// Non-standard: sent in SUBSCRIBE_UPDATE to stop delivering groups older than the latest
+    /// one, so a subscriber that fell behind jumps to the live edge. Peers that don't understand
+    /// it ignore it.
+    SkipToLatest = 0x4D54,
+
+    /// Non-standard: pace delivery of the subscription to at most this many bits per second,
+    /// ex. the subscriber's link capacity. Peers that don't understand it send at full speed.
+    MaxBitrate = 0x4D56,
+
+    /// Non-standard: the range of groups the subscriber already has, ex. cached by a relay from an
+    /// earlier session, as the first and last group ID. The publisher doesn't send them again.
+    /// Peers that don't understand it send them anyway.
+    CachedGroups = 0x4D57,
+
+    /// Non-standard: the subscriber's playback telemetry as CMCD keys, see [crate::coding::Telemetry].
+    /// Sent in SUBSCRIBE, and again in SUBSCRIBE_UPDATE as it changes. Peers that don't understand
+    /// it ignore it.
+    Telemetry = 0x4D59,
+}
+
+impl From<ParameterType> for u64 {
+    fn from(value: ParameterType) -> Self {
+        value as u64
+    }
+}
@@ -2,7 +2,59 @@ use crate::coding::{Decode, DecodeError, Encode, EncodeError, ReasonPhrase};
 
 // TODO SLG - The next draft is going to merge all these error messages to a
 //            common RequestError message, so we won't do a lot of work on these
-//            existing messages.  We should add an enum for all the various error codes.
+//            existing messages.
+
+/// Error codes for PUBLISH_NAMESPACE_ERROR, from draft-ietf-moq-transport-14 Section 13.1.8.
+///
+/// The draft has no codes for a duplicate namespace or an exceeded limit, so those use
+/// values from the range after the registry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublishNamespaceErrorCode {
+    InternalError,
+    Unauthorized,
+    Timeout,
+    NotSupported,
+    Uninterested,
+    Duplicate,
+    LimitExceeded,
+    MalformedAuthToken,
+    UnknownAuthTokenAlias,
+    ExpiredAuthToken,
+}
+
+impl PublishNamespaceErrorCode {
+    pub fn code(&self) -> u64 {
+        match self {
+            Self::InternalError => 0x0,
+            Self::Unauthorized => 0x1,
+            Self::Timeout => 0x2,
+            Self::NotSupported => 0x3,
+            Self::Uninterested => 0x4,
+            Self::Duplicate => 0x5,
+            Self::LimitExceeded => 0x6,
+            Self::MalformedAuthToken => 0x10,
+            Self::UnknownAuthTokenAlias => 0x11,
+            Self::ExpiredAuthToken => 0x12,
+        }
+    }
+
+    /// The known code with this value, if any.
+    pub fn from_code(code: u64) -> Option<Self> {
+        Some(match code {
+            0x0 => Self::InternalError,
+            0x1 => Self::Unauthorized,
+            0x2 => Self::Timeout,
+            0x3 => Self::NotSupported,
+            0x4 => Self::Uninterested,
+            0x5 => Self::Duplicate,
+            0x6 => Self::LimitExceeded,
+            0x10 => Self::MalformedAuthToken,
+            0x11 => Self::UnknownAuthTokenAlias,
+            0x12 => Self::ExpiredAuthToken,
+            _ => return None,
+        })
+    }
+}
 
 /// Sent by the subscriber to reject an PUBLISH_NAMESPACE.
 #[derive(Clone, Debug)]
@@ -39,3 +91,22 @@ impl Encode for PublishNamespaceError {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_codes() {
+        for code in 0..0x20 {
+            if let Some(known) = PublishNamespaceErrorCode::from_code(code) {
+                assert_eq!(known.code(), code);
+            }
+        }
+        assert_eq!(
+            PublishNamespaceErrorCode::from_code(0x6),
+            Some(PublishNamespaceErrorCode::LimitExceeded)
+        );
+        assert_eq!(PublishNamespaceErrorCode::from_code(0x7), None);
+    }
+}
@@ -1,14 +1,3 @@
-// TODO: Unimplemented control message events (not yet needed for basic relay interop testing):
-// - SubscribeUpdate (parsed/created)
-// - PublishNamespaceDone (parsed/created)
-// - PublishNamespaceCancel (parsed/created)
-// - TrackStatus, TrackStatusOk, TrackStatusError (parsed/created)
-// - SubscribeNamespace, SubscribeNamespaceOk, SubscribeNamespaceError, UnsubscribeNamespace (parsed/created)
-// - Fetch, FetchOk, FetchError, FetchCancel (parsed/created)
-// - Publish, PublishOk, PublishError, PublishDone (parsed/created)
-// - MaxRequestId (parsed/created)
-// - RequestsBlocked (parsed/created)
-//
 // TODO: Unimplemented data plane events (from draft-pardue-moq-qlog-moq-events):
 // - stream_type_set (when stream type becomes known)
 // - object_datagram_status_created/parsed
@@ -205,20 +194,54 @@ fn create_control_message_event(
     }
 }
 
+/// Helper to convert CLIENT_SETUP message to JSON
+fn client_setup_to_json(msg: &setup::Client) -> JsonValue {
+    let versions: Vec<String> = msg.versions.0.iter().map(|v| format!("{:?}", v)).collect();
+    json!({
+        "number_of_supported_versions": msg.versions.0.len(),
+        "supported_versions": versions,
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    })
+}
+
 /// Create a control_message_parsed event for CLIENT_SETUP
 pub fn client_setup_parsed(time: f64, stream_id: u64, msg: &setup::Client) -> Event {
-    let versions: Vec<String> = msg.versions.0.iter().map(|v| format!("{:?}", v)).collect();
     create_control_message_event(
         time,
         stream_id,
         true,
         "client_setup",
-        json!(
-        {
-            "number_of_supported_versions": msg.versions.0.len(),
-            "supported_versions": versions,
-            "parameters": key_value_pairs_to_vec(&msg.params.0),
-        }),
+        client_setup_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for CLIENT_SETUP
+pub fn client_setup_created(time: f64, stream_id: u64, msg: &setup::Client) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "client_setup",
+        client_setup_to_json(msg),
+    )
+}
+
+/// Helper to convert SERVER_SETUP message to JSON
+fn server_setup_to_json(msg: &setup::Server) -> JsonValue {
+    json!({
+        "selected_version": format!("{:?}", msg.version),
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    })
+}
+
+/// Create a control_message_parsed event for SERVER_SETUP
+pub fn server_setup_parsed(time: f64, stream_id: u64, msg: &setup::Server) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "server_setup",
+        server_setup_to_json(msg),
     )
 }
 
@@ -229,11 +252,7 @@ pub fn server_setup_created(time: f64, stream_id: u64, msg: &setup::Server) -> E
         stream_id,
         false,
         "server_setup",
-        json!(
-        {
-            "selected_version": format!("{:?}", msg.version),
-            "parameters": key_value_pairs_to_vec(&msg.params.0),
-        }),
+        server_setup_to_json(msg),
     )
 }
 
@@ -257,6 +276,12 @@ fn subscribe_to_json(msg: &message::Subscribe) -> JsonValue {
     if let Some(end_group) = msg.end_group_id {
         json["end_group"] = json!(end_group);
     }
+    if let Some(trace_id) = msg
+        .params
+        .get_bytesvalue(message::ParameterType::TraceId.into())
+    {
+        json["trace_id"] = json!(String::from_utf8_lossy(trace_id));
+    }
 
     json
 }
@@ -513,6 +538,713 @@ pub fn go_away_created(time: f64, stream_id: u64, msg: &message::GoAway) -> Even
     )
 }
 
+/// Helper to convert SUBSCRIBE_UPDATE message to JSON
+fn subscribe_update_to_json(msg: &message::SubscribeUpdate) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "subscription_request_id": msg.subscription_request_id,
+        "start_group": msg.start_location.group_id,
+        "start_object": msg.start_location.object_id,
+        "end_group": msg.end_group_id,
+        "subscriber_priority": msg.subscriber_priority,
+        "forward": msg.forward,
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    })
+}
+
+/// Create a control_message_parsed event for SUBSCRIBE_UPDATE
+pub fn subscribe_update_parsed(time: f64, stream_id: u64, msg: &message::SubscribeUpdate) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "subscribe_update",
+        subscribe_update_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for SUBSCRIBE_UPDATE
+pub fn subscribe_update_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeUpdate,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "subscribe_update",
+        subscribe_update_to_json(msg),
+    )
+}
+
+/// Helper to convert PUBLISH_NAMESPACE_DONE message to JSON
+fn publish_namespace_done_to_json(msg: &message::PublishNamespaceDone) -> JsonValue {
+    json!({
+        "track_namespace": msg.track_namespace.to_string(),
+    })
+}
+
+/// Create a control_message_parsed event for PUBLISH_NAMESPACE_DONE
+pub fn publish_namespace_done_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::PublishNamespaceDone,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "publish_namespace_done",
+        publish_namespace_done_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for PUBLISH_NAMESPACE_DONE
+pub fn publish_namespace_done_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::PublishNamespaceDone,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "publish_namespace_done",
+        publish_namespace_done_to_json(msg),
+    )
+}
+
+/// Helper to convert PUBLISH_NAMESPACE_CANCEL message to JSON
+fn publish_namespace_cancel_to_json(msg: &message::PublishNamespaceCancel) -> JsonValue {
+    json!({
+        "track_namespace": msg.track_namespace.to_string(),
+        "error_code": msg.error_code,
+        "reason_phrase": &msg.reason_phrase.0,
+    })
+}
+
+/// Create a control_message_parsed event for PUBLISH_NAMESPACE_CANCEL
+pub fn publish_namespace_cancel_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::PublishNamespaceCancel,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "publish_namespace_cancel",
+        publish_namespace_cancel_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for PUBLISH_NAMESPACE_CANCEL
+pub fn publish_namespace_cancel_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::PublishNamespaceCancel,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "publish_namespace_cancel",
+        publish_namespace_cancel_to_json(msg),
+    )
+}
+
+/// Helper to convert TRACK_STATUS message to JSON
+fn track_status_to_json(msg: &message::TrackStatus) -> JsonValue {
+    let mut json = json!({
+        "request_id": msg.id,
+        "track_namespace": msg.track_namespace.to_string(),
+        "track_name": &msg.track_name,
+        "subscriber_priority": msg.subscriber_priority,
+        "group_order": format!("{:?}", msg.group_order),
+        "forward": msg.forward,
+        "filter_type": format!("{:?}", msg.filter_type),
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    });
+
+    // Add optional fields based on filter type
+    if let Some(start_loc) = &msg.start_location {
+        json["start_group"] = json!(start_loc.group_id);
+        json["start_object"] = json!(start_loc.object_id);
+    }
+    if let Some(end_group) = msg.end_group_id {
+        json["end_group"] = json!(end_group);
+    }
+
+    json
+}
+
+/// Create a control_message_parsed event for TRACK_STATUS
+pub fn track_status_parsed(time: f64, stream_id: u64, msg: &message::TrackStatus) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "track_status",
+        track_status_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for TRACK_STATUS
+pub fn track_status_created(time: f64, stream_id: u64, msg: &message::TrackStatus) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "track_status",
+        track_status_to_json(msg),
+    )
+}
+
+/// Helper to convert TRACK_STATUS_OK message to JSON
+fn track_status_ok_to_json(msg: &message::TrackStatusOk) -> JsonValue {
+    let mut json = json!({
+        "request_id": msg.id,
+        "track_alias": msg.track_alias,
+        "expires": msg.expires,
+        "group_order": format!("{:?}", msg.group_order),
+        "content_exists": msg.content_exists,
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    });
+
+    if let Some(largest) = &msg.largest_location {
+        json["largest_group_id"] = json!(largest.group_id);
+        json["largest_object_id"] = json!(largest.object_id);
+    }
+
+    json
+}
+
+/// Create a control_message_parsed event for TRACK_STATUS_OK
+pub fn track_status_ok_parsed(time: f64, stream_id: u64, msg: &message::TrackStatusOk) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "track_status_ok",
+        track_status_ok_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for TRACK_STATUS_OK
+pub fn track_status_ok_created(time: f64, stream_id: u64, msg: &message::TrackStatusOk) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "track_status_ok",
+        track_status_ok_to_json(msg),
+    )
+}
+
+/// Helper to convert TRACK_STATUS_ERROR message to JSON
+fn track_status_error_to_json(msg: &message::TrackStatusError) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "error_code": msg.error_code,
+        "reason_phrase": &msg.reason_phrase.0,
+    })
+}
+
+/// Create a control_message_parsed event for TRACK_STATUS_ERROR
+pub fn track_status_error_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::TrackStatusError,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "track_status_error",
+        track_status_error_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for TRACK_STATUS_ERROR
+pub fn track_status_error_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::TrackStatusError,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "track_status_error",
+        track_status_error_to_json(msg),
+    )
+}
+
+/// Helper to convert SUBSCRIBE_NAMESPACE message to JSON
+fn subscribe_namespace_to_json(msg: &message::SubscribeNamespace) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "track_namespace_prefix": msg.track_namespace_prefix.to_string(),
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    })
+}
+
+/// Create a control_message_parsed event for SUBSCRIBE_NAMESPACE
+pub fn subscribe_namespace_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespace,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "subscribe_namespace",
+        subscribe_namespace_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for SUBSCRIBE_NAMESPACE
+pub fn subscribe_namespace_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespace,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "subscribe_namespace",
+        subscribe_namespace_to_json(msg),
+    )
+}
+
+/// Helper to convert SUBSCRIBE_NAMESPACE_OK message to JSON
+fn subscribe_namespace_ok_to_json(msg: &message::SubscribeNamespaceOk) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+    })
+}
+
+/// Create a control_message_parsed event for SUBSCRIBE_NAMESPACE_OK
+pub fn subscribe_namespace_ok_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespaceOk,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "subscribe_namespace_ok",
+        subscribe_namespace_ok_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for SUBSCRIBE_NAMESPACE_OK
+pub fn subscribe_namespace_ok_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespaceOk,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "subscribe_namespace_ok",
+        subscribe_namespace_ok_to_json(msg),
+    )
+}
+
+/// Helper to convert SUBSCRIBE_NAMESPACE_ERROR message to JSON
+fn subscribe_namespace_error_to_json(msg: &message::SubscribeNamespaceError) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "error_code": msg.error_code,
+        "reason_phrase": &msg.reason_phrase.0,
+    })
+}
+
+/// Create a control_message_parsed event for SUBSCRIBE_NAMESPACE_ERROR
+pub fn subscribe_namespace_error_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespaceError,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "subscribe_namespace_error",
+        subscribe_namespace_error_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for SUBSCRIBE_NAMESPACE_ERROR
+pub fn subscribe_namespace_error_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::SubscribeNamespaceError,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "subscribe_namespace_error",
+        subscribe_namespace_error_to_json(msg),
+    )
+}
+
+/// Helper to convert UNSUBSCRIBE_NAMESPACE message to JSON
+fn unsubscribe_namespace_to_json(msg: &message::UnsubscribeNamespace) -> JsonValue {
+    json!({
+        "track_namespace_prefix": msg.track_namespace_prefix.to_string(),
+    })
+}
+
+/// Create a control_message_parsed event for UNSUBSCRIBE_NAMESPACE
+pub fn unsubscribe_namespace_parsed(
+    time: f64,
+    stream_id: u64,
+    msg: &message::UnsubscribeNamespace,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "unsubscribe_namespace",
+        unsubscribe_namespace_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for UNSUBSCRIBE_NAMESPACE
+pub fn unsubscribe_namespace_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::UnsubscribeNamespace,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "unsubscribe_namespace",
+        unsubscribe_namespace_to_json(msg),
+    )
+}
+
+/// Helper to convert FETCH message to JSON
+fn fetch_to_json(msg: &message::Fetch) -> JsonValue {
+    let mut json = json!({
+        "request_id": msg.id,
+        "subscriber_priority": msg.subscriber_priority,
+        "group_order": format!("{:?}", msg.group_order),
+        "fetch_type": format!("{:?}", msg.fetch_type),
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    });
+
+    if let Some(standalone) = &msg.standalone_fetch {
+        json["track_namespace"] = json!(standalone.track_namespace.to_string());
+        json["track_name"] = json!(&standalone.track_name);
+        json["start_group"] = json!(standalone.start_location.group_id);
+        json["start_object"] = json!(standalone.start_location.object_id);
+        json["end_group"] = json!(standalone.end_location.group_id);
+        json["end_object"] = json!(standalone.end_location.object_id);
+    }
+    if let Some(joining) = &msg.joining_fetch {
+        json["joining_request_id"] = json!(joining.joining_request_id);
+        json["joining_start"] = json!(joining.joining_start);
+    }
+
+    json
+}
+
+/// Create a control_message_parsed event for FETCH
+pub fn fetch_parsed(time: f64, stream_id: u64, msg: &message::Fetch) -> Event {
+    create_control_message_event(time, stream_id, true, "fetch", fetch_to_json(msg))
+}
+
+/// Create a control_message_created event for FETCH
+pub fn fetch_created(time: f64, stream_id: u64, msg: &message::Fetch) -> Event {
+    create_control_message_event(time, stream_id, false, "fetch", fetch_to_json(msg))
+}
+
+/// Helper to convert FETCH_OK message to JSON
+fn fetch_ok_to_json(msg: &message::FetchOk) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "group_order": format!("{:?}", msg.group_order),
+        "end_of_track": msg.end_of_track,
+        "end_group": msg.end_location.group_id,
+        "end_object": msg.end_location.object_id,
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    })
+}
+
+/// Create a control_message_parsed event for FETCH_OK
+pub fn fetch_ok_parsed(time: f64, stream_id: u64, msg: &message::FetchOk) -> Event {
+    create_control_message_event(time, stream_id, true, "fetch_ok", fetch_ok_to_json(msg))
+}
+
+/// Create a control_message_created event for FETCH_OK
+pub fn fetch_ok_created(time: f64, stream_id: u64, msg: &message::FetchOk) -> Event {
+    create_control_message_event(time, stream_id, false, "fetch_ok", fetch_ok_to_json(msg))
+}
+
+/// Helper to convert FETCH_ERROR message to JSON
+fn fetch_error_to_json(msg: &message::FetchError) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "error_code": msg.error_code,
+        "reason_phrase": &msg.reason_phrase.0,
+    })
+}
+
+/// Create a control_message_parsed event for FETCH_ERROR
+pub fn fetch_error_parsed(time: f64, stream_id: u64, msg: &message::FetchError) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "fetch_error",
+        fetch_error_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for FETCH_ERROR
+pub fn fetch_error_created(time: f64, stream_id: u64, msg: &message::FetchError) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "fetch_error",
+        fetch_error_to_json(msg),
+    )
+}
+
+/// Helper to convert FETCH_CANCEL message to JSON
+fn fetch_cancel_to_json(msg: &message::FetchCancel) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+    })
+}
+
+/// Create a control_message_parsed event for FETCH_CANCEL
+pub fn fetch_cancel_parsed(time: f64, stream_id: u64, msg: &message::FetchCancel) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "fetch_cancel",
+        fetch_cancel_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for FETCH_CANCEL
+pub fn fetch_cancel_created(time: f64, stream_id: u64, msg: &message::FetchCancel) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "fetch_cancel",
+        fetch_cancel_to_json(msg),
+    )
+}
+
+/// Helper to convert PUBLISH message to JSON
+fn publish_to_json(msg: &message::Publish) -> JsonValue {
+    let mut json = json!({
+        "request_id": msg.id,
+        "track_namespace": msg.track_namespace.to_string(),
+        "track_name": &msg.track_name,
+        "track_alias": msg.track_alias,
+        "group_order": format!("{:?}", msg.group_order),
+        "content_exists": msg.content_exists,
+        "forward": msg.forward,
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    });
+
+    if let Some(largest) = &msg.largest_location {
+        json["largest_group_id"] = json!(largest.group_id);
+        json["largest_object_id"] = json!(largest.object_id);
+    }
+
+    json
+}
+
+/// Create a control_message_parsed event for PUBLISH
+pub fn publish_parsed(time: f64, stream_id: u64, msg: &message::Publish) -> Event {
+    create_control_message_event(time, stream_id, true, "publish", publish_to_json(msg))
+}
+
+/// Create a control_message_created event for PUBLISH
+pub fn publish_created(time: f64, stream_id: u64, msg: &message::Publish) -> Event {
+    create_control_message_event(time, stream_id, false, "publish", publish_to_json(msg))
+}
+
+/// Helper to convert PUBLISH_OK message to JSON
+fn publish_ok_to_json(msg: &message::PublishOk) -> JsonValue {
+    let mut json = json!({
+        "request_id": msg.id,
+        "forward": msg.forward,
+        "subscriber_priority": msg.subscriber_priority,
+        "group_order": format!("{:?}", msg.group_order),
+        "filter_type": format!("{:?}", msg.filter_type),
+        "parameters": key_value_pairs_to_vec(&msg.params.0),
+    });
+
+    if let Some(start_loc) = &msg.start_location {
+        json["start_group"] = json!(start_loc.group_id);
+        json["start_object"] = json!(start_loc.object_id);
+    }
+    if let Some(end_group) = msg.end_group_id {
+        json["end_group"] = json!(end_group);
+    }
+
+    json
+}
+
+/// Create a control_message_parsed event for PUBLISH_OK
+pub fn publish_ok_parsed(time: f64, stream_id: u64, msg: &message::PublishOk) -> Event {
+    create_control_message_event(time, stream_id, true, "publish_ok", publish_ok_to_json(msg))
+}
+
+/// Create a control_message_created event for PUBLISH_OK
+pub fn publish_ok_created(time: f64, stream_id: u64, msg: &message::PublishOk) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "publish_ok",
+        publish_ok_to_json(msg),
+    )
+}
+
+/// Helper to convert PUBLISH_ERROR message to JSON
+fn publish_error_to_json(msg: &message::PublishError) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "error_code": msg.error_code,
+        "reason_phrase": &msg.reason_phrase.0,
+    })
+}
+
+/// Create a control_message_parsed event for PUBLISH_ERROR
+pub fn publish_error_parsed(time: f64, stream_id: u64, msg: &message::PublishError) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "publish_error",
+        publish_error_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for PUBLISH_ERROR
+pub fn publish_error_created(time: f64, stream_id: u64, msg: &message::PublishError) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "publish_error",
+        publish_error_to_json(msg),
+    )
+}
+
+/// Helper to convert PUBLISH_DONE message to JSON
+fn publish_done_to_json(msg: &message::PublishDone) -> JsonValue {
+    json!({
+        "request_id": msg.id,
+        "status_code": msg.status_code,
+        "stream_count": msg.stream_count,
+        "reason_phrase": &msg.reason.0,
+    })
+}
+
+/// Create a control_message_parsed event for PUBLISH_DONE
+pub fn publish_done_parsed(time: f64, stream_id: u64, msg: &message::PublishDone) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "publish_done",
+        publish_done_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for PUBLISH_DONE
+pub fn publish_done_created(time: f64, stream_id: u64, msg: &message::PublishDone) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "publish_done",
+        publish_done_to_json(msg),
+    )
+}
+
+/// Helper to convert MAX_REQUEST_ID message to JSON
+fn max_request_id_to_json(msg: &message::MaxRequestId) -> JsonValue {
+    json!({
+        "request_id": msg.request_id,
+    })
+}
+
+/// Create a control_message_parsed event for MAX_REQUEST_ID
+pub fn max_request_id_parsed(time: f64, stream_id: u64, msg: &message::MaxRequestId) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "max_request_id",
+        max_request_id_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for MAX_REQUEST_ID
+pub fn max_request_id_created(time: f64, stream_id: u64, msg: &message::MaxRequestId) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "max_request_id",
+        max_request_id_to_json(msg),
+    )
+}
+
+/// Helper to convert REQUESTS_BLOCKED message to JSON
+fn requests_blocked_to_json(msg: &message::RequestsBlocked) -> JsonValue {
+    json!({
+        "maximum_request_id": msg.max_request_id,
+    })
+}
+
+/// Create a control_message_parsed event for REQUESTS_BLOCKED
+pub fn requests_blocked_parsed(time: f64, stream_id: u64, msg: &message::RequestsBlocked) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        true,
+        "requests_blocked",
+        requests_blocked_to_json(msg),
+    )
+}
+
+/// Create a control_message_created event for REQUESTS_BLOCKED
+pub fn requests_blocked_created(
+    time: f64,
+    stream_id: u64,
+    msg: &message::RequestsBlocked,
+) -> Event {
+    create_control_message_event(
+        time,
+        stream_id,
+        false,
+        "requests_blocked",
+        requests_blocked_to_json(msg),
+    )
+}
+
 // Data plane events
 
 /// Helper to convert SubgroupHeader to JSON
@@ -0,0 +1,335 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use flate2::read::GzDecoder;
+use serde_json::{Map, Number, Value as JsonValue};
+
+use super::EventData;
+
+// The CBOR self-described tag (RFC 8949 section 3.4.6), written at the start of binary logs.
+const CBOR_MAGIC: [u8; 3] = [0xd9, 0xd9, 0xf7];
+
+/// How mlog records are serialized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MlogFormat {
+    /// JSON-SEQ, one JSON record per line, compatible with qlog tooling.
+    #[default]
+    Json,
+
+    /// A CBOR sequence (RFC 8742) of the same records, for logging data-plane events at high rates.
+    ///
+    /// The file starts with the CBOR self-described tag so it can be told apart from JSON.
+    /// Use [`convert_to_json`] to read it with qlog tooling.
+    Cbor,
+}
+
+impl MlogFormat {
+    /// Detect the format of an mlog file, looking through gzip compression.
+    pub fn detect(path: &Path) -> io::Result<Self> {
+        let mut magic = Vec::new();
+        open(path)?.take(3).read_to_end(&mut magic)?;
+
+        Ok(match magic == CBOR_MAGIC {
+            true => Self::Cbor,
+            false => Self::Json,
+        })
+    }
+
+    pub(super) fn encode(&self, record: &impl serde::Serialize) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Json => {
+                let mut buf = serde_json::to_vec(record)?;
+                buf.push(b'\n');
+                Ok(buf)
+            }
+            Self::Cbor => {
+                let mut buf = Vec::new();
+                encode_cbor(&serde_json::to_value(record)?, &mut buf);
+                Ok(buf)
+            }
+        }
+    }
+
+    // Anything written before the first record.
+    pub(super) fn preamble(&self) -> &'static [u8] {
+        match self {
+            Self::Json => &[],
+            Self::Cbor => &CBOR_MAGIC,
+        }
+    }
+}
+
+impl FromStr for MlogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "cbor" => Ok(Self::Cbor),
+            _ => Err(format!(
+                "unknown mlog format '{}', expected json or cbor",
+                s
+            )),
+        }
+    }
+}
+
+/// Which categories of events are logged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MlogEvents {
+    #[default]
+    All,
+
+    /// Control messages and log lines, skipping the per-object data-plane events.
+    Control,
+
+    /// Only the subgroup and datagram events, plus log lines.
+    Data,
+}
+
+impl MlogEvents {
+    /// Whether subgroup and datagram events are logged.
+    pub fn data_plane(&self) -> bool {
+        *self != Self::Control
+    }
+
+    pub(super) fn includes(&self, data: &EventData) -> bool {
+        let data_plane = match data {
+            EventData::ControlMessageParsed(_) | EventData::ControlMessageCreated(_) => false,
+            // Log lines are kept whatever the category.
+            EventData::LogLevel(_) => return true,
+            _ => true,
+        };
+
+        match self {
+            Self::All => true,
+            Self::Control => !data_plane,
+            Self::Data => data_plane,
+        }
+    }
+}
+
+impl FromStr for MlogEvents {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "control" => Ok(Self::Control),
+            "data" => Ok(Self::Data),
+            _ => Err(format!(
+                "unknown mlog events '{}', expected all, control or data",
+                s
+            )),
+        }
+    }
+}
+
+/// Write the mlog file at `path` to `output` as JSON-SEQ, whatever its format and compression.
+///
+/// Returns the number of records written, including the header.
+pub fn convert_to_json(path: &Path, mut output: impl Write) -> io::Result<usize> {
+    let mut input = BufReader::new(open(path)?);
+    let mut count = 0;
+
+    if MlogFormat::detect(path)? == MlogFormat::Json {
+        for line in input.lines() {
+            writeln!(output, "{}", line?)?;
+            count += 1;
+        }
+        return Ok(count);
+    }
+
+    read_bytes::<3>(&mut input)?;
+    while !input.fill_buf()?.is_empty() {
+        let record = decode_cbor(&mut input)?;
+        serde_json::to_writer(&mut output, &record)?;
+        output.write_all(b"\n")?;
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+fn open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let mut file = File::open(path)?;
+    let mut magic = [0; 2];
+    let gzip = file.read(&mut magic)? == 2 && magic == [0x1f, 0x8b];
+
+    let file = File::open(path)?;
+    Ok(match gzip {
+        true => Box::new(GzDecoder::new(file)),
+        false => Box::new(file),
+    })
+}
+
+// Only the subset of CBOR needed for JSON values: definite lengths, and every number as an
+// integer or a 64-bit float.
+fn encode_head(major: u8, value: u64, buf: &mut Vec<u8>) {
+    let major = major << 5;
+    match value {
+        0..=23 => buf.push(major | value as u8),
+        24..=0xff => buf.extend_from_slice(&[major | 24, value as u8]),
+        0x100..=0xffff => {
+            buf.push(major | 25);
+            buf.extend_from_slice(&(value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(major | 26);
+            buf.extend_from_slice(&(value as u32).to_be_bytes());
+        }
+        _ => {
+            buf.push(major | 27);
+            buf.extend_from_slice(&value.to_be_bytes());
+        }
+    }
+}
+
+fn encode_cbor(value: &JsonValue, buf: &mut Vec<u8>) {
+    match value {
+        JsonValue::Null => buf.push(0xf6),
+        JsonValue::Bool(false) => buf.push(0xf4),
+        JsonValue::Bool(true) => buf.push(0xf5),
+        JsonValue::Number(number) => {
+            if let Some(value) = number.as_u64() {
+                encode_head(0, value, buf);
+            } else if let Some(value) = number.as_i64() {
+                // Negative integers are encoded as -1 - n.
+                encode_head(1, !value as u64, buf);
+            } else {
+                buf.push(0xfb);
+                buf.extend_from_slice(&number.as_f64().unwrap_or(0.0).to_be_bytes());
+            }
+        }
+        JsonValue::String(value) => {
+            encode_head(3, value.len() as u64, buf);
+            buf.extend_from_slice(value.as_bytes());
+        }
+        JsonValue::Array(values) => {
+            encode_head(4, values.len() as u64, buf);
+            for value in values {
+                encode_cbor(value, buf);
+            }
+        }
+        JsonValue::Object(map) => {
+            encode_head(5, map.len() as u64, buf);
+            for (key, value) in map {
+                encode_head(3, key.len() as u64, buf);
+                buf.extend_from_slice(key.as_bytes());
+                encode_cbor(value, buf);
+            }
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("invalid CBOR mlog: {}", msg),
+    )
+}
+
+fn read_bytes<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn decode_head(initial: u8, input: &mut impl Read) -> io::Result<u64> {
+    Ok(match initial & 0x1f {
+        value @ 0..=23 => value as u64,
+        24 => u8::from_be_bytes(read_bytes(input)?) as u64,
+        25 => u16::from_be_bytes(read_bytes(input)?) as u64,
+        26 => u32::from_be_bytes(read_bytes(input)?) as u64,
+        27 => u64::from_be_bytes(read_bytes(input)?),
+        _ => return Err(invalid("unsupported length")),
+    })
+}
+
+fn decode_string(len: u64, input: &mut impl Read) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    input.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"))
+}
+
+fn decode_cbor(input: &mut impl Read) -> io::Result<JsonValue> {
+    let [initial] = read_bytes(input)?;
+
+    Ok(match initial >> 5 {
+        0 => decode_head(initial, input)?.into(),
+        1 => {
+            let value = decode_head(initial, input)?;
+            let value = i64::try_from(value).map_err(|_| invalid("integer out of range"))?;
+            (-1 - value).into()
+        }
+        3 => decode_string(decode_head(initial, input)?, input)?.into(),
+        4 => {
+            let len = decode_head(initial, input)?;
+            let values = (0..len)
+                .map(|_| decode_cbor(input))
+                .collect::<io::Result<_>>()?;
+            JsonValue::Array(values)
+        }
+        5 => {
+            let mut map = Map::new();
+            for _ in 0..decode_head(initial, input)? {
+                let key = match decode_cbor(input)? {
+                    JsonValue::String(key) => key,
+                    _ => return Err(invalid("map key is not a string")),
+                };
+                map.insert(key, decode_cbor(input)?);
+            }
+            JsonValue::Object(map)
+        }
+        _ => match initial {
+            0xf4 => false.into(),
+            0xf5 => true.into(),
+            0xf6 => JsonValue::Null,
+            0xfa => {
+                let value = f32::from_be_bytes(read_bytes(input)?) as f64;
+                Number::from_f64(value).map_or(JsonValue::Null, JsonValue::Number)
+            }
+            0xfb => {
+                let value = f64::from_be_bytes(read_bytes(input)?);
+                Number::from_f64(value).map_or(JsonValue::Null, JsonValue::Number)
+            }
+            _ => return Err(invalid("unsupported item")),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn cbor_roundtrip() {
+        let value = json!({
+            "time": 12.5,
+            "name": "moqt:subgroup_object_parsed",
+            "data": {
+                "stream_id": 0,
+                "group_id": 70000,
+                "object_id": 4294967296u64,
+                "delta": -25,
+                "extension_headers": [null, true, false, "é"],
+            },
+        });
+
+        let mut buf = Vec::new();
+        encode_cbor(&value, &mut buf);
+        assert!(buf.len() < serde_json::to_vec(&value).unwrap().len());
+
+        let decoded = decode_cbor(&mut buf.as_slice()).unwrap();
+        assert_eq!(decoded, value);
+    }
+}
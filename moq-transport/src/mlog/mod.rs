@@ -1,9 +1,16 @@
 //! MoQ Transport logging (mlog) following qlog patterns
 //!
 //! Based on draft-pardue-moq-qlog-moq-events but adapted for MoQ Transport draft-14
-//! This creates qlog-compatible JSON-SEQ files that can be aggregated with QUIC qlog files
+//! This creates qlog-compatible JSON-SEQ files that can be aggregated with QUIC qlog files,
+//! or more compact CBOR files that [convert_to_json] turns back into JSON-SEQ
 
+mod format;
+mod rotation;
+mod sampling;
 mod writer;
+pub use format::{convert_to_json, MlogEvents, MlogFormat};
+pub use rotation::{compress_file, enforce_retention, MlogConfig};
+pub use sampling::{MlogSampling, SampleReason, SampledObject};
 pub use writer::MlogWriter;
 
 pub mod events;
@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::{MlogEvents, MlogFormat, MlogSampling};
+
+/// Format, size, compression, and retention policy for mlog files.
+///
+/// The default disables every limit, matching the behaviour of a plain [`super::MlogWriter::new`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MlogConfig {
+    /// Start a new segment once the current file reaches this many bytes.
+    /// Closed segments are renamed to `<file>.1`, `<file>.2`, ...
+    pub max_file_size: Option<u64>,
+
+    /// Gzip each file once it is closed, either by rotation or at the end of the connection.
+    /// Files are compressed in the background, and left as is if the process exits first.
+    pub compress: bool,
+
+    /// Keep at most this many closed mlog files in the directory, removing the oldest first.
+    pub max_files: Option<usize>,
+
+    /// Remove mlog files that have not been modified for this long.
+    pub max_age: Option<Duration>,
+
+    /// How records are serialized.
+    pub format: MlogFormat,
+
+    /// Which categories of events are logged.
+    pub events: MlogEvents,
+
+    /// How many data-plane object events are logged.
+    pub sampling: MlogSampling,
+}
+
+impl MlogConfig {
+    /// Returns true if a retention policy (max files or max age) is configured.
+    pub fn has_retention(&self) -> bool {
+        self.max_files.is_some() || self.max_age.is_some()
+    }
+}
+
+/// Gzip the file at `path` into `<path>.gz` and remove the original.
+pub fn compress_file(path: &Path) -> io::Result<PathBuf> {
+    let mut dst = path.as_os_str().to_owned();
+    dst.push(".gz");
+    let dst = PathBuf::from(dst);
+
+    let mut input = BufReader::new(File::open(path)?);
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(&dst)?), Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+
+    fs::remove_file(path)?;
+    Ok(dst)
+}
+
+// The mlog files this process is still writing or compressing, which retention leaves alone.
+static OPEN_FILES: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+pub(super) fn mark_open(path: &Path) {
+    OPEN_FILES.lock().unwrap().insert(path.to_path_buf());
+}
+
+pub(super) fn mark_closed(path: &Path) {
+    OPEN_FILES.lock().unwrap().remove(path);
+}
+
+// Gzip a closed file on a background thread, one at a time, so the connection logging to it
+// isn't held up. The file counts as open until it is compressed.
+pub(super) fn compress_later(path: PathBuf) {
+    static COMPRESSOR: OnceLock<mpsc::Sender<PathBuf>> = OnceLock::new();
+
+    let compressor = COMPRESSOR.get_or_init(|| {
+        let (tx, rx) = mpsc::channel::<PathBuf>();
+        thread::Builder::new()
+            .name("mlog-compress".to_string())
+            .spawn(move || {
+                for path in rx {
+                    if let Err(err) = compress_file(&path) {
+                        log::warn!("failed to compress mlog {}: {}", path.display(), err);
+                    }
+                    mark_closed(&path);
+                }
+            })
+            .expect("failed to spawn the mlog compressor");
+        tx
+    });
+
+    mark_open(&path);
+    if let Err(mpsc::SendError(path)) = compressor.send(path) {
+        mark_closed(&path);
+    }
+}
+
+/// Apply the retention policy in `config` to the mlog files in `dir`.
+///
+/// Files are matched by an `.mlog` extension, optionally followed by a segment number and/or `.gz`.
+/// Age is measured from the last modification. Files this process is still writing or compressing
+/// are never removed, and don't count toward `max_files`.
+///
+/// Returns the number of files removed.
+pub fn enforce_retention(dir: &Path, config: &MlogConfig) -> io::Result<usize> {
+    if !config.has_retention() {
+        return Ok(0);
+    }
+
+    let open = OPEN_FILES.lock().unwrap().clone();
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() || !is_mlog_file(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+
+        // A `.gz` file is open while it is being compressed from the original.
+        let path = entry.path();
+        let original = path.with_extension("");
+        if open.contains(&path)
+            || (path.extension() == Some("gz".as_ref()) && open.contains(&original))
+        {
+            continue;
+        }
+
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        files.push((modified, path));
+    }
+
+    // Newest first, so everything past max_files is the oldest.
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+    let now = SystemTime::now();
+    let mut removed = 0;
+
+    for (index, (modified, path)) in files.into_iter().enumerate() {
+        let too_many = config.max_files.is_some_and(|max| index >= max);
+        let too_old = config
+            .max_age
+            .is_some_and(|max| now.duration_since(modified).is_ok_and(|age| age > max));
+
+        if !too_many && !too_old {
+            continue;
+        }
+
+        match fs::remove_file(&path) {
+            Ok(()) => removed += 1,
+            // Another task may have compressed or removed it in the meantime.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Matches `x.mlog`, `x.mlog.gz`, `x.mlog.3`, and `x.mlog.3.gz`.
+fn is_mlog_file(name: &str) -> bool {
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    if name.ends_with(".mlog") {
+        return true;
+    }
+
+    match name.rsplit_once('.') {
+        Some((base, segment)) => {
+            base.ends_with(".mlog")
+                && !segment.is_empty()
+                && segment.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mlog_file_names() {
+        assert!(is_mlog_file("abc_server.mlog"));
+        assert!(is_mlog_file("abc_server.mlog.gz"));
+        assert!(is_mlog_file("abc_server.mlog.12"));
+        assert!(is_mlog_file("abc_server.mlog.12.gz"));
+
+        assert!(!is_mlog_file("abc_server.qlog"));
+        assert!(!is_mlog_file("abc_server.mlog.tmp"));
+        assert!(!is_mlog_file("abc_server.mlog."));
+        assert!(!is_mlog_file("mlog"));
+    }
+
+    #[test]
+    fn retention_skips_open_files() {
+        let dir = std::env::temp_dir().join(format!("moq-mlog-retention-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let files: Vec<PathBuf> = (0..4)
+            .map(|index| dir.join(format!("{}_server.mlog", index)))
+            .collect();
+        for file in &files {
+            fs::write(file, "{}").unwrap();
+        }
+        let compressing = dir.join("3_server.mlog.gz");
+        fs::write(&compressing, "").unwrap();
+
+        // The oldest files are still being written or compressed.
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        for file in [&files[0], &files[3], &compressing] {
+            File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(old)
+                .unwrap();
+        }
+        mark_open(&files[0]);
+        mark_open(&files[3]);
+
+        let config = MlogConfig {
+            max_files: Some(1),
+            max_age: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        // Only one of the two closed files is over the limit.
+        assert_eq!(enforce_retention(&dir, &config).unwrap(), 1);
+        assert!(files[0].exists() && files[3].exists() && compressing.exists());
+
+        mark_closed(&files[0]);
+        mark_closed(&files[3]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::coding::TrackNamespace;
+use crate::data;
+
+use super::Event;
+
+/// How many data-plane object events are logged, so they can stay on at high object rates.
+///
+/// One in [Self::rate] objects of each track is logged, and so are the first and last object of
+/// each group and any anomaly. Each object event records the rate it was sampled at as
+/// `sample_rate`, and those logged only because they're first, last or an anomaly also record
+/// why as `sample_reason`, so the object count is about the events without a reason times the rate.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MlogSampling {
+    /// Log one object in this many, or all of them if 0 or 1.
+    pub rate: u64,
+
+    /// The rate of the tracks under each namespace prefix, replacing [Self::rate].
+    /// The longest matching prefix applies.
+    pub namespaces: Vec<(TrackNamespace, u64)>,
+}
+
+impl MlogSampling {
+    /// The rate objects of tracks under `namespace` are sampled at, 1 if they're all logged.
+    pub fn rate(&self, namespace: Option<&TrackNamespace>) -> u64 {
+        let rate = namespace
+            .and_then(|namespace| {
+                self.namespaces
+                    .iter()
+                    .filter(|(prefix, _)| namespace.has_prefix(prefix))
+                    .max_by_key(|(prefix, _)| prefix.fields.len())
+            })
+            .map_or(self.rate, |(_, rate)| *rate);
+
+        rate.max(1)
+    }
+
+    /// Whether every object is logged.
+    pub fn is_full(&self) -> bool {
+        self.rate <= 1 && self.namespaces.iter().all(|(_, rate)| *rate <= 1)
+    }
+}
+
+/// The object an event is about, for [super::MlogWriter::add_object_event] to sample it.
+#[derive(Clone, Copy, Debug)]
+pub struct SampledObject<'a> {
+    /// The track's namespace, if known, to pick its rate.
+    pub namespace: Option<&'a TrackNamespace>,
+    pub track_alias: u64,
+    pub group_id: u64,
+    pub object_id: u64,
+
+    /// Logged whatever the rate, ex. an object with a status or following a gap.
+    pub anomaly: bool,
+}
+
+impl SampledObject<'_> {
+    /// Whether an object with this status and extension headers is an anomaly: one with a status
+    /// other than normal, or following a gap in the groups.
+    pub fn is_anomaly(
+        status: Option<data::ObjectStatus>,
+        extension_headers: Option<&data::ExtensionHeaders>,
+    ) -> bool {
+        status.is_some_and(|status| status != data::ObjectStatus::NormalObject)
+            || extension_headers.is_some_and(|headers| headers.prior_group_id_gap().is_some())
+    }
+}
+
+/// Why an object was logged outside its sample.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleReason {
+    First,
+    Last,
+    Anomaly,
+}
+
+/// An event as written, with how it was sampled.
+#[derive(Serialize)]
+pub(super) struct SampledEvent<'a> {
+    #[serde(flatten)]
+    pub event: &'a Event,
+    pub sample_rate: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_reason: Option<SampleReason>,
+}
+
+// The objects seen of one track, in one direction.
+struct TrackSampler {
+    group_id: u64,
+    next_object_id: u64,
+    seen: u64,
+
+    // The latest object of the current group that wasn't logged, written if it turns out to be
+    // the last one, once the next group starts.
+    pending: Option<(Event, u64)>,
+}
+
+/// Picks the object events to write, see [MlogSampling].
+#[derive(Default)]
+pub(super) struct Sampler {
+    // Keyed by event name and track alias, as each direction numbers tracks on its own.
+    tracks: HashMap<(String, u64), TrackSampler>,
+}
+
+impl Sampler {
+    /// The events to write for `event`, with their rate and reason: maybe the last object of the
+    /// previous group, and maybe this one.
+    pub fn sample(
+        &mut self,
+        sampling: &MlogSampling,
+        event: Event,
+        object: SampledObject,
+    ) -> Vec<(Event, u64, Option<SampleReason>)> {
+        let rate = sampling.rate(object.namespace);
+        let mut written = Vec::new();
+
+        let key = (event.name.clone(), object.track_alias);
+        let track = self.tracks.entry(key).or_insert(TrackSampler {
+            group_id: object.group_id,
+            next_object_id: object.object_id,
+            seen: 0,
+            pending: None,
+        });
+
+        let first = track.seen == 0 || track.group_id != object.group_id;
+        if track.group_id != object.group_id {
+            if let Some((last, rate)) = track.pending.take() {
+                written.push((last, rate, Some(SampleReason::Last)));
+            }
+            track.group_id = object.group_id;
+        }
+
+        // Objects missing since the last one, within a group, are an anomaly too.
+        let gap = !first && object.object_id != track.next_object_id;
+        track.next_object_id = object.object_id + 1;
+
+        let sampled = track.seen.is_multiple_of(rate);
+        track.seen += 1;
+
+        let reason = match () {
+            _ if sampled => None,
+            _ if first => Some(SampleReason::First),
+            _ if object.anomaly || gap => Some(SampleReason::Anomaly),
+            _ => {
+                track.pending = Some((event, rate));
+                return written;
+            }
+        };
+
+        track.pending = None;
+        written.push((event, rate, reason));
+        written
+    }
+
+    /// The objects held back in case they were the last of their group, written on close.
+    pub fn drain(&mut self) -> Vec<(Event, u64)> {
+        self.tracks
+            .values_mut()
+            .filter_map(|track| track.pending.take())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mlog::{loglevel_event, LogLevel};
+
+    fn object(group_id: u64, object_id: u64) -> SampledObject<'static> {
+        SampledObject {
+            namespace: None,
+            track_alias: 1,
+            group_id,
+            object_id,
+            anomaly: false,
+        }
+    }
+
+    #[test]
+    fn rates() {
+        let sampling = MlogSampling {
+            rate: 10,
+            namespaces: vec![
+                (TrackNamespace::from_utf8_path("live"), 100),
+                (TrackNamespace::from_utf8_path("live/debug"), 1),
+            ],
+        };
+        assert_eq!(sampling.rate(None), 10);
+        assert_eq!(
+            sampling.rate(Some(&TrackNamespace::from_utf8_path("live/room"))),
+            100
+        );
+        assert_eq!(
+            sampling.rate(Some(&TrackNamespace::from_utf8_path("live/debug/a"))),
+            1
+        );
+        assert!(!sampling.is_full());
+        assert!(MlogSampling::default().is_full());
+    }
+
+    #[test]
+    fn samples() {
+        let sampling = MlogSampling {
+            rate: 3,
+            ..Default::default()
+        };
+        let mut sampler = Sampler::default();
+        let mut sample = |group_id, object_id, anomaly| {
+            let event = loglevel_event(object_id as f64, LogLevel::Info, String::new());
+            let object = SampledObject {
+                anomaly,
+                ..object(group_id, object_id)
+            };
+            sampler
+                .sample(&sampling, event, object)
+                .into_iter()
+                .map(|(event, _, reason)| (event.time as u64, reason))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(sample(0, 0, false), [(0, None)]);
+        assert_eq!(sample(0, 1, false), []);
+        assert_eq!(sample(0, 2, false), []);
+        assert_eq!(sample(0, 3, false), [(3, None)]);
+        assert_eq!(sample(0, 4, true), [(4, Some(SampleReason::Anomaly))]);
+        // Object 5 is missing.
+        assert_eq!(sample(0, 6, false), [(6, Some(SampleReason::Anomaly))]);
+        assert_eq!(sample(0, 7, false), [(7, None)]);
+        assert_eq!(sample(0, 8, false), []);
+
+        // The next group logs the last object of this one, and its own first object.
+        assert_eq!(
+            sample(1, 0, false),
+            [
+                (8, Some(SampleReason::Last)),
+                (0, Some(SampleReason::First))
+            ]
+        );
+        assert_eq!(sample(1, 1, false), [(1, None)]);
+        assert_eq!(sample(1, 2, false), []);
+        assert_eq!(sampler.drain().len(), 1);
+    }
+}
@@ -1,27 +1,126 @@
-use std::fs::File;
+use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufWriter, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use super::Event;
+use super::rotation::{compress_later, mark_closed, mark_open};
+use super::sampling::{SampledEvent, Sampler};
+use super::{Event, MlogConfig, MlogFormat, MlogSampling, SampleReason, SampledObject};
 
 /// Writer for MoQ Transport logs (mlog)
-/// Writes JSON-SEQ format compatible with qlog aggregation
+/// Writes JSON-SEQ format compatible with qlog aggregation, or CBOR if configured
 pub struct MlogWriter {
-    writer: BufWriter<File>,
+    // None once the log has been closed, or while on standby.
+    writer: Option<BufWriter<File>>,
+    path: PathBuf,
+    config: MlogConfig,
+
+    // Events are dropped instead of failing while on standby, see [Self::standby].
+    standby: bool,
+
+    // Whether the file at `path` was created and not closed yet.
+    created: bool,
+
+    // Bytes written to the current segment, including the header.
+    written: u64,
+
+    // Number of segments rotated out so far.
+    segments: u64,
+
+    sampler: Sampler,
+
     start_time: Instant,
 }
 
 impl MlogWriter {
     /// Create a new mlog writer for the given file path
     pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::with_config(path, MlogConfig::default())
+    }
+
+    /// Create a new mlog writer that rotates and compresses according to `config`.
+    ///
+    /// Retention limits are not applied here; see [`super::enforce_retention`].
+    pub fn with_config(path: impl AsRef<Path>, config: MlogConfig) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let (writer, written) = Self::open(&path, config.format)?;
+
+        Ok(Self {
+            writer: Some(writer),
+            path,
+            config,
+            standby: false,
+            created: true,
+            written,
+            segments: 0,
+            sampler: Sampler::default(),
+            start_time: Instant::now(),
+        })
+    }
+
+    /// Create a writer on standby, which doesn't create the file or log any event until
+    /// [Self::start] is called, ex. to only capture a connection once something goes wrong.
+    pub fn standby(path: impl AsRef<Path>, config: MlogConfig) -> Self {
+        Self {
+            writer: None,
+            path: path.as_ref().to_path_buf(),
+            config,
+            standby: true,
+            created: false,
+            written: 0,
+            segments: 0,
+            sampler: Sampler::default(),
+            start_time: Instant::now(),
+        }
+    }
+
+    /// Start logging events, creating the file, or appending to the one an earlier capture left.
+    pub fn start(&mut self) -> io::Result<()> {
+        if self.writer.is_some() {
+            return Ok(());
+        }
+        if !self.standby {
+            return Err(io::Error::other("mlog closed"));
+        }
+
+        let (writer, written) = match self.created {
+            true => {
+                let file = OpenOptions::new().append(true).open(&self.path)?;
+                let written = file.metadata()?.len();
+                (BufWriter::new(file), written)
+            }
+            false => Self::open(&self.path, self.config.format)?,
+        };
+
+        self.writer = Some(writer);
+        self.written = written;
+        self.created = true;
+
+        Ok(())
+    }
+
+    /// Stop logging events until [Self::start] is called again, flushing those logged so far.
+    pub fn stop(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+            self.standby = true;
+        }
+
+        Ok(())
+    }
+
+    /// Whether events are being logged, rather than dropped on standby.
+    pub fn is_capturing(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    // Create the file and write the qlog-compatible header as the first record.
+    // This follows qlog JSON-SEQ format (RFC 7464)
+    fn open(path: &Path, format: MlogFormat) -> io::Result<(BufWriter<File>, u64)> {
         let file = File::create(path)?;
+        mark_open(path);
         let mut writer = BufWriter::new(file);
 
-        let start_time = Instant::now();
-
-        // Write qlog-compatible header as first record
-        // This follows qlog JSON-SEQ format (RFC 7464)
         let header = serde_json::json!({
             "qlog_version": "0.3",
             "qlog_format": "JSON-SEQ",
@@ -38,11 +137,12 @@ impl MlogWriter {
             }
         });
 
-        serde_json::to_writer(&mut writer, &header)?;
-        writer.write_all(b"\n")?;
+        let mut record = format.preamble().to_vec();
+        record.extend(format.encode(&header)?);
+        writer.write_all(&record)?;
         writer.flush()?;
 
-        Ok(Self { writer, start_time })
+        Ok((writer, record.len() as u64))
     }
 
     /// Get elapsed time in milliseconds since connection start
@@ -50,16 +150,258 @@ impl MlogWriter {
         self.start_time.elapsed().as_secs_f64() * 1000.0
     }
 
-    /// Add an event to the log
+    /// The path of the file currently being written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Whether subgroup and datagram events are logged, so callers can skip building them.
+    pub fn data_plane(&self) -> bool {
+        self.is_capturing() && self.config.events.data_plane()
+    }
+
+    /// Change how many data-plane object events are logged from now on, ex. for one session.
+    pub fn set_sampling(&mut self, sampling: MlogSampling) {
+        self.config.sampling = sampling;
+    }
+
+    /// Add an event to the log, unless its category is filtered out
     pub fn add_event(&mut self, event: Event) -> io::Result<()> {
-        serde_json::to_writer(&mut self.writer, &event)?;
-        self.writer.write_all(b"\n")?;
-        self.writer.flush()?;
+        self.write_event(&event, None)
+    }
+
+    /// Add an event about `object`, like [Self::add_event] unless [MlogConfig::sampling] leaves
+    /// it out. Such an event may be written later, if it turns out to be the last of its group.
+    pub fn add_object_event(&mut self, event: Event, object: SampledObject) -> io::Result<()> {
+        // Nothing to sample, or nothing is written anyway.
+        if self.config.sampling.is_full() || !self.is_capturing() {
+            return self.add_event(event);
+        }
+
+        let sampled = self.sampler.sample(&self.config.sampling, event, object);
+        for (event, rate, reason) in sampled {
+            self.write_event(&event, Some((rate, reason)))?;
+        }
+
+        Ok(())
+    }
+
+    // Write an event, with the rate and reason it was sampled for if any.
+    fn write_event(
+        &mut self,
+        event: &Event,
+        sample: Option<(u64, Option<SampleReason>)>,
+    ) -> io::Result<()> {
+        let writer = match self.writer.as_mut() {
+            Some(writer) => writer,
+            None if self.standby => return Ok(()),
+            None => return Err(io::Error::other("mlog closed")),
+        };
+
+        if !self.config.events.includes(&event.data) {
+            return Ok(());
+        }
+
+        let record = match sample {
+            Some((sample_rate, sample_reason)) => self.config.format.encode(&SampledEvent {
+                event,
+                sample_rate,
+                sample_reason,
+            })?,
+            None => self.config.format.encode(event)?,
+        };
+        writer.write_all(&record)?;
+        writer.flush()?;
+        self.written += record.len() as u64;
+
+        if let Some(max) = self.config.max_file_size {
+            if self.written >= max {
+                self.rotate()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Move the current file to `<path>.<n>` and start a fresh segment at the original path,
+    // so the live log is always found at the same location.
+    fn rotate(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+
+        self.segments += 1;
+
+        let mut segment = self.path.as_os_str().to_owned();
+        segment.push(format!(".{}", self.segments));
+        let segment = PathBuf::from(segment);
+
+        fs::rename(&self.path, &segment)?;
+        if self.config.compress {
+            compress_later(segment);
+        }
+
+        let (writer, written) = Self::open(&self.path, self.config.format)?;
+        self.writer = Some(writer);
+        self.written = written;
+
+        Ok(())
+    }
+
+    // Flush the current segment and queue it to be compressed if configured. Safe to call more
+    // than once.
+    fn close(&mut self) -> io::Result<()> {
+        // The objects held back were the last of their groups.
+        if self.is_capturing() {
+            for (event, rate) in self.sampler.drain() {
+                self.write_event(&event, Some((rate, Some(SampleReason::Last))))?;
+            }
+        }
+
+        self.standby = false;
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+
+        // Nothing to compress if the file was never created, ex. on standby the whole time.
+        if !std::mem::take(&mut self.created) {
+            return Ok(());
+        }
+
+        match self.config.compress {
+            true => compress_later(self.path.clone()),
+            false => mark_closed(&self.path),
+        }
+
         Ok(())
     }
 
     /// Flush and close the log
     pub fn finish(mut self) -> io::Result<()> {
-        self.writer.flush()
+        self.close()
+    }
+}
+
+impl Drop for MlogWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            log::warn!("failed to close mlog {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mlog::{loglevel_event, LogLevel, MlogSampling};
+
+    #[test]
+    fn standby() {
+        let path =
+            std::env::temp_dir().join(format!("moq-mlog-standby-{}.mlog", std::process::id()));
+        let event = || loglevel_event(0.0, LogLevel::Info, "event".to_string());
+
+        // Nothing is written until started.
+        let mut mlog = MlogWriter::standby(&path, MlogConfig::default());
+        mlog.add_event(event()).unwrap();
+        assert!(!mlog.is_capturing());
+        assert!(!path.exists());
+
+        mlog.start().unwrap();
+        mlog.add_event(event()).unwrap();
+        mlog.stop().unwrap();
+        let captured = fs::read(&path).unwrap();
+
+        // Events between captures are dropped, and the next capture appends to the file.
+        mlog.add_event(event()).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), captured);
+        mlog.start().unwrap();
+        mlog.add_event(event()).unwrap();
+        mlog.finish().unwrap();
+        assert!(fs::read(&path).unwrap().len() > captured.len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compresses_in_background() {
+        let path =
+            std::env::temp_dir().join(format!("moq-mlog-compress-{}.mlog", std::process::id()));
+        let config = MlogConfig {
+            max_file_size: Some(1),
+            compress: true,
+            ..Default::default()
+        };
+
+        let mut mlog = MlogWriter::with_config(&path, config).unwrap();
+        mlog.add_event(loglevel_event(0.0, LogLevel::Info, "event".to_string()))
+            .unwrap();
+        mlog.finish().unwrap();
+
+        // The rotated segment and the last one are gzipped once the compressor gets to them.
+        let gz = |name: &str| PathBuf::from(format!("{}{}.gz", path.display(), name));
+        let deadline = Instant::now() + std::time::Duration::from_secs(5);
+        while !(gz(".1").exists() && gz("").exists() && !path.exists()) {
+            assert!(Instant::now() < deadline, "mlog wasn't compressed");
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        fs::remove_file(gz(".1")).unwrap();
+        fs::remove_file(gz("")).unwrap();
+    }
+
+    #[test]
+    fn sampling() {
+        let path =
+            std::env::temp_dir().join(format!("moq-mlog-sampling-{}.mlog", std::process::id()));
+        let config = MlogConfig {
+            sampling: MlogSampling {
+                rate: 4,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut mlog = MlogWriter::with_config(&path, config).unwrap();
+        for object_id in 0..8 {
+            let event = loglevel_event(0.0, LogLevel::Info, format!("object {}", object_id));
+            let object = SampledObject {
+                namespace: None,
+                track_alias: 1,
+                group_id: 0,
+                object_id,
+                anomaly: false,
+            };
+            mlog.add_object_event(event, object).unwrap();
+        }
+        mlog.finish().unwrap();
+
+        // Objects 0 and 4 are sampled, and 7 was the last of its group.
+        let records: Vec<serde_json::Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let sampled: Vec<_> = records
+            .iter()
+            .map(|record| {
+                (
+                    record["data"]["message"].as_str().unwrap(),
+                    record["sample_rate"].as_u64().unwrap(),
+                    record["sample_reason"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            sampled,
+            [
+                ("object 0", 4, None),
+                ("object 4", 4, None),
+                ("object 7", 4, Some("last")),
+            ]
+        );
+
+        fs::remove_file(&path).unwrap();
     }
 }
@@ -1,5 +1,6 @@
-use std::{fmt, sync::Arc};
+use std::{fmt, future, sync::Arc, task};
 
+use crate::data;
 use crate::watch::State;
 
 use super::{ServeError, Track};
@@ -26,6 +27,9 @@ struct DatagramsState {
     // Increased each time datagram changes.
     epoch: u64,
 
+    // The largest payload every session serving the datagrams sends as a datagram.
+    max_payload: Option<usize>,
+
     // Set when the writer or all readers are dropped.
     closed: Result<(), ServeError>,
 }
@@ -35,6 +39,7 @@ impl Default for DatagramsState {
         Self {
             latest: None,
             epoch: 0,
+            max_payload: None,
             closed: Ok(()),
         }
     }
@@ -59,6 +64,13 @@ impl DatagramsWriter {
         Ok(())
     }
 
+    /// The largest payload sent as a datagram, derived from the QUIC connection of each session
+    /// serving the track, or None if no session limits it. Larger objects are still delivered,
+    /// on a subgroup stream of their own, so it's only a hint to keep objects within a packet.
+    pub fn max_payload(&self) -> Option<usize> {
+        self.state.lock().max_payload
+    }
+
     pub fn close(self, err: ServeError) -> Result<(), ServeError> {
         let state = self.state.lock();
         state.closed.clone()?;
@@ -76,6 +88,9 @@ pub struct DatagramsReader {
     pub track: Arc<Track>,
 
     epoch: u64,
+
+    // The number of datagrams skipped by the last call to read().
+    skipped: u64,
 }
 
 impl DatagramsReader {
@@ -84,25 +99,46 @@ impl DatagramsReader {
             state,
             track,
             epoch: 0,
+            skipped: 0,
+        }
+    }
+
+    /// The number of datagrams skipped by the last call to [Self::read], because this reader fell behind.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Lower [DatagramsWriter::max_payload] to `max`, if it's smaller, ex. when a session with a
+    /// smaller packet budget starts serving the datagrams.
+    pub fn limit_payload(&self, max: Option<usize>) {
+        let Some(max) = max else {
+            return;
+        };
+        if let Some(mut state) = self.state.lock_mut() {
+            state.max_payload = Some(state.max_payload.map_or(max, |current| current.min(max)));
         }
     }
 
     pub async fn read(&mut self) -> Result<Option<Datagram>, ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
-                if self.epoch < state.epoch {
-                    self.epoch = state.epoch;
-                    return Ok(state.latest.clone());
-                }
-
-                state.closed.clone()?;
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Ok(None), // No more updates will come
-                }
-            }
-            .await;
+        future::poll_fn(|cx| self.poll_read(cx)).await
+    }
+
+    /// Like [Self::read], returning Pending and waking `cx` when the next datagram arrives.
+    pub fn poll_read(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<Option<Datagram>, ServeError>> {
+        let state = self.state.lock();
+        if self.epoch < state.epoch {
+            self.skipped = state.epoch - self.epoch - 1;
+            self.epoch = state.epoch;
+            return task::Poll::Ready(Ok(state.latest.clone()));
+        }
+
+        state.closed.clone()?;
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Ok(None)), // No more updates will come
         }
     }
 
@@ -128,6 +164,40 @@ pub struct Datagram {
     pub extension_headers: crate::data::ExtensionHeaders,
 }
 
+impl Datagram {
+    /// Convert to the wire format for the given track alias.
+    /// Extension headers are forwarded unchanged.
+    pub fn into_data(self, track_alias: u64) -> data::Datagram {
+        let has_extension_headers = !self.extension_headers.is_empty();
+
+        data::Datagram {
+            datagram_type: match has_extension_headers {
+                true => data::DatagramType::ObjectIdPayloadExt,
+                false => data::DatagramType::ObjectIdPayload,
+            },
+            track_alias,
+            group_id: self.group_id,
+            object_id: Some(self.object_id),
+            publisher_priority: self.priority,
+            extension_headers: has_extension_headers.then_some(self.extension_headers),
+            status: None,
+            payload: Some(self.payload),
+        }
+    }
+}
+
+impl From<data::Datagram> for Datagram {
+    fn from(datagram: data::Datagram) -> Self {
+        Self {
+            group_id: datagram.group_id,
+            object_id: datagram.object_id.unwrap_or(0),
+            priority: datagram.publisher_priority,
+            payload: datagram.payload.unwrap_or_default(),
+            extension_headers: datagram.extension_headers.unwrap_or_default(),
+        }
+    }
+}
+
 impl fmt::Debug for Datagram {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Datagram")
@@ -139,3 +209,54 @@ impl fmt::Debug for Datagram {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::{Decode, Encode, KeyValuePair, TrackNamespace};
+    use bytes::BytesMut;
+
+    // A relay receives a datagram into the cache and forwards it under a new track alias.
+    #[test]
+    fn forward_extension_headers() {
+        let mut extension_headers = data::ExtensionHeaders::new();
+        extension_headers
+            .set_immutable(&[KeyValuePair::new_bytes(1, b"sig".to_vec())])
+            .unwrap();
+        extension_headers.set_prior_group_id_gap(2);
+        extension_headers.set_intvalue(100, 7);
+
+        let upstream = Datagram {
+            group_id: 5,
+            object_id: 1,
+            priority: 3,
+            payload: bytes::Bytes::from_static(b"pointer"),
+            extension_headers,
+        }
+        .into_data(9);
+
+        let mut buf = BytesMut::new();
+        upstream.encode(&mut buf).unwrap();
+        let received: Datagram = data::Datagram::decode(&mut buf).unwrap().into();
+
+        let downstream = received.into_data(9);
+        assert_eq!(downstream, upstream);
+    }
+
+    #[test]
+    fn max_payload() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "pointer".into(),
+        ));
+        let (writer, reader) = Datagrams { track }.produce();
+        assert_eq!(writer.max_payload(), None);
+
+        // The smallest budget of the sessions serving the datagrams wins.
+        reader.limit_payload(Some(1400));
+        reader.clone().limit_payload(Some(1100));
+        reader.limit_payload(Some(1300));
+        reader.limit_payload(None);
+        assert_eq!(writer.max_payload(), Some(1100));
+    }
+}
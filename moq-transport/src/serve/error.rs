@@ -25,6 +25,27 @@ pub enum ServeError {
     #[error("wrong size")]
     Size,
 
+    #[error("track is live-only")]
+    LiveOnly,
+
+    #[error("unauthorized")]
+    Unauthorized,
+
+    #[error("authorization expired")]
+    AuthExpired,
+
+    #[error("timed out")]
+    Timeout,
+
+    #[error("track ended")]
+    TrackEnded,
+
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    #[error("subscription quota violated: {0}")]
+    QuotaViolated(super::QuotaViolation),
+
     #[error("internal error: {0}")]
     Internal(String),
 
@@ -58,6 +79,18 @@ impl ServeError {
             Self::Mode => 0x3,
             Self::Size => 0x3,
             Self::NotImplemented(_) | Self::NotImplementedWithId(_, _) => 0x3,
+            // NOT_SUPPORTED (0x3) - the track isn't stored for fetching, a policy rather than a denial
+            Self::LiveOnly => 0x3,
+            // UNAUTHORIZED (0x1) - the request's authorization tokens were rejected
+            Self::Unauthorized => 0x1,
+            // UNAUTHORIZED (0x1) - re-checking the tokens of an established request failed
+            Self::AuthExpired => 0x1,
+            // TIMEOUT (0x2) - the relay couldn't route the request in time; the subscriber may retry
+            Self::Timeout => 0x2,
+            // TRACK_ENDED (0x2) from PUBLISH_DONE codes - the publisher marked the end of the track
+            Self::TrackEnded => 0x2,
+            // INTERNAL_ERROR (0x0) - no code is defined for resource limits; the reason phrase names the quota
+            Self::QuotaExceeded(_) | Self::QuotaViolated(_) => 0x0,
             // INTERNAL_ERROR (0x0) - per-request error registries use 0x0
             Self::Internal(_) | Self::InternalWithId(_, _) => 0x0,
         }
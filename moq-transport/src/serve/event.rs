@@ -0,0 +1,173 @@
+use std::collections::VecDeque;
+
+use crate::coding::Location;
+use crate::data::ObjectStatus;
+use crate::watch::State;
+
+use super::ServeError;
+
+/// An object status marker received on a track, so readers can tell why objects are missing
+/// and how the track ended. See [super::TrackReader::next_event].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackEvent {
+    /// The object at this location doesn't exist, ex. the publisher dropped it.
+    ObjectDoesNotExist(Location),
+
+    /// The group ends at this location, with no objects after it.
+    EndOfGroup(Location),
+
+    /// The track ends cleanly at this location, with no objects after it.
+    EndOfTrack(Location),
+}
+
+impl TrackEvent {
+    /// The event signalled by an object at `location` with `status`, if any.
+    pub fn from_status(status: ObjectStatus, location: Location) -> Option<Self> {
+        match status {
+            ObjectStatus::NormalObject => None,
+            ObjectStatus::ObjectDoesNotExist => Some(Self::ObjectDoesNotExist(location)),
+            ObjectStatus::EndOfGroup => Some(Self::EndOfGroup(location)),
+            ObjectStatus::EndOfTrack => Some(Self::EndOfTrack(location)),
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        match self {
+            Self::ObjectDoesNotExist(location)
+            | Self::EndOfGroup(location)
+            | Self::EndOfTrack(location) => *location,
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackEventsState {
+    // The latest events, the first one numbered `first`.
+    events: VecDeque<TrackEvent>,
+    first: u64,
+
+    end_of_track: Option<Location>,
+}
+
+impl TrackEventsState {
+    // Readers that fall further behind skip the older events.
+    const MAX_EVENTS: usize = 64;
+}
+
+/// Reports the [TrackEvent]s of a track to its readers, see [super::TrackWriter::events].
+#[derive(Clone)]
+pub struct TrackEventsWriter {
+    state: State<TrackEventsState>,
+}
+
+impl TrackEventsWriter {
+    /// Deliver `event` to every reader of the track.
+    pub fn report(&self, event: TrackEvent) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        if let TrackEvent::EndOfTrack(location) = event {
+            state.end_of_track = Some(location);
+        }
+
+        state.events.push_back(event);
+        if state.events.len() > TrackEventsState::MAX_EVENTS {
+            state.events.pop_front();
+            state.first += 1;
+        }
+
+        Ok(())
+    }
+}
+
+// The events of a track not read yet by one reader.
+#[derive(Clone)]
+pub(super) struct TrackEventsReader {
+    state: State<TrackEventsState>,
+    next: u64,
+}
+
+impl TrackEventsReader {
+    pub fn end_of_track(&self) -> Option<Location> {
+        self.state.lock().end_of_track
+    }
+
+    pub async fn next(&mut self) -> Option<TrackEvent> {
+        loop {
+            {
+                let state = self.state.lock();
+                self.next = self.next.max(state.first);
+
+                let index = (self.next - state.first) as usize;
+                if let Some(event) = state.events.get(index) {
+                    self.next += 1;
+                    return Some(*event);
+                }
+
+                state.modified()?
+            }
+            .await;
+        }
+    }
+}
+
+pub(super) fn track_events() -> (TrackEventsWriter, TrackEventsReader) {
+    let (writer, reader) = State::default().split();
+    (
+        TrackEventsWriter { state: writer },
+        TrackEventsReader {
+            state: reader,
+            next: 0,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn events() {
+        let (writer, mut reader) = track_events();
+        let end = Location::new(1, 3);
+
+        for object_id in 0..TrackEventsState::MAX_EVENTS as u64 + 2 {
+            let location = Location::new(0, object_id);
+            writer
+                .report(TrackEvent::ObjectDoesNotExist(location))
+                .unwrap();
+        }
+        writer.report(TrackEvent::EndOfTrack(end)).unwrap();
+        assert_eq!(reader.end_of_track(), Some(end));
+
+        // A reader that fell behind skips the oldest events.
+        assert_eq!(
+            reader.next().await,
+            Some(TrackEvent::ObjectDoesNotExist(Location::new(0, 3)))
+        );
+
+        drop(writer);
+        let mut last = None;
+        while let Some(event) = reader.next().await {
+            last = Some(event);
+        }
+        assert_eq!(last, Some(TrackEvent::EndOfTrack(end)));
+    }
+
+    #[test]
+    fn statuses() {
+        let location = Location::new(2, 5);
+        assert_eq!(
+            TrackEvent::from_status(ObjectStatus::NormalObject, location),
+            None
+        );
+        assert_eq!(
+            TrackEvent::from_status(ObjectStatus::EndOfGroup, location),
+            Some(TrackEvent::EndOfGroup(location))
+        );
+        assert_eq!(
+            TrackEvent::from_status(ObjectStatus::EndOfGroup, location)
+                .unwrap()
+                .location(),
+            location
+        );
+    }
+}
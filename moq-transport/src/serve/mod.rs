@@ -1,6 +1,9 @@
 mod datagram;
 mod error;
+mod event;
 mod object;
+mod ordered;
+mod quota;
 mod stream;
 mod subgroup;
 mod track;
@@ -8,8 +11,22 @@ mod tracks;
 
 pub use datagram::*;
 pub use error::*;
+pub use event::*;
 pub use object::*;
+pub use ordered::*;
+pub use quota::*;
 pub use stream::*;
 pub use subgroup::*;
 pub use track::*;
 pub use tracks::*;
+
+use bytes::Bytes;
+
+// Join the chunks of an object, without copying a payload that arrived in one piece.
+fn join(mut chunks: Vec<Bytes>) -> Bytes {
+    match chunks.len() {
+        0 => Bytes::new(),
+        1 => chunks.pop().unwrap(),
+        _ => chunks.concat().into(),
+    }
+}
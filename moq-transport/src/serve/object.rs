@@ -344,7 +344,7 @@ impl ObjectReader {
             chunks.push(chunk);
         }
 
-        Ok(Bytes::from(chunks.concat()))
+        Ok(super::join(chunks))
     }
 }
 
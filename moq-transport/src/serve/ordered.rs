@@ -0,0 +1,300 @@
+//! Delivery of a track's objects in object ID order, merging the subgroups of each group.
+//!
+//! Subgroups are carried on parallel streams, so a later object can arrive before an earlier one
+//! sent on another subgroup. An [OrderedReader] holds objects back until the earlier ones arrive,
+//! waiting a bounded time before giving up on them.
+use std::{collections::BTreeMap, time::Duration};
+
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use tokio::time::Instant;
+
+use super::{ServeError, SubgroupObjectReader, SubgroupReader, SubgroupsReader};
+
+// A subgroup and the result of waiting for its next object.
+type Read = (
+    SubgroupReader,
+    Result<Option<SubgroupObjectReader>, ServeError>,
+);
+
+/// Reads the objects of every subgroup, in (group ID, object ID) order.
+///
+/// An object is returned once the object before it has been, or it is the first of a group and
+/// every earlier group has ended. Otherwise it waits up to `max_wait` for the missing objects,
+/// which are then skipped; objects arriving after a later one was returned are dropped.
+///
+/// Created with [SubgroupsReader::ordered]. Datagrams aren't included, see [SubgroupsReader::datagrams].
+pub struct OrderedReader {
+    // None once all subgroups have been received.
+    subgroups: Option<SubgroupsReader>,
+    max_wait: Duration,
+
+    // Subgroups waiting for their next object.
+    reading: FuturesUnordered<BoxFuture<'static, Read>>,
+
+    // The number of subgroups of each group waiting for their next object.
+    open: BTreeMap<u64, usize>,
+
+    // Objects received but not yet returned, keyed by (group, object), with their subgroup.
+    pending: BTreeMap<(u64, u64), (SubgroupObjectReader, SubgroupReader)>,
+
+    // The (group, object) expected next, once an object has been returned.
+    next: Option<(u64, u64)>,
+
+    // When the oldest pending object started waiting for earlier ones.
+    blocked: Option<Instant>,
+
+    skipped: u64,
+}
+
+impl OrderedReader {
+    /// The number of subgroups buffered if the reader was in [super::Backpressure::Latest] mode.
+    pub const BUFFER: usize = 16;
+
+    pub(super) fn new(subgroups: SubgroupsReader, max_wait: Duration) -> Self {
+        Self {
+            subgroups: Some(subgroups),
+            max_wait,
+            reading: FuturesUnordered::new(),
+            open: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            next: None,
+            blocked: None,
+            skipped: 0,
+        }
+    }
+
+    /// The next object in order, or None once every subgroup has ended.
+    pub async fn next(&mut self) -> Result<Option<SubgroupObjectReader>, ServeError> {
+        loop {
+            if let Some(object) = self.ready() {
+                return Ok(Some(object));
+            }
+
+            if self.subgroups.is_none() && self.reading.is_empty() {
+                return Ok(None);
+            }
+
+            // Only start a timer while actually waiting for a missing object.
+            let deadline = self.blocked.map(|since| since + self.max_wait);
+            let expired = async move {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            let (subgroups, reading) = (&mut self.subgroups, &mut self.reading);
+            tokio::select! {
+                res = async { subgroups.as_mut().unwrap().next().await }, if subgroups.is_some() => match res? {
+                    Some(subgroup) => self.read(subgroup),
+                    None => self.subgroups = None,
+                },
+                Some((subgroup, res)) = reading.next() => self.received(subgroup, res),
+                _ = expired => {},
+            }
+        }
+    }
+
+    /// The number of objects given up on so far, because they didn't arrive within `max_wait`
+    /// or arrived after a later object was returned.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    // Return the oldest pending object, if it's its turn.
+    fn ready(&mut self) -> Option<SubgroupObjectReader> {
+        loop {
+            let &(group, object) = self.pending.keys().next()?;
+
+            if self.next.is_some_and(|next| (group, object) < next) {
+                // Too late, a later object was already returned.
+                let (_, subgroup) = self.pending.remove(&(group, object)).unwrap();
+                log::debug!("dropping late object: group={} object={}", group, object);
+                self.skipped += 1;
+                self.read(subgroup);
+                continue;
+            }
+
+            let expected = match self.next {
+                Some((next_group, next_object)) if next_group == group => next_object,
+                _ => 0,
+            };
+            let in_order = object == expected && self.open.range(..group).next().is_none();
+            let finished = self.subgroups.is_none() && self.reading.is_empty();
+            let expired = self
+                .blocked
+                .is_some_and(|since| since.elapsed() >= self.max_wait);
+
+            if !(in_order || finished || expired) {
+                self.blocked.get_or_insert_with(Instant::now);
+                return None;
+            }
+
+            if !in_order {
+                log::debug!(
+                    "skipping missing objects: group={} object={}",
+                    group,
+                    object
+                );
+                self.skipped += object - expected;
+            }
+
+            let (reader, subgroup) = self.pending.remove(&(group, object)).unwrap();
+            self.next = Some((group, object + 1));
+            self.blocked = None;
+            self.read(subgroup);
+
+            return Some(reader);
+        }
+    }
+
+    // Wait for the next object of a subgroup.
+    fn read(&mut self, mut subgroup: SubgroupReader) {
+        *self.open.entry(subgroup.group_id).or_default() += 1;
+        self.reading.push(
+            async move {
+                let res = subgroup.next().await;
+                (subgroup, res)
+            }
+            .boxed(),
+        );
+    }
+
+    fn received(
+        &mut self,
+        subgroup: SubgroupReader,
+        res: Result<Option<SubgroupObjectReader>, ServeError>,
+    ) {
+        let group = subgroup.group_id;
+        if let Some(open) = self.open.get_mut(&group) {
+            *open -= 1;
+            if *open == 0 {
+                self.open.remove(&group);
+            }
+        }
+
+        match res {
+            Ok(Some(object)) => {
+                self.pending
+                    .insert((group, object.object_id), (object, subgroup));
+            }
+            Ok(None) => {}
+            Err(err) => log::debug!(
+                "subgroup ended: group={} subgroup={} err={}",
+                group,
+                subgroup.subgroup_id,
+                err
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::coding::TrackNamespace;
+    use crate::serve::{Subgroup, SubgroupWriter, Subgroups, SubgroupsWriter, Track};
+
+    const WAIT: Duration = Duration::from_millis(100);
+
+    fn produce() -> (SubgroupsWriter, OrderedReader) {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (writer, reader) = Subgroups { track }.produce();
+        (writer, reader.ordered(WAIT))
+    }
+
+    fn create(writer: &mut SubgroupsWriter, group_id: u64, subgroup_id: u64) -> SubgroupWriter {
+        writer
+            .create(Subgroup {
+                group_id,
+                subgroup_id,
+                priority: 0,
+            })
+            .unwrap()
+    }
+
+    fn write(subgroup: &mut SubgroupWriter, object_id: u64) {
+        subgroup.skip_to(object_id).unwrap();
+        subgroup.write(object_id.to_string().into()).unwrap();
+    }
+
+    async fn next(reader: &mut OrderedReader) -> Option<(u64, u64)> {
+        let object = reader.next().await.unwrap()?;
+        Some((object.group.group_id, object.object_id))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn merges_subgroups() {
+        let (mut writer, mut reader) = produce();
+
+        // Even objects on one subgroup and odd ones on another, which got ahead.
+        let mut even = create(&mut writer, 0, 0);
+        let mut odd = create(&mut writer, 0, 1);
+        for object_id in [1, 3, 5] {
+            write(&mut odd, object_id);
+        }
+        for object_id in [0, 2, 4] {
+            write(&mut even, object_id);
+        }
+
+        let mut next_group = create(&mut writer, 1, 0);
+        write(&mut next_group, 0);
+        drop((even, odd, next_group, writer));
+
+        let start = Instant::now();
+        let mut objects = Vec::new();
+        while let Some(object) = next(&mut reader).await {
+            objects.push(object);
+        }
+
+        let mut expected: Vec<_> = (0..6).map(|object_id| (0, object_id)).collect();
+        expected.push((1, 0));
+        assert_eq!(objects, expected);
+        assert_eq!(reader.skipped(), 0);
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn waits_for_missing() {
+        let (mut writer, mut reader) = produce();
+
+        let mut first = create(&mut writer, 0, 0);
+        let mut second = create(&mut writer, 0, 1);
+        write(&mut second, 1);
+
+        // Object 1 is held back while object 0 may still arrive.
+        let wait = tokio::time::timeout(WAIT / 2, reader.next()).await;
+        assert!(wait.is_err());
+
+        write(&mut first, 0);
+        assert_eq!(next(&mut reader).await, Some((0, 0)));
+        assert_eq!(next(&mut reader).await, Some((0, 1)));
+        assert_eq!(reader.skipped(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skips_after_wait() {
+        let (mut writer, mut reader) = produce();
+
+        let mut first = create(&mut writer, 0, 0);
+        let mut second = create(&mut writer, 0, 1);
+        write(&mut second, 1);
+
+        // Object 0 never shows up in time, so it's skipped.
+        let start = Instant::now();
+        assert_eq!(next(&mut reader).await, Some((0, 1)));
+        assert_eq!(start.elapsed(), WAIT);
+        assert_eq!(reader.skipped(), 1);
+
+        // It's dropped when it does arrive, rather than delivered out of order.
+        write(&mut first, 0);
+        write(&mut first, 2);
+        assert_eq!(next(&mut reader).await, Some((0, 2)));
+        assert_eq!(reader.skipped(), 2);
+    }
+}
@@ -0,0 +1,86 @@
+/// Limits on how a single subscription to one of our tracks is delivered, so one runaway track
+/// can't use up a session's whole budget.
+///
+/// A subscription breaking a limit is ended with [ServeError::QuotaViolated](super::ServeError::QuotaViolated).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubscriptionQuota {
+    /// Maximum payload bytes of the objects being sent but not written yet, ex. waiting for flow
+    /// or congestion control because the subscriber can't keep up.
+    pub max_buffered_bytes: Option<u64>,
+
+    /// Maximum rate payload is written at, in bytes per second, as measured over about half a second.
+    pub max_bytes_per_second: Option<u64>,
+}
+
+impl SubscriptionQuota {
+    /// Whether any limit is set.
+    pub fn is_limited(&self) -> bool {
+        self.max_buffered_bytes.is_some() || self.max_bytes_per_second.is_some()
+    }
+
+    /// Check the bytes currently buffered and the rate (in bits per second) they are sent at.
+    pub fn check(&self, buffered_bytes: u64, send_rate: u64) -> Result<(), QuotaViolation> {
+        if let Some(limit) = self.max_buffered_bytes {
+            if buffered_bytes > limit {
+                return Err(QuotaViolation::Buffered {
+                    bytes: buffered_bytes,
+                    limit,
+                });
+            }
+        }
+
+        if let Some(limit) = self.max_bytes_per_second {
+            let rate = send_rate / 8;
+            if rate > limit {
+                return Err(QuotaViolation::Rate { rate, limit });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The [SubscriptionQuota] limit a subscription broke.
+#[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaViolation {
+    #[error("{bytes} bytes buffered, over the limit of {limit}")]
+    Buffered { bytes: u64, limit: u64 },
+
+    #[error("sent at {rate} bytes/s, over the limit of {limit}")]
+    Rate { rate: u64, limit: u64 },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check() {
+        let quota = SubscriptionQuota {
+            max_buffered_bytes: Some(1000),
+            max_bytes_per_second: Some(125_000),
+        };
+
+        assert_eq!(quota.check(1000, 1_000_000), Ok(()));
+        assert_eq!(
+            quota.check(1001, 0),
+            Err(QuotaViolation::Buffered {
+                bytes: 1001,
+                limit: 1000
+            })
+        );
+        assert_eq!(
+            quota.check(0, 1_000_008),
+            Err(QuotaViolation::Rate {
+                rate: 125_001,
+                limit: 125_000
+            })
+        );
+
+        assert!(!SubscriptionQuota::default().is_limited());
+        assert_eq!(
+            SubscriptionQuota::default().check(u64::MAX, u64::MAX),
+            Ok(())
+        );
+    }
+}
@@ -526,7 +526,7 @@ impl StreamObjectReader {
             chunks.push(chunk);
         }
 
-        Ok(Bytes::from(chunks.concat()))
+        Ok(super::join(chunks))
     }
 }
 
@@ -7,14 +7,36 @@
 //! The reader can be cloned, in which case each reader receives a copy of each object. (fanout)
 //!
 //! The stream is closed with [ServeError::Closed] when all writers or readers are dropped.
-use std::{cmp, ops::Deref, sync::Arc};
+//!
+//! Each object has a forwarding preference: objects are normally carried in subgroup streams,
+//! but [SubgroupsWriter::datagram] sends an object as a datagram instead, ex. for low-latency
+//! telemetry mixed into a track. The preference is preserved when the track is forwarded.
+//!
+//! Objects remember when they were created and may carry a deadline, see [SubgroupWriter::set_budget].
+//! The publisher skips an object that misses it before transmission begins, so a stalled uplink
+//! drops stale objects instead of queueing them.
+use std::{
+    cmp,
+    collections::{BTreeMap, HashMap, VecDeque},
+    future,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task,
+    time::{Duration, Instant},
+};
 
 use bytes::Bytes;
+use futures::task::AtomicWaker;
 
 use crate::data::ObjectStatus;
 use crate::watch::State;
 
-use super::{ServeError, Track};
+use super::{
+    Datagram, Datagrams, DatagramsReader, DatagramsWriter, OrderedReader, ServeError, Track,
+};
 
 pub struct Subgroups {
     pub track: Arc<Track>,
@@ -22,10 +44,28 @@ pub struct Subgroups {
 
 impl Subgroups {
     pub fn produce(self) -> (SubgroupsWriter, SubgroupsReader) {
+        self.produce_retained(Retention::default())
+    }
+
+    /// Like [Self::produce], buffering subgroups for the readers holding on to `retention`,
+    /// ex. those of the track created before it chose a mode.
+    pub(super) fn produce_retained(
+        self,
+        retention: Retention,
+    ) -> (SubgroupsWriter, SubgroupsReader) {
         let (writer, reader) = State::default().split();
+        let (datagrams_writer, datagrams_reader) = Datagrams {
+            track: self.track.clone(),
+        }
+        .produce();
 
-        let writer = SubgroupsWriter::new(writer, self.track.clone());
-        let reader = SubgroupsReader::new(reader, self.track);
+        let writer = SubgroupsWriter::new(
+            writer,
+            datagrams_writer,
+            self.track.clone(),
+            retention.clone(),
+        );
+        let reader = SubgroupsReader::new(reader, datagrams_reader, self.track, retention);
 
         (writer, reader)
     }
@@ -39,10 +79,70 @@ impl Deref for Subgroups {
     }
 }
 
+/// How a [SubgroupsReader] behaves when it falls behind the writer.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// Skip straight to the newest subgroup, ex. for a player that only cares about live data.
+    #[default]
+    Latest,
+
+    /// Return every subgroup in order, buffering up to this many.
+    /// The oldest subgroups are skipped once the buffer is full, ex. for a recorder.
+    Buffer(usize),
+}
+
+/// The buffer sizes of the [Backpressure::Buffer] readers of a track, shared by all of them.
+///
+/// Subgroups are buffered for the largest, from when a reader asks until it's dropped.
+#[derive(Clone, Default)]
+pub(super) struct Retention {
+    // The number of readers holding each buffer size.
+    sizes: Arc<Mutex<BTreeMap<usize, usize>>>,
+}
+
+impl Retention {
+    /// Buffer `size` subgroups until the returned hold and its clones are dropped.
+    pub(super) fn hold(&self, size: usize) -> Arc<RetentionHold> {
+        *self.sizes.lock().unwrap().entry(size).or_default() += 1;
+        Arc::new(RetentionHold {
+            retention: self.clone(),
+            size,
+        })
+    }
+
+    /// The number of subgroups to buffer, for the largest reader still holding on.
+    fn size(&self) -> usize {
+        let sizes = self.sizes.lock().unwrap();
+        sizes.last_key_value().map(|(size, _)| *size).unwrap_or(0)
+    }
+}
+
+/// Keeps a [Retention] buffering subgroups for a reader, see [Retention::hold].
+pub(super) struct RetentionHold {
+    retention: Retention,
+    size: usize,
+}
+
+impl Drop for RetentionHold {
+    fn drop(&mut self) {
+        let mut sizes = self.retention.sizes.lock().unwrap();
+        if let Some(count) = sizes.get_mut(&self.size) {
+            *count -= 1;
+            if *count == 0 {
+                sizes.remove(&self.size);
+            }
+        }
+    }
+}
+
 // State shared between the writer and reader.
 struct SubgroupsState {
     latest_subgroup_reader: Option<SubgroupReader>,
     epoch: u64, // Updated each time latest changes
+
+    // Recent subgroups with the epoch and time they were created at, for buffered readers.
+    recent: VecDeque<(u64, Instant, SubgroupReader)>,
+
     closed: Result<(), ServeError>,
 }
 
@@ -51,6 +151,7 @@ impl Default for SubgroupsState {
         Self {
             latest_subgroup_reader: None,
             epoch: 0,
+            recent: VecDeque::new(),
             closed: Ok(()),
         }
     }
@@ -59,22 +160,47 @@ impl Default for SubgroupsState {
 pub struct SubgroupsWriter {
     pub info: Arc<Track>,
     state: State<SubgroupsState>,
+    datagrams: DatagramsWriter,
+    retention: Retention,
     next_subgroup_id: u64, // Not in the state to avoid a lock
     next_group_id: u64,    // Not in the state to avoid a lock
     last_group_id: u64,    // Not in the state to avoid a lock
+    skipped_groups: u64,   // Signalled on the next appended group
 }
 
 impl SubgroupsWriter {
-    fn new(state: State<SubgroupsState>, track: Arc<Track>) -> Self {
+    fn new(
+        state: State<SubgroupsState>,
+        datagrams: DatagramsWriter,
+        track: Arc<Track>,
+        retention: Retention,
+    ) -> Self {
         Self {
             info: track,
             state,
+            datagrams,
+            retention,
             next_subgroup_id: 0,
             next_group_id: 0,
             last_group_id: 0,
+            skipped_groups: 0,
         }
     }
 
+    /// Intentionally skip the given number of groups, ex. after a source discontinuity.
+    ///
+    /// The next [Self::append] uses a group ID past the skipped ones, and its first object carries
+    /// a Prior Group ID Gap extension so players can tell the gap apart from loss.
+    pub fn skip_groups(&mut self, count: u64) {
+        self.next_group_id += count;
+        self.skipped_groups += count;
+    }
+
+    /// Write an object that prefers datagram forwarding, rather than a subgroup stream.
+    pub fn datagram(&mut self, datagram: Datagram) -> Result<(), ServeError> {
+        self.datagrams.write(datagram)
+    }
+
     // Helper to increment the group by one.
     pub fn append(&mut self, priority: u8) -> Result<SubgroupWriter, ServeError> {
         let group_id;
@@ -91,11 +217,18 @@ impl SubgroupsWriter {
             subgroup_id = self.next_subgroup_id;
         }
 
-        self.create(Subgroup {
+        let mut writer = self.create(Subgroup {
             group_id,
             subgroup_id,
             priority,
-        })
+        })?;
+
+        if self.skipped_groups > 0 {
+            writer.prior_group_id_gap = Some(self.skipped_groups);
+            self.skipped_groups = 0;
+        }
+
+        Ok(writer)
     }
 
     /// Create a new subgroup with the given parameters, inserting it into the track.
@@ -132,16 +265,61 @@ impl SubgroupsWriter {
         self.last_group_id = state.latest_subgroup_reader.as_ref().unwrap().group_id;
         state.epoch += 1;
 
+        // Shrinks again once the buffered readers are dropped.
+        let retain = self.retention.size();
+        while state.recent.len() > retain.saturating_sub(1) {
+            state.recent.pop_front();
+        }
+
+        if retain > 0 {
+            let now = Instant::now();
+            let latest = state.latest_subgroup_reader.clone().unwrap();
+            let latest_group_id = latest.group_id;
+            let entry = (state.epoch, now, latest);
+            state.recent.push_back(entry);
+
+            // Drop the expired subgroups too, ex. while every buffered reader is paused.
+            while let Some((_, created, subgroup)) = state.recent.front() {
+                if state.recent.len() == 1
+                    || !expired(
+                        &self.info,
+                        subgroup.group_id,
+                        *created,
+                        latest_group_id,
+                        now,
+                    )
+                {
+                    break;
+                }
+                state.recent.pop_front();
+            }
+        }
+
         Ok(writer)
     }
 
+    /// Drop the subgroups buffered for readers that fell behind, except the latest,
+    /// so each reader jumps straight to it on its next [SubgroupsReader::next].
+    pub fn discard_buffered(&mut self) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        let keep = state.recent.len().min(1);
+        let discard = state.recent.len() - keep;
+        state.recent.drain(..discard);
+
+        Ok(())
+    }
+
     /// Close the segment with an error.
     pub fn close(self, err: ServeError) -> Result<(), ServeError> {
         let state = self.state.lock();
         state.closed.clone()?;
 
         let mut state = state.into_mut().ok_or(ServeError::Cancel)?;
-        state.closed = Err(err);
+        state.closed = Err(err.clone());
+        drop(state);
+
+        // The datagram readers may already be gone, which is fine.
+        self.datagrams.close(err).ok();
 
         Ok(())
     }
@@ -159,35 +337,128 @@ impl Deref for SubgroupsWriter {
 pub struct SubgroupsReader {
     pub info: Arc<Track>,
     state: State<SubgroupsState>,
+    datagrams: DatagramsReader,
     epoch: u64,
+    backpressure: Backpressure,
+    retention: Retention,
+    // Keeps subgroups buffered for this reader and its clones, while in Buffer mode.
+    hold: Option<Arc<RetentionHold>>,
+    // The number of subgroups skipped by the last call to next().
+    skipped: u64,
 }
 
 impl SubgroupsReader {
-    fn new(state: State<SubgroupsState>, track_info: Arc<Track>) -> Self {
+    fn new(
+        state: State<SubgroupsState>,
+        datagrams: DatagramsReader,
+        track_info: Arc<Track>,
+        retention: Retention,
+    ) -> Self {
         Self {
             info: track_info,
             state,
+            datagrams,
             epoch: 0,
+            backpressure: Backpressure::Latest,
+            retention,
+            hold: None,
+            skipped: 0,
         }
     }
 
+    /// The number of subgroups skipped by the last call to [Self::next], because this reader fell behind.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Objects written with [SubgroupsWriter::datagram], which should be forwarded as datagrams.
+    pub fn datagrams(&self) -> DatagramsReader {
+        self.datagrams.clone()
+    }
+
+    /// Change how this reader behaves when it falls behind.
+    /// Clones made beforehand keep their own behaviour.
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.hold = match backpressure {
+            Backpressure::Buffer(size) => Some(self.retention.hold(size)),
+            Backpressure::Latest => None,
+        };
+
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Read the objects of every subgroup in object ID order, waiting up to `max_wait` for
+    /// objects still in flight on other subgroups, see [OrderedReader].
+    ///
+    /// A reader in [Backpressure::Latest] mode would skip all but the newest subgroup of a group,
+    /// so it buffers [OrderedReader::BUFFER] subgroups instead.
+    pub fn ordered(self, max_wait: Duration) -> OrderedReader {
+        let subgroups = match self.backpressure {
+            Backpressure::Latest => {
+                self.with_backpressure(Backpressure::Buffer(OrderedReader::BUFFER))
+            }
+            Backpressure::Buffer(_) => self,
+        };
+
+        OrderedReader::new(subgroups, max_wait)
+    }
+
     pub async fn next(&mut self) -> Result<Option<SubgroupReader>, ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
+        future::poll_fn(|cx| self.poll_next(cx)).await
+    }
 
-                if self.epoch != state.epoch {
-                    self.epoch = state.epoch;
-                    return Ok(state.latest_subgroup_reader.clone());
-                }
+    /// Like [Self::next], returning Pending and waking `cx` when a subgroup is created.
+    pub fn poll_next(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<Option<SubgroupReader>, ServeError>> {
+        let state = self.state.lock();
 
-                state.closed.clone()?;
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Ok(None),
+        if self.epoch != state.epoch {
+            // Return the oldest buffered subgroup we haven't seen yet, if any.
+            // Expired subgroups are skipped, except the latest, see [Track::max_groups].
+            if let Backpressure::Buffer(size) = self.backpressure {
+                let oldest = self.epoch.max(state.epoch.saturating_sub(size as u64));
+                let now = Instant::now();
+                let latest_group_id = state
+                    .latest_subgroup_reader
+                    .as_ref()
+                    .map(|latest| latest.group_id)
+                    .unwrap_or_default();
+                let last = state.recent.len().saturating_sub(1);
+                if let Some((_, (epoch, _, subgroup))) =
+                    state
+                        .recent
+                        .iter()
+                        .enumerate()
+                        .find(|(index, (epoch, created, subgroup))| {
+                            *epoch > oldest
+                                && (*index == last
+                                    || !expired(
+                                        &self.info,
+                                        subgroup.group_id,
+                                        *created,
+                                        latest_group_id,
+                                        now,
+                                    ))
+                        })
+                {
+                    self.skipped = *epoch - self.epoch - 1;
+                    self.epoch = *epoch;
+                    return task::Poll::Ready(Ok(Some(subgroup.clone())));
                 }
             }
-            .await; // Try again when the state changes
+
+            self.skipped = state.epoch - self.epoch - 1;
+            self.epoch = state.epoch;
+            return task::Poll::Ready(Ok(state.latest_subgroup_reader.clone()));
+        }
+
+        state.closed.clone()?;
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Ok(None)),
         }
     }
 
@@ -209,6 +480,24 @@ impl Deref for SubgroupsReader {
     }
 }
 
+// Whether a subgroup buffered for readers that fell behind is past the track's limits.
+fn expired(
+    track: &Track,
+    group_id: u64,
+    created: Instant,
+    latest_group_id: u64,
+    now: Instant,
+) -> bool {
+    let too_many = track
+        .max_groups
+        .is_some_and(|max| latest_group_id.saturating_sub(group_id) >= max);
+    let too_old = track
+        .max_age
+        .is_some_and(|max| now.saturating_duration_since(created) > max);
+
+    too_many || too_old
+}
+
 /// Parameters that can be specified by the user
 #[derive(Debug, Clone, PartialEq)]
 pub struct Subgroup {
@@ -288,6 +577,15 @@ pub struct SubgroupWriter {
 
     // The next object sequence number to use.
     next_object_id: u64,
+
+    // Attached to the first object, when groups were skipped before this one.
+    prior_group_id_gap: Option<u64>,
+
+    // How long after creation each object may still start being sent.
+    budget: Option<Duration>,
+
+    // When the next object was created, if not now.
+    created: Option<Instant>,
 }
 
 impl SubgroupWriter {
@@ -296,9 +594,41 @@ impl SubgroupWriter {
             state,
             info: group,
             next_object_id: 0,
+            prior_group_id_gap: None,
+            budget: None,
+            created: None,
         }
     }
 
+    /// Date the next object `created`, ex. when the publisher captured it, rather than now.
+    /// Its budget and the subscriber's DELIVERY_TIMEOUT count from then.
+    pub fn created_at(&mut self, created: Instant) {
+        self.created = Some(created);
+    }
+
+    /// Give objects created from now on a deadline, this long after they are created.
+    ///
+    /// Objects that can't start being sent before their deadline are skipped, and signalled
+    /// as not existing. The subscriber's DELIVERY_TIMEOUT applies as well, whichever is sooner.
+    pub fn set_budget(&mut self, budget: Option<Duration>) {
+        self.budget = budget;
+    }
+
+    /// Number the next object `object_id`, leaving a gap for objects sent on other subgroups.
+    /// Object IDs only increase within a subgroup.
+    pub fn skip_to(&mut self, object_id: u64) -> Result<(), ServeError> {
+        if object_id < self.next_object_id {
+            return Err(ServeError::Internal(format!(
+                "object ID {} after {}",
+                object_id,
+                self.next_object_id - 1
+            )));
+        }
+
+        self.next_object_id = object_id;
+        Ok(())
+    }
+
     /// Create the next object ID with the given payload.
     pub fn write(&mut self, payload: bytes::Bytes) -> Result<(), ServeError> {
         let mut object = self.create(payload.len(), None)?;
@@ -306,6 +636,17 @@ impl SubgroupWriter {
         Ok(())
     }
 
+    /// Create the next object ID with the given payload and extension headers.
+    pub fn write_with_extensions(
+        &mut self,
+        payload: bytes::Bytes,
+        extension_headers: crate::data::ExtensionHeaders,
+    ) -> Result<(), ServeError> {
+        let mut object = self.create(payload.len(), Some(extension_headers))?;
+        object.write(payload)?;
+        Ok(())
+    }
+
     /// Write an object over multiple writes.
     ///
     /// BAD STUFF will happen if the size is wrong; this is an advanced feature.
@@ -314,12 +655,38 @@ impl SubgroupWriter {
         size: usize,
         extension_headers: Option<crate::data::ExtensionHeaders>,
     ) -> Result<SubgroupObjectWriter, ServeError> {
+        self.create_object(size, extension_headers, ObjectStatus::NormalObject)
+    }
+
+    /// Create the next object ID without a payload, signalling `status` instead.
+    pub fn create_status(
+        &mut self,
+        status: ObjectStatus,
+        extension_headers: Option<crate::data::ExtensionHeaders>,
+    ) -> Result<SubgroupObjectWriter, ServeError> {
+        self.create_object(0, extension_headers, status)
+    }
+
+    fn create_object(
+        &mut self,
+        size: usize,
+        extension_headers: Option<crate::data::ExtensionHeaders>,
+        status: ObjectStatus,
+    ) -> Result<SubgroupObjectWriter, ServeError> {
+        let mut extension_headers = extension_headers.unwrap_or_default();
+        if let Some(gap) = self.prior_group_id_gap.take() {
+            extension_headers.set_prior_group_id_gap(gap);
+        }
+
+        let created = self.created.take().unwrap_or_else(Instant::now);
         let (writer, reader) = SubgroupObject {
             group: self.info.clone(),
             object_id: self.next_object_id,
-            status: ObjectStatus::NormalObject,
+            status,
             size,
-            extension_headers: extension_headers.unwrap_or_default(),
+            extension_headers,
+            created,
+            deadline: self.budget.map(|budget| created + budget),
         }
         .produce();
 
@@ -399,23 +766,26 @@ impl SubgroupReader {
     }
 
     pub async fn next(&mut self) -> Result<Option<SubgroupObjectReader>, ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
-
-                if self.read_index < state.objects.len() {
-                    let object = state.objects[self.read_index].clone();
-                    self.read_index += 1;
-                    return Ok(Some(object));
-                }
+        future::poll_fn(|cx| self.poll_next(cx)).await
+    }
 
-                state.closed.clone()?;
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Ok(None),
-                }
-            }
-            .await; // Try again when the state changes
+    /// Like [Self::next], returning Pending and waking `cx` when an object is created.
+    pub fn poll_next(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<Option<SubgroupObjectReader>, ServeError>> {
+        let state = self.state.lock();
+
+        if self.read_index < state.objects.len() {
+            let object = state.objects[self.read_index].clone();
+            self.read_index += 1;
+            return task::Poll::Ready(Ok(Some(object)));
+        }
+
+        state.closed.clone()?;
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Ok(None)),
         }
     }
 
@@ -455,15 +825,31 @@ pub struct SubgroupObject {
 
     // Extension headers (for draft-14 compliance, particularly immutable extensions)
     pub extension_headers: crate::data::ExtensionHeaders,
+
+    // When the object was created, ex. captured by the publisher or received by a relay.
+    pub created: Instant,
+
+    // The object is skipped if it can't start being sent by then.
+    pub deadline: Option<Instant>,
 }
 
 impl SubgroupObject {
+    /// The sooner of the deadline and `timeout` after creation, if either is set.
+    pub fn expires(&self, timeout: Option<Duration>) -> Option<Instant> {
+        let timeout = timeout.map(|timeout| self.created + timeout);
+        match (self.deadline, timeout) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
     pub fn produce(self) -> (SubgroupObjectWriter, SubgroupObjectReader) {
         let (writer, reader) = State::default().split();
         let info = Arc::new(self);
+        let progress = Arc::new(ReaderProgress::default());
 
-        let writer = SubgroupObjectWriter::new(writer, info.clone());
-        let reader = SubgroupObjectReader::new(reader, info);
+        let writer = SubgroupObjectWriter::new(writer, info.clone(), progress.clone());
+        let reader = SubgroupObjectReader::new(reader, info, progress);
 
         (writer, reader)
     }
@@ -481,6 +867,9 @@ struct SubgroupObjectState {
     // The data that has been received thus far.
     chunks: Vec<Bytes>,
 
+    // The total number of bytes written thus far.
+    written: usize,
+
     // Set when the writer is dropped.
     closed: Result<(), ServeError>,
 }
@@ -489,11 +878,28 @@ impl Default for SubgroupObjectState {
     fn default() -> Self {
         Self {
             chunks: Vec::new(),
+            written: 0,
             closed: Ok(()),
         }
     }
 }
 
+// How far each reader got, used by the writer to apply backpressure.
+// Kept apart from the object state so reporting progress only wakes the writer, and only once it
+// waits on readers, instead of every reader on every chunk.
+#[derive(Default)]
+struct ReaderProgress {
+    // Set once the writer first waits with [SubgroupObjectWriter::ready].
+    tracked: AtomicBool,
+
+    // The number of bytes consumed by each reader that has reported, keyed by reader id.
+    readers: Mutex<HashMap<u64, usize>>,
+    next_reader_id: AtomicU64,
+
+    // Woken when a reader reports progress or is dropped.
+    writer: AtomicWaker,
+}
+
 /// Used to write data to a segment and notify readers.
 pub struct SubgroupObjectWriter {
     // Mutable segment state.
@@ -504,15 +910,23 @@ pub struct SubgroupObjectWriter {
 
     // The amount of promised data that has yet to be written.
     remain: usize,
+
+    // How far the readers got.
+    progress: Arc<ReaderProgress>,
 }
 
 impl SubgroupObjectWriter {
     /// Create a new segment with the given info.
-    fn new(state: State<SubgroupObjectState>, object: Arc<SubgroupObject>) -> Self {
+    fn new(
+        state: State<SubgroupObjectState>,
+        object: Arc<SubgroupObject>,
+        progress: Arc<ReaderProgress>,
+    ) -> Self {
         Self {
             state,
             remain: object.size,
             info: object,
+            progress,
         }
     }
 
@@ -524,11 +938,38 @@ impl SubgroupObjectWriter {
         self.remain -= chunk.len();
 
         let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        state.written += chunk.len();
         state.chunks.push(chunk);
 
         Ok(())
     }
 
+    /// Wait until the furthest reader is at most `max_buffered` bytes behind the writer.
+    ///
+    /// Call this between writes to propagate backpressure to the source (ex. a QUIC stream) instead
+    /// of buffering the entire object ahead of the readers. Returns immediately if no reader has
+    /// reported progress, since the object is then only being cached. Readers only start
+    /// reporting once this is first called.
+    pub async fn ready(&self, max_buffered: usize) -> Result<(), ServeError> {
+        self.progress.tracked.store(true, Ordering::Relaxed);
+
+        future::poll_fn(|cx| {
+            // Register first, so progress reported while we check isn't missed.
+            self.progress.writer.register(cx.waker());
+
+            let furthest = match self.progress.readers.lock().unwrap().values().max() {
+                Some(furthest) => *furthest,
+                None => return task::Poll::Ready(Ok(())), // No reader, or all of them are gone
+            };
+
+            match self.state.lock().written.saturating_sub(furthest) <= max_buffered {
+                true => task::Poll::Ready(Ok(())),
+                false => task::Poll::Pending,
+            }
+        })
+        .await
+    }
+
     /// Close the segment with an error.
     pub fn close(self, err: ServeError) -> Result<(), ServeError> {
         if self.remain != 0 {
@@ -566,7 +1007,6 @@ impl Deref for SubgroupObjectWriter {
 }
 
 /// Notified when a segment has new data available.
-#[derive(Clone)]
 pub struct SubgroupObjectReader {
     // Modify the segment state.
     state: State<SubgroupObjectState>,
@@ -577,36 +1017,71 @@ pub struct SubgroupObjectReader {
     // The number of chunks that we've read.
     // NOTE: Cloned readers inherit this index, but then run in parallel.
     index: usize,
+
+    // The number of bytes that we've read.
+    offset: usize,
+
+    // Where we report our progress, if the writer applies backpressure.
+    progress: Arc<ReaderProgress>,
+
+    // Our id in the progress, assigned on the first report.
+    id: Option<u64>,
 }
 
 impl SubgroupObjectReader {
-    fn new(state: State<SubgroupObjectState>, object: Arc<SubgroupObject>) -> Self {
+    fn new(
+        state: State<SubgroupObjectState>,
+        object: Arc<SubgroupObject>,
+        progress: Arc<ReaderProgress>,
+    ) -> Self {
         Self {
             state,
             info: object,
             index: 0,
+            offset: 0,
+            progress,
+            id: None,
         }
     }
 
     /// Block until the next chunk of bytes is available.
+    ///
+    /// Chunks are returned as they were written, so an object can be consumed while it is still
+    /// being received instead of waiting for the full payload.
     pub async fn read(&mut self) -> Result<Option<Bytes>, ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
-
-                if self.index < state.chunks.len() {
-                    let chunk = state.chunks[self.index].clone();
-                    self.index += 1;
-                    return Ok(Some(chunk));
-                }
+        future::poll_fn(|cx| self.poll_read(cx)).await
+    }
 
-                state.closed.clone()?;
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Ok(None), // No more changes will come
-                }
+    /// Like [Self::read], returning Pending and waking `cx` when more of the payload arrives.
+    pub fn poll_read(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<Option<Bytes>, ServeError>> {
+        let state = self.state.lock();
+
+        if self.index < state.chunks.len() {
+            let chunk = state.chunks[self.index].clone();
+            drop(state);
+            self.index += 1;
+            self.offset += chunk.len();
+
+            // Report our progress, if the writer is waiting on it.
+            if self.progress.tracked.load(Ordering::Relaxed) {
+                let progress = &self.progress;
+                let id = *self
+                    .id
+                    .get_or_insert_with(|| progress.next_reader_id.fetch_add(1, Ordering::Relaxed));
+                progress.readers.lock().unwrap().insert(id, self.offset);
+                progress.writer.wake();
             }
-            .await; // Try again when the state changes
+
+            return task::Poll::Ready(Ok(Some(chunk)));
+        }
+
+        state.closed.clone()?;
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Ok(None)), // No more changes will come
         }
     }
 
@@ -616,7 +1091,31 @@ impl SubgroupObjectReader {
             chunks.push(chunk);
         }
 
-        Ok(Bytes::from(chunks.concat()))
+        Ok(super::join(chunks))
+    }
+}
+
+impl Clone for SubgroupObjectReader {
+    fn clone(&self) -> Self {
+        // The clone starts at the same position, but registers its own progress once it reads.
+        Self {
+            state: self.state.clone(),
+            info: self.info.clone(),
+            index: self.index,
+            offset: self.offset,
+            progress: self.progress.clone(),
+            id: None,
+        }
+    }
+}
+
+impl Drop for SubgroupObjectReader {
+    fn drop(&mut self) {
+        // Stop holding back the writer once we're gone.
+        if let Some(id) = self.id {
+            self.progress.readers.lock().unwrap().remove(&id);
+            self.progress.writer.wake();
+        }
     }
 }
 
@@ -627,3 +1126,393 @@ impl Deref for SubgroupObjectReader {
         &self.info
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::{Decode, Encode, KeyValuePair, TrackNamespace};
+    use crate::data::{SubgroupObjectExt, IMMUTABLE_EXTENSIONS};
+    use crate::serve::TrackReaderMode;
+    use bytes::BytesMut;
+    use futures::{executor::block_on, FutureExt};
+
+    fn group_ids(reader: &mut SubgroupsReader, count: usize) -> Vec<u64> {
+        (0..count)
+            .map(|_| block_on(reader.next()).unwrap().unwrap().group_id)
+            .collect()
+    }
+
+    #[test]
+    fn backpressure() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, reader) = Subgroups { track }.produce();
+
+        let mut latest = reader.clone();
+        let mut all = reader.clone().with_backpressure(Backpressure::Buffer(8));
+        let mut few = reader.with_backpressure(Backpressure::Buffer(2));
+
+        let _groups: Vec<_> = (0..4).map(|_| writer.append(0).unwrap()).collect();
+
+        assert_eq!(group_ids(&mut latest, 1), vec![3]);
+        assert_eq!(group_ids(&mut all, 4), vec![0, 1, 2, 3]);
+        assert_eq!(group_ids(&mut few, 2), vec![2, 3]);
+    }
+
+    #[test]
+    fn tee_buffers_before_mode() {
+        let (writer, reader) =
+            Track::new(TrackNamespace::from_utf8_path("test"), "video".into()).produce();
+        let mut consumers = reader.tee(&[Backpressure::Latest, Backpressure::Buffer(8)]);
+        let recorder = consumers.pop().unwrap();
+
+        // Written before the recorder first asks for the mode.
+        let mut writer = writer.subgroups().unwrap();
+        let _groups: Vec<_> = (0..4).map(|_| writer.append(0).unwrap()).collect();
+
+        let mut subgroups = match block_on(recorder.mode()).unwrap() {
+            TrackReaderMode::Subgroups(subgroups) => subgroups,
+            _ => panic!("expected subgroups"),
+        };
+        assert_eq!(group_ids(&mut subgroups, 4), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn buffers_until_reader_dropped() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, reader) = Subgroups { track }.produce();
+
+        let all = reader.clone().with_backpressure(Backpressure::Buffer(8));
+        let _groups: Vec<_> = (0..4).map(|_| writer.append(0).unwrap()).collect();
+        drop(all);
+
+        // Nothing is buffered once no reader asks for it, so a new one starts at the latest.
+        let _group = writer.append(0).unwrap();
+        let mut late = reader.with_backpressure(Backpressure::Buffer(8));
+        assert_eq!(group_ids(&mut late, 1), vec![4]);
+    }
+
+    #[test]
+    fn discard_buffered() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, reader) = Subgroups { track }.produce();
+        let mut all = reader.with_backpressure(Backpressure::Buffer(8));
+
+        let _groups: Vec<_> = (0..4).map(|_| writer.append(0).unwrap()).collect();
+        assert_eq!(group_ids(&mut all, 1), vec![0]);
+
+        // The reader jumps to the latest group, then carries on from there.
+        writer.discard_buffered().unwrap();
+        assert_eq!(group_ids(&mut all, 1), vec![3]);
+        assert_eq!(all.skipped(), 2);
+
+        let _group = writer.append(0).unwrap();
+        assert_eq!(group_ids(&mut all, 1), vec![4]);
+    }
+
+    #[test]
+    fn expires_buffered_groups() {
+        let track = Track::new(TrackNamespace::from_utf8_path("test"), "video".into());
+        let track = Arc::new(track.with_max_groups(Some(2)));
+        let (mut writer, reader) = Subgroups { track }.produce();
+        let mut paused = reader.with_backpressure(Backpressure::Buffer(8));
+
+        let _groups: Vec<_> = (0..5).map(|_| writer.append(0).unwrap()).collect();
+        assert_eq!(group_ids(&mut paused, 1), vec![3]);
+        assert_eq!(paused.skipped(), 3);
+        assert_eq!(group_ids(&mut paused, 1), vec![4]);
+
+        // Expired by age even without new groups, except the latest.
+        let track = Track::new(TrackNamespace::from_utf8_path("test"), "video".into());
+        let track = Arc::new(track.with_max_age(Some(Duration::from_millis(50))));
+        let (mut writer, reader) = Subgroups { track }.produce();
+        let mut paused = reader.with_backpressure(Backpressure::Buffer(8));
+
+        let _groups: Vec<_> = (0..3).map(|_| writer.append(0).unwrap()).collect();
+        assert_eq!(group_ids(&mut paused, 1), vec![0]);
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(group_ids(&mut paused, 1), vec![2]);
+        assert_eq!(paused.skipped(), 1);
+    }
+
+    #[test]
+    fn object_backpressure() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+        let mut subgroup = writer.append(0).unwrap();
+        let mut object = subgroup.create(8, None).unwrap();
+        let mut subgroup = block_on(reader.next()).unwrap().unwrap();
+        let mut read = block_on(subgroup.next()).unwrap().unwrap();
+
+        // Readers don't report progress until the writer waits on it.
+        object.write(Bytes::from_static(b"ab")).unwrap();
+        assert_eq!(block_on(read.read()).unwrap().unwrap(), "ab");
+        assert!(object.progress.readers.lock().unwrap().is_empty());
+        assert!(object.ready(0).now_or_never().is_some());
+
+        object.write(Bytes::from_static(b"cd")).unwrap();
+        assert_eq!(block_on(read.read()).unwrap().unwrap(), "cd");
+        object.write(Bytes::from_static(b"ef")).unwrap();
+        assert!(object.ready(2).now_or_never().is_some());
+        assert!(object.ready(0).now_or_never().is_none());
+
+        // Caught up, or gone.
+        assert_eq!(block_on(read.read()).unwrap().unwrap(), "ef");
+        assert!(object.ready(0).now_or_never().is_some());
+        object.write(Bytes::from_static(b"gh")).unwrap();
+        assert!(object.ready(0).now_or_never().is_none());
+        drop(read);
+        assert!(object.ready(0).now_or_never().is_some());
+    }
+
+    #[test]
+    fn payloads_shared() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+        let mut subgroup = writer.append(0).unwrap();
+
+        let payload = Bytes::from(vec![7u8; 1200]);
+        subgroup.write(payload.clone()).unwrap();
+        let mut object = subgroup.create(4, None).unwrap();
+        object.write(Bytes::from_static(b"ke")).unwrap();
+        object.write(Bytes::from_static(b"ys")).unwrap();
+        drop(object);
+
+        // A payload written in one piece reaches readers without being copied.
+        let mut subgroup = block_on(reader.next()).unwrap().unwrap();
+        let read = block_on(subgroup.read_next()).unwrap().unwrap();
+        assert_eq!(read.as_ptr(), payload.as_ptr());
+        assert_eq!(block_on(subgroup.read_next()).unwrap().unwrap(), "keys");
+    }
+
+    #[test]
+    fn poll_without_runtime() {
+        use futures::task::{waker, ArcWake};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::task::{Context, Poll};
+
+        #[derive(Default)]
+        struct Woken(AtomicBool);
+
+        impl ArcWake for Woken {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+        let woken = Arc::new(Woken::default());
+        let waker = waker(woken.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        assert!(reader.poll_next(&mut cx).is_pending());
+        let mut subgroup = writer.append(0).unwrap();
+        assert!(woken.0.swap(false, Ordering::SeqCst));
+
+        let Poll::Ready(Ok(Some(mut subgroup_reader))) = reader.poll_next(&mut cx) else {
+            panic!("expected a subgroup");
+        };
+        assert!(subgroup_reader.poll_next(&mut cx).is_pending());
+
+        subgroup.write(Bytes::from_static(b"key")).unwrap();
+        assert!(woken.0.swap(false, Ordering::SeqCst));
+
+        let Poll::Ready(Ok(Some(mut object))) = subgroup_reader.poll_next(&mut cx) else {
+            panic!("expected an object");
+        };
+        assert_eq!(
+            object.poll_read(&mut cx),
+            Poll::Ready(Ok(Some("key".into())))
+        );
+        assert_eq!(object.poll_read(&mut cx), Poll::Ready(Ok(None)));
+
+        // Dropping the writer wakes the reader, which sees the subgroup end.
+        assert!(subgroup_reader.poll_next(&mut cx).is_pending());
+        drop(subgroup);
+        assert!(woken.0.load(Ordering::SeqCst));
+        assert_eq!(
+            subgroup_reader.poll_next(&mut cx).map_ok(|o| o.is_some()),
+            Poll::Ready(Ok(false))
+        );
+    }
+
+    #[test]
+    fn deadlines() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+        let mut subgroup = writer.append(0).unwrap();
+
+        subgroup.write(Bytes::from_static(b"live")).unwrap();
+        subgroup.set_budget(Some(Duration::from_millis(100)));
+        subgroup.write(Bytes::from_static(b"budget")).unwrap();
+        subgroup
+            .create_status(ObjectStatus::ObjectDoesNotExist, None)
+            .unwrap();
+
+        // Captured a while before it was written, so its budget is mostly spent.
+        let captured = Instant::now() - Duration::from_millis(80);
+        subgroup.created_at(captured);
+        subgroup.write(Bytes::from_static(b"captured")).unwrap();
+        subgroup.write(Bytes::from_static(b"now")).unwrap();
+
+        let mut subgroup = block_on(reader.next()).unwrap().unwrap();
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert_eq!(object.expires(None), None);
+        let timeout = object.expires(Some(Duration::from_millis(50))).unwrap();
+        assert_eq!(timeout - object.created, Duration::from_millis(50));
+
+        // The sooner of the budget and the subscriber's timeout applies.
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        let budget = object.expires(None).unwrap();
+        assert_eq!(budget - object.created, Duration::from_millis(100));
+        assert_eq!(object.expires(Some(Duration::from_secs(1))), Some(budget));
+        assert!(object.expires(Some(Duration::from_millis(10))).unwrap() < budget);
+
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert_eq!(object.status, ObjectStatus::ObjectDoesNotExist);
+        assert_eq!(object.size, 0);
+
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert_eq!(object.created, captured);
+        assert_eq!(
+            object.expires(None),
+            Some(captured + Duration::from_millis(100))
+        );
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert!(object.created > captured);
+    }
+
+    // Objects carrying Immutable Extensions are forwarded byte-for-byte, as on a relay hop.
+    #[test]
+    fn forward_immutable_extensions() {
+        let mut contents = BytesMut::new();
+        KeyValuePair::new_bytes(3, b"signature".to_vec())
+            .encode(&mut contents)
+            .unwrap();
+
+        let mut extension_headers = crate::data::ExtensionHeaders::new();
+        extension_headers.set_bytesvalue(IMMUTABLE_EXTENSIONS, contents.to_vec());
+        extension_headers.set_intvalue(2, 42);
+
+        let received = SubgroupObjectExt {
+            object_id_delta: 0,
+            extension_headers,
+            payload_length: 5,
+            status: None,
+        };
+        let mut wire = BytesMut::new();
+        received.encode(&mut wire).unwrap();
+        let upstream = wire.clone();
+
+        // Receive from upstream into the cache.
+        let received = SubgroupObjectExt::decode(&mut wire).unwrap();
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, reader) = Subgroups { track }.produce();
+        let mut subgroup = writer.append(0).unwrap();
+        let mut object = subgroup
+            .create(received.payload_length, Some(received.extension_headers))
+            .unwrap();
+        object.write(Bytes::from_static(b"hello")).unwrap();
+
+        // Serve it downstream.
+        let mut reader = reader;
+        let mut subgroup_reader = block_on(reader.next()).unwrap().unwrap();
+        let object = block_on(subgroup_reader.next()).unwrap().unwrap();
+        let forwarded = SubgroupObjectExt {
+            object_id_delta: 0,
+            extension_headers: object.extension_headers.clone(),
+            payload_length: object.size,
+            status: None,
+        };
+        let mut downstream = BytesMut::new();
+        forwarded.encode(&mut downstream).unwrap();
+
+        assert_eq!(upstream, downstream);
+        assert_eq!(
+            object.extension_headers.immutable_extensions(),
+            Some(&contents[..])
+        );
+    }
+
+    #[test]
+    fn datagram_forwarding_preference() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "pointer".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+        let mut datagrams = reader.datagrams();
+
+        let subgroup = writer.append(0).unwrap();
+        writer
+            .datagram(Datagram {
+                group_id: 0,
+                object_id: 1,
+                priority: 0,
+                payload: Bytes::from_static(b"x=1,y=2"),
+                extension_headers: Default::default(),
+            })
+            .unwrap();
+
+        assert_eq!(block_on(reader.next()).unwrap().unwrap().group_id, 0);
+        let datagram = block_on(datagrams.read()).unwrap().unwrap();
+        assert_eq!(datagram.object_id, 1);
+        assert_eq!(datagram.payload, Bytes::from_static(b"x=1,y=2"));
+
+        // Closing the track also ends the datagrams.
+        drop(subgroup);
+        writer.close(ServeError::Done).unwrap();
+        assert!(block_on(datagrams.read()).is_err());
+    }
+
+    #[test]
+    fn skip_groups() {
+        let track = Arc::new(Track::new(
+            TrackNamespace::from_utf8_path("test"),
+            "video".into(),
+        ));
+        let (mut writer, mut reader) = Subgroups { track }.produce();
+
+        let mut first = writer.append(0).unwrap();
+        first.write(Bytes::from_static(b"a")).unwrap();
+
+        writer.skip_groups(2);
+        let mut next = writer.append(0).unwrap();
+        assert_eq!(next.group_id, 3);
+        next.write(Bytes::from_static(b"b")).unwrap();
+        next.write(Bytes::from_static(b"c")).unwrap();
+
+        let mut subgroup = block_on(reader.next()).unwrap().unwrap();
+        assert_eq!(subgroup.group_id, 3);
+        assert_eq!(reader.skipped(), 1);
+
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert_eq!(object.extension_headers.prior_group_id_gap(), Some(2));
+        let object = block_on(subgroup.next()).unwrap().unwrap();
+        assert_eq!(object.extension_headers.prior_group_id_gap(), None);
+    }
+}
@@ -9,39 +9,157 @@
 //! These streams are meant to be transmitted over congested networks and the key to MoQ Tranport is to not block on them.
 //! streams will be cached for a potentially limited duration added to the unreliable nature.
 //! A cloned [Reader] will receive a copy of all new stream going forward (fanout).
+//! Use [TrackReader::tee] to give each clone its own [Backpressure] policy.
+//! Object status markers are surfaced as [TrackEvent]s, see [TrackReader::next_event].
 //!
 //! The track is closed with [ServeError::Closed] when all writers or readers are dropped.
 
 use crate::watch::State;
 
 use super::{
-    Datagrams, DatagramsReader, DatagramsWriter, ObjectsWriter, ServeError, Stream, StreamReader,
-    StreamWriter, Subgroups, SubgroupsReader, SubgroupsWriter,
+    event::{track_events, TrackEventsReader},
+    subgroup::{Retention, RetentionHold},
+    Backpressure, Datagrams, DatagramsReader, DatagramsWriter, ObjectsWriter, ServeError, Stream,
+    StreamReader, StreamWriter, Subgroups, SubgroupsReader, SubgroupsWriter, TrackEvent,
+    TrackEventsWriter,
 };
-use crate::coding::{Location, TrackNamespace};
+use crate::coding::{Location, Telemetry, Token, TrackNamespace};
 use paste::paste;
-use std::{ops::Deref, sync::Arc};
+use std::{
+    future,
+    ops::{Deref, RangeInclusive},
+    sync::Arc,
+    task,
+    time::Duration,
+};
 
 /// Static information about a track.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Track {
     pub namespace: TrackNamespace,
     pub name: String,
+
+    /// Trace ID of the subscription that caused this track to be requested, if any.
+    /// Propagated upstream so a subscription can be followed across relay hops.
+    pub trace_id: Option<String>,
+
+    /// Authorization token sent with the SUBSCRIBE for this track, if any.
+    pub authorization_token: Option<Token>,
+
+    /// Subscribe so delivery starts at object 0 of a group, never mid-group, ex. for a video
+    /// decoder that needs the group-leading keyframe.
+    pub group_start: bool,
+
+    /// Subscribe from this location with an AbsoluteStart filter, ex. to resume after a reconnect.
+    /// Takes precedence over [Self::group_start].
+    pub start: Option<Location>,
+
+    /// Sent as DELIVERY_TIMEOUT, asking the publisher to skip objects it can't start sending
+    /// within this long of their creation, instead of queueing them behind a slow uplink.
+    pub delivery_timeout: Option<Duration>,
+
+    /// Sent as MAX_BITRATE, asking the publisher to pace delivery to this many bits per second,
+    /// so a burst from upstream doesn't overwhelm the subscriber's link.
+    pub max_bitrate: Option<u64>,
+
+    /// Sent as CACHED_GROUPS, telling the publisher these groups are already cached here so it
+    /// doesn't send them again, ex. after a relay's upstream session was briefly lost.
+    pub cached_groups: Option<RangeInclusive<u64>>,
+
+    /// Sent as TELEMETRY, reporting the subscriber's playback to the publisher.
+    pub telemetry: Option<Telemetry>,
+
+    /// Expire the groups buffered for readers that fell behind once this many newer group IDs
+    /// were created, so a paused reader can't hold on to the whole track, see [Backpressure::Buffer].
+    pub max_groups: Option<u64>,
+
+    /// Expire the groups buffered for readers that fell behind this long after their creation.
+    pub max_age: Option<Duration>,
 }
 
 impl Track {
     pub fn new(namespace: TrackNamespace, name: String) -> Self {
-        Self { namespace, name }
+        Self {
+            namespace,
+            name,
+            trace_id: None,
+            authorization_token: None,
+            group_start: false,
+            start: None,
+            delivery_timeout: None,
+            max_bitrate: None,
+            cached_groups: None,
+            telemetry: None,
+            max_groups: None,
+            max_age: None,
+        }
+    }
+
+    pub fn with_trace_id(mut self, trace_id: Option<String>) -> Self {
+        self.trace_id = trace_id;
+        self
+    }
+
+    pub fn with_authorization_token(mut self, token: Option<Token>) -> Self {
+        self.authorization_token = token;
+        self
+    }
+
+    pub fn with_group_start(mut self, group_start: bool) -> Self {
+        self.group_start = group_start;
+        self
+    }
+
+    pub fn with_start(mut self, start: Option<Location>) -> Self {
+        self.start = start;
+        self
+    }
+
+    pub fn with_delivery_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.delivery_timeout = timeout;
+        self
+    }
+
+    pub fn with_max_bitrate(mut self, bitrate: Option<u64>) -> Self {
+        self.max_bitrate = bitrate;
+        self
+    }
+
+    pub fn with_cached_groups(mut self, groups: Option<RangeInclusive<u64>>) -> Self {
+        self.cached_groups = groups;
+        self
+    }
+
+    pub fn with_telemetry(mut self, telemetry: Option<Telemetry>) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    pub fn with_max_groups(mut self, max_groups: Option<u64>) -> Self {
+        self.max_groups = max_groups;
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: Option<Duration>) -> Self {
+        self.max_age = max_age;
+        self
     }
 
     pub fn produce(self) -> (TrackWriter, TrackReader) {
         // Create sharable TrackState and Info(Track)
         let (writer_track_state, reader_track_state) = State::default().split();
+        let (writer_events, reader_events) = track_events();
         let info = Arc::new(self);
+        let retention = Retention::default();
 
         // Create TrackReader and TrackWriter with shared state and info
-        let writer = TrackWriter::new(writer_track_state, info.clone());
-        let reader = TrackReader::new(reader_track_state, info);
+        let writer = TrackWriter::new(
+            writer_track_state,
+            writer_events,
+            info.clone(),
+            retention.clone(),
+        );
+        let reader = TrackReader::new(reader_track_state, reader_events, info, retention);
 
         (writer, reader)
     }
@@ -50,6 +168,12 @@ impl Track {
 struct TrackState {
     /// The ReaderMode for this track. Set to None on creation.
     reader_mode: Option<TrackReaderMode>,
+    /// Whether past objects may be cached and served via FETCH. Cleared for live-only tracks.
+    fetchable: bool,
+    /// The declared bitrate in bits per second, if known.
+    bitrate: Option<u64>,
+    /// The largest location the publisher reported, ex. in SUBSCRIBE_OK, if any.
+    largest_location: Option<Location>,
     /// Watchable closed state
     closed: Result<(), ServeError>,
 }
@@ -58,6 +182,9 @@ impl Default for TrackState {
     fn default() -> Self {
         Self {
             reader_mode: None,
+            fetchable: true,
+            bitrate: None,
+            largest_location: None,
             closed: Ok(()),
         }
     }
@@ -66,13 +193,26 @@ impl Default for TrackState {
 /// Creates new streams for a track.
 pub struct TrackWriter {
     state: State<TrackState>,
+    events: TrackEventsWriter,
     pub info: Arc<Track>,
+    // The buffers of the readers, kept even before the track is in subgroups mode.
+    retention: Retention,
 }
 
 impl TrackWriter {
     /// Create a track with the given name (info/Track)
-    fn new(state: State<TrackState>, info: Arc<Track>) -> Self {
-        Self { state, info }
+    fn new(
+        state: State<TrackState>,
+        events: TrackEventsWriter,
+        info: Arc<Track>,
+        retention: Retention,
+    ) -> Self {
+        Self {
+            state,
+            events,
+            info,
+            retention,
+        }
     }
 
     /// Create a new stream with the given priority, inserting it into the track.
@@ -98,7 +238,7 @@ impl TrackWriter {
         let (writer, reader) = Subgroups {
             track: self.info.clone(),
         }
-        .produce();
+        .produce_retained(self.retention.clone());
 
         // Lock state to modify it
         let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
@@ -122,6 +262,37 @@ impl TrackWriter {
         Ok(writer)
     }
 
+    /// Mark the track as fetchable (the default) or live-only.
+    /// Live-only tracks are never cached, and FETCH requests for them are refused.
+    pub fn set_fetchable(&self, fetchable: bool) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        state.fetchable = fetchable;
+        Ok(())
+    }
+
+    /// Declare the track's bitrate in bits per second, ex. from the catalog.
+    /// Subscriptions are paced to it, with headroom to catch up after a stall.
+    pub fn set_bitrate(&self, bitrate: Option<u64>) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        state.bitrate = bitrate;
+        Ok(())
+    }
+
+    /// Report the largest location the publisher has, ex. from SUBSCRIBE_OK, so readers
+    /// can tell the live edge before any object arrives. See [TrackReader::latest].
+    pub fn set_largest_location(&self, location: Option<Location>) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+        state.largest_location = location;
+        Ok(())
+    }
+
+    /// A handle to report the track's [TrackEvent]s, which outlives the writer, so take it
+    /// before choosing a mode with [Self::subgroups] or [Self::datagrams].
+    /// Readers see no more events once every handle is dropped.
+    pub fn events(&self) -> TrackEventsWriter {
+        self.events.clone()
+    }
+
     /// Close the track with an error.
     pub fn close(self, err: ServeError) -> Result<(), ServeError> {
         let state = self.state.lock();
@@ -145,38 +316,115 @@ impl Deref for TrackWriter {
 #[derive(Clone)]
 pub struct TrackReader {
     state: State<TrackState>,
+    events: TrackEventsReader,
     pub info: Arc<Track>,
+    backpressure: Backpressure,
+    retention: Retention,
+    // Buffers subgroups from now on, before [Self::mode] is first called.
+    hold: Option<Arc<RetentionHold>>,
 }
 
 impl TrackReader {
-    fn new(state: State<TrackState>, info: Arc<Track>) -> Self {
-        Self { state, info }
+    fn new(
+        state: State<TrackState>,
+        events: TrackEventsReader,
+        info: Arc<Track>,
+        retention: Retention,
+    ) -> Self {
+        Self {
+            state,
+            events,
+            info,
+            backpressure: Backpressure::Latest,
+            retention,
+            hold: None,
+        }
+    }
+
+    /// Change how subgroups are delivered when this reader falls behind.
+    /// Only applies to tracks in subgroups mode.
+    pub fn with_backpressure(mut self, backpressure: Backpressure) -> Self {
+        self.hold = match backpressure {
+            Backpressure::Buffer(size) => Some(self.retention.hold(size)),
+            Backpressure::Latest => None,
+        };
+        self.backpressure = backpressure;
+        self
+    }
+
+    /// Split the track into independent in-process consumers, one per backpressure policy.
+    ///
+    /// Every consumer shares the same upstream, so a single subscription can feed a player,
+    /// a recorder, and an analyzer without any of them slowing down the others.
+    pub fn tee(&self, policies: &[Backpressure]) -> Vec<TrackReader> {
+        policies
+            .iter()
+            .map(|policy| self.clone().with_backpressure(*policy))
+            .collect()
     }
 
     /// Get the current mode of the track, waiting if necessary.
     pub async fn mode(&self) -> Result<TrackReaderMode, ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
-                if let Some(mode) = &state.reader_mode {
-                    return Ok(mode.clone());
-                }
+        future::poll_fn(|cx| self.poll_mode(cx)).await
+    }
 
-                state.closed.clone()?;
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Err(ServeError::Done),
-                }
-            }
-            .await;
+    /// Like [Self::mode], returning Pending and waking `cx` when the track changes.
+    pub fn poll_mode(
+        &self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Result<TrackReaderMode, ServeError>> {
+        let state = self.state.lock();
+        if let Some(mode) = &state.reader_mode {
+            return task::Poll::Ready(Ok(match mode {
+                TrackReaderMode::Subgroups(subgroups) => subgroups
+                    .clone()
+                    .with_backpressure(self.backpressure)
+                    .into(),
+                mode => mode.clone(),
+            }));
+        }
+
+        state.closed.clone()?;
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Err(ServeError::Done)),
         }
     }
 
-    // Returns the largest group/sequence
-    pub fn largest_location(&self) -> Option<Location> {
-        // We don't even know the mode yet.
-        // TODO populate from SUBSCRIBE_OK
-        None
+    /// Returns false if the publisher marked this track as live-only.
+    pub fn is_fetchable(&self) -> bool {
+        self.state.lock().fetchable
+    }
+
+    /// The bitrate declared with [TrackWriter::set_bitrate], if any.
+    pub fn bitrate(&self) -> Option<u64> {
+        self.state.lock().bitrate
+    }
+
+    /// The live edge: the largest location written to the track, or reported by the
+    /// publisher with [TrackWriter::set_largest_location], whichever is larger.
+    pub fn latest(&self) -> Option<Location> {
+        let state = self.state.lock();
+        let written = state
+            .reader_mode
+            .as_ref()
+            .and_then(TrackReaderMode::latest)
+            .map(|(group_id, object_id)| Location::new(group_id, object_id));
+
+        written.max(state.largest_location)
+    }
+
+    /// The next object status marker of the track, waiting for it, or `None` once no more
+    /// will be reported. Each clone reads every event from where it was cloned, skipping
+    /// the oldest if it falls far behind.
+    pub async fn next_event(&mut self) -> Option<TrackEvent> {
+        self.events.next().await
+    }
+
+    /// Where the publisher marked the end of the track, if it did, telling a track that ended
+    /// cleanly apart from one whose source went away.
+    pub fn end_of_track(&self) -> Option<Location> {
+        self.events.end_of_track()
     }
 
     /// Wait until the track is closed, returning the closing error.
@@ -67,11 +67,7 @@ impl TracksWriter {
     /// The track will use this writer's namespace.
     /// None is returned if all [TracksReader]s have been dropped.
     pub fn create(&mut self, track: &str) -> Option<TrackWriter> {
-        let (writer, reader) = Track {
-            namespace: self.namespace.clone(),
-            name: track.to_owned(),
-        }
-        .produce();
+        let (writer, reader) = Track::new(self.namespace.clone(), track.to_owned()).produce();
 
         // NOTE: We overwrite the track if it already exists.
         let full_name = FullTrackName {
@@ -158,6 +154,11 @@ impl TracksReader {
         Self { state, queue, info }
     }
 
+    /// Whether the [TracksRequest] was dropped, so unknown tracks can no longer be requested.
+    pub fn is_closed(&self) -> bool {
+        self.queue.is_closed()
+    }
+
     /// Get a track from the broadcast by full name, if it exists.
     pub fn get_track_reader(
         &mut self,
@@ -183,6 +184,17 @@ impl TracksReader {
         &mut self,
         namespace: TrackNamespace,
         track_name: &str,
+    ) -> Option<TrackReader> {
+        self.subscribe_with_trace_id(namespace, track_name, None)
+    }
+
+    /// Same as [Self::subscribe], but tags a newly requested track with the given trace ID.
+    /// An existing track keeps the trace ID of the subscription that first requested it.
+    pub fn subscribe_with_trace_id(
+        &mut self,
+        namespace: TrackNamespace,
+        track_name: &str,
+        trace_id: Option<String>,
     ) -> Option<TrackReader> {
         let state = self.state.lock();
         let full_name = FullTrackName {
@@ -196,11 +208,9 @@ impl TracksReader {
 
         let mut state = state.into_mut()?;
         // Use the full requested namespace, not self.namespace
-        let track_writer_reader = Track {
-            namespace: namespace.clone(),
-            name: track_name.to_owned(),
-        }
-        .produce();
+        let track_writer_reader = Track::new(namespace.clone(), track_name.to_owned())
+            .with_trace_id(trace_id)
+            .produce();
 
         if self.queue.push(track_writer_reader.0).is_err() {
             return None;
@@ -0,0 +1,47 @@
+use crate::coding::{Location, Telemetry, TrackNamespace};
+
+/// How far a request has progressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestState {
+    /// Waiting for the OK response.
+    Pending,
+
+    /// Accepted, and not yet ended.
+    Active,
+}
+
+/// A subscription, as listed by [super::Publisher::subscriptions] and [super::Subscriber::subscriptions].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionSnapshot {
+    pub id: u64,
+    pub track_namespace: TrackNamespace,
+    pub track_name: String,
+
+    /// The track alias, once SUBSCRIBE_OK has been sent or received.
+    pub track_alias: Option<u64>,
+
+    pub state: RequestState,
+
+    /// The latest fully delivered object, see [super::SubscriptionPosition].
+    pub position: Option<Location>,
+
+    /// The latest telemetry the subscriber reported, if any.
+    pub telemetry: Option<Telemetry>,
+}
+
+/// A namespace announced by the peer, as listed by [super::Subscriber::announces].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnnounceSnapshot {
+    pub request_id: u64,
+    pub namespace: TrackNamespace,
+    pub state: RequestState,
+}
+
+impl RequestState {
+    pub(super) fn from_ok(ok: bool) -> Self {
+        match ok {
+            true => Self::Active,
+            false => Self::Pending,
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use crate::serve::FullTrackName;
+
+/// Assigns the track aliases of the subscriptions to our tracks, sent in SUBSCRIBE_OK and used by
+/// every subgroup and datagram of the subscription.
+///
+/// Aliases are assigned in order, starting at 0, following the rules of draft-14 Section 9.8:
+/// - An alias never refers to two tracks at once, so concurrent subscriptions to the same track
+///   get different aliases.
+/// - Once a subscription ends, its alias is only reused for the same track, so objects of the old
+///   subscription arriving late can't be mistaken for another track's.
+#[derive(Debug, Default)]
+pub(crate) struct TrackAliases {
+    next: u64,
+
+    /// The track of each alias in use.
+    active: HashMap<u64, FullTrackName>,
+
+    /// The latest alias of each track, which it gets back when subscribed again.
+    latest: HashMap<FullTrackName, u64>,
+}
+
+impl TrackAliases {
+    /// Assign an alias to a new subscription to `track`, until it's released.
+    pub fn assign(&mut self, track: FullTrackName) -> u64 {
+        let alias = match self.latest.get(&track) {
+            Some(alias) if !self.active.contains_key(alias) => *alias,
+            _ => {
+                let alias = self.next;
+                self.next += 1;
+                alias
+            }
+        };
+
+        self.latest.insert(track.clone(), alias);
+        self.active.insert(alias, track);
+        alias
+    }
+
+    /// Release the alias of a subscription that ended.
+    pub fn release(&mut self, alias: u64) {
+        self.active.remove(&alias);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coding::TrackNamespace;
+
+    fn track(name: &str) -> FullTrackName {
+        FullTrackName {
+            namespace: TrackNamespace::from_utf8_path("live"),
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn reuse() {
+        let mut aliases = TrackAliases::default();
+        assert_eq!(aliases.assign(track("video")), 0);
+        assert_eq!(aliases.assign(track("audio")), 1);
+
+        // Never shared by subscriptions at once, even to the same track.
+        assert_eq!(aliases.assign(track("video")), 2);
+
+        // Released aliases only go back to the same track.
+        aliases.release(1);
+        assert_eq!(aliases.assign(track("captions")), 3);
+        assert_eq!(aliases.assign(track("audio")), 1);
+
+        aliases.release(2);
+        assert_eq!(aliases.assign(track("video")), 2);
+    }
+}
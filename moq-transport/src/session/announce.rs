@@ -1,15 +1,21 @@
 use std::{collections::VecDeque, ops};
 
-use crate::coding::TrackNamespace;
+use crate::coding::{KeyValuePairs, Token, TrackNamespace};
 use crate::watch::State;
 use crate::{message, serve::ServeError};
 
-use super::{Publisher, Subscribed, TrackStatusRequested};
+use super::{auth_token_param, Publisher, Subscribed, TrackStatusRequested};
 
 #[derive(Debug, Clone)]
 pub struct AnnounceInfo {
     pub request_id: u64,
     pub namespace: TrackNamespace,
+
+    /// Authorization tokens carried by the PUBLISH_NAMESPACE, with aliases resolved.
+    pub authorization_tokens: Vec<Token>,
+
+    /// Every parameter of the PUBLISH_NAMESPACE, including those this library doesn't interpret.
+    pub params: KeyValuePairs,
 }
 
 struct AnnounceState {
@@ -55,16 +61,24 @@ impl Announce {
         mut publisher: Publisher,
         request_id: u64,
         namespace: TrackNamespace,
+        token: Option<Token>,
+        mut params: KeyValuePairs,
     ) -> (Announce, AnnounceRecv) {
+        if let Some(token) = &token {
+            auth_token_param(&mut params, token);
+        }
+
         let info = AnnounceInfo {
             request_id,
             namespace: namespace.clone(),
+            authorization_tokens: token.into_iter().collect(),
+            params: params.clone(),
         };
 
         publisher.send_message(message::PublishNamespace {
             id: request_id,
             track_namespace: namespace.clone(),
-            params: Default::default(),
+            params,
         });
 
         let (send, recv) = State::default().split();
@@ -77,6 +91,7 @@ impl Announce {
         let recv = AnnounceRecv {
             state: recv,
             request_id,
+            routed: true,
         };
 
         (send, recv)
@@ -182,6 +197,10 @@ impl ops::Deref for Announce {
 pub(super) struct AnnounceRecv {
     state: State<AnnounceState>,
     pub request_id: u64, // TODO SLG - Announcements need to be looked up by both request_id and namespace, consider 2 hashmaps in publisher instead of this
+
+    /// Whether subscriptions and track status requests for the namespace are routed here,
+    /// see [Publisher::announce_namespace].
+    pub routed: bool,
 }
 
 impl AnnounceRecv {
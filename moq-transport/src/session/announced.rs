@@ -1,15 +1,17 @@
 use std::ops;
 
-use crate::coding::{ReasonPhrase, TrackNamespace};
+use crate::coding::{KeyValuePairs, ReasonPhrase, Token, TrackNamespace};
 use crate::watch::State;
 use crate::{message, serve::ServeError};
 
-use super::{AnnounceInfo, Subscriber};
+use super::{AnnounceInfo, AnnounceSnapshot, RequestState, Subscriber};
 
-// There's currently no feedback from the peer, so the shared state is empty.
+// There's currently no feedback from the peer, so the shared state only records our response.
 // If Unannounce contained an error code then we'd be talking.
 #[derive(Default)]
-struct AnnouncedState {}
+struct AnnouncedState {
+    ok: bool,
+}
 
 pub struct Announced {
     session: Subscriber,
@@ -19,6 +21,7 @@ pub struct Announced {
 
     ok: bool,
     error: Option<ServeError>,
+    rejected: Option<(u64, String)>,
 }
 
 impl Announced {
@@ -26,10 +29,14 @@ impl Announced {
         session: Subscriber,
         request_id: u64,
         namespace: TrackNamespace,
+        authorization_tokens: Vec<Token>,
+        params: KeyValuePairs,
     ) -> (Announced, AnnouncedRecv) {
         let info = AnnounceInfo {
             request_id,
             namespace,
+            authorization_tokens,
+            params,
         };
 
         let (send, recv) = State::default().split();
@@ -38,9 +45,13 @@ impl Announced {
             info,
             ok: false,
             error: None,
+            rejected: None,
             state: send,
         };
-        let recv = AnnouncedRecv { _state: recv };
+        let recv = AnnouncedRecv {
+            state: recv,
+            request_id,
+        };
 
         (send, recv)
     }
@@ -56,6 +67,9 @@ impl Announced {
         });
 
         self.ok = true;
+        if let Some(mut state) = self.state.lock_mut() {
+            state.ok = true;
+        }
 
         Ok(())
     }
@@ -76,6 +90,21 @@ impl Announced {
         self.error = Some(err);
         Ok(())
     }
+
+    /// Reply with PUBLISH_NAMESPACE_ERROR, telling the publisher why the namespace wasn't accepted.
+    /// Once accepted, use [Self::close] to cancel the announce instead.
+    pub fn reject(
+        mut self,
+        code: message::PublishNamespaceErrorCode,
+        reason: &str,
+    ) -> Result<(), ServeError> {
+        if self.ok {
+            return Err(ServeError::Duplicate);
+        }
+
+        self.rejected = Some((code.code(), reason.to_string()));
+        Ok(())
+    }
 }
 
 impl ops::Deref for Announced {
@@ -97,6 +126,12 @@ impl Drop for Announced {
                 error_code: err.code(),
                 reason_phrase: ReasonPhrase(err.to_string()),
             });
+        } else if let Some((error_code, reason)) = self.rejected.take() {
+            self.session.send_message(message::PublishNamespaceError {
+                id: self.info.request_id,
+                error_code,
+                reason_phrase: ReasonPhrase(reason),
+            });
         } else {
             self.session.send_message(message::PublishNamespaceError {
                 id: self.info.request_id,
@@ -108,10 +143,19 @@ impl Drop for Announced {
 }
 
 pub(super) struct AnnouncedRecv {
-    _state: State<AnnouncedState>,
+    state: State<AnnouncedState>,
+    pub request_id: u64,
 }
 
 impl AnnouncedRecv {
+    pub fn snapshot(&self, namespace: &TrackNamespace) -> AnnounceSnapshot {
+        AnnounceSnapshot {
+            request_id: self.request_id,
+            namespace: namespace.clone(),
+            state: RequestState::from_ok(self.state.lock().ok),
+        }
+    }
+
     pub fn recv_unannounce(self) -> Result<(), ServeError> {
         // Will cause the state to be dropped
         Ok(())
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use crate::coding::{AuthToken, Decode, Encode, KeyValuePair, KeyValuePairs, Token, Value};
+use crate::message;
+
+use super::SessionError;
+
+/// The MAX_AUTH_TOKEN_CACHE_SIZE advertised by a server.
+pub const DEFAULT_AUTH_TOKEN_CACHE_SIZE: u64 = 4096;
+
+/// Token aliases registered by the peer, following the caching rules of draft-14 Section 9.2.1.
+///
+/// Aliases are scoped to the session, so a token registered in CLIENT_SETUP or one request
+/// can be referenced by any later request.
+#[derive(Debug, Default)]
+pub(crate) struct AuthTokenCache {
+    /// The MAX_AUTH_TOKEN_CACHE_SIZE we advertised; zero if the peer may not register aliases.
+    max_size: u64,
+    size: u64,
+    aliases: HashMap<u64, Token>,
+}
+
+impl AuthTokenCache {
+    pub fn new(max_size: u64) -> Self {
+        Self {
+            max_size,
+            ..Default::default()
+        }
+    }
+
+    /// Apply every AUTHORIZATION TOKEN parameter in `params`, returning the tokens the request uses.
+    pub fn resolve(&mut self, params: &KeyValuePairs) -> Result<Vec<Token>, SessionError> {
+        let mut tokens = Vec::new();
+
+        for kvp in &params.0 {
+            if kvp.key != u64::from(message::ParameterType::AuthorizationToken) {
+                continue;
+            }
+
+            let mut bytes = match &kvp.value {
+                Value::BytesValue(bytes) => bytes.as_slice(),
+                Value::IntValue(_) => return Err(SessionError::MalformedAuthToken),
+            };
+            let token =
+                AuthToken::decode(&mut bytes).map_err(|_| SessionError::MalformedAuthToken)?;
+
+            if let Some(token) = self.apply(token)? {
+                tokens.push(token);
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn apply(&mut self, token: AuthToken) -> Result<Option<Token>, SessionError> {
+        match token {
+            AuthToken::Delete { alias } => {
+                let token = self
+                    .aliases
+                    .remove(&alias)
+                    .ok_or(SessionError::UnknownAuthTokenAlias(alias))?;
+                self.size -= Self::token_size(&token);
+                Ok(None)
+            }
+            AuthToken::Register { alias, token } => {
+                if self.aliases.contains_key(&alias) {
+                    return Err(SessionError::DuplicateAuthTokenAlias(alias));
+                }
+
+                let size = Self::token_size(&token);
+                if self.size + size > self.max_size {
+                    return Err(SessionError::AuthTokenCacheOverflow);
+                }
+
+                self.size += size;
+                self.aliases.insert(alias, token.clone());
+                Ok(Some(token))
+            }
+            AuthToken::UseAlias { alias } => self
+                .aliases
+                .get(&alias)
+                .cloned()
+                .map(Some)
+                .ok_or(SessionError::UnknownAuthTokenAlias(alias)),
+            AuthToken::UseValue { token } => Ok(Some(token)),
+        }
+    }
+
+    // The draft leaves the accounting to the implementation; we count the token value plus 8 bytes for its type.
+    fn token_size(token: &Token) -> u64 {
+        token.value.len() as u64 + 8
+    }
+}
+
+/// Build the parameter carrying `token` by value.
+pub(super) fn auth_token_param(params: &mut KeyValuePairs, token: &Token) {
+    let mut buf = Vec::new();
+    AuthToken::UseValue {
+        token: token.clone(),
+    }
+    .encode(&mut buf)
+    .expect("encoding to a Vec can't fail");
+
+    // AUTHORIZATION TOKEN has the same type in setup and request parameters.
+    params.0.push(KeyValuePair::new_bytes(
+        message::ParameterType::AuthorizationToken.into(),
+        buf,
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(tokens: &[AuthToken]) -> KeyValuePairs {
+        let mut params = KeyValuePairs::default();
+        for token in tokens {
+            let mut buf = Vec::new();
+            token.encode(&mut buf).unwrap();
+            params.0.push(KeyValuePair::new_bytes(
+                message::ParameterType::AuthorizationToken.into(),
+                buf,
+            ));
+        }
+        params
+    }
+
+    #[test]
+    fn alias_caching() {
+        let mut cache = AuthTokenCache::new(20);
+        let token = Token::new(0, b"secret".to_vec());
+
+        let register = params(&[AuthToken::Register {
+            alias: 1,
+            token: token.clone(),
+        }]);
+        assert_eq!(cache.resolve(&register).unwrap(), vec![token.clone()]);
+
+        // Registering the same alias twice is a protocol error.
+        assert!(matches!(
+            cache.resolve(&register),
+            Err(SessionError::DuplicateAuthTokenAlias(1))
+        ));
+
+        let use_alias = params(&[AuthToken::UseAlias { alias: 1 }]);
+        assert_eq!(cache.resolve(&use_alias).unwrap(), vec![token.clone()]);
+
+        // Another 14 byte token would exceed the cache size.
+        let overflow = params(&[AuthToken::Register {
+            alias: 2,
+            token: token.clone(),
+        }]);
+        assert!(matches!(
+            cache.resolve(&overflow),
+            Err(SessionError::AuthTokenCacheOverflow)
+        ));
+
+        // Deleting frees the space, and the alias can no longer be used.
+        let delete = params(&[AuthToken::Delete { alias: 1 }]);
+        assert_eq!(cache.resolve(&delete).unwrap(), vec![]);
+        assert!(matches!(
+            cache.resolve(&use_alias),
+            Err(SessionError::UnknownAuthTokenAlias(1))
+        ));
+        assert_eq!(cache.resolve(&overflow).unwrap(), vec![token]);
+    }
+
+    #[test]
+    fn malformed() {
+        let mut cache = AuthTokenCache::default();
+
+        let mut params = KeyValuePairs::default();
+        params.0.push(KeyValuePair::new_bytes(
+            message::ParameterType::AuthorizationToken.into(),
+            vec![0x07],
+        ));
+        assert!(matches!(
+            cache.resolve(&params),
+            Err(SessionError::MalformedAuthToken)
+        ));
+
+        // A server that advertised no cache accepts tokens by value only.
+        let token = Token::new(0, b"secret".to_vec());
+        let mut params = KeyValuePairs::default();
+        auth_token_param(&mut params, &token);
+        assert_eq!(cache.resolve(&params).unwrap(), vec![token.clone()]);
+
+        let params = self::params(&[AuthToken::Register { alias: 1, token }]);
+        assert!(matches!(
+            cache.resolve(&params),
+            Err(SessionError::AuthTokenCacheOverflow)
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::BytesMut;
+
+/// Recycles stream buffers, so opening a stream per group doesn't allocate a fresh buffer each time.
+#[derive(Clone, Default)]
+pub(super) struct BufferPool {
+    buffers: Arc<Mutex<Vec<BytesMut>>>,
+}
+
+impl BufferPool {
+    // Enough for the streams of several concurrent groups.
+    const MAX_BUFFERS: usize = 64;
+
+    // Smaller buffers aren't worth keeping, and larger ones would pin memory after a burst.
+    const MIN_CAPACITY: usize = 1024;
+    const MAX_CAPACITY: usize = 64 * 1024;
+
+    /// Take an empty buffer from the pool, or allocate one lazily if the pool is empty.
+    pub fn get(&self) -> BytesMut {
+        self.buffers.lock().unwrap().pop().unwrap_or_default()
+    }
+
+    /// Return a buffer to the pool once its stream is done.
+    pub fn put(&self, mut buffer: BytesMut) {
+        buffer.clear();
+        if !(Self::MIN_CAPACITY..=Self::MAX_CAPACITY).contains(&buffer.capacity()) {
+            return;
+        }
+
+        let mut buffers = self.buffers.lock().unwrap();
+        if buffers.len() < Self::MAX_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse() {
+        let pool = BufferPool::default();
+
+        let mut buffer = pool.get();
+        buffer.extend_from_slice(&[0u8; 2048]);
+        let ptr = buffer.as_ptr();
+        pool.put(buffer);
+
+        // The same allocation comes back, emptied.
+        let buffer = pool.get();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.as_ptr(), ptr);
+
+        // Tiny and huge buffers are dropped instead of pooled.
+        pool.put(BytesMut::with_capacity(16));
+        pool.put(BytesMut::with_capacity(1024 * 1024));
+        assert_eq!(pool.buffers.lock().unwrap().len(), 0);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::coding::KeyValuePairs;
+use crate::data;
+use crate::setup::ParameterType;
+
+// The SETUP parameters interpreted by this implementation.
+const KNOWN_PARAMS: [ParameterType; 6] = [
+    ParameterType::Path,
+    ParameterType::MaxRequestId,
+    ParameterType::AuthorizationToken,
+    ParameterType::MaxAuthTokenCacheSize,
+    ParameterType::Authority,
+    ParameterType::MOQTImplementation,
+];
+
+/// What the peer of a [crate::session::Session] supports, from its SETUP parameters and the
+/// QUIC connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCapabilities {
+    /// The largest datagram we can send the peer, or None if it doesn't accept datagrams.
+    /// WebTransport requires datagrams, so they're accepted at any size unless the transport
+    /// says otherwise, see [crate::session::Session::with_max_datagram_size].
+    pub max_datagram_size: Option<usize>,
+
+    /// The MAX_REQUEST_ID the peer sent in SETUP, if any.
+    pub max_request_id: Option<u64>,
+
+    /// The MOQT_IMPLEMENTATION the peer sent in SETUP, if any.
+    pub implementation: Option<String>,
+
+    /// The types of the SETUP parameters we don't interpret, ex. extensions the peer supports.
+    pub extensions: Vec<u64>,
+}
+
+impl PeerCapabilities {
+    pub(super) fn from_params(params: &KeyValuePairs) -> Self {
+        let extensions = params
+            .0
+            .iter()
+            .map(|param| param.key)
+            .filter(|key| !KNOWN_PARAMS.iter().any(|known| u64::from(*known) == *key))
+            .collect();
+
+        Self {
+            max_request_id: params.get_intvalue(ParameterType::MaxRequestId.into()),
+            implementation: params
+                .get_bytesvalue(ParameterType::MOQTImplementation.into())
+                .map(|value| String::from_utf8_lossy(value).into_owned()),
+            extensions,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the peer accepts datagrams at all.
+    pub fn datagrams(&self) -> bool {
+        self.max_datagram_size.is_some()
+    }
+
+    /// Whether the peer accepts a datagram of `size` bytes.
+    pub fn accepts_datagram(&self, size: usize) -> bool {
+        self.max_datagram_size.is_some_and(|max| size <= max)
+    }
+
+    /// The largest object payload that fits in a datagram to the peer, leaving room for the
+    /// header, or None if any size does. Zero if the peer doesn't accept datagrams.
+    pub fn max_datagram_payload(&self) -> Option<usize> {
+        match self.max_datagram_size {
+            Some(usize::MAX) => None,
+            Some(max) => Some(max.saturating_sub(data::Datagram::MAX_HEADER_SIZE)),
+            None => Some(0),
+        }
+    }
+}
+
+impl Default for PeerCapabilities {
+    fn default() -> Self {
+        Self {
+            max_datagram_size: Some(usize::MAX),
+            max_request_id: None,
+            implementation: None,
+            extensions: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_params() {
+        let mut params = KeyValuePairs::new();
+        params.set_intvalue(ParameterType::MaxRequestId.into(), 100);
+        params.set_bytesvalue(ParameterType::MOQTImplementation.into(), b"moq-rs".to_vec());
+        params.set_intvalue(0x40, 1);
+
+        let capabilities = PeerCapabilities::from_params(&params);
+        assert_eq!(capabilities.max_request_id, Some(100));
+        assert_eq!(capabilities.implementation.as_deref(), Some("moq-rs"));
+        assert_eq!(capabilities.extensions, vec![0x40]);
+
+        // Datagrams are assumed until the transport says otherwise.
+        assert!(capabilities.accepts_datagram(1 << 20));
+        assert_eq!(capabilities.max_datagram_payload(), None);
+        let capabilities = PeerCapabilities {
+            max_datagram_size: Some(1200),
+            ..capabilities
+        };
+        assert!(capabilities.accepts_datagram(1200));
+        assert!(!capabilities.accepts_datagram(1201));
+        assert_eq!(capabilities.max_datagram_payload(), Some(1174));
+
+        let capabilities = PeerCapabilities {
+            max_datagram_size: None,
+            ..capabilities
+        };
+        assert_eq!(capabilities.max_datagram_payload(), Some(0));
+    }
+}
@@ -42,6 +42,32 @@ pub enum SessionError {
 
     #[error("wrong size")]
     WrongSize,
+
+    /// A datagram was larger than the peer accepts, so it wasn't sent. Unlike [Self::Session],
+    /// the session is fine, and the object can still be sent on a stream.
+    #[error("datagram too large: {size} > {max} bytes")]
+    DatagramTooLarge { size: usize, max: usize },
+
+    /// The peer's authorization tokens were rejected.
+    #[error("unauthorized")]
+    Unauthorized,
+
+    /// Registering a token alias would exceed the MAX_AUTH_TOKEN_CACHE_SIZE we advertised.
+    #[error("auth token cache overflow")]
+    AuthTokenCacheOverflow,
+
+    #[error("duplicate auth token alias: {0}")]
+    DuplicateAuthTokenAlias(u64),
+
+    #[error("malformed auth token")]
+    MalformedAuthToken,
+
+    #[error("unknown auth token alias: {0}")]
+    UnknownAuthTokenAlias(u64),
+
+    /// The peer sent a message it shouldn't have, ex. a response to a request we never sent.
+    #[error("protocol violation: {0}")]
+    ProtocolViolation(String),
 }
 
 // Session Termination Error Codes from draft-ietf-moq-transport-14 Section 13.1.1
@@ -59,13 +85,25 @@ impl SessionError {
             Self::Encode(_) => 0x1,
             Self::BoundsExceeded(_) => 0x1,
             Self::Internal => 0x1,
+            Self::DatagramTooLarge { .. } => 0x1,
             // VERSION_NEGOTIATION_FAILED (0x15)
             Self::Version(..) => 0x15,
             // PROTOCOL_VIOLATION (0x3) - Malformed messages
             Self::Decode(_) => 0x3,
             Self::WrongSize => 0x3,
+            Self::ProtocolViolation(_) => 0x3,
             // DUPLICATE_TRACK_ALIAS (0x5)
             Self::Duplicate => 0x5,
+            // UNAUTHORIZED (0x2)
+            Self::Unauthorized => 0x2,
+            // AUTH_TOKEN_CACHE_OVERFLOW (0x13)
+            Self::AuthTokenCacheOverflow => 0x13,
+            // DUPLICATE_AUTH_TOKEN_ALIAS (0x14)
+            Self::DuplicateAuthTokenAlias(_) => 0x14,
+            // MALFORMED_AUTH_TOKEN (0x16)
+            Self::MalformedAuthToken => 0x16,
+            // UNKNOWN_AUTH_TOKEN_ALIAS (0x17)
+            Self::UnknownAuthTokenAlias(_) => 0x17,
             // Delegate to ServeError for per-request error codes
             Self::Serve(err) => err.code(),
         }
@@ -0,0 +1,134 @@
+use bytes::Bytes;
+
+use super::{subscribed::fetch_stream_priority, Publisher, SessionError, Writer};
+use crate::coding::{KeyValuePairs, Location, ReasonPhrase};
+use crate::data::{self, ExtensionHeaders, ObjectStatus};
+use crate::message::{self, GroupOrder};
+use crate::serve::ServeError;
+
+/// An object sent in response to a FETCH.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchedObject {
+    pub group_id: u64,
+    pub subgroup_id: u64,
+    pub object_id: u64,
+    pub priority: u8,
+    pub status: ObjectStatus,
+    pub extension_headers: ExtensionHeaders,
+    pub payload: Bytes,
+}
+
+/// A FETCH from the peer, refused with FETCH_ERROR if dropped without a response.
+pub struct FetchRequested {
+    publisher: Publisher,
+    pub request_msg: message::Fetch,
+    responded: bool,
+}
+
+impl FetchRequested {
+    pub fn new(publisher: Publisher, request_msg: message::Fetch) -> Self {
+        Self {
+            publisher,
+            request_msg,
+            responded: false,
+        }
+    }
+
+    /// Accept the fetch with FETCH_OK, then send `objects` on a new stream in the order given.
+    ///
+    /// The objects should already be sorted in the requested group order; the order is only
+    /// reported to the subscriber, which is Ascending unless Descending was asked for.
+    pub async fn respond(
+        mut self,
+        objects: Vec<FetchedObject>,
+        end_of_track: bool,
+    ) -> Result<(), SessionError> {
+        let end_location = objects
+            .iter()
+            .map(|object| Location::new(object.group_id, object.object_id))
+            .max()
+            .unwrap_or_default();
+
+        self.responded = true;
+        self.publisher.send_message(message::FetchOk {
+            id: self.request_msg.id,
+            group_order: match self.request_msg.group_order {
+                GroupOrder::Descending => GroupOrder::Descending,
+                _ => GroupOrder::Ascending,
+            },
+            end_of_track,
+            end_location,
+            params: KeyValuePairs::default(),
+        });
+
+        let mut send_stream = self.publisher.open_uni().await?;
+        send_stream.set_priority(fetch_stream_priority(
+            self.request_msg.subscriber_priority,
+            objects
+                .first()
+                .map(|object| object.priority)
+                .unwrap_or_default(),
+        ));
+
+        // The objects are all at hand, so only full packets are written until the end.
+        let mut writer =
+            Writer::with_pool(send_stream, self.publisher.buffers()).with_batching(Writer::PACKET);
+        let pacing = self.publisher.fetch_pacing();
+
+        writer
+            .encode(&data::FetchHeader {
+                header_type: data::StreamHeaderType::Fetch,
+                request_id: self.request_msg.id,
+            })
+            .await?;
+
+        for object in objects {
+            // Hold the object back until the session's FETCH pace allows it.
+            if let Some((pacer, bitrate)) = &pacing {
+                let until = pacer.reserve(object.payload.len(), *bitrate);
+                writer
+                    .flush_unless_ready(tokio::time::sleep_until(until))
+                    .await?;
+            }
+
+            writer
+                .encode(&data::FetchObject {
+                    group_id: object.group_id,
+                    subgroup_id: object.subgroup_id,
+                    object_id: object.object_id,
+                    publisher_priority: object.priority,
+                    extension_headers: object.extension_headers,
+                    payload_length: object.payload.len(),
+                    status: object.payload.is_empty().then_some(object.status),
+                })
+                .await?;
+            writer.write(object.payload).await?;
+        }
+
+        writer.flush().await
+    }
+
+    pub fn respond_error(
+        &mut self,
+        error_code: u64,
+        error_message: &str,
+    ) -> Result<(), SessionError> {
+        self.responded = true;
+        let fetch_error = message::FetchError {
+            id: self.request_msg.id,
+            error_code,
+            reason_phrase: ReasonPhrase(error_message.to_string()),
+        };
+        self.publisher.send_message(fetch_error);
+        Ok(())
+    }
+}
+
+impl Drop for FetchRequested {
+    fn drop(&mut self) {
+        if !self.responded {
+            let err = ServeError::internal_ctx("FETCH dropped without a response");
+            self.respond_error(err.code(), &err.to_string()).ok();
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::coding::SessionUri;
+use crate::message::{self, Message};
+use crate::watch::{Queue, State};
+
+use super::SessionError;
+
+#[derive(Default)]
+struct GoAwayState {
+    // The URI from the peer's GOAWAY, empty to reconnect to the same one.
+    uri: Option<String>,
+}
+
+/// Sends GOAWAY to the peer and waits for one from it, see [super::Session::goaway].
+///
+/// A server sends GOAWAY before shutting down, so clients can reconnect elsewhere
+/// instead of losing the session when the connection is closed.
+#[derive(Clone)]
+pub struct GoAway {
+    outgoing: Queue<Message>,
+    state: State<GoAwayState>,
+}
+
+impl GoAway {
+    pub(super) fn new(outgoing: Queue<Message>) -> (GoAway, GoAwayRecv) {
+        let (send, recv) = State::default().split();
+
+        let send = Self {
+            outgoing,
+            state: send,
+        };
+        let recv = GoAwayRecv { state: recv };
+
+        (send, recv)
+    }
+
+    /// Ask the peer to move to `uri`, or to reconnect to the same URI if empty.
+    pub fn send(&mut self, uri: &str) {
+        // TODO report dropped messages?
+        let _ = self.outgoing.push(
+            message::GoAway {
+                uri: SessionUri(uri.to_string()),
+            }
+            .into(),
+        );
+    }
+
+    /// Wait for the peer's GOAWAY and return its URI, empty to reconnect to the same one.
+    /// Returns None if the session ends first.
+    pub async fn received(&self) -> Option<String> {
+        loop {
+            {
+                let state = self.state.lock();
+                if let Some(uri) = &state.uri {
+                    return Some(uri.clone());
+                }
+
+                state.modified()?
+            }
+            .await;
+        }
+    }
+}
+
+pub(super) struct GoAwayRecv {
+    state: State<GoAwayState>,
+}
+
+impl GoAwayRecv {
+    pub fn recv(&mut self, msg: message::GoAway) -> Result<(), SessionError> {
+        let mut state = self.state.lock_mut().ok_or(SessionError::Internal)?;
+        if state.uri.is_some() {
+            return Err(SessionError::Duplicate);
+        }
+        state.uri = Some(msg.uri.0);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_and_receive() {
+        let outgoing = Queue::default();
+        let (mut goaway, mut recv) = GoAway::new(outgoing.clone());
+
+        goaway.send("https://relay.example/next");
+        let msg = match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::GoAway(msg)) => msg,
+            _ => panic!("expected GOAWAY"),
+        };
+        assert_eq!(msg.uri.0, "https://relay.example/next");
+
+        recv.recv(msg.clone()).unwrap();
+        assert_eq!(
+            futures::executor::block_on(goaway.received()),
+            Some("https://relay.example/next".to_string())
+        );
+
+        // Only one GOAWAY may be sent per session.
+        assert!(matches!(recv.recv(msg), Err(SessionError::Duplicate)));
+    }
+
+    #[test]
+    fn session_ended() {
+        let (goaway, recv) = GoAway::new(Queue::default());
+        drop(recv);
+        assert_eq!(futures::executor::block_on(goaway.received()), None);
+    }
+}
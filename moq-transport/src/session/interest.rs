@@ -0,0 +1,129 @@
+use std::ops;
+
+use crate::coding::{ReasonPhrase, TrackNamespace};
+use crate::watch::State;
+use crate::{message, serve::ServeError};
+
+use super::{Publisher, SubscribeNamespaceInfo};
+
+// Like Announced, the only feedback from the peer is UNSUBSCRIBE_NAMESPACE, which drops the state.
+#[derive(Default)]
+struct InterestState {
+    ok: bool,
+}
+
+/// A subscriber's interest in the namespaces under a prefix, received as SUBSCRIBE_NAMESPACE.
+///
+/// Call [Self::ok] to accept it, then announce any matching namespaces.
+/// Dropping it without accepting replies with SUBSCRIBE_NAMESPACE_ERROR.
+pub struct Interest {
+    publisher: Publisher,
+    state: State<InterestState>,
+
+    pub info: SubscribeNamespaceInfo,
+
+    ok: bool,
+    error: Option<ServeError>,
+}
+
+impl Interest {
+    pub(super) fn new(
+        publisher: Publisher,
+        request_id: u64,
+        prefix: TrackNamespace,
+    ) -> (Interest, InterestRecv) {
+        let info = SubscribeNamespaceInfo { request_id, prefix };
+
+        let (send, recv) = State::default().split();
+        let send = Self {
+            publisher,
+            state: send,
+            info,
+            ok: false,
+            error: None,
+        };
+        let recv = InterestRecv {
+            _state: recv,
+            request_id,
+        };
+
+        (send, recv)
+    }
+
+    // Send a SUBSCRIBE_NAMESPACE_OK
+    pub fn ok(&mut self) -> Result<(), ServeError> {
+        if self.ok {
+            return Err(ServeError::Duplicate);
+        }
+
+        self.publisher.send_message(message::SubscribeNamespaceOk {
+            id: self.info.request_id,
+        });
+
+        self.ok = true;
+        if let Some(mut state) = self.state.lock_mut() {
+            state.ok = true;
+        }
+
+        Ok(())
+    }
+
+    /// Whether `namespace` falls under the prefix the subscriber is interested in.
+    pub fn matches(&self, namespace: &TrackNamespace) -> bool {
+        namespace.has_prefix(&self.info.prefix)
+    }
+
+    // Run until the subscriber sends UNSUBSCRIBE_NAMESPACE, or the session ends.
+    pub async fn closed(&self) -> Result<(), ServeError> {
+        loop {
+            match self.state.lock().modified() {
+                Some(notified) => notified,
+                None => return Ok(()),
+            }
+            .await;
+        }
+    }
+
+    pub fn close(mut self, err: ServeError) -> Result<(), ServeError> {
+        self.error = Some(err);
+        Ok(())
+    }
+}
+
+impl ops::Deref for Interest {
+    type Target = SubscribeNamespaceInfo;
+
+    fn deref(&self) -> &SubscribeNamespaceInfo {
+        &self.info
+    }
+}
+
+impl Drop for Interest {
+    fn drop(&mut self) {
+        // There's no message to end an accepted interest; only the subscriber can unsubscribe.
+        if !self.ok {
+            let err = self.error.clone().unwrap_or(ServeError::Done);
+            self.publisher
+                .send_message(message::SubscribeNamespaceError {
+                    id: self.info.request_id,
+                    error_code: err.code(),
+                    reason_phrase: ReasonPhrase(err.to_string()),
+                });
+        }
+
+        self.publisher
+            .drop_interest(&self.info.prefix, self.info.request_id);
+    }
+}
+
+pub(super) struct InterestRecv {
+    _state: State<InterestState>,
+    pub request_id: u64,
+}
+
+impl InterestRecv {
+    pub fn recv_unsubscribe(self) -> Result<(), ServeError> {
+        // Will cause the state to be dropped
+        Ok(())
+    }
+}
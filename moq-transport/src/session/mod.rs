@@ -1,30 +1,70 @@
+mod activity;
+mod alias;
 mod announce;
 mod announced;
+mod auth;
+mod buffer_pool;
+mod capabilities;
 mod error;
+mod fetch_requested;
+mod goaway;
+mod interest;
+mod pacer;
+mod position;
 mod publisher;
 mod reader;
+mod requests;
+mod resilient;
+mod scope;
+mod script;
+mod shaper;
+mod skew;
+mod stats;
 mod subscribe;
+mod subscribe_all;
+mod subscribe_namespace;
 mod subscribed;
 mod subscriber;
+mod track_status;
 mod track_status_requested;
 mod writer;
 
+pub use activity::*;
+pub(crate) use alias::*;
 pub use announce::*;
 pub use announced::*;
+pub use auth::*;
+pub use capabilities::*;
 pub use error::*;
+pub use fetch_requested::*;
+pub use goaway::*;
+pub use interest::*;
+pub use position::*;
 pub use publisher::*;
+pub use resilient::*;
+pub use scope::*;
+pub use script::*;
+pub use skew::*;
+pub use stats::*;
 pub use subscribe::*;
+pub use subscribe_all::*;
+pub use subscribe_namespace::*;
 pub use subscribed::*;
 pub use subscriber::*;
+pub use track_status::*;
 pub use track_status_requested::*;
 
+use buffer_pool::*;
+use pacer::*;
 use reader::*;
+use requests::*;
+use shaper::*;
 use writer::*;
 
 use futures::{stream::FuturesUnordered, StreamExt};
-use std::sync::{atomic, Arc, Mutex};
+use std::sync::{Arc, Mutex};
 
-use crate::coding::KeyValuePairs;
+use crate::coding::{KeyValuePairs, Token};
 use crate::message::Message;
 use crate::mlog;
 use crate::watch::Queue;
@@ -46,9 +86,28 @@ pub struct Session {
     /// Queue used by Publisher and Subscriber for sending Control Messages
     outgoing: Queue<Message>,
 
+    /// GOAWAY sent by either side, handed out by [Session::goaway]
+    goaway: GoAway,
+    goaway_recv: GoAwayRecv,
+
     /// Optional mlog writer for MoQ Transport events
     /// Wrapped in Arc<Mutex<>> to share across send/recv tasks when enabled
     mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+
+    /// Authorization tokens the client presented in CLIENT_SETUP.
+    authorization_tokens: Vec<Token>,
+
+    /// What the peer supports, shared with the Publisher.
+    capabilities: Arc<Mutex<PeerCapabilities>>,
+
+    /// Rewrites the namespaces of control messages, if set with [Session::with_scope].
+    scope: Option<NamespaceScope>,
+
+    /// The SETUP messages exchanged, recorded first by [Session::with_script].
+    setup: Vec<ScriptMessage>,
+
+    /// Records every control message, if set with [Session::with_script].
+    script: Option<Arc<Mutex<ScriptWriter>>>,
 }
 
 impl Session {
@@ -60,29 +119,39 @@ impl Session {
             .max() // take the largest
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new(
         webtransport: web_transport::Session,
         sender: Writer,
         recver: Reader,
         first_requestid: u64,
         mlog: Option<mlog::MlogWriter>,
+        auth_tokens: AuthTokenCache,
+        authorization_tokens: Vec<Token>,
+        capabilities: PeerCapabilities,
     ) -> (Self, Option<Publisher>, Option<Subscriber>) {
-        let next_requestid = Arc::new(atomic::AtomicU64::new(first_requestid));
+        let requests = Requests::new(first_requestid);
+        let auth_tokens = Arc::new(Mutex::new(auth_tokens));
         let outgoing = Queue::default().split();
+        let (goaway, goaway_recv) = GoAway::new(outgoing.0.clone());
 
         // Wrap mlog in Arc<Mutex<>> for sharing across tasks
         let mlog_shared = mlog.map(|m| Arc::new(Mutex::new(m)));
+        let capabilities = Arc::new(Mutex::new(capabilities));
 
         let publisher = Some(Publisher::new(
             outgoing.0.clone(),
             webtransport.clone(),
-            next_requestid.clone(),
+            requests.clone(),
             mlog_shared.clone(),
+            auth_tokens.clone(),
+            capabilities.clone(),
         ));
         let subscriber = Some(Subscriber::new(
             outgoing.0,
-            next_requestid,
+            requests,
             mlog_shared.clone(),
+            auth_tokens,
         ));
 
         let session = Self {
@@ -92,7 +161,14 @@ impl Session {
             publisher: publisher.clone(),
             subscriber: subscriber.clone(),
             outgoing: outgoing.1,
+            goaway,
+            goaway_recv,
             mlog: mlog_shared,
+            authorization_tokens,
+            capabilities,
+            scope: None,
+            setup: Vec::new(),
+            script: None,
         };
 
         (session, publisher, subscriber)
@@ -101,10 +177,19 @@ impl Session {
     /// Create an outbound/client QUIC connection, by opening a bi-directional QUIC stream for
     /// MOQT control messaging.  Performs SETUP messaging and version negotiation.
     pub async fn connect(
+        session: web_transport::Session,
+        mlog_path: Option<PathBuf>,
+    ) -> Result<(Session, Publisher, Subscriber), SessionError> {
+        Self::connect_with_token(session, mlog_path, None).await
+    }
+
+    /// Like [`Session::connect`], but presents an authorization token in CLIENT_SETUP.
+    pub async fn connect_with_token(
         mut session: web_transport::Session,
         mlog_path: Option<PathBuf>,
+        token: Option<Token>,
     ) -> Result<(Session, Publisher, Subscriber), SessionError> {
-        let mlog = mlog_path.and_then(|path| {
+        let mut mlog = mlog_path.and_then(|path| {
             mlog::MlogWriter::new(path)
                 .map_err(|e| log::warn!("Failed to create mlog: {}", e))
                 .ok()
@@ -118,6 +203,9 @@ impl Session {
         // TODO SLG - make configurable?
         let mut params = KeyValuePairs::default();
         params.set_intvalue(setup::ParameterType::MaxRequestId.into(), 100);
+        if let Some(token) = &token {
+            auth_token_param(&mut params, token);
+        }
 
         let client = setup::Client {
             versions: versions.clone(),
@@ -127,29 +215,59 @@ impl Session {
         log::debug!("sending CLIENT_SETUP: {:?}", client);
         sender.encode(&client).await?;
 
-        // TODO: emit client_setup_created event when we add that
+        // Emit mlog event for CLIENT_SETUP created
+        if let Some(ref mut mlog) = mlog {
+            let event = mlog::events::client_setup_created(mlog.elapsed_ms(), 0, &client);
+            let _ = mlog.add_event(event);
+        }
 
         let server: setup::Server = recver.decode().await?;
         log::debug!("received SERVER_SETUP: {:?}", server);
 
-        // TODO: emit server_setup_parsed event
+        // Emit mlog event for SERVER_SETUP parsed
+        if let Some(ref mut mlog) = mlog {
+            let event = mlog::events::server_setup_parsed(mlog.elapsed_ms(), 0, &server);
+            let _ = mlog.add_event(event);
+        }
 
-        // We are the client, so the first request id is 0
-        let session = Session::new(session, sender, recver, 0, mlog);
-        Ok((session.0, session.1.unwrap(), session.2.unwrap()))
+        // We are the client, so the first request id is 0.
+        // We don't advertise a token cache, so the server can't register aliases with us.
+        let (mut session, publisher, subscriber) = Session::new(
+            session,
+            sender,
+            recver,
+            0,
+            mlog,
+            AuthTokenCache::default(),
+            Vec::new(),
+            PeerCapabilities::from_params(&server.params),
+        );
+        session.setup = vec![
+            ScriptMessage::new(ScriptDirection::Sent, "ClientSetup", &client),
+            ScriptMessage::new(ScriptDirection::Received, "ServerSetup", &server),
+        ];
+        Ok((session, publisher.unwrap(), subscriber.unwrap()))
     }
 
     /// Accepts an inbound/server QUIC connection, by accepting a bi-directional QUIC stream for
     /// MOQT control messaging.  Performs SETUP messaging and version negotiation.
     pub async fn accept(
-        mut session: web_transport::Session,
+        session: web_transport::Session,
         mlog_path: Option<PathBuf>,
     ) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
-        let mut mlog = mlog_path.and_then(|path| {
+        let mlog = mlog_path.and_then(|path| {
             mlog::MlogWriter::new(path)
                 .map_err(|e| log::warn!("Failed to create mlog: {}", e))
                 .ok()
         });
+        Self::accept_with_mlog(session, mlog).await
+    }
+
+    /// Like [`Session::accept`], but logs to an already configured [`mlog::MlogWriter`].
+    pub async fn accept_with_mlog(
+        mut session: web_transport::Session,
+        mut mlog: Option<mlog::MlogWriter>,
+    ) -> Result<(Session, Option<Publisher>, Option<Subscriber>), SessionError> {
         let control = session.accept_bi().await?;
         let mut sender = Writer::new(control.0);
         let mut recver = Reader::new(control.1);
@@ -165,12 +283,19 @@ impl Session {
 
         let server_versions = setup::Versions(vec![setup::Version::DRAFT_14]);
 
+        let mut auth_tokens = AuthTokenCache::new(DEFAULT_AUTH_TOKEN_CACHE_SIZE);
+        let authorization_tokens = auth_tokens.resolve(&client.params)?;
+
         if let Some(largest_common_version) =
             Self::largest_common(&server_versions, &client.versions)
         {
             // TODO SLG - make configurable?
             let mut params = KeyValuePairs::default();
             params.set_intvalue(setup::ParameterType::MaxRequestId.into(), 100);
+            params.set_intvalue(
+                setup::ParameterType::MaxAuthTokenCacheSize.into(),
+                DEFAULT_AUTH_TOKEN_CACHE_SIZE,
+            );
 
             let server = setup::Server {
                 version: largest_common_version,
@@ -188,19 +313,85 @@ impl Session {
             sender.encode(&server).await?;
 
             // We are the server, so the first request id is 1
-            Ok(Session::new(session, sender, recver, 1, mlog))
+            let (mut session, publisher, subscriber) = Session::new(
+                session,
+                sender,
+                recver,
+                1,
+                mlog,
+                auth_tokens,
+                authorization_tokens,
+                PeerCapabilities::from_params(&client.params),
+            );
+            session.setup = vec![
+                ScriptMessage::new(ScriptDirection::Received, "ClientSetup", &client),
+                ScriptMessage::new(ScriptDirection::Sent, "ServerSetup", &server),
+            ];
+            Ok((session, publisher, subscriber))
         } else {
             Err(SessionError::Version(client.versions, server_versions))
         }
     }
 
+    /// Authorization tokens the client presented in CLIENT_SETUP, with aliases resolved.
+    /// Always empty on the client side.
+    pub fn authorization_tokens(&self) -> &[Token] {
+        &self.authorization_tokens
+    }
+
+    /// What the peer supports, from its SETUP parameters and [Session::with_max_datagram_size].
+    pub fn peer_capabilities(&self) -> PeerCapabilities {
+        self.capabilities.lock().unwrap().clone()
+    }
+
+    /// Only send the peer datagrams up to `size` bytes, or none if None, ex. as reported by the
+    /// QUIC connection. Larger objects are sent on streams instead, and publishers can size
+    /// theirs with [crate::serve::DatagramsWriter::max_payload].
+    pub fn with_max_datagram_size(self, size: Option<usize>) -> Self {
+        self.capabilities.lock().unwrap().max_datagram_size = size;
+        self
+    }
+
+    /// Confine the session to the namespaces under `scope`, see [NamespaceScope].
+    /// Must be set before [Session::run], as messages are only rewritten from then on.
+    pub fn with_scope(mut self, scope: NamespaceScope) -> Self {
+        self.scope = Some(scope);
+        self
+    }
+
+    /// Record every control message to `script`, starting with the SETUP messages already exchanged.
+    /// Must be set before [Session::run], as messages are only recorded from then on.
+    pub fn with_script(mut self, mut script: ScriptWriter) -> Self {
+        for msg in self.setup.drain(..) {
+            if let Err(err) = script.record(msg) {
+                log::warn!("failed to record script: {}", err);
+            }
+        }
+        self.script = Some(Arc::new(Mutex::new(script)));
+        self
+    }
+
+    /// A handle for sending GOAWAY to the peer and waiting for one from it, while the session runs.
+    pub fn goaway(&self) -> GoAway {
+        self.goaway.clone()
+    }
+
+    /// The session's mlog writer, if any, ex. to [mlog::MlogWriter::start] one on standby
+    /// while the session runs.
+    pub fn mlog(&self) -> Option<Arc<Mutex<mlog::MlogWriter>>> {
+        self.mlog.clone()
+    }
+
     /// Run Tasks for the session, including sending of control messages, receiving and processing
     /// inbound control messages, receiving and processing new inbound uni-directional QUIC streams,
     /// and receiving and processing QUIC datagrams received
     pub async fn run(self) -> Result<(), SessionError> {
+        // However the session ends, even if this future is dropped.
+        let _closed = RequestsClosed(self.subscriber.clone());
+
         tokio::select! {
-            res = Self::run_recv(self.recver, self.publisher, self.subscriber.clone(), self.mlog.clone()) => res,
-            res = Self::run_send(self.sender, self.outgoing, self.mlog.clone()) => res,
+            res = Self::run_recv(self.recver, self.publisher, self.subscriber.clone(), self.goaway_recv, self.scope.clone(), self.mlog.clone(), self.script.clone()) => res,
+            res = Self::run_send(self.sender, self.outgoing, self.scope, self.mlog.clone(), self.script) => res,
             res = Self::run_streams(self.webtransport.clone(), self.subscriber.clone()) => res,
             res = Self::run_datagrams(self.webtransport, self.subscriber) => res,
         }
@@ -208,11 +399,24 @@ impl Session {
 
     /// Processes the outgoing control message queue, and sends queued messages on the control stream sender/writer.
     async fn run_send(
-        mut sender: Writer,
+        sender: Writer,
         mut outgoing: Queue<message::Message>,
+        scope: Option<NamespaceScope>,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+        script: Option<Arc<Mutex<ScriptWriter>>>,
     ) -> Result<(), SessionError> {
-        while let Some(msg) = outgoing.pop().await {
+        // Messages queued together, ex. a burst of SUBSCRIBE_OKs or announces, go out in one write.
+        let mut sender = sender
+            .with_batching(Writer::PACKET)
+            .with_delay(Writer::DELAY);
+        while let Some((mut msg, popped)) =
+            sender.flush_unless_ready(outgoing.pop_deferred()).await?
+        {
+            if scope.as_ref().is_some_and(|scope| !scope.send(&mut msg)) {
+                log::warn!("not sending message outside the session's scope: {:?}", msg);
+                continue;
+            }
+
             log::debug!("sending message: {:?}", msg);
 
             // Emit mlog event for sent control messages
@@ -224,57 +428,121 @@ impl Session {
                     // Emit events based on message type
                     let event = match &msg {
                         Message::Subscribe(m) => {
-                            Some(mlog::events::subscribe_created(time, stream_id, m))
+                            mlog::events::subscribe_created(time, stream_id, m)
                         }
                         Message::SubscribeOk(m) => {
-                            Some(mlog::events::subscribe_ok_created(time, stream_id, m))
+                            mlog::events::subscribe_ok_created(time, stream_id, m)
                         }
                         Message::SubscribeError(m) => {
-                            Some(mlog::events::subscribe_error_created(time, stream_id, m))
+                            mlog::events::subscribe_error_created(time, stream_id, m)
+                        }
+                        Message::SubscribeUpdate(m) => {
+                            mlog::events::subscribe_update_created(time, stream_id, m)
                         }
                         Message::Unsubscribe(m) => {
-                            Some(mlog::events::unsubscribe_created(time, stream_id, m))
+                            mlog::events::unsubscribe_created(time, stream_id, m)
                         }
                         Message::PublishNamespace(m) => {
-                            Some(mlog::events::publish_namespace_created(time, stream_id, m))
-                        }
-                        Message::PublishNamespaceOk(m) => Some(
-                            mlog::events::publish_namespace_ok_created(time, stream_id, m),
-                        ),
-                        Message::PublishNamespaceError(m) => Some(
-                            mlog::events::publish_namespace_error_created(time, stream_id, m),
-                        ),
-                        Message::GoAway(m) => {
-                            Some(mlog::events::go_away_created(time, stream_id, m))
-                        }
-                        _ => None, // TODO: Add other message types
+                            mlog::events::publish_namespace_created(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceOk(m) => {
+                            mlog::events::publish_namespace_ok_created(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceError(m) => {
+                            mlog::events::publish_namespace_error_created(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceDone(m) => {
+                            mlog::events::publish_namespace_done_created(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceCancel(m) => {
+                            mlog::events::publish_namespace_cancel_created(time, stream_id, m)
+                        }
+                        Message::TrackStatus(m) => {
+                            mlog::events::track_status_created(time, stream_id, m)
+                        }
+                        Message::TrackStatusOk(m) => {
+                            mlog::events::track_status_ok_created(time, stream_id, m)
+                        }
+                        Message::TrackStatusError(m) => {
+                            mlog::events::track_status_error_created(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespace(m) => {
+                            mlog::events::subscribe_namespace_created(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespaceOk(m) => {
+                            mlog::events::subscribe_namespace_ok_created(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespaceError(m) => {
+                            mlog::events::subscribe_namespace_error_created(time, stream_id, m)
+                        }
+                        Message::UnsubscribeNamespace(m) => {
+                            mlog::events::unsubscribe_namespace_created(time, stream_id, m)
+                        }
+                        Message::Fetch(m) => mlog::events::fetch_created(time, stream_id, m),
+                        Message::FetchOk(m) => mlog::events::fetch_ok_created(time, stream_id, m),
+                        Message::FetchError(m) => {
+                            mlog::events::fetch_error_created(time, stream_id, m)
+                        }
+                        Message::FetchCancel(m) => {
+                            mlog::events::fetch_cancel_created(time, stream_id, m)
+                        }
+                        Message::Publish(m) => mlog::events::publish_created(time, stream_id, m),
+                        Message::PublishOk(m) => {
+                            mlog::events::publish_ok_created(time, stream_id, m)
+                        }
+                        Message::PublishError(m) => {
+                            mlog::events::publish_error_created(time, stream_id, m)
+                        }
+                        Message::PublishDone(m) => {
+                            mlog::events::publish_done_created(time, stream_id, m)
+                        }
+                        Message::GoAway(m) => mlog::events::go_away_created(time, stream_id, m),
+                        Message::MaxRequestId(m) => {
+                            mlog::events::max_request_id_created(time, stream_id, m)
+                        }
+                        Message::RequestsBlocked(m) => {
+                            mlog::events::requests_blocked_created(time, stream_id, m)
+                        }
                     };
 
-                    if let Some(event) = event {
-                        let _ = mlog_guard.add_event(event);
-                    }
+                    let _ = mlog_guard.add_event(event);
                 }
             }
 
+            record(&script, ScriptDirection::Sent, &msg);
             sender.encode(&msg).await?;
+
+            // Whoever waits for the message, ex. SUBSCRIBE_OK before opening the track's streams,
+            // is told once it's written rather than while it's still batched.
+            if popped.is_awaited() {
+                sender.flush().await?;
+            }
+            popped.confirm();
         }
 
-        Ok(())
+        sender.flush().await
     }
 
     /// Receives inbound messages from the control stream reader/receiver.  Analyzes if the message
     /// is to be handled by Subscriber or Publisher logic and calls recv_message on either the
     /// Publisher or Subscriber.
-    /// Note:  Should also be handling messages common to both roles, ie: GOAWAY, MAX_REQUEST_ID and
+    /// Note:  Should also be handling messages common to both roles, ie: MAX_REQUEST_ID and
     ///        REQUESTS_BLOCKED
     async fn run_recv(
         mut recver: Reader,
         mut publisher: Option<Publisher>,
         mut subscriber: Option<Subscriber>,
+        mut goaway: GoAwayRecv,
+        scope: Option<NamespaceScope>,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+        script: Option<Arc<Mutex<ScriptWriter>>>,
     ) -> Result<(), SessionError> {
         loop {
-            let msg: message::Message = recver.decode().await?;
+            let mut msg: message::Message = recver.decode().await?;
+            record(&script, ScriptDirection::Received, &msg);
+            if let Some(scope) = &scope {
+                scope.recv(&mut msg)?;
+            }
             log::debug!("received message: {:?}", msg);
 
             // Emit mlog event for received control messages
@@ -285,39 +553,94 @@ impl Session {
 
                     // Emit events based on message type
                     let event = match &msg {
-                        Message::Subscribe(m) => {
-                            Some(mlog::events::subscribe_parsed(time, stream_id, m))
-                        }
+                        Message::Subscribe(m) => mlog::events::subscribe_parsed(time, stream_id, m),
                         Message::SubscribeOk(m) => {
-                            Some(mlog::events::subscribe_ok_parsed(time, stream_id, m))
+                            mlog::events::subscribe_ok_parsed(time, stream_id, m)
                         }
                         Message::SubscribeError(m) => {
-                            Some(mlog::events::subscribe_error_parsed(time, stream_id, m))
+                            mlog::events::subscribe_error_parsed(time, stream_id, m)
+                        }
+                        Message::SubscribeUpdate(m) => {
+                            mlog::events::subscribe_update_parsed(time, stream_id, m)
                         }
                         Message::Unsubscribe(m) => {
-                            Some(mlog::events::unsubscribe_parsed(time, stream_id, m))
+                            mlog::events::unsubscribe_parsed(time, stream_id, m)
                         }
                         Message::PublishNamespace(m) => {
-                            Some(mlog::events::publish_namespace_parsed(time, stream_id, m))
-                        }
-                        Message::PublishNamespaceOk(m) => Some(
-                            mlog::events::publish_namespace_ok_parsed(time, stream_id, m),
-                        ),
-                        Message::PublishNamespaceError(m) => Some(
-                            mlog::events::publish_namespace_error_parsed(time, stream_id, m),
-                        ),
-                        Message::GoAway(m) => {
-                            Some(mlog::events::go_away_parsed(time, stream_id, m))
-                        }
-                        _ => None, // TODO: Add other message types
+                            mlog::events::publish_namespace_parsed(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceOk(m) => {
+                            mlog::events::publish_namespace_ok_parsed(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceError(m) => {
+                            mlog::events::publish_namespace_error_parsed(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceDone(m) => {
+                            mlog::events::publish_namespace_done_parsed(time, stream_id, m)
+                        }
+                        Message::PublishNamespaceCancel(m) => {
+                            mlog::events::publish_namespace_cancel_parsed(time, stream_id, m)
+                        }
+                        Message::TrackStatus(m) => {
+                            mlog::events::track_status_parsed(time, stream_id, m)
+                        }
+                        Message::TrackStatusOk(m) => {
+                            mlog::events::track_status_ok_parsed(time, stream_id, m)
+                        }
+                        Message::TrackStatusError(m) => {
+                            mlog::events::track_status_error_parsed(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespace(m) => {
+                            mlog::events::subscribe_namespace_parsed(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespaceOk(m) => {
+                            mlog::events::subscribe_namespace_ok_parsed(time, stream_id, m)
+                        }
+                        Message::SubscribeNamespaceError(m) => {
+                            mlog::events::subscribe_namespace_error_parsed(time, stream_id, m)
+                        }
+                        Message::UnsubscribeNamespace(m) => {
+                            mlog::events::unsubscribe_namespace_parsed(time, stream_id, m)
+                        }
+                        Message::Fetch(m) => mlog::events::fetch_parsed(time, stream_id, m),
+                        Message::FetchOk(m) => mlog::events::fetch_ok_parsed(time, stream_id, m),
+                        Message::FetchError(m) => {
+                            mlog::events::fetch_error_parsed(time, stream_id, m)
+                        }
+                        Message::FetchCancel(m) => {
+                            mlog::events::fetch_cancel_parsed(time, stream_id, m)
+                        }
+                        Message::Publish(m) => mlog::events::publish_parsed(time, stream_id, m),
+                        Message::PublishOk(m) => {
+                            mlog::events::publish_ok_parsed(time, stream_id, m)
+                        }
+                        Message::PublishError(m) => {
+                            mlog::events::publish_error_parsed(time, stream_id, m)
+                        }
+                        Message::PublishDone(m) => {
+                            mlog::events::publish_done_parsed(time, stream_id, m)
+                        }
+                        Message::GoAway(m) => mlog::events::go_away_parsed(time, stream_id, m),
+                        Message::MaxRequestId(m) => {
+                            mlog::events::max_request_id_parsed(time, stream_id, m)
+                        }
+                        Message::RequestsBlocked(m) => {
+                            mlog::events::requests_blocked_parsed(time, stream_id, m)
+                        }
                     };
 
-                    if let Some(event) = event {
-                        let _ = mlog_guard.add_event(event);
-                    }
+                    let _ = mlog_guard.add_event(event);
                 }
             }
 
+            let msg = match msg {
+                Message::GoAway(msg) => {
+                    goaway.recv(msg)?;
+                    continue;
+                }
+                msg => msg,
+            };
+
             let msg = match TryInto::<message::Publisher>::try_into(msg) {
                 Ok(msg) => {
                     subscriber
@@ -340,7 +663,7 @@ impl Session {
                 Err(msg) => msg,
             };
 
-            // TODO GOAWAY, MAX_REQUEST_ID, REQUESTS_BLOCKED
+            // TODO MAX_REQUEST_ID, REQUESTS_BLOCKED
             log::warn!("Unimplemented message type received: {:?}", msg);
             return Err(SessionError::unimplemented(&format!(
                 "message type {:?}",
@@ -365,8 +688,9 @@ impl Session {
                     let subscriber = subscriber.clone().ok_or(SessionError::RoleViolation)?;
 
                     tasks.push(async move {
-                        if let Err(err) = Subscriber::recv_stream(subscriber, stream).await {
+                        if let Err(err) = Subscriber::recv_stream(subscriber.clone(), stream).await {
                             log::warn!("failed to serve stream: {}", err);
+                            subscriber.count_violation(&err);
                         };
                     });
                 },
@@ -390,3 +714,24 @@ impl Session {
         }
     }
 }
+
+// Record a control message to the session's script, if any.
+fn record(script: &Option<Arc<Mutex<ScriptWriter>>>, direction: ScriptDirection, msg: &Message) {
+    if let Some(script) = script {
+        let msg = ScriptMessage::new(direction, msg.name(), msg);
+        if let Err(err) = script.lock().unwrap().record(msg) {
+            log::warn!("failed to record script: {}", err);
+        }
+    }
+}
+
+// Fails the subscriber's requests still waiting on a response once the session ends.
+struct RequestsClosed(Option<Subscriber>);
+
+impl Drop for RequestsClosed {
+    fn drop(&mut self) {
+        if let Some(subscriber) = &self.0 {
+            subscriber.close_requests();
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// Spreads a subscription's objects out at a bitrate, so a burst from upstream doesn't overwhelm
+/// the subscriber's last-mile link. Shared by every subgroup of the subscription.
+#[derive(Clone, Default)]
+pub(super) struct Pacer {
+    // When the objects reserved so far will have been sent at the pace.
+    next: Arc<Mutex<Option<Instant>>>,
+}
+
+impl Pacer {
+    // Objects may get this far ahead of their pace, absorbing scheduling jitter without a burst.
+    const BURST: Duration = Duration::from_millis(50);
+
+    // A declared bitrate is an average, so pace faster to let the subscriber catch up after a stall.
+    const HEADROOM_PERCENT: u64 = 25;
+
+    /// The bitrate to pace to, from the track's declared bitrate and the subscriber's limit.
+    pub fn bitrate(declared: Option<u64>, max: Option<u64>) -> Option<u64> {
+        let declared =
+            declared.map(|bitrate| bitrate.saturating_mul(100 + Self::HEADROOM_PERCENT) / 100);
+        match (declared, max) {
+            (Some(declared), Some(max)) => Some(declared.min(max)),
+            (declared, max) => declared.or(max),
+        }
+    }
+
+    /// Reserve the time to send `bytes` at `bitrate` bits per second, returning when they may be sent.
+    pub fn reserve(&self, bytes: usize, bitrate: u64) -> Instant {
+        let now = Instant::now();
+        let earliest = now.checked_sub(Self::BURST).unwrap_or(now);
+
+        let mut next = self.next.lock().unwrap();
+        let start = next.map_or(earliest, |next| next.max(earliest));
+        let duration = Duration::from_secs_f64(bytes as f64 * 8.0 / bitrate.max(1) as f64);
+        *next = Some(start + duration);
+
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitrate() {
+        assert_eq!(Pacer::bitrate(None, None), None);
+        assert_eq!(Pacer::bitrate(Some(1_000_000), None), Some(1_250_000));
+        assert_eq!(Pacer::bitrate(None, Some(500_000)), Some(500_000));
+        assert_eq!(
+            Pacer::bitrate(Some(1_000_000), Some(500_000)),
+            Some(500_000)
+        );
+        assert_eq!(
+            Pacer::bitrate(Some(1_000_000), Some(5_000_000)),
+            Some(1_250_000)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn spreads_bursts() {
+        let pacer = Pacer::default();
+        let start = Instant::now();
+
+        // 1000 bytes at 80 kbps take 100ms each, after a 50ms burst allowance.
+        let sends: Vec<_> = (0..4)
+            .map(|_| pacer.reserve(1000, 80_000) - start)
+            .collect();
+        assert_eq!(sends[0], Duration::ZERO);
+        assert_eq!(sends[1], Duration::from_millis(50));
+        assert_eq!(sends[2], Duration::from_millis(150));
+        assert_eq!(sends[3], Duration::from_millis(250));
+
+        // Idle time doesn't build up into a burst.
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        let now = Instant::now();
+        assert!(pacer.reserve(1000, 80_000) <= now);
+        assert_eq!(pacer.reserve(1000, 80_000) - now, Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,79 @@
+use crate::coding::Location;
+use crate::watch::State;
+
+/// The latest object of a subscription that was fully delivered: written to its stream or
+/// datagram by a publisher, or completely received by a subscriber.
+///
+/// Objects in different subgroups may complete out of order, so this is the largest
+/// (group, object) delivered so far rather than the most recent one. Cloned handles observe
+/// the same position, which is `None` until the first object is delivered.
+#[derive(Clone)]
+pub struct SubscriptionPosition {
+    state: State<Option<Location>>,
+}
+
+impl SubscriptionPosition {
+    /// Returns the handle updated by the session, and the one handed to the application.
+    pub(super) fn produce() -> (Self, Self) {
+        let (send, recv) = State::default().split();
+        (Self { state: send }, Self { state: recv })
+    }
+
+    pub fn get(&self) -> Option<Location> {
+        *self.state.lock()
+    }
+
+    /// Wait until the position moves past `last`, returning the new position,
+    /// or `None` once the subscription has ended without doing so.
+    pub async fn next(&self, last: Option<Location>) -> Option<Location> {
+        loop {
+            {
+                let state = self.state.lock();
+                if *state > last {
+                    return *state;
+                }
+
+                state.modified()?
+            }
+            .await;
+        }
+    }
+
+    pub(super) fn advance(&self, group_id: u64, object_id: u64) {
+        let location = Some(Location::new(group_id, object_id));
+
+        let state = self.state.lock();
+        if location > *state {
+            if let Some(mut state) = state.into_mut() {
+                *state = location;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn advance() {
+        let (send, recv) = SubscriptionPosition::produce();
+        assert_eq!(recv.get(), None);
+
+        send.advance(1, 4);
+        send.advance(1, 2);
+        send.advance(0, 9);
+        assert_eq!(recv.get(), Some(Location::new(1, 4)));
+
+        let last = recv.get();
+        send.advance(2, 0);
+        let next = block_on(recv.next(last));
+        assert_eq!(next, Some(Location::new(2, 0)));
+
+        // Nothing more is delivered once the session's side is gone.
+        drop(send);
+        assert_eq!(block_on(recv.next(next)), None);
+        assert_eq!(recv.get(), Some(Location::new(2, 0)));
+    }
+}
@@ -1,21 +1,29 @@
 use std::{
     collections::{hash_map, HashMap},
-    sync::{atomic, Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task,
+    time::Duration,
 };
 
 use futures::{stream::FuturesUnordered, StreamExt};
 
 use crate::{
-    coding::TrackNamespace,
+    coding::{KeyValuePairs, ReasonPhrase, Token, TrackNamespace},
     message::{self, Message},
     mlog,
-    serve::{ServeError, TracksReader},
+    serve::{FullTrackName, ServeError, SubscriptionQuota, TracksReader},
 };
 
 use crate::watch::Queue;
 
 use super::{
-    Announce, AnnounceRecv, Session, SessionError, Subscribed, SubscribedRecv, TrackStatusRequested,
+    Announce, AnnounceRecv, AuthTokenCache, BufferPool, DeliveryStats, EgressShaper,
+    FetchRequested, Interest, InterestRecv, Pacer, PeerCapabilities, RequestKind, Requests,
+    Response, Session, SessionError, StatsEvents, Subscribed, SubscribedRecv, SubscriptionSnapshot,
+    TrackAliases, TrackStatusRequested,
 };
 
 // TODO remove Clone.
@@ -38,27 +46,61 @@ pub struct Publisher {
     /// added to this Queue to track the inbound track status request
     unknown_track_status_requested: Queue<TrackStatusRequested>,
 
+    /// When a Fetch is received, a new entry is added to this Queue so the application can decide
+    /// whether it can be served.
+    unknown_fetch_requested: Queue<FetchRequested>,
+
+    /// Set once the application accepts fetch requests; until then FETCH is refused as not supported.
+    serves_fetch: Arc<AtomicBool>,
+
+    /// When a SubscribeNamespace is received, a new entry is added to this HashMap to track the
+    /// inbound interest, keyed by prefix since that's all UNSUBSCRIBE_NAMESPACE carries.
+    interests: Arc<Mutex<HashMap<TrackNamespace, InterestRecv>>>,
+
+    /// ... and the matching Interest is added to this Queue so the application can decide what to announce.
+    unknown_interest: Queue<Interest>,
+
     /// The queue we will write any outbound control messages we want to sent, the session run_send task
     /// will process the queue and send the message on the control stream.
     outgoing: Queue<Message>,
 
-    /// When we need a new Request Id for sending a request, we can get it from here.  Note:  The instance
-    /// of AtomicU64 is shared with the Subscriber, so the session uses unique request ids for all requests
-    /// generated.  Note:  If we initiated the QUIC connection then request id's start at 0 and increment by 2
-    /// for each request (even numbers).  If we accepted an inbound QUIC connection then request id's start at 1 and
-    /// increment by 2 for each request (odd numbers).
-    next_requestid: Arc<atomic::AtomicU64>,
+    /// The request IDs of the session, shared with the Subscriber, matching responses to our requests.
+    requests: Requests,
 
     /// Optional mlog writer for logging transport events
     mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+
+    /// Token aliases registered by the peer, shared with the Subscriber.
+    auth_tokens: Arc<Mutex<AuthTokenCache>>,
+
+    /// Encode buffers recycled across the streams we open.
+    buffers: BufferPool,
+
+    /// Limits applied to each subscription to our tracks.
+    subscription_quota: Arc<Mutex<SubscriptionQuota>>,
+
+    /// The track aliases of the subscriptions to our tracks.
+    aliases: Arc<Mutex<TrackAliases>>,
+
+    /// The bitrate FETCH responses are paced to, shared by every one of the session.
+    fetch_bitrate: Arc<Mutex<Option<u64>>>,
+    fetch_pacer: Pacer,
+
+    /// Limits everything sent on live subgroups and datagrams, shared by every subscription.
+    egress: EgressShaper,
+
+    /// What the peer supports, shared with the Session.
+    capabilities: Arc<Mutex<PeerCapabilities>>,
 }
 
 impl Publisher {
-    pub(crate) fn new(
+    pub(super) fn new(
         outgoing: Queue<Message>,
         webtransport: web_transport::Session,
-        next_requestid: Arc<atomic::AtomicU64>,
+        requests: Requests,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+        auth_tokens: Arc<Mutex<AuthTokenCache>>,
+        capabilities: Arc<Mutex<PeerCapabilities>>,
     ) -> Self {
         Self {
             webtransport,
@@ -66,12 +108,76 @@ impl Publisher {
             subscribeds: Default::default(),
             unknown_subscribed: Default::default(),
             unknown_track_status_requested: Default::default(),
+            unknown_fetch_requested: Default::default(),
+            serves_fetch: Default::default(),
+            interests: Default::default(),
+            unknown_interest: Default::default(),
             outgoing,
-            next_requestid,
+            requests,
             mlog,
+            auth_tokens,
+            buffers: Default::default(),
+            subscription_quota: Default::default(),
+            aliases: Default::default(),
+            fetch_bitrate: Default::default(),
+            fetch_pacer: Default::default(),
+            egress: Default::default(),
+            capabilities,
         }
     }
 
+    /// What the peer supports, ex. to choose how to deliver a track to it.
+    pub fn peer_capabilities(&self) -> PeerCapabilities {
+        self.capabilities.lock().unwrap().clone()
+    }
+
+    /// Configure the limits applied to each subscription to our tracks.
+    /// Applies to all clones of this publisher, and to subscriptions received after the call.
+    /// Use [Subscribed::set_quota] to override them for a single subscription.
+    pub fn set_subscription_quota(&self, quota: SubscriptionQuota) {
+        *self.subscription_quota.lock().unwrap() = quota;
+    }
+
+    /// The limits applied to each subscription to our tracks.
+    pub fn subscription_quota(&self) -> SubscriptionQuota {
+        *self.subscription_quota.lock().unwrap()
+    }
+
+    /// Pace the objects sent in response to FETCH to this many bits per second, across all
+    /// of them, so a replay leaves room for live subgroups. Unlimited by default.
+    ///
+    /// FETCH streams are always sent after live subgroup streams, whatever their priorities,
+    /// but without a limit they still take any bandwidth the live streams leave unused.
+    pub fn set_fetch_bitrate(&self, bitrate: Option<u64>) {
+        *self.fetch_bitrate.lock().unwrap() = bitrate;
+    }
+
+    /// The pacer shared by FETCH responses and the bitrate to pace them to, if limited.
+    pub(super) fn fetch_pacing(&self) -> Option<(Pacer, u64)> {
+        let bitrate = (*self.fetch_bitrate.lock().unwrap())?;
+        Some((self.fetch_pacer.clone(), bitrate))
+    }
+
+    /// Limit the objects sent on live subgroups and datagrams to this many bits per second,
+    /// across every subscription, so one subscriber can't take all of our bandwidth.
+    /// Unlimited by default.
+    ///
+    /// Past the limit, the most important subgroups being sent wait for their turn, and the
+    /// rest of less important ones are dropped, counted by [super::SubscriptionStats::egress_dropped].
+    /// FETCH responses are only paced by [Self::set_fetch_bitrate].
+    pub fn set_egress_bitrate(&self, bitrate: Option<u64>) {
+        self.egress.set_bitrate(bitrate);
+    }
+
+    /// The bitrate set by [Self::set_egress_bitrate].
+    pub fn egress_bitrate(&self) -> Option<u64> {
+        self.egress.bitrate()
+    }
+
+    pub(super) fn egress(&self) -> &EgressShaper {
+        &self.egress
+    }
+
     pub async fn accept(
         session: web_transport::Session,
     ) -> Result<(Session, Publisher), SessionError> {
@@ -86,31 +192,76 @@ impl Publisher {
         Ok((session, publisher))
     }
 
+    /// List the peer's active subscriptions to our tracks, in request order.
+    pub fn subscriptions(&self) -> Vec<SubscriptionSnapshot> {
+        let mut list: Vec<_> = self
+            .subscribeds
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, subscribed)| subscribed.snapshot(*id))
+            .collect();
+
+        list.sort_by_key(|subscription| subscription.id);
+        list
+    }
+
+    /// The delivery stats of the peer's subscription `id`, as listed by [Self::subscriptions],
+    /// ex. to choose the rendition to publish. None if the subscription ended.
+    pub fn subscription_stats(&self, id: u64) -> Option<DeliveryStats> {
+        self.subscribeds
+            .lock()
+            .unwrap()
+            .get(&id)
+            .map(|subscribed| subscribed.stats())
+    }
+
+    /// Report the stats of every subscription to our tracks each `period`, see [StatsEvents].
+    pub fn stats_events(&self, period: Duration) -> StatsEvents {
+        StatsEvents::new(Arc::downgrade(&self.subscribeds), period)
+    }
+
     /// Announce a namespace and serve tracks using the provided [serve::TracksReader].
     /// The caller uses [serve::TracksWriter] for static tracks and [serve::TracksRequest] for dynamic tracks.
     pub async fn announce(&mut self, tracks: TracksReader) -> Result<(), SessionError> {
-        // Check if annouce for this namespace already exists or not, and if not, then create a new Announce
-        let announce = match self
-            .announces
-            .lock()
-            .unwrap()
-            .entry(tracks.namespace.clone())
-        {
-            // Namespace already exists in HashMap (has already been announced) - return Duplicate error
-            hash_map::Entry::Occupied(_) => return Err(ServeError::Duplicate.into()),
+        self.announce_with_token(tracks, None).await
+    }
 
-            // This is a new announce, send announce message to peer.
-            hash_map::Entry::Vacant(entry) => {
-                // Get the current next request id to use and increment the value for by 2 for the next request
-                let request_id = self.next_requestid.fetch_add(2, atomic::Ordering::Relaxed);
+    /// Like [`Publisher::announce`], but sends an authorization token with the PUBLISH_NAMESPACE.
+    pub async fn announce_with_token(
+        &mut self,
+        tracks: TracksReader,
+        token: Option<Token>,
+    ) -> Result<(), SessionError> {
+        let announce =
+            self.start_announce(tracks.namespace.clone(), token, Default::default(), true)?;
+        Self::serve_announce(announce, tracks).await
+    }
 
-                let (send, recv) =
-                    Announce::new(self.clone(), request_id, tracks.namespace.clone());
-                entry.insert(recv);
-                send
-            }
-        };
+    /// Like [`Publisher::announce`] for each of `tracks`, queueing every PUBLISH_NAMESPACE before
+    /// serving any so they're written to the control stream together, ex. when a large publisher starts.
+    /// Runs until every announce ends, or returns the first error, which ends the others.
+    pub async fn announce_many(&mut self, tracks: Vec<TracksReader>) -> Result<(), SessionError> {
+        let mut announces = Vec::with_capacity(tracks.len());
+        for tracks in tracks {
+            let announce =
+                self.start_announce(tracks.namespace.clone(), None, Default::default(), true)?;
+            announces.push((announce, tracks));
+        }
+
+        let mut tasks: FuturesUnordered<_> = announces
+            .into_iter()
+            .map(|(announce, tracks)| Self::serve_announce(announce, tracks))
+            .collect();
+        while let Some(res) = tasks.next().await {
+            res?;
+        }
+
+        Ok(())
+    }
 
+    // Serve subscriptions and track status requests for `announce` from `tracks` until it ends.
+    async fn serve_announce(announce: Announce, tracks: TracksReader) -> Result<(), SessionError> {
         let mut subscribe_tasks = FuturesUnordered::new();
         let mut status_tasks = FuturesUnordered::new();
         let mut subscribe_done = false;
@@ -163,6 +314,60 @@ impl Publisher {
         }
     }
 
+    /// Announce a namespace without serving its tracks: subscriptions and track status requests
+    /// for it are returned by [`Publisher::subscribed`] and [`Publisher::track_status_requested`],
+    /// like those for namespaces never announced. Runs until the peer cancels or rejects the announce.
+    ///
+    /// Suits an application that resolves tracks when they are requested, ex. a relay whose
+    /// namespaces can change publishers while announced.
+    pub async fn announce_namespace(
+        &mut self,
+        namespace: TrackNamespace,
+        token: Option<Token>,
+    ) -> Result<(), SessionError> {
+        self.announce_namespace_with_params(namespace, token, Default::default())
+            .await
+    }
+
+    /// Like [`Publisher::announce_namespace`], sending `params` with the PUBLISH_NAMESPACE too,
+    /// ex. extension parameters the peer interprets.
+    pub async fn announce_namespace_with_params(
+        &mut self,
+        namespace: TrackNamespace,
+        token: Option<Token>,
+        params: KeyValuePairs,
+    ) -> Result<(), SessionError> {
+        let announce = self.start_announce(namespace, token, params, false)?;
+        announce.closed().await?;
+        Ok(())
+    }
+
+    // Send the PUBLISH_NAMESPACE, routing requests for the namespace to the returned announce if `routed`.
+    fn start_announce(
+        &mut self,
+        namespace: TrackNamespace,
+        token: Option<Token>,
+        params: KeyValuePairs,
+        routed: bool,
+    ) -> Result<Announce, SessionError> {
+        // Check if annouce for this namespace already exists or not, and if not, then create a new Announce
+        match self.announces.lock().unwrap().entry(namespace.clone()) {
+            // Namespace already exists in HashMap (has already been announced) - return Duplicate error
+            hash_map::Entry::Occupied(_) => Err(ServeError::Duplicate.into()),
+
+            // This is a new announce, send announce message to peer.
+            hash_map::Entry::Vacant(entry) => {
+                let request_id = self.requests.issue(RequestKind::PublishNamespace);
+
+                let (send, mut recv) =
+                    Announce::new(self.clone(), request_id, namespace, token, params);
+                recv.routed = routed;
+                entry.insert(recv);
+                Ok(send)
+            }
+        }
+    }
+
     pub async fn serve_subscribe(
         subscribed: Subscribed,
         mut tracks: TracksReader,
@@ -211,27 +416,68 @@ impl Publisher {
         self.unknown_subscribed.pop().await
     }
 
+    /// Like [Self::subscribed], returning Pending and waking `cx` when the next one arrives.
+    pub fn poll_subscribed(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<Subscribed>> {
+        self.unknown_subscribed.poll_pop(cx)
+    }
+
     // Returns track_status requests that do not map to an active announce.
     pub async fn track_status_requested(&mut self) -> Option<TrackStatusRequested> {
         self.unknown_track_status_requested.pop().await
     }
 
+    /// Like [Self::track_status_requested], returning Pending and waking `cx` when the next one arrives.
+    pub fn poll_track_status_requested(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<TrackStatusRequested>> {
+        self.unknown_track_status_requested.poll_pop(cx)
+    }
+
+    /// Queue FETCH requests for [Self::fetch_requested] rather than refusing them as not supported.
+    /// Call before the session runs so none arrive first.
+    pub fn accept_fetches(&self) {
+        self.serves_fetch.store(true, Ordering::Relaxed);
+    }
+
+    // Returns fetch requests, refused as not supported unless [Self::accept_fetches] was called.
+    pub async fn fetch_requested(&mut self) -> Option<FetchRequested> {
+        self.unknown_fetch_requested.pop().await
+    }
+
+    /// Like [Self::fetch_requested], returning Pending and waking `cx` when the next one arrives.
+    pub fn poll_fetch_requested(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<FetchRequested>> {
+        self.unknown_fetch_requested.poll_pop(cx)
+    }
+
+    /// Returns namespace prefixes the subscriber is interested in, from SUBSCRIBE_NAMESPACE.
+    pub async fn interest(&mut self) -> Option<Interest> {
+        self.unknown_interest.pop().await
+    }
+
+    /// Like [Self::interest], returning Pending and waking `cx` when the next one arrives.
+    pub fn poll_interest(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Option<Interest>> {
+        self.unknown_interest.poll_pop(cx)
+    }
+
     pub(crate) fn recv_message(&mut self, msg: message::Subscriber) -> Result<(), SessionError> {
         let res = match msg {
             message::Subscriber::Subscribe(msg) => self.recv_subscribe(msg),
             message::Subscriber::SubscribeUpdate(msg) => self.recv_subscribe_update(msg),
             message::Subscriber::Unsubscribe(msg) => self.recv_unsubscribe(msg),
-            message::Subscriber::Fetch(_msg) => Err(SessionError::unimplemented("FETCH")),
+            message::Subscriber::Fetch(msg) => self.recv_fetch(msg),
             message::Subscriber::FetchCancel(_msg) => {
                 Err(SessionError::unimplemented("FETCH_CANCEL"))
             }
             message::Subscriber::TrackStatus(msg) => self.recv_track_status(msg),
-            message::Subscriber::SubscribeNamespace(_msg) => {
-                Err(SessionError::unimplemented("SUBSCRIBE_NAMESPACE"))
-            }
-            message::Subscriber::UnsubscribeNamespace(_msg) => {
-                Err(SessionError::unimplemented("UNSUBSCRIBE_NAMESPACE"))
-            }
+            message::Subscriber::SubscribeNamespace(msg) => self.recv_subscribe_namespace(msg),
+            message::Subscriber::UnsubscribeNamespace(msg) => self.recv_unsubscribe_namespace(msg),
             message::Subscriber::PublishNamespaceCancel(msg) => {
                 self.recv_publish_namespace_cancel(msg)
             }
@@ -245,8 +491,11 @@ impl Publisher {
             }
         };
 
-        if let Err(err) = res {
-            log::warn!("failed to process message: {}", err);
+        match res {
+            // Responses that don't match our requests end the session.
+            Err(err @ SessionError::ProtocolViolation(_)) => return Err(err),
+            Err(err) => log::warn!("failed to process message: {}", err),
+            Ok(()) => {}
         }
 
         Ok(())
@@ -256,6 +505,13 @@ impl Publisher {
         &mut self,
         msg: message::PublishNamespaceOk,
     ) -> Result<(), SessionError> {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::PublishNamespace, Response::Ok)?
+        {
+            return Ok(());
+        }
+
         // We need to find the announce request using the request id, however the self.announces data structure
         // is a HashMap indexed by Namespace (which is needed for handling PUBLISH_NAMESPACE_CANCEL).  TODO - make more efficient.
         // For now iterate through all self.annouces until we find the matching id.
@@ -273,6 +529,13 @@ impl Publisher {
         &mut self,
         msg: message::PublishNamespaceError,
     ) -> Result<(), SessionError> {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::PublishNamespace, Response::Error)?
+        {
+            return Ok(());
+        }
+
         // We need to find the announce request using the request id, however the self.announces data structure
         // is a HashMap indexed by Namespace (which is needed for handling PUBLISH_NAMESPACE_CANCEL).  TODO - make more efficient.
         // For now iterate through all self.annouces until we find the matching id.
@@ -286,7 +549,14 @@ impl Publisher {
 
         // Remove from HashMap and take ownership
         if let Some(key) = key_opt {
-            if let Some((_ns, v)) = announces.remove_entry(&key) {
+            if let Some((ns, v)) = announces.remove_entry(&key) {
+                log::debug!(
+                    "announce rejected: namespace={} code={} reason={}",
+                    ns,
+                    msg.error_code,
+                    msg.reason_phrase.0
+                );
+
                 // Step 3: call recv_error, consuming v
                 v.recv_error(ServeError::Closed(msg.error_code))?;
             }
@@ -302,14 +572,66 @@ impl Publisher {
         // TODO: If a publisher receives new subscriptions for that namespace after receiving an ANNOUNCE_CANCEL,
         // it SHOULD close the session as a 'Protocol Violation'.
         if let Some(announce) = self.announces.lock().unwrap().remove(&msg.track_namespace) {
+            self.requests.end(announce.request_id);
             announce.recv_error(ServeError::Cancel)?;
         }
 
         Ok(())
     }
 
+    fn recv_subscribe_namespace(
+        &mut self,
+        msg: message::SubscribeNamespace,
+    ) -> Result<(), SessionError> {
+        let interest = {
+            let mut interests = self.interests.lock().unwrap();
+            match interests.entry(msg.track_namespace_prefix.clone()) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(entry) => {
+                    let (send, recv) =
+                        Interest::new(self.clone(), msg.id, msg.track_namespace_prefix);
+                    entry.insert(recv);
+                    Some(send)
+                }
+            }
+        };
+
+        // TODO: Overlapping prefixes should be refused too, with PREFIX_OVERLAP.
+        let Some(interest) = interest else {
+            self.send_message(message::SubscribeNamespaceError {
+                id: msg.id,
+                error_code: ServeError::Duplicate.code(),
+                reason_phrase: ReasonPhrase(ServeError::Duplicate.to_string()),
+            });
+            return Ok(());
+        };
+
+        if let Err(err) = self.unknown_interest.push(interest) {
+            err.close(ServeError::Done)?;
+        }
+
+        Ok(())
+    }
+
+    fn recv_unsubscribe_namespace(
+        &mut self,
+        msg: message::UnsubscribeNamespace,
+    ) -> Result<(), SessionError> {
+        if let Some(interest) = self
+            .interests
+            .lock()
+            .unwrap()
+            .remove(&msg.track_namespace_prefix)
+        {
+            interest.recv_unsubscribe()?;
+        }
+
+        Ok(())
+    }
+
     fn recv_subscribe(&mut self, msg: message::Subscribe) -> Result<(), SessionError> {
         let namespace = msg.track_namespace.clone();
+        let tokens = self.auth_tokens.lock().unwrap().resolve(&msg.params)?;
 
         let subscribed = {
             let mut subscribeds = self.subscribeds.lock().unwrap();
@@ -321,14 +643,20 @@ impl Publisher {
             };
 
             // Create new Subscribed entry and add to HashMap
-            let (send, recv) = Subscribed::new(self.clone(), msg, self.mlog.clone());
+            let (send, recv) = Subscribed::new(self.clone(), msg, tokens, self.mlog.clone());
             entry.insert(recv);
 
             send
         };
 
         // If we have an announce, route the subscribe to it.
-        if let Some(announce) = self.announces.lock().unwrap().get_mut(&namespace) {
+        if let Some(announce) = self
+            .announces
+            .lock()
+            .unwrap()
+            .get_mut(&namespace)
+            .filter(|announce| announce.routed)
+        {
             return announce.recv_subscribe(subscribed).map_err(Into::into);
         }
 
@@ -346,12 +674,21 @@ impl Publisher {
         Ok(())
     }
 
-    fn recv_subscribe_update(
-        &mut self,
-        _msg: message::SubscribeUpdate,
-    ) -> Result<(), SessionError> {
-        // TODO: Implement updating subscriptions.
-        Err(SessionError::unimplemented("SUBSCRIBE_UPDATE"))
+    fn recv_subscribe_update(&mut self, msg: message::SubscribeUpdate) -> Result<(), SessionError> {
+        // Resolved even if the subscription ended, so token aliases stay in sync.
+        let tokens = self.auth_tokens.lock().unwrap().resolve(&msg.params)?;
+
+        // Updates for subscriptions that already ended are ignored.
+        if let Some(subscribed) = self
+            .subscribeds
+            .lock()
+            .unwrap()
+            .get_mut(&msg.subscription_request_id)
+        {
+            subscribed.recv_update(&msg, tokens)?;
+        }
+
+        Ok(())
     }
 
     fn recv_track_status(&mut self, msg: message::TrackStatus) -> Result<(), SessionError> {
@@ -361,7 +698,13 @@ impl Publisher {
         let track_status_requested = TrackStatusRequested::new(self.clone(), msg);
 
         // If we have an announce, route the track_status to it.
-        if let Some(announce) = self.announces.lock().unwrap().get_mut(&namespace) {
+        if let Some(announce) = self
+            .announces
+            .lock()
+            .unwrap()
+            .get_mut(&namespace)
+            .filter(|announce| announce.routed)
+        {
             return announce
                 .recv_track_status_requested(track_status_requested)
                 .map_err(Into::into);
@@ -381,6 +724,29 @@ impl Publisher {
         Ok(())
     }
 
+    fn recv_fetch(&mut self, msg: message::Fetch) -> Result<(), SessionError> {
+        let mut fetch_requested = FetchRequested::new(self.clone(), msg);
+
+        // Joining fetches reference an existing subscription, which we don't track for fetching yet.
+        if fetch_requested.request_msg.fetch_type != message::FetchType::Standalone {
+            let err = ServeError::not_implemented_ctx("joining FETCH");
+            return fetch_requested.respond_error(err.code(), &err.to_string());
+        }
+
+        // Nothing would answer the fetch unless the application opted in with accept_fetches.
+        if !self.serves_fetch.load(Ordering::Relaxed) {
+            let err = ServeError::not_implemented_ctx("FETCH");
+            return fetch_requested.respond_error(err.code(), &err.to_string());
+        }
+
+        if let Err(mut err) = self.unknown_fetch_requested.push(fetch_requested) {
+            // push only fails if the queue is dropped, send FetchError, Internal error
+            err.respond_error(0, "Internal error")?;
+        }
+
+        Ok(())
+    }
+
     fn recv_unsubscribe(&mut self, msg: message::Unsubscribe) -> Result<(), SessionError> {
         if let Some(subscribed) = self.subscribeds.lock().unwrap().get_mut(&msg.id) {
             subscribed.recv_unsubscribe()?;
@@ -424,19 +790,49 @@ impl Publisher {
             .ok();
     }
 
+    pub(super) fn assign_alias(&self, track: FullTrackName) -> u64 {
+        self.aliases.lock().unwrap().assign(track)
+    }
+
+    pub(super) fn release_alias(&self, alias: u64) {
+        self.aliases.lock().unwrap().release(alias);
+    }
+
     fn drop_subscribe(&mut self, id: u64) {
         self.subscribeds.lock().unwrap().remove(&id);
     }
 
     fn drop_publish_namespace(&mut self, namespace: &TrackNamespace) {
-        self.announces.lock().unwrap().remove(namespace);
+        if let Some(announce) = self.announces.lock().unwrap().remove(namespace) {
+            self.requests.end(announce.request_id);
+        }
+    }
+
+    pub(super) fn drop_interest(&mut self, prefix: &TrackNamespace, request_id: u64) {
+        let mut interests = self.interests.lock().unwrap();
+        if interests.get(prefix).map(|recv| recv.request_id) == Some(request_id) {
+            interests.remove(prefix);
+        }
+    }
+
+    pub(super) fn buffers(&self) -> BufferPool {
+        self.buffers.clone()
     }
 
     pub(super) async fn open_uni(&mut self) -> Result<web_transport::SendStream, SessionError> {
         Ok(self.webtransport.open_uni().await?)
     }
 
+    /// Send a datagram, or fail with [SessionError::DatagramTooLarge] if the peer can't take it.
     pub(super) async fn send_datagram(&mut self, data: bytes::Bytes) -> Result<(), SessionError> {
+        let capabilities = self.peer_capabilities();
+        if !capabilities.accepts_datagram(data.len()) {
+            return Err(SessionError::DatagramTooLarge {
+                size: data.len(),
+                max: capabilities.max_datagram_size.unwrap_or(0),
+            });
+        }
+
         Ok(self.webtransport.send_datagram(data).await?)
     }
 }
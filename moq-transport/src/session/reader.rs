@@ -4,102 +4,136 @@ use bytes::{Buf, Bytes, BytesMut};
 
 use crate::coding::{Decode, DecodeError};
 
-use super::SessionError;
+use super::{BufferPool, SessionError};
 
 pub struct Reader {
     stream: web_transport::RecvStream,
+
+    // Data received but not yet consumed, kept as the stream's own chunk so payloads aren't copied.
+    chunk: Bytes,
+
+    // A message split across chunks is copied here to be decoded. Unread data is `buffer` then `chunk`.
     buffer: BytesMut,
+
+    // The buffer is returned here on drop, if set.
+    pool: Option<BufferPool>,
 }
 
 impl Reader {
     pub fn new(stream: web_transport::RecvStream) -> Self {
         Self {
             stream,
+            chunk: Bytes::new(),
             buffer: Default::default(),
+            pool: None,
+        }
+    }
+
+    /// Read with a buffer taken from `pool`, returning it when the reader is dropped.
+    pub fn with_pool(stream: web_transport::RecvStream, pool: BufferPool) -> Self {
+        Self {
+            stream,
+            chunk: Bytes::new(),
+            buffer: pool.get(),
+            pool: Some(pool),
         }
     }
 
     pub async fn decode<T: Decode>(&mut self) -> Result<T, SessionError> {
         log::trace!(
-            "[READER] decode: attempting to decode {} (buffer_len={})",
+            "[READER] decode: attempting to decode {} (buffer_len={}, chunk_len={})",
             std::any::type_name::<T>(),
-            self.buffer.len()
+            self.buffer.len(),
+            self.chunk.len()
         );
 
         loop {
-            let mut cursor = io::Cursor::new(&self.buffer);
+            // Decode straight from the chunk unless part of the message had to be buffered.
+            let buffered = !self.buffer.is_empty();
+            let mut cursor = match buffered {
+                true => io::Cursor::new(&self.buffer[..]),
+                false => io::Cursor::new(&self.chunk[..]),
+            };
 
-            // Try to decode with the current buffer.
             let required = match T::decode(&mut cursor) {
                 Ok(msg) => {
                     let consumed = cursor.position() as usize;
-                    self.buffer.advance(consumed);
+                    match buffered {
+                        true => self.buffer.advance(consumed),
+                        false => self.chunk.advance(consumed),
+                    }
                     log::debug!(
-                        "[READER] decode: successfully decoded {} (consumed={} bytes, buffer_remaining={})",
+                        "[READER] decode: successfully decoded {} (consumed={} bytes, buffered={})",
                         std::any::type_name::<T>(),
                         consumed,
-                        self.buffer.len()
+                        buffered
                     );
                     return Ok(msg);
                 }
                 Err(DecodeError::More(required)) => {
-                    let total_needed = self.buffer.len() + required;
                     log::trace!(
-                        "[READER] decode: need more data for {} (current={} bytes, need={} more, total_required={})",
+                        "[READER] decode: need more data for {} (buffer_len={}, chunk_len={}, need={} more)",
                         std::any::type_name::<T>(),
                         self.buffer.len(),
-                        required,
-                        total_needed
+                        self.chunk.len(),
+                        required
                     );
-                    total_needed
+                    required
                 }
                 Err(err) => {
                     log::error!(
-                        "[READER] decode: ERROR decoding {} - {:?} (buffer_len={})",
+                        "[READER] decode: ERROR decoding {} - {:?} (buffer_len={}, chunk_len={})",
                         std::any::type_name::<T>(),
                         err,
-                        self.buffer.len()
+                        self.buffer.len(),
+                        self.chunk.len()
                     );
                     return Err(err.into());
                 }
             };
 
-            // Read in more data until we reach the requested amount.
-            // We always read at least once to avoid an infinite loop if some dingus puts remain=0
-            loop {
-                let before_read = self.buffer.len();
-                if !self.stream.read_buf(&mut self.buffer).await? {
-                    log::warn!(
-                        "[READER] decode: stream ended while waiting for data (have={} bytes, need={})",
-                        self.buffer.len(),
-                        required
-                    );
-                    return Err(DecodeError::More(required - self.buffer.len()).into());
-                };
-
-                let read_amount = self.buffer.len() - before_read;
-                log::trace!(
-                    "[READER] decode: read {} bytes from stream (buffer_len={})",
-                    read_amount,
-                    self.buffer.len()
-                );
+            self.fill(required).await?;
+        }
+    }
 
-                if self.buffer.len() >= required {
-                    log::trace!(
-                        "[READER] decode: have enough data now (buffer_len={}), retrying decode",
-                        self.buffer.len()
-                    );
-                    break;
-                }
+    // Buffer up to `required` more bytes of a message that doesn't fit in the current chunk,
+    // reading the next chunk if this one is used up. Only the message itself is copied.
+    async fn fill(&mut self, required: usize) -> Result<(), SessionError> {
+        let size = match self.buffer.is_empty() {
+            // The whole chunk is the start of the message.
+            true => self.chunk.len(),
+            false => cmp::min(required, self.chunk.len()),
+        };
+
+        if size > 0 {
+            self.buffer.extend_from_slice(&self.chunk.split_to(size));
+            return Ok(());
+        }
+
+        // We always read at least once to avoid an infinite loop if some dingus puts remain=0
+        match self.stream.read_chunk(usize::MAX).await? {
+            Some(chunk) => {
+                log::trace!("[READER] fill: read {} bytes from stream", chunk.len());
+                self.chunk = chunk;
+                Ok(())
+            }
+            None => {
+                log::warn!(
+                    "[READER] fill: stream ended while waiting for data (have={} bytes, need={})",
+                    self.buffer.len(),
+                    required
+                );
+                Err(DecodeError::More(required).into())
             }
         }
     }
 
     pub async fn read_chunk(&mut self, max: usize) -> Result<Option<Bytes>, SessionError> {
         log::trace!(
-            "[READER] read_chunk: requested max={} bytes (buffer_len={})",
+            "[READER] read_chunk: requested max={} bytes (buffer_len={}, chunk_len={})",
             max,
-            self.buffer.len()
+            self.buffer.len(),
+            self.chunk.len()
         );
 
         if !self.buffer.is_empty() {
@@ -113,6 +147,17 @@ impl Reader {
             return Ok(Some(data));
         }
 
+        if !self.chunk.is_empty() {
+            let size = cmp::min(max, self.chunk.len());
+            let data = self.chunk.split_to(size);
+            log::trace!(
+                "[READER] read_chunk: returned {} bytes from chunk (chunk_remaining={})",
+                data.len(),
+                self.chunk.len()
+            );
+            return Ok(Some(data));
+        }
+
         let chunk = self.stream.read_chunk(max).await?;
         if let Some(ref data) = chunk {
             log::trace!("[READER] read_chunk: read {} bytes from stream", data.len());
@@ -123,10 +168,24 @@ impl Reader {
     }
 
     pub async fn done(&mut self) -> Result<bool, SessionError> {
-        if !self.buffer.is_empty() {
+        if !self.buffer.is_empty() || !self.chunk.is_empty() {
             return Ok(false);
         }
 
-        Ok(!self.stream.read_buf(&mut self.buffer).await?)
+        match self.stream.read_chunk(usize::MAX).await? {
+            Some(chunk) => {
+                self.chunk = chunk;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.put(std::mem::take(&mut self.buffer));
+        }
     }
 }
@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{atomic, Arc, Mutex},
+};
+
+use super::SessionError;
+
+/// The requests whose responses are matched to them by request ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum RequestKind {
+    /// Answered by SUBSCRIBE_OK or SUBSCRIBE_ERROR.
+    Subscribe,
+
+    /// Answered by PUBLISH_NAMESPACE_OK or PUBLISH_NAMESPACE_ERROR.
+    PublishNamespace,
+
+    /// Answered by TRACK_STATUS_OK or TRACK_STATUS_ERROR.
+    TrackStatus,
+}
+
+impl fmt::Display for RequestKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Subscribe => write!(f, "SUBSCRIBE"),
+            Self::PublishNamespace => write!(f, "PUBLISH_NAMESPACE"),
+            Self::TrackStatus => write!(f, "TRACK_STATUS"),
+        }
+    }
+}
+
+/// Whether the peer accepted or refused a request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Response {
+    Ok,
+    Error,
+}
+
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Ok => write!(f, "OK"),
+            Self::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// The request IDs of a session and the requests still waiting on, or using, their response.
+///
+/// Shared by the Publisher and Subscriber, so a response is only accepted once, for a request of
+/// the right kind, and a response for a request we never sent is a protocol violation rather than
+/// silently ignored. If we initiated the QUIC connection, request IDs are even, starting at 0,
+/// otherwise they're odd, starting at 1.
+#[derive(Clone)]
+pub(super) struct Requests {
+    next: Arc<atomic::AtomicU64>,
+    first: u64,
+    issued: Arc<Mutex<HashMap<u64, Issued>>>,
+}
+
+struct Issued {
+    kind: RequestKind,
+    answered: bool,
+}
+
+impl Requests {
+    pub fn new(first: u64) -> Self {
+        Self {
+            next: Arc::new(atomic::AtomicU64::new(first)),
+            first,
+            issued: Default::default(),
+        }
+    }
+
+    /// The ID of a request whose response isn't tracked, ex. SUBSCRIBE_UPDATE.
+    pub fn next_id(&self) -> u64 {
+        self.next.fetch_add(2, atomic::Ordering::Relaxed)
+    }
+
+    /// The ID of a request of `kind`, whose response is expected until [Self::end].
+    pub fn issue(&self, kind: RequestKind) -> u64 {
+        let mut issued = self.issued.lock().unwrap();
+        let id = self.next_id();
+        issued.insert(
+            id,
+            Issued {
+                kind,
+                answered: false,
+            },
+        );
+        id
+    }
+
+    /// Match a response to the request `id` of `kind`.
+    ///
+    /// Returns false if we ended the request already, in which case the response crossed our
+    /// cancellation and should be ignored.
+    pub fn respond(
+        &self,
+        id: u64,
+        kind: RequestKind,
+        response: Response,
+    ) -> Result<bool, SessionError> {
+        let violation = |reason: &str| {
+            Err(SessionError::ProtocolViolation(format!(
+                "{}_{} for {} request {}",
+                kind, response, reason, id
+            )))
+        };
+
+        let mut issued = self.issued.lock().unwrap();
+        let request = match issued.get_mut(&id) {
+            Some(request) => request,
+            None if self.sent(id) => return Ok(false),
+            None => return violation("unknown"),
+        };
+
+        if request.kind != kind {
+            return violation(&request.kind.to_string());
+        }
+        if request.answered {
+            return violation("answered");
+        }
+
+        match response {
+            Response::Ok => request.answered = true,
+            Response::Error => {
+                issued.remove(&id);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Stop expecting a response to the request `id`, once it ended on our side.
+    pub fn end(&self, id: u64) {
+        self.issued.lock().unwrap().remove(&id);
+    }
+
+    // Whether we used the ID for a request already.
+    fn sent(&self, id: u64) -> bool {
+        id >= self.first
+            && id % 2 == self.first % 2
+            && id < self.next.load(atomic::Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    // xorshift64*, so the orderings are random but reproducible from the seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            (self.0.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 32) as usize % n
+        }
+
+        fn kind(&mut self) -> RequestKind {
+            match self.below(3) {
+                0 => RequestKind::Subscribe,
+                1 => RequestKind::PublishNamespace,
+                _ => RequestKind::TrackStatus,
+            }
+        }
+
+        fn response(&mut self) -> Response {
+            match self.below(2) {
+                0 => Response::Ok,
+                _ => Response::Error,
+            }
+        }
+    }
+
+    fn other(kind: RequestKind) -> RequestKind {
+        match kind {
+            RequestKind::Subscribe => RequestKind::PublishNamespace,
+            RequestKind::PublishNamespace => RequestKind::TrackStatus,
+            RequestKind::TrackStatus => RequestKind::Subscribe,
+        }
+    }
+
+    fn violation(res: Result<bool, SessionError>) -> bool {
+        matches!(res, Err(SessionError::ProtocolViolation(_)))
+    }
+
+    #[test]
+    fn responses() {
+        let requests = Requests::new(0);
+        let subscribe = requests.issue(RequestKind::Subscribe);
+        let update = requests.next_id();
+        let announce = requests.issue(RequestKind::PublishNamespace);
+        assert_eq!((subscribe, update, announce), (0, 2, 4));
+
+        assert!(requests
+            .respond(subscribe, RequestKind::Subscribe, Response::Ok)
+            .unwrap());
+        assert!(violation(requests.respond(
+            subscribe,
+            RequestKind::Subscribe,
+            Response::Error
+        )));
+
+        // Refused after we withdrew it: the responses crossed.
+        requests.end(announce);
+        assert!(!requests
+            .respond(announce, RequestKind::PublishNamespace, Response::Error)
+            .unwrap());
+
+        // The peer's IDs, and those we haven't used yet.
+        for id in [1, 6] {
+            assert!(violation(requests.respond(
+                id,
+                RequestKind::Subscribe,
+                Response::Ok
+            )));
+        }
+
+        match requests.respond(subscribe, RequestKind::PublishNamespace, Response::Ok) {
+            Err(err) => assert_eq!(
+                err.to_string(),
+                "protocol violation: PUBLISH_NAMESPACE_OK for SUBSCRIBE request 0"
+            ),
+            Ok(_) => panic!("expected a protocol violation"),
+        }
+    }
+
+    // A peer answering each request once, in any order, while we end some of them first.
+    #[test]
+    fn valid_orderings() {
+        for seed in 1..=500 {
+            let mut rng = Rng(seed);
+            let requests = Requests::new(seed % 2);
+
+            let mut waiting = Vec::new();
+            for _ in 0..1 + rng.below(20) {
+                let kind = rng.kind();
+                waiting.push((requests.issue(kind), kind));
+                if rng.below(4) == 0 {
+                    requests.next_id();
+                }
+            }
+
+            let mut ended = HashSet::new();
+            while !waiting.is_empty() {
+                let (id, kind) = waiting.swap_remove(rng.below(waiting.len()));
+                if rng.below(4) == 0 {
+                    requests.end(id);
+                    ended.insert(id);
+                }
+
+                let handled = requests.respond(id, kind, rng.response());
+                assert_eq!(handled.unwrap(), !ended.contains(&id), "seed {}", seed);
+            }
+        }
+    }
+
+    // Like valid_orderings, with one response that doesn't match a request we're waiting on.
+    #[test]
+    fn invalid_orderings() {
+        for seed in 1..=500 {
+            let mut rng = Rng(seed);
+            let first = seed % 2;
+            let requests = Requests::new(first);
+
+            let issued: Vec<_> = (0..1 + rng.below(20))
+                .map(|_| {
+                    let kind = rng.kind();
+                    (requests.issue(kind), kind)
+                })
+                .collect();
+
+            // Accept some, and refuse some others.
+            let mut accepted = Vec::new();
+            for &(id, kind) in &issued {
+                match rng.below(3) {
+                    0 => {
+                        requests.respond(id, kind, Response::Ok).unwrap();
+                        accepted.push((id, kind));
+                    }
+                    1 => assert!(requests.respond(id, kind, Response::Error).unwrap()),
+                    _ => {}
+                }
+            }
+
+            let kind = rng.kind();
+            let unused = first + 2 * (issued.len() + rng.below(10)) as u64;
+            let peers = 2 * rng.below(100) as u64 + (1 - first);
+            let (id, kind) = match rng.below(4) {
+                0 if !accepted.is_empty() => accepted[rng.below(accepted.len())],
+                1 => (unused, kind),
+                2 => (peers, kind),
+                // A response for a request of the other kind.
+                _ => (requests.issue(kind), other(kind)),
+            };
+
+            let res = requests.respond(id, kind, rng.response());
+            assert!(violation(res), "seed {}", seed);
+        }
+    }
+}
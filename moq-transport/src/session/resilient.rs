@@ -0,0 +1,286 @@
+use std::{fmt, future::Future, sync::Arc, time::Duration};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+
+use crate::{
+    coding::Location,
+    serve::{
+        DatagramsReader, ServeError, SubgroupReader, SubgroupWriter, SubgroupsReader, Track,
+        TrackReaderMode, TrackWriter, TrackWriterMode,
+    },
+};
+
+use super::{Session, Subscriber};
+
+/// Subscribes to a track through a list of publishers, keeping a single [crate::serve::TrackReader]
+/// fed across transient outages.
+///
+/// The track is resubscribed whenever the subscription ends, ex. after PUBLISH_DONE, or when it
+/// expires. When the session fails, or the publisher sends GOAWAY, the next target is connected
+/// to. Resubscriptions start from the group after the latest one received, so the reader sees
+/// each group at most once.
+pub struct ResilientSubscriber<T, F> {
+    targets: Vec<T>,
+    connect: F,
+    min_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl<T, F, Fut, E> ResilientSubscriber<T, F>
+where
+    T: Clone + fmt::Display,
+    F: FnMut(T) -> Fut,
+    Fut: Future<Output = Result<(Session, Subscriber), E>>,
+    E: fmt::Display,
+{
+    /// Connect to each of `targets` in turn with `connect`, starting with the first.
+    pub fn new(targets: Vec<T>, connect: F) -> Self {
+        Self {
+            targets,
+            connect,
+            min_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+
+    /// Wait between `min` and `max` before resubscribing or reconnecting, doubling on each failure.
+    pub fn with_backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max.max(min);
+        self
+    }
+
+    /// Subscribe to `track` until it can no longer be written, ex. because every reader was dropped.
+    pub async fn subscribe(&mut self, track: TrackWriter) -> Result<(), ServeError> {
+        if self.targets.is_empty() {
+            return Err(ServeError::Internal(
+                "no targets to subscribe to".to_string(),
+            ));
+        }
+
+        let mut resume = Resume::new(track);
+        let mut backoff = self.min_backoff;
+
+        for target in self.targets.clone().into_iter().cycle() {
+            match (self.connect)(target.clone()).await {
+                Ok((session, mut subscriber)) => {
+                    let goaway = session.goaway();
+                    let session = session.run();
+                    tokio::pin!(session);
+
+                    loop {
+                        tokio::select! {
+                            res = &mut session => {
+                                log::warn!("session to {} ended: {:?}", target, res);
+                                break;
+                            },
+                            Some(uri) = goaway.received() => {
+                                log::info!("{} is going away, reconnecting: uri={:?}", target, uri);
+                                break;
+                            },
+                            res = resume.subscribe(&mut subscriber) => res?,
+                        }
+
+                        if resume.take_delivered() {
+                            backoff = self.min_backoff;
+                        }
+
+                        log::info!(
+                            "resubscribing to {}/{} via {} in {:?}",
+                            resume.info.namespace,
+                            resume.info.name,
+                            target,
+                            backoff
+                        );
+                        tokio::time::sleep(backoff).await;
+                        backoff = (backoff * 2).min(self.max_backoff);
+                    }
+                }
+                Err(err) => log::warn!("failed to connect to {}: {}", target, err),
+            }
+
+            if resume.take_delivered() {
+                backoff = self.min_backoff;
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.max_backoff);
+        }
+
+        unreachable!("targets cycle forever")
+    }
+}
+
+// Copies each subscription into the application's track, remembering where to resume from.
+struct Resume {
+    info: Arc<Track>,
+    writer: Option<TrackWriterMode>,
+    latest_group: Option<u64>,
+    delivered: bool,
+}
+
+impl Resume {
+    fn new(track: TrackWriter) -> Self {
+        Self {
+            info: track.info.clone(),
+            writer: Some(track.into()),
+            latest_group: None,
+            delivered: false,
+        }
+    }
+
+    // Whether anything was received since the last call.
+    fn take_delivered(&mut self) -> bool {
+        std::mem::take(&mut self.delivered)
+    }
+
+    // Subscribe once, returning when the subscription ends and an error if the track can't be written.
+    async fn subscribe(&mut self, subscriber: &mut Subscriber) -> Result<(), ServeError> {
+        let track = Track::new(self.info.namespace.clone(), self.info.name.clone())
+            .with_trace_id(self.info.trace_id.clone())
+            .with_authorization_token(self.info.authorization_token.clone())
+            .with_group_start(self.info.group_start)
+            .with_delivery_timeout(self.info.delivery_timeout)
+            .with_cached_groups(self.info.cached_groups.clone())
+            .with_telemetry(self.info.telemetry.clone())
+            .with_start(match self.latest_group {
+                Some(group_id) => Some(Location::new(group_id + 1, 0)),
+                None => self.info.start,
+            });
+        let (writer, reader) = track.produce();
+        let subscribe = subscriber.subscribe_handle(writer);
+
+        // Resubscribe just before the publisher would end the subscription.
+        let expired = async {
+            if subscribe.ok().await.is_err() {
+                return futures::future::pending().await;
+            }
+            match subscribe.expires() {
+                Some(expires) => tokio::time::sleep(expires).await,
+                None => futures::future::pending().await,
+            }
+        };
+
+        let info = self.info.clone();
+        let copy = async {
+            match reader.mode().await {
+                Ok(mode) => self.copy(mode).await,
+                // The subscription ended before any objects arrived.
+                Err(_) => Ok(()),
+            }
+        };
+
+        tokio::select! {
+            res = copy => res,
+            _ = expired => {
+                log::info!("subscription to {}/{} expired", info.namespace, info.name);
+                Ok(())
+            }
+        }
+    }
+
+    async fn copy(&mut self, mode: TrackReaderMode) -> Result<(), ServeError> {
+        let writer = self.writer.take().ok_or(ServeError::Done)?;
+
+        match (mode, writer) {
+            (TrackReaderMode::Subgroups(reader), TrackWriterMode::Track(track)) => {
+                self.writer = Some(track.subgroups()?.into());
+                self.copy_subgroups(reader).await
+            }
+            (TrackReaderMode::Subgroups(reader), writer @ TrackWriterMode::Subgroups(_)) => {
+                self.writer = Some(writer);
+                self.copy_subgroups(reader).await
+            }
+            (TrackReaderMode::Datagrams(reader), TrackWriterMode::Track(track)) => {
+                self.writer = Some(track.datagrams()?.into());
+                self.copy_datagrams(reader).await
+            }
+            (
+                TrackReaderMode::Datagrams(reader),
+                writer @ (TrackWriterMode::Datagrams(_) | TrackWriterMode::Subgroups(_)),
+            ) => {
+                self.writer = Some(writer);
+                self.copy_datagrams(reader).await
+            }
+            (_, writer) => {
+                self.writer = Some(writer);
+                Err(ServeError::Mode)
+            }
+        }
+    }
+
+    async fn copy_subgroups(&mut self, mut reader: SubgroupsReader) -> Result<(), ServeError> {
+        let mut datagrams = reader.datagrams();
+        let mut tasks = FuturesUnordered::new();
+
+        loop {
+            tokio::select! {
+                res = reader.next() => {
+                    let Ok(Some(subgroup)) = res else { return Ok(()) };
+
+                    let Some(TrackWriterMode::Subgroups(writer)) = &mut self.writer else {
+                        return Err(ServeError::Mode);
+                    };
+                    let output = match writer.create(crate::serve::Subgroup {
+                        group_id: subgroup.group_id,
+                        subgroup_id: subgroup.subgroup_id,
+                        priority: subgroup.priority,
+                    }) {
+                        Ok(output) => output,
+                        // Already received before resubscribing.
+                        Err(ServeError::Duplicate) => continue,
+                        Err(err) => return Err(err),
+                    };
+
+                    self.received(subgroup.group_id);
+                    tasks.push(Self::copy_subgroup(subgroup, output));
+                },
+                res = datagrams.read() => {
+                    let Ok(Some(datagram)) = res else { return Ok(()) };
+
+                    let Some(TrackWriterMode::Subgroups(writer)) = &mut self.writer else {
+                        return Err(ServeError::Mode);
+                    };
+                    let group_id = datagram.group_id;
+                    writer.datagram(datagram)?;
+                    self.received(group_id);
+                },
+                Some(res) = tasks.next() => res?,
+            }
+        }
+    }
+
+    async fn copy_subgroup(
+        mut reader: SubgroupReader,
+        mut writer: SubgroupWriter,
+    ) -> Result<(), ServeError> {
+        // A subgroup cut short by the outage just ends early.
+        while let Ok(Some(mut object)) = reader.next().await {
+            let mut output = writer.create(object.size, Some(object.extension_headers.clone()))?;
+            while let Ok(Some(chunk)) = object.read().await {
+                output.write(chunk)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn copy_datagrams(&mut self, mut reader: DatagramsReader) -> Result<(), ServeError> {
+        while let Ok(Some(datagram)) = reader.read().await {
+            self.received(datagram.group_id);
+
+            match &mut self.writer {
+                Some(TrackWriterMode::Datagrams(writer)) => writer.write(datagram)?,
+                Some(TrackWriterMode::Subgroups(writer)) => writer.datagram(datagram)?,
+                _ => return Err(ServeError::Mode),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn received(&mut self, group_id: u64) {
+        self.latest_group = self.latest_group.max(Some(group_id));
+        self.delivered = true;
+    }
+}
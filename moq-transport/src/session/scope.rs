@@ -0,0 +1,170 @@
+use crate::coding::{DecodeError, TrackNamespace};
+use crate::message::Message;
+
+/// Confines a session to the namespaces under a prefix, ex. for a relay serving several tenants.
+///
+/// The prefix is added to every namespace the peer sends, and removed from every namespace
+/// sent to it, so the peer uses its own names while the application sees them under the prefix.
+/// Peers in different scopes may then use the same names without colliding.
+///
+/// Set with [super::Session::with_scope].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NamespaceScope {
+    prefix: TrackNamespace,
+}
+
+impl NamespaceScope {
+    pub fn new(prefix: TrackNamespace) -> Self {
+        Self { prefix }
+    }
+
+    pub fn prefix(&self) -> &TrackNamespace {
+        &self.prefix
+    }
+
+    /// The application's name for a namespace used by the peer.
+    pub fn scope(&self, namespace: &TrackNamespace) -> Result<TrackNamespace, DecodeError> {
+        let mut fields = self.prefix.fields.clone();
+        fields.extend(namespace.fields.iter().cloned());
+
+        TrackNamespace::try_from(fields)
+            .map_err(|_| DecodeError::FieldBoundsExceeded("TrackNamespace tuples".to_string()))
+    }
+
+    /// The peer's name for `namespace`, or None if it's outside the scope.
+    pub fn unscope(&self, namespace: &TrackNamespace) -> Option<TrackNamespace> {
+        let fields = namespace
+            .fields
+            .strip_prefix(self.prefix.fields.as_slice())?;
+        Some(TrackNamespace {
+            fields: fields.to_vec(),
+        })
+    }
+
+    /// The peer's name for a prefix of namespaces. A prefix covering the whole scope becomes
+    /// the empty prefix, matching all of the peer's namespaces.
+    pub fn unscope_prefix(&self, prefix: &TrackNamespace) -> Option<TrackNamespace> {
+        match self.prefix.has_prefix(prefix) {
+            true => Some(TrackNamespace::new()),
+            false => self.unscope(prefix),
+        }
+    }
+
+    // Add the prefix to the namespace of a message received from the peer.
+    pub(super) fn recv(&self, msg: &mut Message) -> Result<(), DecodeError> {
+        if let Some(namespace) = namespace_mut(msg) {
+            *namespace = self.scope(namespace)?;
+        }
+        Ok(())
+    }
+
+    // Remove the prefix from the namespace of a message sent to the peer.
+    // Returns false if the namespace is outside the scope, so the message must not be sent.
+    pub(super) fn send(&self, msg: &mut Message) -> bool {
+        let is_prefix = matches!(
+            msg,
+            Message::SubscribeNamespace(_) | Message::UnsubscribeNamespace(_)
+        );
+        let Some(namespace) = namespace_mut(msg) else {
+            return true;
+        };
+
+        let unscoped = match is_prefix {
+            true => self.unscope_prefix(namespace),
+            false => self.unscope(namespace),
+        };
+
+        match unscoped {
+            Some(unscoped) => {
+                *namespace = unscoped;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// The namespace a control message refers to, if any.
+fn namespace_mut(msg: &mut Message) -> Option<&mut TrackNamespace> {
+    match msg {
+        Message::Subscribe(msg) => Some(&mut msg.track_namespace),
+        Message::Publish(msg) => Some(&mut msg.track_namespace),
+        Message::PublishNamespace(msg) => Some(&mut msg.track_namespace),
+        Message::PublishNamespaceDone(msg) => Some(&mut msg.track_namespace),
+        Message::PublishNamespaceCancel(msg) => Some(&mut msg.track_namespace),
+        Message::TrackStatus(msg) => Some(&mut msg.track_namespace),
+        Message::SubscribeNamespace(msg) => Some(&mut msg.track_namespace_prefix),
+        Message::UnsubscribeNamespace(msg) => Some(&mut msg.track_namespace_prefix),
+        Message::Fetch(msg) => msg
+            .standalone_fetch
+            .as_mut()
+            .map(|fetch| &mut fetch.track_namespace),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message;
+
+    fn scope() -> NamespaceScope {
+        NamespaceScope::new(TrackNamespace::from_utf8_path("tenant"))
+    }
+
+    #[test]
+    fn rewrites_namespaces() {
+        let scope = scope();
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let mut msg = Message::PublishNamespaceDone(message::PublishNamespaceDone {
+            track_namespace: namespace.clone(),
+        });
+        scope.recv(&mut msg).unwrap();
+        let Message::PublishNamespaceDone(done) = &msg else {
+            unreachable!()
+        };
+        assert_eq!(
+            done.track_namespace,
+            TrackNamespace::from_utf8_path("tenant/live")
+        );
+
+        assert!(scope.send(&mut msg));
+        let Message::PublishNamespaceDone(done) = &msg else {
+            unreachable!()
+        };
+        assert_eq!(done.track_namespace, namespace);
+
+        // Other scopes' namespaces are never sent.
+        let mut other = Message::PublishNamespaceDone(message::PublishNamespaceDone {
+            track_namespace: TrackNamespace::from_utf8_path("other/live"),
+        });
+        assert!(!scope.send(&mut other));
+    }
+
+    #[test]
+    fn unscopes_prefixes() {
+        let scope = scope();
+
+        assert_eq!(
+            scope.unscope_prefix(&TrackNamespace::from_utf8_path("tenant/live")),
+            Some(TrackNamespace::from_utf8_path("live"))
+        );
+        // Interest in everything covers every namespace of the scope.
+        assert_eq!(
+            scope.unscope_prefix(&TrackNamespace::new()),
+            Some(TrackNamespace::new())
+        );
+        assert_eq!(
+            scope.unscope_prefix(&TrackNamespace::from_utf8_path("other")),
+            None
+        );
+
+        // The scope can't grow a namespace past the field limit.
+        let field = TrackNamespace::from_utf8_path("a").fields.remove(0);
+        let long = TrackNamespace {
+            fields: vec![field; TrackNamespace::MAX_FIELDS],
+        };
+        assert!(scope.scope(&long).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::Instant,
+};
+
+use bytes::{Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+use crate::coding::Encode;
+
+/// Whether the recorded session sent or received a [ScriptLine].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptDirection {
+    Sent,
+    Received,
+}
+
+/// A control message recorded by a [ScriptWriter], as it went over the control stream.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptLine {
+    /// Milliseconds since the recording started.
+    pub time_ms: u64,
+
+    pub direction: ScriptDirection,
+
+    /// The message type, ex. `Subscribe`, to make the script readable.
+    pub message: String,
+
+    /// The encoded message, including its type and length, in hex.
+    pub data: String,
+}
+
+impl ScriptLine {
+    /// The encoded message.
+    pub fn bytes(&self) -> io::Result<Bytes> {
+        hex::decode(&self.data)
+            .map(Bytes::from)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+}
+
+/// Read a script written by a [ScriptWriter], one line per message.
+pub fn read_script(path: impl AsRef<Path>) -> io::Result<Vec<ScriptLine>> {
+    let file = BufReader::new(File::open(path)?);
+
+    let mut lines = Vec::new();
+    for line in file.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        lines.push(serde_json::from_str(&line)?);
+    }
+
+    Ok(lines)
+}
+
+// A control message waiting to be recorded, ex. the SETUP messages exchanged before recording.
+pub(super) struct ScriptMessage {
+    direction: ScriptDirection,
+    message: &'static str,
+    data: Bytes,
+}
+
+impl ScriptMessage {
+    pub(super) fn new<M: Encode>(
+        direction: ScriptDirection,
+        message: &'static str,
+        msg: &M,
+    ) -> Self {
+        // Only messages that went over the wire are recorded, so they encode.
+        let mut data = BytesMut::new();
+        let _ = msg.encode(&mut data);
+
+        Self {
+            direction,
+            message,
+            data: data.freeze(),
+        }
+    }
+}
+
+/// Records every control message of a session with its timing, see
+/// [crate::session::Session::with_script].
+///
+/// Each message is a JSON line of its own, flushed as it's recorded, so the script survives a
+/// crash. Replaying it, ex. with moq-test's `Replayer`, turns an interop failure into a
+/// deterministic regression test.
+pub struct ScriptWriter {
+    writer: BufWriter<File>,
+    start: Instant,
+}
+
+impl ScriptWriter {
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    pub(super) fn record(&mut self, msg: ScriptMessage) -> io::Result<()> {
+        let line = ScriptLine {
+            time_ms: self.start.elapsed().as_millis() as u64,
+            direction: msg.direction,
+            message: msg.message.to_string(),
+            data: hex::encode(&msg.data),
+        };
+
+        serde_json::to_writer(&mut self.writer, &line)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{coding::Decode, message};
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("moq-script-{}.jsonl", uuid::Uuid::new_v4()));
+        let msg = message::Message::MaxRequestId(message::MaxRequestId { request_id: 42 });
+
+        let mut writer = ScriptWriter::new(&path).unwrap();
+        writer
+            .record(ScriptMessage::new(
+                ScriptDirection::Received,
+                msg.name(),
+                &msg,
+            ))
+            .unwrap();
+        drop(writer);
+
+        let lines = read_script(&path).unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].direction, ScriptDirection::Received);
+        assert_eq!(lines[0].message, "MaxRequestId");
+
+        let mut data = lines[0].bytes().unwrap();
+        match message::Message::decode(&mut data).unwrap() {
+            message::Message::MaxRequestId(m) => assert_eq!(m.request_id, 42),
+            _ => panic!("wrong message"),
+        }
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+/// What to do with an object under the session's egress limit, see [EgressShaper::admit].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Admit {
+    /// Send it now.
+    Now,
+
+    /// Send it once the limit allows, at this time.
+    At(Instant),
+
+    /// Drop it, leaving the bandwidth to more important subgroups.
+    Drop,
+}
+
+#[derive(Default)]
+struct ShaperState {
+    // Bits per second, unlimited if unset.
+    bitrate: Option<u64>,
+
+    // Bytes that may be sent right away, negative once more important objects borrowed ahead.
+    tokens: f64,
+    refilled: Option<Instant>,
+
+    // The number of subgroup streams with an object to send at each stream priority.
+    active: BTreeMap<i32, usize>,
+}
+
+impl ShaperState {
+    fn capacity(bitrate: u64) -> f64 {
+        bitrate as f64 / 8.0 * EgressShaper::BURST.as_secs_f64()
+    }
+
+    fn refill(&mut self, bitrate: u64, now: Instant) {
+        let capacity = Self::capacity(bitrate);
+        self.tokens = match self.refilled {
+            Some(refilled) => {
+                let earned = (now - refilled).as_secs_f64() * bitrate as f64 / 8.0;
+                (self.tokens + earned).min(capacity)
+            }
+            None => capacity,
+        };
+        self.refilled = Some(now);
+    }
+}
+
+/// A token bucket limiting everything a session sends on live subgroups and datagrams to a
+/// bitrate, so one subscriber on a fast link can't take all of the relay's bandwidth.
+///
+/// When the bucket runs dry, objects of the most important subgroups being sent wait for it to
+/// refill, and the others are dropped, so less important subgroups are the first to go.
+#[derive(Clone, Default)]
+pub(super) struct EgressShaper {
+    state: Arc<Mutex<ShaperState>>,
+}
+
+impl EgressShaper {
+    // Objects may be sent this far ahead of the bitrate, absorbing bursts of small objects.
+    const BURST: Duration = Duration::from_millis(100);
+
+    pub fn set_bitrate(&self, bitrate: Option<u64>) {
+        let mut state = self.state.lock().unwrap();
+        state.bitrate = bitrate;
+        state.refilled = None;
+    }
+
+    pub fn bitrate(&self) -> Option<u64> {
+        self.state.lock().unwrap().bitrate
+    }
+
+    /// Shape a subgroup stream sent at `priority`. It only holds back less important streams
+    /// while it has an object to send, see [ShapedStream::idle].
+    pub fn stream(&self, priority: i32) -> ShapedStream {
+        ShapedStream {
+            shaper: self.clone(),
+            priority,
+            active: false,
+        }
+    }
+
+    /// Whether an object of `bytes`, sent at stream `priority`, may be sent.
+    pub fn admit(&self, bytes: usize, priority: i32) -> Admit {
+        let mut state = self.state.lock().unwrap();
+        let Some(bitrate) = state.bitrate.filter(|bitrate| *bitrate > 0) else {
+            return Admit::Now;
+        };
+
+        let now = Instant::now();
+        state.refill(bitrate, now);
+
+        // Objects larger than the bucket go through once it's full.
+        let bytes = bytes as f64;
+        if state.tokens >= bytes.min(ShaperState::capacity(bitrate)) {
+            state.tokens -= bytes;
+            return Admit::Now;
+        }
+
+        // Only the most important subgroups borrow ahead, and wait to send.
+        let highest = state.active.keys().next_back().copied();
+        if highest.is_some_and(|highest| priority < highest) {
+            return Admit::Drop;
+        }
+
+        state.tokens -= bytes;
+        let wait = -state.tokens * 8.0 / bitrate as f64;
+        Admit::At(now + Duration::from_secs_f64(wait.max(0.0)))
+    }
+
+    fn register(&self, priority: i32) {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .active
+            .entry(priority)
+            .or_default() += 1;
+    }
+
+    fn unregister(&self, priority: i32) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(count) = state.active.get_mut(&priority) {
+            *count -= 1;
+            if *count == 0 {
+                state.active.remove(&priority);
+            }
+        }
+    }
+}
+
+/// A subgroup stream registered with an [EgressShaper], see [EgressShaper::stream].
+pub(super) struct ShapedStream {
+    shaper: EgressShaper,
+    priority: i32,
+
+    // Registered with the shaper, from admitting an object until it's written.
+    active: bool,
+}
+
+impl ShapedStream {
+    /// Move the stream to another priority, ex. after SUBSCRIBE_UPDATE.
+    pub fn set_priority(&mut self, priority: i32) {
+        if priority != self.priority && self.active {
+            self.shaper.unregister(self.priority);
+            self.shaper.register(priority);
+        }
+        self.priority = priority;
+    }
+
+    /// Whether the stream's next object, of `bytes`, may be sent.
+    pub fn admit(&mut self, bytes: usize) -> Admit {
+        if !self.active {
+            self.shaper.register(self.priority);
+            self.active = true;
+        }
+        self.shaper.admit(bytes, self.priority)
+    }
+
+    /// The stream wrote its object and waits for the next one, leaving the bandwidth to others.
+    pub fn idle(&mut self) {
+        if self.active {
+            self.shaper.unregister(self.priority);
+            self.active = false;
+        }
+    }
+}
+
+impl Drop for ShapedStream {
+    fn drop(&mut self) {
+        self.idle();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn drops_less_important() {
+        let shaper = EgressShaper::default();
+        assert_eq!(shaper.admit(1_000_000, 0), Admit::Now);
+
+        // 80 kbps fills a 1000 byte bucket every 100ms.
+        shaper.set_bitrate(Some(80_000));
+        let mut audio = shaper.stream(2);
+        let mut video = shaper.stream(1);
+        let start = Instant::now();
+
+        assert_eq!(video.admit(600), Admit::Now);
+        assert_eq!(audio.admit(300), Admit::Now);
+
+        // The bucket is nearly empty, so the video is dropped and the audio waits.
+        assert_eq!(video.admit(600), Admit::Drop);
+        assert_eq!(
+            audio.admit(300),
+            Admit::At(start + Duration::from_millis(20))
+        );
+
+        // Once refilled, both are sent again.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(video.admit(600), Admit::Now);
+
+        // Without the audio, the video is the most important and waits instead.
+        drop(audio);
+        assert_eq!(
+            video.admit(600),
+            Admit::At(Instant::now() + Duration::from_millis(20))
+        );
+
+        // Unless a more important stream has an object to send.
+        video.set_priority(0);
+        let mut catalog = shaper.stream(1);
+        assert!(matches!(catalog.admit(100), Admit::At(_)));
+        assert_eq!(video.admit(600), Admit::Drop);
+
+        // But not once it's idle.
+        catalog.idle();
+        assert!(matches!(video.admit(600), Admit::At(_)));
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::coding::TrackNamespace;
+
+/// Estimates how far the clocks of a peer's publishers are ahead of ours, from the capture
+/// timestamps of the objects they send, see [crate::data::ExtensionHeaders::capture_timestamp].
+///
+/// An object's capture timestamp less the time we received it is the offset less the delay of
+/// that object. The largest over a window comes from the object delayed the least, which is
+/// assumed to have taken just the one-way delay of the path, see [Self::set_one_way_delay].
+///
+/// A relay forwards the tracks of many publishers over one session, so each namespace has an
+/// estimate of its own. Cloned handles share the estimates.
+#[derive(Clone, Default)]
+pub struct ClockSkew {
+    state: Arc<Mutex<SkewState>>,
+}
+
+#[derive(Default)]
+struct SkewState {
+    origins: HashMap<TrackNamespace, Estimate>,
+    one_way_delay: Duration,
+
+    // The namespace of the latest object with a capture timestamp.
+    latest: Option<TrackNamespace>,
+
+    samples: u64,
+    latency_sum: Duration,
+    latency_max: Duration,
+}
+
+#[derive(Default)]
+struct Estimate {
+    // The samples that may still be the largest in the window, in microseconds, each smaller
+    // than those before it, so the front is the largest.
+    window: VecDeque<(Instant, i64)>,
+
+    // A sample too far ahead of the others to be trusted on its own, see ClockSkew::OUTLIER.
+    suspect: Option<(Instant, i64)>,
+}
+
+/// A snapshot of a [ClockSkew].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClockSkewStats {
+    /// Microseconds the clock of the publisher of the latest object with a capture timestamp is
+    /// ahead of ours, or behind if negative. None until such an object arrives.
+    pub offset_us: Option<i64>,
+
+    /// Objects received with a capture timestamp.
+    pub samples: u64,
+
+    /// How long after their capture those objects were received, corrected for the offset.
+    pub latency_avg: Duration,
+    pub latency_max: Duration,
+}
+
+impl ClockSkew {
+    /// How long an object counts towards the estimate, so it follows a clock that drifts or jumps.
+    pub const WINDOW: Duration = Duration::from_secs(10);
+
+    /// How much further ahead than the estimate a capture timestamp may be on its own. A larger
+    /// jump only counts once the next timestamp confirms it, so one wrong timestamp doesn't
+    /// throw the estimate off for a whole window.
+    pub const OUTLIER: Duration = Duration::from_secs(1);
+
+    /// Set the delay of the fastest objects on the path, ex. half the round-trip time, which the
+    /// capture timestamps can't tell apart from the offset. Zero until set.
+    pub fn set_one_way_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().one_way_delay = delay;
+    }
+
+    /// Count an object of `namespace` captured at `captured` by its publisher's clock and
+    /// received now, returning how long ago it was captured by our clock.
+    pub fn record(&self, namespace: &TrackNamespace, captured: SystemTime) -> Duration {
+        self.record_at(namespace, captured, SystemTime::now(), Instant::now())
+    }
+
+    fn record_at(
+        &self,
+        namespace: &TrackNamespace,
+        captured: SystemTime,
+        received: SystemTime,
+        now: Instant,
+    ) -> Duration {
+        let sample = micros(captured) - micros(received);
+
+        let mut state = self.state.lock().unwrap();
+        let one_way_delay = state.one_way_delay;
+
+        // Forget the publishers that went quiet, before adding one.
+        if !state.origins.contains_key(namespace) {
+            state.origins.retain(|_, estimate| {
+                estimate
+                    .window
+                    .back()
+                    .is_some_and(|(at, _)| *at + Self::WINDOW > now)
+            });
+        }
+
+        let estimate = state.origins.entry(namespace.clone()).or_default();
+        estimate.record(sample, now);
+        let offset = estimate.offset(one_way_delay).unwrap_or(sample);
+        let age = Duration::from_micros((offset - sample).max(0) as u64);
+
+        state.latest = Some(namespace.clone());
+        state.samples += 1;
+        state.latency_sum = state.latency_sum.saturating_add(age);
+        state.latency_max = state.latency_max.max(age);
+
+        age
+    }
+
+    /// Microseconds the clock of the publisher of `namespace` is ahead of ours, if any of its
+    /// timestamps arrived.
+    pub fn offset_us(&self, namespace: &TrackNamespace) -> Option<i64> {
+        let state = self.state.lock().unwrap();
+        state.origins.get(namespace)?.offset(state.one_way_delay)
+    }
+
+    pub fn stats(&self) -> ClockSkewStats {
+        let state = self.state.lock().unwrap();
+        let offset_us = state
+            .latest
+            .as_ref()
+            .and_then(|latest| state.origins.get(latest))
+            .and_then(|estimate| estimate.offset(state.one_way_delay));
+
+        // Averaged in nanoseconds, as the sample count may not fit a u32.
+        let latency_avg = state
+            .latency_sum
+            .as_nanos()
+            .checked_div(state.samples.into())
+            .map_or(Duration::ZERO, |avg| Duration::from_nanos(avg as u64));
+
+        ClockSkewStats {
+            offset_us,
+            samples: state.samples,
+            latency_avg,
+            latency_max: state.latency_max,
+        }
+    }
+}
+
+impl Estimate {
+    fn record(&mut self, sample: i64, now: Instant) {
+        while self
+            .window
+            .front()
+            .is_some_and(|(at, _)| *at + ClockSkew::WINDOW <= now)
+        {
+            self.window.pop_front();
+        }
+
+        // Hold back a sample far ahead of the window, unless it confirms the previous one.
+        let outlier = ClockSkew::OUTLIER.as_micros() as i64;
+        if let Some((_, max)) = self.window.front() {
+            if sample > max + outlier {
+                match self.suspect.take() {
+                    Some(suspect) if (suspect.1 - sample).abs() <= outlier => self.push(suspect),
+                    _ => {
+                        self.suspect = Some((now, sample));
+                        return;
+                    }
+                }
+            }
+        }
+        self.suspect = None;
+        self.push((now, sample));
+    }
+
+    fn push(&mut self, (at, sample): (Instant, i64)) {
+        while self.window.back().is_some_and(|(_, max)| *max <= sample) {
+            self.window.pop_back();
+        }
+        self.window.push_back((at, sample));
+    }
+
+    fn offset(&self, one_way_delay: Duration) -> Option<i64> {
+        let (_, max) = self.window.front()?;
+        Some(max + one_way_delay.as_micros() as i64)
+    }
+}
+
+// Microseconds since the Unix epoch, negative before it.
+fn micros(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_micros() as i64,
+        Err(err) => -(err.duration().as_micros() as i64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    fn live() -> TrackNamespace {
+        TrackNamespace::from_utf8_path("live")
+    }
+
+    #[test]
+    fn estimates_offset() {
+        let skew = ClockSkew::default();
+        assert_eq!(skew.offset_us(&live()), None);
+
+        // The publisher's clock is 5s ahead, and objects take 20-60ms to arrive.
+        let now = Instant::now();
+        let start = SystemTime::now();
+        let ahead = Duration::from_secs(5);
+        let mut ages = Vec::new();
+        for (i, delay) in [60, 20, 40, 35].into_iter().enumerate() {
+            let captured = start + ahead + i as u32 * 100 * MS;
+            let received = start + i as u32 * 100 * MS + delay * MS;
+            ages.push(skew.record_at(&live(), captured, received, now + i as u32 * 100 * MS));
+        }
+
+        // Relative to the fastest object so far, until the path's delay is known.
+        assert_eq!(ages, [Duration::ZERO, Duration::ZERO, 20 * MS, 15 * MS]);
+        assert_eq!(skew.offset_us(&live()), Some(4_980_000));
+
+        skew.set_one_way_delay(20 * MS);
+        assert_eq!(skew.offset_us(&live()), Some(5_000_000));
+        let captured = start + ahead + Duration::from_secs(1);
+        let received = start + Duration::from_secs(1) + 30 * MS;
+        assert_eq!(
+            skew.record_at(&live(), captured, received, now + Duration::from_secs(1)),
+            30 * MS
+        );
+
+        let stats = skew.stats();
+        assert_eq!(stats.offset_us, Some(5_000_000));
+        assert_eq!(stats.samples, 5);
+        assert_eq!(stats.latency_max, 30 * MS);
+        assert_eq!(stats.latency_avg, 13 * MS);
+    }
+
+    #[test]
+    fn follows_clock_changes() {
+        let skew = ClockSkew::default();
+        let now = Instant::now();
+        let start = SystemTime::now();
+
+        // 2s behind, then corrected by the publisher, which takes two timestamps to trust.
+        skew.record_at(&live(), start - Duration::from_secs(2), start, now);
+        assert_eq!(skew.offset_us(&live()), Some(-2_000_000));
+        skew.record_at(
+            &live(),
+            start,
+            start + 10 * MS,
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(skew.offset_us(&live()), Some(-2_000_000));
+        skew.record_at(
+            &live(),
+            start,
+            start + 20 * MS,
+            now + Duration::from_secs(2),
+        );
+        assert_eq!(skew.offset_us(&live()), Some(-10_000));
+
+        // An old fast sample is forgotten after the window.
+        let skew = ClockSkew::default();
+        skew.record_at(&live(), start, start, now);
+        skew.record_at(
+            &live(),
+            start,
+            start + 50 * MS,
+            now + Duration::from_secs(1),
+        );
+        assert_eq!(skew.offset_us(&live()), Some(0));
+        skew.record_at(&live(), start, start + 80 * MS, now + ClockSkew::WINDOW);
+        assert_eq!(skew.offset_us(&live()), Some(-50_000));
+    }
+
+    #[test]
+    fn rejects_outliers() {
+        let skew = ClockSkew::default();
+        let now = Instant::now();
+        let start = SystemTime::now();
+
+        // One timestamp an hour ahead, between two that agree, is ignored.
+        let hour = Duration::from_secs(3600);
+        skew.record_at(&live(), start, start + 20 * MS, now);
+        skew.record_at(&live(), start + hour, start, now + 100 * MS);
+        skew.record_at(&live(), start, start + 30 * MS, now + 200 * MS);
+        assert_eq!(skew.offset_us(&live()), Some(-20_000));
+
+        let stats = skew.stats();
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.latency_max, 10 * MS);
+    }
+
+    #[test]
+    fn estimates_each_namespace() {
+        let skew = ClockSkew::default();
+        let now = Instant::now();
+        let start = SystemTime::now();
+
+        // Two publishers behind a relay, one 5s ahead and one in sync.
+        let ahead = TrackNamespace::from_utf8_path("ahead");
+        skew.record_at(&ahead, start + Duration::from_secs(5), start, now);
+        skew.record_at(&live(), start, start, now);
+        assert_eq!(skew.offset_us(&ahead), Some(5_000_000));
+        assert_eq!(skew.offset_us(&live()), Some(0));
+        assert_eq!(skew.stats().offset_us, Some(0));
+
+        // A publisher that went quiet is forgotten once another appears.
+        let later = now + ClockSkew::WINDOW;
+        skew.record_at(&live(), start, start, later);
+        skew.record_at(&TrackNamespace::from_utf8_path("new"), start, start, later);
+        assert_eq!(skew.offset_us(&ahead), None);
+        assert_eq!(skew.offset_us(&live()), Some(0));
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::watch::State;
+
+use super::{SubscribedRecv, SubscriptionSnapshot};
+
+/// How a subscription to one of our tracks is being delivered, see [DeliveryStats].
+///
+/// Counts cover object payloads only, not the headers framing them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubscriptionStats {
+    /// Payload bytes written to the subscriber's streams and datagrams.
+    pub bytes_sent: u64,
+
+    /// Objects written in full.
+    pub objects_sent: u64,
+
+    /// Objects skipped instead of sent, because they were past their delivery timeout.
+    pub objects_skipped: u64,
+
+    /// Payload bytes of the objects being sent but not written yet, ex. waiting for flow control,
+    /// congestion control or pacing. Growing steadily when the subscriber can't keep up.
+    pub queued_bytes: u64,
+
+    /// Subgroup streams that ended with an error, ex. reset by the subscriber.
+    pub streams_reset: u64,
+
+    /// Groups not delivered on purpose, ex. while forwarding was paused, after skipping to the
+    /// latest group or dropping subgroups to keep up. Counted once delivery resumes.
+    pub groups_skipped: u64,
+
+    /// Subgroups cut short, and datagrams not sent, to keep the session within its egress
+    /// bitrate, see [super::Publisher::set_egress_bitrate].
+    pub egress_dropped: u64,
+
+    /// The subscriber priority currently requested, as updated by SUBSCRIBE_UPDATE.
+    /// Lower values are more important.
+    pub subscriber_priority: u8,
+
+    /// The rate payload was written at recently, in bits per second.
+    pub send_rate: u64,
+}
+
+/// The live [SubscriptionStats] of a subscription, updated as its objects are written.
+///
+/// A publisher adapting its encoding to the subscriber (ABR) can watch `send_rate` and
+/// `queued_bytes` to pick the rendition to publish. Cloned handles observe the same stats.
+#[derive(Clone)]
+pub struct DeliveryStats {
+    state: State<StatsState>,
+}
+
+#[derive(Debug, Default)]
+struct StatsState {
+    stats: SubscriptionStats,
+
+    // The bytes sent since the current rate window started.
+    window_start: Option<Instant>,
+    window_bytes: u64,
+}
+
+impl StatsState {
+    fn send_rate(&self, now: Instant) -> u64 {
+        match self.window_start {
+            // Nothing was written for a while, so the last full window is out of date.
+            Some(start) if now - start >= DeliveryStats::WINDOW * 2 => {
+                rate(self.window_bytes, now - start)
+            }
+            _ => self.stats.send_rate,
+        }
+    }
+}
+
+fn rate(bytes: u64, elapsed: Duration) -> u64 {
+    (bytes as f64 * 8.0 / elapsed.as_secs_f64()) as u64
+}
+
+impl DeliveryStats {
+    // The send rate is measured over windows of about this long.
+    const WINDOW: Duration = Duration::from_millis(500);
+
+    /// Returns the handle updated by the session, and the one handed to the application.
+    pub(super) fn produce(subscriber_priority: u8) -> (Self, Self) {
+        let mut state = StatsState::default();
+        state.stats.subscriber_priority = subscriber_priority;
+
+        let (send, recv) = State::new(state).split();
+        (Self { state: send }, Self { state: recv })
+    }
+
+    pub fn get(&self) -> SubscriptionStats {
+        let state = self.state.lock();
+        SubscriptionStats {
+            send_rate: state.send_rate(Instant::now()),
+            ..state.stats.clone()
+        }
+    }
+
+    /// Wait for the stats to change, returning them, or `None` once the subscription has ended.
+    pub async fn next(&self) -> Option<SubscriptionStats> {
+        self.state.lock().modified()?.await;
+        Some(self.get())
+    }
+
+    /// Count `bytes` of an object as queued, until they are written through the returned handle.
+    pub(super) fn queue(&self, bytes: usize) -> Queued {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.queued_bytes += bytes as u64;
+        }
+
+        Queued {
+            stats: self.clone(),
+            remaining: bytes as u64,
+        }
+    }
+
+    pub(super) fn sent(&self, bytes: usize) {
+        self.sent_at(bytes, Instant::now());
+    }
+
+    fn sent_at(&self, bytes: usize, now: Instant) {
+        let Some(mut state) = self.state.lock_mut() else {
+            return;
+        };
+
+        state.stats.bytes_sent += bytes as u64;
+        state.window_bytes += bytes as u64;
+
+        let start = *state.window_start.get_or_insert(now);
+        if now - start >= Self::WINDOW {
+            state.stats.send_rate = rate(state.window_bytes, now - start);
+            state.window_start = Some(now);
+            state.window_bytes = 0;
+        }
+    }
+
+    pub(super) fn object_sent(&self) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.objects_sent += 1;
+        }
+    }
+
+    pub(super) fn object_skipped(&self) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.objects_skipped += 1;
+        }
+    }
+
+    pub(super) fn stream_reset(&self) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.streams_reset += 1;
+        }
+    }
+
+    pub(super) fn egress_dropped(&self) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.egress_dropped += 1;
+        }
+    }
+
+    pub(super) fn groups_skipped(&self, count: u64) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.stats.groups_skipped += count;
+        }
+    }
+
+    pub(super) fn set_subscriber_priority(&self, priority: u8) {
+        let state = self.state.lock();
+        if state.stats.subscriber_priority == priority {
+            return;
+        }
+
+        if let Some(mut state) = state.into_mut() {
+            state.stats.subscriber_priority = priority;
+        }
+    }
+}
+
+/// The queued bytes of an object, no longer counted once written or dropped.
+pub(super) struct Queued {
+    stats: DeliveryStats,
+    remaining: u64,
+}
+
+impl Queued {
+    pub fn sent(&mut self, bytes: usize) {
+        let bytes = (bytes as u64).min(self.remaining);
+        self.remaining -= bytes;
+
+        if let Some(mut state) = self.stats.state.lock_mut() {
+            state.stats.queued_bytes -= bytes;
+        }
+    }
+}
+
+impl Drop for Queued {
+    fn drop(&mut self) {
+        if self.remaining == 0 {
+            return;
+        }
+
+        if let Some(mut state) = self.stats.state.lock_mut() {
+            state.stats.queued_bytes -= self.remaining;
+        }
+    }
+}
+
+/// The stats of every subscription to our tracks, reported periodically.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StatsEvent {
+    pub subscription: SubscriptionSnapshot,
+    pub stats: SubscriptionStats,
+}
+
+/// Reports the [StatsEvent]s of a session's subscriptions every period, created with
+/// [super::Publisher::stats_events].
+///
+/// Suits reporting aggregate health upstream, or adapting per subscriber without watching
+/// each [DeliveryStats] separately.
+pub struct StatsEvents {
+    subscribeds: Weak<Mutex<HashMap<u64, SubscribedRecv>>>,
+    interval: tokio::time::Interval,
+}
+
+impl StatsEvents {
+    pub(super) fn new(
+        subscribeds: Weak<Mutex<HashMap<u64, SubscribedRecv>>>,
+        period: Duration,
+    ) -> Self {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        Self {
+            subscribeds,
+            interval,
+        }
+    }
+
+    /// Wait for the next period, returning the stats of the active subscriptions in request
+    /// order, or `None` once the session and every [super::Publisher] handle are gone.
+    pub async fn next(&mut self) -> Option<Vec<StatsEvent>> {
+        self.interval.tick().await;
+
+        let subscribeds = self.subscribeds.upgrade()?;
+        let mut events: Vec<_> = subscribeds
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, subscribed)| StatsEvent {
+                subscription: subscribed.snapshot(*id),
+                stats: subscribed.stats().get(),
+            })
+            .collect();
+
+        events.sort_by_key(|event| event.subscription.id);
+        Some(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_delivery() {
+        let (send, recv) = DeliveryStats::produce(127);
+
+        // An object stays queued until written, or abandoned.
+        let mut queued = send.queue(1000);
+        assert_eq!(recv.get().queued_bytes, 1000);
+        queued.sent(400);
+        send.sent(400);
+        assert_eq!(recv.get().queued_bytes, 600);
+        drop(queued);
+        send.stream_reset();
+
+        let stats = recv.get();
+        assert_eq!(stats.queued_bytes, 0);
+        assert_eq!(stats.bytes_sent, 400);
+        assert_eq!(stats.streams_reset, 1);
+        assert_eq!(stats.objects_sent, 0);
+        assert_eq!(stats.subscriber_priority, 127);
+
+        send.groups_skipped(3);
+        recv.set_subscriber_priority(1);
+        let stats = recv.get();
+        assert_eq!(stats.groups_skipped, 3);
+        assert_eq!(stats.subscriber_priority, 1);
+
+        // Nothing more is counted once the session's side is gone.
+        drop(send);
+        assert_eq!(futures::executor::block_on(recv.next()), None);
+    }
+
+    #[test]
+    fn send_rate() {
+        let (send, recv) = DeliveryStats::produce(127);
+        let start = Instant::now();
+
+        // 125KB over half a second is 2 Mbps.
+        send.sent_at(0, start);
+        send.sent_at(62_500, start + Duration::from_millis(250));
+        send.sent_at(62_500, start + DeliveryStats::WINDOW);
+        assert_eq!(
+            recv.state.lock().send_rate(start + DeliveryStats::WINDOW),
+            2_000_000
+        );
+
+        // The rate decays once nothing more is sent.
+        send.sent_at(12_500, start + Duration::from_millis(600));
+        let idle = recv.state.lock().send_rate(start + Duration::from_secs(2));
+        assert_eq!(idle, 12_500 * 8 * 2 / 3);
+    }
+}
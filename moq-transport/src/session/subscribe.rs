@@ -1,7 +1,7 @@
-use std::ops;
+use std::{future, ops, task, time::Duration};
 
 use crate::{
-    coding::{KeyValuePairs, Location, TrackNamespace},
+    coding::{Decode, Encode, KeyValuePairs, Location, Telemetry, Token, TrackNamespace},
     data,
     message::{self, FilterType, GroupOrder},
     serve::{self, ServeError, TrackWriter, TrackWriterMode},
@@ -9,7 +9,9 @@ use crate::{
 
 use crate::watch::State;
 
-use super::Subscriber;
+use super::{
+    auth_token_param, RequestState, Subscriber, SubscriptionPosition, SubscriptionSnapshot,
+};
 
 // TODO rename to SubscriptionInfo when used for Publishes as well?
 #[derive(Debug, Clone)]
@@ -38,6 +40,9 @@ pub struct SubscribeInfo {
 
     // Set to true if this is a track_status request only
     pub track_status: bool,
+
+    /// Authorization tokens carried by the request, with aliases resolved.
+    pub authorization_tokens: Vec<Token>,
 }
 
 impl SubscribeInfo {
@@ -54,13 +59,87 @@ impl SubscribeInfo {
             end_group_id: msg.end_group_id,
             params: msg.params.clone(),
             track_status: false,
+            authorization_tokens: Vec::new(),
         }
     }
+
+    /// Whether the subscriber asked to start at the first object of the latest cached group.
+    pub fn group_start(&self) -> bool {
+        self.params
+            .get_intvalue(message::ParameterType::GroupStart.into())
+            .is_some_and(|value| value != 0)
+    }
+
+    /// The DELIVERY_TIMEOUT carried in the subscription parameters, if any.
+    pub fn delivery_timeout(&self) -> Option<Duration> {
+        delivery_timeout_param(&self.params)
+    }
+
+    /// The bitrate the subscriber asked delivery to be paced to, if any.
+    pub fn max_bitrate(&self) -> Option<u64> {
+        max_bitrate_param(&self.params)
+    }
+
+    /// The trace ID carried in the subscription parameters, if any.
+    pub fn trace_id(&self) -> Option<String> {
+        let bytes = self
+            .params
+            .get_bytesvalue(message::ParameterType::TraceId.into())?;
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    /// The playback telemetry carried in the subscription parameters, if any.
+    pub fn telemetry(&self) -> Option<Telemetry> {
+        telemetry_param(&self.params)
+    }
+
+    /// The groups the subscriber said it already has, which aren't sent again.
+    pub fn cached_groups(&self) -> Option<ops::RangeInclusive<u64>> {
+        let mut bytes = self
+            .params
+            .get_bytesvalue(message::ParameterType::CachedGroups.into())?;
+        let first = u64::decode(&mut bytes).ok()?;
+        let last = u64::decode(&mut bytes).ok()?;
+        (first <= last).then_some(first..=last)
+    }
+}
+
+// DELIVERY_TIMEOUT is in milliseconds; zero is treated as absent.
+pub(super) fn delivery_timeout_param(params: &KeyValuePairs) -> Option<Duration> {
+    params
+        .get_intvalue(message::ParameterType::DeliveryTimeout.into())
+        .filter(|&millis| millis > 0)
+        .map(Duration::from_millis)
+}
+
+// MAX_BITRATE is in bits per second; zero is treated as absent.
+pub(super) fn max_bitrate_param(params: &KeyValuePairs) -> Option<u64> {
+    params
+        .get_intvalue(message::ParameterType::MaxBitrate.into())
+        .filter(|&bitrate| bitrate > 0)
+}
+
+// TELEMETRY is a CMCD string; one without any known keys is treated as absent.
+pub(super) fn telemetry_param(params: &KeyValuePairs) -> Option<Telemetry> {
+    let bytes = params.get_bytesvalue(message::ParameterType::Telemetry.into())?;
+    let telemetry = Telemetry::parse(std::str::from_utf8(bytes).ok()?);
+    (!telemetry.is_empty()).then_some(telemetry)
+}
+
+fn set_telemetry_param(params: &mut KeyValuePairs, telemetry: &Telemetry) {
+    params.set_bytesvalue(
+        message::ParameterType::Telemetry.into(),
+        telemetry.to_string().into_bytes(),
+    );
 }
 
 struct SubscribeState {
     ok: bool,
     track_alias: Option<u64>,
+    expires: Option<Duration>,
+    largest_location: Option<Location>,
+    // The latest telemetry sent to the publisher.
+    telemetry: Option<Telemetry>,
     closed: Result<(), ServeError>,
 }
 
@@ -69,6 +148,9 @@ impl Default for SubscribeState {
         Self {
             ok: Default::default(),
             track_alias: None,
+            expires: None,
+            largest_location: None,
+            telemetry: None,
             closed: Ok(()),
         }
     }
@@ -79,6 +161,7 @@ impl Default for SubscribeState {
 pub struct Subscribe {
     state: State<SubscribeState>,
     subscriber: Subscriber,
+    position: SubscriptionPosition,
 
     pub info: SubscribeInfo,
 }
@@ -88,56 +171,281 @@ impl Subscribe {
         mut subscriber: Subscriber,
         request_id: u64,
         track: TrackWriter,
+        subscriber_priority: u8,
     ) -> (Subscribe, SubscribeRecv) {
+        let mut params = KeyValuePairs::default();
+        if let Some(trace_id) = &track.trace_id {
+            log::debug!(
+                "subscribing to {}/{} with trace_id={}",
+                track.namespace,
+                track.name,
+                trace_id
+            );
+            params.set_bytesvalue(
+                message::ParameterType::TraceId.into(),
+                trace_id.as_bytes().to_vec(),
+            );
+        }
+
+        if let Some(token) = &track.authorization_token {
+            auth_token_param(&mut params, token);
+        }
+
+        if let Some(timeout) = track.delivery_timeout {
+            params.set_intvalue(
+                message::ParameterType::DeliveryTimeout.into(),
+                timeout.as_millis() as u64,
+            );
+        }
+
+        if let Some(bitrate) = track.max_bitrate {
+            params.set_intvalue(message::ParameterType::MaxBitrate.into(), bitrate);
+        }
+
+        if let Some(groups) = &track.cached_groups {
+            let mut bytes = Vec::new();
+            // Group IDs always fit in a varint.
+            let _ = groups.start().encode(&mut bytes);
+            let _ = groups.end().encode(&mut bytes);
+            params.set_bytesvalue(message::ParameterType::CachedGroups.into(), bytes);
+        }
+
+        if let Some(telemetry) = &track.telemetry {
+            set_telemetry_param(&mut params, telemetry);
+        }
+
+        let filter_type = match (track.start, track.group_start) {
+            (Some(_), _) => FilterType::AbsoluteStart,
+            (None, true) => {
+                params.set_intvalue(message::ParameterType::GroupStart.into(), 1);
+                FilterType::NextGroupStart
+            }
+            (None, false) => FilterType::LargestObject,
+        };
+
         let subscribe_message = message::Subscribe {
             id: request_id,
             track_namespace: track.namespace.clone(),
             track_name: track.name.clone(),
             // TODO add prioritization logic on the publisher side
-            subscriber_priority: 127, // default to mid value, see: https://github.com/moq-wg/moq-transport/issues/504
+            subscriber_priority,
             group_order: GroupOrder::Publisher, // defer to publisher send order
-            forward: true,            // default to forwarding objects
-            filter_type: FilterType::LargestObject,
-            start_location: None,
+            forward: true,                      // default to forwarding objects
+            filter_type,
+            start_location: track.start,
             end_group_id: None,
-            params: Default::default(),
+            params,
         };
-        let info = SubscribeInfo::new_from_subscribe(&subscribe_message);
+        let mut info = SubscribeInfo::new_from_subscribe(&subscribe_message);
+        info.authorization_tokens = track.authorization_token.iter().cloned().collect();
 
         subscriber.send_message(subscribe_message);
 
-        let (send, recv) = State::default().split();
+        let (send, recv) = State::new(SubscribeState {
+            telemetry: track.telemetry.clone(),
+            ..Default::default()
+        })
+        .split();
+        let (position, watcher) = SubscriptionPosition::produce();
 
         let send = Subscribe {
             state: send,
             subscriber,
+            position: watcher,
             info,
         };
 
         let recv = SubscribeRecv {
             state: recv,
+            track_namespace: track.namespace.clone(),
+            track_name: track.name.clone(),
+            events: track.events(),
             writer: Some(track.into()),
+            position,
         };
 
         (send, recv)
     }
 
+    /// The latest object fully received from the publisher.
+    pub fn position(&self) -> SubscriptionPosition {
+        self.position.clone()
+    }
+
+    /// Wait until the publisher accepts the subscription with SUBSCRIBE_OK.
+    pub async fn ok(&self) -> Result<(), ServeError> {
+        future::poll_fn(|cx| self.poll_ok(cx)).await
+    }
+
+    /// Like [Self::ok], returning Pending and waking `cx` when the subscription changes.
+    pub fn poll_ok(&self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), ServeError>> {
+        let state = self.state.lock();
+        if state.ok {
+            return task::Poll::Ready(Ok(()));
+        }
+        state.closed.clone()?;
+
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Err(ServeError::Done)),
+        }
+    }
+
+    /// How long the publisher will keep the subscription, from SUBSCRIBE_OK, if it expires at all.
+    pub fn expires(&self) -> Option<Duration> {
+        self.state.lock().expires
+    }
+
+    /// The publisher's live edge when it accepted the subscription, from the Largest Object in
+    /// SUBSCRIBE_OK, or None if the track had no objects yet. The track's objects since then are
+    /// tracked by [serve::TrackReader::latest].
+    pub fn largest_location(&self) -> Option<Location> {
+        self.state.lock().largest_location
+    }
+
     pub async fn closed(&self) -> Result<(), ServeError> {
-        loop {
-            {
-                let state = self.state.lock();
-                state.closed.clone()?;
-
-                match state.modified() {
-                    Some(notify) => notify,
-                    None => return Ok(()),
-                }
-            }
-            .await;
+        future::poll_fn(|cx| self.poll_closed(cx)).await
+    }
+
+    /// Like [Self::closed], returning Pending and waking `cx` when the subscription changes.
+    pub fn poll_closed(&self, cx: &mut task::Context<'_>) -> task::Poll<Result<(), ServeError>> {
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        match state.register(cx.waker()) {
+            Some(()) => task::Poll::Pending,
+            None => task::Poll::Ready(Ok(())),
         }
     }
 }
 
+impl Subscribe {
+    /// Send a SUBSCRIBE_UPDATE changing the priority, forward flag, and inclusive end group.
+    ///
+    /// The end group may only be narrowed: once set, it can't be removed or increased.
+    pub fn update(
+        &mut self,
+        subscriber_priority: u8,
+        forward: bool,
+        end_group_id: Option<u64>,
+    ) -> Result<(), ServeError> {
+        self.state.lock().closed.clone()?;
+
+        let widened = match (self.info.end_group_id, end_group_id) {
+            (Some(_), None) => true,
+            (Some(current), Some(end)) => end > current,
+            (None, _) => false,
+        };
+        if widened {
+            return Err(ServeError::Internal(
+                "SUBSCRIBE_UPDATE can't widen the end group".to_string(),
+            ));
+        }
+
+        let update = message::SubscribeUpdate {
+            id: self.subscriber.get_next_request_id(),
+            subscription_request_id: self.info.id,
+            start_location: self.info.start_location.unwrap_or_default(),
+            // Encoded plus 1, with 0 meaning open-ended.
+            end_group_id: end_group_id.map_or(0, |end| end + 1),
+            subscriber_priority,
+            forward,
+            params: Default::default(),
+        };
+        self.subscriber.send_message(update);
+
+        self.info.subscriber_priority = subscriber_priority;
+        self.info.forward = forward;
+        self.info.end_group_id = end_group_id;
+
+        Ok(())
+    }
+}
+
+impl Subscribe {
+    /// Jump to the live edge after falling behind, without resubscribing.
+    ///
+    /// Discards the groups buffered locally but not yet read, then sends a SUBSCRIBE_UPDATE asking
+    /// the publisher to stop sending groups older than its latest one.
+    pub fn skip_to_latest(&mut self) -> Result<(), ServeError> {
+        self.state.lock().closed.clone()?;
+
+        self.subscriber.discard_buffered(self.info.id)?;
+
+        let mut params = KeyValuePairs::default();
+        params.set_intvalue(message::ParameterType::SkipToLatest.into(), 1);
+
+        let update = message::SubscribeUpdate {
+            id: self.subscriber.get_next_request_id(),
+            subscription_request_id: self.info.id,
+            start_location: self.info.start_location.unwrap_or_default(),
+            end_group_id: self.info.end_group_id.map_or(0, |end| end + 1),
+            subscriber_priority: self.info.subscriber_priority,
+            forward: self.info.forward,
+            params,
+        };
+        self.subscriber.send_message(update);
+
+        Ok(())
+    }
+}
+
+impl Subscribe {
+    /// Send a SUBSCRIBE_UPDATE reporting the latest playback telemetry, ex. as the buffer drains.
+    pub fn report_telemetry(&mut self, telemetry: Telemetry) -> Result<(), ServeError> {
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        let mut params = KeyValuePairs::default();
+        set_telemetry_param(&mut params, &telemetry);
+
+        let update = message::SubscribeUpdate {
+            id: self.subscriber.get_next_request_id(),
+            subscription_request_id: self.info.id,
+            start_location: self.info.start_location.unwrap_or_default(),
+            end_group_id: self.info.end_group_id.map_or(0, |end| end + 1),
+            subscriber_priority: self.info.subscriber_priority,
+            forward: self.info.forward,
+            params,
+        };
+        self.subscriber.send_message(update);
+
+        if let Some(mut state) = state.into_mut() {
+            state.telemetry = Some(telemetry);
+        }
+
+        Ok(())
+    }
+}
+
+impl Subscribe {
+    /// Send a SUBSCRIBE_UPDATE carrying a fresh AUTHORIZATION TOKEN, before the old one expires.
+    ///
+    /// Publishers that re-check long-lived subscriptions end them with UNAUTHORIZED in PUBLISH_DONE
+    /// once the token is rejected, unless it's renewed in time.
+    pub fn renew_authorization(&mut self, token: Token) -> Result<(), ServeError> {
+        self.state.lock().closed.clone()?;
+
+        let mut params = KeyValuePairs::default();
+        auth_token_param(&mut params, &token);
+
+        let update = message::SubscribeUpdate {
+            id: self.subscriber.get_next_request_id(),
+            subscription_request_id: self.info.id,
+            start_location: self.info.start_location.unwrap_or_default(),
+            end_group_id: self.info.end_group_id.map_or(0, |end| end + 1),
+            subscriber_priority: self.info.subscriber_priority,
+            forward: self.info.forward,
+            params,
+        };
+        self.subscriber.send_message(update);
+
+        self.info.authorization_tokens = vec![token];
+
+        Ok(())
+    }
+}
+
 impl Drop for Subscribe {
     fn drop(&mut self) {
         self.subscriber
@@ -155,11 +463,51 @@ impl ops::Deref for Subscribe {
 
 pub(super) struct SubscribeRecv {
     state: State<SubscribeState>,
+    track_namespace: TrackNamespace,
+    track_name: String,
     writer: Option<TrackWriterMode>,
+    events: serve::TrackEventsWriter,
+    position: SubscriptionPosition,
 }
 
 impl SubscribeRecv {
-    pub fn ok(&mut self, alias: u64) -> Result<(), ServeError> {
+    pub fn track(&self) -> (&TrackNamespace, &str) {
+        (&self.track_namespace, &self.track_name)
+    }
+
+    /// Whether the track is carried in datagrams, so objects arriving on streams are delivered
+    /// as datagrams too, ex. those the publisher found too large for a datagram.
+    pub fn carries_datagrams(&self) -> bool {
+        matches!(self.writer, Some(TrackWriterMode::Datagrams(_)))
+    }
+
+    pub fn snapshot(&self, id: u64) -> SubscriptionSnapshot {
+        let state = self.state.lock();
+
+        SubscriptionSnapshot {
+            id,
+            track_namespace: self.track_namespace.clone(),
+            track_name: self.track_name.clone(),
+            track_alias: state.track_alias,
+            state: RequestState::from_ok(state.ok),
+            position: self.position.get(),
+            telemetry: state.telemetry.clone(),
+        }
+    }
+
+    /// Advanced by the stream readers as each object is received in full.
+    pub fn position(&self) -> SubscriptionPosition {
+        self.position.clone()
+    }
+
+    /// Mark the subscription as accepted, with an expiry of zero meaning it never expires,
+    /// and the publisher's largest location if it has any objects.
+    pub fn ok(
+        &mut self,
+        alias: u64,
+        expires: u64,
+        largest_location: Option<Location>,
+    ) -> Result<(), ServeError> {
         let state = self.state.lock();
         if state.ok {
             return Err(ServeError::Duplicate);
@@ -168,16 +516,46 @@ impl SubscribeRecv {
         if let Some(mut state) = state.into_mut() {
             state.ok = true;
             state.track_alias = Some(alias);
+            state.expires = (expires > 0).then(|| Duration::from_millis(expires));
+            state.largest_location = largest_location;
+        }
+
+        // Objects that arrived before SUBSCRIBE_OK already tell readers the live edge.
+        if let Some(TrackWriterMode::Track(track)) = &self.writer {
+            track.set_largest_location(largest_location)?;
         }
 
         Ok(())
     }
 
+    /// Mark the track as fetchable or live-only, based on the publisher's SUBSCRIBE_OK.
+    /// Ignored once objects have started arriving.
+    pub fn set_fetchable(&self, fetchable: bool) -> Result<(), ServeError> {
+        match &self.writer {
+            Some(TrackWriterMode::Track(track)) => track.set_fetchable(fetchable),
+            _ => Ok(()),
+        }
+    }
+
+    /// Drop the subgroups buffered for the application, see [Subscribe::skip_to_latest].
+    pub fn discard_buffered(&mut self) -> Result<(), ServeError> {
+        match &mut self.writer {
+            Some(TrackWriterMode::Subgroups(subgroups)) => subgroups.discard_buffered(),
+            // Datagrams only keep the latest object, and nothing has arrived in track mode.
+            _ => Ok(()),
+        }
+    }
+
     pub fn track_alias(&self) -> Option<u64> {
         let state = self.state.lock();
         state.track_alias
     }
 
+    /// Whether both subscriptions are to the same track.
+    pub fn same_track(&self, other: &SubscribeRecv) -> bool {
+        self.track_namespace == other.track_namespace && self.track_name == other.track_name
+    }
+
     pub fn error(mut self, err: ServeError) -> Result<(), ServeError> {
         if let Some(writer) = self.writer.take() {
             writer.close(err.clone())?;
@@ -217,31 +595,41 @@ impl SubscribeRecv {
         Ok(writer)
     }
 
+    /// Reports the object status markers of the track to its readers.
+    pub fn events(&self) -> serve::TrackEventsWriter {
+        self.events.clone()
+    }
+
     pub fn datagram(&mut self, datagram: data::Datagram) -> Result<(), ServeError> {
         let writer = self.writer.take().ok_or(ServeError::Done)?;
+        let (group_id, object_id) = (datagram.group_id, datagram.object_id.unwrap_or(0));
+
+        // Readers get any status as an event, as well as the empty datagram.
+        let location = Location::new(group_id, object_id);
+        if let Some(event) = datagram
+            .status
+            .and_then(|status| serve::TrackEvent::from_status(status, location))
+        {
+            // Nobody may be listening for events.
+            let _ = self.events.report(event);
+        }
 
         match writer {
             TrackWriterMode::Track(track) => {
                 // convert Track -> Datagrams writer, write, then put Datagrams back
                 let mut datagrams = track.datagrams()?;
-                datagrams.write(serve::Datagram {
-                    group_id: datagram.group_id,
-                    object_id: datagram.object_id.unwrap_or(0),
-                    priority: datagram.publisher_priority,
-                    payload: datagram.payload.unwrap_or_default(),
-                    extension_headers: datagram.extension_headers.unwrap_or_default(),
-                })?;
+                datagrams.write(datagram.into())?;
                 self.writer = Some(TrackWriterMode::Datagrams(datagrams));
                 Ok(())
             }
+            // The track is already carried in subgroups, so keep this object as a datagram alongside them.
+            TrackWriterMode::Subgroups(mut subgroups) => {
+                subgroups.datagram(datagram.into())?;
+                self.writer = Some(TrackWriterMode::Subgroups(subgroups));
+                Ok(())
+            }
             TrackWriterMode::Datagrams(mut datagrams) => {
-                datagrams.write(serve::Datagram {
-                    group_id: datagram.group_id,
-                    object_id: datagram.object_id.unwrap_or(0),
-                    priority: datagram.publisher_priority,
-                    payload: datagram.payload.unwrap_or_default(),
-                    extension_headers: datagram.extension_headers.unwrap_or_default(),
-                })?;
+                datagrams.write(datagram.into())?;
                 self.writer = Some(TrackWriterMode::Datagrams(datagrams));
                 Ok(())
             }
@@ -250,6 +638,9 @@ impl SubscribeRecv {
                 self.writer = Some(other);
                 Err(ServeError::Mode)
             }
-        }
+        }?;
+
+        self.position.advance(group_id, object_id);
+        Ok(())
     }
 }
@@ -0,0 +1,326 @@
+use std::{collections::VecDeque, future, pin::Pin, task};
+
+use crate::serve::{ServeError, TrackWriter};
+
+use super::{Subscribe, Subscriber};
+
+/// Subscribes to many tracks with one call, see [Subscriber::subscribe_all].
+///
+/// Each track is subscribed with the filter set on its [TrackWriter], ex. with
+/// [crate::serve::Track::with_start], and the priority given here.
+pub struct SubscribeAll {
+    tracks: Vec<(TrackWriter, u8)>,
+    shared_fate: bool,
+}
+
+impl SubscribeAll {
+    // The subscriber priority of tracks added without one, see Subscriber::subscribe_handle.
+    const DEFAULT_PRIORITY: u8 = 127;
+
+    pub fn new(tracks: Vec<TrackWriter>) -> Self {
+        Self {
+            tracks: tracks
+                .into_iter()
+                .map(|track| (track, Self::DEFAULT_PRIORITY))
+                .collect(),
+            shared_fate: false,
+        }
+    }
+
+    /// Add a track, subscribed at `priority`, where a lower value is more important.
+    pub fn with_track(mut self, track: TrackWriter, priority: u8) -> Self {
+        self.tracks.push((track, priority));
+        self
+    }
+
+    /// Unsubscribe from every track once one of them fails, ex. when a broadcast can't play
+    /// without all of its tracks. The others then fail with [ServeError::Cancel].
+    pub fn with_shared_fate(mut self, shared_fate: bool) -> Self {
+        self.shared_fate = shared_fate;
+        self
+    }
+
+    /// Send a SUBSCRIBE for each track, in the order they were added.
+    pub fn subscribe(self, subscriber: &mut Subscriber) -> Subscriptions {
+        let subscriptions = self
+            .tracks
+            .into_iter()
+            .map(|(track, priority)| Subscription {
+                subscribe: Some(subscriber.subscribe_with_priority(track, priority)),
+                status: SubscriptionStatus::Pending,
+            })
+            .collect();
+
+        Subscriptions {
+            subscriptions,
+            changes: VecDeque::new(),
+            shared_fate: self.shared_fate,
+        }
+    }
+}
+
+/// Where one subscription of [Subscriptions] stands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SubscriptionStatus {
+    /// Waiting for SUBSCRIBE_OK.
+    Pending,
+
+    /// Accepted by the publisher.
+    Active,
+
+    /// Ended cleanly.
+    Ended,
+
+    /// Rejected or ended with an error.
+    Failed(ServeError),
+}
+
+impl SubscriptionStatus {
+    /// Whether the subscription is over.
+    pub fn is_closed(&self) -> bool {
+        matches!(self, Self::Ended | Self::Failed(_))
+    }
+}
+
+/// A change of status of the subscription at `index` of [Subscriptions].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SubscriptionChange {
+    pub index: usize,
+    pub status: SubscriptionStatus,
+}
+
+struct Subscription {
+    // Dropped to unsubscribe, with shared fate.
+    subscribe: Option<Subscribe>,
+    status: SubscriptionStatus,
+}
+
+impl Subscription {
+    // The new status of the subscription, registering `cx` to be woken until it changes.
+    fn poll_status(&self, cx: &mut task::Context<'_>) -> Option<SubscriptionStatus> {
+        let subscribe = self.subscribe.as_ref()?;
+
+        let closed = |cx: &mut task::Context<'_>| match subscribe.poll_closed(cx) {
+            task::Poll::Ready(Ok(())) => Some(SubscriptionStatus::Ended),
+            task::Poll::Ready(Err(err)) => Some(SubscriptionStatus::Failed(err)),
+            task::Poll::Pending => None,
+        };
+
+        match self.status {
+            SubscriptionStatus::Pending => match subscribe.poll_ok(cx) {
+                task::Poll::Ready(Ok(())) => Some(SubscriptionStatus::Active),
+                // Closed before SUBSCRIBE_OK, maybe cleanly.
+                task::Poll::Ready(Err(err)) => {
+                    Some(closed(cx).unwrap_or(SubscriptionStatus::Failed(err)))
+                }
+                task::Poll::Pending => None,
+            },
+            SubscriptionStatus::Active => closed(cx),
+            SubscriptionStatus::Ended | SubscriptionStatus::Failed(_) => None,
+        }
+    }
+}
+
+/// The subscriptions made by [Subscriber::subscribe_all], in the order of their tracks.
+///
+/// Their status changes are read with [Self::next_status], or as a [futures::Stream].
+/// Every remaining subscription is unsubscribed when this is dropped.
+#[must_use = "unsubscribe on drop"]
+pub struct Subscriptions {
+    subscriptions: Vec<Subscription>,
+
+    // Changes made along with another one, not read yet.
+    changes: VecDeque<SubscriptionChange>,
+    shared_fate: bool,
+}
+
+impl Subscriptions {
+    pub fn len(&self) -> usize {
+        self.subscriptions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subscriptions.is_empty()
+    }
+
+    /// The subscription at `index`, or None once unsubscribed because another one failed.
+    pub fn get(&self, index: usize) -> Option<&Subscribe> {
+        self.subscriptions.get(index)?.subscribe.as_ref()
+    }
+
+    /// Like [Self::get], ex. to update the subscription.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Subscribe> {
+        self.subscriptions.get_mut(index)?.subscribe.as_mut()
+    }
+
+    /// The status of the subscription at `index`, as of the latest change read.
+    pub fn status(&self, index: usize) -> Option<&SubscriptionStatus> {
+        Some(&self.subscriptions.get(index)?.status)
+    }
+
+    /// Wait for the next status change, or None once every subscription is closed.
+    pub async fn next_status(&mut self) -> Option<SubscriptionChange> {
+        future::poll_fn(|cx| self.poll_next_status(cx)).await
+    }
+
+    /// Like [Self::next_status], returning Pending and waking `cx` when a subscription changes.
+    pub fn poll_next_status(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<SubscriptionChange>> {
+        if let Some(change) = self.changes.pop_front() {
+            return task::Poll::Ready(Some(change));
+        }
+
+        let changed = self
+            .subscriptions
+            .iter()
+            .enumerate()
+            .find_map(|(index, subscription)| Some((index, subscription.poll_status(cx)?)));
+
+        let Some((index, status)) = changed else {
+            return match self.subscriptions.iter().all(|sub| sub.status.is_closed()) {
+                true => task::Poll::Ready(None),
+                false => task::Poll::Pending,
+            };
+        };
+
+        self.subscriptions[index].status = status.clone();
+        if self.shared_fate && matches!(status, SubscriptionStatus::Failed(_)) {
+            self.cancel(index);
+        }
+
+        task::Poll::Ready(Some(SubscriptionChange { index, status }))
+    }
+
+    /// Wait until every subscription is closed, returning the first error if any failed.
+    pub async fn closed(&mut self) -> Result<(), ServeError> {
+        let mut res = Ok(());
+        while let Some(change) = self.next_status().await {
+            if let (Ok(()), SubscriptionStatus::Failed(err)) = (&res, change.status) {
+                res = Err(err);
+            }
+        }
+
+        res
+    }
+
+    // Unsubscribe from everything but the subscription at `failed`.
+    fn cancel(&mut self, failed: usize) {
+        for (index, subscription) in self.subscriptions.iter_mut().enumerate() {
+            if index == failed || subscription.status.is_closed() {
+                continue;
+            }
+
+            subscription.subscribe = None;
+            subscription.status = SubscriptionStatus::Failed(ServeError::Cancel);
+            self.changes.push_back(SubscriptionChange {
+                index,
+                status: subscription.status.clone(),
+            });
+        }
+    }
+}
+
+impl futures::Stream for Subscriptions {
+    type Item = SubscriptionChange;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> task::Poll<Option<SubscriptionChange>> {
+        self.get_mut().poll_next_status(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        coding::TrackNamespace,
+        message::{self, GroupOrder},
+        serve::Track,
+        session::Requests,
+        watch::Queue,
+    };
+
+    #[test]
+    fn subscribe_all() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+        let namespace = TrackNamespace::from_utf8_path("live");
+        let track = |name: &str| Track::new(namespace.clone(), name.to_string()).produce();
+
+        // Request IDs 0, 2 and 4.
+        let (video, _video) = track("video");
+        let (audio, _audio) = track("audio");
+        let (captions, _captions) = track("captions");
+        let mut subscriptions = SubscribeAll::new(vec![video])
+            .with_track(audio, 1)
+            .with_track(captions, 200)
+            .with_shared_fate(true)
+            .subscribe(&mut subscriber);
+        assert_eq!(subscriptions.len(), 3);
+
+        let priorities: Vec<_> = (0..3)
+            .map(|_| match block_on(outgoing.clone().pop()) {
+                Some(message::Message::Subscribe(msg)) => msg.subscriber_priority,
+                msg => panic!("expected SUBSCRIBE, got {:?}", msg),
+            })
+            .collect();
+        assert_eq!(priorities, [127, 1, 200]);
+
+        subscriber
+            .recv_message(message::Publisher::SubscribeOk(message::SubscribeOk {
+                id: 2,
+                track_alias: 7,
+                expires: 0,
+                group_order: GroupOrder::Ascending,
+                content_exists: false,
+                largest_location: None,
+                params: Default::default(),
+            }))
+            .unwrap();
+        assert_eq!(
+            block_on(subscriptions.next_status()),
+            Some(SubscriptionChange {
+                index: 1,
+                status: SubscriptionStatus::Active,
+            })
+        );
+
+        // The captions failing brings down the others.
+        subscriber
+            .recv_message(message::Publisher::SubscribeError(
+                message::SubscribeError {
+                    id: 4,
+                    error_code: 0x4,
+                    reason_phrase: Default::default(),
+                },
+            ))
+            .unwrap();
+        let changes: Vec<_> = block_on(futures::StreamExt::collect(&mut subscriptions));
+        assert_eq!(
+            changes[0].status,
+            SubscriptionStatus::Failed(ServeError::Closed(0x4))
+        );
+        assert_eq!(
+            changes[1..],
+            [
+                SubscriptionChange {
+                    index: 0,
+                    status: SubscriptionStatus::Failed(ServeError::Cancel),
+                },
+                SubscriptionChange {
+                    index: 1,
+                    status: SubscriptionStatus::Failed(ServeError::Cancel),
+                },
+            ]
+        );
+        assert!(subscriptions.get(0).is_none());
+        assert!(subscriptions.get(2).is_some());
+    }
+}
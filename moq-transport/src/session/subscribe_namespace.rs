@@ -0,0 +1,151 @@
+use std::ops;
+
+use crate::coding::{KeyValuePairs, TrackNamespace};
+use crate::watch::State;
+use crate::{message, serve::ServeError};
+
+use super::Subscriber;
+
+#[derive(Debug, Clone)]
+pub struct SubscribeNamespaceInfo {
+    pub request_id: u64,
+    pub prefix: TrackNamespace,
+}
+
+struct SubscribeNamespaceState {
+    ok: bool,
+    closed: Result<(), ServeError>,
+}
+
+impl Default for SubscribeNamespaceState {
+    fn default() -> Self {
+        Self {
+            ok: false,
+            closed: Ok(()),
+        }
+    }
+}
+
+/// Interest in the namespaces under a prefix, sent to the publisher as SUBSCRIBE_NAMESPACE.
+///
+/// A publisher may use it to decide what to announce, see [super::Publisher::interest].
+#[must_use = "unsubscribe on drop"]
+pub struct SubscribeNamespace {
+    subscriber: Subscriber,
+    state: State<SubscribeNamespaceState>,
+
+    pub info: SubscribeNamespaceInfo,
+}
+
+impl SubscribeNamespace {
+    pub(super) fn new(
+        mut subscriber: Subscriber,
+        request_id: u64,
+        prefix: TrackNamespace,
+    ) -> (SubscribeNamespace, SubscribeNamespaceRecv) {
+        subscriber.send_message(message::SubscribeNamespace {
+            id: request_id,
+            track_namespace_prefix: prefix.clone(),
+            params: KeyValuePairs::default(),
+        });
+
+        let (send, recv) = State::default().split();
+
+        let send = Self {
+            subscriber,
+            state: send,
+            info: SubscribeNamespaceInfo {
+                request_id,
+                prefix: prefix.clone(),
+            },
+        };
+        let recv = SubscribeNamespaceRecv {
+            state: recv,
+            prefix,
+        };
+
+        (send, recv)
+    }
+
+    // Wait until an OK is received
+    pub async fn ok(&self) -> Result<(), ServeError> {
+        loop {
+            {
+                let state = self.state.lock();
+                if state.ok {
+                    return Ok(());
+                }
+                state.closed.clone()?;
+
+                match state.modified() {
+                    Some(notified) => notified,
+                    None => return Err(ServeError::Done),
+                }
+            }
+            .await;
+        }
+    }
+
+    // Run until the publisher refuses the interest, or the session ends.
+    pub async fn closed(&self) -> Result<(), ServeError> {
+        loop {
+            {
+                let state = self.state.lock();
+                state.closed.clone()?;
+
+                match state.modified() {
+                    Some(notified) => notified,
+                    None => return Ok(()),
+                }
+            }
+            .await;
+        }
+    }
+}
+
+impl Drop for SubscribeNamespace {
+    fn drop(&mut self) {
+        if self.state.lock().closed.is_err() {
+            return;
+        }
+
+        self.subscriber.send_message(message::UnsubscribeNamespace {
+            track_namespace_prefix: self.info.prefix.clone(),
+        });
+    }
+}
+
+impl ops::Deref for SubscribeNamespace {
+    type Target = SubscribeNamespaceInfo;
+
+    fn deref(&self) -> &Self::Target {
+        &self.info
+    }
+}
+
+pub(super) struct SubscribeNamespaceRecv {
+    state: State<SubscribeNamespaceState>,
+    pub prefix: TrackNamespace,
+}
+
+impl SubscribeNamespaceRecv {
+    pub fn recv_ok(&mut self) -> Result<(), ServeError> {
+        let mut state = self.state.lock_mut().ok_or(ServeError::Done)?;
+        if state.ok {
+            return Err(ServeError::Duplicate);
+        }
+        state.ok = true;
+
+        Ok(())
+    }
+
+    pub fn recv_error(self, err: ServeError) -> Result<(), ServeError> {
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        let mut state = state.into_mut().ok_or(ServeError::Done)?;
+        state.closed = Err(err);
+
+        Ok(())
+    }
+}
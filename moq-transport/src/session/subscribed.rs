@@ -1,16 +1,23 @@
 use std::ops;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
 
-use crate::coding::{Encode, Location, ReasonPhrase};
+use crate::coding::{
+    Encode, KeyValuePairs, Location, ReasonPhrase, Telemetry, Token, TrackNamespace,
+};
 use crate::mlog;
-use crate::serve::{ServeError, TrackReaderMode};
+use crate::serve::{FullTrackName, ServeError, SubscriptionQuota, TrackReaderMode};
 use crate::watch::State;
 use crate::{data, message, serve};
 
-use super::{Publisher, SessionError, SubscribeInfo, Writer};
+use super::{
+    subscribe::{delivery_timeout_param, max_bitrate_param, telemetry_param},
+    Admit, DeliveryStats, Pacer, Publisher, RequestState, SessionError, SubscribeInfo,
+    SubscriptionPosition, SubscriptionSnapshot, Writer,
+};
 
 // This file defines Publisher handling of inbound Subscriptions
 
@@ -18,30 +25,241 @@ use super::{Publisher, SessionError, SubscribeInfo, Writer};
 struct SubscribedState {
     largest_location: Option<Location>,
     closed: Result<(), ServeError>,
+
+    // Set once SUBSCRIBE_OK has been sent, with the track alias it assigned.
+    track_alias: Option<u64>,
+
+    // Subscriber preferences, which may be changed by SUBSCRIBE_UPDATE.
+    subscriber_priority: u8,
+    // The least important subscriber priority objects are sent with, whatever the updates say.
+    priority_boost: Option<u8>,
+    forward: bool,
+    // Inclusive end group, if any.
+    end_group_id: Option<u64>,
+
+    // The latest group delivery started on.
+    latest_group_id: Option<u64>,
+    // Groups before this one are no longer sent, after SUBSCRIBE_UPDATE asked to skip to the latest.
+    skip_before: Option<u64>,
+    // Groups the subscriber already has, which are never sent.
+    cached_groups: Option<ops::RangeInclusive<u64>>,
+
+    // Objects that can't start being sent this long after they were created are skipped.
+    delivery_timeout: Option<Duration>,
+
+    // Objects are paced to this many bits per second, if set.
+    max_bitrate: Option<u64>,
+    // The bitrate the track declared, also paced to with some headroom.
+    declared_bitrate: Option<u64>,
+
+    // The latest telemetry the subscriber reported, which SUBSCRIBE_UPDATE may refresh.
+    telemetry: Option<Telemetry>,
+
+    // The latest AUTHORIZATION TOKENs, which SUBSCRIBE_UPDATE may renew.
+    authorization_tokens: Vec<Token>,
+
+    // Limits the subscription is ended for breaking.
+    quota: SubscriptionQuota,
 }
 
 impl SubscribedState {
     fn update_largest_location(&mut self, group_id: u64, object_id: u64) -> Result<(), ServeError> {
-        if let Some(current_largest_location) = self.largest_location {
-            let update_largest_location = Location::new(group_id, object_id);
-            if update_largest_location > current_largest_location {
-                self.largest_location = Some(update_largest_location);
-            }
-        }
+        let update_largest_location = Some(Location::new(group_id, object_id));
+        self.largest_location = self.largest_location.max(update_largest_location);
 
         Ok(())
     }
 }
 
-impl Default for SubscribedState {
-    fn default() -> Self {
+impl SubscribedState {
+    fn new(info: &SubscribeInfo, quota: SubscriptionQuota) -> Self {
         Self {
             largest_location: None,
             closed: Ok(()),
+            track_alias: None,
+            subscriber_priority: info.subscriber_priority,
+            priority_boost: None,
+            forward: info.forward,
+            end_group_id: info.end_group_id,
+            latest_group_id: None,
+            skip_before: None,
+            cached_groups: info.cached_groups(),
+            delivery_timeout: info.delivery_timeout(),
+            max_bitrate: info.max_bitrate(),
+            declared_bitrate: None,
+            telemetry: info.telemetry(),
+            authorization_tokens: info.authorization_tokens.clone(),
+            quota,
+        }
+    }
+
+    // The subscriber priority objects are sent with.
+    fn priority(&self) -> u8 {
+        match self.priority_boost {
+            Some(boost) => self.subscriber_priority.min(boost),
+            None => self.subscriber_priority,
+        }
+    }
+
+    // Returns true if objects in this group should be sent to the subscriber.
+    fn forwards(&self, group_id: u64) -> bool {
+        self.forward && !self.past_end(group_id)
+    }
+
+    // The bitrate objects are sent at, or None to send them as fast as possible.
+    fn pacing(&self) -> Option<u64> {
+        Pacer::bitrate(self.declared_bitrate, self.max_bitrate)
+    }
+
+    fn past_end(&self, group_id: u64) -> bool {
+        self.end_group_id.is_some_and(|end| group_id > end)
+    }
+
+    // Returns true if the subscriber skipped past this group to the latest one.
+    fn skipped(&self, group_id: u64) -> bool {
+        self.skip_before.is_some_and(|before| group_id < before)
+    }
+
+    // Returns true if the subscriber already has this group. Not a gap, since nothing is missing.
+    fn cached(&self, group_id: u64) -> bool {
+        self.cached_groups
+            .as_ref()
+            .is_some_and(|groups| groups.contains(&group_id))
+    }
+
+    // Only groups older than the latest one when subscribed can be cached by the subscriber
+    // already. Later groups are always sent, as they may reuse the IDs of cached ones after the
+    // publisher restarted.
+    fn limit_cached(&mut self, latest_group_id: Option<u64>) {
+        self.cached_groups = self.cached_groups.take().and_then(|groups| {
+            let end = (*groups.end()).min(latest_group_id?.checked_sub(1)?);
+            (*groups.start() <= end).then(|| *groups.start()..=end)
+        });
+    }
+}
+
+// Tracks groups that were skipped on purpose, so the next group can signal a Prior Group ID Gap.
+// Gaps that come from loss upstream aren't signalled, since nothing was skipped here.
+#[derive(Default)]
+struct GapTracker {
+    last_group_id: Option<u64>,
+    skipped: bool,
+}
+
+impl GapTracker {
+    fn skipped(&mut self) {
+        self.skipped = true;
+    }
+
+    // Returns the gap to signal on an object in this group, if any.
+    fn next(&mut self, group_id: u64) -> Option<u64> {
+        let gap = match self.last_group_id {
+            Some(last) if self.skipped && group_id > last + 1 => Some(group_id - last - 1),
+            _ => None,
+        };
+
+        if self.last_group_id.is_none_or(|last| group_id > last) {
+            self.last_group_id = Some(group_id);
+            self.skipped = false;
         }
+
+        gap
     }
 }
 
+// Holds back delivery until a group starts, for subscriptions that must never begin mid-group.
+// Subgroups are served from their first object, so a group starts with its subgroup 0.
+#[derive(Default)]
+struct GroupStart {
+    required: bool,
+    // Groups up to and including this one are too old to start at.
+    after: Option<u64>,
+    // The group delivery started at; older groups are dropped.
+    started: Option<u64>,
+}
+
+impl GroupStart {
+    fn new(info: &SubscribeInfo, largest_location: Option<Location>) -> Self {
+        let group_start = info.group_start();
+
+        Self {
+            required: group_start || info.filter_type == message::FilterType::NextGroupStart,
+            // A plain NextGroupStart waits for the next group, even if the current one is cached from its start.
+            after: match group_start {
+                true => None,
+                false => largest_location.map(|location| location.group_id),
+            },
+            started: None,
+        }
+    }
+
+    // Returns true if an object in this group may be delivered; `first` is true if it starts the group.
+    fn admits(&mut self, group_id: u64, first: bool) -> bool {
+        if !self.required {
+            return true;
+        }
+
+        if let Some(started) = self.started {
+            return group_id >= started;
+        }
+
+        if !first || self.after.is_some_and(|after| group_id <= after) {
+            return false;
+        }
+
+        self.started = Some(group_id);
+        true
+    }
+}
+
+// Attach a Prior Group ID Gap, keeping a larger gap already signalled upstream.
+fn signal_gap(extension_headers: &mut data::ExtensionHeaders, gap: Option<u64>) {
+    if let Some(gap) = gap {
+        if extension_headers.prior_group_id_gap().unwrap_or(0) < gap {
+            // Only fails for immutable extensions, which this isn't.
+            extension_headers.set_prior_group_id_gap(gap);
+        }
+    }
+}
+
+// End the subscription if it's breaking its quota, so every subgroup stops being served.
+fn enforce_quota(state: &State<SubscribedState>, stats: &DeliveryStats) -> Result<(), ServeError> {
+    let quota = state.lock().quota;
+    if !quota.is_limited() {
+        return Ok(());
+    }
+
+    let current = stats.get();
+    let Err(violation) = quota.check(current.queued_bytes, current.send_rate) else {
+        return Ok(());
+    };
+
+    log::warn!("subscription quota violated: {}", violation);
+    let err = ServeError::QuotaViolated(violation);
+
+    let state = state.lock();
+    state.closed.clone()?;
+    if let Some(mut state) = state.into_mut() {
+        state.closed = Err(err.clone());
+    }
+
+    Err(err)
+}
+
+// Combine the subscriber and publisher priorities into a stream priority.
+// The subscriber priority takes precedence; lower values are more important, per the spec.
+fn stream_priority(subscriber_priority: u8, publisher_priority: u8) -> i32 {
+    // TODO figure out u32 vs u64 priority
+    // Lower values of either are more important, but streams with higher priorities are sent first.
+    ((u8::MAX - subscriber_priority) as i32) << 8 | (u8::MAX - publisher_priority) as i32
+}
+
+// Like [stream_priority], for a FETCH stream, which is a tier below every live subgroup stream
+// so a replay never delays live objects.
+pub(super) fn fetch_stream_priority(subscriber_priority: u8, publisher_priority: u8) -> i32 {
+    stream_priority(subscriber_priority, publisher_priority) - (1 << 16)
+}
+
 pub struct Subscribed {
     /// The sessions Publisher manager, used to send control messages,
     /// create new QUIC streams, and send datagrams
@@ -52,38 +270,72 @@ pub struct Subscribed {
 
     state: State<SubscribedState>,
 
-    /// Tracks if SubscribeOk has been sent yet or not. Used to send
+    /// The track alias, assigned once SubscribeOk is sent. Used to send
     /// SubscribeDone vs SubscribeError on drop.
-    ok: bool,
+    alias: Option<u64>,
 
     /// Optional mlog writer for logging transport events
     mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+
+    /// Advanced as objects are written, and observed through `watcher`.
+    position: SubscriptionPosition,
+    watcher: SubscriptionPosition,
+
+    /// Updated as objects are written, and observed through `stats_watcher`.
+    stats: DeliveryStats,
+    stats_watcher: DeliveryStats,
 }
 
 impl Subscribed {
     pub(super) fn new(
         publisher: Publisher,
         msg: message::Subscribe,
+        authorization_tokens: Vec<Token>,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
     ) -> (Self, SubscribedRecv) {
-        let (send, recv) = State::default().split();
-        let info = SubscribeInfo::new_from_subscribe(&msg);
+        let mut info = SubscribeInfo::new_from_subscribe(&msg);
+        info.authorization_tokens = authorization_tokens;
+        let quota = publisher.subscription_quota();
+        let (send, recv) = State::new(SubscribedState::new(&info, quota)).split();
+        let (position, watcher) = SubscriptionPosition::produce();
+        let (stats, stats_watcher) = DeliveryStats::produce(info.subscriber_priority);
         let send = Self {
             publisher,
             state: send,
             info,
-            ok: false,
+            alias: None,
             mlog,
+            position,
+            watcher: watcher.clone(),
+            stats,
+            stats_watcher: stats_watcher.clone(),
         };
 
         // Prevents updates after being closed
-        let recv = SubscribedRecv { state: recv };
+        let recv = SubscribedRecv {
+            state: recv,
+            track_namespace: send.info.track_namespace.clone(),
+            track_name: send.info.track_name.clone(),
+            position: watcher,
+            stats: stats_watcher,
+        };
 
         (send, recv)
     }
 
     pub async fn serve(mut self, track: serve::TrackReader) -> Result<(), SessionError> {
-        let res = self.serve_inner(track).await;
+        let res = self.serve_inner(&track).await;
+
+        // However the track closed after its publisher marked the end, ex. its session went away
+        // right after, it ended cleanly, so tell the subscriber unless it's the one that left.
+        if matches!(res, Ok(()) | Err(SessionError::Serve(_)))
+            && track.end_of_track().is_some()
+            && self.state.lock().closed.is_ok()
+        {
+            self.close(ServeError::TrackEnded)?;
+            return Ok(());
+        }
+
         if let Err(err) = &res {
             self.close(err.clone().into())?;
         }
@@ -91,37 +343,60 @@ impl Subscribed {
         res
     }
 
-    async fn serve_inner(&mut self, track: serve::TrackReader) -> Result<(), SessionError> {
-        // Update largest location before sending SubscribeOk
-        let largest_location = track.largest_location();
-        self.state
-            .lock_mut()
-            .ok_or(ServeError::Cancel)?
-            .largest_location = largest_location;
+    async fn serve_inner(&mut self, track: &serve::TrackReader) -> Result<(), SessionError> {
+        // Update largest location before sending SubscribeOk, keeping any set with set_largest_location
+        let largest_location = {
+            let mut state = self.state.lock_mut().ok_or(ServeError::Cancel)?;
+            state.largest_location = state.largest_location.max(track.latest());
+            state.declared_bitrate = track.bitrate();
+            state.limit_cached(track.latest().map(|latest| latest.group_id));
+
+            state.largest_location
+        };
 
-        // Send SubscribeOk using send_message_and_wait to ensure it is sent at least to the QUIC stack before
-        // we start serving the track.  If a subscriber gets the stream before SubscribeOk
-        // then they won't recognize the track_alias in the stream header.
+        // Tell the subscriber not to cache live-only tracks
+        let mut params = KeyValuePairs::default();
+        if !track.is_fetchable() {
+            params.set_intvalue(message::ParameterType::MaxCacheDuration.into(), 0);
+        }
+
+        let alias = self.publisher.assign_alias(FullTrackName {
+            namespace: self.info.track_namespace.clone(),
+            name: self.info.track_name.clone(),
+        });
+        self.alias = Some(alias); // So we send SubscribeDone on drop, and release the alias
+
+        // Send SubscribeOk using send_message_and_wait to ensure it is written to the QUIC stack before
+        // we open any stream for the track.  If a subscriber gets the stream before SubscribeOk
+        // then it has to wait to recognize the track_alias in the stream header.
         self.publisher
             .send_message_and_wait(message::SubscribeOk {
                 id: self.info.id,
-                track_alias: self.info.id, // use subscription id as track alias
-                expires: 0,                // TODO SLG
+                track_alias: alias,
+                expires: 0,                                   // TODO SLG
                 group_order: message::GroupOrder::Descending, // TODO: resolve correct value from publisher / subscriber prefs
                 content_exists: largest_location.is_some(),
                 largest_location,
-                params: Default::default(),
+                params,
             })
             .await;
 
-        self.ok = true; // So we send SubscribeDone on drop
+        if let Some(mut state) = self.state.lock_mut() {
+            state.track_alias = Some(alias);
+        }
+
+        let start = GroupStart::new(&self.info, largest_location);
 
         // Serve based on track mode
         match track.mode().await? {
             // TODO cancel track/datagrams on closed
             TrackReaderMode::Stream(_stream) => panic!("deprecated"),
-            TrackReaderMode::Subgroups(subgroups) => self.serve_subgroups(subgroups).await,
-            TrackReaderMode::Datagrams(datagrams) => self.serve_datagrams(datagrams).await,
+            TrackReaderMode::Subgroups(subgroups) => {
+                self.serve_subgroups(subgroups, start, alias).await
+            }
+            TrackReaderMode::Datagrams(datagrams) => {
+                self.serve_datagrams(datagrams, start, alias).await
+            }
         }
     }
 
@@ -135,6 +410,71 @@ impl Subscribed {
         Ok(())
     }
 
+    /// The latest object fully written to the subscriber, which keeps updating while served.
+    pub fn position(&self) -> SubscriptionPosition {
+        self.watcher.clone()
+    }
+
+    /// How the subscription is being delivered, which keeps updating while served.
+    pub fn stats(&self) -> DeliveryStats {
+        self.stats_watcher.clone()
+    }
+
+    /// The track alias the subscription's objects are sent with, once SUBSCRIBE_OK has been sent.
+    pub fn track_alias(&self) -> Option<u64> {
+        self.alias
+    }
+
+    /// Override the limits this subscription is served under, set by default with
+    /// [Publisher::set_subscription_quota]. Breaking them ends it with [ServeError::QuotaViolated].
+    pub fn set_quota(&mut self, quota: SubscriptionQuota) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.quota = quota;
+        }
+    }
+
+    /// The limits this subscription is served under.
+    pub fn quota(&self) -> SubscriptionQuota {
+        self.state.lock().quota
+    }
+
+    /// The latest playback telemetry the subscriber reported, from the SUBSCRIBE or a later
+    /// SUBSCRIBE_UPDATE, if any.
+    pub fn telemetry(&self) -> Option<Telemetry> {
+        self.state.lock().telemetry.clone()
+    }
+
+    /// Send objects at least as important as subscriber `priority`, even if the subscriber asks
+    /// for less, ex. a relay preferring audio over video. Lower values are more important.
+    pub fn boost_priority(&mut self, priority: u8) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.priority_boost = Some(priority);
+        }
+    }
+
+    /// Report a location known to exist beyond the served track, ex. cached by a relay, as the
+    /// Largest Object in SUBSCRIBE_OK if it's larger than the track's [serve::TrackReader::latest].
+    /// Must be called before [Self::serve].
+    pub fn set_largest_location(&mut self, location: Location) {
+        if let Some(mut state) = self.state.lock_mut() {
+            state.largest_location = state.largest_location.max(Some(location));
+        }
+    }
+
+    /// A handle to re-check the subscription's authorization while it's served.
+    pub fn authorization(&self) -> SubscribedAuthorization {
+        SubscribedAuthorization {
+            state: self.state.clone(),
+        }
+    }
+
+    /// A handle to end the subscription while it's served, ex. once its publisher went stale.
+    pub fn closer(&self) -> SubscribedCloser {
+        SubscribedCloser {
+            state: self.state.clone(),
+        }
+    }
+
     pub async fn closed(&self) -> Result<(), ServeError> {
         loop {
             {
@@ -170,13 +510,16 @@ impl Drop for Subscribed {
             .unwrap_or(ServeError::Done);
         drop(state); // Important to avoid a deadlock
 
-        if self.ok {
+        if let Some(alias) = self.alias {
             self.publisher.send_message(message::PublishDone {
                 id: self.info.id,
                 status_code: err.code(),
                 stream_count: 0, // TODO SLG
                 reason: ReasonPhrase(err.to_string()),
             });
+
+            // Released after PUBLISH_DONE, so a SUBSCRIBE_OK reusing it can't overtake it.
+            self.publisher.release_alias(alias);
         } else {
             self.publisher.send_message(message::SubscribeError {
                 id: self.info.id,
@@ -188,20 +531,79 @@ impl Drop for Subscribed {
 }
 
 impl Subscribed {
+    // The RESET_STREAM code of a subgroup cut short on purpose, CANCELLED in the draft.
+    const RESET_CANCELLED: u32 = 0x1;
+
     async fn serve_subgroups(
         &mut self,
         mut subgroups: serve::SubgroupsReader,
+        mut start: GroupStart,
+        alias: u64,
     ) -> Result<(), SessionError> {
         let mut tasks = FuturesUnordered::new();
         let mut done: Option<Result<(), ServeError>> = None;
 
+        // Objects that prefer datagrams are sent as datagrams, even though the track uses subgroups.
+        let mut datagrams = Some(subgroups.datagrams());
+        if let Some(datagrams) = &datagrams {
+            datagrams.limit_payload(self.publisher.peer_capabilities().max_datagram_payload());
+        }
+        let mut datagram_count = 0;
+
+        let mut gaps = GapTracker::default();
+        let mut datagram_gaps = GapTracker::default();
+
+        // Shared by every subgroup, so the subscription as a whole is paced.
+        let pacer = Pacer::default();
+
         loop {
             tokio::select! {
+                res = async { datagrams.as_mut().unwrap().read().await }, if datagrams.is_some() && done.is_none() => match res {
+                    // Not delivered yet, so there is nothing to signal a gap against.
+                    Ok(Some(datagram)) if !start.admits(datagram.group_id, datagram.object_id == 0) => {}
+                    Ok(Some(datagram)) if self.state.lock().cached(datagram.group_id) => {}
+                    Ok(Some(mut datagram)) if self.state.lock().forwards(datagram.group_id) => {
+                        if datagrams.as_ref().is_some_and(|datagrams| datagrams.skipped() > 0) {
+                            datagram_gaps.skipped();
+                        }
+                        signal_gap(&mut datagram.extension_headers, datagram_gaps.next(datagram.group_id));
+
+                        self.serve_datagram(datagram, datagram_count, alias).await?;
+                        datagram_count += 1;
+                    }
+                    Ok(Some(_)) => datagram_gaps.skipped(),
+                    // The track finished (or failed) and the subgroups branch will report it.
+                    Ok(None) | Err(_) => datagrams = None,
+                },
                 res = subgroups.next(), if done.is_none() => match res {
+                    // The subscription ended at a group before this one.
+                    Ok(Some(subgroup)) if self.state.lock().past_end(subgroup.group_id) => {
+                        done = Some(Ok(()));
+                    }
+                    // Forwarding is paused, or the subscriber skipped ahead, so skip this subgroup entirely.
+                    Ok(Some(subgroup)) if !self.state.lock().forward || self.state.lock().skipped(subgroup.group_id) => {
+                        gaps.skipped()
+                    }
+                    // The subscriber already has this group.
+                    Ok(Some(subgroup)) if self.state.lock().cached(subgroup.group_id) => {}
+                    // Waiting for a group to start.
+                    Ok(Some(subgroup)) if !start.admits(subgroup.group_id, subgroup.subgroup_id == 0) => {}
                     Ok(Some(subgroup)) => {
+                        if subgroups.skipped() > 0 {
+                            gaps.skipped();
+                        }
+                        let gap = gaps.next(subgroup.group_id);
+                        if let Some(skipped) = gap {
+                            self.stats.groups_skipped(skipped);
+                        }
+
+                        if let Some(mut state) = self.state.lock_mut() {
+                            state.latest_group_id = state.latest_group_id.max(Some(subgroup.group_id));
+                        }
+
                         let header = data::SubgroupHeader {
                             header_type: data::StreamHeaderType::SubgroupIdExt,  // SubGroupId = Yes, Extensions = Yes, ContainsEndOfGroup = No
-                            track_alias: self.info.id, // use subscription id as track_alias
+                            track_alias: alias,
                             group_id: subgroup.group_id,
                             subgroup_id: Some(subgroup.subgroup_id),
                             publisher_priority: subgroup.priority,
@@ -209,12 +611,16 @@ impl Subscribed {
 
                         let publisher = self.publisher.clone();
                         let state = self.state.clone();
+                        let position = self.position.clone();
                         let info = subgroup.info.clone();
                         let mlog = self.mlog.clone();
+                        let pacer = pacer.clone();
+                        let stats = self.stats.clone();
 
                         tasks.push(async move {
-                            if let Err(err) = Self::serve_subgroup(header, subgroup, publisher, state, mlog).await {
+                            if let Err(err) = Self::serve_subgroup(header, subgroup, gap, publisher, state, position, stats.clone(), pacer, mlog).await {
                                 log::warn!("failed to serve subgroup: {:?}, error: {}", info, err);
+                                stats.stream_reset();
                             }
                         });
                     },
@@ -228,11 +634,16 @@ impl Subscribed {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn serve_subgroup(
         header: data::SubgroupHeader,
         mut subgroup_reader: serve::SubgroupReader,
+        mut gap: Option<u64>,
         mut publisher: Publisher,
         state: State<SubscribedState>,
+        position: SubscriptionPosition,
+        stats: DeliveryStats,
+        pacer: Pacer,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
     ) -> Result<(), SessionError> {
         log::debug!(
@@ -242,13 +653,35 @@ impl Subscribed {
             subgroup_reader.priority
         );
 
+        let mut priority = stream_priority(state.lock().priority(), subgroup_reader.priority);
+        let mut shaped = publisher.egress().stream(priority);
+
+        // Admit the first object before opening the stream, so a subgroup dropped from the start
+        // isn't sent at all: resetting a stream before the subscriber accepted it fails the session.
+        let mut first = subgroup_reader.next().await?;
+        let mut admitted = None;
+        if let Some(object) = &first {
+            match shaped.admit(object.size) {
+                Admit::Drop => {
+                    log::debug!(
+                        "[PUBLISHER] serve_subgroup: dropping the subgroup over the egress limit - group_id={}, subgroup_id={:?}",
+                        subgroup_reader.group_id,
+                        subgroup_reader.subgroup_id
+                    );
+                    stats.egress_dropped();
+                    return Ok(());
+                }
+                admit => admitted = Some(admit),
+            }
+        }
+
         let mut send_stream = publisher.open_uni().await?;
         log::trace!("[PUBLISHER] serve_subgroup: opened unidirectional stream");
+        send_stream.set_priority(priority);
 
-        // TODO figure out u32 vs u64 priority
-        send_stream.set_priority(subgroup_reader.priority as i32);
-
-        let mut writer = Writer::new(send_stream);
+        // Small objects, ex. audio frames, are written together with their headers.
+        let mut writer =
+            Writer::with_pool(send_stream, publisher.buffers()).with_batching(Writer::PACKET);
 
         log::debug!(
             "[PUBLISHER] serve_subgroup: sending header - track_alias={}, group_id={}, subgroup_id={:?}, priority={}, header_type={:?}",
@@ -263,7 +696,7 @@ impl Subscribed {
 
         // Log subgroup header created/sent
         if let Some(ref mlog) = mlog {
-            if let Ok(mut mlog_guard) = mlog.lock() {
+            if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane()) {
                 let time = mlog_guard.elapsed_ms();
                 let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
                 let event = mlog::subgroup_header_created(time, stream_id, &header);
@@ -272,10 +705,101 @@ impl Subscribed {
         }
 
         let mut object_count = 0;
-        while let Some(mut subgroup_object_reader) = subgroup_reader.next().await? {
+        let mut next_object_id = 0;
+        while let Some(mut subgroup_object_reader) = match first.take() {
+            Some(object) => Some(object),
+            None => writer.flush_unless_ready(subgroup_reader.next()).await??,
+        } {
+            // Stop early if the subscriber skipped to a later group.
+            if state.lock().skipped(subgroup_reader.group_id) {
+                log::debug!(
+                    "[PUBLISHER] serve_subgroup: skipped to a later group - group_id={}, subgroup_id={:?}",
+                    subgroup_reader.group_id,
+                    subgroup_reader.subgroup_id
+                );
+                break;
+            }
+
+            // Apply any SUBSCRIBE_UPDATE priority change to the open stream.
+            let updated = stream_priority(state.lock().priority(), subgroup_reader.priority);
+            if updated != priority {
+                priority = updated;
+                writer.set_priority(priority);
+                shaped.set_priority(priority);
+            }
+
+            // Objects can be numbered with gaps, ex. when a group is split across subgroups.
+            let object_id_delta = subgroup_object_reader.object_id - next_object_id;
+            next_object_id = subgroup_object_reader.object_id + 1;
+
+            // Pass through extension headers, signalling any groups we skipped on the first object.
+            let mut extension_headers = subgroup_object_reader.extension_headers.clone();
+            signal_gap(&mut extension_headers, gap.take());
+
+            // Skip objects that queued behind the stream past their deadline, keeping the object ID.
+            let expires = subgroup_object_reader.expires(state.lock().delivery_timeout);
+            if expires.is_some_and(|expires| expires <= Instant::now()) {
+                log::debug!(
+                    "[PUBLISHER] serve_subgroup: skipping object past its deadline - group_id={}, object_id={}",
+                    subgroup_reader.group_id,
+                    subgroup_object_reader.object_id
+                );
+
+                writer
+                    .encode(&data::SubgroupObjectExt {
+                        object_id_delta,
+                        extension_headers,
+                        payload_length: 0,
+                        status: Some(data::ObjectStatus::ObjectDoesNotExist),
+                    })
+                    .await?;
+                stats.object_skipped();
+                admitted = None;
+                continue;
+            }
+
+            // Counted as queued from now on, until written.
+            let mut queued = stats.queue(subgroup_object_reader.size);
+            enforce_quota(&state, &stats)?;
+
+            // Wait for the session's egress limit, unless more important subgroups need it.
+            let admit = admitted
+                .take()
+                .unwrap_or_else(|| shaped.admit(subgroup_object_reader.size));
+            match admit {
+                Admit::Now => {}
+                Admit::At(until) => {
+                    writer
+                        .flush_unless_ready(tokio::time::sleep_until(until))
+                        .await?
+                }
+                Admit::Drop => {
+                    log::debug!(
+                        "[PUBLISHER] serve_subgroup: dropping the rest of the subgroup over the egress limit - group_id={}, subgroup_id={:?}, object_id={}",
+                        subgroup_reader.group_id,
+                        subgroup_reader.subgroup_id,
+                        subgroup_object_reader.object_id
+                    );
+                    stats.egress_dropped();
+
+                    // Reset rather than finish, so the subscriber doesn't take it as complete.
+                    writer.reset(Self::RESET_CANCELLED);
+                    return Ok(());
+                }
+            }
+
+            // Hold the object back until the subscription's pace allows it.
+            let pacing = state.lock().pacing();
+            if let Some(bitrate) = pacing {
+                let until = pacer.reserve(subgroup_object_reader.size, bitrate);
+                writer
+                    .flush_unless_ready(tokio::time::sleep_until(until))
+                    .await?;
+            }
+
             let subgroup_object = data::SubgroupObjectExt {
-                object_id_delta: 0, // before delta logic, used to be subgroup_object_reader.object_id,
-                extension_headers: subgroup_object_reader.extension_headers.clone(), // Pass through extension headers
+                object_id_delta,
+                extension_headers,
                 payload_length: subgroup_object_reader.size,
                 status: if subgroup_object_reader.size == 0 {
                     // Only set status if payload length is zero
@@ -299,7 +823,7 @@ impl Subscribed {
 
             // Log subgroup object created/sent
             if let Some(ref mlog) = mlog {
-                if let Ok(mut mlog_guard) = mlog.lock() {
+                if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane()) {
                     let time = mlog_guard.elapsed_ms();
                     let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
                     let event = mlog::subgroup_object_ext_created(
@@ -310,7 +834,17 @@ impl Subscribed {
                         subgroup_object_reader.object_id,
                         &subgroup_object,
                     );
-                    let _ = mlog_guard.add_event(event);
+                    let object = mlog::SampledObject {
+                        namespace: Some(&subgroup_reader.track.namespace),
+                        track_alias: header.track_alias,
+                        group_id: subgroup_reader.group_id,
+                        object_id: subgroup_object_reader.object_id,
+                        anomaly: mlog::SampledObject::is_anomaly(
+                            subgroup_object.status,
+                            Some(&subgroup_object.extension_headers),
+                        ),
+                    };
+                    let _ = mlog_guard.add_object_event(event, object);
                 }
             }
 
@@ -324,7 +858,10 @@ impl Subscribed {
 
             let mut chunks_sent = 0;
             let mut bytes_sent = 0;
-            while let Some(chunk) = subgroup_object_reader.read().await? {
+            while let Some(chunk) = writer
+                .flush_unless_ready(subgroup_object_reader.read())
+                .await??
+            {
                 log::trace!(
                     "[PUBLISHER] serve_subgroup: sending payload chunk #{} for object #{} ({} bytes)",
                     chunks_sent + 1,
@@ -332,7 +869,11 @@ impl Subscribed {
                     chunk.len()
                 );
                 bytes_sent += chunk.len();
-                writer.write(&chunk).await?;
+                let size = chunk.len();
+                writer.write(chunk).await?;
+                queued.sent(size);
+                stats.sent(size);
+                enforce_quota(&state, &stats)?;
                 chunks_sent += 1;
             }
 
@@ -342,9 +883,14 @@ impl Subscribed {
                 chunks_sent,
                 bytes_sent
             );
+            position.advance(subgroup_reader.group_id, subgroup_object_reader.object_id);
+            stats.object_sent();
             object_count += 1;
+            shaped.idle();
         }
 
+        writer.flush().await?;
+
         log::info!(
             "[PUBLISHER] serve_subgroup: completed subgroup (group_id={}, subgroup_id={:?}, {} objects sent)",
             subgroup_reader.group_id,
@@ -358,77 +904,55 @@ impl Subscribed {
     async fn serve_datagrams(
         &mut self,
         mut datagrams: serve::DatagramsReader,
+        mut start: GroupStart,
+        alias: u64,
     ) -> Result<(), SessionError> {
         log::debug!("[PUBLISHER] serve_datagrams: starting");
 
-        let mut datagram_count = 0;
-        while let Some(datagram) = datagrams.read().await? {
-            // Determine datagram type based on extension headers presence
-            let has_extension_headers = !datagram.extension_headers.is_empty();
-            let datagram_type = if has_extension_headers {
-                data::DatagramType::ObjectIdPayloadExt
-            } else {
-                data::DatagramType::ObjectIdPayload
-            };
+        // Let the publisher size its objects to the packet budget of this session too.
+        datagrams.limit_payload(self.publisher.peer_capabilities().max_datagram_payload());
 
-            let encoded_datagram = data::Datagram {
-                datagram_type,
-                track_alias: self.info.id, // use subscription id as track_alias
-                group_id: datagram.group_id,
-                object_id: Some(datagram.object_id),
-                publisher_priority: datagram.priority,
-                extension_headers: if has_extension_headers {
-                    Some(datagram.extension_headers.clone())
-                } else {
-                    None
+        let mut datagram_count = 0;
+        let mut gaps = GapTracker::default();
+        loop {
+            let mut datagram = tokio::select! {
+                res = datagrams.read() => match res? {
+                    Some(datagram) => datagram,
+                    None => break,
                 },
-                status: None,
-                payload: Some(datagram.payload),
+                // Stop once the subscriber unsubscribes, even if the track is quiet.
+                res = self.closed() => return Ok(res?),
             };
 
-            let payload_len = encoded_datagram
-                .payload
-                .as_ref()
-                .map(|p| p.len())
-                .unwrap_or(0);
-            let mut buffer = bytes::BytesMut::with_capacity(payload_len + 100);
-            encoded_datagram.encode(&mut buffer)?;
+            if datagrams.skipped() > 0 {
+                gaps.skipped();
+            }
 
-            log::debug!(
-                "[PUBLISHER] serve_datagrams: sending datagram #{} - track_alias={}, group_id={}, object_id={}, priority={}, payload_len={}, extension_headers={:?}, total_encoded_len={}",
-                datagram_count + 1,
-                encoded_datagram.track_alias,
-                encoded_datagram.group_id,
-                encoded_datagram.object_id.unwrap(),
-                encoded_datagram.publisher_priority,
-                payload_len,
-                encoded_datagram.extension_headers,
-                buffer.len()
-            );
+            if !start.admits(datagram.group_id, datagram.object_id == 0) {
+                continue;
+            }
 
-            // Create mlog event for datagram created
-            if let Some(ref mlog) = self.mlog {
-                if let Ok(mut mlog_guard) = mlog.lock() {
-                    let time = mlog_guard.elapsed_ms();
-                    let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
-                    let _ = mlog_guard.add_event(mlog::object_datagram_created(
-                        time,
-                        stream_id,
-                        &encoded_datagram,
-                    ));
+            {
+                let state = self.state.lock();
+                if state.past_end(datagram.group_id) {
+                    break;
+                }
+                if state.cached(datagram.group_id) {
+                    continue;
+                }
+                if !state.forwards(datagram.group_id) {
+                    gaps.skipped();
+                    continue;
                 }
             }
 
-            self.publisher.send_datagram(buffer.into()).await?;
-
-            self.state
-                .lock_mut()
-                .ok_or(ServeError::Done)?
-                .update_largest_location(
-                    encoded_datagram.group_id,
-                    encoded_datagram.object_id.unwrap(),
-                )?;
+            let gap = gaps.next(datagram.group_id);
+            if let Some(skipped) = gap {
+                self.stats.groups_skipped(skipped);
+            }
+            signal_gap(&mut datagram.extension_headers, gap);
 
+            self.serve_datagram(datagram, datagram_count, alias).await?;
             datagram_count += 1;
         }
 
@@ -439,13 +963,284 @@ impl Subscribed {
 
         Ok(())
     }
+
+    // Send a datagram, or put it on a stream of its own if it's larger than the peer accepts,
+    // ex. any of them if the peer doesn't accept datagrams.
+    async fn serve_datagram(
+        &mut self,
+        datagram: serve::Datagram,
+        index: usize,
+        alias: u64,
+    ) -> Result<(), SessionError> {
+        // Wait for the session's egress limit, unless more important subgroups need it.
+        let priority = stream_priority(self.state.lock().priority(), datagram.priority);
+        match self
+            .publisher
+            .egress()
+            .admit(datagram.payload.len(), priority)
+        {
+            Admit::Now => {}
+            Admit::At(until) => tokio::time::sleep_until(until).await,
+            Admit::Drop => {
+                self.stats.egress_dropped();
+                return Ok(());
+            }
+        }
+
+        let encoded_datagram = datagram.into_data(alias);
+
+        let payload_len = encoded_datagram
+            .payload
+            .as_ref()
+            .map(|p| p.len())
+            .unwrap_or(0);
+        let mut buffer = bytes::BytesMut::with_capacity(payload_len + 100);
+        encoded_datagram.encode(&mut buffer)?;
+
+        log::debug!(
+            "[PUBLISHER] serve_datagrams: sending datagram #{} - track_alias={}, group_id={}, object_id={}, priority={}, payload_len={}, extension_headers={:?}, total_encoded_len={}",
+            index + 1,
+            encoded_datagram.track_alias,
+            encoded_datagram.group_id,
+            encoded_datagram.object_id.unwrap(),
+            encoded_datagram.publisher_priority,
+            payload_len,
+            encoded_datagram.extension_headers,
+            buffer.len()
+        );
+
+        match self.publisher.send_datagram(buffer.into()).await {
+            Ok(()) => {
+                // Create mlog event for datagram created
+                if let Some(ref mlog) = self.mlog {
+                    if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane())
+                    {
+                        let time = mlog_guard.elapsed_ms();
+                        let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
+                        let event =
+                            mlog::object_datagram_created(time, stream_id, &encoded_datagram);
+                        let object = mlog::SampledObject {
+                            namespace: Some(&self.info.track_namespace),
+                            track_alias: encoded_datagram.track_alias,
+                            group_id: encoded_datagram.group_id,
+                            object_id: encoded_datagram.object_id.unwrap_or(0),
+                            anomaly: mlog::SampledObject::is_anomaly(
+                                encoded_datagram.status,
+                                encoded_datagram.extension_headers.as_ref(),
+                            ),
+                        };
+                        let _ = mlog_guard.add_object_event(event, object);
+                    }
+                }
+            }
+            Err(SessionError::DatagramTooLarge { size, max }) => {
+                log::debug!(
+                    "[PUBLISHER] serve_datagrams: sending datagram #{} on a stream, larger than the peer accepts ({} > {} bytes) - group_id={}, object_id={}",
+                    index + 1,
+                    size,
+                    max,
+                    encoded_datagram.group_id,
+                    encoded_datagram.object_id.unwrap(),
+                );
+                self.serve_datagram_stream(&encoded_datagram).await?;
+            }
+            Err(err) => return Err(err),
+        }
+
+        self.state
+            .lock_mut()
+            .ok_or(ServeError::Done)?
+            .update_largest_location(
+                encoded_datagram.group_id,
+                encoded_datagram.object_id.unwrap(),
+            )?;
+        self.position.advance(
+            encoded_datagram.group_id,
+            encoded_datagram.object_id.unwrap(),
+        );
+        self.stats.sent(payload_len);
+        self.stats.object_sent();
+        enforce_quota(&self.state, &self.stats)?;
+
+        Ok(())
+    }
+
+    // Send a datagram's object as the only one in a subgroup, numbered after it so each is unique.
+    async fn serve_datagram_stream(
+        &mut self,
+        datagram: &data::Datagram,
+    ) -> Result<(), SessionError> {
+        let object_id = datagram.object_id.unwrap_or(0);
+        let payload = datagram.payload.clone().unwrap_or_default();
+
+        let mut send_stream = self.publisher.open_uni().await?;
+        send_stream.set_priority(stream_priority(
+            self.state.lock().priority(),
+            datagram.publisher_priority,
+        ));
+        let mut writer =
+            Writer::with_pool(send_stream, self.publisher.buffers()).with_batching(Writer::PACKET);
+
+        writer
+            .encode(&data::SubgroupHeader {
+                header_type: data::StreamHeaderType::SubgroupIdExt,
+                track_alias: datagram.track_alias,
+                group_id: datagram.group_id,
+                subgroup_id: Some(object_id),
+                publisher_priority: datagram.publisher_priority,
+            })
+            .await?;
+        writer
+            .encode(&data::SubgroupObjectExt {
+                object_id_delta: object_id,
+                extension_headers: datagram.extension_headers.clone().unwrap_or_default(),
+                payload_length: payload.len(),
+                status: datagram.status.or(payload
+                    .is_empty()
+                    .then_some(data::ObjectStatus::NormalObject)),
+            })
+            .await?;
+        writer.write(payload).await?;
+        writer.flush().await?;
+
+        Ok(())
+    }
+}
+
+/// Re-checks the authorization of a [Subscribed] while it's served, ex. as its tokens expire.
+#[derive(Clone)]
+pub struct SubscribedAuthorization {
+    state: State<SubscribedState>,
+}
+
+impl SubscribedAuthorization {
+    /// The tokens from the SUBSCRIBE, or from the latest SUBSCRIBE_UPDATE carrying any.
+    pub fn tokens(&self) -> Vec<Token> {
+        self.state.lock().authorization_tokens.clone()
+    }
+
+    /// End the subscription with [ServeError::AuthExpired], sent as UNAUTHORIZED in PUBLISH_DONE.
+    pub fn expire(&self) -> Result<(), ServeError> {
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        let mut state = state.into_mut().ok_or(ServeError::Done)?;
+        state.closed = Err(ServeError::AuthExpired);
+
+        Ok(())
+    }
+}
+
+/// Ends a [Subscribed] while it's served, from outside of [Subscribed::serve].
+#[derive(Clone)]
+pub struct SubscribedCloser {
+    state: State<SubscribedState>,
+}
+
+impl SubscribedCloser {
+    /// End the subscription with `err`, whose code is sent in PUBLISH_DONE.
+    pub fn close(&self, err: ServeError) -> Result<(), ServeError> {
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        let mut state = state.into_mut().ok_or(ServeError::Done)?;
+        state.closed = Err(err);
+
+        Ok(())
+    }
 }
 
 pub(super) struct SubscribedRecv {
     state: State<SubscribedState>,
+    track_namespace: TrackNamespace,
+    track_name: String,
+    position: SubscriptionPosition,
+    stats: DeliveryStats,
 }
 
 impl SubscribedRecv {
+    pub fn snapshot(&self, id: u64) -> SubscriptionSnapshot {
+        let state = self.state.lock();
+
+        SubscriptionSnapshot {
+            id,
+            track_namespace: self.track_namespace.clone(),
+            track_name: self.track_name.clone(),
+            track_alias: state.track_alias,
+            state: RequestState::from_ok(state.track_alias.is_some()),
+            position: self.position.get(),
+            telemetry: state.telemetry.clone(),
+        }
+    }
+
+    pub fn stats(&self) -> DeliveryStats {
+        self.stats.clone()
+    }
+
+    /// Apply a SUBSCRIBE_UPDATE, along with the authorization tokens it carries, if any.
+    pub fn recv_update(
+        &mut self,
+        msg: &message::SubscribeUpdate,
+        tokens: Vec<Token>,
+    ) -> Result<(), SessionError> {
+        // The end group is encoded plus 1, with 0 meaning open-ended.
+        let end_group_id = msg.end_group_id.checked_sub(1);
+
+        let state = self.state.lock();
+        state.closed.clone()?;
+
+        // An update may only narrow the subscription.
+        let widened = match (state.end_group_id, end_group_id) {
+            (Some(_), None) => true,
+            (Some(current), Some(end)) => end > current,
+            (None, _) => false,
+        };
+        if widened {
+            log::warn!(
+                "SUBSCRIBE_UPDATE widened subscription {}: end_group_id={:?} -> {:?}",
+                msg.subscription_request_id,
+                state.end_group_id,
+                end_group_id
+            );
+            return Err(SessionError::RoleViolation);
+        }
+
+        let skip_to_latest = msg
+            .params
+            .get_intvalue(message::ParameterType::SkipToLatest.into())
+            .is_some_and(|value| value != 0);
+
+        if let Some(mut state) = state.into_mut() {
+            state.subscriber_priority = msg.subscriber_priority;
+            state.forward = msg.forward;
+            state.end_group_id = end_group_id;
+
+            if skip_to_latest {
+                state.skip_before = state.skip_before.max(state.latest_group_id);
+            }
+
+            if let Some(timeout) = delivery_timeout_param(&msg.params) {
+                state.delivery_timeout = Some(timeout);
+            }
+
+            if let Some(bitrate) = max_bitrate_param(&msg.params) {
+                state.max_bitrate = Some(bitrate);
+            }
+
+            if let Some(telemetry) = telemetry_param(&msg.params) {
+                state.telemetry = Some(telemetry);
+            }
+
+            if !tokens.is_empty() {
+                state.authorization_tokens = tokens;
+            }
+        }
+
+        self.stats.set_subscriber_priority(msg.subscriber_priority);
+
+        Ok(())
+    }
+
     pub fn recv_unsubscribe(&mut self) -> Result<(), ServeError> {
         let state = self.state.lock();
         state.closed.clone()?;
@@ -457,3 +1252,283 @@ impl SubscribedRecv {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serve::QuotaViolation;
+
+    #[test]
+    fn gap_tracker() {
+        let mut gaps = GapTracker::default();
+        assert_eq!(gaps.next(3), None);
+
+        // Groups missing upstream aren't signalled.
+        assert_eq!(gaps.next(5), None);
+
+        // Groups we skipped are.
+        gaps.skipped();
+        assert_eq!(gaps.next(8), Some(2));
+        assert_eq!(gaps.next(8), None);
+        assert_eq!(gaps.next(9), None);
+    }
+
+    #[test]
+    fn group_start() {
+        // Other filters deliver from wherever they join.
+        let mut start = GroupStart::default();
+        assert!(start.admits(5, false));
+
+        // NextGroupStart skips the current group, even from its start.
+        let mut start = GroupStart {
+            required: true,
+            after: Some(5),
+            started: None,
+        };
+        assert!(!start.admits(5, true));
+        assert!(!start.admits(6, false));
+        assert!(start.admits(6, true));
+        assert!(start.admits(6, false));
+        assert!(start.admits(7, false));
+        assert!(!start.admits(5, true));
+
+        // GroupStart also accepts the latest group, if it is still cached from its start.
+        let mut start = GroupStart {
+            required: true,
+            after: None,
+            started: None,
+        };
+        assert!(!start.admits(5, false));
+        assert!(start.admits(5, true));
+    }
+
+    #[test]
+    fn skip_to_latest() {
+        let info = SubscribeInfo::new_from_subscribe(&message::Subscribe {
+            id: 1,
+            track_namespace: TrackNamespace::from_utf8_path("live"),
+            track_name: "video".to_string(),
+            subscriber_priority: 127,
+            group_order: message::GroupOrder::Publisher,
+            forward: true,
+            filter_type: message::FilterType::LargestObject,
+            start_location: None,
+            end_group_id: None,
+            params: Default::default(),
+        });
+        let (state, recv) = State::new(SubscribedState::new(&info, Default::default())).split();
+        let (position, _) = SubscriptionPosition::produce();
+        let (_, stats) = DeliveryStats::produce(127);
+        let mut recv = SubscribedRecv {
+            state: recv,
+            track_namespace: info.track_namespace.clone(),
+            track_name: info.track_name.clone(),
+            position,
+            stats,
+        };
+        state.lock_mut().unwrap().latest_group_id = Some(7);
+
+        let mut update = message::SubscribeUpdate {
+            id: 2,
+            subscription_request_id: 1,
+            start_location: Default::default(),
+            end_group_id: 0,
+            subscriber_priority: 127,
+            forward: true,
+            params: Default::default(),
+        };
+        recv.recv_update(&update, Vec::new()).unwrap();
+        assert!(!state.lock().skipped(6));
+
+        // Groups before the latest one delivered are dropped.
+        update
+            .params
+            .set_intvalue(message::ParameterType::SkipToLatest.into(), 1);
+        recv.recv_update(&update, Vec::new()).unwrap();
+        assert!(state.lock().skipped(6));
+        assert!(!state.lock().skipped(7));
+    }
+
+    #[test]
+    fn skips_cached_groups() {
+        let mut params = KeyValuePairs::default();
+        let mut bytes = Vec::new();
+        4u64.encode(&mut bytes).unwrap();
+        6u64.encode(&mut bytes).unwrap();
+        params.set_bytesvalue(message::ParameterType::CachedGroups.into(), bytes);
+
+        let info = SubscribeInfo::new_from_subscribe(&message::Subscribe {
+            id: 1,
+            track_namespace: TrackNamespace::from_utf8_path("live"),
+            track_name: "video".to_string(),
+            subscriber_priority: 127,
+            group_order: message::GroupOrder::Publisher,
+            forward: true,
+            filter_type: message::FilterType::LargestObject,
+            start_location: None,
+            end_group_id: None,
+            params,
+        });
+        let mut state = SubscribedState::new(&info, Default::default());
+        assert!(!state.cached(3));
+        assert!(state.cached(4) && state.cached(6));
+        assert!(!state.cached(7));
+
+        // Groups from the latest on may be new, ex. after the publisher restarted.
+        state.limit_cached(Some(6));
+        assert!(state.cached(5) && !state.cached(6));
+        state.limit_cached(Some(4));
+        assert!(!state.cached(4));
+        assert_eq!(state.cached_groups, None);
+    }
+
+    #[test]
+    fn boosted_priority() {
+        let info = SubscribeInfo::new_from_subscribe(&message::Subscribe {
+            id: 1,
+            track_namespace: TrackNamespace::from_utf8_path("live"),
+            track_name: "audio".to_string(),
+            subscriber_priority: 127,
+            group_order: message::GroupOrder::Publisher,
+            forward: true,
+            filter_type: message::FilterType::LargestObject,
+            start_location: None,
+            end_group_id: None,
+            params: Default::default(),
+        });
+        let (state, recv) = State::new(SubscribedState::new(&info, Default::default())).split();
+        let (position, _) = SubscriptionPosition::produce();
+        let (_, stats) = DeliveryStats::produce(127);
+        let mut recv = SubscribedRecv {
+            state: recv,
+            track_namespace: info.track_namespace.clone(),
+            track_name: info.track_name.clone(),
+            position,
+            stats,
+        };
+        assert_eq!(state.lock().priority(), 127);
+
+        state.lock_mut().unwrap().priority_boost = Some(10);
+        assert_eq!(state.lock().priority(), 10);
+
+        // Updates can't undo the boost, only ask for more.
+        let mut update = message::SubscribeUpdate {
+            id: 2,
+            subscription_request_id: 1,
+            start_location: Default::default(),
+            end_group_id: 0,
+            subscriber_priority: 200,
+            forward: true,
+            params: Default::default(),
+        };
+        recv.recv_update(&update, Vec::new()).unwrap();
+        assert_eq!(state.lock().priority(), 10);
+
+        update.subscriber_priority = 3;
+        recv.recv_update(&update, Vec::new()).unwrap();
+        assert_eq!(state.lock().priority(), 3);
+    }
+
+    #[test]
+    fn renews_authorization() {
+        let mut info = SubscribeInfo::new_from_subscribe(&message::Subscribe {
+            id: 1,
+            track_namespace: TrackNamespace::from_utf8_path("live"),
+            track_name: "video".to_string(),
+            subscriber_priority: 127,
+            group_order: message::GroupOrder::Publisher,
+            forward: true,
+            filter_type: message::FilterType::LargestObject,
+            start_location: None,
+            end_group_id: None,
+            params: Default::default(),
+        });
+        let first = Token::new(0, b"first".to_vec());
+        info.authorization_tokens = vec![first.clone()];
+
+        let (state, recv) = State::new(SubscribedState::new(&info, Default::default())).split();
+        let (position, _) = SubscriptionPosition::produce();
+        let (_, stats) = DeliveryStats::produce(127);
+        let mut recv = SubscribedRecv {
+            state: recv,
+            track_namespace: info.track_namespace.clone(),
+            track_name: info.track_name.clone(),
+            position,
+            stats,
+        };
+        let authorization = SubscribedAuthorization { state };
+
+        // Updates without tokens keep the current ones.
+        let update = message::SubscribeUpdate {
+            id: 2,
+            subscription_request_id: 1,
+            start_location: Default::default(),
+            end_group_id: 0,
+            subscriber_priority: 127,
+            forward: true,
+            params: Default::default(),
+        };
+        recv.recv_update(&update, Vec::new()).unwrap();
+        assert_eq!(authorization.tokens(), vec![first]);
+
+        let second = Token::new(0, b"second".to_vec());
+        recv.recv_update(&update, vec![second.clone()]).unwrap();
+        assert_eq!(authorization.tokens(), vec![second]);
+
+        authorization.expire().unwrap();
+        assert_eq!(
+            authorization.state.lock().closed,
+            Err(ServeError::AuthExpired)
+        );
+        assert!(recv.recv_update(&update, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn enforces_quota() {
+        let info = SubscribeInfo::new_from_subscribe(&message::Subscribe {
+            id: 1,
+            track_namespace: TrackNamespace::from_utf8_path("live"),
+            track_name: "video".to_string(),
+            subscriber_priority: 127,
+            group_order: message::GroupOrder::Publisher,
+            forward: true,
+            filter_type: message::FilterType::LargestObject,
+            start_location: None,
+            end_group_id: None,
+            params: Default::default(),
+        });
+        let quota = SubscriptionQuota {
+            max_buffered_bytes: Some(1000),
+            ..Default::default()
+        };
+        let (state, _recv) = State::new(SubscribedState::new(&info, quota)).split();
+        let (stats, _watcher) = DeliveryStats::produce(127);
+
+        let _first = stats.queue(1000);
+        assert_eq!(enforce_quota(&state, &stats), Ok(()));
+
+        // Going over the limit ends the subscription.
+        let _second = stats.queue(1);
+        let violated = Err(ServeError::QuotaViolated(QuotaViolation::Buffered {
+            bytes: 1001,
+            limit: 1000,
+        }));
+        assert_eq!(enforce_quota(&state, &stats), violated);
+        assert_eq!(state.lock().closed, violated);
+    }
+
+    #[test]
+    fn subscriber_priority_takes_precedence() {
+        // Lower subscriber priority values are more important.
+        assert!(stream_priority(0, 0) > stream_priority(1, 255));
+        // The publisher priority breaks ties, also with lower values being more important.
+        assert!(stream_priority(127, 1) > stream_priority(127, 2));
+        assert!(stream_priority(127, 0) > stream_priority(127, 255));
+    }
+
+    #[test]
+    fn fetch_below_live() {
+        assert!(fetch_stream_priority(0, 255) < stream_priority(255, 0));
+        assert!(fetch_stream_priority(0, 0) > fetch_stream_priority(1, 255));
+    }
+}
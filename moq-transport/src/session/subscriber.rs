@@ -1,14 +1,16 @@
 use std::{
     collections::{hash_map, HashMap},
-    io,
+    future::Future,
     sync::{atomic, Arc, Mutex},
-    time::Duration,
+    task,
+    time::{Duration, Instant},
 };
 
+use futures::channel::oneshot;
 use tokio::sync::Notify;
 
 use crate::{
-    coding::{Decode, TrackNamespace},
+    coding::{Decode, Location, TrackNamespace},
     data,
     message::{self, FilterType, GroupOrder, Message},
     mlog,
@@ -17,11 +19,73 @@ use crate::{
 
 use crate::watch::Queue;
 
-use super::{Announced, AnnouncedRecv, Reader, Session, SessionError, Subscribe, SubscribeRecv};
+use super::{
+    AnnounceSnapshot, Announced, AnnouncedRecv, AuthTokenCache, BufferPool, ClockSkew, Reader,
+    RequestKind, Requests, Response, Session, SessionError, Subscribe, SubscribeAll,
+    SubscribeNamespace, SubscribeNamespaceRecv, SubscribeRecv, SubscriptionPosition,
+    SubscriptionSnapshot, Subscriptions, TrackStatus, TrackStatusError,
+};
 
-// Default timeout for waiting for subscribe aliases to become available via SUBSCRIBE_OK (1 second)
+// Default timeout for waiting for subscribe aliases to become available via SUBSCRIBE_OK (1 second).
+// The publisher writes SUBSCRIBE_OK before opening the track's streams, but they're independent of
+// the control stream, so one can still arrive first, ex. when the packet carrying SUBSCRIBE_OK is lost.
 const DEFAULT_ALIAS_WAIT_TIME_MS: u64 = 1000;
 
+/// Limits applied to objects received from the publisher.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObjectLimits {
+    /// Objects (or datagram payloads) larger than this are rejected with [ServeError::Size]
+    /// before any of the payload is buffered.
+    pub max_object_size: Option<usize>,
+
+    /// Stop reading a stream while the furthest local reader of the current object is more than
+    /// this many bytes behind, pushing backpressure onto the QUIC stream.
+    pub max_buffered: Option<usize>,
+}
+
+impl ObjectLimits {
+    /// The largest object read whole before any reader sees it, ex. to be validated or when
+    /// datagrams are sent on a stream, when [Self::max_object_size] isn't set.
+    pub const DEFAULT_MAX_WHOLE_OBJECT: usize = 4 << 20;
+
+    /// The largest object read whole before any reader sees it: [Self::max_object_size], or
+    /// [Self::DEFAULT_MAX_WHOLE_OBJECT] if unset, and no more than [Self::max_buffered].
+    pub fn max_whole_object(&self) -> usize {
+        let max = self
+            .max_object_size
+            .unwrap_or(Self::DEFAULT_MAX_WHOLE_OBJECT);
+        self.max_buffered.map_or(max, |buffered| max.min(buffered))
+    }
+}
+
+/// Checks the payloads of objects received from the publisher before any reader sees them, ex.
+/// schema checking for metadata tracks. Set with [Subscriber::set_object_validator].
+pub trait ObjectValidator: Send + Sync {
+    /// Whether the objects of this track are checked. Their payloads are buffered whole before
+    /// being delivered, while the objects of other tracks are streamed as they arrive, so objects
+    /// larger than [ObjectLimits::max_whole_object] are rejected.
+    fn validates(&self, namespace: &TrackNamespace, track: &str) -> bool;
+
+    /// Whether an object of this track may be delivered. Rejected objects are dropped.
+    fn validate(&self, namespace: &TrackNamespace, track: &str, payload: &bytes::Bytes) -> bool;
+}
+
+type TrackStatusSender = oneshot::Sender<Result<TrackStatus, TrackStatusError>>;
+
+// Removes a TRACK_STATUS request from [Subscriber::track_statuses] when its future is dropped.
+struct PendingTrackStatus {
+    track_statuses: Arc<Mutex<Option<HashMap<u64, TrackStatusSender>>>>,
+    request_id: u64,
+}
+
+impl Drop for PendingTrackStatus {
+    fn drop(&mut self) {
+        if let Some(pending) = self.track_statuses.lock().unwrap().as_mut() {
+            pending.remove(&self.request_id);
+        }
+    }
+}
+
 // TODO remove Clone.
 #[derive(Clone)]
 pub struct Subscriber {
@@ -34,6 +98,13 @@ pub struct Subscriber {
     /// The currently active outbound subscribes, keyed by request id.
     subscribes: Arc<Mutex<HashMap<u64, SubscribeRecv>>>,
 
+    /// The currently active outbound namespace subscriptions, keyed by request id.
+    subscribe_namespaces: Arc<Mutex<HashMap<u64, SubscribeNamespaceRecv>>>,
+
+    /// The outbound track status requests waiting on their response, keyed by request id.
+    /// None once the session ended, see [Subscriber::close_requests].
+    track_statuses: Arc<Mutex<Option<HashMap<u64, TrackStatusSender>>>>,
+
     /// Map of track alias to subscription id for quick lookup when receiving streams/datagrams.
     subscribe_alias_map: Arc<Mutex<HashMap<u64, u64>>>,
 
@@ -44,32 +115,105 @@ pub struct Subscriber {
     /// will process the queue and send the message on the control stream.
     outgoing: Queue<Message>,
 
-    /// When we need a new Request Id for sending a request, we can get it from here.  Note:  The instance
-    /// of AtomicU64 is shared with the Subscriber, so the session uses unique request ids for all requests
-    /// generated.  Note:  If we initiated the QUIC connection then request id's start at 0 and increment by 2
-    /// for each request (even numbers).  If we accepted an inbound QUIC connection then request id's start at 1 and
-    /// increment by 2 for each request (odd numbers).
-    next_requestid: Arc<atomic::AtomicU64>,
+    /// The request IDs of the session, shared with the Publisher, matching responses to our requests.
+    requests: Requests,
 
     /// Optional mlog writer for logging transport events
     mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+
+    /// Limits for received objects, shared with all clones.
+    object_limits: Arc<Mutex<ObjectLimits>>,
+
+    /// Checks the payloads of received objects, if set, shared with all clones.
+    object_validator: Arc<Mutex<Option<Arc<dyn ObjectValidator>>>>,
+
+    /// Token aliases registered by the peer, shared with the Publisher.
+    auth_tokens: Arc<Mutex<AuthTokenCache>>,
+
+    /// Read buffers recycled across the streams we accept.
+    buffers: BufferPool,
+
+    /// Streams and datagrams the publisher sent in violation of the protocol or our limits.
+    violations: Arc<atomic::AtomicU64>,
+
+    /// How far the publisher's clock is off ours, from the capture timestamps of its objects.
+    clock: ClockSkew,
 }
 
 impl Subscriber {
     pub(super) fn new(
         outgoing: Queue<Message>,
-        next_requestid: Arc<atomic::AtomicU64>,
+        requests: Requests,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
+        auth_tokens: Arc<Mutex<AuthTokenCache>>,
     ) -> Self {
         Self {
             announced: Default::default(),
             announced_queue: Default::default(),
             subscribes: Default::default(),
+            subscribe_namespaces: Default::default(),
+            track_statuses: Arc::new(Mutex::new(Some(HashMap::new()))),
             subscribe_alias_map: Default::default(),
             outgoing,
-            next_requestid,
+            requests,
             mlog,
             subscribe_alias_notify: Arc::new(Notify::new()),
+            object_limits: Default::default(),
+            object_validator: Default::default(),
+            auth_tokens,
+            buffers: Default::default(),
+            violations: Default::default(),
+            clock: Default::default(),
+        }
+    }
+
+    /// The estimated offsets of the publishers' clocks, by namespace, shared with all clones.
+    /// Objects received with a capture timestamp are dated by it, so their DELIVERY_TIMEOUT and
+    /// budget count from their capture by our clock.
+    pub fn clock_skew(&self) -> ClockSkew {
+        self.clock.clone()
+    }
+
+    /// Configure the limits for objects received from the publisher.
+    /// Applies to all clones of this subscriber, and to streams accepted after the call.
+    pub fn set_object_limits(&self, limits: ObjectLimits) {
+        *self.object_limits.lock().unwrap() = limits;
+    }
+
+    /// The limits for objects received from the publisher.
+    pub fn object_limits(&self) -> ObjectLimits {
+        *self.object_limits.lock().unwrap()
+    }
+
+    /// Check the payloads of received objects with `validator`, or stop checking them.
+    /// Applies to all clones of this subscriber, and to streams accepted after the call.
+    pub fn set_object_validator(&self, validator: Option<Arc<dyn ObjectValidator>>) {
+        *self.object_validator.lock().unwrap() = validator;
+    }
+
+    // The validator checking the objects of this track, if any.
+    fn validator(
+        &self,
+        namespace: &TrackNamespace,
+        track: &str,
+    ) -> Option<Arc<dyn ObjectValidator>> {
+        let validator = self.object_validator.lock().unwrap().clone()?;
+        validator.validates(namespace, track).then_some(validator)
+    }
+
+    /// How many streams and datagrams were dropped for being malformed or exceeding the
+    /// [ObjectLimits], as a symptom of a misbehaving publisher.
+    pub fn violations(&self) -> u64 {
+        self.violations.load(atomic::Ordering::Relaxed)
+    }
+
+    // Count a stream that failed with `err`, if the publisher was at fault.
+    pub(super) fn count_violation(&self, err: &SessionError) {
+        if matches!(
+            err,
+            SessionError::Decode(_) | SessionError::Serve(ServeError::Size)
+        ) {
+            self.violations.fetch_add(1, atomic::Ordering::Relaxed);
         }
     }
 
@@ -90,14 +234,62 @@ impl Subscriber {
         self.announced_queue.pop().await
     }
 
-    /// Get the current next request id to use and increment the value for by 2 for the next request
-    fn get_next_request_id(&self) -> u64 {
-        self.next_requestid.fetch_add(2, atomic::Ordering::Relaxed)
+    /// Like [Self::announced], for callers driving their own event loop, ex. over FFI.
+    /// Returns Pending and wakes `cx` once a namespace is announced.
+    pub fn poll_announced(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Option<Announced>> {
+        self.announced_queue.poll_pop(cx)
+    }
+
+    /// List our active subscriptions, in request order.
+    pub fn subscriptions(&self) -> Vec<SubscriptionSnapshot> {
+        let mut list: Vec<_> = self
+            .subscribes
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, subscribe)| subscribe.snapshot(*id))
+            .collect();
+
+        list.sort_by_key(|subscription| subscription.id);
+        list
     }
 
-    pub fn track_status(&mut self, track_namespace: &TrackNamespace, track_name: &str) {
+    /// List the namespaces currently announced by the publisher, in request order.
+    pub fn announces(&self) -> Vec<AnnounceSnapshot> {
+        let mut list: Vec<_> = self
+            .announced
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(namespace, announced)| announced.snapshot(namespace))
+            .collect();
+
+        list.sort_by_key(|announce| announce.request_id);
+        list
+    }
+
+    /// Get the next request id to use, for a request whose response isn't tracked.
+    pub(super) fn get_next_request_id(&self) -> u64 {
+        self.requests.next_id()
+    }
+
+    /// Ask the publisher for the status of a track with TRACK_STATUS, resolving to its answer.
+    ///
+    /// The request is sent right away, whether or not the returned future is polled. It fails with
+    /// [TrackStatusError::Closed] once the session ends.
+    pub fn track_status(
+        &mut self,
+        track_namespace: &TrackNamespace,
+        track_name: &str,
+    ) -> impl Future<Output = Result<TrackStatus, TrackStatusError>> {
+        let request_id = self.requests.issue(RequestKind::TrackStatus);
+        let (send, recv) = oneshot::channel();
+        if let Some(pending) = self.track_statuses.lock().unwrap().as_mut() {
+            pending.insert(request_id, send);
+        }
+
         self.send_message(message::TrackStatus {
-            id: self.get_next_request_id(),
+            id: request_id,
             track_namespace: track_namespace.clone(),
             track_name: track_name.to_string(),
             subscriber_priority: 127, // default to mid value, see: https://github.com/moq-wg/moq-transport/issues/504
@@ -108,19 +300,77 @@ impl Subscriber {
             end_group_id: None,
             params: Default::default(),
         });
-        // TODO make async and wait for response?
+
+        // Forget the request if the caller stops waiting for it.
+        let pending = PendingTrackStatus {
+            track_statuses: self.track_statuses.clone(),
+            request_id,
+        };
+
+        // The sender is dropped unanswered once the session ends.
+        async move {
+            let _pending = pending;
+            recv.await.unwrap_or(Err(TrackStatusError::Closed))
+        }
+    }
+
+    /// Fail the requests still waiting on a response, and any made later, as the session ended.
+    pub(super) fn close_requests(&self) {
+        self.track_statuses.lock().unwrap().take();
     }
 
     /// Subscribe to a track by creating a new subscribe request to the publisher.  Block until subscription is closed.
     pub async fn subscribe(&mut self, track: serve::TrackWriter) -> Result<(), ServeError> {
-        let request_id = self.get_next_request_id();
-        let (send, recv) = Subscribe::new(self.clone(), request_id, track);
+        self.subscribe_handle(track).closed().await
+    }
+
+    /// Subscribe to a track, returning the [Subscribe] handle instead of blocking.
+    /// The handle can be used to update the subscription, and unsubscribes when dropped.
+    pub fn subscribe_handle(&mut self, track: serve::TrackWriter) -> Subscribe {
+        // default to mid value, see: https://github.com/moq-wg/moq-transport/issues/504
+        self.subscribe_with_priority(track, 127)
+    }
+
+    /// Subscribe to many tracks at once, see [SubscribeAll] to set their priorities.
+    /// The returned handle reports each subscription's status, and unsubscribes when dropped.
+    pub fn subscribe_all(&mut self, tracks: Vec<serve::TrackWriter>) -> Subscriptions {
+        SubscribeAll::new(tracks).subscribe(self)
+    }
+
+    pub(super) fn subscribe_with_priority(
+        &mut self,
+        track: serve::TrackWriter,
+        subscriber_priority: u8,
+    ) -> Subscribe {
+        let request_id = self.requests.issue(RequestKind::Subscribe);
+        let (send, recv) = Subscribe::new(self.clone(), request_id, track, subscriber_priority);
         self.subscribes.lock().unwrap().insert(request_id, recv);
 
-        send.closed().await
+        send
+    }
+
+    /// Tell the publisher we're interested in the namespaces under `prefix`, with SUBSCRIBE_NAMESPACE.
+    /// The interest lasts until the returned handle is dropped.
+    pub fn subscribe_namespace(&mut self, prefix: TrackNamespace) -> SubscribeNamespace {
+        let request_id = self.get_next_request_id();
+        let (send, recv) = SubscribeNamespace::new(self.clone(), request_id, prefix);
+        self.subscribe_namespaces
+            .lock()
+            .unwrap()
+            .insert(request_id, recv);
+
+        send
     }
 
     /// Send a message to the publisher via the control stream.
+    /// Drop the subgroups buffered for subscription `id`, if it is still active.
+    pub(super) fn discard_buffered(&self, id: u64) -> Result<(), ServeError> {
+        match self.subscribes.lock().unwrap().get_mut(&id) {
+            Some(subscribe) => subscribe.discard_buffered(),
+            None => Ok(()),
+        }
+    }
+
     pub(super) fn send_message<M: Into<message::Subscriber>>(&mut self, msg: M) {
         let msg = msg.into();
 
@@ -129,8 +379,17 @@ impl Subscriber {
             message::Subscriber::PublishNamespaceCancel(msg) => {
                 self.drop_publish_namespace(&msg.track_namespace)
             }
-            // TODO SLG - there is no longer a namespace in the error, need to map via request id
-            message::Subscriber::PublishNamespaceError(_msg) => {} // Not implemented yet - need request id mapping
+            message::Subscriber::UnsubscribeNamespace(msg) => self
+                .subscribe_namespaces
+                .lock()
+                .unwrap()
+                .retain(|_, recv| recv.prefix != msg.track_namespace_prefix),
+            // There is no longer a namespace in the error, so map via the request id.
+            message::Subscriber::PublishNamespaceError(msg) => self
+                .announced
+                .lock()
+                .unwrap()
+                .retain(|_, recv| recv.request_id != msg.id),
             _ => {}
         }
 
@@ -148,16 +407,12 @@ impl Subscriber {
             message::Publisher::SubscribeOk(msg) => self.recv_subscribe_ok(msg),
             message::Publisher::SubscribeError(msg) => self.recv_subscribe_error(msg),
             message::Publisher::TrackStatusOk(msg) => self.recv_track_status_ok(msg),
-            message::Publisher::TrackStatusError(_msg) => {
-                Err(SessionError::unimplemented("TRACK_STATUS_ERROR"))
-            }
+            message::Publisher::TrackStatusError(msg) => self.recv_track_status_error(msg),
             message::Publisher::FetchOk(_msg) => Err(SessionError::unimplemented("FETCH_OK")),
             message::Publisher::FetchError(_msg) => Err(SessionError::unimplemented("FETCH_ERROR")),
-            message::Publisher::SubscribeNamespaceOk(_msg) => {
-                Err(SessionError::unimplemented("SUBSCRIBE_NAMESPACE_OK"))
-            }
-            message::Publisher::SubscribeNamespaceError(_msg) => {
-                Err(SessionError::unimplemented("SUBSCRIBE_NAMESPACE_ERROR"))
+            message::Publisher::SubscribeNamespaceOk(msg) => self.recv_subscribe_namespace_ok(msg),
+            message::Publisher::SubscribeNamespaceError(msg) => {
+                self.recv_subscribe_namespace_error(msg)
             }
         };
 
@@ -182,8 +437,16 @@ impl Subscriber {
             hash_map::Entry::Vacant(entry) => entry,
         };
 
+        let tokens = self.auth_tokens.lock().unwrap().resolve(&msg.params)?;
+
         // Create the announced namespace and insert it into our map of active announces, and the announced queue.
-        let (announced, recv) = Announced::new(self.clone(), msg.id, msg.track_namespace.clone());
+        let (announced, recv) = Announced::new(
+            self.clone(),
+            msg.id,
+            msg.track_namespace.clone(),
+            tokens,
+            msg.params.clone(),
+        );
         if let Err(announced) = self.announced_queue.push(announced) {
             announced.close(ServeError::Cancel)?;
             return Ok(());
@@ -207,7 +470,32 @@ impl Subscriber {
 
     /// Handle the reception of a SubscribeOk message from the publisher.
     fn recv_subscribe_ok(&mut self, msg: &message::SubscribeOk) -> Result<(), SessionError> {
-        if let Some(subscribe) = self.subscribes.lock().unwrap().get_mut(&msg.id) {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::Subscribe, Response::Ok)?
+        {
+            return Ok(());
+        }
+
+        let mut subscribes = self.subscribes.lock().unwrap();
+
+        // An alias may be reused once its subscription ends, but never for two tracks at once.
+        let current = self
+            .subscribe_alias_map
+            .lock()
+            .unwrap()
+            .get(&msg.track_alias)
+            .copied();
+        if let (Some(current), Some(subscribe)) = (
+            current.and_then(|id| subscribes.get(&id)),
+            subscribes.get(&msg.id),
+        ) {
+            if !subscribe.same_track(current) {
+                return Err(SessionError::Duplicate);
+            }
+        }
+
+        if let Some(subscribe) = subscribes.get_mut(&msg.id) {
             // Map track alias to subscription id for quick lookup when receiving streams/datagrams
             self.subscribe_alias_map
                 .lock()
@@ -217,8 +505,15 @@ impl Subscriber {
             // Notify waiting tasks that the alias map has been updated
             self.subscribe_alias_notify.notify_waiters();
 
+            // A MAX_CACHE_DURATION of zero means the publisher marked the track as live-only
+            let max_cache_duration = msg
+                .params
+                .get_intvalue(message::ParameterType::MaxCacheDuration.into());
+            subscribe.set_fetchable(max_cache_duration != Some(0))?;
+
             // Notify the subscribe of the successful subscription
-            subscribe.ok(msg.track_alias)?;
+            let largest_location = msg.largest_location.filter(|_| msg.content_exists);
+            subscribe.ok(msg.track_alias, msg.expires, largest_location)?;
         }
 
         Ok(())
@@ -226,6 +521,7 @@ impl Subscriber {
 
     /// Remove a subscribe from our map of active subscribes, and the alias map if present.
     fn remove_subscribe(&mut self, id: u64) -> Option<SubscribeRecv> {
+        self.requests.end(id);
         if let Some(subscribe) = self.subscribes.lock().unwrap().remove(&id) {
             // Remove from alias map if present
             if let Some(track_alias) = subscribe.track_alias() {
@@ -242,6 +538,13 @@ impl Subscriber {
 
     /// Handle the reception of a SubscribeError message from the publisher.
     fn recv_subscribe_error(&mut self, msg: &message::SubscribeError) -> Result<(), SessionError> {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::Subscribe, Response::Error)?
+        {
+            return Ok(());
+        }
+
         if let Some(subscribe) = self.remove_subscribe(msg.id) {
             subscribe.error(ServeError::Closed(msg.error_code))?;
         }
@@ -249,6 +552,30 @@ impl Subscriber {
         Ok(())
     }
 
+    /// Handle the reception of a SubscribeNamespaceOk message from the publisher.
+    fn recv_subscribe_namespace_ok(
+        &mut self,
+        msg: &message::SubscribeNamespaceOk,
+    ) -> Result<(), SessionError> {
+        if let Some(subscribe) = self.subscribe_namespaces.lock().unwrap().get_mut(&msg.id) {
+            subscribe.recv_ok()?;
+        }
+
+        Ok(())
+    }
+
+    /// Handle the reception of a SubscribeNamespaceError message from the publisher.
+    fn recv_subscribe_namespace_error(
+        &mut self,
+        msg: &message::SubscribeNamespaceError,
+    ) -> Result<(), SessionError> {
+        if let Some(subscribe) = self.subscribe_namespaces.lock().unwrap().remove(&msg.id) {
+            subscribe.recv_error(ServeError::Closed(msg.error_code))?;
+        }
+
+        Ok(())
+    }
+
     /// Handle the reception of a PublishDone message from the publisher.
     fn recv_publish_done(&mut self, msg: &message::PublishDone) -> Result<(), SessionError> {
         if let Some(subscribe) = self.remove_subscribe(msg.id) {
@@ -259,13 +586,50 @@ impl Subscriber {
     }
 
     /// Handle the reception of a TrackStatusOk message from the publisher.
-    fn recv_track_status_ok(&mut self, _msg: &message::TrackStatusOk) -> Result<(), SessionError> {
-        // TODO: Expose this somehow?
-        // TODO: Also add a way to send a Track Status Request in the first place
+    fn recv_track_status_ok(&mut self, msg: &message::TrackStatusOk) -> Result<(), SessionError> {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::TrackStatus, Response::Ok)?
+        {
+            return Ok(());
+        }
+        self.requests.end(msg.id);
+
+        // The caller may have stopped waiting.
+        if let Some(send) = self.take_track_status(msg.id) {
+            let _ = send.send(Ok(msg.into()));
+        }
+
+        Ok(())
+    }
+
+    /// Handle the reception of a TrackStatusError message from the publisher.
+    fn recv_track_status_error(
+        &mut self,
+        msg: &message::TrackStatusError,
+    ) -> Result<(), SessionError> {
+        if !self
+            .requests
+            .respond(msg.id, RequestKind::TrackStatus, Response::Error)?
+        {
+            return Ok(());
+        }
+
+        if let Some(send) = self.take_track_status(msg.id) {
+            let _ = send.send(Err(msg.into()));
+        }
 
         Ok(())
     }
 
+    fn take_track_status(&self, request_id: u64) -> Option<TrackStatusSender> {
+        self.track_statuses
+            .lock()
+            .unwrap()
+            .as_mut()?
+            .remove(&request_id)
+    }
+
     /// Remove an announced namespace from our map of active announces.
     fn drop_publish_namespace(&mut self, namespace: &TrackNamespace) {
         self.announced.lock().unwrap().remove(namespace);
@@ -273,6 +637,13 @@ impl Subscriber {
 
     /// Get a subscribe id by track alias, waiting up to the specified timeout if not present.
     /// If timeout_ms is None, only check if already present and return None if not.
+    // The namespace of the track with `track_alias`, if it's known yet.
+    fn namespace_by_alias(&self, track_alias: u64) -> Option<TrackNamespace> {
+        let id = *self.subscribe_alias_map.lock().unwrap().get(&track_alias)?;
+        let subscribes = self.subscribes.lock().unwrap();
+        Some(subscribes.get(&id)?.track().0.clone())
+    }
+
     async fn get_subscribe_id_by_alias(
         &self,
         track_alias: u64,
@@ -324,7 +695,7 @@ impl Subscriber {
         stream: web_transport::RecvStream,
     ) -> Result<(), SessionError> {
         log::trace!("[SUBSCRIBER] recv_stream: new stream received, decoding header");
-        let mut reader = Reader::new(stream);
+        let mut reader = Reader::with_pool(stream, self.buffers.clone());
 
         // Decode the stream header
         let stream_header: data::StreamHeader = reader.decode().await?;
@@ -341,7 +712,7 @@ impl Subscriber {
         // Log subgroup header parsed/received
         if let Some(ref subgroup_header) = stream_header.subgroup_header {
             if let Some(ref mlog) = self.mlog {
-                if let Ok(mut mlog_guard) = mlog.lock() {
+                if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane()) {
                     let time = mlog_guard.elapsed_ms();
                     let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
                     let event = mlog::subgroup_header_parsed(time, stream_id, subgroup_header);
@@ -392,7 +763,12 @@ impl Subscriber {
         // This is super silly, but I couldn't figure out a way to avoid the mutex guard across awaits.
         enum Writer {
             //Fetch(serve::FetchWriter),
-            Subgroup(serve::SubgroupWriter),
+            Subgroup(
+                serve::SubgroupWriter,
+                SubscriptionPosition,
+                serve::TrackEventsWriter,
+            ),
+            Datagrams(data::SubgroupHeader),
         }
 
         let writer = {
@@ -411,9 +787,15 @@ impl Subscriber {
                 })?;
 
                 // Create the appropriate writer based on the stream header type
-                if stream_header.header_type.is_subgroup() {
+                if stream_header.header_type.is_subgroup() && subscribe.carries_datagrams() {
+                    Writer::Datagrams(stream_header.subgroup_header.unwrap())
+                } else if stream_header.header_type.is_subgroup() {
                     log::trace!("[SUBSCRIBER] recv_stream_inner: creating subgroup writer");
-                    Writer::Subgroup(subscribe.subgroup(stream_header.subgroup_header.unwrap())?)
+                    Writer::Subgroup(
+                        subscribe.subgroup(stream_header.subgroup_header.unwrap())?,
+                        subscribe.position(),
+                        subscribe.events(),
+                    )
                 } else {
                     return Err(SessionError::Serve(ServeError::internal_ctx(format!(
                         "unsupported stream header type={}",
@@ -431,11 +813,27 @@ impl Subscriber {
         // Handle the stream based on the writer type
         match writer {
             //Writer::Fetch(fetch) => Self::recv_fetch(fetch, reader).await?,
-            Writer::Subgroup(subgroup_writer) => {
-                log::trace!("[SUBSCRIBER] recv_stream_inner: receiving subgroup data");
-                Self::recv_subgroup(stream_header.header_type, subgroup_writer, reader, mlog)
+            Writer::Datagrams(header) => {
+                self.recv_datagram_stream(stream_header.header_type, header, reader)
                     .await?
             }
+            Writer::Subgroup(subgroup_writer, position, events) => {
+                log::trace!("[SUBSCRIBER] recv_stream_inner: receiving subgroup data");
+                let track = subgroup_writer.info.track.clone();
+                Self::recv_subgroup(
+                    stream_header.header_type,
+                    track_alias,
+                    subgroup_writer,
+                    position,
+                    events,
+                    reader,
+                    self.object_limits(),
+                    self.validator(&track.namespace, &track.name),
+                    self.clock.clone(),
+                    mlog,
+                )
+                .await?
+            }
         };
 
         log::debug!(
@@ -446,10 +844,17 @@ impl Subscriber {
     }
 
     /// If new stream is a Subgroup stream, handle reception of subgroup objects and payloads.
+    #[allow(clippy::too_many_arguments)]
     async fn recv_subgroup(
         stream_header_type: data::StreamHeaderType,
+        track_alias: u64,
         mut subgroup_writer: serve::SubgroupWriter,
+        position: SubscriptionPosition,
+        events: serve::TrackEventsWriter,
         mut reader: Reader,
+        limits: ObjectLimits,
+        validator: Option<Arc<dyn ObjectValidator>>,
+        clock: ClockSkew,
         mlog: Option<Arc<Mutex<mlog::MlogWriter>>>,
     ) -> Result<(), SessionError> {
         log::debug!(
@@ -460,7 +865,7 @@ impl Subscriber {
         );
 
         let mut object_count = 0;
-        let mut current_object_id = 0u64;
+        let mut next_object_id = 0u64;
         while !reader.done().await? {
             log::trace!(
                 "[SUBSCRIBER] recv_subgroup: reading object #{} (has_ext_headers={})",
@@ -486,12 +891,14 @@ impl Subscriber {
                         // Check for known draft-14 extension types
 
                         // Check for Immutable Extensions (type 0xB = 11)
-                        if object.extension_headers.has(0xB) {
+                        if object.extension_headers.has(data::IMMUTABLE_EXTENSIONS) {
                             log::info!(
                                 "[SUBSCRIBER] recv_subgroup: object #{} contains IMMUTABLE EXTENSIONS (type 0xB) - will be forwarded",
                                 object_count + 1
                             );
-                            if let Some(immutable_ext) = object.extension_headers.get(0xB) {
+                            if let Some(immutable_ext) =
+                                object.extension_headers.get(data::IMMUTABLE_EXTENSIONS)
+                            {
                                 log::debug!(
                                     "[SUBSCRIBER] recv_subgroup: immutable extension details: {:?}",
                                     immutable_ext
@@ -500,12 +907,12 @@ impl Subscriber {
                         }
 
                         // Check for Prior Group ID Gap (type 0x3C = 60)
-                        if object.extension_headers.has(0x3C) {
+                        if object.extension_headers.has(data::PRIOR_GROUP_ID_GAP) {
                             log::info!(
                                 "[SUBSCRIBER] recv_subgroup: object #{} contains PRIOR GROUP ID GAP (type 0x3C)",
                                 object_count + 1
                             );
-                            if let Some(gap_ext) = object.extension_headers.get(0x3C) {
+                            if let Some(gap_ext) = object.extension_headers.prior_group_id_gap() {
                                 log::debug!(
                                     "[SUBSCRIBER] recv_subgroup: prior group id gap details: {:?}",
                                     gap_ext
@@ -513,12 +920,11 @@ impl Subscriber {
                             }
                         }
 
-                        let obj_copy = object.clone();
                         (
                             object.payload_length,
                             object.object_id_delta,
                             object.status,
-                            Some(obj_copy),
+                            Some(object),
                         )
                     }
                     false => {
@@ -539,27 +945,26 @@ impl Subscriber {
                     }
                 };
 
-            // Calculate absolute object_id from delta
-            current_object_id += object_id_delta;
-
-            // Extract extension headers if present
-            let extension_headers = decoded_object
-                .as_ref()
-                .map(|obj| obj.extension_headers.clone());
+            // Each object ID is the delta past the one after the previous object
+            let current_object_id = next_object_id
+                .checked_add(object_id_delta)
+                .filter(|id| *id < u64::MAX)
+                .ok_or_else(|| ServeError::Internal("object ID overflow".into()))?;
+            next_object_id = current_object_id + 1;
 
             // Log subgroup object parsed/received
             if let Some(ref mlog) = mlog {
-                if let Ok(mut mlog_guard) = mlog.lock() {
+                if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane()) {
                     let time = mlog_guard.elapsed_ms();
                     let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
-                    let event = if let Some(obj_ext) = decoded_object {
+                    let event = if let Some(obj_ext) = &decoded_object {
                         mlog::subgroup_object_ext_parsed(
                             time,
                             stream_id,
                             subgroup_writer.info.group_id,
                             subgroup_writer.info.subgroup_id,
                             current_object_id,
-                            &obj_ext,
+                            obj_ext,
                         )
                     } else {
                         // For non-extension objects, create a temporary SubgroupObject for logging
@@ -577,14 +982,96 @@ impl Subscriber {
                             &temp_obj,
                         )
                     };
-                    let _ = mlog_guard.add_event(event);
+                    let object = mlog::SampledObject {
+                        namespace: Some(&subgroup_writer.info.track.namespace),
+                        track_alias,
+                        group_id: subgroup_writer.info.group_id,
+                        object_id: current_object_id,
+                        anomaly: mlog::SampledObject::is_anomaly(
+                            status,
+                            decoded_object.as_ref().map(|obj| &obj.extension_headers),
+                        ),
+                    };
+                    let _ = mlog_guard.add_object_event(event, object);
                 }
             }
 
-            // Pass extension headers through to the serve layer
-            // TODO SLG - object_id_delta and object status are still being ignored
+            // Refuse oversized objects before buffering any of the payload
+            if let Some(max) = limits.max_object_size {
+                if remaining_bytes > max {
+                    log::warn!(
+                        "[SUBSCRIBER] recv_subgroup: object #{} exceeds max object size ({} > {} bytes)",
+                        object_count + 1,
+                        remaining_bytes,
+                        max
+                    );
+                    return Err(ServeError::Size.into());
+                }
+            }
+
+            // Pass the object ID, extension headers and any status through to the serve layer
+            subgroup_writer.skip_to(current_object_id)?;
+            let extension_headers = decoded_object.map(|obj| obj.extension_headers);
+
+            // Deadlines count from the capture, by our clock, rather than from now.
+            if let Some(captured) = extension_headers
+                .as_ref()
+                .and_then(|headers| headers.capture_timestamp())
+            {
+                let now = Instant::now();
+                let age = clock.record(&subgroup_writer.info.track.namespace, captured);
+                subgroup_writer.created_at(now.checked_sub(age).unwrap_or(now));
+            }
+
+            let status_only = remaining_bytes == 0
+                && status.is_some_and(|status| status != data::ObjectStatus::NormalObject);
+
+            // Checked objects are read whole, and dropped before any reader sees them if rejected.
+            let checked = match &validator {
+                Some(validator) if !status_only => {
+                    if remaining_bytes > limits.max_whole_object() {
+                        log::warn!(
+                            "[SUBSCRIBER] recv_subgroup: checked object #{} too large to buffer ({} > {} bytes)",
+                            object_count + 1,
+                            remaining_bytes,
+                            limits.max_whole_object()
+                        );
+                        return Err(ServeError::Size.into());
+                    }
+                    let payload = read_whole(&mut reader, remaining_bytes).await?;
+
+                    let track = &subgroup_writer.info.track;
+                    if !validator.validate(&track.namespace, &track.name, &payload) {
+                        log::debug!(
+                            "[SUBSCRIBER] recv_subgroup: rejected object #{} (group_id={}, object_id={})",
+                            object_count + 1,
+                            subgroup_writer.info.group_id,
+                            current_object_id
+                        );
+                        object_count += 1;
+                        continue;
+                    }
+                    Some(payload)
+                }
+                _ => None,
+            };
 
-            let mut object_writer = subgroup_writer.create(remaining_bytes, extension_headers)?;
+            let mut object_writer = match status {
+                Some(status) if status_only => {
+                    // Readers get the status as an event, as well as the object.
+                    let location = Location::new(subgroup_writer.info.group_id, current_object_id);
+                    if let Some(event) = serve::TrackEvent::from_status(status, location) {
+                        // Nobody may be listening for events.
+                        let _ = events.report(event);
+                    }
+                    subgroup_writer.create_status(status, extension_headers)?
+                }
+                _ => subgroup_writer.create(remaining_bytes, extension_headers)?,
+            };
+            if let Some(payload) = checked {
+                object_writer.write(payload)?;
+                remaining_bytes = 0;
+            }
             log::trace!(
                 "[SUBSCRIBER] recv_subgroup: reading payload for object #{} ({} bytes)",
                 object_count + 1,
@@ -614,6 +1101,11 @@ impl Subscriber {
                 remaining_bytes -= data.len();
                 object_writer.write(data)?;
                 chunks_read += 1;
+
+                // Don't read further ahead of our readers than allowed
+                if let Some(max) = limits.max_buffered {
+                    object_writer.ready(max).await?;
+                }
             }
 
             log::trace!(
@@ -621,6 +1113,7 @@ impl Subscriber {
                 object_count + 1,
                 chunks_read
             );
+            position.advance(subgroup_writer.info.group_id, object_writer.info.object_id);
             object_count += 1;
         }
 
@@ -634,22 +1127,136 @@ impl Subscriber {
         Ok(())
     }
 
+    /// Receive the objects of a stream as datagrams, for a track carried in datagrams whose
+    /// publisher sent some objects on streams instead, ex. as they didn't fit in a datagram.
+    async fn recv_datagram_stream(
+        &mut self,
+        stream_header_type: data::StreamHeaderType,
+        header: data::SubgroupHeader,
+        mut reader: Reader,
+    ) -> Result<(), SessionError> {
+        let mut next_object_id = 0u64;
+        while !reader.done().await? {
+            let (payload_length, object_id_delta, status, extension_headers) =
+                match stream_header_type.has_extension_headers() {
+                    true => {
+                        let object = reader.decode::<data::SubgroupObjectExt>().await?;
+                        (
+                            object.payload_length,
+                            object.object_id_delta,
+                            object.status,
+                            Some(object.extension_headers),
+                        )
+                    }
+                    false => {
+                        let object = reader.decode::<data::SubgroupObject>().await?;
+                        (
+                            object.payload_length,
+                            object.object_id_delta,
+                            object.status,
+                            None,
+                        )
+                    }
+                };
+
+            let object_id = next_object_id
+                .checked_add(object_id_delta)
+                .filter(|id| *id < u64::MAX)
+                .ok_or_else(|| ServeError::Internal("object ID overflow".into()))?;
+            next_object_id = object_id + 1;
+
+            // Refuse oversized objects before buffering any of the payload. They are delivered as
+            // datagrams, so read whole.
+            let max = self.object_limits().max_whole_object();
+            if payload_length > max {
+                log::warn!(
+                    "[SUBSCRIBER] recv_datagram_stream: object too large to buffer ({} > {} bytes)",
+                    payload_length,
+                    max
+                );
+                return Err(ServeError::Size.into());
+            }
+
+            let payload = read_whole(&mut reader, payload_length).await?;
+
+            log::debug!(
+                "[SUBSCRIBER] recv_datagram_stream: object on a stream - track_alias={}, group_id={}, object_id={}, payload_length={}",
+                header.track_alias,
+                header.group_id,
+                object_id,
+                payload_length
+            );
+
+            let datagram = data::Datagram {
+                datagram_type: match extension_headers.is_some() {
+                    true => data::DatagramType::ObjectIdPayloadExt,
+                    false => data::DatagramType::ObjectIdPayload,
+                },
+                track_alias: header.track_alias,
+                group_id: header.group_id,
+                object_id: Some(object_id),
+                publisher_priority: header.publisher_priority,
+                extension_headers,
+                status,
+                payload: Some(payload),
+            };
+            self.recv_datagram_object(datagram).await?;
+        }
+
+        Ok(())
+    }
+
     /// Handle reception of a datagram from the QUIC session.
-    pub async fn recv_datagram(&mut self, datagram: bytes::Bytes) -> Result<(), SessionError> {
-        let mut cursor = io::Cursor::new(datagram);
-        let datagram = data::Datagram::decode(&mut cursor)?;
+    pub async fn recv_datagram(&mut self, mut datagram: bytes::Bytes) -> Result<(), SessionError> {
+        // Decoding straight from Bytes lets the payload share the received buffer instead of copying it.
+        let datagram = data::Datagram::decode(&mut datagram)?;
+        self.recv_datagram_object(datagram).await
+    }
+
+    // Deliver a datagram, whether it arrived as one or on a stream.
+    async fn recv_datagram_object(&mut self, datagram: data::Datagram) -> Result<(), SessionError> {
+        if let Some(max) = self.object_limits().max_object_size {
+            let payload_len = datagram.payload.as_ref().map_or(0, |p| p.len());
+            if payload_len > max {
+                log::warn!(
+                    "[SUBSCRIBER] recv_datagram: discarded datagram exceeding max object size ({} > {} bytes)",
+                    payload_len,
+                    max
+                );
+                self.violations.fetch_add(1, atomic::Ordering::Relaxed);
+                return Ok(());
+            }
+        }
 
         if let Some(ref mlog) = self.mlog {
-            if let Ok(mut mlog_guard) = mlog.lock() {
+            if let Some(mut mlog_guard) = mlog.lock().ok().filter(|mlog| mlog.data_plane()) {
                 let time = mlog_guard.elapsed_ms();
                 let stream_id = 0; // TODO: Placeholder, need actual QUIC stream ID
-                let _ =
-                    mlog_guard.add_event(mlog::object_datagram_parsed(time, stream_id, &datagram));
+                let event = mlog::object_datagram_parsed(time, stream_id, &datagram);
+                let namespace = self.namespace_by_alias(datagram.track_alias);
+                let object = mlog::SampledObject {
+                    namespace: namespace.as_ref(),
+                    track_alias: datagram.track_alias,
+                    group_id: datagram.group_id,
+                    object_id: datagram.object_id.unwrap_or(0),
+                    anomaly: mlog::SampledObject::is_anomaly(
+                        datagram.status,
+                        datagram.extension_headers.as_ref(),
+                    ),
+                };
+                let _ = mlog_guard.add_object_event(event, object);
             }
         }
 
         // Check for extension headers in the datagram
         if let Some(ref ext_headers) = datagram.extension_headers {
+            if let Some((captured, namespace)) = ext_headers
+                .capture_timestamp()
+                .zip(self.namespace_by_alias(datagram.track_alias))
+            {
+                self.clock.record(&namespace, captured);
+            }
+
             log::debug!(
                 "[SUBSCRIBER] recv_datagram: datagram contains extension headers: {:?}",
                 ext_headers
@@ -658,11 +1265,11 @@ impl Subscriber {
             // Check for known draft-14 extension types
 
             // Check for Immutable Extensions (type 0xB = 11)
-            if ext_headers.has(0xB) {
+            if ext_headers.has(data::IMMUTABLE_EXTENSIONS) {
                 log::info!(
                     "[SUBSCRIBER] recv_datagram: datagram contains IMMUTABLE EXTENSIONS (type 0xB)"
                 );
-                if let Some(immutable_ext) = ext_headers.get(0xB) {
+                if let Some(immutable_ext) = ext_headers.get(data::IMMUTABLE_EXTENSIONS) {
                     log::debug!(
                         "[SUBSCRIBER] recv_datagram: immutable extension details: {:?}",
                         immutable_ext
@@ -671,11 +1278,11 @@ impl Subscriber {
             }
 
             // Check for Prior Group ID Gap (type 0x3C = 60)
-            if ext_headers.has(0x3C) {
+            if ext_headers.has(data::PRIOR_GROUP_ID_GAP) {
                 log::info!(
                     "[SUBSCRIBER] recv_datagram: datagram contains PRIOR GROUP ID GAP (type 0x3C)"
                 );
-                if let Some(gap_ext) = ext_headers.get(0x3C) {
+                if let Some(gap_ext) = ext_headers.prior_group_id_gap() {
                     log::debug!(
                         "[SUBSCRIBER] recv_datagram: prior group id gap details: {:?}",
                         gap_ext
@@ -699,7 +1306,30 @@ impl Subscriber {
                     datagram.publisher_priority,
                     datagram.status.as_ref().map_or("None".to_string(), |s| format!("{:?}", s)),
                     datagram.payload.as_ref().map_or(0, |p| p.len()));
-                subscribe.datagram(datagram)?;
+
+                if let Some(payload) = &datagram.payload {
+                    let (namespace, track) = subscribe.track();
+                    let rejected = self
+                        .validator(namespace, track)
+                        .is_some_and(|validator| !validator.validate(namespace, track, payload));
+                    if rejected {
+                        log::debug!(
+                            "[SUBSCRIBER] recv_datagram: rejected object (group_id={}, object_id={})",
+                            datagram.group_id,
+                            datagram.object_id.unwrap_or(0)
+                        );
+                        return Ok(());
+                    }
+                }
+
+                // Nobody is reading the track, ex. datagrams still in flight after UNSUBSCRIBE.
+                if let Err(err) = subscribe.datagram(datagram) {
+                    log::debug!(
+                        "[SUBSCRIBER] recv_datagram: discarded for subscribe_id={}: {}",
+                        subscribe_id,
+                        err
+                    );
+                }
             }
         } else {
             log::warn!(
@@ -715,3 +1345,372 @@ impl Subscriber {
         Ok(())
     }
 }
+
+// Read an object's payload of `size` bytes whole, growing the buffer as the chunks arrive rather
+// than allocating the size declared by the publisher up front.
+async fn read_whole(reader: &mut Reader, size: usize) -> Result<bytes::Bytes, SessionError> {
+    let mut payload = bytes::BytesMut::new();
+    while payload.len() < size {
+        let chunk = reader
+            .read_chunk(size - payload.len())
+            .await?
+            .ok_or(SessionError::WrongSize)?;
+        payload.extend_from_slice(&chunk);
+    }
+
+    Ok(payload.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        coding::Telemetry,
+        message::GroupOrder,
+        serve::Track,
+        session::{RequestState, SubscribeInfo},
+    };
+
+    #[test]
+    fn whole_object_limit() {
+        let limits = ObjectLimits::default();
+        assert_eq!(
+            limits.max_whole_object(),
+            ObjectLimits::DEFAULT_MAX_WHOLE_OBJECT
+        );
+
+        let limits = ObjectLimits {
+            max_object_size: Some(1 << 30),
+            max_buffered: Some(64 << 10),
+        };
+        assert_eq!(limits.max_whole_object(), 64 << 10);
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut subscriber =
+            Subscriber::new(Queue::default(), Requests::new(0), None, Default::default());
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let (writer, _reader) = Track::new(namespace.clone(), "video".to_string()).produce();
+        let subscribe = subscriber.subscribe_handle(writer);
+
+        let expected = SubscriptionSnapshot {
+            id: 0,
+            track_namespace: namespace.clone(),
+            track_name: "video".to_string(),
+            track_alias: None,
+            state: RequestState::Pending,
+            position: None,
+            telemetry: None,
+        };
+        assert_eq!(subscriber.subscriptions(), vec![expected.clone()]);
+
+        subscriber
+            .recv_message(message::Publisher::SubscribeOk(message::SubscribeOk {
+                id: 0,
+                track_alias: 7,
+                expires: 0,
+                group_order: GroupOrder::Ascending,
+                content_exists: false,
+                largest_location: None,
+                params: Default::default(),
+            }))
+            .unwrap();
+        assert_eq!(
+            subscriber.subscriptions(),
+            vec![SubscriptionSnapshot {
+                track_alias: Some(7),
+                state: RequestState::Active,
+                ..expected
+            }]
+        );
+
+        // Receiving an object advances the position watched by the application.
+        let position = subscribe.position();
+        let datagram = serve::Datagram {
+            group_id: 3,
+            object_id: 5,
+            priority: 0,
+            payload: bytes::Bytes::from_static(b"hello"),
+            extension_headers: Default::default(),
+        };
+        subscriber
+            .subscribes
+            .lock()
+            .unwrap()
+            .get_mut(&0)
+            .unwrap()
+            .datagram(datagram.into_data(7))
+            .unwrap();
+
+        assert_eq!(position.get(), Some(Location::new(3, 5)));
+        assert_eq!(
+            subscriber.subscriptions()[0].position,
+            Some(Location::new(3, 5))
+        );
+
+        subscriber
+            .recv_message(message::Publisher::PublishNamespace(
+                message::PublishNamespace {
+                    id: 1,
+                    track_namespace: namespace.clone(),
+                    params: Default::default(),
+                },
+            ))
+            .unwrap();
+        assert_eq!(
+            subscriber.announces(),
+            vec![AnnounceSnapshot {
+                request_id: 1,
+                namespace,
+                state: RequestState::Pending,
+            }]
+        );
+    }
+
+    #[test]
+    fn resume_from_start() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+
+        let (writer, _reader) =
+            Track::new(TrackNamespace::from_utf8_path("live"), "video".to_string())
+                .with_start(Some(Location::new(4, 0)))
+                .produce();
+        let subscribe = subscriber.subscribe_handle(writer);
+        assert_eq!(subscribe.filter_type, FilterType::AbsoluteStart);
+        assert_eq!(subscribe.start_location, Some(Location::new(4, 0)));
+
+        match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::Subscribe(msg)) => {
+                assert_eq!(msg.filter_type, FilterType::AbsoluteStart);
+                assert_eq!(msg.start_location, Some(Location::new(4, 0)));
+            }
+            _ => panic!("expected SUBSCRIBE"),
+        }
+
+        subscriber
+            .recv_message(message::Publisher::SubscribeOk(message::SubscribeOk {
+                id: 0,
+                track_alias: 7,
+                expires: 1500,
+                group_order: GroupOrder::Ascending,
+                content_exists: false,
+                largest_location: None,
+                params: Default::default(),
+            }))
+            .unwrap();
+        futures::executor::block_on(subscribe.ok()).unwrap();
+        assert_eq!(subscribe.expires(), Some(Duration::from_millis(1500)));
+        assert_eq!(subscribe.largest_location(), None);
+    }
+
+    #[test]
+    fn advertises_cached_groups() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+
+        let (writer, _reader) =
+            Track::new(TrackNamespace::from_utf8_path("live"), "video".to_string())
+                .with_cached_groups(Some(3..=300))
+                .produce();
+        let _subscribe = subscriber.subscribe_handle(writer);
+
+        match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::Subscribe(msg)) => {
+                let info = SubscribeInfo::new_from_subscribe(&msg);
+                assert_eq!(info.cached_groups(), Some(3..=300));
+            }
+            _ => panic!("expected SUBSCRIBE"),
+        }
+    }
+
+    #[test]
+    fn reports_telemetry() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+
+        let telemetry = Telemetry {
+            buffer_length: Some(Duration::from_millis(3000)),
+            session_id: Some("player".to_string()),
+            ..Default::default()
+        };
+        let (writer, _reader) =
+            Track::new(TrackNamespace::from_utf8_path("live"), "video".to_string())
+                .with_telemetry(Some(telemetry.clone()))
+                .produce();
+        let mut subscribe = subscriber.subscribe_handle(writer);
+
+        match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::Subscribe(msg)) => {
+                let info = SubscribeInfo::new_from_subscribe(&msg);
+                assert_eq!(info.telemetry(), Some(telemetry));
+            }
+            _ => panic!("expected SUBSCRIBE"),
+        }
+
+        // Later reports are sent in SUBSCRIBE_UPDATE.
+        let drained = Telemetry {
+            buffer_length: Some(Duration::from_millis(200)),
+            playback_rate: Some(1.0),
+            ..Default::default()
+        };
+        subscribe.report_telemetry(drained.clone()).unwrap();
+        match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::SubscribeUpdate(msg)) => {
+                let value = msg
+                    .params
+                    .get_bytesvalue(message::ParameterType::Telemetry.into())
+                    .unwrap();
+                assert_eq!(
+                    Telemetry::parse(std::str::from_utf8(value).unwrap()),
+                    drained
+                );
+            }
+            _ => panic!("expected SUBSCRIBE_UPDATE"),
+        }
+        assert_eq!(subscriber.subscriptions()[0].telemetry, Some(drained));
+    }
+
+    #[test]
+    fn live_edge() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+
+        let (writer, reader) =
+            Track::new(TrackNamespace::from_utf8_path("live"), "video".to_string()).produce();
+        let subscribe = subscriber.subscribe_handle(writer);
+        assert_eq!(reader.latest(), None);
+
+        subscriber
+            .recv_message(message::Publisher::SubscribeOk(message::SubscribeOk {
+                id: 0,
+                track_alias: 7,
+                expires: 0,
+                group_order: GroupOrder::Ascending,
+                content_exists: true,
+                largest_location: Some(Location::new(12, 3)),
+                params: Default::default(),
+            }))
+            .unwrap();
+        futures::executor::block_on(subscribe.ok()).unwrap();
+        assert_eq!(subscribe.largest_location(), Some(Location::new(12, 3)));
+        assert_eq!(reader.latest(), Some(Location::new(12, 3)));
+    }
+
+    #[test]
+    fn reject_announce() {
+        let outgoing = Queue::default();
+        let mut subscriber =
+            Subscriber::new(outgoing.clone(), Requests::new(0), None, Default::default());
+
+        subscriber
+            .recv_message(message::Publisher::PublishNamespace(
+                message::PublishNamespace {
+                    id: 3,
+                    track_namespace: TrackNamespace::from_utf8_path("live"),
+                    params: Default::default(),
+                },
+            ))
+            .unwrap();
+        let announced = futures::executor::block_on(subscriber.announced()).unwrap();
+        announced
+            .reject(
+                message::PublishNamespaceErrorCode::LimitExceeded,
+                "too many namespaces",
+            )
+            .unwrap();
+
+        match futures::executor::block_on(outgoing.clone().pop()) {
+            Some(Message::PublishNamespaceError(msg)) => {
+                assert_eq!(msg.id, 3);
+                assert_eq!(
+                    msg.error_code,
+                    message::PublishNamespaceErrorCode::LimitExceeded.code()
+                );
+                assert_eq!(msg.reason_phrase.0, "too many namespaces");
+            }
+            _ => panic!("expected PUBLISH_NAMESPACE_ERROR"),
+        }
+        assert!(subscriber.announces().is_empty());
+    }
+
+    #[test]
+    fn duplicate_track_alias() {
+        let mut subscriber =
+            Subscriber::new(Queue::default(), Requests::new(0), None, Default::default());
+        let namespace = TrackNamespace::from_utf8_path("live");
+
+        let mut subscribe = |name: &str| {
+            let (writer, reader) = Track::new(namespace.clone(), name.to_string()).produce();
+            (subscriber.subscribe_handle(writer), reader)
+        };
+        // Request IDs 0, 2 and 4.
+        let _video = subscribe("video");
+        let _audio = subscribe("audio");
+        let _video_again = subscribe("video");
+
+        let ok = |id| {
+            message::Publisher::SubscribeOk(message::SubscribeOk {
+                id,
+                track_alias: 7,
+                expires: 0,
+                group_order: GroupOrder::Ascending,
+                content_exists: false,
+                largest_location: None,
+                params: Default::default(),
+            })
+        };
+        subscriber.recv_message(ok(0)).unwrap();
+
+        // The same track may be given the same alias, but another track may not.
+        subscriber.recv_message(ok(4)).unwrap();
+        assert!(matches!(
+            subscriber.recv_message(ok(2)),
+            Err(SessionError::Duplicate)
+        ));
+    }
+
+    #[test]
+    fn unmatched_responses() {
+        let mut subscriber =
+            Subscriber::new(Queue::default(), Requests::new(0), None, Default::default());
+        let (writer, _reader) =
+            Track::new(TrackNamespace::from_utf8_path("live"), "video".to_string()).produce();
+        let _subscribe = subscriber.subscribe_handle(writer);
+
+        let ok = |id| {
+            message::Publisher::SubscribeOk(message::SubscribeOk {
+                id,
+                track_alias: 7,
+                expires: 0,
+                group_order: GroupOrder::Ascending,
+                content_exists: false,
+                largest_location: None,
+                params: Default::default(),
+            })
+        };
+
+        // Never sent, so the peer is confused rather than late.
+        assert!(matches!(
+            subscriber.recv_message(ok(2)),
+            Err(SessionError::ProtocolViolation(_))
+        ));
+
+        subscriber.recv_message(ok(0)).unwrap();
+        let error = message::Publisher::SubscribeError(message::SubscribeError {
+            id: 0,
+            error_code: 0,
+            reason_phrase: Default::default(),
+        });
+        assert!(matches!(
+            subscriber.recv_message(error),
+            Err(SessionError::ProtocolViolation(_))
+        ));
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use crate::{
+    coding::Location,
+    message::{self, GroupOrder},
+};
+
+/// The status of a track, as answered by the publisher to [super::Subscriber::track_status].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrackStatus {
+    pub request_id: u64,
+    pub status: TrackStatusCode,
+
+    /// The largest object the publisher has, if the track has begun.
+    pub largest_location: Option<Location>,
+
+    /// How long the status is valid for, or None if it doesn't expire.
+    pub expires: Option<Duration>,
+    pub group_order: GroupOrder,
+}
+
+/// Whether a track has begun, from the content flag of TRACK_STATUS_OK.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackStatusCode {
+    /// The track has objects, up to [TrackStatus::largest_location].
+    InProgress,
+
+    /// The track exists, but has no objects yet.
+    NotBegun,
+}
+
+impl From<&message::TrackStatusOk> for TrackStatus {
+    fn from(msg: &message::TrackStatusOk) -> Self {
+        let status = match msg.content_exists {
+            true => TrackStatusCode::InProgress,
+            false => TrackStatusCode::NotBegun,
+        };
+
+        Self {
+            request_id: msg.id,
+            status,
+            largest_location: msg.largest_location.filter(|_| msg.content_exists),
+            expires: (msg.expires > 0).then(|| Duration::from_millis(msg.expires)),
+            group_order: msg.group_order,
+        }
+    }
+}
+
+/// Why [super::Subscriber::track_status] didn't get a status.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum TrackStatusError {
+    /// The publisher answered with TRACK_STATUS_ERROR, ex. code 0x4 if the track doesn't exist.
+    #[error("track status refused, code={code}: {reason}")]
+    Refused { code: u64, reason: String },
+
+    /// The session ended before the publisher answered.
+    #[error("session closed")]
+    Closed,
+}
+
+impl From<&message::TrackStatusError> for TrackStatusError {
+    fn from(msg: &message::TrackStatusError) -> Self {
+        Self::Refused {
+            code: msg.error_code,
+            reason: msg.reason_phrase.0.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_ok() {
+        let mut msg = message::TrackStatusOk {
+            id: 4,
+            track_alias: 4,
+            expires: 0,
+            group_order: GroupOrder::Ascending,
+            content_exists: false,
+            largest_location: None,
+            params: Default::default(),
+        };
+        let status = TrackStatus::from(&msg);
+        assert_eq!(status.status, TrackStatusCode::NotBegun);
+        assert_eq!((status.largest_location, status.expires), (None, None));
+
+        msg.expires = 1500;
+        msg.content_exists = true;
+        msg.largest_location = Some(Location::new(3, 1));
+        let status = TrackStatus::from(&msg);
+        assert_eq!(status.status, TrackStatusCode::InProgress);
+        assert_eq!(status.largest_location, Some(Location::new(3, 1)));
+        assert_eq!(status.expires, Some(Duration::from_millis(1500)));
+    }
+}
@@ -37,8 +37,8 @@ impl TrackStatusRequested {
             track_alias: self.request_msg.id, // TODO SLG does a track alias make sense in track_status response?  Using track_status request id for now
             expires: 0,                       // TODO SLG
             group_order: message::GroupOrder::Ascending, // TODO: resolve correct value from publisher / subscriber prefs
-            content_exists: track.largest_location().is_some(),
-            largest_location: track.largest_location(),
+            content_exists: track.latest().is_some(),
+            largest_location: track.latest(),
             params: Default::default(),
         });
 
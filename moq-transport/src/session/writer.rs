@@ -1,88 +1,202 @@
-use std::io;
+use std::{future::Future, pin::pin, time::Duration};
 
-use crate::coding::{Encode, EncodeError};
+use crate::coding::Encode;
 
-use super::SessionError;
-use bytes::Buf;
+use super::{BufferPool, SessionError};
+use bytes::Bytes;
+use futures::FutureExt;
+use tokio::time::Instant;
 
 pub struct Writer {
-    stream: web_transport::SendStream,
+    // Only taken by Self::reset.
+    stream: Option<web_transport::SendStream>,
+
+    // Encoded data not yet written to the stream.
     buffer: bytes::BytesMut,
+
+    // Writes are coalesced in the buffer until it holds this many bytes. Zero writes through.
+    threshold: usize,
+
+    // The buffer is returned here on drop, if set.
+    pool: Option<BufferPool>,
+
+    // How long a flush shortly after the last one waits for more data, see Self::with_delay.
+    delay: Duration,
+
+    // When buffered data was last written to the stream.
+    flushed: Option<Instant>,
 }
 
 impl Writer {
+    /// Roughly the payload of one QUIC packet, a good threshold for [Self::with_batching].
+    pub const PACKET: usize = 1200;
+
+    /// A delay for [Self::with_delay] short enough to go unnoticed on the control stream.
+    pub const DELAY: Duration = Duration::from_millis(1);
+
     pub fn new(stream: web_transport::SendStream) -> Self {
         Self {
-            stream,
+            stream: Some(stream),
             buffer: Default::default(),
+            threshold: 0,
+            pool: None,
+            delay: Duration::ZERO,
+            flushed: None,
         }
     }
 
-    pub async fn encode<T: Encode>(&mut self, msg: &T) -> Result<(), SessionError> {
+    /// Encode into a buffer taken from `pool`, returning it when the writer is dropped.
+    pub fn with_pool(stream: web_transport::SendStream, pool: BufferPool) -> Self {
+        Self {
+            stream: Some(stream),
+            buffer: pool.get(),
+            threshold: 0,
+            pool: Some(pool),
+            delay: Duration::ZERO,
+            flushed: None,
+        }
+    }
+
+    /// Coalesce messages and payload chunks smaller than `threshold` bytes into one write,
+    /// sent once `threshold` bytes are buffered or on [Self::flush].
+    ///
+    /// Anything still buffered when the writer is dropped is lost, so callers must flush before
+    /// finishing the stream, and before waiting for more data, see [Self::flush_unless_ready].
+    pub fn with_batching(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Like Nagle's algorithm, hold a batch for up to `delay` in [Self::flush_unless_ready] if
+    /// the last one was written less than `delay` ago, so a burst of messages queued one at a
+    /// time, ex. by [crate::session::Publisher::announce_many], is written together.
+    /// Data after a quiet period is still written right away.
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Change the priority of the underlying stream.
+    pub fn set_priority(&mut self, priority: i32) {
+        self.stream().set_priority(priority);
+    }
+
+    /// Reset the stream with `code` instead of finishing it, discarding anything buffered, so
+    /// the peer can tell the stream was cut short.
+    pub fn reset(mut self, code: u32) {
         self.buffer.clear();
+        if let Some(stream) = self.stream.take() {
+            stream.reset(code);
+        }
+    }
+
+    fn stream(&mut self) -> &mut web_transport::SendStream {
+        self.stream.as_mut().expect("stream was reset")
+    }
+
+    pub async fn encode<T: Encode>(&mut self, msg: &T) -> Result<(), SessionError> {
         log::trace!(
             "[WRITER] encode: encoding {} to buffer",
             std::any::type_name::<T>()
         );
 
-        msg.encode(&mut self.buffer)?;
-        let encoded_len = self.buffer.len();
+        // Don't leave half a message behind if encoding fails.
+        let buffered = self.buffer.len();
+        if let Err(err) = msg.encode(&mut self.buffer) {
+            self.buffer.truncate(buffered);
+            return Err(err.into());
+        }
+
         log::debug!(
-            "[WRITER] encode: encoded {} ({} bytes), sending to stream",
+            "[WRITER] encode: encoded {} ({} bytes, {} buffered)",
             std::any::type_name::<T>(),
-            encoded_len
+            self.buffer.len() - buffered,
+            self.buffer.len()
         );
 
-        let mut total_written = 0;
+        if self.buffer.len() >= self.threshold {
+            self.flush().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a payload chunk. Small chunks are batched, larger ones handed to the stream without copying.
+    pub async fn write(&mut self, chunk: Bytes) -> Result<(), SessionError> {
+        log::trace!("[WRITER] write: writing {} bytes to stream", chunk.len());
+
+        if chunk.len() < self.threshold {
+            self.buffer.extend_from_slice(&chunk);
+            if self.buffer.len() >= self.threshold {
+                self.flush().await?;
+            }
+            return Ok(());
+        }
+
+        self.flush().await?;
+
+        let size = chunk.len();
+        self.stream().write_chunk(chunk).await?;
+
+        log::debug!("[WRITER] write: finished writing {} bytes", size);
+
+        Ok(())
+    }
+
+    /// Write everything buffered to the stream.
+    pub async fn flush(&mut self) -> Result<(), SessionError> {
+        let buffered = self.buffer.len();
         while !self.buffer.is_empty() {
-            let written = self.stream.write_buf(&mut self.buffer).await?;
-            total_written += written;
+            let written = self
+                .stream
+                .as_mut()
+                .expect("stream was reset")
+                .write_buf(&mut self.buffer)
+                .await?;
             log::trace!(
-                "[WRITER] encode: wrote {} bytes to stream (total={}/{}, remaining={})",
+                "[WRITER] flush: wrote {} bytes to stream (remaining={})",
                 written,
-                total_written,
-                encoded_len,
                 self.buffer.len()
             );
         }
 
-        log::debug!(
-            "[WRITER] encode: finished sending {} ({} bytes total)",
-            std::any::type_name::<T>(),
-            total_written
-        );
+        if buffered > 0 {
+            log::debug!("[WRITER] flush: finished writing {} bytes", buffered);
+            self.flushed = Some(Instant::now());
+        }
 
         Ok(())
     }
 
-    pub async fn write(&mut self, buf: &[u8]) -> Result<(), SessionError> {
-        log::trace!("[WRITER] write: writing {} bytes to stream", buf.len());
-
-        let mut cursor = io::Cursor::new(buf);
-        let total_len = buf.len();
-        let mut total_written = 0;
-
-        while cursor.has_remaining() {
-            let size = self.stream.write_buf(&mut cursor).await?;
-            if size == 0 {
-                log::error!(
-                    "[WRITER] write: ERROR - wrote 0 bytes with {} bytes remaining",
-                    cursor.remaining()
-                );
-                return Err(EncodeError::More(cursor.remaining()).into());
+    /// Wait for `next`, flushing first unless it's already ready, so batching never holds data
+    /// back while the caller waits for more, or only for the delay set by [Self::with_delay].
+    pub async fn flush_unless_ready<F: Future>(
+        &mut self,
+        next: F,
+    ) -> Result<F::Output, SessionError> {
+        let mut next = pin!(next);
+        if let Some(output) = (&mut next).now_or_never() {
+            return Ok(output);
+        }
+
+        let bursting = self
+            .flushed
+            .is_some_and(|flushed| flushed.elapsed() < self.delay);
+        if bursting && !self.buffer.is_empty() {
+            if let Ok(output) = tokio::time::timeout(self.delay, &mut next).await {
+                return Ok(output);
             }
-            total_written += size;
-            log::trace!(
-                "[WRITER] write: wrote {} bytes (total={}/{}, remaining={})",
-                size,
-                total_written,
-                total_len,
-                cursor.remaining()
-            );
         }
 
-        log::debug!("[WRITER] write: finished writing {} bytes", total_written);
+        self.flush().await?;
+        Ok(next.await)
+    }
+}
 
-        Ok(())
+impl Drop for Writer {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            pool.put(std::mem::take(&mut self.buffer));
+        }
     }
 }
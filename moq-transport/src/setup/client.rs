@@ -1,5 +1,5 @@
 use super::Versions;
-use crate::coding::{Decode, DecodeError, Encode, EncodeError, KeyValuePairs};
+use crate::coding::{decode_exact, Decode, DecodeError, Encode, EncodeError, KeyValuePairs};
 
 /// Sent by the client to setup the session.
 /// This CLIENT_SETUP message is used by moq-transport draft versions 11 and later.
@@ -23,9 +23,14 @@ impl Decode for Client {
             return Err(DecodeError::InvalidMessage(typ));
         }
 
-        let _len = u16::decode(r)?;
-        // TODO: Check the length of the message.
+        let len = u16::decode(r)?;
+        decode_exact(r, len.into(), Self::decode_payload)
+    }
+}
 
+impl Client {
+    /// Decode the message after its type and length.
+    pub(crate) fn decode_payload<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
         let versions = Versions::decode(r)?;
         let params = KeyValuePairs::decode(r)?;
 
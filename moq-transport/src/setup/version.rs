@@ -73,10 +73,19 @@ impl fmt::Display for Version {
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Versions(pub Vec<Version>);
 
+impl Versions {
+    /// The most versions accepted in CLIENT_SETUP.
+    pub const MAX_VERSIONS: usize = 64;
+}
+
 impl Decode for Versions {
     /// Decode the version list.
     fn decode<R: bytes::Buf>(r: &mut R) -> Result<Self, DecodeError> {
         let count = usize::decode(r)?;
+        if count > Self::MAX_VERSIONS {
+            return Err(DecodeError::FieldBoundsExceeded("Versions".to_string()));
+        }
+
         let mut vs = Vec::new();
 
         for _ in 0..count {
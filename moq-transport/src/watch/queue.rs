@@ -1,6 +1,6 @@
 use super::State;
 use futures::channel::oneshot;
-use std::collections::VecDeque;
+use std::{collections::VecDeque, future, task};
 
 pub struct Queue<T> {
     state: State<VecDeque<(T, Option<oneshot::Sender<()>>)>>, // store optional notifier per item
@@ -17,30 +17,48 @@ impl<T> Queue<T> {
         Ok(())
     }
 
+    /// Whether the other side was dropped, so [Self::push] fails.
+    pub fn is_closed(&self) -> bool {
+        self.state.is_closed()
+    }
+
     /// Pop an item from the queue, waiting if necessary.
     pub async fn pop(&mut self) -> Option<T> {
-        loop {
-            // Scope 1: try to pop an item
-            {
-                let queue = self.state.lock();
-                if !queue.is_empty() {
-                    // Take mutable access only in a block
-                    if let Some((item, notifier)) = {
-                        let mut state_mut = queue.into_mut()?;
-                        state_mut.pop_front()
-                    } {
-                        if let Some(tx) = notifier {
-                            let _ = tx.send(()); // notify waiter
-                        }
-                        return Some(item);
-                    }
-                }
-            }
-
-            // Scope 2: wait for modifications
-            let queue = self.state.lock();
-            queue.modified()?.await;
+        future::poll_fn(|cx| self.poll_pop(cx)).await
+    }
+
+    /// Pop an item from the queue, or register `cx` to be woken when one is pushed.
+    pub fn poll_pop(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Option<T>> {
+        self.poll_pop_deferred(cx).map(|popped| {
+            popped.map(|(item, popped)| {
+                popped.confirm();
+                item
+            })
+        })
+    }
+
+    /// Pop an item from the queue, waiting if necessary, without notifying whoever pushed it
+    /// with [Self::push_and_wait_until_popped] until the returned [Popped] is confirmed,
+    /// ex. once the item has been written out.
+    pub async fn pop_deferred(&mut self) -> Option<(T, Popped)> {
+        future::poll_fn(|cx| self.poll_pop_deferred(cx)).await
+    }
+
+    fn poll_pop_deferred(&mut self, cx: &mut task::Context<'_>) -> task::Poll<Option<(T, Popped)>> {
+        let queue = self.state.lock();
+        if queue.is_empty() {
+            return match queue.register(cx.waker()) {
+                Some(()) => task::Poll::Pending,
+                None => task::Poll::Ready(None),
+            };
         }
+
+        let Some((item, notifier)) = queue.into_mut().and_then(|mut queue| queue.pop_front())
+        else {
+            return task::Poll::Ready(None);
+        };
+
+        task::Poll::Ready(Some((item, Popped(notifier))))
     }
 
     /// Drop the state
@@ -57,7 +75,7 @@ impl<T> Queue<T> {
         res
     }
 
-    /// Push an item and wait until it is popped.
+    /// Push an item and wait until it is popped, or confirmed if popped with [Self::pop_deferred].
     /// Returns Ok(()) if the item was successfully popped.
     /// Returns Err(()) if the queue was closed before the item could be confirmed popped.
     pub async fn push_and_wait_until_popped(&mut self, item: T) -> Result<(), ()> {
@@ -98,3 +116,20 @@ impl<T> Default for Queue<T> {
         }
     }
 }
+
+/// Notifies whoever is waiting for a popped item, see [Queue::pop_deferred].
+/// Dropping it without confirming tells them the queue was closed.
+pub struct Popped(Option<oneshot::Sender<()>>);
+
+impl Popped {
+    /// Whether anyone is waiting for the item.
+    pub fn is_awaited(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn confirm(self) {
+        if let Some(tx) = self.0 {
+            let _ = tx.send(()); // notify waiter
+        }
+    }
+}
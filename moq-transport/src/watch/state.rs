@@ -81,6 +81,11 @@ impl<T> State<T> {
         })
     }
 
+    /// Whether the other side was dropped, so no more updates will come.
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().dropped.is_none()
+    }
+
     pub fn downgrade(&self) -> StateWeak<T> {
         StateWeak {
             state: Arc::downgrade(&self.state),
@@ -141,6 +146,14 @@ impl<'a, T> StateRef<'a, T> {
         })
     }
 
+    // Release the lock, waking `waker` when next updated, for poll-based callers.
+    // Returns None if no more updates will come.
+    pub fn register(mut self, waker: &task::Waker) -> Option<()> {
+        self.lock.dropped?;
+        self.lock.register(waker);
+        Some(())
+    }
+
     // Upgrade to a mutable references that automatically calls notify on drop.
     pub fn into_mut(self) -> Option<StateMut<'a, T>> {
         self.lock.dropped?;